@@ -0,0 +1,29 @@
+-- Contracts tracked by the indexer: the root mixer contract and its forks.
+CREATE TABLE IF NOT EXISTS mixer_contracts (
+    address TEXT PRIMARY KEY,
+    parent TEXT REFERENCES mixer_contracts (address),
+    created_at BIGINT NOT NULL,
+    last_lt BIGINT NOT NULL DEFAULT 0
+);
+
+-- Events decoded from the transactions of tracked contracts.
+CREATE TABLE IF NOT EXISTS mixer_events (
+    id BIGSERIAL PRIMARY KEY,
+    contract TEXT NOT NULL REFERENCES mixer_contracts (address),
+    lt BIGINT NOT NULL,
+    hash TEXT NOT NULL,
+    utime BIGINT NOT NULL,
+    op TEXT NOT NULL,
+    query_id BIGINT,
+    mode SMALLINT,
+    source TEXT,
+    value_in BIGINT NOT NULL,
+    value_out BIGINT NOT NULL,
+    total_fees BIGINT NOT NULL,
+    fwd_fees BIGINT NOT NULL,
+    body JSONB,
+    UNIQUE (contract, lt)
+);
+
+CREATE INDEX IF NOT EXISTS mixer_events_utime_idx ON mixer_events (utime);
+CREATE INDEX IF NOT EXISTS mixer_events_query_id_idx ON mixer_events (query_id);
@@ -0,0 +1,7 @@
+-- Channels each notification event type is routed to.
+-- Events without any route are delivered to every configured channel.
+CREATE TABLE IF NOT EXISTS notification_routes (
+    event TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    PRIMARY KEY (event, channel)
+);
@@ -0,0 +1,15 @@
+-- Signed external messages, written before they are broadcast.
+CREATE TABLE IF NOT EXISTS outbox (
+    id BIGSERIAL PRIMARY KEY,
+    wallet TEXT NOT NULL,
+    op TEXT NOT NULL,
+    seqno BIGINT NOT NULL,
+    valid_until BIGINT NOT NULL,
+    boc BYTEA NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    message_hash TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS outbox_status_idx ON outbox (status);
@@ -0,0 +1,2 @@
+-- Normalized external-in message hash (TEP-467), as indexed by explorers.
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS normalized_hash TEXT;
@@ -0,0 +1,4 @@
+-- Query id embedded in the body of mixer messages, used to reconcile operations with events.
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS query_id BIGINT;
+
+CREATE INDEX IF NOT EXISTS outbox_query_id_idx ON outbox (query_id);
@@ -0,0 +1,6 @@
+-- Mixer contracts that spread, collect and fork may target besides `MIXER_CONTRACT`.
+CREATE TABLE IF NOT EXISTS allowed_contracts (
+    address TEXT PRIMARY KEY,
+    label TEXT,
+    created_at BIGINT NOT NULL
+);
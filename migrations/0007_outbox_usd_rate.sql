@@ -0,0 +1,2 @@
+-- TON/USD rate locked in for operations with USD-denominated amounts.
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS usd_rate DOUBLE PRECISION;
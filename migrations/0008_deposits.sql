@@ -0,0 +1,17 @@
+-- Payment requests matched against incoming transfers by their text comment.
+CREATE TABLE IF NOT EXISTS deposits (
+    id BIGSERIAL PRIMARY KEY,
+    contract TEXT NOT NULL,
+    comment TEXT NOT NULL UNIQUE,
+    amount BIGINT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    expires_at BIGINT NOT NULL,
+    received BIGINT,
+    sender TEXT,
+    tx_hash TEXT,
+    tx_lt BIGINT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS deposits_status_idx ON deposits (status);
@@ -0,0 +1,19 @@
+-- Operations scheduled to run in the background, e.g. the spread of a paid deposit.
+CREATE TABLE IF NOT EXISTS jobs (
+    id BIGSERIAL PRIMARY KEY,
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    run_at BIGINT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    result TEXT,
+    error TEXT,
+    deposit_id BIGINT REFERENCES deposits (id),
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS jobs_status_run_at_idx ON jobs (status, run_at);
+
+-- Mixing plan a deposit is spread by once it is paid.
+ALTER TABLE deposits ADD COLUMN IF NOT EXISTS plan JSONB;
@@ -0,0 +1,22 @@
+-- TON spread or collected, summed over a rolling 24 hour window for the daily withdrawal limit.
+CREATE TABLE IF NOT EXISTS withdrawals (
+    id BIGSERIAL PRIMARY KEY,
+    op TEXT NOT NULL,
+    contract TEXT,
+    amount BIGINT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS withdrawals_created_at_idx ON withdrawals (created_at);
+
+-- Extra allowance granted by an admin on top of the daily withdrawal limit, kept as the audit trail.
+CREATE TABLE IF NOT EXISTS limit_overrides (
+    id BIGSERIAL PRIMARY KEY,
+    amount BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    requested_by TEXT,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS limit_overrides_expires_at_idx ON limit_overrides (expires_at);
@@ -0,0 +1,2 @@
+-- Progress reported by long-running jobs, e.g. the steps of a rebalance.
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS progress JSONB;
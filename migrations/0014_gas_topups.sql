@@ -0,0 +1,14 @@
+-- Top-ups of the gas wallet sent by the auto top-up policy, kept as the audit trail.
+CREATE TABLE IF NOT EXISTS gas_topups (
+    id BIGSERIAL PRIMARY KEY,
+    source TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    wallet_balance BIGINT NOT NULL,
+    threshold BIGINT NOT NULL,
+    tx_hash TEXT,
+    error TEXT,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS gas_topups_created_at_idx ON gas_topups (created_at);
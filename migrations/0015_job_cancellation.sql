@@ -0,0 +1,3 @@
+-- When and why a queued job was cancelled before it ran.
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS cancelled_at BIGINT;
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS cancel_reason TEXT;
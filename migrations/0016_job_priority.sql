@@ -0,0 +1,4 @@
+-- Priority of a job, due jobs are claimed from the highest priority down, e.g. a collect before routine spreads.
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS priority INTEGER NOT NULL DEFAULT 1;
+
+CREATE INDEX IF NOT EXISTS jobs_status_priority_idx ON jobs (status, priority DESC, run_at);
@@ -0,0 +1,19 @@
+-- Recurring operations run on a cron expression, managed with the admin API.
+CREATE TABLE IF NOT EXISTS schedules (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    cron TEXT NOT NULL,
+    -- Kind and payload of the operation template.
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 1,
+    paused BOOLEAN NOT NULL DEFAULT FALSE,
+    next_run_at BIGINT NOT NULL,
+    last_run_at BIGINT,
+    last_job_id BIGINT,
+    last_error TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS schedules_next_run_at_idx ON schedules (next_run_at) WHERE NOT paused;
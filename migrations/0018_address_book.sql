@@ -0,0 +1,15 @@
+-- Labeled addresses, referenced as `@name` in spread and collect payloads.
+CREATE TABLE IF NOT EXISTS address_book (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    -- The address as it was entered, keeping its bounceable or non-bounceable form.
+    address TEXT NOT NULL,
+    -- The raw form `<workchain>:<hex>`, the address is matched by.
+    raw_address TEXT NOT NULL,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    notes TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS address_book_raw_address_idx ON address_book (raw_address);
@@ -0,0 +1,19 @@
+-- Named sets of address book entries with default weights, spread to by id.
+CREATE TABLE IF NOT EXISTS recipient_groups (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+-- Entries in a group can not be removed from the address book, so a payout never loses a recipient silently.
+CREATE TABLE IF NOT EXISTS recipient_group_members (
+    group_id BIGINT NOT NULL REFERENCES recipient_groups (id) ON DELETE CASCADE,
+    entry_id BIGINT NOT NULL REFERENCES address_book (id) ON DELETE RESTRICT,
+    weight DOUBLE PRECISION NOT NULL,
+    -- Position of the member in its group, the order of the spread legs.
+    position INTEGER NOT NULL,
+    PRIMARY KEY (group_id, entry_id)
+);
+
+CREATE INDEX IF NOT EXISTS recipient_group_members_entry_id_idx ON recipient_group_members (entry_id);
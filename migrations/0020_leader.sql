@@ -0,0 +1,8 @@
+-- The instance holding the sender lock, read by followers to forward writes to it.
+CREATE TABLE IF NOT EXISTS leader (
+    id SMALLINT PRIMARY KEY CHECK (id = 1),
+    instance TEXT NOT NULL,
+    -- Base URL the leader is reachable at by the other replicas, `NULL` if it did not advertise one.
+    url TEXT,
+    elected_at BIGINT NOT NULL
+);
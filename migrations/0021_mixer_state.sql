@@ -0,0 +1,10 @@
+-- State of the mixer shared by all instances, so a pause applies to the one sending.
+CREATE TABLE IF NOT EXISTS mixer_state (
+    id SMALLINT PRIMARY KEY CHECK (id = 1),
+    paused BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Operator or channel that last paused or resumed the mixer.
+    updated_by TEXT,
+    updated_at BIGINT NOT NULL
+);
+
+INSERT INTO mixer_state (id, paused, updated_at) VALUES (1, FALSE, 0) ON CONFLICT (id) DO NOTHING;
@@ -0,0 +1,7 @@
+-- Unix time the lease of a running job ends, a runner reclaims running jobs whose lease ran out.
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS locked_until BIGINT;
+
+-- Job that wrote an outbox entry, so a failed or interrupted job only runs again if it sent nothing.
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS job_id BIGINT;
+
+CREATE INDEX IF NOT EXISTS outbox_job_id_idx ON outbox (job_id) WHERE job_id IS NOT NULL;
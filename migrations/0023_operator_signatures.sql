@@ -0,0 +1,8 @@
+-- Operator signed requests accepted within their signature window, shared by all instances so none accepts a replay.
+CREATE TABLE IF NOT EXISTS operator_requests (
+    digest TEXT PRIMARY KEY,
+    operator TEXT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS operator_requests_expires_at_idx ON operator_requests (expires_at);
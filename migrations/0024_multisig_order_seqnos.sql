@@ -0,0 +1,6 @@
+-- Next order seqno of each multisig, allocated atomically so concurrent orders never share one.
+CREATE TABLE IF NOT EXISTS multisig_order_seqnos (
+    multisig TEXT PRIMARY KEY,
+    next_seqno BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- When a queued job was last held by a limit, and when and by whom running it anyway was approved.
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS held_at BIGINT;
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS approved_at BIGINT;
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS approved_by TEXT;
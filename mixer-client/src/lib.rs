@@ -0,0 +1,229 @@
+//! # Mixer Client
+//!
+//! This crate is a typed HTTP client of the mixer API for other Rust services. Payloads
+//! and responses are the types of the `mixer-types` crate, re-exported here, which the
+//! server shares without pulling the server itself into clients.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), mixer_client::ClientError> {
+//! use mixer_client::{MixerClient, types::{SpreadPayload, SpreadWalletPayload}};
+//!
+//! let client: MixerClient = MixerClient::new("http://localhost:8080");
+//! let receipt = client.spread(None, &SpreadPayload { wallets: vec![SpreadWalletPayload {
+//!     account: String::from("EQ..."),
+//!     amount: Some(1.5),
+//!     amount_usd: None,
+//!     percent: None,
+//!     bounce: None,
+//!     send_mode: None
+//! }] }).await?;
+//! let status = client.status(receipt.query_id.unwrap()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+pub use mixer_types as types;
+
+use types::{
+    jobs::Job, outbox::OperationLookup, CollectPayload, OperationReceipt, Response, SpreadPayload
+};
+
+/// Prefix of the versioned mixer routes.
+const MIXER_PREFIX: &str = "/v1/mixer";
+
+/// Represents an error of a request to the mixer API.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request could not be sent or its response could not be read.
+    Http(reqwest::Error),
+    /// The API answered with an error status, with the error response it sent.
+    Api {
+        status: StatusCode,
+        response: Response
+    },
+    /// The API answered with a body that is not the expected type.
+    Decode(serde_json::Error)
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(err) => write!(f, "request failed: {}", err),
+            ClientError::Api { status, response } => match &response.message {
+                Value::String(message) => write!(f, "{}: {}", status, message),
+                // e.g. the failed rules of a validation error
+                message => write!(f, "{}: {}", status, message)
+            },
+            ClientError::Decode(err) => write!(f, "unexpected response: {}", err)
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(err: reqwest::Error) -> ClientError {
+        ClientError::Http(err)
+    }
+}
+
+/// Represents the outcome of a collect.
+#[derive(Debug, Clone)]
+pub enum Collected {
+    /// The collect was sent.
+    Sent(OperationReceipt),
+    /// The collect waits for `hold_hours` and was scheduled as a job.
+    Scheduled(Job)
+}
+
+/// Represents a client of the mixer API.
+#[derive(Debug, Clone)]
+pub struct MixerClient {
+    http: reqwest::Client,
+    base_url: String,
+    wait_for_confirmation: bool
+}
+
+impl MixerClient {
+    /// Creates a client of the API served at a base URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The URL of the server, e.g. `http://localhost:8080`.
+    pub fn new(base_url: &str) -> MixerClient {
+        return MixerClient::with_client(reqwest::Client::new(), base_url);
+    }
+
+    /// Creates a client sending requests with a configured `reqwest::Client`, e.g. to set
+    /// timeouts or a proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `http` - The HTTP client.
+    /// * `base_url` - The URL of the server, e.g. `http://localhost:8080`.
+    pub fn with_client(http: reqwest::Client, base_url: &str) -> MixerClient {
+        return MixerClient { http, base_url: base_url.trim_end_matches('/').to_string(), wait_for_confirmation: false };
+    }
+
+    /// Makes spread, collect and fork wait until their message is applied on chain, so
+    /// receipts carry the `transaction` it was applied in.
+    pub fn wait_for_confirmation(mut self, wait: bool) -> MixerClient {
+        self.wait_for_confirmation = wait;
+        return self;
+    }
+
+    /// Spreads funds from a mixer contract to the recipients.
+    ///
+    /// # Arguments
+    ///
+    /// * `contract` - The allow-listed mixer contract, `MIXER_CONTRACT` of the server if `None`.
+    /// * `payload` - The recipients and their amounts.
+    ///
+    /// # Returns
+    ///
+    /// The receipt of the sent message.
+    pub async fn spread(&self, contract: Option<&str>, payload: &SpreadPayload) -> Result<OperationReceipt, ClientError> {
+        let request: RequestBuilder = self.operation("/spread", contract).json(payload);
+
+        return self.send(request).await.map(| (_, receipt) | receipt);
+    }
+
+    /// Collects funds from a mixer contract.
+    ///
+    /// # Arguments
+    ///
+    /// * `contract` - The allow-listed mixer contract, `MIXER_CONTRACT` of the server if `None`.
+    /// * `payload` - The mode of the collect and its options.
+    ///
+    /// # Returns
+    ///
+    /// The receipt of the sent message, or the job of a collect that waits for its hold.
+    pub async fn collect(&self, contract: Option<&str>, payload: &CollectPayload) -> Result<Collected, ClientError> {
+        let request: RequestBuilder = self.operation("/collect", contract).json(payload);
+        let (status, body): (StatusCode, Value) = self.send(request).await?;
+
+        let collected: Collected = if status == StatusCode::ACCEPTED {
+            Collected::Scheduled(serde_json::from_value(body).map_err(ClientError::Decode)?)
+        } else {
+            Collected::Sent(serde_json::from_value(body).map_err(ClientError::Decode)?)
+        };
+
+        return Ok(collected);
+    }
+
+    /// Forks a mixer contract.
+    ///
+    /// # Arguments
+    ///
+    /// * `contract` - The allow-listed mixer contract, `MIXER_CONTRACT` of the server if `None`.
+    /// * `query_id` - The query id of the fork, the current time of the server if `None`.
+    ///
+    /// # Returns
+    ///
+    /// The receipt of the sent message.
+    pub async fn fork(&self, contract: Option<&str>, query_id: Option<u64>) -> Result<OperationReceipt, ClientError> {
+        let mut request: RequestBuilder = self.operation("/fork", contract);
+        if let Some(query_id) = query_id {
+            request = request.query(&[("query_id", query_id)]);
+        }
+
+        return self.send(request).await.map(| (_, receipt) | receipt);
+    }
+
+    /// Looks up the status of an operation, its outbox entries and indexed transactions.
+    ///
+    /// # Arguments
+    ///
+    /// * `query_id` - The query id of the operation, as returned in its receipt.
+    pub async fn status(&self, query_id: u64) -> Result<OperationLookup, ClientError> {
+        let request: RequestBuilder = self.request(Method::GET, &format!("/operations/by-query-id/{}", query_id), None);
+
+        return self.send(request).await.map(| (_, lookup) | lookup);
+    }
+
+    /// Builds a request to a mixer route, with the contract as query parameter if any.
+    fn request(&self, method: Method, path: &str, contract: Option<&str>) -> RequestBuilder {
+        let mut request: RequestBuilder = self.http.request(method, format!("{}{}{}", self.base_url, MIXER_PREFIX, path));
+        if let Some(contract) = contract {
+            request = request.query(&[("contract", contract)]);
+        }
+
+        return request;
+    }
+
+    /// Builds a request sending an operation, asking to wait for its confirmation if configured.
+    fn operation(&self, path: &str, contract: Option<&str>) -> RequestBuilder {
+        let request: RequestBuilder = self.request(Method::POST, path, contract);
+        if !self.wait_for_confirmation {
+            return request;
+        }
+
+        return request.query(&[("wait_for_confirmation", true)]);
+    }
+
+    /// Sends a request and decodes its body.
+    ///
+    /// Error bodies are decoded as the `Response` envelope of the API, or wrapped in one
+    /// when they are not JSON, e.g. errors of proxies in front of the server.
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<(StatusCode, T), ClientError> {
+        let response: reqwest::Response = request.send().await?;
+        let status: StatusCode = response.status();
+        let body: String = response.text().await?;
+
+        if !status.is_success() {
+            let response: Response = serde_json::from_str(&body)
+                .unwrap_or_else(|_| Response::error(Value::String(body)));
+            return Err(ClientError::Api { status, response });
+        }
+
+        let value: T = serde_json::from_str(&body).map_err(ClientError::Decode)?;
+
+        return Ok((status, value));
+    }
+}
@@ -0,0 +1,8 @@
+//! # Address Book Types
+//!
+//! This module defines the address book names attached to responses.
+
+use std::collections::BTreeMap;
+
+/// Names of the known addresses of a response, keyed by the addresses as they appear in it.
+pub type AddressLabels = BTreeMap<String, String>;
@@ -0,0 +1,30 @@
+//! # Mixer Event Types
+//!
+//! This module defines the decoded transactions of the mixer contract and its forks.
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::explorer::TransactionLinks;
+
+/// Represents a decoded transaction of a tracked contract.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct MixerEvent {
+    pub contract: String,
+    pub lt: i64,
+    pub hash: String,
+    pub utime: i64,
+    pub op: String,
+    pub query_id: Option<i64>,
+    pub mode: Option<i16>,
+    pub source: Option<String>,
+    pub value_in: i64,
+    pub value_out: i64,
+    pub total_fees: i64,
+    pub fwd_fees: i64,
+    pub body: Option<Value>,
+    #[cfg_attr(feature = "sqlx", sqlx(skip))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<TransactionLinks>
+}
@@ -0,0 +1,36 @@
+//! # Explorer Link Types
+//!
+//! This module defines the block explorer links attached to operation responses and history entries.
+
+use serde::{Serialize, Deserialize};
+
+/// Represents the pages of one hash or address on the supported explorers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ExplorerLinks {
+    pub tonviewer: String,
+    pub tonscan: String
+}
+
+/// Represents the explorer links of a sent operation.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OperationLinks {
+    /// The external message, by its normalized hash.
+    pub message: ExplorerLinks,
+    pub wallet: ExplorerLinks,
+    pub contract: ExplorerLinks
+}
+
+/// Represents the explorer links of an indexed transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TransactionLinks {
+    pub transaction: ExplorerLinks,
+    pub contract: ExplorerLinks
+}
+
+/// Represents the explorer links of an outbox entry.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OutboxLinks {
+    /// The external message, once its normalized hash is known.
+    pub message: Option<ExplorerLinks>,
+    pub wallet: ExplorerLinks
+}
@@ -0,0 +1,35 @@
+//! # Job Types
+//!
+//! This module defines the background jobs operations are scheduled as.
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+/// Represents a background job.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: Value,
+    pub status: String,
+    pub run_at: i64,
+    /// Due jobs are claimed by priority, higher first, raised while they wait.
+    pub priority: i32,
+    pub attempts: i32,
+    /// Receipt of the sent operation.
+    pub result: Option<String>,
+    pub error: Option<String>,
+    pub deposit_id: Option<i64>,
+    /// Progress reported by a running job, e.g. the `RebalanceProgress` of a rebalance.
+    pub progress: Option<Value>,
+    pub cancelled_at: Option<i64>,
+    pub cancel_reason: Option<String>,
+    /// When the job was last held by a limit.
+    pub held_at: Option<i64>,
+    /// When an operator approved running the held job despite the spread limits, and who.
+    pub approved_at: Option<i64>,
+    pub approved_by: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64
+}
@@ -0,0 +1,193 @@
+//! # Mixer Types
+//!
+//! This crate defines the payloads and responses of the mixer API that clients need, with
+//! no dependency on the server, so `mixer-client` does not pull in actix, sqlx or tonlib.
+//!
+//! The server re-exports the response types from here, so they can not drift from what it
+//! sends. The request payloads and the `Response` envelope are plain mirrors of the ones of
+//! the server, which additionally validate their fields and redact error messages.
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+pub mod addressbook;
+pub mod events;
+pub mod explorer;
+pub mod jobs;
+pub mod outbox;
+
+/// Represents the status of a response.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ResponseStatus {
+    #[serde(rename = "error")]
+    Error,
+    #[serde(rename = "info")]
+    Info,
+    #[serde(rename = "success")]
+    Success
+}
+
+/// Represents a response with a status and a message.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Response {
+    pub status: ResponseStatus,
+    pub message: Value,
+    /// Id of the request, so a failure can be found in the logs of the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Non-fatal issues of the request.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>
+}
+
+impl Response {
+    /// Creates a new error response.
+    pub fn error(message: Value) -> Response {
+        Response{
+            status: ResponseStatus::Error,
+            message,
+            request_id: None,
+            warnings: Vec::new()
+        }
+    }
+}
+
+/// Represents the hashes identifying a sent external message.
+///
+/// `hex`/`base64` hold the message hash returned by the liteserver, while
+/// `normalized_hex`/`normalized_base64` hold the normalized external-in message
+/// hash (TEP-467) that explorers and indexers use to look the message up.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TXHash {
+    pub hex: String,
+    pub base64: String,
+    pub normalized_hex: String,
+    pub normalized_base64: String
+}
+
+impl TXHash {
+    pub fn new(hex: String, base64: String, normalized_hex: String, normalized_base64: String) -> Self {
+        TXHash{
+            hex,
+            base64,
+            normalized_hex,
+            normalized_base64
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        serde_json::to_string::<TXHash>(self).unwrap()
+    }
+}
+
+/// Represents the result of a submitted operation.
+///
+/// Besides the message hashes it carries everything needed to match the
+/// response with the transaction on chain: the wallet seqno the message was
+/// signed with, the query id of the body, the contract the message targets,
+/// the nanotons attached for gas and the time the message stops being valid,
+/// plus explorer links of the message, the wallet and the contract.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OperationReceipt {
+    #[serde(flatten)]
+    pub hash: TXHash,
+    pub op: String,
+    pub seqno: u32,
+    pub query_id: Option<u64>,
+    pub contract: String,
+    pub gas: u64,
+    pub valid_until: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usd_rate: Option<f64>,
+    #[serde(default)]
+    pub links: explorer::OperationLinks,
+    /// Estimated forward fees of the messages the contract sends, for spreads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fees: Option<SpreadFees>,
+    /// The wallet transaction the message was applied in, set when the caller waited for confirmation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<AppliedTransaction>
+}
+
+impl OperationReceipt {
+    pub fn to_string(&self) -> String {
+        serde_json::to_string::<OperationReceipt>(self).unwrap()
+    }
+}
+
+/// Represents the wallet transaction an external message was applied in.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AppliedTransaction {
+    pub lt: i64,
+    /// Hash of the transaction in hex.
+    pub hash: String,
+    pub utime: i64
+}
+
+/// Represents the estimated forward fee of one leg of a spread.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpreadLegFee {
+    pub account: String,
+    /// Nanotons sent to the recipient.
+    pub amount: u64,
+    /// Nanotons the forward fee of the message takes from the amount.
+    pub fwd_fee: u64,
+    /// Nanotons the recipient is expected to receive.
+    pub received: u64
+}
+
+/// Represents the estimated fees of the messages a spread makes the contract send.
+///
+/// The contract sends every leg without paying fees separately, so the forward fee is
+/// deducted from the amount the recipient receives.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpreadFees {
+    pub legs: Vec<SpreadLegFee>,
+    /// Nanotons of all forward fees.
+    pub total_fwd_fees: u64,
+    /// Nanotons of the forward fees charged in the action phase of the contract.
+    pub total_action_fees: u64
+}
+
+/// Represents the body of a spread operation: the list of its recipients.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(transparent)]
+pub struct SpreadPayload {
+    pub wallets: Vec<SpreadWalletPayload>
+}
+
+/// Represents a recipient of a spread.
+///
+/// Exactly one of `amount` in TON, `amount_usd` and `percent` of the total is given.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpreadWalletPayload {
+    /// The recipient, an address or `@name` of an address book entry.
+    pub account: String,
+    pub amount: Option<f64>,
+    pub amount_usd: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    #[serde(default)]
+    pub bounce: Option<bool>,
+    /// Send mode of the transfer, for direct spreads only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_mode: Option<u8>
+}
+
+/// Represents the payload for a collect operation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectPayload {
+    pub mode: u8,
+    /// The jetton wallet collected from, an address or `@name` of an address book entry.
+    pub jetton_wallet: Option<String>,
+    pub amount: Option<f64>,
+    /// Hours the newest uncollected funds must have been on the contract before the collect is sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_dwell_hours: Option<u32>,
+    /// Priority of the scheduled collect job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Send mode of the message to the contract.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_mode: Option<u8>
+}
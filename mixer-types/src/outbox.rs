@@ -0,0 +1,41 @@
+//! # Outbox Types
+//!
+//! This module defines the outbox entries of sent operations and their lookup by query id.
+
+use serde::{Serialize, Deserialize};
+
+use crate::{addressbook::AddressLabels, events::MixerEvent, explorer::OutboxLinks};
+
+/// Represents a signed external message stored in the outbox.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub wallet: String,
+    pub op: String,
+    pub query_id: Option<i64>,
+    pub seqno: i64,
+    pub valid_until: i64,
+    #[serde(skip)]
+    pub boc: Vec<u8>,
+    pub status: String,
+    pub message_hash: Option<String>,
+    pub normalized_hash: Option<String>,
+    pub usd_rate: Option<f64>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[cfg_attr(feature = "sqlx", sqlx(skip))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<OutboxLinks>
+}
+
+/// Represents the operations and indexed transactions sharing a query id.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OperationLookup {
+    pub query_id: i64,
+    pub operations: Vec<OutboxEntry>,
+    pub transactions: Vec<MixerEvent>,
+    /// Address book names of the wallets, contracts and sources of the operation.
+    #[serde(default, skip_serializing_if = "AddressLabels::is_empty")]
+    pub labels: AddressLabels
+}
@@ -0,0 +1,123 @@
+//! # Low-balance Alerts
+//!
+//! This module implements a background task that watches the balances of the gas wallet
+//! and the mixer contract and notifies the configured channels when they drop below
+//! their thresholds, before spreads start failing with insufficient funds.
+
+use std::{collections::{BTreeMap, HashMap}, sync::Mutex, time::Duration};
+
+use serde_json::json;
+use sqlx::PgPool;
+use tonlib::address::TonAddress;
+
+use crate::{config, leader, notify::{Notification, Notifier}, ton, types::nanotons::Nanotons};
+
+/// Interval between balance checks in seconds, used when `ALERT_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 60;
+
+/// Warnings of the watched accounts whose balance is below the threshold, by account name.
+static LOW: Mutex<BTreeMap<&'static str, String>> = Mutex::new(BTreeMap::new());
+
+/// Returns a warning for every watched account whose balance was below its threshold at the last check.
+pub fn low_balances() -> Vec<String> {
+    LOW.lock().unwrap().values().cloned().collect()
+}
+
+/// Represents a watched account and the balance it must stay above.
+struct BalanceWatch {
+    name: &'static str,
+    address: TonAddress,
+    threshold: i64
+}
+
+/// Reads a TON amount threshold from the environment and converts it to nanotons.
+fn threshold_from_env(key: &str) -> Option<i64> {
+    Nanotons::parse_ton(&config::var(key).ok()?).ok().map(Nanotons::signed)
+}
+
+/// Runs the alerting loop forever.
+///
+/// Thresholds are configured in TON with `ALERT_WALLET_MIN_BALANCE` and
+/// `ALERT_CONTRACT_MIN_BALANCE`; the task exits right away when neither is set.
+/// An alert fires once when a balance drops below its threshold and is re-armed
+/// after the balance recovers. The notification channels follow configuration reloads.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool used for notification routing.
+pub async fn run(pool: PgPool) {
+    let mut watches: Vec<BalanceWatch> = Vec::new();
+
+    if let Some(threshold) = threshold_from_env("ALERT_WALLET_MIN_BALANCE") {
+        watches.push(BalanceWatch { name: "gas wallet", address: ton::wallet_address(), threshold });
+    }
+    if let Some(threshold) = threshold_from_env("ALERT_CONTRACT_MIN_BALANCE") {
+        watches.push(BalanceWatch { name: "mixer contract", address: ton::mixer_contract_address(), threshold });
+    }
+
+    if watches.is_empty() {
+        log_info!("Low-balance alerting is disabled");
+        return;
+    }
+
+    let interval: u64 = config::env_or("ALERT_INTERVAL", DEFAULT_INTERVAL);
+    let mut notifier: Notifier = Notifier::from_env(pool.clone());
+    let mut generation: u64 = config::generation();
+    let mut firing: HashMap<&'static str, bool> = HashMap::new();
+
+    loop {
+        // Pick up reloaded webhook URLs and channels
+        if generation != config::generation() {
+            generation = config::generation();
+            notifier = Notifier::from_env(pool.clone());
+        }
+
+        for watch in watches.iter() {
+            let balance: i64 = match ton::get_balance(&watch.address).await {
+                Ok(balance) => balance,
+                Err(err) => {
+                    log_error!("Can not fetch {} balance: {}", watch.name, err);
+                    continue;
+                }
+            };
+
+            let low: bool = balance < watch.threshold;
+            let was_low: bool = firing.insert(watch.name, low).unwrap_or(false);
+
+            match low {
+                true => LOW.lock().unwrap().insert(watch.name, format!(
+                    "{} balance is low: {} TON, below {} TON",
+                    watch.name,
+                    Nanotons::from_signed(balance),
+                    Nanotons::from_signed(watch.threshold)
+                )),
+                false => LOW.lock().unwrap().remove(watch.name)
+            };
+
+            // every instance keeps its warnings, the leader alone notifies
+            if low && !was_low && leader::is_leader() {
+                let notification: Notification = Notification::new(
+                    "low_balance",
+                    format!(
+                        "Balance of the {} {} is {} TON, below the threshold of {} TON",
+                        watch.name,
+                        watch.address.to_base64_url(),
+                        Nanotons::from_signed(balance),
+                        Nanotons::from_signed(watch.threshold)
+                    ),
+                    json!({
+                        "account": watch.name,
+                        "address": watch.address.to_base64_url(),
+                        "balance": balance,
+                        "threshold": watch.threshold
+                    })
+                );
+
+                log_warn!("{}", notification.message);
+                notifier.send(&notification).await;
+            }
+        }
+
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
@@ -0,0 +1,39 @@
+//! # Authentication
+//!
+//! This module implements the middleware protecting administrative routes.
+//! Requests must carry the `ADMIN_TOKEN` from the environment as a bearer token, which is
+//! compared in constant time like every token of `API_KEYS`, see `rbac`.
+//! Irreversible requests must be signed by an operator on top of it, see `operator`, and
+//! writes can be restricted to the networks of the backends, see `network`. The mixer routes
+//! are grouped by the role of `API_KEYS` they require, see `rbac`.
+
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, error::ErrorUnauthorized, http::header::{HeaderMap, AUTHORIZATION}, middleware::Next, Error};
+use serde_json::Value;
+
+use crate::types::Response;
+
+pub mod network;
+pub mod operator;
+pub mod rbac;
+
+/// Extracts the bearer token from the `Authorization` header of a request.
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(| v | v.to_str().ok())
+        .and_then(| v | v.strip_prefix("Bearer "))
+        .map(| v | v.trim().to_string())
+}
+
+/// Rejects requests that do not carry the admin bearer token or an admin key of `API_KEYS`.
+///
+/// All requests are rejected when neither is set, so the admin API is never left open by a
+/// missing configuration.
+pub async fn require_admin(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    match rbac::role_of(&req) {
+        Some(rbac::Role::Admin) => next.call(req).await,
+        _ => Err(ErrorUnauthorized(
+            Response::error(Value::String(String::from("valid admin bearer token is required"))).to_string()
+        ))
+    }
+}
@@ -0,0 +1,310 @@
+//! # Network Allow-List
+//!
+//! This module implements the middleware restricting write requests, every method other than
+//! `GET`, `HEAD` and `OPTIONS`, to clients in the CIDR ranges of `WRITE_ALLOWED_CIDRS`. Reads
+//! and probes stay reachable from anywhere, and without `WRITE_ALLOWED_CIDRS` the middleware
+//! lets every request through.
+//!
+//! The client is the peer of the connection, unless the peer is a proxy in `TRUSTED_PROXIES`:
+//! then `X-Forwarded-For` is read from the right, skipping trusted proxies, and the first
+//! address that is not one is the client. Entries left of it were written by the client and
+//! can not be trusted.
+
+use std::{net::IpAddr, str::FromStr, sync::{Arc, RwLock}};
+
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, error::ErrorForbidden, http::{header::HeaderMap, Method}, middleware::Next, Error};
+use serde_json::Value;
+
+use crate::{config, types::Response};
+
+/// Represents a range of addresses in CIDR notation, e.g. `10.0.0.0/8` or `fd00::/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8
+}
+
+impl Cidr {
+    /// Returns whether the address is in the range, IPv4-mapped IPv6 addresses as IPv4 ones.
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        match (self.network, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask: u32 = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask: u128 = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            },
+            _ => false
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// Parses a range, a bare address being a range of one.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix): (&str, Option<&str>) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None)
+        };
+
+        let network: IpAddr = address.trim().parse::<IpAddr>().map_err(| e | format!("`{}` is no address: {}", s, e))?.to_canonical();
+        let max: u8 = if network.is_ipv4() { 32 } else { 128 };
+        let prefix: u8 = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().ok().filter(| p | *p <= max).ok_or_else(|| format!("`{}` has an invalid prefix", s))?,
+            None => max
+        };
+
+        Ok(Cidr { network, prefix })
+    }
+}
+
+/// Represents the ranges writes are allowed from and the proxies forwarding requests.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkRules {
+    /// Ranges of the clients allowed to write, writes are not restricted if empty.
+    pub write_allowed: Vec<Cidr>,
+    pub trusted_proxies: Vec<Cidr>
+}
+
+/// The rules with the configuration generation they were loaded in.
+static RULES: RwLock<Option<(u64, Arc<NetworkRules>)>> = RwLock::new(None);
+
+/// Parses a comma-separated list of ranges from the environment.
+fn cidrs_from_env(key: &str) -> Result<Vec<Cidr>, String> {
+    config::env_or(key, String::new())
+        .split(',')
+        .map(| c | c.trim())
+        .filter(| c | !c.is_empty())
+        .map(| c | c.parse::<Cidr>().map_err(| e | format!("`{}` has an invalid range: {}", key, e)))
+        .collect()
+}
+
+impl NetworkRules {
+    /// Loads the rules from `WRITE_ALLOWED_CIDRS` and `TRUSTED_PROXIES`.
+    pub fn from_env() -> Result<NetworkRules, String> {
+        Ok(NetworkRules {
+            write_allowed: cidrs_from_env("WRITE_ALLOWED_CIDRS")?,
+            trusted_proxies: cidrs_from_env("TRUSTED_PROXIES")?
+        })
+    }
+
+    /// Returns whether the address belongs to a trusted proxy.
+    fn trusted(&self, address: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(| c | c.contains(address))
+    }
+
+    /// Returns the address of the client of a request, see the module documentation.
+    pub fn client(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let peer: IpAddr = peer?;
+        if !self.trusted(&peer) {
+            return Some(peer);
+        }
+
+        let hops: &str = match forwarded_for.filter(| f | !f.trim().is_empty()) {
+            Some(hops) => hops,
+            None => return Some(peer)
+        };
+
+        let mut client: IpAddr = peer;
+        for hop in hops.rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(address) if self.trusted(&address) => client = address,
+                Ok(address) => return Some(address),
+                // a hop that is not an address ends what the proxies vouch for
+                Err(_) => return None
+            }
+        }
+
+        Some(client)
+    }
+}
+
+/// Returns all lines of `X-Forwarded-For` joined in order, `None` without the header.
+///
+/// A proxy may add its hop as a separate line instead of appending it to the one the client
+/// sent, so reading only the first line could take a client-written hop for the rightmost one.
+/// Unreadable bytes are kept as replacement characters, which no hop parses from.
+pub fn forwarded_for(headers: &HeaderMap) -> Option<String> {
+    let lines: Vec<String> = headers.get_all("X-Forwarded-For")
+        .map(| v | String::from_utf8_lossy(v.as_bytes()).into_owned())
+        .collect();
+
+    match lines.is_empty() {
+        true => None,
+        false => Some(lines.join(", "))
+    }
+}
+
+/// Returns the network rules, loading them again if the configuration was reloaded.
+///
+/// # Panics
+///
+/// Panics if the rules were never loaded and a range is invalid. Invalid ranges after a
+/// reload are logged and the previous rules stay in effect.
+pub fn rules() -> Arc<NetworkRules> {
+    let generation: u64 = config::generation();
+
+    if let Some((loaded, rules)) = RULES.read().unwrap().as_ref() {
+        if *loaded == generation {
+            return rules.clone();
+        }
+    }
+
+    let mut cached = RULES.write().unwrap();
+    let rules: Arc<NetworkRules> = match (NetworkRules::from_env(), cached.take()) {
+        (Ok(rules), _) => Arc::new(rules),
+        (Err(err), None) => panic!("[ FATAL ] Configuration Error: {}", err),
+        (Err(err), Some((_, previous))) => {
+            log_error!("Can not reload the network allow-list, keeping the previous one: {}", err);
+            previous
+        }
+    };
+
+    *cached = Some((generation, rules.clone()));
+    rules
+}
+
+/// Rejects write requests of clients outside `WRITE_ALLOWED_CIDRS` with a 403 error.
+pub async fn restrict_writes(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let rules: Arc<NetworkRules> = rules();
+
+    if rules.write_allowed.is_empty() || [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) {
+        return next.call(req).await;
+    }
+
+    let forwarded_for: Option<String> = forwarded_for(req.headers());
+    let client: Option<IpAddr> = rules.client(req.peer_addr().map(| a | a.ip()), forwarded_for.as_deref());
+
+    match client {
+        Some(client) if rules.write_allowed.iter().any(| c | c.contains(&client)) => next.call(req).await,
+        _ => {
+            let client: String = client.map(| c | c.to_string()).unwrap_or_else(|| String::from("unknown"));
+            log_warn!("Rejected {} {} from {} outside the write allow-list", req.method(), req.path(), client);
+
+            Err(ErrorForbidden(
+                Response::error(Value::String(format!("writes are not allowed from {}", client))).to_string()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse::<Cidr>().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse::<IpAddr>().unwrap()
+    }
+
+    /// Rules trusting the proxies of `10.0.0.0/8`.
+    fn behind_proxies() -> NetworkRules {
+        NetworkRules { write_allowed: vec![cidr("192.0.2.0/24")], trusted_proxies: vec![cidr("10.0.0.0/8")] }
+    }
+
+    #[test]
+    fn contains_addresses_of_the_range() {
+        let range: Cidr = cidr("10.1.0.0/16");
+
+        assert!(range.contains(&ip("10.1.0.0")));
+        assert!(range.contains(&ip("10.1.255.255")));
+        assert!(!range.contains(&ip("10.2.0.0")));
+        assert!(!range.contains(&ip("::ffff:10.2.0.1")));
+        assert!(cidr("fd00::/8").contains(&ip("fd12::1")));
+        assert!(!cidr("fd00::/8").contains(&ip("fe80::1")));
+    }
+
+    #[test]
+    fn matches_ipv4_mapped_ipv6_as_ipv4() {
+        assert!(cidr("10.0.0.0/8").contains(&ip("::ffff:10.1.2.3")));
+        assert!(cidr("::ffff:10.0.0.0/8").contains(&ip("10.1.2.3")));
+        assert!(!cidr("::/0").contains(&ip("::ffff:10.1.2.3")));
+    }
+
+    #[test]
+    fn zero_prefix_contains_its_family_only() {
+        assert!(cidr("0.0.0.0/0").contains(&ip("203.0.113.9")));
+        assert!(cidr("0.0.0.0/0").contains(&ip("255.255.255.255")));
+        assert!(!cidr("0.0.0.0/0").contains(&ip("2001:db8::1")));
+        assert!(cidr("::/0").contains(&ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn full_prefix_contains_one_address() {
+        assert_eq!(cidr("192.0.2.7"), cidr("192.0.2.7/32"));
+        assert!(cidr("192.0.2.7/32").contains(&ip("192.0.2.7")));
+        assert!(!cidr("192.0.2.7/32").contains(&ip("192.0.2.6")));
+        assert!(cidr("2001:db8::7/128").contains(&ip("2001:db8::7")));
+        assert!(!cidr("2001:db8::7/128").contains(&ip("2001:db8::8")));
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        for range in ["10.0.0.0/33", "::/129", "10.0.0.0/-1", "10.0.0.0/", "10.0.0/8", "example.com"] {
+            assert!(range.parse::<Cidr>().is_err(), "`{}` was accepted", range);
+        }
+    }
+
+    #[test]
+    fn untrusted_peer_is_the_client() {
+        let rules: NetworkRules = behind_proxies();
+
+        assert_eq!(rules.client(Some(ip("203.0.113.9")), Some("192.0.2.1")), Some(ip("203.0.113.9")));
+        assert_eq!(rules.client(None, Some("192.0.2.1")), None);
+    }
+
+    #[test]
+    fn trusted_peer_without_hops_is_the_client() {
+        let rules: NetworkRules = behind_proxies();
+
+        assert_eq!(rules.client(Some(ip("10.0.0.1")), None), Some(ip("10.0.0.1")));
+        assert_eq!(rules.client(Some(ip("10.0.0.1")), Some(" ")), Some(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn skips_trusted_hops_from_the_right() {
+        let rules: NetworkRules = behind_proxies();
+
+        assert_eq!(rules.client(Some(ip("10.0.0.1")), Some("203.0.113.9, 10.0.0.2")), Some(ip("203.0.113.9")));
+        assert_eq!(rules.client(Some(ip("10.0.0.1")), Some("10.0.0.3,10.0.0.2")), Some(ip("10.0.0.3")));
+    }
+
+    #[test]
+    fn ignores_spoofed_hops_left_of_the_client() {
+        let rules: NetworkRules = behind_proxies();
+
+        assert_eq!(rules.client(Some(ip("10.0.0.1")), Some("192.0.2.1, 203.0.113.9")), Some(ip("203.0.113.9")));
+        assert_eq!(rules.client(Some(ip("10.0.0.1")), Some("10.0.0.5, 203.0.113.9, 10.0.0.2")), Some(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn non_address_hop_has_no_client() {
+        let rules: NetworkRules = behind_proxies();
+
+        assert_eq!(rules.client(Some(ip("10.0.0.1")), Some("unknown")), None);
+        assert_eq!(rules.client(Some(ip("10.0.0.1")), Some("192.0.2.1, unknown, 10.0.0.2")), None);
+        assert_eq!(rules.client(Some(ip("10.0.0.1")), Some("192.0.2.1,, 10.0.0.2")), None);
+    }
+
+    #[test]
+    fn joins_header_lines_in_order() {
+        let mut headers: HeaderMap = HeaderMap::new();
+        assert_eq!(forwarded_for(&headers), None);
+
+        headers.append(HeaderName::from_static("x-forwarded-for"), HeaderValue::from_static("192.0.2.1, 203.0.113.9"));
+        headers.append(HeaderName::from_static("x-forwarded-for"), HeaderValue::from_static("10.0.0.2"));
+        let hops: Option<String> = forwarded_for(&headers);
+
+        assert_eq!(hops.as_deref(), Some("192.0.2.1, 203.0.113.9, 10.0.0.2"));
+        assert_eq!(behind_proxies().client(Some(ip("10.0.0.1")), hops.as_deref()), Some(ip("203.0.113.9")));
+    }
+}
@@ -0,0 +1,308 @@
+//! # Operator Signatures
+//!
+//! This module verifies the ed25519 signatures required on irreversible requests, like
+//! sweeping the contracts, pausing the mixer or upgrading the contract code. A bearer token
+//! leaks with any log or proxy it passes, a signature only authorizes the request it was
+//! made for. Signed requests carry three headers:
+//!
+//! - `X-Operator` - the name of an operator key registered in `OPERATOR_KEYS`
+//! - `X-Operator-Timestamp` - the Unix time the request was signed at
+//! - `X-Operator-Signature` - the hex ed25519 signature of
+//!   `{timestamp}.{method}.{path and query}.{canonical body}` with the key of the operator
+//!
+//! The canonical body is the JSON body without whitespace and with the keys of every object
+//! sorted, or empty for a request without a body. Timestamps further than
+//! `OPERATOR_SIGNATURE_WINDOW` seconds from the server time are rejected, and so are
+//! requests already accepted within the window by any instance. A replay is recognized by
+//! the operator and the signed message rather than by the signature, as ed25519 signatures
+//! can be altered without invalidating them.
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::OnceLock};
+
+use actix_web::{dev::Payload, error::{ErrorBadRequest, ErrorInternalServerError, ErrorUnauthorized}, web::{Bytes, Data}, Error, FromRequest, HttpRequest};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use validator::Validate;
+
+use crate::{config, db, ton, types::Response};
+
+/// Seconds a signature is accepted around its timestamp, used when `OPERATOR_SIGNATURE_WINDOW` is not set.
+const DEFAULT_SIGNATURE_WINDOW: u64 = 300;
+
+/// The operator keys, parsed once on startup.
+static OPERATOR_KEYS: OnceLock<HashMap<String, Vec<u8>>> = OnceLock::new();
+
+/// Returns the operator keys of `OPERATOR_KEYS`, given as `name:hex public key` pairs.
+///
+/// The keys are parsed on the first call, which `main` makes on startup.
+///
+/// # Panics
+///
+/// Panics if an entry is not a name and a 32 byte hex key.
+pub fn operator_keys() -> &'static HashMap<String, Vec<u8>> {
+    OPERATOR_KEYS.get_or_init(|| {
+        config::env_or("OPERATOR_KEYS", String::new())
+            .split(',')
+            .map(| k | k.trim())
+            .filter(| k | !k.is_empty())
+            .map(| entry | match entry.split_once(':').map(| (name, key) | (name.trim(), hex::decode(key.trim()))) {
+                Some((name, Ok(key))) if !name.is_empty() && key.len() == 32 => (name.to_string(), key),
+                _ => panic!("[ FATAL ] Configuration Error: `OPERATOR_KEYS` has an invalid entry `{}`", entry)
+            })
+            .collect()
+    })
+}
+
+/// Returns the body as signed: compact JSON with sorted keys, or empty.
+fn canonical_body(body: &[u8]) -> Result<String, String> {
+    if body.iter().all(| b | b.is_ascii_whitespace()) {
+        return Ok(String::new());
+    }
+
+    // objects of `Value` keep their keys sorted
+    let value: Value = serde_json::from_slice(body).map_err(| e | format!("body is not JSON: {}", e))?;
+
+    Ok(value.to_string())
+}
+
+/// Returns the message an operator signs for a request, see the module documentation.
+fn signed_message(timestamp: u64, method: &str, path: &str, body: &[u8]) -> Result<String, String> {
+    Ok(format!("{}.{}.{}.{}", timestamp, method, path, canonical_body(body)?))
+}
+
+/// Returns whether the signature is a valid ed25519 signature of the message with the key.
+fn signature_matches(key: &[u8], signature: &[u8], message: &str) -> bool {
+    nacl::sign::verify(signature, message.as_bytes(), key).unwrap_or(false)
+}
+
+/// Returns the digest a request is recorded under once accepted.
+///
+/// The operator and the message identify the request, whichever encoding of the signature
+/// came with it.
+fn request_digest(operator: &str, message: &str) -> String {
+    hex::encode(Sha256::digest(format!("{}.{}", operator, message).as_bytes()))
+}
+
+/// Returns the 401 error of a rejected signature.
+fn unauthorized(reason: &str) -> Error {
+    ErrorUnauthorized(Response::error(Value::String(format!("valid operator signature is required: {}", reason))).to_string())
+}
+
+/// Returns the value of a signature header.
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Result<&'a str, Error> {
+    req.headers()
+        .get(name)
+        .and_then(| v | v.to_str().ok())
+        .map(| v | v.trim())
+        .ok_or_else(|| unauthorized(&format!("header `{}` is missing", name)))
+}
+
+/// Verifies the operator signature of a request over its body.
+///
+/// # Returns
+///
+/// The name of the operator who signed the request, or a 401 error.
+async fn verify(req: &HttpRequest, body: &[u8]) -> Result<String, Error> {
+    let operator: &str = header(req, "X-Operator")?;
+    let timestamp: u64 = header(req, "X-Operator-Timestamp")?.parse().map_err(|_| unauthorized("timestamp is not a Unix time"))?;
+    let signature: Vec<u8> = hex::decode(header(req, "X-Operator-Signature")?).map_err(|_| unauthorized("signature is not hex"))?;
+
+    let key: &Vec<u8> = operator_keys().get(operator).ok_or_else(|| unauthorized(&format!("operator `{}` is not registered", operator)))?;
+
+    let window: u64 = config::env_or("OPERATOR_SIGNATURE_WINDOW", DEFAULT_SIGNATURE_WINDOW);
+    let now: u64 = ton::time_now();
+    if timestamp.abs_diff(now) > window {
+        return Err(unauthorized("timestamp is outside the signature window"));
+    }
+
+    let path: &str = req.uri().path_and_query().map(| p | p.as_str()).unwrap_or(req.path());
+    let message: String = signed_message(timestamp, req.method().as_str(), path, body)
+        .map_err(| e | ErrorBadRequest(Response::error(Value::String(e)).to_string()))?;
+
+    if !signature_matches(key, &signature, &message) {
+        log_warn!("Rejected signature of operator {} on {} {}", operator, req.method(), path);
+        return Err(unauthorized("signature does not match"));
+    }
+
+    let digest: String = request_digest(operator, &message);
+    let pool: &Data<PgPool> = req.app_data::<Data<PgPool>>()
+        .ok_or_else(|| ErrorInternalServerError(Response::error(Value::String(String::from("no database pool"))).to_string()))?;
+
+    match db::operators::claim(pool, &digest, operator, (timestamp + window) as i64).await {
+        Ok(true) => {},
+        Ok(false) => return Err(unauthorized("request was already accepted")),
+        Err(err) => return Err(ErrorInternalServerError(
+            Response::error(Value::String(format!("can not record the operator request: {}", err))).to_string()
+        ))
+    }
+
+    log_info!("Request {} {} signed by operator {}", req.method(), path, operator);
+    Ok(operator.to_string())
+}
+
+/// A request without a body signed by a registered operator, see `verify`.
+pub struct OperatorSignature {
+    /// The name of the operator who signed the request.
+    pub operator: String
+}
+
+impl FromRequest for OperatorSignature {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req: HttpRequest = req.clone();
+        let bytes = Bytes::from_request(&req, payload);
+
+        Box::pin(async move {
+            let body: Bytes = bytes.await?;
+
+            Ok(OperatorSignature { operator: verify(&req, &body).await? })
+        })
+    }
+}
+
+/// A JSON body signed by a registered operator that passed the validation rules of its type.
+///
+/// The signature is checked before the body is parsed, rejected bodies are answered like
+/// rejected `ValidatedJson` bodies.
+pub struct SignedJson<T> {
+    /// The name of the operator who signed the request.
+    pub operator: String,
+    pub body: T
+}
+
+impl<T> SignedJson<T> {
+    /// Unwraps the validated body.
+    pub fn into_inner(self) -> T {
+        self.body
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for SignedJson<T> {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req: HttpRequest = req.clone();
+        let bytes = Bytes::from_request(&req, payload);
+
+        Box::pin(async move {
+            let body: Bytes = bytes.await?;
+            let operator: String = verify(&req, &body).await?;
+
+            let value: T = serde_json::from_slice(&body).map_err(| e | {
+                ErrorBadRequest(Response::error(Value::String(format!("invalid JSON body: {}", e))).to_string())
+            })?;
+
+            if let Err(errors) = value.validate() {
+                return Err(ErrorBadRequest(
+                    Response::error(serde_json::to_value(&errors).unwrap()).to_string()
+                ));
+            }
+
+            Ok(SignedJson { operator, body: value })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the public and the secret key of an operator, a distinct pair for every seed.
+    fn key_pair(seed: u8) -> (Vec<u8>, Vec<u8>) {
+        let pair = nacl::sign::generate_keypair(&[seed; 32]);
+        (pair.pkey.to_vec(), pair.skey.to_vec())
+    }
+
+    /// Signs a message like an operator client does.
+    fn sign(secret_key: &[u8], message: &str) -> Vec<u8> {
+        nacl::sign::signature(message.as_bytes(), secret_key).unwrap()
+    }
+
+    #[test]
+    fn canonical_body_is_compact_with_sorted_keys() {
+        let body: &[u8] = b"{ \"b\": 1,\n  \"a\": { \"d\": [1, 2], \"c\": null } }";
+
+        assert_eq!(canonical_body(body).unwrap(), r#"{"a":{"c":null,"d":[1,2]},"b":1}"#);
+        assert_eq!(canonical_body(br#"{"a":{"c":null,"d":[1,2]},"b":1}"#).unwrap(), canonical_body(body).unwrap());
+        assert_eq!(canonical_body(b"[ \"x\" , 1.5 ]").unwrap(), r#"["x",1.5]"#);
+    }
+
+    #[test]
+    fn canonical_body_of_an_empty_body_is_empty() {
+        assert_eq!(canonical_body(b"").unwrap(), "");
+        assert_eq!(canonical_body(b" \r\n\t").unwrap(), "");
+    }
+
+    #[test]
+    fn canonical_body_rejects_other_than_json() {
+        assert!(canonical_body(b"amount=1").is_err());
+        assert!(canonical_body(b"{\"a\": 1").is_err());
+    }
+
+    #[test]
+    fn signed_message_joins_the_parts() {
+        assert_eq!(signed_message(1700000000, "POST", "/admin/pause", b"").unwrap(), "1700000000.POST./admin/pause.");
+        assert_eq!(
+            signed_message(1700000000, "POST", "/admin/limits/daily/overrides?x=1", b"{\"b\": 2, \"a\": 1}").unwrap(),
+            "1700000000.POST./admin/limits/daily/overrides?x=1.{\"a\":1,\"b\":2}"
+        );
+    }
+
+    #[test]
+    fn accepts_the_signature_of_the_request() {
+        let (public_key, secret_key): (Vec<u8>, Vec<u8>) = key_pair(1);
+        let message: String = signed_message(1700000000, "POST", "/admin/pause", b"").unwrap();
+
+        assert!(signature_matches(&public_key, &sign(&secret_key, &message), &message));
+    }
+
+    #[test]
+    fn signature_holds_for_any_formatting_of_the_body() {
+        let (public_key, secret_key): (Vec<u8>, Vec<u8>) = key_pair(1);
+        let signed: String = signed_message(1700000000, "POST", "/admin/limits/daily/overrides", br#"{"a":1,"b":2}"#).unwrap();
+        let received: String = signed_message(1700000000, "POST", "/admin/limits/daily/overrides", b"{ \"b\": 2, \"a\": 1 }").unwrap();
+
+        assert!(signature_matches(&public_key, &sign(&secret_key, &signed), &received));
+    }
+
+    #[test]
+    fn rejects_signatures_of_other_requests_or_keys() {
+        let (public_key, secret_key): (Vec<u8>, Vec<u8>) = key_pair(1);
+        let (other_key, _): (Vec<u8>, Vec<u8>) = key_pair(2);
+        let message: String = signed_message(1700000000, "POST", "/admin/pause", b"").unwrap();
+        let signature: Vec<u8> = sign(&secret_key, &message);
+
+        for other in [
+            signed_message(1700000001, "POST", "/admin/pause", b"").unwrap(),
+            signed_message(1700000000, "POST", "/admin/resume", b"").unwrap(),
+            signed_message(1700000000, "POST", "/admin/pause", b"{}").unwrap()
+        ] {
+            assert!(!signature_matches(&public_key, &signature, &other), "signature was accepted for `{}`", other);
+        }
+        assert!(!signature_matches(&other_key, &signature, &message));
+        assert!(!signature_matches(&public_key, &signature[..63], &message));
+        assert!(!signature_matches(&public_key, &[0u8; 64], &message));
+    }
+
+    #[test]
+    fn replays_share_the_digest_of_the_request() {
+        let message: String = signed_message(1700000000, "POST", "/admin/pause", b"").unwrap();
+        let reformatted: String = signed_message(1700000000, "POST", "/admin/limits/daily/overrides", b"{ \"a\" : 1 }").unwrap();
+
+        assert_eq!(request_digest("alice", &message), request_digest("alice", &message));
+        assert_eq!(
+            request_digest("alice", &reformatted),
+            request_digest("alice", &signed_message(1700000000, "POST", "/admin/limits/daily/overrides", br#"{"a":1}"#).unwrap())
+        );
+        assert_ne!(request_digest("alice", &message), request_digest("bob", &message));
+        assert_ne!(
+            request_digest("alice", &message),
+            request_digest("alice", &signed_message(1700000001, "POST", "/admin/pause", b"").unwrap())
+        );
+    }
+}
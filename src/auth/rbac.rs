@@ -0,0 +1,233 @@
+//! # Role-Based Access Control
+//!
+//! This module maps the bearer tokens of `API_KEYS` to roles and the route groups of the mixer
+//! API to the role they require, so one deployment can serve reads publicly while spreads and
+//! collects need an operator token. Roles are ordered, a role grants everything the roles
+//! below it do:
+//!
+//! - `viewer` - reads the state of the mixer
+//! - `operator` - manages the address book and jobs, and invokes the contract
+//! - `admin` - everything, including the `/admin` API; `ADMIN_TOKEN` is an admin token
+//!
+//! A token of `API_KEYS` may name the tenant it belongs to as `role:token:tenant`, which
+//! selects per tenant settings like the mixing strategy of `MIX_STRATEGY_TENANTS`.
+//!
+//! `ROUTE_ROLES` sets the role of every group as `group:role` pairs, `public` letting anyone
+//! in. Groups it does not name keep their default: `read` is public, `manage` and `invoke`
+//! require `operator`, so writes stay closed until tokens are configured.
+//!
+//! Both variables are parsed on startup and again after a configuration reload. Tokens are
+//! kept as SHA-256 digests and a presented token is compared with every one of them in
+//! constant time, so the time of a check tells nothing about the configured tokens.
+
+use std::{str::FromStr, sync::{Arc, RwLock}};
+
+use actix_web::{dev::ServiceRequest, error::{ErrorForbidden, ErrorUnauthorized}, Error, HttpRequest};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{config, types::Response};
+
+use super::bearer_token;
+
+/// Represents the role of a token, ordered from the least to the most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("unknown role `{}`", s))
+        }
+    }
+}
+
+/// Represents a group of routes sharing the role they require.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteGroup {
+    /// Reads and requests that build messages for other wallets without sending anything.
+    Read,
+    /// Writes to the address book, recipient groups, jobs and deposits.
+    Manage,
+    /// Requests making the wallet send messages.
+    Invoke
+}
+
+impl RouteGroup {
+    /// Returns the name of the group in `ROUTE_ROLES`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RouteGroup::Read => "read",
+            RouteGroup::Manage => "manage",
+            RouteGroup::Invoke => "invoke"
+        }
+    }
+}
+
+/// Represents the tokens and the roles the route groups require.
+#[derive(Debug, Clone)]
+pub struct AccessRules {
+    /// SHA-256 digests of the tokens of `API_KEYS` and `ADMIN_TOKEN`, with their roles and tenants.
+    keys: Vec<([u8; 32], Role, Option<String>)>,
+    /// Role of the `read`, `manage` and `invoke` groups, `None` for a public one.
+    roles: [Option<Role>; 3]
+}
+
+/// The rules loaded from the environment and the configuration generation they were loaded in.
+static RULES: RwLock<Option<(u64, Arc<AccessRules>)>> = RwLock::new(None);
+
+/// Returns the SHA-256 digest of a token.
+fn digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+/// Compares two digests in constant time.
+///
+/// Every byte is compared and the difference is hidden from the optimizer, so the comparison
+/// is never turned into one that returns at the first differing byte.
+fn digests_match(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    let diff: u8 = a.iter().zip(b.iter()).fold(0u8, | diff, (x, y) | std::hint::black_box(diff | (x ^ y)));
+    std::hint::black_box(diff) == 0
+}
+
+impl AccessRules {
+    /// Loads the tokens of `API_KEYS`, given as `role:token` or `role:token:tenant` entries,
+    /// `ADMIN_TOKEN` as an admin token, and the roles of `ROUTE_ROLES`.
+    ///
+    /// # Returns
+    ///
+    /// The rules, or an error if an entry has no token, an unknown group or an unknown role.
+    pub fn from_env() -> Result<AccessRules, String> {
+        let mut keys: Vec<([u8; 32], Role, Option<String>)> = Vec::new();
+        for entry in config::env_or("API_KEYS", String::new()).split(',').map(| k | k.trim()).filter(| k | !k.is_empty()) {
+            let mut fields = entry.splitn(3, ':').map(str::trim);
+            let (role, token, tenant) = (fields.next().map(str::parse::<Role>), fields.next(), fields.next());
+
+            match (role, token, tenant) {
+                (Some(Ok(role)), Some(token), None) if !token.is_empty() => keys.push((digest(token), role, None)),
+                (Some(Ok(role)), Some(token), Some(tenant)) if !token.is_empty() && !tenant.is_empty() => {
+                    keys.push((digest(token), role, Some(tenant.to_string())));
+                },
+                _ => return Err(String::from("`API_KEYS` has an invalid entry"))
+            }
+        }
+
+        if let Some(admin_token) = config::var("ADMIN_TOKEN").ok().filter(| t | !t.is_empty()) {
+            keys.push((digest(&admin_token), Role::Admin, None));
+        }
+
+        let mut roles: [Option<Role>; 3] = [None, Some(Role::Operator), Some(Role::Operator)];
+        for entry in config::env_or("ROUTE_ROLES", String::new()).split(',').map(| r | r.trim()).filter(| r | !r.is_empty()) {
+            let (group, role): (RouteGroup, &str) = match entry.split_once(':') {
+                Some((name, role)) => match [RouteGroup::Read, RouteGroup::Manage, RouteGroup::Invoke].into_iter().find(| g | g.name() == name.trim()) {
+                    Some(group) => (group, role.trim()),
+                    None => return Err(format!("`ROUTE_ROLES` has an invalid entry `{}`", entry))
+                },
+                None => return Err(format!("`ROUTE_ROLES` has an invalid entry `{}`", entry))
+            };
+
+            roles[group as usize] = match role {
+                "public" => None,
+                _ => Some(role.parse::<Role>().map_err(| e | format!("`ROUTE_ROLES` has an {}", e))?)
+            };
+        }
+
+        Ok(AccessRules { keys, roles })
+    }
+
+    /// Returns the role of a token, `None` if it is not known.
+    ///
+    /// Every configured token is compared, so the time taken does not depend on which one matched.
+    pub fn role_of(&self, token: &str) -> Option<Role> {
+        let presented: [u8; 32] = digest(token);
+
+        self.keys.iter()
+            .filter(| (key, _, _) | digests_match(key, &presented))
+            .map(| (_, role, _) | *role)
+            .max()
+    }
+
+    /// Returns the tenant of a token, `None` if it is not known or belongs to no tenant.
+    pub fn tenant_of(&self, token: &str) -> Option<String> {
+        let presented: [u8; 32] = digest(token);
+
+        self.keys.iter()
+            .filter(| (key, _, _) | digests_match(key, &presented))
+            .find_map(| (_, _, tenant) | tenant.clone())
+    }
+
+    /// Returns the role a route group requires, `None` if it is public.
+    pub fn required_role(&self, group: RouteGroup) -> Option<Role> {
+        self.roles[group as usize]
+    }
+}
+
+/// Returns the access rules, loading them again if the configuration was reloaded.
+///
+/// # Panics
+///
+/// Panics if the rules were never loaded and an entry is invalid. Invalid entries after a
+/// reload are logged and the previous rules stay in effect.
+pub fn rules() -> Arc<AccessRules> {
+    let generation: u64 = config::generation();
+
+    if let Some((loaded, rules)) = RULES.read().unwrap().as_ref() {
+        if *loaded == generation {
+            return rules.clone();
+        }
+    }
+
+    let mut cached = RULES.write().unwrap();
+    let rules: Arc<AccessRules> = match (AccessRules::from_env(), cached.take()) {
+        (Ok(rules), _) => Arc::new(rules),
+        (Err(err), None) => panic!("[ FATAL ] Configuration Error: {}", err),
+        (Err(err), Some((_, previous))) => {
+            log_error!("Can not reload the access rules, keeping the previous ones: {}", err);
+            previous
+        }
+    };
+
+    *cached = Some((generation, rules.clone()));
+    rules
+}
+
+/// Returns the role of the bearer token of a request, `None` without a known token.
+pub fn role_of(req: &ServiceRequest) -> Option<Role> {
+    rules().role_of(&bearer_token(req.headers())?)
+}
+
+/// Returns the tenant of the bearer token of a request, `None` without a token of a tenant.
+pub fn tenant_of(req: &HttpRequest) -> Option<String> {
+    rules().tenant_of(&bearer_token(req.headers())?)
+}
+
+/// Checks that a request carries a token with the role its route group requires.
+///
+/// # Returns
+///
+/// Nothing, a 401 error without a known token, or a 403 error if the role is not enough.
+pub fn authorize(group: RouteGroup, req: &ServiceRequest) -> Result<(), Error> {
+    let required: Role = match rules().required_role(group) {
+        Some(required) => required,
+        None => return Ok(())
+    };
+
+    match role_of(req) {
+        Some(role) if role >= required => Ok(()),
+        Some(role) => Err(ErrorForbidden(Response::error(Value::String(format!(
+            "{} routes require the {:?} role, the token has the {:?} role", group.name(), required, role
+        ))).to_string())),
+        None => Err(ErrorUnauthorized(Response::error(Value::String(format!(
+            "{} routes require a bearer token with the {:?} role", group.name(), required
+        ))).to_string()))
+    }
+}
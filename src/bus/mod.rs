@@ -0,0 +1,235 @@
+//! # Event Bus
+//!
+//! This module publishes the lifecycle of operations to an event bus, so downstream
+//! accounting and fraud systems can consume a stream instead of polling the REST API.
+//! The bus is selected with `EVENT_BUS` and disabled by default:
+//!
+//! - `kafka` - records are produced through a Kafka REST proxy at `KAFKA_REST_URL`
+//! - `nats` - messages are published to the NATS server at `NATS_URL`, through `async-nats`,
+//!   which answers the pings of the server, reconnects on its own and speaks TLS for a
+//!   `tls://` URL
+//!
+//! Every event goes to the topic (subject in NATS) `{EVENT_BUS_PREFIX}.{type}`, e.g.
+//! `mixer.submitted`, as a JSON envelope with a unique `id`, the `type`, the Unix `time`
+//! and the event `data`. Types are:
+//!
+//! - `submitted` - a signed message of the wallet was broadcast
+//! - `confirmed` / `expired` - the wallet seqno moved past the message, or it expired
+//! - `bounced` - a message of a tracked contract bounced back to it
+//! - `collected` - the indexer saw a collect of a tracked contract
+//!
+//! Publishing never blocks an operation: events are queued in memory and sent by a
+//! background task, and dropped with an error log when the queue is full or the bus
+//! stays unreachable.
+
+use std::{sync::OnceLock, time::Duration};
+
+use async_nats::{ConnectOptions, Event};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::{config, logging::trace, ton};
+
+/// Number of events queued for publishing before new ones are dropped.
+const QUEUE_SIZE: usize = 4096;
+
+/// Number of attempts to publish an event.
+const PUBLISH_ATTEMPTS: u32 = 3;
+
+/// Timeout of connecting and publishing to the bus.
+const BUS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A message of the wallet was broadcast.
+pub const EVENT_SUBMITTED: &str = "submitted";
+
+/// The wallet seqno moved past a sent message, so it was applied.
+pub const EVENT_CONFIRMED: &str = "confirmed";
+
+/// A sent message expired before it was applied.
+pub const EVENT_EXPIRED: &str = "expired";
+
+/// A message of a tracked contract bounced back to it.
+pub const EVENT_BOUNCED: &str = "bounced";
+
+/// Funds were collected from a tracked contract.
+pub const EVENT_COLLECTED: &str = "collected";
+
+/// Sender of the queue the background task publishes from, set while a bus is configured.
+static QUEUE: OnceLock<mpsc::Sender<BusEvent>> = OnceLock::new();
+
+/// Represents an event published to the bus.
+#[derive(Serialize, Debug, Clone)]
+pub struct BusEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub time: u64,
+    pub data: Value
+}
+
+/// Represents the configured bus.
+enum Bus {
+    Kafka {
+        client: reqwest::Client,
+        url: String
+    },
+    Nats {
+        url: String,
+        /// The client, connected on the first event.
+        client: Option<async_nats::Client>
+    }
+}
+
+impl Bus {
+    /// Reads the bus from `EVENT_BUS`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if no bus is configured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bus is unknown or its URL is missing.
+    fn from_env() -> Option<Bus> {
+        let url = | key: &str | match config::var(key).ok().filter(| u | !u.is_empty()) {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => panic!("[ FATAL ] Configuration Error: `EVENT_BUS` requires `{}`", key)
+        };
+
+        match config::env_or("EVENT_BUS", String::new()).as_str() {
+            "" | "none" => None,
+            "kafka" => Some(Bus::Kafka { client: reqwest::Client::new(), url: url("KAFKA_REST_URL") }),
+            "nats" => Some(Bus::Nats { url: url("NATS_URL"), client: None }),
+            bus => panic!("[ FATAL ] Configuration Error: `EVENT_BUS` has an invalid value `{}`", bus)
+        }
+    }
+
+    /// Returns the name of the bus.
+    fn name(&self) -> &'static str {
+        match self {
+            Bus::Kafka { .. } => "kafka",
+            Bus::Nats { .. } => "nats"
+        }
+    }
+
+    /// Publishes an event to a topic.
+    async fn publish(&mut self, topic: &str, event: &BusEvent) -> Result<(), String> {
+        let payload: Vec<u8> = serde_json::to_vec(event).map_err(|e| e.to_string())?;
+
+        match self {
+            Bus::Kafka { client, url } => {
+                // the REST proxy embeds the record as JSON, keyed by type so a type stays ordered
+                client.post(format!("{}/topics/{}", url, topic))
+                    .header(reqwest::header::CONTENT_TYPE, "application/vnd.kafka.json.v2+json")
+                    .timeout(BUS_TIMEOUT)
+                    .json(&serde_json::json!({ "records": [{ "key": &event.kind, "value": event }] }))
+                    .send()
+                    .await
+                    .and_then(| r | r.error_for_status())
+                    .map_err(|e| e.to_string())?;
+            },
+            Bus::Nats { url, client } => {
+                if client.is_none() {
+                    *client = Some(nats_connect(url).await?);
+                }
+                let nats: &async_nats::Client = client.as_ref().unwrap();
+
+                nats.publish(topic.to_string(), payload.into()).await.map_err(|e| e.to_string())?;
+
+                // the publish is only buffered, the flush fails if it can not reach the server
+                actix_web::rt::time::timeout(BUS_TIMEOUT, nats.flush()).await
+                    .map_err(|_| String::from("NATS flush timed out"))?
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Connects to a NATS server, authenticating with the credentials of the URL if any.
+///
+/// Errors the server reports, e.g. for a subject the credentials may not publish to, are
+/// logged as they arrive, as NATS reports them apart from the publish.
+async fn nats_connect(url: &str) -> Result<async_nats::Client, String> {
+    ConnectOptions::new()
+        .name("rust-mixer-api")
+        .connection_timeout(BUS_TIMEOUT)
+        .event_callback(| event | async move {
+            match event {
+                Event::ServerError(err) => log_error!("NATS server reported an error: {}", err),
+                Event::Disconnected => log_warn!("Disconnected from the NATS server, reconnecting"),
+                Event::Connected => log_info!("Connected to the NATS server"),
+                _ => {}
+            }
+        })
+        .connect(url)
+        .await
+        .map_err(|e| format!("can not connect to NATS: {}", e))
+}
+
+/// Queues an event for publishing, doing nothing if no bus is configured.
+///
+/// # Arguments
+///
+/// * `kind` - The type of the event, e.g. `EVENT_SUBMITTED`.
+/// * `data` - The data of the event.
+pub fn publish(kind: &str, data: Value) {
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+
+    let event: BusEvent = BusEvent {
+        id: trace::random_hex(16),
+        kind: kind.to_string(),
+        time: ton::time_now(),
+        data
+    };
+
+    if let Err(err) = queue.try_send(event) {
+        log_error!("Event bus queue is full, dropping a `{}` event: {}", kind, err);
+    }
+}
+
+/// Starts the publisher task if a bus is configured.
+///
+/// Must be called before the background tasks that publish events start, events
+/// published earlier are dropped.
+pub fn start() {
+    let Some(bus) = Bus::from_env() else {
+        log_info!("Event bus publishing is disabled");
+        return;
+    };
+
+    let (sender, receiver) = mpsc::channel::<BusEvent>(QUEUE_SIZE);
+    if QUEUE.set(sender).is_err() {
+        return;
+    }
+
+    actix_web::rt::spawn(run(bus, receiver));
+}
+
+/// Publishes queued events forever.
+async fn run(mut bus: Bus, mut receiver: mpsc::Receiver<BusEvent>) {
+    let prefix: String = config::env_or("EVENT_BUS_PREFIX", String::from("mixer"));
+
+    log_info!("Publishing operation events to {} with prefix `{}`", bus.name(), prefix);
+
+    while let Some(event) = receiver.recv().await {
+        let topic: String = format!("{}.{}", prefix, event.kind);
+
+        for attempt in 1..=PUBLISH_ATTEMPTS {
+            match bus.publish(&topic, &event).await {
+                Ok(_) => break,
+                Err(err) if attempt == PUBLISH_ATTEMPTS => {
+                    log_error!("Can not publish event {} to `{}`, dropping it: {}", event.id, topic, err);
+                },
+                Err(err) => {
+                    log_warn!("Publishing event {} to `{}` failed, retrying: {}", event.id, topic, err);
+                    actix_web::rt::time::sleep(Duration::from_secs(attempt as u64)).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! # Application Configuration
+//!
+//! This module loads the application configuration from environment variables,
+//! falling back to defaults for every optional setting. Non-critical settings are
+//! kept in a `RuntimeConfig` that `reload` rebuilds on SIGHUP or on request of the
+//! admin API, without restarting the server or losing in-flight jobs.
+//!
+//! A reload never writes to the process environment, which other threads read meanwhile.
+//! The variables of the `.env` file are kept in memory instead and take precedence over the
+//! environment in `var` and `env_or`, which every setting is read through.
+
+use std::{collections::HashMap, env::VarError, str::FromStr, sync::{atomic::{AtomicU64, Ordering}, Arc, LazyLock, OnceLock, RwLock}};
+
+use serde::Serialize;
+
+use crate::types::nanotons::Nanotons;
+
+/// The variables of the `.env` file as of the last reload.
+static RELOADED: LazyLock<RwLock<HashMap<String, String>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Reads a configuration variable, from the `.env` file of the last reload or the environment.
+pub fn var(key: &str) -> Result<String, VarError> {
+    match RELOADED.read().unwrap().get(key) {
+        Some(value) => Ok(value.clone()),
+        None => std::env::var(key)
+    }
+}
+
+/// Parses the value of a variable, returning `default` when it is not set.
+///
+/// # Returns
+///
+/// The parsed value, or an error naming the variable if it can not be parsed.
+fn parse_or<T: FromStr>(key: &str, value: Option<String>, default: T) -> Result<T, String> {
+    match value {
+        Some(value) if !value.is_empty() => value.parse::<T>().map_err(|_| format!("`{}` has an invalid value `{}`", key, value)),
+        _ => Ok(default)
+    }
+}
+
+/// Reads an environment variable and parses it, returning `default` when it is not set.
+///
+/// # Panics
+///
+/// Panics if the variable is set but can not be parsed.
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    parse_or(key, var(key).ok(), default).unwrap_or_else(| err | panic!("[ FATAL ] Configuration Error: {}", err))
+}
+
+/// Represents how the application talks to the TON network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Messages are signed and broadcast through liteservers.
+    Online,
+    /// The network is replaced by `ton::offline::OfflineBackend`, for local development.
+    Offline
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "online" => Ok(Mode::Online),
+            "offline" => Ok(Mode::Offline),
+            _ => Err(format!("unknown mode `{}`", s))
+        }
+    }
+}
+
+/// Represents what an instance does in a deployment of several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceRole {
+    /// Serves every route, sends from the wallet and runs the background tasks.
+    Primary,
+    /// Serves reads only, never sends and runs no background tasks, for horizontally scaled replicas.
+    ReadOnly
+}
+
+impl FromStr for InstanceRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "primary" => Ok(InstanceRole::Primary),
+            "read_only" => Ok(InstanceRole::ReadOnly),
+            _ => Err(format!("unknown role `{}`", s))
+        }
+    }
+}
+
+/// The role of the instance, fixed at startup.
+static INSTANCE_ROLE: OnceLock<InstanceRole> = OnceLock::new();
+
+/// Returns the role of the instance configured with `ROLE`, primary by default.
+///
+/// The role is read once and not affected by `reload`, a replica never turns into a primary
+/// while it runs.
+pub fn instance_role() -> InstanceRole {
+    *INSTANCE_ROLE.get_or_init(|| env_or("ROLE", InstanceRole::Primary))
+}
+
+/// Returns whether the instance is a read-only replica.
+pub fn read_only() -> bool {
+    instance_role() == InstanceRole::ReadOnly
+}
+
+/// Represents the TON network the mixer runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet
+}
+
+impl Network {
+    /// Returns the network configured with `TON_NETWORK`, testnet by default.
+    pub fn from_env() -> Network {
+        env_or("TON_NETWORK", Network::Testnet)
+    }
+
+    /// Returns the network id used by TON Connect.
+    pub fn global_id(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "-239",
+            Network::Testnet => "-3"
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            _ => Err(format!("unknown network `{}`", s))
+        }
+    }
+}
+
+/// Represents the configuration of the HTTP server.
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    /// Port the HTTP server listens on.
+    pub port: u16,
+    /// Number of worker threads.
+    pub workers: usize,
+    /// Maximum number of concurrent connections per worker.
+    pub max_connections: usize,
+    /// Maximum number of new connections per worker being accepted concurrently.
+    pub max_connection_rate: usize,
+    /// Keep-alive duration of idle connections in seconds.
+    pub keep_alive: u64,
+    /// Time a client has to send the request head in milliseconds.
+    pub client_request_timeout: u64,
+    /// Time a client has to acknowledge connection shutdown in milliseconds.
+    pub client_disconnect_timeout: u64,
+    /// Maximum size of a JSON request body in bytes.
+    pub json_limit: usize,
+    /// Whether the TON network is used or stubbed out.
+    pub mode: Mode,
+    /// Whether the instance is a primary or a read-only replica.
+    pub role: InstanceRole
+}
+
+impl AppConfig {
+    /// Loads the configuration from the environment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `PORT` is not set or any variable has an invalid value.
+    pub fn from_env() -> AppConfig {
+        AppConfig {
+            port: std::env::var("PORT").unwrap().parse::<u16>().unwrap(),
+            workers: env_or("HTTP_WORKERS", num_cpus::get() * 2),
+            max_connections: env_or("HTTP_MAX_CONNECTIONS", 25_000),
+            max_connection_rate: env_or("HTTP_MAX_CONNECTION_RATE", 256),
+            keep_alive: env_or("HTTP_KEEP_ALIVE", 5),
+            client_request_timeout: env_or("HTTP_CLIENT_REQUEST_TIMEOUT", 5_000),
+            client_disconnect_timeout: env_or("HTTP_CLIENT_DISCONNECT_TIMEOUT", 5_000),
+            json_limit: env_or("HTTP_JSON_LIMIT", 256 * 1024),
+            mode: env_or("MODE", Mode::Online),
+            role: instance_role()
+        }
+    }
+}
+
+/// Origins allowed by CORS, used when `CORS_ORIGINS` is not set.
+const DEFAULT_CORS_ORIGINS: &str = "http://localhost:5173,http://localhost:3001";
+
+/// Nanotons attached to a fork message for gas, used when `FORK_GAS` is not set.
+const DEFAULT_FORK_GAS: u64 = 5000000;
+
+/// Nanotons attached to a spread message for gas, used when `SPREAD_GAS` is not set.
+const DEFAULT_SPREAD_GAS: u64 = 5000000;
+
+/// Nanotons attached to a collect message for gas, used when `COLLECT_GAS` is not set.
+const DEFAULT_COLLECT_GAS: u64 = 50000000;
+
+/// Nanotons attached to a jetton transfer for gas, used when `JETTON_TRANSFER_GAS` is not set.
+const DEFAULT_JETTON_TRANSFER_GAS: u64 = 50000000;
+
+/// Basechain forward prices of config param 25, used when `FWD_LUMP_PRICE`, `FWD_BIT_PRICE`
+/// and `FWD_CELL_PRICE` are not set. Bit and cell prices are in 1/65536 nanotons.
+const DEFAULT_FWD_LUMP_PRICE: u64 = 400000;
+const DEFAULT_FWD_BIT_PRICE: u64 = 26214400;
+const DEFAULT_FWD_CELL_PRICE: u64 = 2621440000;
+
+/// Deadline of an API call in milliseconds, used when `REQUEST_DEADLINE` is not set.
+const DEFAULT_REQUEST_DEADLINE: u64 = 60000;
+
+/// Bounds of the deadline a caller may request in milliseconds, used when
+/// `REQUEST_DEADLINE_MIN` and `REQUEST_DEADLINE_MAX` are not set.
+const DEFAULT_REQUEST_DEADLINE_MIN: u64 = 1000;
+const DEFAULT_REQUEST_DEADLINE_MAX: u64 = 300000;
+
+/// Represents the configuration that can change while the server is running.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeConfig {
+    /// Origins allowed by CORS.
+    pub cors_origins: Vec<String>,
+    /// Nanotons attached to a fork message for gas.
+    pub fork_gas: u64,
+    /// Nanotons attached to a spread message for gas, on top of the spread amount.
+    pub spread_gas: u64,
+    /// Nanotons attached to a collect message for gas.
+    pub collect_gas: u64,
+    /// Nanotons attached to a jetton transfer for gas.
+    pub jetton_transfer_gas: u64,
+    /// Forward fee of a message in nanotons, charged on top of the bits and cells it carries.
+    pub fwd_lump_price: u64,
+    /// Forward fee of a bit in 1/65536 nanotons.
+    pub fwd_bit_price: u64,
+    /// Forward fee of a cell in 1/65536 nanotons.
+    pub fwd_cell_price: u64,
+    /// Deadline of an API call in milliseconds.
+    pub request_deadline: u64,
+    /// Shortest deadline a caller may request in milliseconds.
+    pub request_deadline_min: u64,
+    /// Longest deadline a caller may request in milliseconds.
+    pub request_deadline_max: u64
+}
+
+/// Variables holding amounts of TON that the policies, limits and consolidation read.
+///
+/// They are checked with the runtime configuration, so an invalid amount fails the startup
+/// or the reload instead of disabling a policy or limit, or falling back to zero.
+const TON_AMOUNTS: [&str; 10] = [
+    "AUTO_FORK_THRESHOLD",
+    "AUTO_COLLECT_THRESHOLD",
+    "GAS_TOPUP_THRESHOLD",
+    "GAS_TOPUP_AMOUNT",
+    "SPREAD_MIN_LEG",
+    "SPREAD_MAX_LEG",
+    "SPREAD_MAX_TOTAL",
+    "DAILY_WITHDRAWAL_LIMIT",
+    "CONSOLIDATE_THRESHOLD",
+    "CONSOLIDATE_WALLET_RESERVE"
+];
+
+impl RuntimeConfig {
+    /// Loads the runtime configuration, reading every variable through `lookup`.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, or an error naming the first variable with an invalid value.
+    fn load(lookup: impl Fn(&str) -> Option<String>) -> Result<RuntimeConfig, String> {
+        for key in TON_AMOUNTS {
+            if let Some(value) = lookup(key).filter(| v | !v.trim().is_empty()) {
+                Nanotons::parse_ton(&value).map_err(| err | format!("`{}` is invalid: {}", key, err))?;
+            }
+        }
+
+        Ok(RuntimeConfig {
+            cors_origins: parse_or("CORS_ORIGINS", lookup("CORS_ORIGINS"), String::from(DEFAULT_CORS_ORIGINS))?
+                .split(',')
+                .map(| o | o.trim().to_string())
+                .filter(| o | !o.is_empty())
+                .collect(),
+            fork_gas: parse_or("FORK_GAS", lookup("FORK_GAS"), DEFAULT_FORK_GAS)?,
+            spread_gas: parse_or("SPREAD_GAS", lookup("SPREAD_GAS"), DEFAULT_SPREAD_GAS)?,
+            collect_gas: parse_or("COLLECT_GAS", lookup("COLLECT_GAS"), DEFAULT_COLLECT_GAS)?,
+            jetton_transfer_gas: parse_or("JETTON_TRANSFER_GAS", lookup("JETTON_TRANSFER_GAS"), DEFAULT_JETTON_TRANSFER_GAS)?,
+            fwd_lump_price: parse_or("FWD_LUMP_PRICE", lookup("FWD_LUMP_PRICE"), DEFAULT_FWD_LUMP_PRICE)?,
+            fwd_bit_price: parse_or("FWD_BIT_PRICE", lookup("FWD_BIT_PRICE"), DEFAULT_FWD_BIT_PRICE)?,
+            fwd_cell_price: parse_or("FWD_CELL_PRICE", lookup("FWD_CELL_PRICE"), DEFAULT_FWD_CELL_PRICE)?,
+            request_deadline: parse_or("REQUEST_DEADLINE", lookup("REQUEST_DEADLINE"), DEFAULT_REQUEST_DEADLINE)?,
+            request_deadline_min: parse_or("REQUEST_DEADLINE_MIN", lookup("REQUEST_DEADLINE_MIN"), DEFAULT_REQUEST_DEADLINE_MIN)?,
+            request_deadline_max: parse_or("REQUEST_DEADLINE_MAX", lookup("REQUEST_DEADLINE_MAX"), DEFAULT_REQUEST_DEADLINE_MAX)?
+        })
+    }
+
+    /// Loads the runtime configuration from the environment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any variable has an invalid value.
+    pub fn from_env() -> RuntimeConfig {
+        RuntimeConfig::load(| key | var(key).ok()).unwrap_or_else(| err | panic!("[ FATAL ] Configuration Error: {}", err))
+    }
+}
+
+/// The current runtime configuration, loaded on startup and replaced by `reload`.
+static RUNTIME: OnceLock<RwLock<Arc<RuntimeConfig>>> = OnceLock::new();
+
+/// Number of reloads so far, caches built from the environment rebuild when it changes.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the current runtime configuration.
+///
+/// The configuration is loaded on the first call, which `main` makes on startup.
+///
+/// # Panics
+///
+/// Panics on the first call if any variable has an invalid value.
+pub fn runtime() -> Arc<RuntimeConfig> {
+    RUNTIME.get_or_init(|| RwLock::new(Arc::new(RuntimeConfig::from_env()))).read().unwrap().clone()
+}
+
+/// Returns the number of configuration reloads so far.
+///
+/// Components that cache settings read from the environment, like the notifier or
+/// the spread limits, compare it with the generation they were built in.
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+/// Reloads the non-critical configuration.
+///
+/// Variables from the `.env` file override the environment, then the runtime configuration
+/// is rebuilt and the generation advanced. Settings the server was started with, like the
+/// port, the database or the wallet, are not affected.
+///
+/// # Returns
+///
+/// The new runtime configuration, or an error if a variable has an invalid value,
+/// in which case the previous configuration and variables stay in effect.
+pub fn reload() -> Result<Arc<RuntimeConfig>, String> {
+    let mut reloaded: HashMap<String, String> = HashMap::new();
+    if let Ok(iter) = dotenv::dotenv_iter() {
+        for item in iter {
+            match item {
+                Ok((key, value)) => { reloaded.insert(key, value); },
+                Err(err) => return Err(format!("can not read the .env file: {}", err))
+            }
+        }
+    }
+
+    let config: Arc<RuntimeConfig> = Arc::new(RuntimeConfig::load(| key | match reloaded.get(key) {
+        Some(value) => Some(value.clone()),
+        None => std::env::var(key).ok()
+    })?);
+
+    *RELOADED.write().unwrap() = reloaded;
+    *RUNTIME.get_or_init(|| RwLock::new(config.clone())).write().unwrap() = config.clone();
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+
+    Ok(config)
+}
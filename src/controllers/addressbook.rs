@@ -0,0 +1,84 @@
+//! # Address Book Controllers
+//!
+//! This module defines the controller functions managing the labeled addresses of the address book.
+
+use actix_web::{delete, get, post, put, web::{Data, Path}, Error, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{services::addressbook, types::addressbook::{AddressBookPayload, AddressBookQuery}, validation::{ValidatedJson, ValidatedQuery}};
+
+/// Lists the address book entries, optionally only those with a tag.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - A validated query containing `AddressBookQuery`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the entries or an error.
+#[get("/address-book")]
+pub async fn list(pool: Data<PgPool>, query: ValidatedQuery<AddressBookQuery>) -> Result<HttpResponse, Error> {
+    return addressbook::list(&pool, query.into_inner()).await;
+}
+
+/// Creates an address book entry.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `body_payload` - A validated JSON payload containing `AddressBookPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the entry or an error.
+#[post("/address-book")]
+pub async fn create(pool: Data<PgPool>, body_payload: ValidatedJson<AddressBookPayload>) -> Result<HttpResponse, Error> {
+    return addressbook::create(&pool, body_payload.into_inner()).await;
+}
+
+/// Returns an address book entry.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the entry.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the entry or an error.
+#[get("/address-book/{id}")]
+pub async fn get(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return addressbook::get(&pool, path.into_inner()).await;
+}
+
+/// Replaces an address book entry.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the entry.
+/// * `body_payload` - A validated JSON payload containing `AddressBookPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the entry or an error.
+#[put("/address-book/{id}")]
+pub async fn update(pool: Data<PgPool>, path: Path<i64>, body_payload: ValidatedJson<AddressBookPayload>) -> Result<HttpResponse, Error> {
+    return addressbook::update(&pool, path.into_inner(), body_payload.into_inner()).await;
+}
+
+/// Removes an address book entry.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the entry.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or an error.
+#[delete("/address-book/{id}")]
+pub async fn remove(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return addressbook::remove(&pool, path.into_inner()).await;
+}
@@ -0,0 +1,391 @@
+//! # Admin Controllers
+//!
+//! This module defines the controller functions for the administrative API.
+//! All routes are protected by the admin bearer token middleware.
+
+use actix_web::{delete, get, post, put, web::{Data, Path, Query}, Error, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{auth::operator::{OperatorSignature, SignedJson}, services::admin, types::{allowlist::{AllowedContractPayload, ContractQuery}, faucet::FaucetPayload, limits::LimitOverridePayload, notifications::NotificationRoutePayload, schedules::SchedulePayload, topups::GasTopUpQuery, upgrade::ContractUpgradePayload, webhooks::{WebhookDeliveryQuery, WebhookSubscriptionPayload}}, validation::{ValidatedJson, ValidatedQuery}};
+
+/// Lists the notification routes.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the routes or an error.
+#[get("/notifications/routes")]
+pub async fn list_notification_routes(pool: Data<PgPool>) -> Result<HttpResponse, Error> {
+    return admin::list_notification_routes(&pool).await;
+}
+
+/// Replaces the channels an event type is routed to.
+///
+/// # Arguments
+///
+/// * `path` - The notification event type.
+/// * `body_payload` - A validated JSON payload containing `NotificationRoutePayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the new routes or an error.
+#[put("/notifications/routes/{event}")]
+pub async fn set_notification_route(pool: Data<PgPool>, path: Path<String>, body_payload: ValidatedJson<NotificationRoutePayload>) -> Result<HttpResponse, Error> {
+    let payload: NotificationRoutePayload = body_payload.into_inner();
+
+    return admin::set_notification_route(&pool, path.into_inner(), payload.channels).await;
+}
+
+/// Removes the routes of an event type.
+///
+/// # Arguments
+///
+/// * `path` - The notification event type.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or an error.
+#[delete("/notifications/routes/{event}")]
+pub async fn remove_notification_route(pool: Data<PgPool>, path: Path<String>) -> Result<HttpResponse, Error> {
+    return admin::remove_notification_route(&pool, path.into_inner()).await;
+}
+
+/// Lists the contracts operations may target besides `MIXER_CONTRACT`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the allowed contracts or an error.
+#[get("/contracts")]
+pub async fn list_allowed_contracts(pool: Data<PgPool>) -> Result<HttpResponse, Error> {
+    return admin::list_allowed_contracts(&pool).await;
+}
+
+/// Adds a contract to the allow-list.
+///
+/// # Arguments
+///
+/// * `path` - The address of the contract.
+/// * `body_payload` - A validated JSON payload containing `AllowedContractPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the allowed contract or an error.
+#[put("/contracts/{address}")]
+pub async fn add_allowed_contract(pool: Data<PgPool>, path: Path<String>, body_payload: ValidatedJson<AllowedContractPayload>) -> Result<HttpResponse, Error> {
+    return admin::add_allowed_contract(&pool, path.into_inner(), body_payload.into_inner().label).await;
+}
+
+/// Removes a contract from the allow-list.
+///
+/// # Arguments
+///
+/// * `path` - The address of the contract.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or an error.
+#[delete("/contracts/{address}")]
+pub async fn remove_allowed_contract(pool: Data<PgPool>, path: Path<String>) -> Result<HttpResponse, Error> {
+    return admin::remove_allowed_contract(&pool, path.into_inner()).await;
+}
+
+/// Reloads the non-critical configuration without restarting the server.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the new runtime configuration or an error.
+#[post("/config/reload")]
+pub async fn reload_config() -> Result<HttpResponse, Error> {
+    return admin::reload_config();
+}
+
+/// Pauses invocations of the mixer contract, spreads, collects and forks are rejected until resumed.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `signature` - The operator signature of the request.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the state of the mixer.
+#[post("/pause")]
+pub async fn pause(pool: Data<PgPool>, signature: OperatorSignature) -> Result<HttpResponse, Error> {
+    return admin::set_paused(&pool, true, &signature.operator).await;
+}
+
+/// Resumes invocations of the mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `signature` - The operator signature of the request.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the state of the mixer.
+#[post("/resume")]
+pub async fn resume(pool: Data<PgPool>, signature: OperatorSignature) -> Result<HttpResponse, Error> {
+    return admin::set_paused(&pool, false, &signature.operator).await;
+}
+
+/// Approves a spread job held by a limit, which then runs right away without the spread limits.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the held job.
+/// * `signature` - The operator signature of the request.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the approved job or an error.
+#[post("/jobs/{id}/approve")]
+pub async fn approve_job(pool: Data<PgPool>, path: Path<i64>, signature: OperatorSignature) -> Result<HttpResponse, Error> {
+    return admin::approve_job(&pool, path.into_inner(), &signature.operator).await;
+}
+
+/// Reports the usage of the daily withdrawal limit.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the limit, its usage and the active overrides or an error.
+#[get("/limits/daily")]
+pub async fn daily_limit_status(pool: Data<PgPool>) -> Result<HttpResponse, Error> {
+    return admin::daily_limit_status(&pool).await;
+}
+
+/// Grants extra allowance on top of the daily withdrawal limit.
+///
+/// # Arguments
+///
+/// * `body_payload` - A JSON payload containing `LimitOverridePayload`, signed by the operator recorded with the override.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the override or an error.
+#[post("/limits/daily/overrides")]
+pub async fn add_limit_override(pool: Data<PgPool>, body_payload: SignedJson<LimitOverridePayload>) -> Result<HttpResponse, Error> {
+    let requested_by: Option<String> = Some(body_payload.operator.clone());
+
+    return admin::add_limit_override(&pool, body_payload.into_inner(), requested_by).await;
+}
+
+/// Upgrades the code of the mixer contract after a confirmation round-trip.
+///
+/// A request without `confirmation` returns a preview with a one-time token, the same
+/// request repeated with that token sends the upgrade message.
+///
+/// # Arguments
+///
+/// * `query` - Optional allow-listed mixer contract to upgrade.
+/// * `body_payload` - A JSON payload containing `ContractUpgradePayload`, signed by an operator.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the preview or the receipt, or an error.
+#[post("/contract/upgrade")]
+pub async fn upgrade_contract(pool: Data<PgPool>, query: Query<ContractQuery>, body_payload: SignedJson<ContractUpgradePayload>) -> Result<HttpResponse, Error> {
+    let requested_by: Option<String> = Some(body_payload.operator.clone());
+
+    return admin::upgrade_contract(&pool, query.into_inner().contract, body_payload.into_inner(), requested_by).await;
+}
+
+/// Lists the webhook subscriptions.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the subscriptions or an error.
+#[get("/webhooks")]
+pub async fn list_webhooks(pool: Data<PgPool>) -> Result<HttpResponse, Error> {
+    return admin::list_webhooks(&pool).await;
+}
+
+/// Creates a webhook subscription.
+///
+/// # Arguments
+///
+/// * `body_payload` - A validated JSON payload containing `WebhookSubscriptionPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the subscription or an error.
+#[post("/webhooks")]
+pub async fn create_webhook(pool: Data<PgPool>, body_payload: ValidatedJson<WebhookSubscriptionPayload>) -> Result<HttpResponse, Error> {
+    return admin::create_webhook(&pool, body_payload.into_inner()).await;
+}
+
+/// Returns a webhook subscription.
+///
+/// # Arguments
+///
+/// * `path` - The id of the subscription.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the subscription or an error.
+#[get("/webhooks/{id}")]
+pub async fn get_webhook(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return admin::get_webhook(&pool, path.into_inner()).await;
+}
+
+/// Replaces a webhook subscription.
+///
+/// # Arguments
+///
+/// * `path` - The id of the subscription.
+/// * `body_payload` - A validated JSON payload containing `WebhookSubscriptionPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the subscription or an error.
+#[put("/webhooks/{id}")]
+pub async fn update_webhook(pool: Data<PgPool>, path: Path<i64>, body_payload: ValidatedJson<WebhookSubscriptionPayload>) -> Result<HttpResponse, Error> {
+    return admin::update_webhook(&pool, path.into_inner(), body_payload.into_inner()).await;
+}
+
+/// Removes a webhook subscription.
+///
+/// # Arguments
+///
+/// * `path` - The id of the subscription.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or an error.
+#[delete("/webhooks/{id}")]
+pub async fn remove_webhook(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return admin::remove_webhook(&pool, path.into_inner()).await;
+}
+
+/// Sends a test event to a webhook subscription.
+///
+/// # Arguments
+///
+/// * `path` - The id of the subscription.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the recorded delivery or an error.
+#[post("/webhooks/{id}/test")]
+pub async fn test_webhook(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return admin::test_webhook(&pool, path.into_inner()).await;
+}
+
+/// Lists the deliveries of a webhook subscription.
+///
+/// # Arguments
+///
+/// * `path` - The id of the subscription.
+/// * `query` - A validated query containing `WebhookDeliveryQuery`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the deliveries or an error.
+#[get("/webhooks/{id}/deliveries")]
+pub async fn list_webhook_deliveries(pool: Data<PgPool>, path: Path<i64>, query: ValidatedQuery<WebhookDeliveryQuery>) -> Result<HttpResponse, Error> {
+    return admin::list_webhook_deliveries(&pool, path.into_inner(), query.into_inner()).await;
+}
+
+/// Lists the top-ups of the gas wallet sent by the auto top-up policy.
+///
+/// # Arguments
+///
+/// * `query` - A validated query containing `GasTopUpQuery`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the top-ups or an error.
+#[get("/gas/topups")]
+pub async fn list_gas_topups(pool: Data<PgPool>, query: ValidatedQuery<GasTopUpQuery>) -> Result<HttpResponse, Error> {
+    return admin::list_gas_topups(&pool, query.into_inner()).await;
+}
+
+/// Lists the recurring schedules.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the schedules or an error.
+#[get("/schedules")]
+pub async fn list_schedules(pool: Data<PgPool>) -> Result<HttpResponse, Error> {
+    return admin::list_schedules(&pool).await;
+}
+
+/// Creates a recurring schedule.
+///
+/// # Arguments
+///
+/// * `body_payload` - A validated JSON payload containing `SchedulePayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the schedule or an error.
+#[post("/schedules")]
+pub async fn create_schedule(pool: Data<PgPool>, body_payload: ValidatedJson<SchedulePayload>) -> Result<HttpResponse, Error> {
+    return admin::create_schedule(&pool, body_payload.into_inner()).await;
+}
+
+/// Pauses a schedule.
+///
+/// # Arguments
+///
+/// * `path` - The id of the schedule.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the schedule or an error.
+#[post("/schedules/{id}/pause")]
+pub async fn pause_schedule(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return admin::pause_schedule(&pool, path.into_inner(), true).await;
+}
+
+/// Resumes a paused schedule at its next occurrence.
+///
+/// # Arguments
+///
+/// * `path` - The id of the schedule.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the schedule or an error.
+#[post("/schedules/{id}/resume")]
+pub async fn resume_schedule(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return admin::pause_schedule(&pool, path.into_inner(), false).await;
+}
+
+/// Removes a schedule.
+///
+/// # Arguments
+///
+/// * `path` - The id of the schedule.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or an error.
+#[delete("/schedules/{id}")]
+pub async fn remove_schedule(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return admin::remove_schedule(&pool, path.into_inner()).await;
+}
+
+/// Returns the aggregated state shown on the landing page of the ops UI.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the dashboard or an error.
+#[get("/dashboard")]
+pub async fn dashboard(pool: Data<PgPool>) -> Result<HttpResponse, Error> {
+    return admin::dashboard(&pool).await;
+}
+
+/// Funds the wallet and the mixer contract with testnet TON from the faucet.
+///
+/// # Arguments
+///
+/// * `body_payload` - A validated JSON payload containing `FaucetPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the grants or an error.
+#[post("/faucet")]
+pub async fn faucet(body_payload: ValidatedJson<FaucetPayload>) -> Result<HttpResponse, Error> {
+    return admin::faucet(body_payload.into_inner()).await;
+}
@@ -0,0 +1,56 @@
+//! # TON Connect Controllers
+//!
+//! This module defines the controller functions returning TON Connect transaction requests
+//! for mixer operations, to be signed by the user's wallet instead of the server's mnemonic.
+
+use actix_web::{post, web::{Data, Query}, Error, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{services::connect, types::{allowlist::ContractQuery, CollectPayload, SpreadPayload}, validation::ValidatedJson};
+
+/// Builds a TON Connect request for the spread operation.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to spread through.
+/// * `body_payload` - A validated JSON payload containing a vector of `SpreadWalletPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request or an error.
+#[post("/connect/spread")]
+pub async fn spread(pool: Data<PgPool>, query: Query<ContractQuery>, body_payload: ValidatedJson<SpreadPayload>) -> Result<HttpResponse, Error> {
+    return connect::spread(&pool, query.into_inner().contract, &body_payload.0.wallets).await;
+}
+
+/// Builds a TON Connect request for the collect operation.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to collect from.
+/// * `body_payload` - A validated JSON payload containing `CollectPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request or an error.
+#[post("/connect/collect")]
+pub async fn collect(pool: Data<PgPool>, query: Query<ContractQuery>, body_payload: ValidatedJson<CollectPayload>) -> Result<HttpResponse, Error> {
+    return connect::collect(&pool, query.into_inner().contract, body_payload.into_inner()).await;
+}
+
+/// Builds a TON Connect request for the fork operation.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to fork.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request or an error.
+#[post("/connect/fork")]
+pub async fn fork(pool: Data<PgPool>, query: Query<ContractQuery>) -> Result<HttpResponse, Error> {
+    return connect::fork(&pool, query.into_inner().contract).await;
+}
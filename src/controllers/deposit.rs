@@ -0,0 +1,70 @@
+//! # Deposit Controllers
+//!
+//! This module defines the controller functions that help wallet users deposit into the mixer contract.
+
+use actix_web::{get, post, web::{Data, Path, Query}, Error, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{auth::rbac, services::deposit, types::deposit::{DepositLinkQuery, DepositPayload, QrFormatQuery}, validation::{ValidatedJson, ValidatedQuery}};
+
+/// Returns `ton://transfer` deeplinks pre-filled with the mixer contract, amount and a tracking comment.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional contract, amount in TON and comment.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the deeplinks or an error.
+#[get("/deposit/link")]
+pub async fn link(pool: Data<PgPool>, query: ValidatedQuery<DepositLinkQuery>) -> Result<HttpResponse, Error> {
+    return deposit::link(&pool, query.into_inner()).await;
+}
+
+/// Returns the deposit deeplink rendered as a QR code.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional contract, amount in TON and comment.
+/// * `format` - Image format, `svg` (default) or `png`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the image or an error.
+#[get("/deposit/qr")]
+pub async fn qr(pool: Data<PgPool>, query: ValidatedQuery<DepositLinkQuery>, format: Query<QrFormatQuery>) -> Result<HttpResponse, Error> {
+    return deposit::qr(&pool, query.into_inner(), format.format).await;
+}
+
+/// Creates a payment request with a unique comment to be matched by the deposit watcher.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `req` - The request, whose token names the tenant of the payment request.
+/// * `body_payload` - A validated JSON payload containing `DepositPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request and its deeplinks or an error.
+#[post("/deposits")]
+pub async fn create(pool: Data<PgPool>, req: HttpRequest, body_payload: ValidatedJson<DepositPayload>) -> Result<HttpResponse, Error> {
+    return deposit::create(&pool, body_payload.into_inner(), rbac::tenant_of(&req)).await;
+}
+
+/// Retrieves a payment request and its status.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the request.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request or an error.
+#[get("/deposits/{id}")]
+pub async fn get(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return deposit::get(&pool, path.into_inner()).await;
+}
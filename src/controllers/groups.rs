@@ -0,0 +1,100 @@
+//! # Recipient Group Controllers
+//!
+//! This module defines the controller functions managing the recipient groups and spreading to them.
+
+use actix_web::{delete, get, post, put, web::{Data, Path, Query}, Error, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{services::groups, types::{allowlist::ContractQuery, groups::{GroupSpreadPayload, RecipientGroupPayload}, ConfirmationQuery}, validation::ValidatedJson};
+
+/// Lists the recipient groups with their members.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the groups or an error.
+#[get("/recipient-groups")]
+pub async fn list(pool: Data<PgPool>) -> Result<HttpResponse, Error> {
+    return groups::list(&pool).await;
+}
+
+/// Creates a recipient group.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `body_payload` - A validated JSON payload containing `RecipientGroupPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the group or an error.
+#[post("/recipient-groups")]
+pub async fn create(pool: Data<PgPool>, body_payload: ValidatedJson<RecipientGroupPayload>) -> Result<HttpResponse, Error> {
+    return groups::create(&pool, body_payload.into_inner()).await;
+}
+
+/// Returns a recipient group with its members.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the group.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the group or an error.
+#[get("/recipient-groups/{id}")]
+pub async fn get(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return groups::get(&pool, path.into_inner()).await;
+}
+
+/// Replaces a recipient group.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the group.
+/// * `body_payload` - A validated JSON payload containing `RecipientGroupPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the group or an error.
+#[put("/recipient-groups/{id}")]
+pub async fn update(pool: Data<PgPool>, path: Path<i64>, body_payload: ValidatedJson<RecipientGroupPayload>) -> Result<HttpResponse, Error> {
+    return groups::update(&pool, path.into_inner(), body_payload.into_inner()).await;
+}
+
+/// Removes a recipient group.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the group.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or an error.
+#[delete("/recipient-groups/{id}")]
+pub async fn remove(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return groups::remove(&pool, path.into_inner()).await;
+}
+
+/// Handles the spread to a recipient group.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to spread through.
+/// * `confirmation` - Whether to hold the response until the message is applied on chain.
+/// * `body_payload` - A validated JSON payload containing `GroupSpreadPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response or an error.
+#[post("/spread/group")]
+pub async fn spread(pool: Data<PgPool>, query: Query<ContractQuery>, confirmation: Query<ConfirmationQuery>, body_payload: ValidatedJson<GroupSpreadPayload>) -> Result<HttpResponse, Error> {
+    return groups::spread(&pool, query.into_inner().contract, body_payload.into_inner(), confirmation.wait_for_confirmation).await;
+}
@@ -0,0 +1,30 @@
+//! # Health Controllers
+//!
+//! This module defines the probes used by load balancers and orchestrators,
+//! and the metrics endpoint scraped by Prometheus.
+
+use actix_web::{get, Error, HttpResponse};
+
+use crate::services::health;
+
+/// Reports whether the instance can broadcast, i.e. its liteserver is reachable and in sync.
+///
+/// # Returns
+///
+/// Returns a 200 response when ready and a 503 response otherwise.
+#[get("/ready")]
+pub async fn ready() -> Result<HttpResponse, Error> {
+    return health::ready().await;
+}
+
+/// Exposes the application metrics in the Prometheus text format.
+///
+/// # Returns
+///
+/// Returns a 200 response with the metrics.
+#[get("/metrics")]
+pub async fn metrics() -> HttpResponse {
+    return HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(crate::metrics::render());
+}
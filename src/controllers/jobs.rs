@@ -0,0 +1,39 @@
+//! # Job Controllers
+//!
+//! This module defines the controller functions for the background jobs operations are queued as.
+
+use actix_web::{delete, get, web::{Data, Path}, Error, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{services::jobs, types::jobs::{CancelJobQuery, JobListQuery}, validation::ValidatedQuery};
+
+/// Lists jobs, filtered by status, kind, deposit and creation time.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - The filters and the page of the listing.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the page of jobs or an error.
+#[get("/jobs")]
+pub async fn list(pool: Data<PgPool>, query: ValidatedQuery<JobListQuery>) -> Result<HttpResponse, Error> {
+    return jobs::list(&pool, query.into_inner()).await;
+}
+
+/// Cancels a queued or delay-scheduled job before it is broadcast.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the job.
+/// * `query` - Optional reason of the cancellation.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the cancelled job or an error.
+#[delete("/jobs/{id}")]
+pub async fn cancel(pool: Data<PgPool>, path: Path<i64>, query: ValidatedQuery<CancelJobQuery>) -> Result<HttpResponse, Error> {
+    return jobs::cancel(&pool, path.into_inner(), query.into_inner().reason).await;
+}
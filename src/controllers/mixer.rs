@@ -5,73 +5,196 @@
 
 use std::str::FromStr;
 
-use actix_web::{error::ErrorBadRequest, get, post, web::Json, Error, HttpResponse};
-use tonlib::address::{TonAddress, TonAddressParseError};
+use actix_web::{error::ErrorBadRequest, get, post, web::{Data, Path, Query}, Error, HttpResponse};
+use base64::{Engine as _, engine::general_purpose};
+use sqlx::PgPool;
+use tonlib::{address::TonAddress, types::TonHash};
 
-use crate::{services::mixer, types::{CollectPayload, Response, SpreadWalletPayload}};
+use crate::{auth::operator::OperatorSignature, services::{health, mixer}, types::{allowlist::ContractQuery, rebalance::RebalancePayload, AggregateBalanceQuery, ChildAddressQuery, CollectBatchPayload, CollectPayload, ConfirmationQuery, ForkQuery, MixedSpreadPayload, split::SplitSpreadPayload, Response, SpreadPayload, SpreadTotalQuery, TransactionQuery, TransactionsPageQuery}, validation::{ValidatedJson, ValidatedQuery}};
+
+/// Default number of transactions returned per page.
+const DEFAULT_PAGE_LIMIT: usize = 20;
+
+/// Maximum number of transactions returned per page.
+const MAX_PAGE_LIMIT: usize = 100;
+
+/// Parses a transaction hash given either in hex or in base64 form.
+fn parse_tx_hash(hash: &str) -> Result<TonHash, String> {
+    let bytes: Vec<u8> = if hash.len() == 64 {
+        hex::decode(hash).map_err(|e| e.to_string())?
+    } else {
+        general_purpose::STANDARD.decode(hash)
+            .or_else(|_| general_purpose::URL_SAFE.decode(hash))
+            .map_err(|e| e.to_string())?
+    };
+
+    bytes.try_into().map_err(|_| String::from("transaction hash must be 32 bytes long"))
+}
 
 /// Handles the spread operation.
 ///
 /// # Arguments
 ///
-/// * `body_payload` - A JSON payload containing a vector of `SpreadWalletPayload`.
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to spread through.
+/// * `confirmation` - Whether to hold the response until the message is applied on chain.
+/// * `total` - Optional TON `percent` amounts are taken of, the balance of the contract if omitted.
+/// * `body_payload` - A validated JSON payload containing a vector of `SpreadWalletPayload`.
 ///
 /// # Returns
 ///
 /// Returns an HTTP response or an error.
 #[post("/spread")]
-pub async fn spread(body_payload: Json<Vec<SpreadWalletPayload>>) -> Result<HttpResponse, Error> {
-    return mixer::spread(&body_payload.0).await;
+pub async fn spread(pool: Data<PgPool>, query: Query<ContractQuery>, confirmation: Query<ConfirmationQuery>, total: ValidatedQuery<SpreadTotalQuery>, body_payload: ValidatedJson<SpreadPayload>) -> Result<HttpResponse, Error> {
+    return mixer::spread(&pool, query.into_inner().contract, &body_payload.0.wallets, total.0.total, confirmation.wait_for_confirmation).await;
 }
 
-/// Handles the collect operation.
+/// Handles the split spread operation.
 ///
-/// This function performs input validation for collection mode 3,
-/// checking for the presence and validity of `jetton_wallet` and `amount` fields.
+/// Divides a total amount evenly between a list of addresses, or a count of legs to one recipient.
 ///
 /// # Arguments
 ///
-/// * `body_payload` - A JSON payload containing `CollectPayload`.
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to spread through.
+/// * `confirmation` - Whether to hold the response until the message is applied on chain.
+/// * `body_payload` - A validated JSON payload containing a `SplitSpreadPayload`.
 ///
 /// # Returns
 ///
 /// Returns an HTTP response or an error.
-#[post("/collect")]
-pub async fn collect(body_payload: Json<CollectPayload>) -> Result<HttpResponse, Error> {
-    let payload: CollectPayload = body_payload.0;
+#[post("/spread/split")]
+pub async fn spread_split(pool: Data<PgPool>, query: Query<ContractQuery>, confirmation: Query<ConfirmationQuery>, body_payload: ValidatedJson<SplitSpreadPayload>) -> Result<HttpResponse, Error> {
+    return mixer::spread_split(&pool, query.into_inner().contract, &body_payload.0, confirmation.wait_for_confirmation).await;
+}
 
-    if payload.mode == 3 {
-        if payload.jetton_wallet.is_none() {
-            return Err(ErrorBadRequest(
-                Response::error(
-                    serde_json::Value::String(String::from("in collection mode 3 field `jetton_wallet` is required"))
-                ).to_string()
-            ));
-        } else {
-            let check: Result<TonAddress, TonAddressParseError> = TonAddress::from_str(&payload.jetton_wallet.clone().unwrap());
-
-            match check {
-                Ok(_) => {},
-                Err(err) => {
-                    return Err(ErrorBadRequest(
-                        Response::error(
-                            serde_json::Value::String(err.to_string())
-                        ).to_string()
-                    ));
-                }
-            }
-        }
+/// Handles the direct spread operation.
+///
+/// Legs are sent straight from the wallet instead of through the mixer contract,
+/// packed up to four transfers per external message.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `total` - TON `percent` amounts are taken of, required for percentages.
+/// * `body_payload` - A validated JSON payload containing a vector of `SpreadWalletPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response or an error.
+#[post("/spread/direct")]
+pub async fn spread_direct(pool: Data<PgPool>, total: ValidatedQuery<SpreadTotalQuery>, body_payload: ValidatedJson<SpreadPayload>) -> Result<HttpResponse, Error> {
+    return mixer::spread_direct(&pool, &body_payload.0.wallets, total.0.total).await;
+}
 
-        if payload.amount.is_none() {
-            return Err(ErrorBadRequest(
-                Response::error(
-                    serde_json::Value::String(String::from("in collection mode 3 field `amount` is required"))
-                ).to_string()
-            ));
-        }
-    }
+/// Handles the mixed spread operation.
+///
+/// Legs name their asset, native TON or a configured jetton, and are sent in one batch:
+/// TON through the mixer contract, jettons as transfers from the wallet.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to spread TON through.
+/// * `body_payload` - A validated JSON payload containing a vector of `MixedSpreadLegPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response or an error.
+#[post("/spread/mixed")]
+pub async fn spread_mixed(pool: Data<PgPool>, query: Query<ContractQuery>, body_payload: ValidatedJson<MixedSpreadPayload>) -> Result<HttpResponse, Error> {
+    return mixer::spread_mixed(&pool, query.into_inner().contract, &body_payload.0.wallets).await;
+}
 
-    return mixer::collect(payload).await;
+/// Handles the spread preview.
+///
+/// Builds the spread message body without signing or sending it, to inspect its BOC,
+/// hash and size before a real spread.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `total` - TON `percent` amounts are taken of, required for percentages.
+/// * `body_payload` - A validated JSON payload containing a vector of `SpreadWalletPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response or an error.
+#[post("/spread/preview")]
+pub async fn spread_preview(pool: Data<PgPool>, total: ValidatedQuery<SpreadTotalQuery>, body_payload: ValidatedJson<SpreadPayload>) -> Result<HttpResponse, Error> {
+    return mixer::spread_preview(&pool, &body_payload.0.wallets, total.0.total).await;
+}
+
+/// Handles the rebalance operation.
+///
+/// Plans the moves that bring the given contracts to their share of the pool and
+/// executes them as a background job.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `body_payload` - A validated JSON payload containing `RebalancePayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response or an error.
+#[post("/rebalance")]
+pub async fn rebalance(pool: Data<PgPool>, body_payload: ValidatedJson<RebalancePayload>) -> Result<HttpResponse, Error> {
+    return mixer::rebalance(&pool, body_payload.into_inner()).await;
+}
+
+/// Returns a rebalance job with its progress.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The id of the rebalance job.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the job or an error.
+#[get("/rebalance/{id}")]
+pub async fn rebalance_job(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return mixer::rebalance_job(&pool, path.into_inner()).await;
+}
+
+/// Handles the collect operation.
+///
+/// The payload is validated by its declared rules, which require `jetton_wallet`
+/// and `amount` in collection mode 3. With `min_dwell_hours` the collect is scheduled
+/// as a job until the newest uncollected funds on the contract are at least that old.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to collect from.
+/// * `confirmation` - Whether to hold the response until the message is applied on chain.
+/// * `body_payload` - A validated JSON payload containing `CollectPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response or an error.
+#[post("/collect")]
+pub async fn collect(pool: Data<PgPool>, query: Query<ContractQuery>, confirmation: Query<ConfirmationQuery>, body_payload: ValidatedJson<CollectPayload>) -> Result<HttpResponse, Error> {
+    return mixer::collect(&pool, query.into_inner().contract, body_payload.into_inner(), confirmation.wait_for_confirmation).await;
+}
+
+/// Handles the batch collect operation.
+///
+/// Schedules a job collecting from a list of contracts, or from every tracked contract
+/// with `"all"`, whose result reports the outcome per contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `body_payload` - A validated JSON payload containing `CollectBatchPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response or an error.
+#[post("/collect/batch")]
+pub async fn collect_batch(pool: Data<PgPool>, body_payload: ValidatedJson<CollectBatchPayload>) -> Result<HttpResponse, Error> {
+    return mixer::collect_batch(&pool, body_payload.into_inner()).await;
 }
 
 /// Retrieves the collection modes.
@@ -86,12 +209,55 @@ pub async fn get_collect_modes() -> Result<HttpResponse, Error> {
 
 /// Handles the fork operation.
 ///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to fork and query id of the fork.
+/// * `confirmation` - Whether to hold the response until the message is applied on chain.
+///
 /// # Returns
 ///
 /// Returns an HTTP response or an error.
 #[post("/fork")]
-pub async fn fork() -> Result<HttpResponse, Error> {
-    return mixer::fork().await;
+pub async fn fork(pool: Data<PgPool>, query: ValidatedQuery<ForkQuery>, confirmation: Query<ConfirmationQuery>) -> Result<HttpResponse, Error> {
+    let query: ForkQuery = query.into_inner();
+
+    return mixer::fork(&pool, query.contract, query.query_id, confirmation.wait_for_confirmation).await;
+}
+
+/// Gathers dust left on forks and the gas wallet back into the mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `_signature` - The operator signature the sweep requires, logged when it is verified.
+///
+/// # Returns
+///
+/// Returns an HTTP response or an error.
+#[post("/consolidate")]
+pub async fn consolidate(pool: Data<PgPool>, _signature: OperatorSignature) -> Result<HttpResponse, Error> {
+    return mixer::consolidate(&pool).await;
+}
+
+/// Retrieves the current TON/USD rate used for `amount_usd`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the rate or an error.
+#[get("/rates")]
+pub async fn rates() -> Result<HttpResponse, Error> {
+    return mixer::get_rates().await;
+}
+
+/// Retrieves the latest masterchain seqno, its block time and the lag observed by the API.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the chain info or an error.
+#[get("/chain/info")]
+pub async fn chain_info() -> Result<HttpResponse, Error> {
+    return health::get_chain_info().await;
 }
 
 /// Retrieves the operation codes.
@@ -102,4 +268,193 @@ pub async fn fork() -> Result<HttpResponse, Error> {
 #[get("/op_codes")]
 pub async fn opcodes() -> Result<HttpResponse, Error> {
     return mixer::get_opcodes().await;
-}
\ No newline at end of file
+}
+
+/// Decodes the in-message of a contract transaction.
+///
+/// The transaction hash may be passed in hex or base64 form. The mixer contract
+/// is used unless another contract is given with the `address` query parameter.
+///
+/// # Arguments
+///
+/// * `path` - The logical time and hash of the transaction.
+/// * `query` - Optional contract address the transaction belongs to.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the decoded message or an error.
+#[get("/transactions/{lt}/{hash}/decode")]
+pub async fn decode_transaction(path: Path<(i64, String)>, query: Query<TransactionQuery>) -> Result<HttpResponse, Error> {
+    let (lt, hash) = path.into_inner();
+
+    let hash: TonHash = match parse_tx_hash(&hash) {
+        Ok(h) => h,
+        Err(err) => {
+            return Err(ErrorBadRequest(
+                Response::error(serde_json::Value::String(err)).to_string()
+            ));
+        }
+    };
+
+    let address: Option<TonAddress> = match &query.address {
+        Some(a) => match TonAddress::from_str(a) {
+            Ok(address) => Some(address),
+            Err(err) => {
+                return Err(ErrorBadRequest(
+                    Response::error(serde_json::Value::String(err.to_string())).to_string()
+                ));
+            }
+        },
+        None => None
+    };
+
+    return mixer::decode_transaction(address, lt, hash).await;
+}
+
+/// Lists the most recent transactions of the mixer contract.
+///
+/// Pages are walked backwards in time by passing the `next_lt` and `next_hash`
+/// of the previous page as the `lt` and `hash` query parameters.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional contract address, paging cursor and page size.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the page of transactions or an error.
+#[get("/contract/transactions")]
+pub async fn contract_transactions(pool: Data<PgPool>, query: Query<TransactionsPageQuery>) -> Result<HttpResponse, Error> {
+    let query: TransactionsPageQuery = query.into_inner();
+
+    let limit: usize = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if limit == 0 || limit > MAX_PAGE_LIMIT {
+        return Err(ErrorBadRequest(
+            Response::error(
+                serde_json::Value::String(format!("field `limit` must be between 1 and {}", MAX_PAGE_LIMIT))
+            ).to_string()
+        ));
+    }
+
+    let from: Option<(i64, TonHash)> = match (query.lt, query.hash) {
+        (Some(lt), Some(hash)) => match parse_tx_hash(&hash) {
+            Ok(h) => Some((lt, h)),
+            Err(err) => {
+                return Err(ErrorBadRequest(
+                    Response::error(serde_json::Value::String(err)).to_string()
+                ));
+            }
+        },
+        (None, None) => None,
+        _ => {
+            return Err(ErrorBadRequest(
+                Response::error(
+                    serde_json::Value::String(String::from("fields `lt` and `hash` must be passed together"))
+                ).to_string()
+            ));
+        }
+    };
+
+    let address: Option<TonAddress> = match &query.address {
+        Some(a) => match TonAddress::from_str(a) {
+            Ok(address) => Some(address),
+            Err(err) => {
+                return Err(ErrorBadRequest(
+                    Response::error(serde_json::Value::String(err.to_string())).to_string()
+                ));
+            }
+        },
+        None => None
+    };
+
+    return mixer::list_contract_transactions(&pool, address, from, limit).await;
+}
+
+/// Resolves a query id to the stored operation and its on-chain transactions.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `path` - The query id embedded in the message body.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the operation and its transactions or an error.
+#[get("/operations/by-query-id/{id}")]
+pub async fn operation_by_query_id(pool: Data<PgPool>, path: Path<i64>) -> Result<HttpResponse, Error> {
+    return mixer::operation_by_query_id(&pool, path.into_inner()).await;
+}
+
+/// Lists the jetton balances of the mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the jetton balances or an error.
+#[get("/contract/jettons")]
+pub async fn contract_jettons(pool: Data<PgPool>, query: Query<ContractQuery>) -> Result<HttpResponse, Error> {
+    return mixer::contract_jettons(&pool, query.into_inner().contract).await;
+}
+
+/// Sums the balances of the gas wallet, the root contract and all forks.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional jetton masters to sum as well.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the totals and a balance per account or an error.
+#[get("/balance/aggregate")]
+pub async fn aggregate_balance(pool: Data<PgPool>, query: ValidatedQuery<AggregateBalanceQuery>) -> Result<HttpResponse, Error> {
+    return mixer::aggregate_balance(&pool, query.into_inner().jettons).await;
+}
+
+/// Compares the code hash of the mixer contract with `MIXER_CODE_HASH`.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the code hash and whether it is expected, or an error.
+#[get("/contract/verify")]
+pub async fn verify_contract(pool: Data<PgPool>, query: Query<ContractQuery>) -> Result<HttpResponse, Error> {
+    return mixer::verify_contract(&pool, query.into_inner().contract).await;
+}
+
+/// Returns the fork tree of the root mixer contract and its descendants with their balances.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the contract graph or an error.
+#[get("/contracts/tree")]
+pub async fn contract_tree(pool: Data<PgPool>) -> Result<HttpResponse, Error> {
+    return mixer::contract_tree(&pool).await;
+}
+
+/// Derives the address a fork deploys its child to, so it can be registered and funded in advance
+/// once an indexed fork of the same code confirmed the derivation.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract and the query id the fork will be sent with.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the child address or an error.
+#[get("/contracts/child-address")]
+pub async fn child_address(pool: Data<PgPool>, query: ValidatedQuery<ChildAddressQuery>) -> Result<HttpResponse, Error> {
+    let query: ChildAddressQuery = query.into_inner();
+
+    return mixer::child_address(&pool, query.contract, query.query_id).await;
+}
@@ -0,0 +1,54 @@
+//! # Multisig Controllers
+//!
+//! This module defines the controller functions proposing collects to the multisig
+//! and letting signers approve them.
+
+use actix_web::{get, post, web::{Data, Path, Query}, Error, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{services::multisig, types::{allowlist::ContractQuery, multisig::MultisigApprovePayload, CollectPayload}, validation::ValidatedJson};
+
+/// Proposes a collect to the multisig as an order.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - Optional allow-listed mixer contract to collect from.
+/// * `body_payload` - A validated JSON payload containing `CollectPayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the order or an error.
+#[post("/multisig/collect")]
+pub async fn collect(pool: Data<PgPool>, query: Query<ContractQuery>, body_payload: ValidatedJson<CollectPayload>) -> Result<HttpResponse, Error> {
+    return multisig::collect(&pool, query.into_inner().contract, body_payload.into_inner()).await;
+}
+
+/// Reports the approval state of an order.
+///
+/// # Arguments
+///
+/// * `path` - The seqno of the order.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the state of the order or an error.
+#[get("/multisig/orders/{seqno}")]
+pub async fn order(path: Path<u64>) -> Result<HttpResponse, Error> {
+    return multisig::order(path.into_inner()).await;
+}
+
+/// Builds the TON Connect request a signer approves an order with.
+///
+/// # Arguments
+///
+/// * `path` - The seqno of the order.
+/// * `body_payload` - A validated JSON payload containing `MultisigApprovePayload`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request or an error.
+#[post("/multisig/orders/{seqno}/approve")]
+pub async fn approve(path: Path<u64>, body_payload: ValidatedJson<MultisigApprovePayload>) -> Result<HttpResponse, Error> {
+    return multisig::approve(path.into_inner(), body_payload.into_inner().signer_index).await;
+}
@@ -0,0 +1,93 @@
+//! # Report Controllers
+//!
+//! This module defines the controller functions for the accounting reports.
+//! It validates the report parameters and calls the report services.
+
+use actix_web::{error::ErrorBadRequest, get, web::{Data, Query}, Error, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{services::reports, ton, types::{reports::{window_seconds, FeeReportQuery, StatsQuery}, Response}};
+
+/// Default report window in seconds (30 days).
+const DEFAULT_WINDOW: i64 = 30 * 24 * 60 * 60;
+
+/// Returns the fee and profit report.
+///
+/// Supports `period` (`day` or `week`, default `day`), `format` (`json` or `csv`,
+/// default `json`) and a `from`/`to` window in Unix seconds (default last 30 days).
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - The report parameters.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the report or an error.
+#[get("/reports/fees")]
+pub async fn fees(pool: Data<PgPool>, query: Query<FeeReportQuery>) -> Result<HttpResponse, Error> {
+    let query: FeeReportQuery = query.into_inner();
+
+    let period: String = query.period.unwrap_or(String::from("day"));
+    if period != "day" && period != "week" {
+        return Err(ErrorBadRequest(
+            Response::error(
+                serde_json::Value::String(String::from("field `period` must be `day` or `week`"))
+            ).to_string()
+        ));
+    }
+
+    let csv: bool = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(_) => {
+            return Err(ErrorBadRequest(
+                Response::error(
+                    serde_json::Value::String(String::from("field `format` must be `json` or `csv`"))
+                ).to_string()
+            ));
+        }
+    };
+
+    let to: i64 = query.to.unwrap_or(ton::time_now() as i64);
+    let from: i64 = query.from.unwrap_or(to - DEFAULT_WINDOW);
+    if from >= to {
+        return Err(ErrorBadRequest(
+            Response::error(
+                serde_json::Value::String(String::from("field `from` must be less than `to`"))
+            ).to_string()
+        ));
+    }
+
+    return reports::fees(&pool, &period, from, to, csv).await;
+}
+
+/// Returns counts, volumes, average legs per spread and the success rate
+/// of mixer operations over a `window` of `24h`, `7d` or `30d` (default `24h`).
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - The statistics window.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the statistics or an error.
+#[get("/stats")]
+pub async fn stats(pool: Data<PgPool>, query: Query<StatsQuery>) -> Result<HttpResponse, Error> {
+    let window: String = query.into_inner().window.unwrap_or(String::from("24h"));
+
+    let seconds: i64 = match window_seconds(&window) {
+        Some(seconds) => seconds,
+        None => {
+            return Err(ErrorBadRequest(
+                Response::error(
+                    serde_json::Value::String(String::from("field `window` must be `24h`, `7d` or `30d`"))
+                ).to_string()
+            ));
+        }
+    };
+
+    let to: i64 = ton::time_now() as i64;
+    return reports::stats(&pool, window, to - seconds, to).await;
+}
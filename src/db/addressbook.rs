@@ -0,0 +1,105 @@
+//! # Address Book Queries
+//!
+//! This module provides queries over the labeled addresses of the address book.
+
+use sqlx::PgPool;
+
+use crate::{ton::time_now, types::addressbook::AddressBookEntry};
+
+/// Columns selected into an `AddressBookEntry`.
+const COLUMNS: &str = "id, name, address, raw_address, tags, notes, created_at, updated_at";
+
+/// Returns all entries by name, only those with `tag` if given.
+pub async fn list(pool: &PgPool, tag: Option<&str>) -> Result<Vec<AddressBookEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AddressBookEntry>(&format!(
+        "SELECT {} FROM address_book WHERE $1::TEXT IS NULL OR $1 = ANY(tags) ORDER BY name", COLUMNS
+    ))
+        .bind(tag)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns an entry by its id.
+pub async fn get(pool: &PgPool, id: i64) -> Result<Option<AddressBookEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AddressBookEntry>(&format!("SELECT {} FROM address_book WHERE id = $1", COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Returns the entries with the given names.
+pub async fn by_names(pool: &PgPool, names: &[String]) -> Result<Vec<AddressBookEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AddressBookEntry>(&format!("SELECT {} FROM address_book WHERE name = ANY($1)", COLUMNS))
+        .bind(names)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the entries of the given raw addresses.
+pub async fn by_raw_addresses(pool: &PgPool, raw_addresses: &[String]) -> Result<Vec<AddressBookEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AddressBookEntry>(&format!(
+        "SELECT {} FROM address_book WHERE raw_address = ANY($1) ORDER BY id", COLUMNS
+    ))
+        .bind(raw_addresses)
+        .fetch_all(pool)
+        .await
+}
+
+/// Stores a new entry.
+///
+/// # Returns
+///
+/// The entry, `None` if the name is already taken.
+pub async fn create(pool: &PgPool, name: &str, address: &str, raw_address: &str, tags: &Vec<String>, notes: Option<&str>) -> Result<Option<AddressBookEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AddressBookEntry>(&format!(
+        "INSERT INTO address_book (name, address, raw_address, tags, notes, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $6)
+         ON CONFLICT (name) DO NOTHING
+         RETURNING {}", COLUMNS
+    ))
+        .bind(name)
+        .bind(address)
+        .bind(raw_address)
+        .bind(tags)
+        .bind(notes)
+        .bind(time_now() as i64)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Replaces an entry.
+///
+/// # Returns
+///
+/// The updated entry, `None` if it does not exist. Fails with a unique violation if the name is taken by another entry.
+pub async fn update(pool: &PgPool, id: i64, name: &str, address: &str, raw_address: &str, tags: &Vec<String>, notes: Option<&str>) -> Result<Option<AddressBookEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AddressBookEntry>(&format!(
+        "UPDATE address_book
+         SET name = $2, address = $3, raw_address = $4, tags = $5, notes = $6, updated_at = $7
+         WHERE id = $1
+         RETURNING {}", COLUMNS
+    ))
+        .bind(id)
+        .bind(name)
+        .bind(address)
+        .bind(raw_address)
+        .bind(tags)
+        .bind(notes)
+        .bind(time_now() as i64)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Removes an entry.
+///
+/// # Returns
+///
+/// The number of removed rows.
+pub async fn remove(pool: &PgPool, id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM address_book WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
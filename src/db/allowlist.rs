@@ -0,0 +1,50 @@
+//! # Contract Allow-List Queries
+//!
+//! This module provides queries over the mixer contracts requests may target.
+
+use sqlx::PgPool;
+
+use crate::{ton::time_now, types::allowlist::AllowedContract};
+
+/// Returns all allowed contracts.
+pub async fn list(pool: &PgPool) -> Result<Vec<AllowedContract>, sqlx::Error> {
+    sqlx::query_as::<_, AllowedContract>("SELECT address, label, created_at FROM allowed_contracts ORDER BY created_at")
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns whether a contract is allowed.
+pub async fn contains(pool: &PgPool, address: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM allowed_contracts WHERE address = $1)")
+        .bind(address)
+        .fetch_one(pool)
+        .await
+}
+
+/// Adds a contract, replacing the label if it is already allowed.
+pub async fn add(pool: &PgPool, address: &str, label: Option<&str>) -> Result<AllowedContract, sqlx::Error> {
+    sqlx::query_as::<_, AllowedContract>(
+        "INSERT INTO allowed_contracts (address, label, created_at) VALUES ($1, $2, $3)
+         ON CONFLICT (address) DO UPDATE SET label = EXCLUDED.label
+         RETURNING address, label, created_at"
+    )
+        .bind(address)
+        .bind(label)
+        .bind(time_now() as i64)
+        .fetch_one(pool)
+        .await
+}
+
+/// Removes a contract.
+///
+/// # Returns
+///
+/// The number of removed rows.
+pub async fn remove(pool: &PgPool, address: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM allowed_contracts WHERE address = $1")
+        .bind(address)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
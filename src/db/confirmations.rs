@@ -0,0 +1,50 @@
+//! # Confirmation Queries
+//!
+//! This module provides queries over the one-time tokens confirming sensitive admin actions.
+
+use sqlx::PgPool;
+
+use crate::ton::time_now;
+
+/// Stores a confirmation token for an action on a subject with a digest of its parameters.
+pub async fn insert(pool: &PgPool, token: &str, action: &str, subject: &str, digest: &str, requested_by: Option<&str>, expires_at: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO confirmations (token, action, subject, digest, requested_by, created_at, expires_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)"
+    )
+        .bind(token)
+        .bind(action)
+        .bind(subject)
+        .bind(digest)
+        .bind(requested_by)
+        .bind(time_now() as i64)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Uses a confirmation token, which only succeeds once and before it expires.
+///
+/// # Returns
+///
+/// Whether the token was valid for the action, subject and digest.
+pub async fn consume(pool: &PgPool, token: &str, action: &str, subject: &str, digest: &str) -> Result<bool, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    let result = sqlx::query(
+        "UPDATE confirmations SET used_at = $5
+         WHERE token = $1 AND action = $2 AND subject = $3 AND digest = $4
+           AND used_at IS NULL AND expires_at > $5"
+    )
+        .bind(token)
+        .bind(action)
+        .bind(subject)
+        .bind(digest)
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
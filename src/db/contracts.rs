@@ -0,0 +1,82 @@
+//! # Contract Queries
+//!
+//! This module provides queries over the contracts tracked by the indexer.
+
+use sqlx::PgPool;
+
+use crate::types::events::MixerContract;
+
+/// Registers a contract for indexing, keeping the existing record if it is already known.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `address` - The contract address.
+/// * `parent` - The address of the contract it was forked from, if any.
+/// * `created_at` - The Unix timestamp the contract was discovered at.
+pub async fn register(pool: &PgPool, address: &str, parent: Option<&str>, created_at: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO mixer_contracts (address, parent, created_at) VALUES ($1, $2, $3)
+         ON CONFLICT (address) DO NOTHING"
+    )
+        .bind(address)
+        .bind(parent)
+        .bind(created_at)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns all tracked contracts.
+pub async fn list(pool: &PgPool) -> Result<Vec<MixerContract>, sqlx::Error> {
+    sqlx::query_as::<_, MixerContract>(
+        "SELECT address, parent, created_at, last_lt FROM mixer_contracts ORDER BY created_at"
+    )
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the addresses of the contracts forked from a contract.
+pub async fn children(pool: &PgPool, parent: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar::<_, String>("SELECT address FROM mixer_contracts WHERE parent = $1 ORDER BY created_at")
+        .bind(parent)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the most recent indexed forks as the parent, the query id of the fork and the child it deployed.
+///
+/// A child is matched to the fork event of its parent it was discovered in by the time of the transaction.
+pub async fn indexed_forks(pool: &PgPool, limit: i64) -> Result<Vec<(String, i64, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, i64, String)>(
+        "SELECT e.contract, e.query_id, c.address
+         FROM mixer_events e
+         JOIN mixer_contracts c ON c.parent = e.contract AND c.created_at = e.utime
+         WHERE e.op = 'fork' AND e.query_id IS NOT NULL
+         ORDER BY e.lt DESC
+         LIMIT $1"
+    )
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Stores the logical time of the last indexed transaction of a contract.
+pub async fn set_last_lt(pool: &PgPool, address: &str, last_lt: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE mixer_contracts SET last_lt = $2 WHERE address = $1")
+        .bind(address)
+        .bind(last_lt)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the logical time of the last indexed transaction of a contract, `None` if it is not tracked.
+pub async fn last_lt(pool: &PgPool, address: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>("SELECT last_lt FROM mixer_contracts WHERE address = $1")
+        .bind(address)
+        .fetch_optional(pool)
+        .await
+}
@@ -0,0 +1,102 @@
+//! # Deposit Queries
+//!
+//! This module provides queries over the payment requests incoming transfers are matched against.
+
+use serde_json::Value;
+use sqlx::{PgExecutor, PgPool};
+
+use crate::{ton::time_now, types::deposit::{Deposit, DEPOSIT_EXPIRED, DEPOSIT_PENDING}};
+
+/// Columns selected into a `Deposit`.
+const COLUMNS: &str = "id, contract, comment, amount, status, expires_at, received, sender, tx_hash, tx_lt, plan, created_at, updated_at";
+
+/// Stores a new pending payment request.
+pub async fn insert(pool: &PgPool, contract: &str, comment: &str, amount: i64, expires_at: i64, plan: Option<&Value>) -> Result<Deposit, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query_as::<_, Deposit>(&format!(
+        "INSERT INTO deposits (contract, comment, amount, status, expires_at, plan, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $7)
+         RETURNING {}", COLUMNS
+    ))
+        .bind(contract)
+        .bind(comment)
+        .bind(amount)
+        .bind(DEPOSIT_PENDING)
+        .bind(expires_at)
+        .bind(plan)
+        .bind(now)
+        .fetch_one(pool)
+        .await
+}
+
+/// Returns a payment request by its id.
+pub async fn get(pool: &PgPool, id: i64) -> Result<Option<Deposit>, sqlx::Error> {
+    sqlx::query_as::<_, Deposit>(&format!("SELECT {} FROM deposits WHERE id = $1", COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Returns the payment request of a contract carrying the given comment that no transfer settled
+/// yet, pending or already expired.
+pub async fn unsettled_by_comment(pool: &PgPool, contract: &str, comment: &str) -> Result<Option<Deposit>, sqlx::Error> {
+    sqlx::query_as::<_, Deposit>(&format!(
+        "SELECT {} FROM deposits WHERE contract = $1 AND comment = $2 AND status IN ($3, $4)", COLUMNS
+    ))
+        .bind(contract)
+        .bind(comment)
+        .bind(DEPOSIT_PENDING)
+        .bind(DEPOSIT_EXPIRED)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Records the transfer a payment request was matched with, if the request still has the status it was read with.
+///
+/// # Arguments
+///
+/// * `executor` - The pool, or the transaction scheduling the spread of the request.
+/// * `id` - The id of the request.
+/// * `from` - The status the request was read with, pending or expired.
+/// * `status` - The status the transfer settles it with.
+///
+/// # Returns
+///
+/// Whether the request was settled, `false` if another pass settled it first.
+pub async fn settle<'e, E: PgExecutor<'e>>(executor: E, id: i64, from: &str, status: &str, received: i64, sender: Option<&str>, tx_hash: &str, tx_lt: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE deposits SET status = $2, received = $3, sender = $4, tx_hash = $5, tx_lt = $6, updated_at = $7
+         WHERE id = $1 AND status = $8"
+    )
+        .bind(id)
+        .bind(status)
+        .bind(received)
+        .bind(sender)
+        .bind(tx_hash)
+        .bind(tx_lt)
+        .bind(time_now() as i64)
+        .bind(from)
+        .execute(executor)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Expires the pending payment requests past their expiry.
+///
+/// # Returns
+///
+/// The number of expired requests.
+pub async fn expire(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    let result = sqlx::query("UPDATE deposits SET status = $1, updated_at = $2 WHERE status = $3 AND expires_at < $2")
+        .bind(DEPOSIT_EXPIRED)
+        .bind(now)
+        .bind(DEPOSIT_PENDING)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,108 @@
+//! # Event Queries
+//!
+//! This module provides queries over the mixer events stored by the indexer.
+
+use sqlx::PgPool;
+
+use crate::types::events::MixerEvent;
+
+/// Stores an event, ignoring it if the transaction was already indexed.
+pub async fn insert(pool: &PgPool, event: &MixerEvent) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO mixer_events
+            (contract, lt, hash, utime, op, query_id, mode, source, value_in, value_out, total_fees, fwd_fees, body)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+         ON CONFLICT (contract, lt) DO NOTHING"
+    )
+        .bind(&event.contract)
+        .bind(event.lt)
+        .bind(&event.hash)
+        .bind(event.utime)
+        .bind(&event.op)
+        .bind(event.query_id)
+        .bind(event.mode)
+        .bind(&event.source)
+        .bind(event.value_in)
+        .bind(event.value_out)
+        .bind(event.total_fees)
+        .bind(event.fwd_fees)
+        .bind(&event.body)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the most recent events of all tracked contracts.
+pub async fn recent(pool: &PgPool, limit: i64) -> Result<Vec<MixerEvent>, sqlx::Error> {
+    sqlx::query_as::<_, MixerEvent>(
+        "SELECT contract, lt, hash, utime, op, query_id, mode, source, value_in, value_out, total_fees, fwd_fees, body
+         FROM mixer_events
+         ORDER BY utime DESC, lt DESC
+         LIMIT $1"
+    )
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the events whose message body carries the given query id, oldest first.
+pub async fn by_query_id(pool: &PgPool, query_id: i64) -> Result<Vec<MixerEvent>, sqlx::Error> {
+    sqlx::query_as::<_, MixerEvent>(
+        "SELECT contract, lt, hash, utime, op, query_id, mode, source, value_in, value_out, total_fees, fwd_fees, body
+         FROM mixer_events
+         WHERE query_id = $1
+         ORDER BY lt"
+    )
+        .bind(query_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the time of the oldest deposit of a contract that was not collected yet.
+///
+/// Deposits are incoming transfers and spreads made after the last collect of the contract.
+pub async fn oldest_uncollected(pool: &PgPool, contract: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT MIN(utime)
+         FROM mixer_events
+         WHERE contract = $1
+           AND value_in > 0
+           AND op <> 'collect'
+           AND lt > COALESCE((SELECT MAX(lt) FROM mixer_events WHERE contract = $1 AND op = 'collect'), 0)"
+    )
+        .bind(contract)
+        .fetch_one(pool)
+        .await
+}
+
+/// Returns the time of the newest deposit of a contract that was not collected yet.
+pub async fn newest_uncollected(pool: &PgPool, contract: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT MAX(utime)
+         FROM mixer_events
+         WHERE contract = $1
+           AND value_in > 0
+           AND op <> 'collect'
+           AND lt > COALESCE((SELECT MAX(lt) FROM mixer_events WHERE contract = $1 AND op = 'collect'), 0)"
+    )
+        .bind(contract)
+        .fetch_one(pool)
+        .await
+}
+
+/// Returns the nanotons the indexed collect of a contract with the given query id sent out,
+/// `None` until the indexer recorded it.
+pub async fn collected(pool: &PgPool, contract: &str, query_id: i64) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>(
+        "SELECT value_out
+         FROM mixer_events
+         WHERE contract = $1 AND op = 'collect' AND query_id = $2
+         ORDER BY lt DESC
+         LIMIT 1"
+    )
+        .bind(contract)
+        .bind(query_id)
+        .fetch_optional(pool)
+        .await
+}
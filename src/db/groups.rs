@@ -0,0 +1,137 @@
+//! # Recipient Group Queries
+//!
+//! This module provides queries over the recipient groups and their members.
+
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::{ton::time_now, types::groups::{RecipientGroup, RecipientGroupMember}};
+
+/// Columns selected into a `RecipientGroup`.
+const COLUMNS: &str = "id, name, created_at, updated_at";
+
+/// Loads the members of groups, in their order, into the groups.
+async fn with_members(pool: &PgPool, mut groups: Vec<RecipientGroup>) -> Result<Vec<RecipientGroup>, sqlx::Error> {
+    let ids: Vec<i64> = groups.iter().map(| g | g.id).collect();
+
+    let members: Vec<RecipientGroupMember> = sqlx::query_as::<_, RecipientGroupMember>(
+        "SELECT m.group_id, m.entry_id, e.name, e.address, m.weight
+         FROM recipient_group_members m JOIN address_book e ON e.id = m.entry_id
+         WHERE m.group_id = ANY($1)
+         ORDER BY m.group_id, m.position"
+    )
+        .bind(&ids)
+        .fetch_all(pool)
+        .await?;
+
+    for member in members {
+        if let Some(group) = groups.iter_mut().find(| g | g.id == member.group_id) {
+            group.members.push(member);
+        }
+    }
+
+    Ok(groups)
+}
+
+/// Replaces the members of a group.
+async fn set_members(tx: &mut Transaction<'_, Postgres>, id: i64, members: &[(i64, f64)]) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM recipient_group_members WHERE group_id = $1")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+
+    for (position, (entry_id, weight)) in members.iter().enumerate() {
+        sqlx::query("INSERT INTO recipient_group_members (group_id, entry_id, weight, position) VALUES ($1, $2, $3, $4)")
+            .bind(id)
+            .bind(entry_id)
+            .bind(weight)
+            .bind(position as i32)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Returns all groups with their members, by name.
+pub async fn list(pool: &PgPool) -> Result<Vec<RecipientGroup>, sqlx::Error> {
+    let groups: Vec<RecipientGroup> = sqlx::query_as::<_, RecipientGroup>(&format!("SELECT {} FROM recipient_groups ORDER BY name", COLUMNS))
+        .fetch_all(pool)
+        .await?;
+
+    with_members(pool, groups).await
+}
+
+/// Returns a group with its members by its id.
+pub async fn get(pool: &PgPool, id: i64) -> Result<Option<RecipientGroup>, sqlx::Error> {
+    let group: Option<RecipientGroup> = sqlx::query_as::<_, RecipientGroup>(&format!("SELECT {} FROM recipient_groups WHERE id = $1", COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+    match group {
+        Some(group) => Ok(with_members(pool, vec![group]).await?.pop()),
+        None => Ok(None)
+    }
+}
+
+/// Stores a new group with its members, given as entry ids and weights.
+///
+/// Fails with a unique violation if the name is taken, and a foreign key violation if an entry does not exist.
+pub async fn create(pool: &PgPool, name: &str, members: &[(i64, f64)]) -> Result<RecipientGroup, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let group: RecipientGroup = sqlx::query_as::<_, RecipientGroup>(&format!(
+        "INSERT INTO recipient_groups (name, created_at, updated_at) VALUES ($1, $2, $2) RETURNING {}", COLUMNS
+    ))
+        .bind(name)
+        .bind(time_now() as i64)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    set_members(&mut tx, group.id, members).await?;
+    tx.commit().await?;
+
+    Ok(with_members(pool, vec![group]).await?.remove(0))
+}
+
+/// Replaces the name and the members of a group.
+///
+/// # Returns
+///
+/// The updated group, `None` if it does not exist. Fails like `create` otherwise.
+pub async fn update(pool: &PgPool, id: i64, name: &str, members: &[(i64, f64)]) -> Result<Option<RecipientGroup>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let group: Option<RecipientGroup> = sqlx::query_as::<_, RecipientGroup>(&format!(
+        "UPDATE recipient_groups SET name = $2, updated_at = $3 WHERE id = $1 RETURNING {}", COLUMNS
+    ))
+        .bind(id)
+        .bind(name)
+        .bind(time_now() as i64)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let group: RecipientGroup = match group {
+        Some(group) => group,
+        None => return Ok(None)
+    };
+
+    set_members(&mut tx, id, members).await?;
+    tx.commit().await?;
+
+    Ok(with_members(pool, vec![group]).await?.pop())
+}
+
+/// Removes a group, its members stay in the address book.
+///
+/// # Returns
+///
+/// The number of removed rows.
+pub async fn remove(pool: &PgPool, id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM recipient_groups WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
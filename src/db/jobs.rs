@@ -0,0 +1,259 @@
+//! # Job Queries
+//!
+//! This module provides queries over the background jobs.
+
+use serde_json::Value;
+use sqlx::{PgExecutor, PgPool};
+
+use crate::{ton::time_now, types::jobs::{Job, JobListQuery, JobQueueStats, JOB_CANCELLED, JOB_PENDING, JOB_RUNNING}};
+
+/// Columns selected into a `Job`.
+const COLUMNS: &str = "id, kind, payload, status, run_at, priority, attempts, result, error, deposit_id, progress, cancelled_at, cancel_reason, held_at, approved_at, approved_by, created_at, updated_at";
+
+/// Stores a new pending job, on the pool or within a transaction.
+pub async fn insert<'e, E: PgExecutor<'e>>(executor: E, kind: &str, payload: &Value, run_at: i64, priority: i32, deposit_id: Option<i64>) -> Result<Job, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query_as::<_, Job>(&format!(
+        "INSERT INTO jobs (kind, payload, status, run_at, priority, deposit_id, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $7)
+         RETURNING {}", COLUMNS
+    ))
+        .bind(kind)
+        .bind(payload)
+        .bind(JOB_PENDING)
+        .bind(run_at)
+        .bind(priority)
+        .bind(deposit_id)
+        .bind(now)
+        .fetch_one(executor)
+        .await
+}
+
+/// Claims up to `limit` due jobs, marking them as running with a lease of `lease` seconds.
+///
+/// Jobs are claimed by priority, raised by one level for every `aging` seconds a job has been
+/// due, so routine jobs are not starved by a steady stream of urgent ones. Rows locked by
+/// another instance are skipped, so a job is only claimed once. Running jobs whose lease ran
+/// out, because the runner holding them stopped, are claimed again.
+pub async fn claim_due(pool: &PgPool, limit: i64, aging: i64, lease: i64) -> Result<Vec<Job>, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query_as::<_, Job>(&format!(
+        "UPDATE jobs SET status = $1, attempts = attempts + 1, locked_until = $2 + $6, updated_at = $2
+         WHERE id IN (
+             SELECT id FROM jobs
+             WHERE (status = $3 AND run_at <= $2) OR (status = $1 AND COALESCE(locked_until, 0) < $2)
+             ORDER BY priority + ($2 - run_at) / $5 DESC, run_at LIMIT $4 FOR UPDATE SKIP LOCKED
+         )
+         RETURNING {}", COLUMNS
+    ))
+        .bind(JOB_RUNNING)
+        .bind(now)
+        .bind(JOB_PENDING)
+        .bind(limit)
+        .bind(aging.max(1))
+        .bind(lease)
+        .fetch_all(pool)
+        .await
+}
+
+/// Extends the lease of a running job by `lease` seconds from now.
+pub async fn extend_lease(pool: &PgPool, id: i64, lease: i64) -> Result<(), sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query("UPDATE jobs SET locked_until = $2 + $3, updated_at = $2 WHERE id = $1 AND status = $4")
+        .bind(id)
+        .bind(now)
+        .bind(lease)
+        .bind(JOB_RUNNING)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Records the outcome of a job.
+pub async fn finish(pool: &PgPool, id: i64, status: &str, result: Option<&str>, error: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE jobs SET status = $2, result = $3, error = $4, updated_at = $5 WHERE id = $1")
+        .bind(id)
+        .bind(status)
+        .bind(result)
+        .bind(error)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Stores the progress of a running job, extending its lease by `lease` seconds from now.
+pub async fn set_progress(pool: &PgPool, id: i64, progress: &Value, lease: i64) -> Result<(), sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query("UPDATE jobs SET progress = $2, locked_until = $3 + $4, updated_at = $3 WHERE id = $1")
+        .bind(id)
+        .bind(progress)
+        .bind(now)
+        .bind(lease)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns a job by its id.
+pub async fn get(pool: &PgPool, id: i64) -> Result<Option<Job>, sqlx::Error> {
+    sqlx::query_as::<_, Job>(&format!("SELECT {} FROM jobs WHERE id = $1", COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Cancels a job that is still waiting in the queue.
+///
+/// The status is checked in the same statement, so a job claimed by a runner in the
+/// meantime is left alone.
+///
+/// # Returns
+///
+/// The cancelled job, or `None` if there is no pending job with the id.
+pub async fn cancel(pool: &PgPool, id: i64, reason: Option<&str>) -> Result<Option<Job>, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query_as::<_, Job>(&format!(
+        "UPDATE jobs SET status = $2, cancelled_at = $3, cancel_reason = $4, updated_at = $3
+         WHERE id = $1 AND status = $5
+         RETURNING {}", COLUMNS
+    ))
+        .bind(id)
+        .bind(JOB_CANCELLED)
+        .bind(now)
+        .bind(reason)
+        .bind(JOB_PENDING)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Puts a claimed job back into the queue, due at `run_at`, without counting the claim as an attempt.
+///
+/// A `reason`, e.g. the limit a held job breached, is stored as the error of the job and
+/// marks the job as held, so an operator can approve it.
+pub async fn defer(pool: &PgPool, id: i64, run_at: i64, reason: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE jobs SET status = $2, run_at = $3, attempts = attempts - 1, locked_until = NULL, error = COALESCE($5, error),
+             held_at = CASE WHEN $5::TEXT IS NULL THEN held_at ELSE $4 END, updated_at = $4
+         WHERE id = $1"
+    )
+        .bind(id)
+        .bind(JOB_PENDING)
+        .bind(run_at)
+        .bind(time_now() as i64)
+        .bind(reason)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the queued jobs held by a limit that were not approved since, held the longest first.
+pub async fn held(pool: &PgPool, limit: i64) -> Result<Vec<Job>, sqlx::Error> {
+    sqlx::query_as::<_, Job>(&format!(
+        "SELECT {} FROM jobs
+         WHERE status = $1 AND held_at IS NOT NULL AND (approved_at IS NULL OR approved_at < held_at)
+         ORDER BY held_at LIMIT $2", COLUMNS
+    ))
+        .bind(JOB_PENDING)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Approves a queued job held by a limit, making it due right away.
+///
+/// The status is checked in the same statement, so a job claimed by a runner in the
+/// meantime is left alone.
+///
+/// # Returns
+///
+/// The approved job, or `None` if there is no held job with the id.
+pub async fn approve(pool: &PgPool, id: i64, approved_by: &str) -> Result<Option<Job>, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query_as::<_, Job>(&format!(
+        "UPDATE jobs SET approved_at = $2, approved_by = $3, run_at = $2, updated_at = $2
+         WHERE id = $1 AND status = $4 AND held_at IS NOT NULL
+         RETURNING {}", COLUMNS
+    ))
+        .bind(id)
+        .bind(now)
+        .bind(approved_by)
+        .bind(JOB_PENDING)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Puts a failed job back into the queue to be tried again at `run_at`, keeping the error of the attempt.
+pub async fn retry(pool: &PgPool, id: i64, run_at: i64, error: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE jobs SET status = $2, run_at = $3, error = $4, locked_until = NULL, updated_at = $5 WHERE id = $1")
+        .bind(id)
+        .bind(JOB_PENDING)
+        .bind(run_at)
+        .bind(error)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the jobs matching the filters of a listing, newest first.
+pub async fn list(pool: &PgPool, query: &JobListQuery, limit: i64) -> Result<Vec<Job>, sqlx::Error> {
+    sqlx::query_as::<_, Job>(&format!(
+        "SELECT {} FROM jobs
+         WHERE ($1::TEXT IS NULL OR status = $1)
+           AND ($2::TEXT IS NULL OR kind = $2)
+           AND ($3::BIGINT IS NULL OR deposit_id = $3)
+           AND ($4::BIGINT IS NULL OR created_at >= $4)
+           AND ($5::BIGINT IS NULL OR created_at < $5)
+           AND ($6::BIGINT IS NULL OR id < $6)
+           AND ($8::INTEGER IS NULL OR priority = $8)
+         ORDER BY id DESC LIMIT $7", COLUMNS
+    ))
+        .bind(query.status.as_deref())
+        .bind(query.kind.as_deref())
+        .bind(query.deposit_id)
+        .bind(query.from)
+        .bind(query.to)
+        .bind(query.before)
+        .bind(limit)
+        .bind(query.priority)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the jobs scheduled for a deposit.
+pub async fn by_deposit(pool: &PgPool, deposit_id: i64) -> Result<Vec<Job>, sqlx::Error> {
+    sqlx::query_as::<_, Job>(&format!("SELECT {} FROM jobs WHERE deposit_id = $1 ORDER BY run_at, id", COLUMNS))
+        .bind(deposit_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the state of the queue per kind of job.
+pub async fn queue_stats(pool: &PgPool) -> Result<Vec<JobQueueStats>, sqlx::Error> {
+    sqlx::query_as::<_, JobQueueStats>(
+        "SELECT kind,
+             COUNT(*) FILTER (WHERE status = $1) AS pending,
+             COUNT(*) FILTER (WHERE status = $1 AND run_at <= $3) AS due,
+             COUNT(*) FILTER (WHERE status = $2) AS running,
+             COALESCE($3 - MIN(run_at) FILTER (WHERE status = $1 AND run_at <= $3), 0) AS oldest_due_age,
+             COALESCE(SUM(attempts - 1) FILTER (WHERE attempts > 1), 0) AS retries
+         FROM jobs GROUP BY kind ORDER BY kind"
+    )
+        .bind(JOB_PENDING)
+        .bind(JOB_RUNNING)
+        .bind(time_now() as i64)
+        .fetch_all(pool)
+        .await
+}
@@ -0,0 +1,29 @@
+//! # Leader Queries
+//!
+//! This module provides queries over the record of the elected sender.
+
+use sqlx::PgPool;
+
+use crate::{ton::time_now, types::leader::Leader};
+
+/// Records an instance as the leader, replacing the previous one.
+pub async fn set(pool: &PgPool, instance: &str, url: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO leader (id, instance, url, elected_at) VALUES (1, $1, $2, $3)
+         ON CONFLICT (id) DO UPDATE SET instance = EXCLUDED.instance, url = EXCLUDED.url, elected_at = EXCLUDED.elected_at"
+    )
+        .bind(instance)
+        .bind(url)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the last recorded leader, `None` if no instance was elected yet.
+pub async fn get(pool: &PgPool) -> Result<Option<Leader>, sqlx::Error> {
+    sqlx::query_as::<_, Leader>("SELECT instance, url, elected_at FROM leader WHERE id = 1")
+        .fetch_optional(pool)
+        .await
+}
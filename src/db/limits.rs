@@ -0,0 +1,120 @@
+//! # Withdrawal Limit Queries
+//!
+//! This module provides queries over the withdrawals counted against the daily limit and
+//! the overrides granted by admins.
+
+use sqlx::PgPool;
+
+use crate::{ton::time_now, types::limits::LimitOverride};
+
+/// Length of the rolling window of the daily limit in seconds.
+pub const WINDOW: i64 = 86400;
+
+/// Key of the advisory lock serializing reservations across instances.
+const LOCK_KEY: i64 = 0x6d69786c696d6974;
+
+/// Columns selected into a `LimitOverride`.
+const COLUMNS: &str = "id, amount, reason, requested_by, created_at, expires_at";
+
+/// Returns the nanotons withdrawn in the current window and granted by active overrides.
+pub async fn usage(pool: &PgPool) -> Result<(i64, i64), sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query_as::<_, (i64, i64)>(
+        "SELECT
+             (SELECT COALESCE(SUM(amount), 0)::BIGINT FROM withdrawals WHERE created_at > $1),
+             (SELECT COALESCE(SUM(amount), 0)::BIGINT FROM limit_overrides WHERE expires_at > $2)"
+    )
+        .bind(now - WINDOW)
+        .bind(now)
+        .fetch_one(pool)
+        .await
+}
+
+/// Records a withdrawal if it keeps the window within the limit.
+///
+/// The check and the insert run under an advisory lock, so concurrent requests on several
+/// instances can not overshoot the limit together.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `op` - The operation withdrawing, e.g. `spread`.
+/// * `contract` - The mixer contract of the operation, if any.
+/// * `amount` - The nanotons withdrawn.
+/// * `limit` - The daily limit in nanotons.
+///
+/// # Returns
+///
+/// The id of the recorded withdrawal, or the nanotons used and granted in the window if it exceeds the limit.
+pub async fn reserve(pool: &PgPool, op: &str, contract: Option<&str>, amount: i64, limit: i64) -> Result<Result<i64, (i64, i64)>, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(LOCK_KEY)
+        .execute(&mut *tx)
+        .await?;
+
+    let (used, granted): (i64, i64) = sqlx::query_as::<_, (i64, i64)>(
+        "SELECT
+             (SELECT COALESCE(SUM(amount), 0)::BIGINT FROM withdrawals WHERE created_at > $1),
+             (SELECT COALESCE(SUM(amount), 0)::BIGINT FROM limit_overrides WHERE expires_at > $2)"
+    )
+        .bind(now - WINDOW)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    if used + amount > limit + granted {
+        tx.rollback().await?;
+        return Ok(Err((used, granted)));
+    }
+
+    let id: i64 = sqlx::query_scalar::<_, i64>("INSERT INTO withdrawals (op, contract, amount, created_at) VALUES ($1, $2, $3, $4) RETURNING id")
+        .bind(op)
+        .bind(contract)
+        .bind(amount)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Ok(id))
+}
+
+/// Deletes a withdrawal whose message was never sent, freeing its amount in the window.
+pub async fn release(pool: &PgPool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM withdrawals WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Stores an override granting `amount` extra nanotons until `expires_at`.
+pub async fn add_override(pool: &PgPool, amount: i64, reason: &str, requested_by: Option<&str>, expires_at: i64) -> Result<LimitOverride, sqlx::Error> {
+    sqlx::query_as::<_, LimitOverride>(&format!(
+        "INSERT INTO limit_overrides (amount, reason, requested_by, created_at, expires_at)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING {}", COLUMNS
+    ))
+        .bind(amount)
+        .bind(reason)
+        .bind(requested_by)
+        .bind(time_now() as i64)
+        .bind(expires_at)
+        .fetch_one(pool)
+        .await
+}
+
+/// Returns the overrides that are still active.
+pub async fn active_overrides(pool: &PgPool) -> Result<Vec<LimitOverride>, sqlx::Error> {
+    sqlx::query_as::<_, LimitOverride>(&format!(
+        "SELECT {} FROM limit_overrides WHERE expires_at > $1 ORDER BY created_at", COLUMNS
+    ))
+        .bind(time_now() as i64)
+        .fetch_all(pool)
+        .await
+}
@@ -0,0 +1,51 @@
+//! # Database
+//!
+//! This module sets up the PostgreSQL connection pool used by the application
+//! and applies the migrations from the `migrations` directory on startup.
+
+use sqlx::{postgres::PgPoolOptions, PgPool};
+
+use crate::config;
+
+pub mod addressbook;
+pub mod allowlist;
+pub mod confirmations;
+pub mod contracts;
+pub mod deposits;
+pub mod events;
+pub mod groups;
+pub mod jobs;
+pub mod leader;
+pub mod limits;
+pub mod multisig;
+pub mod notifications;
+pub mod operators;
+pub mod outbox;
+pub mod reports;
+pub mod schedules;
+pub mod state;
+pub mod topups;
+pub mod webhooks;
+
+/// Connects to the database and runs pending migrations.
+///
+/// # Panics
+///
+/// Panics if the `DATABASE_URL` environment variable is not set, the database
+/// is unreachable, or a migration fails to apply.
+pub async fn connect() -> PgPool {
+    let database_url: String = config::var("DATABASE_URL").unwrap();
+
+    let pool: PgPool = match PgPoolOptions::new().max_connections(10).connect(&database_url).await {
+        Ok(pool) => pool,
+        Err(err) => {
+            panic!("[ FATAL ] Database Initialization Error: Can not establish connection \n {:?}", err);
+        }
+    };
+
+    if let Err(err) = sqlx::migrate!("./migrations").run(&pool).await {
+        panic!("[ FATAL ] Database Migration Error: \n {:?}", err);
+    }
+
+    pool
+}
@@ -0,0 +1,57 @@
+//! # Multisig Queries
+//!
+//! This module allocates the seqnos of the orders proposed to a multisig.
+
+use sqlx::PgPool;
+
+use crate::ton::time_now;
+
+/// Seconds after the last allocation from which the seqno on chain is trusted again, long
+/// after the messages of orders that were allocated but never created expired.
+const ALLOCATION_HOLD: i64 = 600;
+
+/// Allocates the seqno of a new order, at least the next seqno of the multisig on chain.
+///
+/// The row of the multisig is locked by the upsert, so concurrent orders get one seqno each.
+/// Once no seqno was allocated for `ALLOCATION_HOLD` seconds the seqno on chain wins, so a
+/// seqno whose order never reached the chain does not block the multisig.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `multisig` - The address of the multisig.
+/// * `onchain` - The next order seqno the multisig reports.
+///
+/// # Returns
+///
+/// The allocated seqno.
+pub async fn allocate_order_seqno(pool: &PgPool, multisig: &str, onchain: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>(
+        "INSERT INTO multisig_order_seqnos (multisig, next_seqno, updated_at) VALUES ($1, $2 + 1, $3)
+         ON CONFLICT (multisig) DO UPDATE
+         SET next_seqno = CASE
+                 WHEN multisig_order_seqnos.updated_at < $3 - $4 THEN $2
+                 ELSE GREATEST(multisig_order_seqnos.next_seqno, $2)
+             END + 1,
+             updated_at = $3
+         RETURNING next_seqno - 1"
+    )
+        .bind(multisig)
+        .bind(onchain)
+        .bind(time_now() as i64)
+        .bind(ALLOCATION_HOLD)
+        .fetch_one(pool)
+        .await
+}
+
+/// Gives back the seqno of an order that was never sent, unless a later one was allocated meanwhile.
+pub async fn release_order_seqno(pool: &PgPool, multisig: &str, seqno: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE multisig_order_seqnos SET next_seqno = $2, updated_at = $3 WHERE multisig = $1 AND next_seqno = $2 + 1")
+        .bind(multisig)
+        .bind(seqno)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
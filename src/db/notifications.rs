@@ -0,0 +1,52 @@
+//! # Notification Route Queries
+//!
+//! This module provides queries over the per-event notification routing table.
+
+use sqlx::PgPool;
+
+use crate::types::notifications::NotificationRoute;
+
+/// Returns all notification routes.
+pub async fn list(pool: &PgPool) -> Result<Vec<NotificationRoute>, sqlx::Error> {
+    sqlx::query_as::<_, NotificationRoute>("SELECT event, channel FROM notification_routes ORDER BY event, channel")
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the channels an event is routed to.
+pub async fn channels_for(pool: &PgPool, event: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar::<_, String>("SELECT channel FROM notification_routes WHERE event = $1")
+        .bind(event)
+        .fetch_all(pool)
+        .await
+}
+
+/// Replaces the channels an event is routed to.
+pub async fn set(pool: &PgPool, event: &str, channels: &Vec<String>) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM notification_routes WHERE event = $1")
+        .bind(event)
+        .execute(&mut *tx)
+        .await?;
+
+    for channel in channels.iter() {
+        sqlx::query("INSERT INTO notification_routes (event, channel) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(event)
+            .bind(channel)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Removes all routes of an event, so it is delivered to every channel again.
+pub async fn remove(pool: &PgPool, event: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM notification_routes WHERE event = $1")
+        .bind(event)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
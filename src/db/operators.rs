@@ -0,0 +1,38 @@
+//! # Operator Request Queries
+//!
+//! This module records the operator signed requests accepted within their signature window.
+
+use sqlx::PgPool;
+
+use crate::ton::time_now;
+
+/// Records an accepted request, dropping the records whose window passed.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `digest` - The hash of the operator and the signed message.
+/// * `operator` - The name of the operator who signed the request.
+/// * `expires_at` - Unix time the signature window of the request ends.
+///
+/// # Returns
+///
+/// `true` if the request was recorded, `false` if it was accepted before.
+pub async fn claim(pool: &PgPool, digest: &str, operator: &str, expires_at: i64) -> Result<bool, sqlx::Error> {
+    sqlx::query("DELETE FROM operator_requests WHERE expires_at < $1")
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    let inserted = sqlx::query(
+        "INSERT INTO operator_requests (digest, operator, expires_at) VALUES ($1, $2, $3)
+         ON CONFLICT (digest) DO NOTHING"
+    )
+        .bind(digest)
+        .bind(operator)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+
+    Ok(inserted.rows_affected() == 1)
+}
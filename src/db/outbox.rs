@@ -0,0 +1,126 @@
+//! # Outbox Queries
+//!
+//! This module provides queries over the outbox of signed external messages.
+
+use sqlx::PgPool;
+
+use crate::{jobs, ton::time_now, types::{outbox::{OutboxEntry, OUTBOX_CONFIRMED, OUTBOX_EXPIRED, OUTBOX_PENDING, OUTBOX_SENT, OUTBOX_UNKNOWN}, SignedExternalMessage}};
+
+/// Stores a signed external message before it is broadcast.
+///
+/// The message is linked to the job sending it, if it is sent by the job runner, see `jobs::current`.
+///
+/// # Returns
+///
+/// The id of the outbox entry.
+pub async fn insert(pool: &PgPool, wallet: &str, op: &str, query_id: Option<u64>, seqno: u32, valid_until: u64, usd_rate: Option<f64>, message: &SignedExternalMessage) -> Result<i64, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query_scalar::<_, i64>(
+        "INSERT INTO outbox (wallet, op, query_id, seqno, valid_until, usd_rate, boc, normalized_hash, status, job_id, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $11, $10, $10)
+         RETURNING id"
+    )
+        .bind(wallet)
+        .bind(op)
+        .bind(query_id.map(| q | q as i64))
+        .bind(seqno as i64)
+        .bind(valid_until as i64)
+        .bind(usd_rate)
+        .bind(&message.boc)
+        .bind(hex::encode(message.normalized_hash))
+        .bind(OUTBOX_PENDING)
+        .bind(now)
+        .bind(jobs::current())
+        .fetch_one(pool)
+        .await
+}
+
+/// Returns the ids of the entries a job wrote that may have been applied, i.e. are not expired,
+/// only those of operation `op` if given.
+pub async fn sent_by_job(pool: &PgPool, job_id: i64, op: Option<&str>) -> Result<Vec<i64>, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>("SELECT id FROM outbox WHERE job_id = $1 AND status <> $2 AND ($3::TEXT IS NULL OR op = $3) ORDER BY id")
+        .bind(job_id)
+        .bind(OUTBOX_EXPIRED)
+        .bind(op)
+        .fetch_all(pool)
+        .await
+}
+
+/// Marks an entry as sent and stores the hash of the broadcast message.
+pub async fn mark_sent(pool: &PgPool, id: i64, message_hash: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE outbox SET status = $2, message_hash = $3, updated_at = $4 WHERE id = $1")
+        .bind(id)
+        .bind(OUTBOX_SENT)
+        .bind(message_hash)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Marks an entry whose request timed out as unknown, unless it is known to be sent already.
+pub async fn mark_unknown(pool: &PgPool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE outbox SET status = $2, updated_at = $3 WHERE id = $1 AND status = $4")
+        .bind(id)
+        .bind(OUTBOX_UNKNOWN)
+        .bind(time_now() as i64)
+        .bind(OUTBOX_PENDING)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Sets the status of an entry.
+pub async fn set_status(pool: &PgPool, id: i64, status: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE outbox SET status = $2, updated_at = $3 WHERE id = $1")
+        .bind(id)
+        .bind(status)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the entries of a wallet that are neither confirmed nor expired, oldest first.
+pub async fn unconfirmed(pool: &PgPool, wallet: &str) -> Result<Vec<OutboxEntry>, sqlx::Error> {
+    sqlx::query_as::<_, OutboxEntry>(
+        "SELECT id, wallet, op, query_id, seqno, valid_until, boc, status, message_hash, normalized_hash, usd_rate, created_at, updated_at
+         FROM outbox
+         WHERE wallet = $1 AND status NOT IN ($2, $3)
+         ORDER BY seqno, id"
+    )
+        .bind(wallet)
+        .bind(OUTBOX_CONFIRMED)
+        .bind(OUTBOX_EXPIRED)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the entries that carry the given query id, oldest first.
+pub async fn by_query_id(pool: &PgPool, query_id: i64) -> Result<Vec<OutboxEntry>, sqlx::Error> {
+    sqlx::query_as::<_, OutboxEntry>(
+        "SELECT id, wallet, op, query_id, seqno, valid_until, boc, status, message_hash, normalized_hash, usd_rate, created_at, updated_at
+         FROM outbox
+         WHERE query_id = $1
+         ORDER BY id"
+    )
+        .bind(query_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns the most recent entries, newest first.
+pub async fn recent(pool: &PgPool, limit: i64) -> Result<Vec<OutboxEntry>, sqlx::Error> {
+    sqlx::query_as::<_, OutboxEntry>(
+        "SELECT id, wallet, op, query_id, seqno, valid_until, boc, status, message_hash, normalized_hash, usd_rate, created_at, updated_at
+         FROM outbox
+         ORDER BY id DESC LIMIT $1"
+    )
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
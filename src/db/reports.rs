@@ -0,0 +1,71 @@
+//! # Report Queries
+//!
+//! This module provides aggregate queries over indexed events used by the accounting reports.
+
+use sqlx::PgPool;
+
+use crate::types::reports::{FeeReportRow, OperationStats};
+
+/// Summarizes fees and flows of all tracked contracts per period.
+///
+/// Contract fee income is the value a spread left on the contract after paying
+/// out the recipients and the fees of the transaction. The forward fees of the legs
+/// are taken from the values sent and the action phase fees are part of the total
+/// fees, so the forward fees are not subtracted on top.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `period` - The `date_trunc` unit to group by (`day` or `week`).
+/// * `from` - The Unix timestamp the report starts at (inclusive).
+/// * `to` - The Unix timestamp the report ends at (exclusive).
+pub async fn fees(pool: &PgPool, period: &str, from: i64, to: i64) -> Result<Vec<FeeReportRow>, sqlx::Error> {
+    sqlx::query_as::<_, FeeReportRow>(
+        "SELECT
+            EXTRACT(EPOCH FROM date_trunc($1, to_timestamp(utime) AT TIME ZONE 'UTC'))::BIGINT AS period_start,
+            COUNT(*)::BIGINT AS transactions,
+            COALESCE(SUM(total_fees), 0)::BIGINT AS gas_fees,
+            COALESCE(SUM(fwd_fees), 0)::BIGINT AS forward_fees,
+            COALESCE(SUM(CASE WHEN op = 'spread' THEN value_in - value_out - total_fees ELSE 0 END), 0)::BIGINT AS fee_income,
+            COALESCE(SUM(value_in), 0)::BIGINT AS inflow,
+            COALESCE(SUM(value_out), 0)::BIGINT AS outflow,
+            COALESCE(SUM(value_in - value_out), 0)::BIGINT AS net_flow
+         FROM mixer_events
+         WHERE utime >= $2 AND utime < $3
+         GROUP BY 1
+         ORDER BY 1"
+    )
+        .bind(period)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await
+}
+
+/// Aggregates spread, collect and fork events since the given time.
+///
+/// An operation counts as succeeded when its transaction sent out value
+/// (paid the recipients, the collector or deployed the fork).
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `from` - The Unix timestamp the window starts at.
+pub async fn operation_stats(pool: &PgPool, from: i64) -> Result<Vec<OperationStats>, sqlx::Error> {
+    sqlx::query_as::<_, OperationStats>(
+        "SELECT
+            op,
+            COUNT(*)::BIGINT AS count,
+            COUNT(*) FILTER (WHERE value_out > 0)::BIGINT AS succeeded,
+            COALESCE(SUM(value_in), 0)::BIGINT AS volume_in,
+            COALESCE(SUM(value_out), 0)::BIGINT AS volume_out,
+            COALESCE(AVG(jsonb_array_length(body -> 'recipients')), 0)::DOUBLE PRECISION AS average_legs
+         FROM mixer_events
+         WHERE utime >= $1 AND op IN ('spread', 'collect', 'fork')
+         GROUP BY op
+         ORDER BY op"
+    )
+        .bind(from)
+        .fetch_all(pool)
+        .await
+}
@@ -0,0 +1,120 @@
+//! # Schedule Queries
+//!
+//! This module provides queries over the recurring schedules.
+
+use sqlx::PgPool;
+
+use crate::{ton::time_now, types::schedules::{OperationTemplate, Schedule}};
+
+/// Columns selected into a `Schedule`.
+const COLUMNS: &str = "id, name, cron, kind, payload, priority, paused, next_run_at, last_run_at, last_job_id, last_error, created_at, updated_at";
+
+/// Returns all schedules.
+pub async fn list(pool: &PgPool) -> Result<Vec<Schedule>, sqlx::Error> {
+    sqlx::query_as::<_, Schedule>(&format!("SELECT {} FROM schedules ORDER BY id", COLUMNS))
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns a schedule by its id.
+pub async fn get(pool: &PgPool, id: i64) -> Result<Option<Schedule>, sqlx::Error> {
+    sqlx::query_as::<_, Schedule>(&format!("SELECT {} FROM schedules WHERE id = $1", COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Stores a new schedule.
+pub async fn create(pool: &PgPool, name: &str, cron: &str, template: &OperationTemplate, priority: i32, paused: bool, next_run_at: i64) -> Result<Schedule, sqlx::Error> {
+    sqlx::query_as::<_, Schedule>(&format!(
+        "INSERT INTO schedules (name, cron, kind, payload, priority, paused, next_run_at, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8)
+         RETURNING {}", COLUMNS
+    ))
+        .bind(name)
+        .bind(cron)
+        .bind(template.kind())
+        .bind(template.payload())
+        .bind(priority)
+        .bind(paused)
+        .bind(next_run_at)
+        .bind(time_now() as i64)
+        .fetch_one(pool)
+        .await
+}
+
+/// Pauses or resumes a schedule, resumed schedules run next at `next_run_at`.
+///
+/// # Returns
+///
+/// The updated schedule, `None` if it does not exist.
+pub async fn set_paused(pool: &PgPool, id: i64, paused: bool, next_run_at: Option<i64>) -> Result<Option<Schedule>, sqlx::Error> {
+    sqlx::query_as::<_, Schedule>(&format!(
+        "UPDATE schedules SET paused = $2, next_run_at = COALESCE($3, next_run_at), updated_at = $4
+         WHERE id = $1
+         RETURNING {}", COLUMNS
+    ))
+        .bind(id)
+        .bind(paused)
+        .bind(next_run_at)
+        .bind(time_now() as i64)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Removes a schedule, the jobs it submitted stay in the queue.
+///
+/// # Returns
+///
+/// The number of removed rows.
+pub async fn remove(pool: &PgPool, id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM schedules WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Returns the schedules that are not paused and due.
+pub async fn due(pool: &PgPool) -> Result<Vec<Schedule>, sqlx::Error> {
+    sqlx::query_as::<_, Schedule>(&format!(
+        "SELECT {} FROM schedules WHERE NOT paused AND next_run_at <= $1 ORDER BY next_run_at", COLUMNS
+    ))
+        .bind(time_now() as i64)
+        .fetch_all(pool)
+        .await
+}
+
+/// Moves a due schedule to its next run, unless another instance already did.
+///
+/// # Returns
+///
+/// Whether this call claimed the run.
+pub async fn claim_run(pool: &PgPool, id: i64, run_at: i64, next_run_at: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE schedules SET next_run_at = $3, last_run_at = $4, updated_at = $4
+         WHERE id = $1 AND next_run_at = $2 AND NOT paused"
+    )
+        .bind(id)
+        .bind(run_at)
+        .bind(next_run_at)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Records the outcome of a run.
+pub async fn record_run(pool: &PgPool, id: i64, job_id: Option<i64>, error: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE schedules SET last_job_id = $2, last_error = $3, updated_at = $4 WHERE id = $1")
+        .bind(id)
+        .bind(job_id)
+        .bind(error)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
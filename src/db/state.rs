@@ -0,0 +1,30 @@
+//! # Mixer State Queries
+//!
+//! This module provides queries over the state of the mixer shared by all instances.
+
+use sqlx::PgPool;
+
+use crate::ton::time_now;
+
+/// Returns whether invocations of the mixer contract are paused.
+pub async fn paused(pool: &PgPool) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>("SELECT paused FROM mixer_state WHERE id = 1")
+        .fetch_optional(pool)
+        .await
+        .map(| paused | paused.unwrap_or(false))
+}
+
+/// Pauses or resumes invocations of the mixer contract.
+pub async fn set_paused(pool: &PgPool, paused: bool, updated_by: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO mixer_state (id, paused, updated_by, updated_at) VALUES (1, $1, $2, $3)
+         ON CONFLICT (id) DO UPDATE SET paused = EXCLUDED.paused, updated_by = EXCLUDED.updated_by, updated_at = EXCLUDED.updated_at"
+    )
+        .bind(paused)
+        .bind(updated_by)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+//! # Gas Top-Up Queries
+//!
+//! This module provides queries over the audit records of the gas wallet top-ups.
+
+use sqlx::PgPool;
+
+use crate::{ton::time_now, types::topups::GasTopUp};
+
+/// Records a top-up of the gas wallet.
+pub async fn insert(pool: &PgPool, source: &str, sender: &str, amount: i64, wallet_balance: i64, threshold: i64, tx_hash: Option<&str>, error: Option<&str>) -> Result<GasTopUp, sqlx::Error> {
+    sqlx::query_as::<_, GasTopUp>(
+        "INSERT INTO gas_topups (source, sender, amount, wallet_balance, threshold, tx_hash, error, created_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+         RETURNING id, source, sender, amount, wallet_balance, threshold, tx_hash, error, created_at"
+    )
+        .bind(source)
+        .bind(sender)
+        .bind(amount)
+        .bind(wallet_balance)
+        .bind(threshold)
+        .bind(tx_hash)
+        .bind(error)
+        .bind(time_now() as i64)
+        .fetch_one(pool)
+        .await
+}
+
+/// Returns the most recent top-ups, newest first.
+pub async fn list(pool: &PgPool, limit: i64) -> Result<Vec<GasTopUp>, sqlx::Error> {
+    sqlx::query_as::<_, GasTopUp>(
+        "SELECT id, source, sender, amount, wallet_balance, threshold, tx_hash, error, created_at
+         FROM gas_topups ORDER BY id DESC LIMIT $1"
+    )
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
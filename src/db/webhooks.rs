@@ -0,0 +1,188 @@
+//! # Webhook Queries
+//!
+//! This module provides queries over the webhook subscriptions and their deliveries.
+
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{ton::time_now, types::webhooks::{WebhookDelivery, WebhookSubscription, DELIVERY_PENDING}};
+
+/// Columns selected into a `WebhookSubscription`.
+const SUBSCRIPTION_COLUMNS: &str = "id, url, secret, events, enabled, created_at, updated_at";
+
+/// Columns selected into a `WebhookDelivery`.
+const DELIVERY_COLUMNS: &str = "id, subscription_id, event, payload, status, attempts, next_attempt_at, response_status, error, created_at, updated_at";
+
+/// Returns all subscriptions.
+pub async fn list(pool: &PgPool) -> Result<Vec<WebhookSubscription>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookSubscription>(&format!("SELECT {} FROM webhook_subscriptions ORDER BY id", SUBSCRIPTION_COLUMNS))
+        .fetch_all(pool)
+        .await
+}
+
+/// Returns a subscription by its id.
+pub async fn get(pool: &PgPool, id: i64) -> Result<Option<WebhookSubscription>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookSubscription>(&format!("SELECT {} FROM webhook_subscriptions WHERE id = $1", SUBSCRIPTION_COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Stores a new subscription.
+pub async fn create(pool: &PgPool, url: &str, secret: &str, events: &Vec<String>, enabled: bool) -> Result<WebhookSubscription, sqlx::Error> {
+    sqlx::query_as::<_, WebhookSubscription>(&format!(
+        "INSERT INTO webhook_subscriptions (url, secret, events, enabled, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $5)
+         RETURNING {}", SUBSCRIPTION_COLUMNS
+    ))
+        .bind(url)
+        .bind(secret)
+        .bind(events)
+        .bind(enabled)
+        .bind(time_now() as i64)
+        .fetch_one(pool)
+        .await
+}
+
+/// Replaces a subscription, keeping its secret when none is given.
+///
+/// # Returns
+///
+/// The updated subscription, `None` if it does not exist.
+pub async fn update(pool: &PgPool, id: i64, url: &str, secret: Option<&str>, events: &Vec<String>, enabled: bool) -> Result<Option<WebhookSubscription>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookSubscription>(&format!(
+        "UPDATE webhook_subscriptions
+         SET url = $2, secret = COALESCE($3, secret), events = $4, enabled = $5, updated_at = $6
+         WHERE id = $1
+         RETURNING {}", SUBSCRIPTION_COLUMNS
+    ))
+        .bind(id)
+        .bind(url)
+        .bind(secret)
+        .bind(events)
+        .bind(enabled)
+        .bind(time_now() as i64)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Removes a subscription and its delivery log.
+///
+/// # Returns
+///
+/// The number of removed rows.
+pub async fn remove(pool: &PgPool, id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM webhook_subscriptions WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Queues a delivery of an event to every enabled subscription that wants it.
+///
+/// # Returns
+///
+/// The number of queued deliveries.
+pub async fn enqueue(pool: &PgPool, event: &str, payload: &Value) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO webhook_deliveries (subscription_id, event, payload, status, next_attempt_at, created_at, updated_at)
+         SELECT id, $1, $2, $3, $4, $4, $4 FROM webhook_subscriptions
+         WHERE enabled AND (cardinality(events) = 0 OR $1 = ANY(events))"
+    )
+        .bind(event)
+        .bind(payload)
+        .bind(DELIVERY_PENDING)
+        .bind(time_now() as i64)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Stores a delivery of an event to one subscription, due at `next_attempt_at`.
+pub async fn insert_delivery(pool: &PgPool, subscription_id: i64, event: &str, payload: &Value, next_attempt_at: i64) -> Result<WebhookDelivery, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query_as::<_, WebhookDelivery>(&format!(
+        "INSERT INTO webhook_deliveries (subscription_id, event, payload, status, next_attempt_at, created_at, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $6)
+         RETURNING {}", DELIVERY_COLUMNS
+    ))
+        .bind(subscription_id)
+        .bind(event)
+        .bind(payload)
+        .bind(DELIVERY_PENDING)
+        .bind(next_attempt_at)
+        .bind(now)
+        .fetch_one(pool)
+        .await
+}
+
+/// Claims up to `limit` due deliveries.
+///
+/// The next attempt is pushed back by `lease` seconds, so a delivery claimed by an
+/// instance that dies is retried, and rows locked by another instance are skipped.
+pub async fn claim_due(pool: &PgPool, limit: i64, lease: i64) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    let now: i64 = time_now() as i64;
+
+    sqlx::query_as::<_, WebhookDelivery>(&format!(
+        "UPDATE webhook_deliveries SET next_attempt_at = $2 + $3, updated_at = $2
+         WHERE id IN (
+             SELECT id FROM webhook_deliveries WHERE status = $1 AND next_attempt_at <= $2
+             ORDER BY next_attempt_at LIMIT $4 FOR UPDATE SKIP LOCKED
+         )
+         RETURNING {}", DELIVERY_COLUMNS
+    ))
+        .bind(DELIVERY_PENDING)
+        .bind(now)
+        .bind(lease)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Records the outcome of an attempt of a claimed delivery and counts it.
+///
+/// A pending `status` schedules the next attempt at `next_attempt_at`. The outcome is only
+/// recorded if the delivery is still pending with the attempts and lease it was claimed with,
+/// so an attempt that outlived its lease does not overwrite the one of the instance that
+/// reclaimed it.
+///
+/// # Returns
+///
+/// The updated delivery, `None` if it was removed or reclaimed meanwhile.
+pub async fn record_attempt(pool: &PgPool, claimed: &WebhookDelivery, status: &str, next_attempt_at: i64, response_status: Option<i32>, error: Option<&str>) -> Result<Option<WebhookDelivery>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookDelivery>(&format!(
+        "UPDATE webhook_deliveries
+         SET status = $2, attempts = attempts + 1, next_attempt_at = $3, response_status = $4, error = $5, updated_at = $6
+         WHERE id = $1 AND status = $7 AND attempts = $8 AND next_attempt_at = $9
+         RETURNING {}", DELIVERY_COLUMNS
+    ))
+        .bind(claimed.id)
+        .bind(status)
+        .bind(next_attempt_at)
+        .bind(response_status)
+        .bind(error)
+        .bind(time_now() as i64)
+        .bind(DELIVERY_PENDING)
+        .bind(claimed.attempts)
+        .bind(claimed.next_attempt_at)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Returns the most recent deliveries of a subscription, newest first.
+pub async fn deliveries(pool: &PgPool, subscription_id: i64, status: Option<&str>, limit: i64) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    sqlx::query_as::<_, WebhookDelivery>(&format!(
+        "SELECT {} FROM webhook_deliveries
+         WHERE subscription_id = $1 AND ($2::TEXT IS NULL OR status = $2)
+         ORDER BY id DESC LIMIT $3", DELIVERY_COLUMNS
+    ))
+        .bind(subscription_id)
+        .bind(status)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
@@ -0,0 +1,107 @@
+//! # Request Deadlines
+//!
+//! This module bounds the time an API call may take. `enforce` runs every handler under a
+//! deadline of `REQUEST_DEADLINE` milliseconds, which a caller may change with the
+//! `X-Request-Deadline` header within `REQUEST_DEADLINE_MIN` and `REQUEST_DEADLINE_MAX`.
+//!
+//! When the deadline passes the handler is dropped, so no further TON work of the request
+//! is started. Outbox entries the request already wrote but did not see broadcast are
+//! marked `unknown`, and the caller gets a 504 listing the operations to poll later with
+//! `GET /operations/by-query-id/{id}`. The outbox task confirms or expires them as usual.
+//! Seqno locks and daily limit reservations of the dropped handler release themselves
+//! when dropped, see `ton::lock` and `ton::send_transfers`.
+
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use actix_web::{body::{EitherBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, middleware::Next, web::Data, Error, HttpMessage, HttpRequest, HttpResponse};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{config, db, logging::RequestId, types::Response, warnings};
+
+/// Header a caller requests a deadline in milliseconds with.
+pub const DEADLINE_HEADER: &str = "x-request-deadline";
+
+/// Represents an operation a request wrote to the outbox.
+#[derive(Serialize, Debug, Clone)]
+pub struct TrackedOperation {
+    pub outbox_id: i64,
+    pub op: String,
+    pub query_id: Option<u64>
+}
+
+tokio::task_local! {
+    /// The operations of the request handled by the current task.
+    static OPERATIONS: Rc<RefCell<Vec<TrackedOperation>>>;
+}
+
+/// Records an operation written to the outbox by the current request.
+///
+/// # Arguments
+///
+/// * `outbox_id` - The id of the outbox entry.
+/// * `op` - The operation, e.g. `spread`.
+/// * `query_id` - The query id of the message body, if any.
+pub fn track(outbox_id: i64, op: &str, query_id: Option<u64>) {
+    let _ = OPERATIONS.try_with(| operations | {
+        operations.borrow_mut().push(TrackedOperation { outbox_id, op: op.to_string(), query_id });
+    });
+}
+
+/// Returns the deadline of a request, the requested one clamped to the configured bounds.
+///
+/// A clamped deadline is reported with `warnings::warn`, which needs `warnings::collect`
+/// to wrap `enforce`.
+fn deadline_of(req: &ServiceRequest) -> Duration {
+    let config: std::sync::Arc<config::RuntimeConfig> = config::runtime();
+    let requested: Option<u64> = req.headers().get(DEADLINE_HEADER)
+        .and_then(| v | v.to_str().ok())
+        .and_then(| v | v.trim().parse::<u64>().ok());
+
+    let millis: u64 = match requested {
+        Some(millis) if millis < config.request_deadline_min || millis > config.request_deadline_max => {
+            let clamped: u64 = millis.clamp(config.request_deadline_min, config.request_deadline_max.max(config.request_deadline_min));
+            warnings::warn(format!("requested deadline of {} ms is out of bounds, using {} ms", millis, clamped));
+            clamped
+        },
+        Some(millis) => millis,
+        None => config.request_deadline
+    };
+
+    Duration::from_millis(millis)
+}
+
+/// Middleware answering requests that exceed their deadline with a 504 error.
+pub async fn enforce(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let deadline: Duration = deadline_of(&req);
+    let request: HttpRequest = req.request().clone();
+    let request_id: String = req.extensions().get::<RequestId>().map(| id | id.0.clone()).unwrap_or_default();
+    let operations: Rc<RefCell<Vec<TrackedOperation>>> = Rc::new(RefCell::new(Vec::new()));
+
+    match actix_web::rt::time::timeout(deadline, OPERATIONS.scope(operations.clone(), next.call(req))).await {
+        Ok(res) => Ok(res?.map_into_left_body()),
+        Err(_) => {
+            let operations: Vec<TrackedOperation> = operations.take();
+            log_warn!(
+                "Request {} {} exceeded its deadline of {} ms with {} operations in flight",
+                request.method(), request.path(), deadline.as_millis(), operations.len()
+            );
+
+            if let Some(pool) = request.app_data::<Data<PgPool>>() {
+                for operation in &operations {
+                    if let Err(err) = db::outbox::mark_unknown(pool, operation.outbox_id).await {
+                        log_error!("Can not mark outbox entry {} as unknown: {:?}", operation.outbox_id, err);
+                    }
+                }
+            }
+
+            let response: Response = Response::error(json!({
+                "error": format!("deadline of {} ms exceeded, the outcome of the operations is unknown", deadline.as_millis()),
+                "operations": operations
+            })).with_request_id(&request_id);
+
+            Ok(ServiceResponse::new(request, HttpResponse::GatewayTimeout().json(response)).map_into_right_body())
+        }
+    }
+}
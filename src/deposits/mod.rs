@@ -0,0 +1,168 @@
+//! # Deposit Watcher
+//!
+//! This module matches incoming transfers to the mixer contract against open payment
+//! requests by their text comment, and implements a background task that expires the
+//! requests nobody paid in time. Transfers are fed in by the indexer as it walks the
+//! contract transactions, so each one is matched exactly once. A paid request with a
+//! mixing plan schedules its spread as a background job, no manual call is needed.
+//! A transfer reaching an expired request settles it as `late` and notifies the operators,
+//! its funds stay on the contract for a refund or a review.
+
+use std::time::Duration;
+
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use tonlib::tl::RawTransaction;
+
+use crate::{config, db, notify::{Notification, Notifier}, strategy::{self, MixLeg, MixStrategy}, ton, types::{deposit::{Deposit, MixPlan, DEPOSIT_EXPIRED, DEPOSIT_LATE, DEPOSIT_PAID, DEPOSIT_UNDERPAID}, jobs::{Job, SpreadJob, JOB_PRIORITY_NORMAL, JOB_SPREAD}, nanotons::Nanotons, SpreadWalletPayload}};
+
+/// Interval between expiry passes in seconds, used when `DEPOSIT_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 60;
+
+/// Runs the expiry loop forever.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+pub async fn run(pool: PgPool) {
+    let interval: u64 = config::env_or("DEPOSIT_INTERVAL", DEFAULT_INTERVAL);
+
+    log_info!("Deposit watcher is running every {:?} seconds", interval);
+
+    loop {
+        match db::deposits::expire(&pool).await {
+            Ok(0) => {},
+            Ok(expired) => log_info!("Deposit watcher expired {} payment requests", expired),
+            Err(err) => log_error!("Deposit watcher can not expire payment requests: {:?}", err)
+        }
+
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Matches an incoming transfer to a contract against its open payment requests.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The contract the transaction belongs to.
+/// * `transaction` - The transaction of the incoming transfer.
+///
+/// # Returns
+///
+/// The payment request the transfer settled, if any.
+pub async fn match_transfer(pool: &PgPool, contract: &str, transaction: &RawTransaction) -> Result<Option<Deposit>, String> {
+    let message = match &transaction.in_msg {
+        Some(m) => m,
+        None => return Ok(None)
+    };
+
+    let comment: String = match ton::message_body(message).ok().flatten().and_then(| b | ton::text_comment(&b)) {
+        Some(c) => c,
+        None => return Ok(None)
+    };
+
+    let deposit: Deposit = match db::deposits::unsettled_by_comment(pool, contract, comment.trim()).await.map_err(|e| e.to_string())? {
+        Some(d) => d,
+        None => return Ok(None)
+    };
+
+    let status: &str = match (deposit.status.as_str(), message.value >= deposit.amount) {
+        (DEPOSIT_EXPIRED, _) => DEPOSIT_LATE,
+        (_, true) => DEPOSIT_PAID,
+        (_, false) => DEPOSIT_UNDERPAID
+    };
+    let sender: Option<&str> = Some(message.source.account_address.as_str()).filter(| s | !s.is_empty());
+
+    // the request is settled and its spread scheduled together, or neither is
+    let mut tx: Transaction<'_, Postgres> = pool.begin().await.map_err(|e| e.to_string())?;
+    let settled: bool = db::deposits::settle(
+        &mut *tx,
+        deposit.id,
+        &deposit.status,
+        status,
+        message.value,
+        sender,
+        &hex::encode(transaction.transaction_id.hash),
+        transaction.transaction_id.lt
+    ).await.map_err(|e| e.to_string())?;
+
+    // the spread is scheduled by whoever settled the request
+    if !settled {
+        return Ok(None);
+    }
+
+    if status == DEPOSIT_PAID {
+        if let Some(plan) = &deposit.plan {
+            let plan: MixPlan = serde_json::from_value(plan.clone()).map_err(|e| e.to_string())?;
+            schedule_spread(pool, &mut tx, &deposit, &plan, message.value as u64).await?;
+        }
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+    log_info!("Deposit watcher matched payment request {} as {}", deposit.id, status);
+
+    if status == DEPOSIT_LATE {
+        let notification: Notification = Notification::new(
+            "late_deposit",
+            format!(
+                "Payment request {} on {} was paid {} after it expired, review or refund it",
+                deposit.id, contract, Nanotons::from_signed(message.value)
+            ),
+            json!({
+                "deposit_id": deposit.id,
+                "contract": contract,
+                "received": message.value,
+                "sender": sender,
+                "tx_hash": hex::encode(transaction.transaction_id.hash)
+            })
+        );
+        log_warn!("{}", notification.message);
+        Notifier::from_env(pool.clone()).send(&notification).await;
+    }
+
+    Ok(Some(deposit))
+}
+
+/// Schedules the spread of a paid deposit as one job per leg of its mixing strategy, within
+/// the transaction settling the deposit.
+///
+/// Legs may be routed through the deposit contract or any of its forks.
+async fn schedule_spread(pool: &PgPool, tx: &mut Transaction<'_, Postgres>, deposit: &Deposit, plan: &MixPlan, received: u64) -> Result<(), String> {
+    let mut contracts: Vec<String> = vec![deposit.contract.clone()];
+    contracts.extend(db::contracts::children(pool, &deposit.contract).await.map_err(|e| e.to_string())?);
+
+    let strategy: Box<dyn MixStrategy> = strategy::for_plan(plan);
+    let legs: Vec<MixLeg> = strategy.legs(plan, &contracts, received);
+    let now: u64 = ton::time_now();
+
+    for leg in legs {
+        let wallets: Vec<SpreadWalletPayload> = plan.recipients.iter().zip(leg.amounts.iter())
+            .filter(| (_, nano) | **nano > 0)
+            .map(| (recipient, nano) | SpreadWalletPayload {
+                account: recipient.account.clone(),
+                amount: Some(Nanotons::from(*nano).to_ton()),
+                amount_usd: None,
+                percent: None,
+                bounce: recipient.bounce,
+                send_mode: None
+            })
+            .collect();
+
+        if wallets.is_empty() {
+            continue;
+        }
+
+        let payload: SpreadJob = SpreadJob { contract: leg.contract, wallets, total: None };
+        let job: Job = db::jobs::insert(&mut **tx, JOB_SPREAD, &serde_json::to_value(&payload).unwrap(), (now + leg.delay) as i64, JOB_PRIORITY_NORMAL, Some(deposit.id))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        log_info!(
+            "Deposit watcher scheduled job {} for payment request {} with the {} strategy",
+            job.id, deposit.id, strategy.name()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! # Block Explorer Links
+//!
+//! This module builds tonviewer and tonscan URLs for the network selected with `TON_NETWORK`,
+//! so support staff can jump from an API response straight to the chain.
+
+use crate::{config::Network, types::{events::MixerEvent, explorer::{ExplorerLinks, OperationLinks, OutboxLinks, TransactionLinks}, outbox::OutboxEntry}};
+
+/// Returns the base URLs of tonviewer and tonscan for the configured network.
+fn bases() -> (&'static str, &'static str) {
+    match Network::from_env() {
+        Network::Mainnet => ("https://tonviewer.com", "https://tonscan.org"),
+        Network::Testnet => ("https://testnet.tonviewer.com", "https://testnet.tonscan.org")
+    }
+}
+
+/// Returns the explorer pages of a transaction or message hash.
+pub fn transaction(hash: &str) -> ExplorerLinks {
+    let (tonviewer, tonscan) = bases();
+
+    ExplorerLinks {
+        tonviewer: format!("{}/transaction/{}", tonviewer, hash),
+        tonscan: format!("{}/tx/{}", tonscan, hash)
+    }
+}
+
+/// Returns the explorer pages of an address.
+pub fn address(address: &str) -> ExplorerLinks {
+    let (tonviewer, tonscan) = bases();
+
+    ExplorerLinks {
+        tonviewer: format!("{}/{}", tonviewer, address),
+        tonscan: format!("{}/address/{}", tonscan, address)
+    }
+}
+
+/// Returns the explorer links of a sent operation.
+///
+/// # Arguments
+///
+/// * `normalized_hash` - The normalized hash of the external message in hex.
+/// * `wallet` - The wallet that sent the message.
+/// * `contract` - The contract the message targets.
+pub fn operation(normalized_hash: &str, wallet: &str, contract: &str) -> OperationLinks {
+    OperationLinks {
+        message: transaction(normalized_hash),
+        wallet: address(wallet),
+        contract: address(contract)
+    }
+}
+
+/// Attaches explorer links to an indexed transaction.
+pub fn link_event(mut event: MixerEvent) -> MixerEvent {
+    event.links = Some(TransactionLinks {
+        transaction: transaction(&event.hash),
+        contract: address(&event.contract)
+    });
+    event
+}
+
+/// Attaches explorer links to an outbox entry.
+///
+/// The message link is only set once the normalized hash is known.
+pub fn link_outbox_entry(mut entry: OutboxEntry) -> OutboxEntry {
+    entry.links = Some(OutboxLinks {
+        message: entry.normalized_hash.as_deref().map(transaction),
+        wallet: address(&entry.wallet)
+    });
+    entry
+}
@@ -0,0 +1,175 @@
+//! # On-chain Indexer
+//!
+//! This module implements a background task that walks the transactions of the mixer contract
+//! and its forks, decodes spread/collect/fork operations by opcode and stores them in the database,
+//! so the history reflects what actually happened on-chain rather than only what this API submitted.
+
+use std::{str::FromStr, time::Duration};
+
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tonlib::{address::TonAddress, tl::{MsgData, RawTransaction}};
+
+use crate::{bus, config, db, deposits, leader, ton, types::{decode::{self, DecodedMessage}, events::{MixerContract, MixerEvent}}};
+
+/// Interval between indexing passes in seconds, used when `INDEXER_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 30;
+
+/// Runs the indexer loop forever.
+///
+/// The mixer contract from the environment is registered on start, forks are
+/// registered as they are discovered in the fork transactions of their parents.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+pub async fn run(pool: PgPool) {
+    let interval: u64 = config::env_or("INDEXER_INTERVAL", DEFAULT_INTERVAL);
+
+    let root: String = ton::mixer_contract_address().to_base64_url();
+    if let Err(err) = db::contracts::register(&pool, &root, None, ton::time_now() as i64).await {
+        log_error!("Indexer can not register the mixer contract: {:?}", err);
+    }
+
+    log_info!("Indexer is running every {:?} seconds", interval);
+
+    loop {
+        // one instance indexes, so a transfer settles its payment request and publishes its events once
+        if leader::is_leader() {
+            if let Err(err) = index(&pool).await {
+                log_error!("Indexer pass failed: {}", err);
+            }
+        }
+
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Performs a single indexing pass over all tracked contracts.
+///
+/// Also used by the policies to register forks before deciding on new operations.
+pub async fn index(pool: &PgPool) -> Result<(), String> {
+    let contracts: Vec<MixerContract> = db::contracts::list(pool).await.map_err(|e| e.to_string())?;
+
+    for contract in contracts {
+        index_contract(pool, &contract).await?;
+    }
+
+    Ok(())
+}
+
+/// Indexes the transactions of one contract made since the last pass.
+async fn index_contract(pool: &PgPool, contract: &MixerContract) -> Result<(), String> {
+    let address: TonAddress = TonAddress::from_str(&contract.address).map_err(|e| e.to_string())?;
+    let transactions: Vec<RawTransaction> = ton::get_transactions_since(&address, contract.last_lt).await?;
+
+    for transaction in transactions.iter() {
+        let event: MixerEvent = to_event(&contract.address, transaction);
+        db::events::insert(pool, &event).await.map_err(|e| e.to_string())?;
+
+        if event.op == "malformed" {
+            log_warn!("Indexer can not decode the message of transaction {} of {}: {}", event.hash, contract.address,
+                event.body.as_ref().and_then(| b | b.get("error")).and_then(| e | e.as_str()).unwrap_or("unknown error"));
+        }
+
+        if event.op == "fork" {
+            for child in forked_contracts(transaction) {
+                db::contracts::register(pool, &child, Some(&contract.address), transaction.utime)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                log_info!("Indexer discovered fork {} of {}", child, contract.address);
+            }
+        }
+
+        if event.op == "transfer" {
+            deposits::match_transfer(pool, &contract.address, transaction).await?;
+        }
+
+        if event.op == "collect" {
+            bus::publish(bus::EVENT_COLLECTED, bus_event(&event));
+        } else if is_bounce(transaction) {
+            bus::publish(bus::EVENT_BOUNCED, bus_event(&event));
+        }
+
+        db::contracts::set_last_lt(pool, &contract.address, transaction.transaction_id.lt)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Converts a raw transaction into an event, decoding its in-message by opcode.
+///
+/// Transactions without a mixer message body (plain deposits, comments) are
+/// recorded as `transfer` events. A body with a mixer opcode that does not decode is
+/// recorded as a `malformed` event with the error as its body, rather than as a transfer.
+pub fn to_event(contract: &str, transaction: &RawTransaction) -> MixerEvent {
+    let parsed: Option<Result<DecodedMessage, String>> = transaction.in_msg.as_ref()
+        .and_then(| m | ton::message_body(m).ok().flatten())
+        .filter(| body | decode::read_opcode(body).is_ok())
+        .map(| body | decode::decode(&body));
+
+    let (decoded, op, error): (Option<DecodedMessage>, &str, Option<Value>) = match parsed {
+        Some(Ok(d)) => {
+            let op: &str = d.op_name();
+            (Some(d), op, None)
+        },
+        Some(Err(err)) => (None, "malformed", Some(json!({ "error": err }))),
+        None => (None, "transfer", None)
+    };
+
+    MixerEvent {
+        contract: contract.to_string(),
+        lt: transaction.transaction_id.lt,
+        hash: hex::encode(transaction.transaction_id.hash),
+        utime: transaction.utime,
+        op: op.to_string(),
+        query_id: decoded.as_ref().and_then(| d | d.query_id()).map(| q | q as i64),
+        mode: decoded.as_ref().and_then(| d | d.mode()).map(| m | m as i16),
+        source: transaction.in_msg.as_ref()
+            .map(| m | m.source.account_address.clone())
+            .filter(| s | !s.is_empty()),
+        value_in: transaction.in_msg.as_ref().map(| m | m.value).unwrap_or(0),
+        value_out: transaction.out_msgs.iter().map(| m | m.value).sum(),
+        total_fees: transaction.fee,
+        fwd_fees: transaction.out_msgs.iter().map(| m | m.fwd_fee).sum(),
+        body: decoded.as_ref().and_then(| d | serde_json::to_value(d).ok()).or(error),
+        links: None
+    }
+}
+
+/// Returns whether the in-message of a transaction is a bounce, i.e. its body starts
+/// with the `0xffffffff` tag of bounced messages.
+fn is_bounce(transaction: &RawTransaction) -> bool {
+    transaction.in_msg.as_ref()
+        .and_then(| m | ton::message_body(m).ok().flatten())
+        .and_then(| body | body.parser().load_u32(32).ok())
+        .is_some_and(| tag | tag == 0xffffffff)
+}
+
+/// Returns the bus event data of an indexed transaction.
+fn bus_event(event: &MixerEvent) -> Value {
+    json!({
+        "contract": event.contract,
+        "lt": event.lt,
+        "hash": event.hash,
+        "utime": event.utime,
+        "query_id": event.query_id,
+        "mode": event.mode,
+        "source": event.source,
+        "value_in": event.value_in,
+        "value_out": event.value_out
+    })
+}
+
+/// Returns the addresses of contracts deployed by a fork transaction.
+///
+/// A child contract is recognised by an outgoing message carrying a state init.
+fn forked_contracts(transaction: &RawTransaction) -> Vec<String> {
+    transaction.out_msgs.iter()
+        .filter(| m | matches!(&m.msg_data, MsgData::Raw { init_state, .. } if !init_state.is_empty()))
+        .filter_map(| m | TonAddress::from_str(&m.destination.account_address).ok())
+        .map(| a | a.to_base64_url())
+        .collect()
+}
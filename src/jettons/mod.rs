@@ -0,0 +1,203 @@
+//! # Jettons
+//!
+//! This module resolves the jetton wallets and balances of an account for the jettons
+//! configured with `JETTON_MASTERS`, including the decimals from the jetton metadata.
+//! Decimals are only taken from off-chain metadata that sets them: amounts scaled by
+//! guessed decimals would send the wrong number of jettons, so a jetton without them is
+//! an error rather than 9 decimals.
+
+use std::{collections::HashMap, str::FromStr, sync::{LazyLock, Mutex, OnceLock}, time::Duration};
+
+use num_bigint::{BigInt, BigUint};
+use serde_json::Value;
+use tonlib::{address::TonAddress, cell::Cell};
+
+use crate::{config, ton, types::jettons::{JettonBalance, JettonMetadata}};
+
+/// Time fetching off-chain metadata may take.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Metadata resolved so far, by jetton master address.
+static METADATA: Mutex<Option<HashMap<String, JettonMetadata>>> = Mutex::new(None);
+
+/// Client off-chain metadata is fetched with.
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder().timeout(METADATA_TIMEOUT).build().unwrap_or_default()
+});
+
+/// The jetton masters, read once on startup.
+static MASTERS: OnceLock<Vec<TonAddress>> = OnceLock::new();
+
+/// Returns the jetton masters configured with `JETTON_MASTERS` (comma separated).
+///
+/// The masters are read on the first call, which `main` makes on startup.
+///
+/// # Panics
+///
+/// Panics on the first call if an address is invalid.
+pub fn configured_masters() -> Vec<TonAddress> {
+    MASTERS.get_or_init(|| {
+        config::var("JETTON_MASTERS").unwrap_or_default()
+            .split(',')
+            .map(| a | a.trim())
+            .filter(| a | !a.is_empty())
+            .map(| a | match TonAddress::from_str(a) {
+                Ok(address) => address,
+                Err(_) => panic!("[ FATAL ] Configuration Error: `JETTON_MASTERS` has an invalid address `{}`", a)
+            })
+            .collect()
+    }).clone()
+}
+
+/// Reads the URI of off-chain metadata from a content cell.
+///
+/// # Returns
+///
+/// The URI, or `None` for on-chain metadata.
+fn offchain_uri(content: &Cell) -> Result<Option<String>, String> {
+    let mut parser = content.parser();
+
+    if parser.load_u8(8).map_err(|e| e.to_string())? != 0x01 {
+        return Ok(None);
+    }
+
+    // the URI is stored in snake format: the bytes continue in the first reference of each cell
+    let mut bytes: Vec<u8> = parser.load_bytes(parser.remaining_bits() / 8).map_err(|e| e.to_string())?;
+    let mut next: Option<Cell> = content.references().first().map(| c | c.as_ref().clone());
+
+    while let Some(cell) = next {
+        let mut parser = cell.parser();
+        bytes.extend(parser.load_bytes(parser.remaining_bits() / 8).map_err(|e| e.to_string())?);
+        next = cell.references().first().map(| c | c.as_ref().clone());
+    }
+
+    String::from_utf8(bytes).map(Some).map_err(|e| e.to_string())
+}
+
+/// Resolves the symbol and decimals of a jetton from its off-chain metadata.
+///
+/// # Returns
+///
+/// The metadata, or an error if it is on-chain, can not be fetched within
+/// `METADATA_TIMEOUT`, or does not set valid `decimals`.
+async fn metadata(master: &TonAddress) -> Result<JettonMetadata, String> {
+    let key: String = master.to_base64_url();
+
+    if let Some(cached) = METADATA.lock().unwrap().as_ref().and_then(| m | m.get(&key)) {
+        return Ok(cached.clone());
+    }
+
+    let content: Cell = ton::get_jetton_content(master).await?;
+    let uri: String = offchain_uri(&content)?
+        .ok_or_else(|| format!("jetton {} has on-chain metadata, its decimals can not be read", key))?;
+    let url: String = match uri.strip_prefix("ipfs://") {
+        Some(path) => format!("https://ipfs.io/ipfs/{}", path),
+        None => uri
+    };
+
+    let json: Value = HTTP.get(&url).send().await
+        .and_then(| r | r.error_for_status())
+        .map_err(| e | format!("can not fetch the metadata of jetton {}: {}", key, e))?
+        .json::<Value>()
+        .await
+        .map_err(| e | format!("metadata of jetton {} is not JSON: {}", key, e))?;
+
+    let decimals: Option<u32> = match &json["decimals"] {
+        Value::String(d) => d.parse::<u32>().ok(),
+        Value::Number(d) => d.as_u64().and_then(| d | u32::try_from(d).ok()),
+        _ => None
+    };
+    let metadata: JettonMetadata = JettonMetadata {
+        symbol: json["symbol"].as_str().map(String::from),
+        decimals: decimals.filter(| d | *d <= 255).ok_or_else(|| format!("metadata of jetton {} sets no valid `decimals`", key))?
+    };
+
+    METADATA.lock().unwrap().get_or_insert_with(HashMap::new).insert(key, metadata.clone());
+    Ok(metadata)
+}
+
+/// Formats an amount in the smallest units as a decimal number.
+pub fn format_amount(balance: &BigInt, decimals: u32) -> String {
+    let digits: String = balance.to_string();
+
+    if decimals == 0 {
+        return digits;
+    }
+
+    let padded: String = format!("{:0>width$}", digits, width = decimals as usize + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - decimals as usize);
+    let fraction: &str = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// Converts an amount in whole jettons to the smallest units of a jetton.
+///
+/// # Arguments
+///
+/// * `master` - The jetton master.
+/// * `amount` - The amount, scaled by the decimals of the jetton.
+///
+/// # Returns
+///
+/// The amount in the smallest units, or an error if it has more fractional digits than the jetton.
+pub async fn to_units(master: &TonAddress, amount: f64) -> Result<BigUint, String> {
+    let decimals: u32 = metadata(master).await?.decimals;
+    let text: String = amount.to_string();
+    let (whole, fraction) = text.split_once('.').unwrap_or((text.as_str(), ""));
+
+    if fraction.len() > decimals as usize {
+        return Err(format!("amount {} has more than {} decimals of jetton {}", text, decimals, master.to_base64_url()));
+    }
+
+    let digits: String = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+    BigUint::from_str(&digits).map_err(|e| e.to_string())
+}
+
+/// Fetches the balances of the configured jettons held by an account.
+///
+/// # Arguments
+///
+/// * `owner` - The account whose jetton wallets are resolved.
+///
+/// # Returns
+///
+/// One balance per configured jetton master, including empty and undeployed wallets.
+pub async fn balances(owner: &TonAddress) -> Result<Vec<JettonBalance>, String> {
+    balances_of(owner, &configured_masters()).await
+}
+
+/// Fetches the balances of the given jettons held by an account.
+///
+/// # Arguments
+///
+/// * `owner` - The account whose jetton wallets are resolved.
+/// * `masters` - The jetton masters.
+///
+/// # Returns
+///
+/// One balance per jetton master, in the order of `masters`.
+pub async fn balances_of(owner: &TonAddress, masters: &[TonAddress]) -> Result<Vec<JettonBalance>, String> {
+    let mut balances: Vec<JettonBalance> = Vec::new();
+
+    for master in masters {
+        let metadata: JettonMetadata = metadata(master).await?;
+        let wallet: TonAddress = ton::get_jetton_wallet_address(master, owner).await?;
+        let balance: BigInt = ton::get_jetton_balance(&wallet).await?;
+
+        balances.push(JettonBalance {
+            master: master.to_base64_url(),
+            wallet: wallet.to_base64_url(),
+            symbol: metadata.symbol,
+            decimals: metadata.decimals,
+            balance: balance.to_string(),
+            amount: format_amount(&balance, metadata.decimals)
+        });
+    }
+
+    Ok(balances)
+}
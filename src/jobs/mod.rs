@@ -0,0 +1,616 @@
+//! # Background Jobs
+//!
+//! This module implements the runner of the jobs queue: operations scheduled for later,
+//! such as the spread of a paid deposit, are stored in the `jobs` table and executed by a
+//! background task once their `run_at` time has come. Jobs are claimed with
+//! `FOR UPDATE SKIP LOCKED`, so several instances can share one queue. Every pass also
+//! refreshes the queue metrics, so a growing backlog is visible in `GET /metrics`.
+//!
+//! A job can defer itself: a time-locked collect whose contract received funds after it was
+//! scheduled goes back into the queue until those funds spent their dwell time too, failing
+//! instead of sweeping young funds once deposits kept it waiting for `JOB_DWELL_MAX_WAIT`, and a
+//! spread breaching the spread limits or the daily withdrawal limit is held until the next
+//! allowed hour or for `JOB_LIMIT_RETRY` seconds, with the breach as its error, instead of
+//! failing and stranding the funds of its deposit. An operator may approve a held spread with a
+//! signed request, which then runs right away without the spread limits. Jobs that
+//! take several steps, like a rebalance, store their progress with the job after each one, and a
+//! rebalance claimed again resumes from it.
+//!
+//! A claimed job holds a lease of `JOB_LEASE` seconds, extended whenever it reports progress,
+//! and a runner that stopped mid-job leaves it to be claimed again once the lease ran out. The
+//! outbox entries a job writes are linked to it: a failed job is retried with an exponential
+//! backoff up to `JOB_MAX_ATTEMPTS` times, and a reclaimed one runs again, only if none of its
+//! messages may have been applied. Otherwise it fails for an operator to review its messages.
+
+use std::{str::FromStr, time::Duration};
+
+use num_bigint::BigUint;
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use tonlib::address::TonAddress;
+
+use crate::{config, db, leader, metrics, multisig, policy::limits, services::mixer, ton, types::{jobs::{CollectBatchJob, CollectJob, ForkJob, Job, SnapshotJob, SpreadJob, JOB_COLLECT, JOB_COLLECT_BATCH, JOB_DEFERRED, JOB_DONE, JOB_FAILED, JOB_FORK, JOB_REBALANCE, JOB_SNAPSHOT, JOB_SPREAD}, nanotons::Nanotons, rebalance::{RebalanceJob, RebalanceProgress, PHASE_COLLECT, PHASE_DONE, PHASE_SETTLE, PHASE_SPREAD}, reports::{window_seconds, MixerStats, OperationStats}, CollectBatchReceipt, CollectBatchResult, CollectMessageData, CollectPayload, MixerCollectionModes, OperationReceipt, SpreadWallet, DEFAULT_SEND_MODE, MAX_BATCH_COLLECT}};
+
+/// Interval between runner passes in seconds, used when `JOB_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 5;
+
+/// Seconds a due job waits before its priority is raised by one level, used when
+/// `JOB_PRIORITY_AGING` is not set.
+const DEFAULT_PRIORITY_AGING: i64 = 300;
+
+/// Maximum number of jobs claimed per pass.
+const BATCH_SIZE: i64 = 16;
+
+/// Seconds a claimed job is leased to its runner, used when `JOB_LEASE` is not set.
+const DEFAULT_LEASE: i64 = 600;
+
+/// Attempts of a job before it fails for good, used when `JOB_MAX_ATTEMPTS` is not set.
+const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+
+/// Seconds before the first retry of a failed job, doubled with every further attempt,
+/// used when `JOB_RETRY_BACKOFF` is not set.
+const DEFAULT_RETRY_BACKOFF: u64 = 30;
+
+/// Seconds a spread breaching a limit is held before it is tried again, used when `JOB_LIMIT_RETRY` is not set.
+const DEFAULT_LIMIT_RETRY: u64 = 900;
+
+/// Seconds a time-locked collect waits past its dwell time for deposits to stop arriving, used
+/// when `JOB_DWELL_MAX_WAIT` is not set.
+const DEFAULT_DWELL_MAX_WAIT: u64 = 7 * 24 * 3600;
+
+/// Longest wait before a retry in seconds.
+const MAX_RETRY_BACKOFF: u64 = 3600;
+
+tokio::task_local! {
+    /// The id of the job executed by the current task.
+    static JOB_ID: i64;
+}
+
+/// Returns the id of the job executed by the current task, `None` outside the job runner.
+pub fn current() -> Option<i64> {
+    JOB_ID.try_with(| id | *id).ok()
+}
+
+/// Returns the lease of a claimed job in seconds, see `JOB_LEASE`.
+fn lease() -> i64 {
+    config::env_or("JOB_LEASE", DEFAULT_LEASE)
+}
+
+/// Seconds a rebalance waits for the indexer to record its collects.
+const SETTLE_TIMEOUT: u64 = 180;
+
+/// Interval between checks of the indexed collects while a rebalance settles.
+const SETTLE_POLL: Duration = Duration::from_secs(5);
+
+/// Runs the job runner loop forever.
+///
+/// Due jobs are left in the queue while the mixer is paused or another instance is the leader.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+pub async fn run(pool: PgPool) {
+    let interval: u64 = config::env_or("JOB_INTERVAL", DEFAULT_INTERVAL);
+    let aging: i64 = config::env_or("JOB_PRIORITY_AGING", DEFAULT_PRIORITY_AGING);
+
+    log_info!("Job runner is running every {:?} seconds", interval);
+
+    loop {
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+
+        match db::jobs::queue_stats(&pool).await {
+            Ok(stats) => metrics::set_job_queue(stats),
+            Err(err) => log_error!("Job runner can not read the queue state: {:?}", err)
+        }
+
+        if !leader::is_leader() || mixer::is_paused(&pool).await {
+            continue;
+        }
+
+        let jobs: Vec<Job> = match db::jobs::claim_due(&pool, BATCH_SIZE, aging, lease()).await {
+            Ok(jobs) => jobs,
+            Err(err) => {
+                log_error!("Job runner can not claim jobs: {:?}", err);
+                continue;
+            }
+        };
+
+        for job in jobs {
+            process(&pool, job).await;
+        }
+    }
+}
+
+/// Schedules a job.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `kind` - The kind of the job, e.g. `JOB_SPREAD`.
+/// * `payload` - The payload of the job.
+/// * `run_at` - Unix time the job becomes due.
+/// * `priority` - The priority of the job, e.g. `JOB_PRIORITY_NORMAL`.
+/// * `deposit_id` - The deposit the job belongs to, if any.
+///
+/// # Returns
+///
+/// The scheduled job.
+pub async fn schedule(pool: &PgPool, kind: &str, payload: &Value, run_at: u64, priority: i32, deposit_id: Option<i64>) -> Result<Job, String> {
+    db::jobs::insert(pool, kind, payload, run_at as i64, priority, deposit_id).await.map_err(|e| e.to_string())
+}
+
+/// Represents the outcome of an executed job.
+enum Executed {
+    /// The operation was sent, with its receipt.
+    Done(String),
+    /// The job is not due yet and runs again at the unix time.
+    Deferred(u64),
+    /// The job breached a limit and runs again at the unix time, with the breach.
+    Held(u64, String)
+}
+
+/// Returns the outbox entries of a job that may have been applied, failing if they can not be read.
+async fn sent_by(pool: &PgPool, id: i64) -> Result<Vec<i64>, String> {
+    db::outbox::sent_by_job(pool, id, None).await.map_err(|e| format!("can not read the outbox entries of the job: {}", e))
+}
+
+/// Returns whether a job of the kind resumes from its stored progress instead of starting over,
+/// so it guards against sending twice itself and may run again after writing outbox entries.
+fn resumable(kind: &str) -> bool {
+    kind == JOB_REBALANCE
+}
+
+/// Executes a claimed job and records its outcome.
+///
+/// The job runs in its own task, so a panic while sending fails the job instead of the runner.
+/// A job claimed again after a failed attempt or an expired lease only runs if none of the
+/// messages it wrote before may have been applied, unless it is `resumable`.
+async fn process(pool: &PgPool, job: Job) {
+    let id: i64 = job.id;
+    let kind: String = job.kind.clone();
+    let attempts: i32 = job.attempts;
+
+    let sent: Result<Vec<i64>, String> = match attempts > 1 && !resumable(&kind) {
+        true => sent_by(pool, id).await,
+        false => Ok(Vec::new())
+    };
+
+    let outcome: Result<Executed, String> = match sent {
+        Err(err) => Err(err),
+        Ok(sent) if !sent.is_empty() => Err(format!(
+            "the job was interrupted after writing outbox entries {:?}, review them instead of running it again", sent
+        )),
+        Ok(_) => match actix_web::rt::spawn(JOB_ID.scope(id, execute(pool.clone(), job))).await {
+            Ok(outcome) => outcome,
+            Err(err) => Err(format!("job panicked: {}", err))
+        }
+    };
+
+    let finished = match &outcome {
+        Ok(Executed::Deferred(run_at)) => {
+            log_info!("Job {} ({}) is deferred to {}", id, kind, run_at);
+            metrics::observe_job(&kind, JOB_DEFERRED);
+            db::jobs::defer(pool, id, *run_at as i64, None).await
+        },
+        Ok(Executed::Held(run_at, reason)) => {
+            log_warn!("Job {} ({}) is held until {}: {}", id, kind, run_at, reason);
+            metrics::observe_job(&kind, JOB_DEFERRED);
+            db::jobs::defer(pool, id, *run_at as i64, Some(reason.as_str())).await
+        },
+        Ok(Executed::Done(result)) => {
+            log_info!("Job {} ({}) is done", id, kind);
+            metrics::observe_job(&kind, JOB_DONE);
+            db::jobs::finish(pool, id, JOB_DONE, Some(result), None).await
+        },
+        Err(err) => match retry_at(pool, id, &kind, attempts).await {
+            Some(run_at) => {
+                log_warn!("Job {} ({}) failed attempt {}, retrying at {}: {}", id, kind, attempts, run_at, err);
+                db::jobs::retry(pool, id, run_at as i64, err).await
+            },
+            None => {
+                log_error!("Job {} ({}) failed: {}", id, kind, err);
+                metrics::observe_job(&kind, JOB_FAILED);
+                db::jobs::finish(pool, id, JOB_FAILED, None, Some(err)).await
+            }
+        }
+    };
+
+    if let Err(err) = finished {
+        log_error!("Can not record the outcome of job {}: {:?}", id, err);
+    }
+}
+
+/// Returns when a failed job is tried again, `None` if it used up `JOB_MAX_ATTEMPTS` or a
+/// message it wrote may have been applied, so running it again could send twice.
+async fn retry_at(pool: &PgPool, id: i64, kind: &str, attempts: i32) -> Option<u64> {
+    if attempts >= config::env_or("JOB_MAX_ATTEMPTS", DEFAULT_MAX_ATTEMPTS) {
+        return None;
+    }
+
+    match sent_by(pool, id).await {
+        Ok(_) if resumable(kind) => {},
+        Ok(sent) if sent.is_empty() => {},
+        Ok(_) => return None,
+        Err(err) => {
+            log_error!("Job {} is not retried: {}", id, err);
+            return None;
+        }
+    }
+
+    let backoff: u64 = config::env_or("JOB_RETRY_BACKOFF", DEFAULT_RETRY_BACKOFF)
+        .saturating_mul(1 << (attempts - 1).clamp(0, 16))
+        .min(MAX_RETRY_BACKOFF);
+
+    Some(ton::time_now() + backoff)
+}
+
+/// Dispatches a job by its kind.
+///
+/// # Returns
+///
+/// The receipt of the sent operation, or the time a deferred job runs again.
+async fn execute(pool: PgPool, job: Job) -> Result<Executed, String> {
+    match job.kind.as_str() {
+        JOB_SPREAD => {
+            let payload: SpreadJob = serde_json::from_value(job.payload).map_err(|e| e.to_string())?;
+            spread(&pool, payload, job.approved_at.is_some()).await
+        },
+        JOB_COLLECT => {
+            let payload: CollectJob = serde_json::from_value(job.payload).map_err(|e| e.to_string())?;
+            collect(&pool, payload, job.created_at).await
+        },
+        JOB_COLLECT_BATCH => {
+            let payload: CollectBatchJob = serde_json::from_value(job.payload).map_err(|e| e.to_string())?;
+            collect_batch(&pool, job.id, payload).await.map(Executed::Done)
+        },
+        JOB_REBALANCE => {
+            let payload: RebalanceJob = serde_json::from_value(job.payload).map_err(|e| e.to_string())?;
+            rebalance(&pool, job.id, payload, job.progress).await.map(Executed::Done)
+        },
+        JOB_FORK => {
+            let payload: ForkJob = serde_json::from_value(job.payload).map_err(|e| e.to_string())?;
+            fork(&pool, payload).await.map(Executed::Done)
+        },
+        JOB_SNAPSHOT => {
+            let payload: SnapshotJob = serde_json::from_value(job.payload).map_err(|e| e.to_string())?;
+            snapshot(&pool, payload).await.map(Executed::Done)
+        },
+        kind => Err(format!("unknown job kind `{}`", kind))
+    }
+}
+
+/// Fails for an address of a job payload still given as `@name`.
+///
+/// Jobs carry the addresses their names resolved to when they were queued, so editing an
+/// address book entry never redirects funds that are already on their way.
+fn ensure_resolved<'a>(accounts: impl IntoIterator<Item = &'a str>) -> Result<(), String> {
+    match accounts.into_iter().find(| a | a.starts_with('@')) {
+        Some(name) => Err(format!("`{}` was not resolved to an address when the job was queued", name)),
+        None => Ok(())
+    }
+}
+
+/// Spreads funds through the contract of a spread job, holding it while it breaches a limit.
+///
+/// A job an operator approved skips the spread limits, the daily withdrawal limit still
+/// holds it until an override is granted.
+async fn spread(pool: &PgPool, payload: SpreadJob, approved: bool) -> Result<Executed, String> {
+    let contract: TonAddress = TonAddress::from_str(&payload.contract).map_err(|e| e.to_string())?;
+    ensure_resolved(payload.wallets.iter().map(| v | v.account.as_str()))?;
+    ton::ensure_code(&contract).await?;
+    let base: Option<Nanotons> = mixer::percent_base(&payload.wallets, payload.total).map_err(|e| e.to_string())?;
+
+    let (total, recipients, rate) = match mixer::prepare_spread(&payload.wallets, base, !approved).await {
+        Ok(prepared) => prepared,
+        Err(err) if limits::violated(&err.to_string()) => return Ok(held(err.to_string())),
+        Err(err) => return Err(err.to_string())
+    };
+
+    match ton::contract_invoke_spread(pool, contract, total, recipients, rate.map(| r | r.rate)).await {
+        Ok(receipt) => Ok(Executed::Done(receipt)),
+        Err(err) if ton::limit_exceeded(&err) => Ok(held(err)),
+        Err(err) => Err(err)
+    }
+}
+
+/// Holds a job breaching a limit until spreads are allowed again, or for `JOB_LIMIT_RETRY` seconds.
+///
+/// The limits may be raised or the daily withdrawals drop meanwhile, a held job can be
+/// approved by an operator or cancelled if neither happens.
+fn held(reason: String) -> Executed {
+    let now: u64 = ton::time_now();
+    let run_at: u64 = limits::spread_limits().next_allowed(now)
+        .unwrap_or(now + config::env_or("JOB_LIMIT_RETRY", DEFAULT_LIMIT_RETRY));
+
+    Executed::Held(run_at, reason)
+}
+
+/// Collects from the contract of a time-locked collect job, unless the newest uncollected
+/// funds have not spent the dwell time yet.
+///
+/// A steady inflow could defer the job forever, so it fails once it waited `JOB_DWELL_MAX_WAIT`
+/// seconds past the dwell time counted from its creation. Funds younger than the dwell time
+/// are never swept.
+async fn collect(pool: &PgPool, payload: CollectJob, created_at: i64) -> Result<Executed, String> {
+    if multisig::enabled() {
+        return Err(String::from("collects require multisig approval"));
+    }
+
+    let contract: TonAddress = TonAddress::from_str(&payload.contract).map_err(|e| e.to_string())?;
+    ensure_resolved(payload.collect.jetton_wallet.as_deref())?;
+    let hours: u32 = payload.collect.min_dwell_hours.unwrap_or(0);
+
+    let due: u64 = mixer::dwell_due(pool, &contract, hours).await?;
+    let now: u64 = ton::time_now();
+    if due > now {
+        let max_wait: u64 = config::env_or("JOB_DWELL_MAX_WAIT", DEFAULT_DWELL_MAX_WAIT);
+        let deadline: u64 = (created_at.max(0) as u64).saturating_add(hours as u64 * 3600).saturating_add(max_wait);
+
+        if now >= deadline {
+            return Err(format!(
+                "deposits kept the funds younger than {} hours for {} seconds past the dwell time, the collect was not sent", hours, max_wait
+            ));
+        }
+        return Ok(Executed::Deferred(due.min(deadline)));
+    }
+
+    ton::ensure_code(&contract).await?;
+    let collect: CollectPayload = payload.collect;
+
+    let send_mode: u8 = collect.send_mode.unwrap_or(DEFAULT_SEND_MODE);
+    let data: CollectMessageData = mixer::collect_message_data(collect).map_err(| e | mixer::error_message(&e))?;
+    ton::contract_invoke_collect(pool, contract, data, send_mode).await.map(Executed::Done)
+}
+
+/// Forks the contract of a fork job.
+async fn fork(pool: &PgPool, payload: ForkJob) -> Result<String, String> {
+    let contract: TonAddress = TonAddress::from_str(&payload.contract).map_err(|e| e.to_string())?;
+    ton::ensure_code(&contract).await?;
+
+    ton::contract_invoke_fork(pool, contract, ton::time_now()).await
+}
+
+/// Builds the mixing statistics of the window of a snapshot job, returned as the result of the job.
+async fn snapshot(pool: &PgPool, payload: SnapshotJob) -> Result<String, String> {
+    let seconds: i64 = window_seconds(&payload.window).ok_or_else(|| format!("unknown statistics window `{}`", payload.window))?;
+    let to: i64 = ton::time_now() as i64;
+    let operations: Vec<OperationStats> = db::reports::operation_stats(pool, to - seconds).await.map_err(|e| e.to_string())?;
+
+    Ok(serde_json::to_string(&MixerStats::new(payload.window, to - seconds, to, operations)).unwrap())
+}
+
+/// Stores the progress of a job and extends its lease, failures are logged.
+async fn report<T: Serialize>(pool: &PgPool, id: i64, progress: &T) {
+    if let Err(err) = db::jobs::set_progress(pool, id, &serde_json::to_value(progress).unwrap(), lease()).await {
+        log_error!("Can not store the progress of job {}: {:?}", id, err);
+    }
+}
+
+/// Collects from the contracts of a batch collect job one after another.
+///
+/// Contracts holding no more than the gas of a collect are skipped, and of the rest the
+/// `MAX_BATCH_COLLECT` richest are collected, the others are left for another batch. The
+/// results are stored as the progress of the job after every collect, and a failed send
+/// stops the batch, as the seqno of the wallet is unknown after it.
+async fn collect_batch(pool: &PgPool, id: i64, payload: CollectBatchJob) -> Result<String, String> {
+    if multisig::enabled() {
+        return Err(String::from("collects require multisig approval"));
+    }
+
+    let mut results: Vec<CollectBatchResult> = Vec::new();
+    let mut targets: Vec<(String, i64)> = Vec::new();
+
+    for contract in payload.contracts {
+        let balance: Result<i64, String> = match TonAddress::from_str(&contract) {
+            Ok(address) => ton::get_balance(&address).await,
+            Err(err) => Err(err.to_string())
+        };
+
+        match balance {
+            Ok(balance) if balance > ton::collect_gas() as i64 => targets.push((contract, balance)),
+            Ok(_) => results.push(CollectBatchResult { contract, receipt: None, error: Some(String::from("skipped, nothing to collect")) }),
+            Err(err) => results.push(CollectBatchResult { contract, receipt: None, error: Some(err) })
+        }
+    }
+
+    targets.sort_by(| a, b | b.1.cmp(&a.1));
+    for (contract, _) in targets.split_off(targets.len().min(MAX_BATCH_COLLECT)) {
+        let error: String = format!("not sent, a batch collects from the {} richest contracts", MAX_BATCH_COLLECT);
+        results.push(CollectBatchResult { contract, receipt: None, error: Some(error) });
+    }
+    report(pool, id, &results).await;
+
+    let mut last_seqno: Option<u32> = None;
+    let mut stopped: Option<String> = None;
+
+    for (contract, _) in targets {
+        if let Some(reason) = &stopped {
+            results.push(CollectBatchResult { contract, receipt: None, error: Some(reason.clone()) });
+            continue;
+        }
+
+        let address: TonAddress = match mixer::prepare_batch_collect(pool, &contract, payload.tracked).await {
+            Ok(address) => address,
+            Err(err) => {
+                results.push(CollectBatchResult { contract, receipt: None, error: Some(mixer::error_message(&err)) });
+                continue;
+            }
+        };
+
+        if let Some(seqno) = last_seqno {
+            if let Err(err) = ton::wait_for_wallet_seqno(seqno, "collect").await {
+                log_error!("Stopping the batch collect of job {}: {}", id, err);
+                stopped = Some(format!("not sent, the batch stopped: {}", err));
+                results.push(CollectBatchResult { contract, receipt: None, error: stopped.clone() });
+                continue;
+            }
+        }
+
+        let data: CollectMessageData = CollectMessageData { mode: payload.mode, jetton_wallet: None, amount: None };
+        match ton::contract_invoke_collect(pool, address, data, DEFAULT_SEND_MODE).await {
+            Ok(tx) => {
+                let receipt: Option<OperationReceipt> = serde_json::from_str(&tx).ok();
+                last_seqno = receipt.as_ref().map(| r | r.seqno);
+                results.push(CollectBatchResult { contract, receipt, error: None });
+            },
+            Err(err) => {
+                // the seqno of the wallet is unknown after a failed send, so the batch stops
+                log_error!("Stopping the batch collect of job {}, the collect from {} was not sent: {}", id, contract, err);
+                stopped = Some(format!("not sent, the batch stopped: {}", err));
+                results.push(CollectBatchResult { contract, receipt: None, error: Some(err) });
+            }
+        }
+        report(pool, id, &results).await;
+    }
+
+    let sent: usize = results.iter().filter(| r | r.error.is_none()).count();
+    log_info!("Batch collect of job {} sent {} of {} collects", id, sent, results.len());
+
+    Ok(serde_json::to_string(&CollectBatchReceipt { sent, failed: results.len() - sent, results }).unwrap())
+}
+
+/// Executes the moves of a rebalance plan.
+///
+/// The surplus contracts are collected one after another, then the job waits until the
+/// indexer recorded the collects and spreads what they sent out through the mixer contract
+/// to the contracts below their target, scaled down if less arrived than planned.
+///
+/// A job claimed again resumes from its stored progress: contracts with a collect receipt
+/// are not collected twice, and the spread is only sent if no earlier one of the job may
+/// have been applied.
+async fn rebalance(pool: &PgPool, id: i64, payload: RebalanceJob, resumed: Option<Value>) -> Result<String, String> {
+    if multisig::enabled() {
+        return Err(String::from("collects require multisig approval"));
+    }
+
+    let mut progress: RebalanceProgress = match resumed.and_then(| p | serde_json::from_value::<RebalanceProgress>(p).ok()) {
+        Some(progress) => {
+            log_info!("Rebalance job {} resumes in the {} phase", id, progress.phase);
+            progress
+        },
+        None => RebalanceProgress {
+            phase: PHASE_COLLECT.to_string(),
+            plan: payload.plan.clone(),
+            received: None,
+            receipts: Vec::new()
+        }
+    };
+    report(pool, id, &progress).await;
+
+    if progress.phase == PHASE_DONE {
+        return Ok(serde_json::to_string(&progress).unwrap());
+    }
+
+    if progress.phase == PHASE_COLLECT {
+        let mut last_seqno: Option<u32> = progress.receipts.last().map(| r | r.seqno);
+
+        for movement in payload.plan.moves.iter().filter(| m | m.collect) {
+            let contract: TonAddress = TonAddress::from_str(&movement.contract).map_err(|e| e.to_string())?;
+            if progress.receipts.iter().any(| r | TonAddress::from_str(&r.contract).is_ok_and(| c | c == contract)) {
+                continue;
+            }
+            ton::ensure_code(&contract).await?;
+
+            if let Some(seqno) = last_seqno {
+                ton::wait_for_wallet_seqno(seqno, "collect").await?;
+            }
+
+            let data: CollectMessageData = CollectMessageData {
+                mode: MixerCollectionModes::new().available_ton_balance,
+                jetton_wallet: None,
+                amount: None
+            };
+            let receipt: OperationReceipt = serde_json::from_str(&ton::contract_invoke_collect(pool, contract, data, DEFAULT_SEND_MODE).await?)
+                .map_err(|e| e.to_string())?;
+
+            last_seqno = Some(receipt.seqno);
+            progress.receipts.push(receipt);
+            report(pool, id, &progress).await;
+        }
+
+        progress.phase = PHASE_SETTLE.to_string();
+        report(pool, id, &progress).await;
+    }
+
+    let expected: u64 = payload.plan.moves.iter().map(| m | m.send).sum();
+
+    if progress.phase == PHASE_SETTLE {
+        let received: u64 = settle(pool, &progress.receipts, expected).await?;
+
+        progress.phase = PHASE_SPREAD.to_string();
+        progress.received = Some(received);
+        report(pool, id, &progress).await;
+    }
+
+    // a spread written by an earlier run may still be applied, sending another one would pay twice
+    let sent: Vec<i64> = db::outbox::sent_by_job(pool, id, Some("spread")).await.map_err(|e| e.to_string())?;
+    if !sent.is_empty() {
+        return Err(format!("the spread of the rebalance may have been sent as outbox entries {:?}, review them instead of sending it again", sent));
+    }
+
+    let received: u64 = progress.received.unwrap_or(0);
+    let scale: f64 = (received as f64 / expected as f64).min(1.0);
+    let mut recipients: Vec<SpreadWallet> = Vec::new();
+    let mut total: u64 = 0;
+    for movement in payload.plan.moves.iter().filter(| m | m.send > 0) {
+        let amount: u64 = (movement.send as f64 * scale) as u64;
+        if amount == 0 {
+            continue;
+        }
+
+        total += amount;
+        recipients.push(SpreadWallet {
+            account: TonAddress::from_str(&movement.contract).map_err(|e| e.to_string())?,
+            amount: BigUint::from(amount),
+            // the contracts are active, so their legs bounce where the layout stores the flag
+            bounce: (ton::dict::spread_layout() == ton::dict::SpreadLayout::Bounce).then_some(true)
+        });
+    }
+
+    if total == 0 {
+        return Err(String::from("the collects sent out no funds"));
+    }
+
+    let root: TonAddress = ton::mixer_contract_address();
+    ton::ensure_code(&root).await?;
+    let receipt: OperationReceipt = serde_json::from_str(&ton::contract_invoke_spread(pool, root, Nanotons::from(total), recipients, None).await?)
+        .map_err(|e| e.to_string())?;
+
+    progress.phase = PHASE_DONE.to_string();
+    progress.receipts.push(receipt);
+    report(pool, id, &progress).await;
+
+    Ok(serde_json::to_string(&progress).unwrap())
+}
+
+/// Waits until the indexer recorded the collects of a rebalance, or `SETTLE_TIMEOUT` passed.
+///
+/// The funds are measured by what the indexed collect transactions sent out, as the balance
+/// of the gas wallet moves with every other message of the wallet as well.
+///
+/// # Returns
+///
+/// The nanotons the indexed collects sent out, at most `expected`.
+async fn settle(pool: &PgPool, collects: &[OperationReceipt], expected: u64) -> Result<u64, String> {
+    let deadline: u64 = ton::time_now() + SETTLE_TIMEOUT;
+
+    loop {
+        let mut received: u64 = 0;
+        let mut indexed: usize = 0;
+
+        for receipt in collects {
+            let query_id: i64 = receipt.query_id.unwrap_or(0) as i64;
+            if let Some(value) = db::events::collected(pool, &receipt.contract, query_id).await.map_err(|e| e.to_string())? {
+                received += value.max(0) as u64;
+                indexed += 1;
+            }
+        }
+
+        if indexed == collects.len() || received >= expected || ton::time_now() >= deadline {
+            return Ok(received.min(expected));
+        }
+
+        actix_web::rt::time::sleep(SETTLE_POLL).await;
+    }
+}
@@ -0,0 +1,267 @@
+//! # Leader Election
+//!
+//! This module elects the one instance of a deployment that sends from the wallet, so
+//! replicas sharing the wallet never race for its seqno. With `LEADER_ELECTION` enabled every
+//! instance keeps trying to take a Postgres advisory lock on a connection of its own, and the
+//! instance holding it is the leader until the connection drops. The leader runs the job
+//! runner, the outbox, the policies, the indexer and the notifiers, and records itself with
+//! its `LEADER_ADVERTISE_URL`.
+//!
+//! Every broadcast checks in `pg_locks` that the session of the leader still holds the lock,
+//! see `ensure_leader`, so a leader whose session was killed stops sending before its next
+//! heartbeat. A broadcast already past the check can still race a new leader; run the seqno
+//! lock of `ton::lock` next to election to close that window.
+//!
+//! Followers never send: writes that would send from the wallet are forwarded to the URL of
+//! the leader, see `forward`, and background operations reach it through the jobs queue the
+//! instances share. Without `LEADER_ELECTION` every instance is its own leader.
+
+use std::{sync::{atomic::{AtomicBool, AtomicI32, Ordering}, OnceLock}, time::Duration};
+
+use actix_web::{body::BoxBody, dev::{ServiceRequest, ServiceResponse}, error::{ErrorBadGateway, ErrorServiceUnavailable}, http::StatusCode, web::{Bytes, Data}, Error, FromRequest, HttpRequest, HttpResponse, HttpResponseBuilder};
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+
+use crate::{auth, config, db, metrics, types::{leader::Leader, Response}};
+
+/// Key of the advisory lock, used when `LEADER_LOCK_KEY` is not set.
+const DEFAULT_LOCK_KEY: i64 = 0x6d69786572;
+
+/// Interval between election attempts and checks of the lock in seconds, used when
+/// `LEADER_ELECTION_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 5;
+
+/// Time a heartbeat or a check of the lock may take before the lock counts as lost.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Header naming the replica a request was forwarded by, forwarded requests are never forwarded again.
+pub const FORWARDED_BY_HEADER: &str = "X-Forwarded-By";
+
+/// Headers of a single connection, not copied to the forwarded request or response.
+const HOP_HEADERS: [&str; 6] = ["connection", "keep-alive", "transfer-encoding", "content-length", "host", "x-forwarded-for"];
+
+/// Whether this instance holds the advisory lock.
+static LEADER: AtomicBool = AtomicBool::new(false);
+
+/// Whether leader election is enabled, fixed at startup.
+static ELECTION: OnceLock<bool> = OnceLock::new();
+
+/// The pool and the lock key the lock is checked with before a broadcast.
+static FENCE: OnceLock<(PgPool, i64)> = OnceLock::new();
+
+/// Backend pid of the session holding the lock, 0 while this instance is a follower.
+static LOCK_PID: AtomicI32 = AtomicI32::new(0);
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Returns whether leader election is enabled with `LEADER_ELECTION`.
+pub fn enabled() -> bool {
+    *ELECTION.get_or_init(|| config::env_or("LEADER_ELECTION", false))
+}
+
+/// Returns whether this instance may send from the wallet.
+pub fn is_leader() -> bool {
+    !enabled() || LEADER.load(Ordering::SeqCst)
+}
+
+/// Returns the name of this instance, `INSTANCE_ID` or the host name.
+pub fn instance_id() -> String {
+    let fallback: String = config::var("HOSTNAME").unwrap_or_else(|_| format!("pid-{}", std::process::id()));
+
+    config::env_or("INSTANCE_ID", fallback)
+}
+
+/// Marks this instance as leader, with the pid of the session holding the lock, or as follower.
+fn set_leader(pid: Option<i32>) {
+    LOCK_PID.store(pid.unwrap_or(0), Ordering::SeqCst);
+    LEADER.store(pid.is_some(), Ordering::SeqCst);
+    metrics::set_gauge("leader", if pid.is_some() { 1.0 } else { 0.0 });
+}
+
+/// Checks in the database that this instance still holds the lock, right before it sends.
+///
+/// `is_leader` only reflects the last heartbeat; the session of the lock may have been
+/// killed since and another instance elected. Without leader election this always passes.
+///
+/// # Returns
+///
+/// Nothing, or an error if this instance is a follower or the lock is no longer held.
+pub async fn ensure_leader() -> Result<(), String> {
+    if !enabled() {
+        return Ok(());
+    }
+
+    let pid: i32 = LOCK_PID.load(Ordering::SeqCst);
+    let (pool, key): &(PgPool, i64) = match FENCE.get() {
+        Some(fence) if LEADER.load(Ordering::SeqCst) && pid != 0 => fence,
+        _ => return Err(String::from("this instance is not the leader and does not send messages"))
+    };
+
+    // an advisory lock on a bigint key is listed with its high half as classid and its low half as objid
+    let held = actix_web::rt::time::timeout(HEARTBEAT_TIMEOUT, sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM pg_locks WHERE locktype = 'advisory' AND granted AND pid = $1
+         AND classid::bigint = $2 AND objid::bigint = $3 AND objsubid = 1)"
+    )
+        .bind(pid)
+        .bind((*key >> 32) & 0xffffffff)
+        .bind(*key & 0xffffffff)
+        .fetch_one(pool)).await;
+
+    match held {
+        Ok(Ok(true)) => Ok(()),
+        Ok(Ok(false)) => {
+            log_error!("Instance {} lost the leader lock, refusing to send", instance_id());
+            set_leader(None);
+            Err(String::from("this instance lost the leader lock and does not send messages"))
+        },
+        Ok(Err(err)) => Err(format!("can not check the leader lock: {}", err)),
+        Err(_) => Err(String::from("checking the leader lock timed out"))
+    }
+}
+
+/// Runs the election loop forever, see the module documentation.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+pub async fn run(pool: PgPool) {
+    if !enabled() {
+        return;
+    }
+
+    let interval: u64 = config::env_or("LEADER_ELECTION_INTERVAL", DEFAULT_INTERVAL);
+    let key: i64 = config::env_or("LEADER_LOCK_KEY", DEFAULT_LOCK_KEY);
+    let instance: String = instance_id();
+    let url: Option<String> = config::var("LEADER_ADVERTISE_URL").ok().filter(| u | !u.is_empty());
+
+    log_info!("Instance {} is running for leader every {:?} seconds", instance, interval);
+    set_leader(None);
+    let _ = FENCE.set((pool.clone(), key));
+
+    // the lock belongs to the session, so the connection is kept out of the pool
+    let mut lock: Option<PgConnection> = None;
+
+    loop {
+        if lock.is_none() {
+            lock = match pool.acquire().await {
+                Ok(conn) => Some(conn.detach()),
+                Err(err) => {
+                    log_error!("Leader election can not connect to the database: {:?}", err);
+                    None
+                }
+            };
+        }
+
+        if let Some(conn) = lock.as_mut() {
+            // a session that stopped answering counts as lost, the lock may be granted to another instance
+            let held: Result<Option<i32>, String> = actix_web::rt::time::timeout(HEARTBEAT_TIMEOUT, async {
+                match LEADER.load(Ordering::SeqCst) {
+                    true => sqlx::query("SELECT 1").execute(&mut *conn).await.map(|_| Some(LOCK_PID.load(Ordering::SeqCst))),
+                    false => match sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_lock($1)").bind(key).fetch_one(&mut *conn).await? {
+                        true => sqlx::query_scalar::<_, i32>("SELECT pg_backend_pid()").fetch_one(&mut *conn).await.map(Some),
+                        false => Ok(None)
+                    }
+                }
+            }).await
+                .map_err(|_| String::from("heartbeat timed out"))
+                .and_then(| held | held.map_err(| e | e.to_string()));
+
+            match held {
+                Ok(Some(pid)) if !LEADER.load(Ordering::SeqCst) => {
+                    set_leader(Some(pid));
+                    log_info!("Instance {} was elected leader", instance);
+
+                    if let Err(err) = db::leader::set(&pool, &instance, url.as_deref()).await {
+                        log_error!("Leader can not record itself, followers can not forward writes: {:?}", err);
+                    }
+                },
+                Ok(_) => {},
+                Err(err) => {
+                    if LEADER.load(Ordering::SeqCst) {
+                        log_error!("Instance {} lost the leader lock: {}", instance, err);
+                    }
+                    set_leader(None);
+                    lock = None;
+                }
+            }
+        }
+
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Returns the 503 error of a write no leader can take.
+fn unavailable(reason: &str) -> Error {
+    ErrorServiceUnavailable(Response::error(Value::String(format!("this instance is not the leader and {}", reason))).to_string())
+}
+
+/// Forwards a request to the leader and answers with its response.
+///
+/// The request keeps its method, path, headers and body; the address of the caller is appended
+/// to `X-Forwarded-For`, so the leader should list its followers in `TRUSTED_PROXIES`.
+///
+/// # Returns
+///
+/// The response of the leader, a 503 error if no leader is known or the request was already
+/// forwarded, or a 502 error if the leader can not be reached.
+pub async fn forward(req: ServiceRequest) -> Result<ServiceResponse<BoxBody>, Error> {
+    if req.headers().contains_key(FORWARDED_BY_HEADER) {
+        return Err(unavailable("the request was already forwarded, the leader is changing"));
+    }
+
+    let pool: Data<PgPool> = req.app_data::<Data<PgPool>>().cloned().ok_or_else(|| unavailable("has no database"))?;
+    let leader: Leader = match db::leader::get(&pool).await {
+        Ok(Some(leader)) if leader.instance != instance_id() => leader,
+        Ok(_) => return Err(unavailable("no other leader is known yet")),
+        Err(err) => {
+            log_error!("Can not read the leader: {:?}", err);
+            return Err(unavailable("can not find the leader"));
+        }
+    };
+    let url: String = leader.url.ok_or_else(|| unavailable(&format!("leader {} does not advertise a URL", leader.instance)))?;
+
+    let (http_req, mut payload) = req.into_parts();
+    let body: Bytes = Bytes::from_request(&http_req, &mut payload).await?;
+    let response: HttpResponse = send(&http_req, &url, body).await?;
+
+    log_info!("Forwarded {} {} to leader {}", http_req.method(), http_req.path(), leader.instance);
+    Ok(ServiceResponse::new(http_req, response))
+}
+
+/// Sends a request with its body to the leader at `url`.
+async fn send(req: &HttpRequest, url: &str, body: Bytes) -> Result<HttpResponse, Error> {
+    let path: &str = req.uri().path_and_query().map(| p | p.as_str()).unwrap_or(req.path());
+    let method: reqwest::Method = reqwest::Method::from_bytes(req.method().as_str().as_bytes())
+        .map_err(| e | ErrorBadGateway(Response::error(Value::String(e.to_string())).to_string()))?;
+
+    let peer: String = req.peer_addr().map(| a | a.ip().to_string()).unwrap_or_else(|| String::from("unknown"));
+    let forwarded_for: String = match auth::network::forwarded_for(req.headers()) {
+        Some(hops) => format!("{}, {}", hops, peer),
+        None => peer
+    };
+
+    let client: &reqwest::Client = CLIENT.get_or_init(reqwest::Client::new);
+    let mut request: reqwest::RequestBuilder = client.request(method, format!("{}{}", url.trim_end_matches('/'), path))
+        .timeout(Duration::from_millis(config::runtime().request_deadline))
+        .header("X-Forwarded-For", forwarded_for)
+        .header(FORWARDED_BY_HEADER, instance_id());
+    for (name, value) in req.headers().iter().filter(| (name, _) | !HOP_HEADERS.contains(&name.as_str())) {
+        request = request.header(name.as_str(), value.as_bytes());
+    }
+
+    let response: reqwest::Response = request.body(body.to_vec()).send().await.map_err(| e | {
+        log_error!("Can not forward {} {} to the leader at {}: {}", req.method(), path, url, e);
+        ErrorBadGateway(Response::error(Value::String(String::from("the leader can not be reached"))).to_string())
+    })?;
+
+    let mut builder: HttpResponseBuilder = HttpResponse::build(StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY));
+    for (name, value) in response.headers().iter().filter(| (name, _) | !HOP_HEADERS.contains(&name.as_str())) {
+        builder.append_header((name.as_str(), value.as_bytes()));
+    }
+
+    let body: Vec<u8> = response.bytes().await
+        .map_err(| e | ErrorBadGateway(Response::error(Value::String(format!("the leader response is incomplete: {}", e))).to_string()))?
+        .to_vec();
+
+    Ok(builder.body(body))
+}
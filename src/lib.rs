@@ -0,0 +1,38 @@
+//! # Mixer API
+//!
+//! This crate holds the modules of the HTTP server used by the binary in `main.rs`. The
+//! types other crates of the workspace share with it, like `mixer-client`, live in the
+//! light `mixer-types` crate.
+
+#[macro_use]
+pub mod logging;
+pub mod routes;
+pub mod controllers;
+pub mod services;
+pub mod types;
+pub mod ton;
+pub mod config;
+pub mod auth;
+pub mod bus;
+pub mod db;
+pub mod deadline;
+pub mod deposits;
+pub mod explorer;
+pub mod indexer;
+pub mod jobs;
+pub mod jettons;
+pub mod leader;
+pub mod metrics;
+pub mod multisig;
+pub mod notify;
+pub mod alerts;
+pub mod outbox;
+pub mod panics;
+pub mod policy;
+pub mod rates;
+pub mod scheduler;
+pub mod strategy;
+pub mod validation;
+pub mod warnings;
+#[cfg(feature = "telegram")]
+pub mod telegram;
@@ -0,0 +1,193 @@
+//! # Logging
+//!
+//! This module emits the application and access logs, either as plain `[ LEVEL ] message`
+//! lines or as one JSON object per line for ingestion into Loki or ELK. The format is
+//! selected with `LOG_FORMAT` (`plain` or `json`). Application code logs with the
+//! `log_info!`, `log_warn!` and `log_error!` macros, requests are logged by `access_log`.
+//! JSON records emitted while a request is handled carry its W3C trace context, see `trace`.
+//! Messages and string fields are scrubbed of secrets before they are written, see `redact`.
+
+use std::{str::FromStr, sync::{atomic::{AtomicU64, Ordering}, OnceLock}, time::{Instant, SystemTime}};
+
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, HttpMessage, http::header::{HeaderName, HeaderValue}, middleware::Next, Error};
+use serde_json::{json, Value};
+
+use crate::{config, metrics};
+
+pub mod redact;
+pub mod trace;
+
+use trace::TraceContext;
+
+/// Header the request id is read from and returned in.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The id of a request, stored in its extensions by `access_log`.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Represents the format logs are emitted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Plain,
+    Json
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(LogFormat::Plain),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format `{}`", s))
+        }
+    }
+}
+
+/// The format configured with `LOG_FORMAT`.
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+/// Counter making generated request ids unique within the process.
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the configured log format, plain by default.
+pub fn format() -> LogFormat {
+    *FORMAT.get_or_init(|| config::env_or("LOG_FORMAT", LogFormat::Plain))
+}
+
+/// Returns the current Unix time in milliseconds.
+fn timestamp_ms() -> u128 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis()
+}
+
+/// Emits a log record.
+///
+/// # Arguments
+///
+/// * `level` - The level, e.g. `INFO`.
+/// * `target` - The module the record comes from.
+/// * `message` - The message.
+/// * `fields` - Structured fields, merged into the JSON record and appended to plain lines.
+pub fn emit(level: &str, target: &str, message: &str, fields: Option<Value>) {
+    let message: &str = &redact::redact(message);
+    let fields: Option<Value> = fields.map(redact_value);
+
+    match format() {
+        LogFormat::Plain => match fields {
+            Some(fields) => println!("[ {} ] {} {}", level, message, fields),
+            None => println!("[ {} ] {}", level, message)
+        },
+        LogFormat::Json => {
+            let mut record: Value = json!({
+                "timestamp": timestamp_ms() as u64,
+                "level": level,
+                "target": target,
+                "message": message
+            });
+
+            if let Some(record) = record.as_object_mut() {
+                if let Some(context) = trace::current() {
+                    record.insert(String::from("trace_id"), Value::String(context.trace_id));
+                    record.insert(String::from("span_id"), Value::String(context.span_id));
+                }
+
+                if let Some(Value::Object(fields)) = fields {
+                    record.extend(fields);
+                }
+            }
+
+            println!("{}", record);
+        }
+    }
+}
+
+/// Redacts the strings of a structured value.
+fn redact_value(value: Value) -> Value {
+    match value {
+        Value::String(s) => Value::String(redact::redact(&s)),
+        Value::Array(values) => Value::Array(values.into_iter().map(redact_value).collect()),
+        Value::Object(fields) => Value::Object(fields.into_iter().map(| (k, v) | (k, redact_value(v))).collect()),
+        value => value
+    }
+}
+
+/// Logs a message at the `INFO` level.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logging::emit("INFO", module_path!(), &format!($($arg)*), None)
+    };
+}
+
+/// Logs a message at the `WARN` level.
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::logging::emit("WARN", module_path!(), &format!($($arg)*), None)
+    };
+}
+
+/// Logs a message at the `ERROR` level.
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logging::emit("ERROR", module_path!(), &format!($($arg)*), None)
+    };
+}
+
+/// Generates a request id from the current time and a process-wide counter.
+fn generate_request_id() -> String {
+    format!("{:x}-{:x}", timestamp_ms(), REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Middleware logging every request with its id, route, status and latency.
+///
+/// The request id is taken from the `X-Request-Id` header, or generated, and is
+/// returned in the same header of the response. The request is handled within its trace
+/// context, continued from the `traceparent` header when present.
+pub async fn access_log(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started: Instant = Instant::now();
+    let request_id: String = req.headers().get(REQUEST_ID_HEADER)
+        .and_then(| v | v.to_str().ok())
+        .filter(| v | !v.is_empty() && v.len() <= 128)
+        .map(String::from)
+        .unwrap_or_else(generate_request_id);
+
+    let method: String = req.method().to_string();
+    let path: String = req.path().to_string();
+    let context: TraceContext = TraceContext::from_headers(req.headers());
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+    req.extensions_mut().insert(context.clone());
+
+    let mut res: ServiceResponse<_> = trace::scope(context.clone(), next.call(req)).await?;
+
+    let route: Option<String> = res.request().match_pattern();
+    let status: u16 = res.status().as_u16();
+    let latency_ms: f64 = started.elapsed().as_secs_f64() * 1000.0;
+    metrics::observe_request(&method, route.as_deref(), status, latency_ms);
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+
+    let message: String = format!("{} {} {} {:.1}ms", method, path, status, latency_ms);
+    let fields: Value = json!({
+        "request_id": request_id,
+        "method": method,
+        "path": path,
+        "route": route,
+        "status": status,
+        "latency_ms": latency_ms,
+        "trace_id": context.trace_id,
+        "span_id": context.span_id,
+        "parent_span_id": context.parent_id
+    });
+
+    match format() {
+        LogFormat::Plain => emit("INFO", "access", &format!("{} id={}", message, request_id), None),
+        LogFormat::Json => emit("INFO", "access", &message, Some(fields))
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,134 @@
+//! # Redaction
+//!
+//! This module scrubs secrets from text before it is logged or returned in an error. tonlib
+//! and the relayer may echo what they were given verbatim, so every log record and error
+//! response passes through `redact`, which replaces:
+//!
+//! - the values of secret environment variables (`WALLET_MNEMONIC`, tokens, keys, passwords,
+//!   webhook and database URLs), and the mnemonic words in sequence however they are quoted
+//! - passwords embedded in URLs
+//! - bags of cells in base64 or hex, i.e. raw signed messages
+//! - TON addresses beyond the first two of a text, so recipient lists are never written out
+
+use std::sync::{Arc, LazyLock, RwLock};
+
+use regex::{Captures, Regex};
+
+use crate::config;
+
+/// Replacement of a secret value.
+const REDACTED: &str = "[REDACTED]";
+
+/// Number of addresses of a text kept before the rest is redacted.
+const KEPT_ADDRESSES: usize = 2;
+
+/// Minimum length of an environment value treated as a secret, shorter ones would match too much.
+const MIN_SECRET_LEN: usize = 6;
+
+/// Matches bags of cells: base64 starts with `te6cc` and hex with the `b5ee9c72` magic.
+static BOC_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"te6cc[A-Za-z0-9+/=_\-]{16,}|(?i:b5ee9c72[0-9a-f]{16,})").unwrap()
+});
+
+/// Matches the password of a URL with credentials.
+static URL_PASSWORD_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(://[^:/@\s]+:)[^@\s]+@").unwrap()
+});
+
+/// Matches TON addresses in raw form, and runs of base64 characters user-friendly ones are
+/// told apart from by their length of exactly 48.
+static ADDRESS_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"-?[0-9]+:[0-9a-fA-F]{64}|[A-Za-z0-9_\-+/]{48,}").unwrap()
+});
+
+/// Represents the secrets of the current configuration.
+struct Secrets {
+    /// Secret values, longest first so one containing another is replaced whole.
+    values: Vec<String>,
+    /// Matches the words of the mnemonic in order, separated by anything but letters.
+    mnemonic: Option<Regex>
+}
+
+/// The secrets and the configuration generation they were read in.
+static SECRETS: RwLock<Option<(u64, Arc<Secrets>)>> = RwLock::new(None);
+
+/// Returns whether an environment variable holds a secret.
+fn is_secret(key: &str) -> bool {
+    ["MNEMONIC", "TOKEN", "KEY", "SECRET", "PASSWORD", "WEBHOOK_URL", "DATABASE_URL"].iter().any(| part | key.contains(part))
+}
+
+impl Secrets {
+    /// Reads the secrets from the environment.
+    fn from_env() -> Secrets {
+        let mut values: Vec<String> = std::env::vars()
+            .filter(| (key, value) | is_secret(key) && value.trim().len() >= MIN_SECRET_LEN)
+            .map(| (_, value) | value.trim().to_string())
+            .collect();
+        values.sort_by_key(| v | std::cmp::Reverse(v.len()));
+        values.dedup();
+
+        let mnemonic: Option<Regex> = config::var("WALLET_MNEMONIC").ok().and_then(| mnemonic | {
+            let words: Vec<String> = mnemonic.split_whitespace().map(regex::escape).collect();
+            // a few words in a row are already specific to the mnemonic
+            (words.len() >= 4).then(|| Regex::new(&format!(r"(?i){}", words.join(r"[^A-Za-z]{1,6}"))).ok()).flatten()
+        });
+
+        Secrets { values, mnemonic }
+    }
+}
+
+/// Returns the secrets, reading them again if the configuration was reloaded.
+fn secrets() -> Arc<Secrets> {
+    let generation: u64 = config::generation();
+
+    if let Some((loaded, secrets)) = SECRETS.read().unwrap().as_ref() {
+        if *loaded == generation {
+            return secrets.clone();
+        }
+    }
+
+    let secrets: Arc<Secrets> = Arc::new(Secrets::from_env());
+    *SECRETS.write().unwrap() = Some((generation, secrets.clone()));
+    secrets
+}
+
+/// Removes secrets, raw messages and recipient lists from a text.
+///
+/// # Arguments
+///
+/// * `text` - A log message or error.
+///
+/// # Returns
+///
+/// The text with every match replaced, unchanged if nothing matched.
+pub fn redact(text: &str) -> String {
+    let secrets: Arc<Secrets> = secrets();
+    let mut text: String = text.to_string();
+
+    for value in &secrets.values {
+        if text.contains(value.as_str()) {
+            text = text.replace(value.as_str(), REDACTED);
+        }
+    }
+
+    if let Some(mnemonic) = &secrets.mnemonic {
+        text = mnemonic.replace_all(&text, REDACTED).into_owned();
+    }
+
+    text = URL_PASSWORD_RE.replace_all(&text, format!("${{1}}{}@", REDACTED)).into_owned();
+    text = BOC_RE.replace_all(&text, "[BOC]").into_owned();
+
+    let mut seen: usize = 0;
+    ADDRESS_RE.replace_all(&text, | captures: &Captures | {
+        let found: &str = &captures[0];
+        if !found.contains(':') && found.len() != 48 {
+            return found.to_string();
+        }
+
+        seen += 1;
+        match seen > KEPT_ADDRESSES {
+            true => String::from("[address]"),
+            false => found.to_string()
+        }
+    }).into_owned()
+}
@@ -0,0 +1,139 @@
+//! # Trace Context
+//!
+//! This module implements W3C trace context propagation. The `traceparent` and `tracestate`
+//! headers of incoming requests are honored, each request gets its own span in the trace of
+//! the caller (or a new trace when it sent none), and the context is attached to log records
+//! and outgoing webhook and relayer calls made while the request is handled.
+
+use actix_web::http::header::HeaderMap;
+use rand::Rng;
+
+/// Header carrying the trace id, parent span id and flags.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Header carrying vendor-specific trace state, passed on unchanged.
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// Flags of traces started by the mixer, marked as sampled.
+const SAMPLED: u8 = 0x01;
+
+tokio::task_local! {
+    /// The trace context of the request handled by the current task.
+    static CURRENT: TraceContext;
+}
+
+/// Represents the trace context of a request.
+#[derive(Debug, Clone)]
+pub struct TraceContext {
+    /// The 32 hex digit id of the trace.
+    pub trace_id: String,
+    /// The 16 hex digit id of the span of the request in the mixer.
+    pub span_id: String,
+    /// The span of the caller, `None` if the trace started here.
+    pub parent_id: Option<String>,
+    pub flags: u8,
+    pub tracestate: Option<String>
+}
+
+impl TraceContext {
+    /// Creates the context of a request, continuing the trace of its headers if they carry a valid one.
+    pub fn from_headers(headers: &HeaderMap) -> TraceContext {
+        let tracestate: Option<String> = headers.get(TRACESTATE_HEADER)
+            .and_then(| v | v.to_str().ok())
+            .filter(| v | !v.is_empty() && v.len() <= 512)
+            .map(String::from);
+
+        let parent = headers.get(TRACEPARENT_HEADER)
+            .and_then(| v | v.to_str().ok())
+            .and_then(parse_traceparent);
+
+        match parent {
+            Some((trace_id, parent_id, flags)) => TraceContext {
+                trace_id,
+                span_id: random_hex(8),
+                parent_id: Some(parent_id),
+                flags,
+                tracestate
+            },
+            // a tracestate without a valid traceparent must be discarded
+            None => TraceContext {
+                trace_id: random_hex(16),
+                span_id: random_hex(8),
+                parent_id: None,
+                flags: SAMPLED,
+                tracestate: None
+            }
+        }
+    }
+
+    /// Returns the `traceparent` header value naming the span of the mixer as parent.
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, self.flags)
+    }
+}
+
+/// Parses a version `00` compatible `traceparent` header.
+///
+/// # Returns
+///
+/// The trace id, parent span id and flags, or `None` if the header is invalid.
+fn parse_traceparent(value: &str) -> Option<(String, String, u8)> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+    let (version, trace_id, parent_id, flags) = match parts.as_slice() {
+        [version, trace_id, parent_id, flags, ..] => (*version, *trace_id, *parent_id, *flags),
+        _ => return None
+    };
+
+    // version 00 has exactly four fields, later versions may append more
+    if !is_hex(version, 2) || version == "ff" || (version == "00" && parts.len() != 4) {
+        return None;
+    }
+
+    if !is_hex(trace_id, 32) || !is_hex(parent_id, 16) || !is_hex(flags, 2) {
+        return None;
+    }
+
+    if trace_id.bytes().all(| b | b == b'0') || parent_id.bytes().all(| b | b == b'0') {
+        return None;
+    }
+
+    Some((trace_id.to_string(), parent_id.to_string(), u8::from_str_radix(flags, 16).ok()?))
+}
+
+/// Checks that a value is `len` lowercase hex digits.
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(| b | b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Returns `bytes` random bytes as lowercase hex.
+///
+/// Besides the ids of traces and spans it generates the ids of bus events and webhook
+/// deliveries and the confirmation tokens of the admin API.
+pub fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes).map(| _ | format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+/// Runs a future with a trace context as the current one.
+pub async fn scope<F: std::future::Future>(context: TraceContext, future: F) -> F::Output {
+    CURRENT.scope(context, future).await
+}
+
+/// Returns the trace context of the request handled by the current task, if any.
+pub fn current() -> Option<TraceContext> {
+    CURRENT.try_with(| context | context.clone()).ok()
+}
+
+/// Adds the current trace context to an outgoing request.
+pub fn inject(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    let Some(context) = current() else {
+        return request;
+    };
+
+    let request = request.header(TRACEPARENT_HEADER, context.traceparent());
+
+    match context.tracestate {
+        Some(tracestate) => request.header(TRACESTATE_HEADER, tracestate),
+        None => request
+    }
+}
@@ -3,16 +3,16 @@
 //! This module implements an HTTP server using Actix Web framework.
 //! It sets up CORS, compression, and routes for the application.
 
-use std::{io::Result, env};
+use std::{io::Result, time::Duration};
 use actix_cors::Cors;
-use actix_web::{middleware::Compress, App, HttpServer};
+use actix_web::{middleware::{from_fn, Compress}, web, App, HttpServer};
 use dotenv::dotenv;
-
-pub mod routes;
-pub mod controllers;
-pub mod services;
-pub mod types;
-pub mod ton;
+use rust_mixer_api::{
+    alerts, auth, bus, config, db, deadline, deposits, indexer, jettons, jobs, leader, logging, metrics, notify, outbox, panics, policy, rates, routes, scheduler, strategy, ton, validation, warnings,
+    log_error, log_info, log_warn
+};
+#[cfg(feature = "telegram")]
+use rust_mixer_api::telegram;
 
 /// The main function that starts the HTTP server.
 ///
@@ -24,32 +24,125 @@ pub mod ton;
 async fn main() -> Result<()> {
     // Load environment variables from .env file
     dotenv().ok();
-    // Parse the PORT environment variable
-    let port: u16 = env::var("PORT").unwrap().parse::<u16>().unwrap();
+    // Log panics in the configured log format
+    panics::install_hook();
+    // Load the server and the runtime configuration
+    let config: config::AppConfig = config::AppConfig::from_env();
+    config::runtime();
+    let port: u16 = config.port;
+    let json_limit: usize = config.json_limit;
+    // Check the ranges writes are allowed from, the tokens, the operator keys, the wallet accounts, the spread encoding and layout, the mixing strategies, the jettons, the send modes callers may request and the rate source
+    auth::network::rules();
+    auth::rbac::rules();
+    auth::operator::operator_keys();
+    ton::wallet_accounts();
+    ton::mixer_contract_address();
+    ton::dict::spread_encoding();
+    ton::dict::spread_layout();
+    strategy::configured();
+    jettons::configured_masters();
+    validation::allowed_send_modes();
+    rates::source();
+
+    // Replace the TON network with a stub in offline mode
+    if config.mode == config::Mode::Offline {
+        ton::set_backend(Box::new(ton::offline::OfflineBackend::new())).unwrap();
+        log_warn!("Running in offline mode, nothing is sent to the TON network");
+    }
+
+    // Select the signer, fetching the public key of a KMS key
+    if let Err(err) = ton::signer::init().await {
+        panic!("[ FATAL ] Configuration Error: {}", err);
+    }
+
+    // Connect to the database and start the background tasks, a read-only replica leaves them to the primary
+    let pool = db::connect().await;
+    bus::start();
+    if config.role == config::InstanceRole::Primary {
+        actix_web::rt::spawn(leader::run(pool.clone()));
+        actix_web::rt::spawn(indexer::run(pool.clone()));
+        actix_web::rt::spawn(alerts::run(pool.clone()));
+        actix_web::rt::spawn(outbox::run(pool.clone()));
+        actix_web::rt::spawn(notify::webhooks::run(pool.clone()));
+        actix_web::rt::spawn(deposits::run(pool.clone()));
+        actix_web::rt::spawn(jobs::run(pool.clone()));
+        actix_web::rt::spawn(scheduler::run(pool.clone()));
+        actix_web::rt::spawn(policy::run(pool.clone()));
+        #[cfg(feature = "telegram")]
+        actix_web::rt::spawn(telegram::run(pool.clone()));
+    } else {
+        log_warn!("Running as a read-only replica, sends and background tasks are left to the primary");
+    }
+    actix_web::rt::spawn(metrics::run());
+    actix_web::rt::spawn(reload_on_hangup());
+    let pool_data = web::Data::new(pool);
 
-    println!("[ INFO ] Http server is starting on port {:?}", port);
+    log_info!("Http server is starting on port {:?}", port);
 
     // Create and run the HTTP server
-    HttpServer::new(|| {
+    HttpServer::new(move || {
         App::new()
+            .app_data(pool_data.clone()) // Share the database pool with handlers
+            .app_data(web::JsonConfig::default().limit(json_limit)) // Limit JSON payload size
             .wrap(
                 // Configure CORS
                 Cors::default()
-                .allowed_origin("http://localhost:5173")
-                .allowed_origin("http://localhost:3001")
+                .allowed_origin_fn(| origin, _ | {
+                    config::runtime().cors_origins.iter().any(| allowed | allowed.as_bytes() == origin.as_bytes())
+                })
                 .allowed_methods(vec![
                     "GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS",
                 ])
                 .allowed_headers(vec![
                     actix_web::http::header::CONTENT_TYPE,
+                    actix_web::http::header::AUTHORIZATION,
+                    actix_web::http::header::HeaderName::from_static(deadline::DEADLINE_HEADER),
                 ])
             )
+            .wrap(from_fn(auth::network::restrict_writes)) // Reject writes from outside `WRITE_ALLOWED_CIDRS`
+            // The last middleware registered runs first, so `warnings::collect` wraps `deadline::enforce`
+            // and also collects the warnings of clamped deadlines and the 504 responses
+            .wrap(from_fn(deadline::enforce)) // Answer requests exceeding their deadline with a 504 error
+            .wrap(from_fn(warnings::collect)) // Add the warnings of the services to JSON responses
             .wrap(Compress::default()) // Enable compression
-            .service(routes::new()) // Add routes
+            .wrap(from_fn(panics::catch_panic)) // Answer panicking handlers with a 500 error
+            .wrap(from_fn(logging::access_log)) // Log every request with its id, route, status and latency
+            .service(routes::new()) // Add versioned routes
+            .service(routes::legacy()) // Add deprecated unversioned routes
+            .service(routes::admin()) // Add admin routes
+            .service(routes::probes()) // Add probes, last as their scope matches every path
     })
-    .workers(num_cpus::get() * 2) // Set number of workers to twice the number of CPU cores
+    .workers(config.workers) // Set number of workers (twice the number of CPU cores by default)
+    .max_connections(config.max_connections)
+    .max_connection_rate(config.max_connection_rate)
+    .keep_alive(Duration::from_secs(config.keep_alive))
+    .client_request_timeout(Duration::from_millis(config.client_request_timeout))
+    .client_disconnect_timeout(Duration::from_millis(config.client_disconnect_timeout))
     .bind(("0.0.0.0", port)) // Bind to all interfaces on the specified port
     .unwrap()
     .run()
     .await
 }
+
+/// Reloads the non-critical configuration every time the process receives SIGHUP.
+async fn reload_on_hangup() {
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(err) => {
+                log_error!("Can not listen for SIGHUP: {}", err);
+                return;
+            }
+        };
+
+        while hangup.recv().await.is_some() {
+            match config::reload() {
+                Ok(_) => log_info!("Configuration reloaded on SIGHUP"),
+                Err(err) => log_error!("Can not reload the configuration: {}", err)
+            }
+        }
+    }
+}
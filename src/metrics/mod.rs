@@ -0,0 +1,392 @@
+//! # Metrics
+//!
+//! This module holds the process-wide counters of the application and renders them
+//! in the Prometheus text format for `GET /metrics`. Every route gets its own latency
+//! histogram with estimated p50/p95/p99 and error counters by status class, so SLOs
+//! can be defined on expensive routes like `/spread` separately from cheap reads.
+//! Calls to the TON network are counted and timed per RPC type and liteserver, so a
+//! degraded liteserver stands out from the others. The balances of the gas wallet and the
+//! mixer contract and the wallet seqno are refreshed as gauges by `run`, the state of the
+//! job queue by the job runner.
+
+use std::{collections::BTreeMap, fmt::Write, sync::{atomic::{AtomicU64, Ordering}, Mutex}, time::Duration};
+
+use crate::{config, ton, types::jobs::JobQueueStats};
+
+/// Upper bounds of the latency histogram buckets in milliseconds.
+const LATENCY_BUCKETS: [f64; 12] = [5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0, 30000.0];
+
+/// Quantiles reported for every histogram.
+const QUANTILES: [(f64, &str); 3] = [(0.5, "0.5"), (0.95, "0.95"), (0.99, "0.99")];
+
+/// Interval between gauge refreshes in seconds, used when `METRICS_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 30;
+
+/// Names and help texts of the gauges refreshed by `run`.
+const GAUGES_HELP: [(&str, &str); 5] = [
+    ("ton_wallet_balance_nanotons", "Balance of the gas wallet in nanotons."),
+    ("ton_contract_balance_nanotons", "Balance of the mixer contract in nanotons."),
+    ("ton_wallet_seqno", "Current seqno of the gas wallet."),
+    ("ton_gauges_refreshed_timestamp_seconds", "Unix time the gauges were last refreshed."),
+    ("ton_backend_degraded", "Whether calls are served by the toncenter HTTP fallback instead of liteservers.")
+];
+
+/// Route label of requests that matched no route, so unknown paths can not grow the label set.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Number of panics caught while handling requests.
+static PANICS: AtomicU64 = AtomicU64::new(0);
+
+/// Metrics of every route, keyed by method and route pattern.
+static ROUTES: Mutex<BTreeMap<(String, String), RouteMetrics>> = Mutex::new(BTreeMap::new());
+
+/// Metrics of every TON RPC, keyed by liteserver and RPC type.
+static RPCS: Mutex<BTreeMap<(String, &'static str), RpcMetrics>> = Mutex::new(BTreeMap::new());
+
+/// Last values of the gauges, missing until the first successful refresh.
+static GAUGES: Mutex<BTreeMap<&'static str, f64>> = Mutex::new(BTreeMap::new());
+
+/// State of the job queue per kind, as of the last runner pass.
+static JOB_QUEUE: Mutex<Vec<JobQueueStats>> = Mutex::new(Vec::new());
+
+/// Number of liteserver set failovers, keyed by the set failed over from, to and the reason.
+static FAILOVERS: Mutex<BTreeMap<(String, String, &'static str), u64>> = Mutex::new(BTreeMap::new());
+
+/// Number of jobs executed by this instance, keyed by kind and outcome.
+static JOB_OUTCOMES: Mutex<BTreeMap<(String, &'static str), u64>> = Mutex::new(BTreeMap::new());
+
+/// Represents a histogram with fixed buckets.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    /// Number of observations per bucket of `LATENCY_BUCKETS`, the last one counts the rest.
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    count: u64,
+    sum: f64
+}
+
+impl Histogram {
+    /// Records an observation.
+    pub fn observe(&mut self, value: f64) {
+        let bucket: usize = LATENCY_BUCKETS.iter().position(| bound | value <= *bound).unwrap_or(LATENCY_BUCKETS.len());
+
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// Estimates a quantile by interpolating linearly within the bucket it falls in.
+    ///
+    /// # Arguments
+    ///
+    /// * `q` - The quantile, between `0` and `1`.
+    ///
+    /// # Returns
+    ///
+    /// The estimated value, `0` without observations. Quantiles in the overflow bucket
+    /// are reported as its lower bound.
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+
+        let rank: f64 = q * self.count as f64;
+        let mut seen: u64 = 0;
+
+        for (i, count) in self.buckets.iter().enumerate() {
+            if *count > 0 && (seen + count) as f64 >= rank {
+                let lower: f64 = if i == 0 { 0.0 } else { LATENCY_BUCKETS[i - 1] };
+                let Some(upper) = LATENCY_BUCKETS.get(i) else {
+                    return lower;
+                };
+
+                return lower + (upper - lower) * ((rank - seen as f64) / *count as f64).clamp(0.0, 1.0);
+            }
+            seen += count;
+        }
+
+        LATENCY_BUCKETS[LATENCY_BUCKETS.len() - 1]
+    }
+
+    /// Writes the histogram in the Prometheus text format.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The output.
+    /// * `name` - The metric name, without the `_bucket`, `_sum` and `_count` suffixes.
+    /// * `labels` - Rendered labels of the series, like `route="/v1/mixer/spread"`.
+    pub fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative: u64 = 0;
+
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.buckets.iter()) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, cumulative);
+        }
+
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+
+    /// Writes the estimated quantiles of the histogram as gauges in the Prometheus text format.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - The output.
+    /// * `name` - The metric name.
+    /// * `labels` - Rendered labels of the series.
+    pub fn render_quantiles(&self, out: &mut String, name: &str, labels: &str) {
+        for (q, label) in QUANTILES {
+            let _ = writeln!(out, "{}{{{},quantile=\"{}\"}} {}", name, labels, label, self.quantile(q));
+        }
+    }
+}
+
+/// Represents the metrics of one route.
+#[derive(Debug, Clone, Default)]
+struct RouteMetrics {
+    latency: Histogram,
+    /// Number of error responses per status class, `4xx` or `5xx`.
+    errors: BTreeMap<&'static str, u64>
+}
+
+/// Represents the metrics of one RPC type of one liteserver.
+#[derive(Debug, Clone, Default)]
+struct RpcMetrics {
+    latency: Histogram,
+    errors: u64
+}
+
+/// Counts a panic caught while handling a request.
+pub fn inc_panics() {
+    PANICS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the number of panics caught while handling requests.
+pub fn panics() -> u64 {
+    PANICS.load(Ordering::Relaxed)
+}
+
+/// Records a handled request.
+///
+/// # Arguments
+///
+/// * `method` - The HTTP method.
+/// * `route` - The matched route pattern, `None` if no route matched.
+/// * `status` - The response status code.
+/// * `latency_ms` - The time spent handling the request in milliseconds.
+pub fn observe_request(method: &str, route: Option<&str>, status: u16, latency_ms: f64) {
+    let key: (String, String) = (method.to_string(), route.unwrap_or(UNMATCHED_ROUTE).to_string());
+    let mut routes = ROUTES.lock().unwrap();
+    let metrics: &mut RouteMetrics = routes.entry(key).or_default();
+
+    metrics.latency.observe(latency_ms);
+
+    let class: Option<&'static str> = match status {
+        400..=499 => Some("4xx"),
+        500..=599 => Some("5xx"),
+        _ => None
+    };
+    if let Some(class) = class {
+        *metrics.errors.entry(class).or_insert(0) += 1;
+    }
+}
+
+/// Records a call to the TON network.
+///
+/// # Arguments
+///
+/// * `liteserver` - The name of the backend that served the call.
+/// * `rpc` - The RPC type, like `seqno`, `run_get_method` or `send_raw_message`.
+/// * `ok` - Whether the call succeeded.
+/// * `latency_ms` - The duration of the call in milliseconds.
+pub fn observe_rpc(liteserver: &str, rpc: &'static str, ok: bool, latency_ms: f64) {
+    let mut rpcs = RPCS.lock().unwrap();
+    let metrics: &mut RpcMetrics = rpcs.entry((liteserver.to_string(), rpc)).or_default();
+
+    metrics.latency.observe(latency_ms);
+    if !ok {
+        metrics.errors += 1;
+    }
+}
+
+/// Counts a failover between liteserver sets.
+///
+/// # Arguments
+///
+/// * `from` - The name of the set that failed.
+/// * `to` - The name of the set that took over.
+/// * `reason` - Why the set failed, `errors`, `latency` or `connect`.
+pub fn observe_failover(from: &str, to: &str, reason: &'static str) {
+    *FAILOVERS.lock().unwrap().entry((from.to_string(), to.to_string(), reason)).or_insert(0) += 1;
+}
+
+/// Sets the value of a gauge.
+pub fn set_gauge(name: &'static str, value: f64) {
+    GAUGES.lock().unwrap().insert(name, value);
+}
+
+/// Replaces the state of the job queue.
+pub fn set_job_queue(stats: Vec<JobQueueStats>) {
+    *JOB_QUEUE.lock().unwrap() = stats;
+}
+
+/// Counts a job executed by this instance.
+///
+/// # Arguments
+///
+/// * `kind` - The kind of the job.
+/// * `outcome` - The status the job finished with, `done` or `failed`, or `deferred`.
+pub fn observe_job(kind: &str, outcome: &'static str) {
+    *JOB_OUTCOMES.lock().unwrap().entry((kind.to_string(), outcome)).or_insert(0) += 1;
+}
+
+/// Runs the gauge refresh loop forever.
+///
+/// The interval is configured with `METRICS_INTERVAL` in seconds. A gauge keeps its
+/// previous value when it can not be fetched, the refresh timestamp shows how old it is.
+pub async fn run() {
+    let interval: u64 = config::env_or("METRICS_INTERVAL", DEFAULT_INTERVAL);
+
+    loop {
+        match ton::get_balance(&ton::wallet_address()).await {
+            Ok(balance) => set_gauge("ton_wallet_balance_nanotons", balance as f64),
+            Err(err) => log_warn!("Can not refresh the gas wallet balance gauge: {}", err)
+        }
+
+        match ton::get_balance(&ton::mixer_contract_address()).await {
+            Ok(balance) => set_gauge("ton_contract_balance_nanotons", balance as f64),
+            Err(err) => log_warn!("Can not refresh the mixer contract balance gauge: {}", err)
+        }
+
+        match ton::wallet_seqno().await {
+            Ok(seqno) => set_gauge("ton_wallet_seqno", seqno as f64),
+            Err(err) => log_warn!("Can not refresh the wallet seqno gauge: {}", err)
+        }
+
+        set_gauge("ton_gauges_refreshed_timestamp_seconds", ton::time_now() as f64);
+
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Escapes a label value for the Prometheus text format.
+pub fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Renders all metrics in the Prometheus text format.
+pub fn render() -> String {
+    let mut out: String = String::new();
+    let routes = ROUTES.lock().unwrap().clone();
+
+    out.push_str("# HELP http_request_duration_ms Time spent handling requests in milliseconds.\n");
+    out.push_str("# TYPE http_request_duration_ms histogram\n");
+    for ((method, route), metrics) in routes.iter() {
+        let labels: String = format!("method=\"{}\",route=\"{}\"", escape_label(method), escape_label(route));
+        metrics.latency.render(&mut out, "http_request_duration_ms", &labels);
+    }
+
+    out.push_str("# HELP http_request_duration_ms_quantile Estimated p50/p95/p99 request latency in milliseconds.\n");
+    out.push_str("# TYPE http_request_duration_ms_quantile gauge\n");
+    for ((method, route), metrics) in routes.iter() {
+        let labels: String = format!("method=\"{}\",route=\"{}\"", escape_label(method), escape_label(route));
+        metrics.latency.render_quantiles(&mut out, "http_request_duration_ms_quantile", &labels);
+    }
+
+    out.push_str("# HELP http_request_errors_total Error responses by status class.\n");
+    out.push_str("# TYPE http_request_errors_total counter\n");
+    for ((method, route), metrics) in routes.iter() {
+        for (class, count) in metrics.errors.iter() {
+            let _ = writeln!(
+                out,
+                "http_request_errors_total{{method=\"{}\",route=\"{}\",class=\"{}\"}} {}",
+                escape_label(method), escape_label(route), class, count
+            );
+        }
+    }
+
+    out.push_str("# HELP http_panics_total Panics caught while handling requests.\n");
+    out.push_str("# TYPE http_panics_total counter\n");
+    let _ = writeln!(out, "http_panics_total {}", panics());
+
+    render_rpcs(&mut out);
+    render_gauges(&mut out);
+    render_jobs(&mut out);
+
+    out
+}
+
+/// Renders the TON RPC metrics in the Prometheus text format.
+fn render_rpcs(out: &mut String) {
+    let rpcs = RPCS.lock().unwrap().clone();
+    let labels = | liteserver: &str, rpc: &str | format!("liteserver=\"{}\",rpc=\"{}\"", escape_label(liteserver), rpc);
+
+    out.push_str("# HELP ton_rpc_duration_ms Duration of TON RPCs in milliseconds.\n");
+    out.push_str("# TYPE ton_rpc_duration_ms histogram\n");
+    for ((liteserver, rpc), metrics) in rpcs.iter() {
+        metrics.latency.render(out, "ton_rpc_duration_ms", &labels(liteserver, rpc));
+    }
+
+    out.push_str("# HELP ton_rpc_duration_ms_quantile Estimated p50/p95/p99 TON RPC duration in milliseconds.\n");
+    out.push_str("# TYPE ton_rpc_duration_ms_quantile gauge\n");
+    for ((liteserver, rpc), metrics) in rpcs.iter() {
+        metrics.latency.render_quantiles(out, "ton_rpc_duration_ms_quantile", &labels(liteserver, rpc));
+    }
+
+    out.push_str("# HELP ton_rpc_requests_total TON RPCs made.\n");
+    out.push_str("# TYPE ton_rpc_requests_total counter\n");
+    for ((liteserver, rpc), metrics) in rpcs.iter() {
+        let _ = writeln!(out, "ton_rpc_requests_total{{{}}} {}", labels(liteserver, rpc), metrics.latency.count);
+    }
+
+    out.push_str("# HELP ton_rpc_errors_total Failed TON RPCs.\n");
+    out.push_str("# TYPE ton_rpc_errors_total counter\n");
+    for ((liteserver, rpc), metrics) in rpcs.iter() {
+        let _ = writeln!(out, "ton_rpc_errors_total{{{}}} {}", labels(liteserver, rpc), metrics.errors);
+    }
+
+    out.push_str("# HELP ton_liteserver_failovers_total Failovers between liteserver sets.\n");
+    out.push_str("# TYPE ton_liteserver_failovers_total counter\n");
+    for ((from, to, reason), count) in FAILOVERS.lock().unwrap().iter() {
+        let _ = writeln!(out, "ton_liteserver_failovers_total{{from=\"{}\",to=\"{}\",reason=\"{}\"}} {}", escape_label(from), escape_label(to), reason, count);
+    }
+}
+
+/// Renders the refreshed gauges in the Prometheus text format.
+fn render_gauges(out: &mut String) {
+    let gauges = GAUGES.lock().unwrap().clone();
+
+    for (name, help) in GAUGES_HELP {
+        if let Some(value) = gauges.get(name) {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+    }
+}
+
+/// Renders the job queue metrics in the Prometheus text format.
+fn render_jobs(out: &mut String) {
+    let queue: Vec<JobQueueStats> = JOB_QUEUE.lock().unwrap().clone();
+    let gauges: [(&str, &str, fn(&JobQueueStats) -> i64); 5] = [
+        ("jobs_pending", "Pending jobs, due or not.", | s | s.pending),
+        ("jobs_due", "Pending jobs whose run time has come, the backlog.", | s | s.due),
+        ("jobs_running", "Jobs claimed by a runner and not finished yet.", | s | s.running),
+        ("jobs_oldest_due_age_seconds", "Seconds the oldest due job has been waiting.", | s | s.oldest_due_age),
+        ("jobs_retries", "Attempts beyond the first of all jobs.", | s | s.retries)
+    ];
+
+    for (name, help, value) in gauges {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for stats in queue.iter() {
+            let _ = writeln!(out, "{}{{kind=\"{}\"}} {}", name, escape_label(&stats.kind), value(stats));
+        }
+    }
+
+    out.push_str("# HELP jobs_executed_total Jobs executed by this instance by outcome.\n");
+    out.push_str("# TYPE jobs_executed_total counter\n");
+    for ((kind, outcome), count) in JOB_OUTCOMES.lock().unwrap().iter() {
+        let _ = writeln!(out, "jobs_executed_total{{kind=\"{}\",outcome=\"{}\"}} {}", escape_label(kind), outcome, count);
+    }
+}
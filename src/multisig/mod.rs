@@ -0,0 +1,223 @@
+//! # Multisig
+//!
+//! This module talks to a TON multisig v2 wallet configured with `MULTISIG_ADDRESS`, so
+//! collects are executed by the multisig once k of its n signers approved them instead of
+//! by the hot wallet alone. The hot wallet only proposes orders; it is a proposer, or a
+//! signer whose proposal counts as its approval, at `MULTISIG_INDEX` of the respective list.
+
+use std::str::FromStr;
+
+use num_bigint::{BigInt, BigUint};
+use sqlx::PgPool;
+use tonlib::{address::TonAddress, cell::{ArcCell, Cell, CellBuilder}, message::TransferMessage, types::TvmStackEntry};
+
+use crate::{config, db, ton};
+
+/// Opcode of a message creating a new order.
+const NEW_ORDER: u32 = 0xf718510f;
+
+/// Opcode of a message approving an order.
+const APPROVE: u32 = 0xa762230f;
+
+/// Opcode of the order action sending a message from the multisig.
+const ACTION_SEND_MESSAGE: u32 = 0xf1381e5b;
+
+/// Seconds an order can be approved in, used when `MULTISIG_ORDER_TTL` is not set.
+const DEFAULT_ORDER_TTL: u64 = 86400;
+
+/// Nanotons attached to a new order, paying for the deployment of the order contract.
+const DEFAULT_ORDER_GAS: u64 = 200000000;
+
+/// Nanotons attached to an approval.
+pub const APPROVE_GAS: u64 = 50000000;
+
+/// Represents the multisig setup of the hot wallet.
+#[derive(Debug, Clone)]
+pub struct MultisigConfig {
+    pub address: TonAddress,
+    /// Whether the hot wallet is a signer, otherwise it is a proposer.
+    pub signer: bool,
+    /// Index of the hot wallet in the signers or proposers.
+    pub index: u8,
+    pub order_ttl: u64,
+    pub order_gas: u64
+}
+
+impl MultisigConfig {
+    /// Loads the multisig setup from the environment, `None` when `MULTISIG_ADDRESS` is not set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `MULTISIG_ADDRESS` is not an address.
+    pub fn from_env() -> Option<MultisigConfig> {
+        let address: String = config::var("MULTISIG_ADDRESS").ok().filter(| a | !a.is_empty())?;
+        let address: TonAddress = match TonAddress::from_str(&address) {
+            Ok(address) => address,
+            Err(_) => panic!("[ FATAL ] Configuration Error: `MULTISIG_ADDRESS` has an invalid address `{}`", address)
+        };
+
+        Some(MultisigConfig {
+            address,
+            signer: config::env_or("MULTISIG_SIGNER", false),
+            index: config::env_or("MULTISIG_INDEX", 0),
+            order_ttl: config::env_or("MULTISIG_ORDER_TTL", DEFAULT_ORDER_TTL),
+            order_gas: config::env_or("MULTISIG_ORDER_GAS", DEFAULT_ORDER_GAS)
+        })
+    }
+}
+
+/// Returns whether collects are routed through a multisig.
+pub fn enabled() -> bool {
+    config::var("MULTISIG_ADDRESS").map(| a | !a.is_empty()).unwrap_or(false)
+}
+
+/// Represents the state of an order contract.
+#[derive(Debug, Clone)]
+pub struct OrderData {
+    pub threshold: u32,
+    /// Whether the order reached its threshold and was sent to the multisig for execution.
+    pub executed: bool,
+    /// Indices of the signers that approved the order.
+    pub approved_by: Vec<u8>,
+    pub expires_at: u64
+}
+
+/// Builds an order with a single action sending a message from the multisig.
+///
+/// The order is a dictionary of actions with 8-bit keys; with only the key `0` its root
+/// is a single leaf whose label is eight zero bits in the `hml_same` form.
+///
+/// # Arguments
+///
+/// * `destination` - The recipient of the message.
+/// * `amount` - The nanotons attached to the message, paid by the multisig.
+/// * `body` - The body of the message.
+/// * `mode` - The send mode of the message, `types::DEFAULT_SEND_MODE` unless a caller chose another one.
+pub fn single_message_order(destination: &TonAddress, amount: u64, body: Cell, mode: u8) -> Result<Cell, String> {
+    let mut message: TransferMessage = TransferMessage::new(destination, &BigUint::from(amount));
+    message.with_data(body);
+    let message: Cell = message.build().map_err(|e| e.to_string())?;
+
+    let mut action: CellBuilder = CellBuilder::new();
+    action.store_u32(32, ACTION_SEND_MESSAGE).map_err(|e| e.to_string())?;
+    action.store_u8(8, mode).map_err(|e| e.to_string())?;
+    action.store_reference(&ArcCell::new(message)).map_err(|e| e.to_string())?;
+    let action: Cell = action.build().map_err(|e| e.to_string())?;
+
+    let mut root: CellBuilder = CellBuilder::new();
+    root.store_u8(2, 0b11).map_err(|e| e.to_string())?; //hml_same
+    root.store_bit(false).map_err(|e| e.to_string())?; //repeated bit
+    root.store_u8(4, 8).map_err(|e| e.to_string())?; //label length
+    root.store_reference(&ArcCell::new(action)).map_err(|e| e.to_string())?;
+
+    root.build().map_err(|e| e.to_string())
+}
+
+/// Builds the body of a message creating an order.
+///
+/// # Arguments
+///
+/// * `setup` - The multisig setup of the hot wallet.
+/// * `query_id` - The query id stored in the body.
+/// * `order_seqno` - The seqno of the new order.
+/// * `expires_at` - Unix time after which the order can no longer be approved.
+/// * `order` - The actions of the order.
+pub fn new_order_body(setup: &MultisigConfig, query_id: u64, order_seqno: u64, expires_at: u64, order: Cell) -> Result<Cell, String> {
+    let mut builder: CellBuilder = CellBuilder::new();
+    builder.store_u32(32, NEW_ORDER).map_err(|e| e.to_string())?;
+    builder.store_u64(64, query_id).map_err(|e| e.to_string())?;
+    builder.store_uint(256, &BigUint::from(order_seqno)).map_err(|e| e.to_string())?;
+    builder.store_bit(setup.signer).map_err(|e| e.to_string())?;
+    builder.store_u8(8, setup.index).map_err(|e| e.to_string())?;
+    builder.store_u64(48, expires_at).map_err(|e| e.to_string())?;
+    builder.store_reference(&ArcCell::new(order)).map_err(|e| e.to_string())?;
+
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Builds the body of a message approving an order, sent by the signer to the order contract.
+pub fn approve_body(query_id: u64, signer_index: u8) -> Result<Cell, String> {
+    let mut builder: CellBuilder = CellBuilder::new();
+    builder.store_u32(32, APPROVE).map_err(|e| e.to_string())?;
+    builder.store_u64(64, query_id).map_err(|e| e.to_string())?;
+    builder.store_u8(8, signer_index).map_err(|e| e.to_string())?;
+
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Returns the expiration time of an order created now.
+pub fn order_expiry(setup: &MultisigConfig) -> u64 {
+    ton::time_now() + setup.order_ttl
+}
+
+/// Allocates the seqno of a new order of the multisig.
+///
+/// The seqno is at least the one the multisig reports and is handed out once, so orders
+/// proposed concurrently, before the previous one reached the chain, get consecutive seqnos.
+/// Give it back with `release_order_seqno` if the order is not sent.
+pub async fn allocate_order_seqno(pool: &PgPool, multisig: &TonAddress) -> Result<u64, String> {
+    let onchain: u64 = next_order_seqno(multisig).await?;
+    let onchain: i64 = i64::try_from(onchain).map_err(|e| e.to_string())?;
+
+    db::multisig::allocate_order_seqno(pool, &multisig.to_base64_url(), onchain).await
+        .map(| seqno | seqno as u64)
+        .map_err(|e| format!("can not allocate an order seqno: {}", e))
+}
+
+/// Gives back the seqno of an order that was not sent.
+pub async fn release_order_seqno(pool: &PgPool, multisig: &TonAddress, order_seqno: u64) {
+    if let Err(err) = db::multisig::release_order_seqno(pool, &multisig.to_base64_url(), order_seqno as i64).await {
+        log_error!("Can not release order seqno {} of multisig {}: {:?}", order_seqno, multisig, err);
+    }
+}
+
+/// Fetches the seqno the next order of the multisig must have on chain.
+async fn next_order_seqno(multisig: &TonAddress) -> Result<u64, String> {
+    let stack: Vec<TvmStackEntry> = ton::backend().await.run_get_method(multisig, "get_multisig_data", Vec::new()).await?;
+
+    match stack.first() {
+        Some(entry) => u64::try_from(entry.get_bigint().map_err(|e| e.to_string())?).map_err(|e| e.to_string()),
+        None => Err(format!("get_multisig_data of {} returned an empty stack", multisig))
+    }
+}
+
+/// Resolves the address of the order contract with a seqno.
+pub async fn order_address(multisig: &TonAddress, order_seqno: u64) -> Result<TonAddress, String> {
+    let stack: Vec<TvmStackEntry> = ton::backend().await
+        .run_get_method(multisig, "get_order_address", vec![TvmStackEntry::Int257(BigInt::from(order_seqno))])
+        .await?;
+
+    match stack.first() {
+        Some(entry) => entry.get_address().map_err(|e| e.to_string()),
+        None => Err(format!("get_order_address of {} returned an empty stack", multisig))
+    }
+}
+
+/// Fetches the state of an order contract.
+///
+/// # Returns
+///
+/// The state, or `None` if the order contract is not deployed.
+pub async fn order_data(order: &TonAddress) -> Result<Option<OrderData>, String> {
+    if !ton::is_account_active(order).await? {
+        return Ok(None);
+    }
+
+    let stack: Vec<TvmStackEntry> = ton::backend().await.run_get_method(order, "get_order_data", Vec::new()).await?;
+    let int = | index: usize | -> Result<BigInt, String> {
+        stack.get(index)
+            .ok_or_else(|| format!("get_order_data of {} returned {} stack entries", order, stack.len()))?
+            .get_bigint()
+            .map_err(|e| e.to_string())
+    };
+
+    let mask: BigInt = int(5)?;
+    let approved_by: Vec<u8> = (0..=255u8).filter(| i | mask.bit(*i as u64)).collect();
+
+    Ok(Some(OrderData {
+        threshold: u32::try_from(int(2)?).map_err(|e| e.to_string())?,
+        executed: int(3)? != BigInt::from(0),
+        approved_by,
+        expires_at: u64::try_from(int(7)?).map_err(|e| e.to_string())?
+    }))
+}
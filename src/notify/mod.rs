@@ -0,0 +1,240 @@
+//! # Notifications
+//!
+//! This module delivers operational notifications (alerts, confirmations) to the channels
+//! configured in the environment: a generic webhook, a Telegram chat, email, Slack and Discord.
+//! Each event type can be routed to a subset of the channels through the admin API.
+//! Webhook deliveries are signed when the endpoint has a shared secret, see `signature`, and
+//! notifications are also queued for the webhook subscriptions of the admin API, see `webhooks`.
+
+pub mod signature;
+pub mod webhooks;
+
+use lettre::{message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{config, db, logging::trace};
+
+/// Represents a notification sent to the configured channels.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Notification {
+    pub event: String,
+    pub message: String,
+    pub data: Value
+}
+
+impl Notification {
+    /// Creates a new Notification instance.
+    pub fn new(event: &str, message: String, data: Value) -> Self {
+        Notification {
+            event: event.to_string(),
+            message,
+            data
+        }
+    }
+}
+
+/// Represents a channel notifications are delivered to.
+#[derive(Debug, Clone)]
+pub enum Channel {
+    Webhook {
+        url: String,
+        /// Secret shared with the endpoint deliveries are signed with, if any.
+        secret: Option<String>
+    },
+    Telegram {
+        token: String,
+        chat_id: String
+    },
+    Email {
+        host: String,
+        username: String,
+        password: String,
+        from: String,
+        to: String
+    },
+    Slack {
+        url: String
+    },
+    Discord {
+        url: String
+    }
+}
+
+impl Channel {
+    /// Returns the name the channel is referred to in notification routes.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Webhook { .. } => "webhook",
+            Channel::Telegram { .. } => "telegram",
+            Channel::Email { .. } => "email",
+            Channel::Slack { .. } => "slack",
+            Channel::Discord { .. } => "discord"
+        }
+    }
+}
+
+/// Delivers notifications to the configured channels.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    pub channels: Vec<Channel>,
+    client: reqwest::Client,
+    pool: PgPool
+}
+
+impl Notifier {
+    /// Creates a notifier with the channels configured in the environment.
+    ///
+    /// * `NOTIFY_WEBHOOK_URL` enables the webhook channel, signed with `NOTIFY_WEBHOOK_SECRET` if set.
+    /// * `NOTIFY_TELEGRAM_TOKEN` and `NOTIFY_TELEGRAM_CHAT_ID` enable the Telegram channel.
+    /// * `NOTIFY_SMTP_HOST`, `NOTIFY_SMTP_USERNAME`, `NOTIFY_SMTP_PASSWORD`, `NOTIFY_EMAIL_FROM`
+    ///   and `NOTIFY_EMAIL_TO` enable the email channel.
+    /// * `NOTIFY_SLACK_WEBHOOK_URL` enables the Slack channel.
+    /// * `NOTIFY_DISCORD_WEBHOOK_URL` enables the Discord channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `pool` - The database connection pool the notification routes are read from.
+    pub fn from_env(pool: PgPool) -> Notifier {
+        let env = | key: &str | config::var(key).ok().filter(| v | !v.is_empty());
+        let mut channels: Vec<Channel> = Vec::new();
+
+        if let Some(url) = env("NOTIFY_WEBHOOK_URL") {
+            channels.push(Channel::Webhook { url, secret: env("NOTIFY_WEBHOOK_SECRET") });
+        }
+
+        if let (Some(token), Some(chat_id)) = (env("NOTIFY_TELEGRAM_TOKEN"), env("NOTIFY_TELEGRAM_CHAT_ID")) {
+            channels.push(Channel::Telegram { token, chat_id });
+        }
+
+        if let (Some(host), Some(username), Some(password), Some(from), Some(to)) = (
+            env("NOTIFY_SMTP_HOST"),
+            env("NOTIFY_SMTP_USERNAME"),
+            env("NOTIFY_SMTP_PASSWORD"),
+            env("NOTIFY_EMAIL_FROM"),
+            env("NOTIFY_EMAIL_TO")
+        ) {
+            channels.push(Channel::Email { host, username, password, from, to });
+        }
+
+        if let Some(url) = env("NOTIFY_SLACK_WEBHOOK_URL") {
+            channels.push(Channel::Slack { url });
+        }
+
+        if let Some(url) = env("NOTIFY_DISCORD_WEBHOOK_URL") {
+            channels.push(Channel::Discord { url });
+        }
+
+        Notifier {
+            channels,
+            client: reqwest::Client::new(),
+            pool
+        }
+    }
+
+    /// Sends a notification to the channels its event is routed to.
+    ///
+    /// Events without routes are sent to every configured channel. Delivery
+    /// failures are logged and do not stop delivery to the remaining channels.
+    /// Webhook subscriptions filter events on their own and are queued regardless of routes.
+    pub async fn send(&self, notification: &Notification) {
+        match serde_json::to_value(notification) {
+            Ok(payload) => webhooks::enqueue(&self.pool, &notification.event, &payload).await,
+            Err(err) => log_error!("Can not serialize notification `{}`: {}", notification.event, err)
+        }
+
+        let routes: Vec<String> = match db::notifications::channels_for(&self.pool, &notification.event).await {
+            Ok(routes) => routes,
+            Err(err) => {
+                log_error!("Can not load notification routes, sending to all channels: {:?}", err);
+                Vec::new()
+            }
+        };
+
+        for channel in self.channels.iter() {
+            if !routes.is_empty() && !routes.iter().any(| r | r == channel.name()) {
+                continue;
+            }
+
+            if let Err(err) = self.send_to(channel, notification).await {
+                log_error!("Notification `{}` delivery failed: {}", notification.event, err);
+            }
+        }
+    }
+
+    /// Sends a notification to a single channel.
+    async fn send_to(&self, channel: &Channel, notification: &Notification) -> Result<(), String> {
+        match channel {
+            Channel::Webhook { url, secret } => {
+                // the signature covers the exact bytes sent
+                let body: Vec<u8> = serde_json::to_vec(notification).map_err(|e| e.to_string())?;
+                let mut request: reqwest::RequestBuilder = trace::inject(self.client.post(url))
+                    .header(reqwest::header::CONTENT_TYPE, "application/json");
+
+                if let Some(secret) = secret {
+                    for (name, value) in signature::sign(secret, &body).headers() {
+                        request = request.header(name, value);
+                    }
+                }
+
+                request.body(body)
+                    .send()
+                    .await
+                    .and_then(| r | r.error_for_status())
+                    .map_err(|e| e.to_string())?;
+            },
+            Channel::Telegram { token, chat_id } => {
+                self.client.post(format!("https://api.telegram.org/bot{}/sendMessage", token))
+                    .json(&serde_json::json!({
+                        "chat_id": chat_id,
+                        "text": notification.message
+                    }))
+                    .send()
+                    .await
+                    .and_then(| r | r.error_for_status())
+                    .map_err(|e| e.to_string())?;
+            },
+            Channel::Email { host, username, password, from, to } => {
+                let from: Mailbox = from.parse().map_err(| e: lettre::address::AddressError | e.to_string())?;
+                let to: Mailbox = to.parse().map_err(| e: lettre::address::AddressError | e.to_string())?;
+
+                let email: Message = Message::builder()
+                    .from(from)
+                    .to(to)
+                    .subject(format!("[mixer] {}", notification.event))
+                    .body(notification.message.clone())
+                    .map_err(|e| e.to_string())?;
+
+                let mailer: AsyncSmtpTransport<Tokio1Executor> = AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                    .map_err(|e| e.to_string())?
+                    .credentials(Credentials::new(username.clone(), password.clone()))
+                    .build();
+
+                mailer.send(email).await.map_err(|e| e.to_string())?;
+            },
+            Channel::Slack { url } => {
+                self.client.post(url)
+                    .json(&serde_json::json!({
+                        "text": format!("*{}*\n{}", notification.event, notification.message)
+                    }))
+                    .send()
+                    .await
+                    .and_then(| r | r.error_for_status())
+                    .map_err(|e| e.to_string())?;
+            },
+            Channel::Discord { url } => {
+                self.client.post(url)
+                    .json(&serde_json::json!({
+                        "content": format!("**{}**\n{}", notification.event, notification.message)
+                    }))
+                    .send()
+                    .await
+                    .and_then(| r | r.error_for_status())
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+}
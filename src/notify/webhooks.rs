@@ -0,0 +1,191 @@
+//! # Webhook Deliveries
+//!
+//! This module delivers events to the webhook subscriptions managed with the admin API.
+//! Every notification is queued in `webhook_deliveries` for each enabled subscription that
+//! wants its event, and a background task posts due deliveries, signed with the secret of
+//! the subscription. Failed attempts are retried with exponential backoff until
+//! `WEBHOOK_MAX_ATTEMPTS` is reached, and the outcome of every attempt stays in the log.
+//!
+//! The deliveries of a pass are attempted concurrently, so a pass takes about one
+//! `DELIVERY_TIMEOUT` and ends well within the `LEASE` of its deliveries. An outcome is only
+//! recorded while the lease is still held, so a delivery reclaimed by another instance is not
+//! counted twice.
+
+use std::time::Duration;
+
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{config, db, logging::trace, ton, types::webhooks::{WebhookDelivery, WebhookSubscription, DELIVERY_DELIVERED, DELIVERY_FAILED, DELIVERY_PENDING}};
+
+use super::signature;
+
+/// Interval between delivery passes in seconds, used when `WEBHOOK_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 5;
+
+/// Number of attempts before a delivery fails, used when `WEBHOOK_MAX_ATTEMPTS` is not set.
+const DEFAULT_MAX_ATTEMPTS: i32 = 8;
+
+/// Maximum number of deliveries claimed per pass.
+const BATCH_SIZE: i64 = 32;
+
+/// Seconds a claimed delivery is hidden from other passes while it is attempted, several
+/// times `DELIVERY_TIMEOUT` since all deliveries of a pass are attempted at once.
+const LEASE: i64 = 60;
+
+/// Delay before the first retry in seconds, doubled on every further attempt.
+const RETRY_BASE: i64 = 30;
+
+/// Longest delay between two attempts in seconds.
+const RETRY_MAX: i64 = 3600;
+
+/// Timeout of a single delivery attempt.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Event type of deliveries sent with the test endpoint.
+pub const TEST_EVENT: &str = "webhook_test";
+
+/// Represents the outcome of a delivery attempt.
+struct Attempt {
+    response_status: Option<i32>,
+    error: Option<String>
+}
+
+/// Posts a delivery to the endpoint of its subscription.
+async fn attempt(client: &reqwest::Client, subscription: &WebhookSubscription, payload: &Value) -> Attempt {
+    let body: Vec<u8> = match serde_json::to_vec(payload) {
+        Ok(body) => body,
+        Err(err) => return Attempt { response_status: None, error: Some(err.to_string()) }
+    };
+
+    let mut request: reqwest::RequestBuilder = trace::inject(client.post(&subscription.url))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .timeout(DELIVERY_TIMEOUT);
+    for (name, value) in signature::sign(&subscription.secret, &body).headers() {
+        request = request.header(name, value);
+    }
+
+    match request.body(body).send().await {
+        Ok(response) => {
+            let status = response.status();
+            Attempt {
+                response_status: Some(status.as_u16() as i32),
+                error: (!status.is_success()).then(|| format!("endpoint answered {}", status))
+            }
+        },
+        Err(err) => Attempt { response_status: None, error: Some(err.to_string()) }
+    }
+}
+
+/// Returns the delay before the next attempt after `attempts` failed ones.
+fn backoff(attempts: i32) -> i64 {
+    let exponent: u32 = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    (RETRY_BASE << exponent).min(RETRY_MAX)
+}
+
+/// Attempts a claimed delivery and records the outcome.
+async fn deliver(pool: PgPool, client: reqwest::Client, delivery: WebhookDelivery, max_attempts: i32) {
+    let subscription: WebhookSubscription = match db::webhooks::get(&pool, delivery.subscription_id).await {
+        Ok(Some(subscription)) => subscription,
+        // removed subscriptions take their deliveries with them
+        Ok(None) => return,
+        Err(err) => {
+            log_error!("Can not load webhook subscription {}: {:?}", delivery.subscription_id, err);
+            return;
+        }
+    };
+
+    // deliveries queued before the subscription was disabled are dropped
+    let outcome: Attempt = match subscription.enabled {
+        true => attempt(&client, &subscription, &delivery.payload).await,
+        false => Attempt { response_status: None, error: Some(String::from("subscription is disabled")) }
+    };
+    let attempts: i32 = delivery.attempts + 1;
+
+    let (status, next_attempt_at): (&str, i64) = match &outcome.error {
+        None => (DELIVERY_DELIVERED, delivery.next_attempt_at),
+        Some(_) if attempts >= max_attempts || !subscription.enabled => (DELIVERY_FAILED, delivery.next_attempt_at),
+        Some(_) => (DELIVERY_PENDING, ton::time_now() as i64 + backoff(attempts))
+    };
+
+    if let Some(err) = &outcome.error {
+        log_warn!("Webhook delivery {} of `{}` to subscription {} failed on attempt {}: {}", delivery.id, delivery.event, subscription.id, attempts, err);
+    }
+
+    match db::webhooks::record_attempt(&pool, &delivery, status, next_attempt_at, outcome.response_status, outcome.error.as_deref()).await {
+        Ok(Some(_)) => (),
+        Ok(None) => log_warn!("Webhook delivery {} outlived its lease, its attempt is not recorded", delivery.id),
+        Err(err) => log_error!("Can not record webhook delivery {}: {:?}", delivery.id, err)
+    }
+}
+
+/// Queues a notification for every subscription that wants its event.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `event` - The event type of the notification.
+/// * `payload` - The notification posted to the endpoints.
+pub async fn enqueue(pool: &PgPool, event: &str, payload: &Value) {
+    if let Err(err) = db::webhooks::enqueue(pool, event, payload).await {
+        log_error!("Can not queue webhook deliveries of `{}`: {:?}", event, err);
+    }
+}
+
+/// Sends a test event to a subscription right away, whatever its event filter.
+///
+/// The attempt is recorded in the delivery log like any other, but never retried.
+///
+/// # Returns
+///
+/// The recorded delivery with the outcome of the attempt.
+pub async fn send_test(pool: &PgPool, subscription: &WebhookSubscription, payload: &Value) -> Result<WebhookDelivery, String> {
+    // due after the lease, so the background task does not pick it up meanwhile
+    let hidden_until: i64 = ton::time_now() as i64 + LEASE;
+    let delivery: WebhookDelivery = db::webhooks::insert_delivery(pool, subscription.id, TEST_EVENT, payload, hidden_until).await
+        .map_err(|e| e.to_string())?;
+
+    let outcome: Attempt = attempt(&reqwest::Client::new(), subscription, payload).await;
+    let status: &str = match outcome.error {
+        None => DELIVERY_DELIVERED,
+        Some(_) => DELIVERY_FAILED
+    };
+
+    db::webhooks::record_attempt(pool, &delivery, status, delivery.next_attempt_at, outcome.response_status, outcome.error.as_deref()).await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| String::from("the subscription was removed during the test"))
+}
+
+/// Runs the delivery loop forever.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+pub async fn run(pool: PgPool) {
+    let interval: u64 = config::env_or("WEBHOOK_INTERVAL", DEFAULT_INTERVAL);
+    let client: reqwest::Client = reqwest::Client::new();
+
+    log_info!("Webhook deliveries are running every {:?} seconds", interval);
+
+    loop {
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+
+        let max_attempts: i32 = config::env_or("WEBHOOK_MAX_ATTEMPTS", DEFAULT_MAX_ATTEMPTS).max(1);
+        let deliveries: Vec<WebhookDelivery> = match db::webhooks::claim_due(&pool, BATCH_SIZE, LEASE).await {
+            Ok(deliveries) => deliveries,
+            Err(err) => {
+                log_error!("Can not claim webhook deliveries: {:?}", err);
+                continue;
+            }
+        };
+
+        let handles: Vec<_> = deliveries.into_iter()
+            .map(| delivery | actix_web::rt::spawn(deliver(pool.clone(), client.clone(), delivery, max_attempts)))
+            .collect();
+        for handle in handles {
+            if let Err(err) = handle.await {
+                log_error!("Webhook delivery task failed: {}", err);
+            }
+        }
+    }
+}
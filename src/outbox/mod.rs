@@ -0,0 +1,109 @@
+//! # Outbox Recovery
+//!
+//! This module closes the "crashed between build and send" gap: on startup every
+//! unconfirmed outbox message that is still valid is broadcast again, and a background
+//! task keeps confirming or expiring sent messages by watching the seqno of the wallet
+//! of every account.
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use tonlib::address::TonAddress;
+
+use crate::{bus, config, db, leader, ton, types::outbox::{OutboxEntry, OUTBOX_CONFIRMED, OUTBOX_EXPIRED, OUTBOX_PENDING}};
+
+/// Interval between outbox confirmation passes in seconds, used when `OUTBOX_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 15;
+
+/// Re-broadcasts the pending messages left by a previous run, then confirms messages forever.
+///
+/// With leader election only the leader works the outbox, and an instance that becomes the
+/// leader recovers the messages its predecessor left pending first.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+pub async fn run(pool: PgPool) {
+    let interval: u64 = config::env_or("OUTBOX_INTERVAL", DEFAULT_INTERVAL);
+    let mut recovered: bool = false;
+
+    loop {
+        if !leader::is_leader() {
+            recovered = false;
+        } else if !recovered {
+            recovered = true;
+            if let Err(err) = process(&pool, true).await {
+                log_error!("Outbox recovery failed: {}", err);
+            }
+        } else if let Err(err) = process(&pool, false).await {
+            log_error!("Outbox confirmation failed: {}", err);
+        }
+
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+    }
+}
+
+/// Confirms, expires and optionally re-broadcasts unconfirmed outbox entries of every account.
+async fn process(pool: &PgPool, rebroadcast: bool) -> Result<(), String> {
+    for (name, wallet) in ton::account_addresses() {
+        if let Err(err) = process_wallet(pool, &wallet, rebroadcast).await {
+            log_error!("Outbox of account {} failed: {}", name, err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Confirms, expires and optionally re-broadcasts unconfirmed outbox entries of a wallet.
+///
+/// An entry is confirmed once the wallet seqno moved past the seqno it was signed
+/// with. Otherwise it expires when its `valid_until` passed, and is broadcast again
+/// when `rebroadcast` is set and it never made it out of the pending state. Sends that
+/// failed leave the pending state as `expired` or `unknown`, so only messages of a run
+/// that stopped between writing and broadcasting them are sent again.
+async fn process_wallet(pool: &PgPool, address: &TonAddress, rebroadcast: bool) -> Result<(), String> {
+    let wallet: String = address.to_base64_url();
+    let entries: Vec<OutboxEntry> = db::outbox::unconfirmed(pool, &wallet).await.map_err(|e| e.to_string())?;
+
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let seqno: i64 = ton::get_seqno(address).await? as i64;
+    let now: i64 = ton::time_now() as i64;
+
+    for entry in entries {
+        if entry.seqno < seqno {
+            db::outbox::set_status(pool, entry.id, OUTBOX_CONFIRMED).await.map_err(|e| e.to_string())?;
+            bus::publish(bus::EVENT_CONFIRMED, outbox_event(&entry));
+        } else if entry.valid_until <= now {
+            log_warn!("Outbox {} `{}` with seqno {} expired unconfirmed", entry.id, entry.op, entry.seqno);
+            db::outbox::set_status(pool, entry.id, OUTBOX_EXPIRED).await.map_err(|e| e.to_string())?;
+            bus::publish(bus::EVENT_EXPIRED, outbox_event(&entry));
+        } else if rebroadcast && entry.status == OUTBOX_PENDING {
+            log_info!("Re-broadcasting outbox {} `{}` with seqno {}", entry.id, entry.op, entry.seqno);
+
+            match ton::broadcast(address, &entry.boc).await {
+                Ok(hash) => db::outbox::mark_sent(pool, entry.id, &hex::encode(&hash)).await.map_err(|e| e.to_string())?,
+                Err(err) => log_error!("Re-broadcast of outbox {} failed: {}", entry.id, err)
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the bus event data of an outbox entry.
+fn outbox_event(entry: &OutboxEntry) -> Value {
+    json!({
+        "outbox_id": entry.id,
+        "op": entry.op,
+        "query_id": entry.query_id,
+        "seqno": entry.seqno,
+        "wallet": entry.wallet,
+        "message_hash": entry.message_hash,
+        "normalized_hash": entry.normalized_hash
+    })
+}
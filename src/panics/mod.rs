@@ -0,0 +1,73 @@
+//! # Panic Handling
+//!
+//! This module turns panics left in services and the TON layer into structured 500
+//! responses. `catch_panic` wraps every handler, so a panicking request is answered with
+//! a JSON `Response::error` carrying the request id instead of a dropped connection, and
+//! `install_hook` routes panic messages through the application log.
+
+use std::{any::Any, future::Future, panic::{self, AssertUnwindSafe}, pin::Pin, task::{Context, Poll}};
+
+use actix_web::{body::{EitherBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, middleware::Next, Error, HttpMessage, HttpRequest, HttpResponse};
+use serde_json::Value;
+
+use crate::{logging::RequestId, metrics, types::Response};
+
+/// Future that resolves to `Err` with the panic payload if the inner future panics while polled.
+struct CatchUnwind<F: Future> {
+    inner: Pin<Box<F>>
+}
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.inner.as_mut();
+
+        match panic::catch_unwind(AssertUnwindSafe(move || inner.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(payload))
+        }
+    }
+}
+
+/// Installs a panic hook that logs panics in the configured log format.
+pub fn install_hook() {
+    panic::set_hook(Box::new(| info | {
+        log_error!("Panic: {}", info);
+    }));
+}
+
+/// Extracts the message of a panic payload.
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+
+    String::from("unknown panic")
+}
+
+/// Middleware answering requests whose handler panicked with a 500 error.
+///
+/// The panic message is only logged, the response carries a generic message and the request id.
+pub async fn catch_panic(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let request: HttpRequest = req.request().clone();
+    let request_id: String = req.extensions().get::<RequestId>().map(| id | id.0.clone()).unwrap_or_default();
+
+    match (CatchUnwind { inner: Box::pin(next.call(req)) }).await {
+        Ok(res) => Ok(res?.map_into_left_body()),
+        Err(payload) => {
+            metrics::inc_panics();
+            log_error!("Request {} {} panicked: {}", request.method(), request.path(), panic_message(&payload));
+
+            let response: Response = Response::error(Value::String(String::from("internal server error")))
+                .with_request_id(&request_id);
+
+            Ok(ServiceResponse::new(request, HttpResponse::InternalServerError().json(response)).map_into_right_body())
+        }
+    }
+}
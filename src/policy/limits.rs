@@ -0,0 +1,203 @@
+//! # Spread Limits
+//!
+//! This module implements the limits every spread has to stay within, so a leaked API key
+//! can not drain the wallet in one request. All limits are optional and read from the
+//! environment again after every configuration reload:
+//!
+//! - `SPREAD_MIN_LEG`, `SPREAD_MAX_LEG` - bounds of a single recipient amount in TON
+//! - `SPREAD_MAX_TOTAL` - maximum total of one operation in TON
+//! - `SPREAD_MAX_LEGS` - maximum number of recipients of one operation
+//! - `SPREAD_ALLOWED_HOURS` - UTC hours spreads are accepted in, e.g. `9-17` or `22-6`
+//! - `SPREAD_MAX_JETTON_LEG`, `SPREAD_MAX_JETTON_TOTAL` - maximum amount of a jetton leg of a
+//!   mixed spread and of all legs of one jetton, in whole jettons
+//!
+//! The hours and the number of recipients apply to all legs of a mixed spread, the TON
+//! amounts to its TON legs and the jetton amounts to each of its jettons.
+//!
+//! `DAILY_WITHDRAWAL_LIMIT` caps the TON spread and collected in a rolling 24 hour window,
+//! counted in the `withdrawals` table so it holds across instances and restarts.
+
+use std::sync::{Arc, RwLock};
+
+use crate::{config, types::nanotons::Nanotons};
+
+use super::nanotons_from_env;
+
+/// Represents the limits spreads are checked against.
+#[derive(Debug, Clone, Default)]
+pub struct SpreadLimits {
+    pub min_leg: Option<Nanotons>,
+    pub max_leg: Option<Nanotons>,
+    pub max_total: Option<Nanotons>,
+    pub max_legs: Option<usize>,
+    /// First and last allowed UTC hour, both inclusive.
+    pub allowed_hours: Option<(u64, u64)>,
+    /// Maximum amount of a jetton leg, in whole jettons.
+    pub max_jetton_leg: Option<f64>,
+    /// Maximum amount of all legs of one jetton, in whole jettons.
+    pub max_jetton_total: Option<f64>
+}
+
+/// Start of the error of a spread violating a limit.
+pub const LIMIT_VIOLATED: &str = "spread limits violated";
+
+/// Returns whether an error, possibly wrapped in a response, is a violation of the spread limits.
+pub fn violated(err: &str) -> bool {
+    err.contains(LIMIT_VIOLATED)
+}
+
+/// The limits loaded from the environment and the configuration generation they were loaded in.
+static LIMITS: RwLock<Option<(u64, Arc<SpreadLimits>)>> = RwLock::new(None);
+
+impl SpreadLimits {
+    /// Loads the limits from the environment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `SPREAD_ALLOWED_HOURS` is not a range of hours.
+    pub fn from_env() -> SpreadLimits {
+        let allowed_hours: Option<(u64, u64)> = config::var("SPREAD_ALLOWED_HOURS").ok()
+            .filter(| v | !v.is_empty())
+            .map(| v | {
+                let parsed = v.split_once('-')
+                    .and_then(| (from, to) | Some((from.trim().parse::<u64>().ok()?, to.trim().parse::<u64>().ok()?)))
+                    .filter(| (from, to) | *from < 24 && *to < 24);
+
+                match parsed {
+                    Some(hours) => hours,
+                    None => panic!("[ FATAL ] Configuration Error: `SPREAD_ALLOWED_HOURS` has an invalid value `{}`", v)
+                }
+            });
+
+        SpreadLimits {
+            min_leg: nanotons_from_env("SPREAD_MIN_LEG"),
+            max_leg: nanotons_from_env("SPREAD_MAX_LEG"),
+            max_total: nanotons_from_env("SPREAD_MAX_TOTAL"),
+            max_legs: config::var("SPREAD_MAX_LEGS").ok().and_then(| v | v.parse::<usize>().ok()),
+            allowed_hours,
+            max_jetton_leg: config::var("SPREAD_MAX_JETTON_LEG").ok().and_then(| v | v.parse::<f64>().ok()),
+            max_jetton_total: config::var("SPREAD_MAX_JETTON_TOTAL").ok().and_then(| v | v.parse::<f64>().ok())
+        }
+    }
+
+    /// Returns the start of the next allowed hour, `None` if spreads are allowed at `now`.
+    pub fn next_allowed(&self, now: u64) -> Option<u64> {
+        let (from, to) = self.allowed_hours?;
+        let hour: u64 = now % 86400 / 3600;
+        let allowed: bool = if from <= to { hour >= from && hour <= to } else { hour >= from || hour <= to };
+
+        match allowed {
+            true => None,
+            false => Some(now - now % 3600 + (from + 24 - hour) % 24 * 3600)
+        }
+    }
+
+    /// Checks the recipient amounts of a spread.
+    ///
+    /// # Arguments
+    ///
+    /// * `amounts` - The amount of each recipient in nanotons.
+    /// * `now` - The current Unix time.
+    ///
+    /// # Returns
+    ///
+    /// Returns the first violated limit as an error.
+    pub fn check(&self, amounts: &[Nanotons], now: u64) -> Result<(), String> {
+        self.check_legs(amounts.len(), now)?;
+
+        for amount in amounts {
+            if let Some(min) = self.min_leg.filter(| min | amount < min) {
+                return Err(format!("recipient amount {} TON is below the minimum of {} TON", amount, min));
+            }
+
+            if let Some(max) = self.max_leg.filter(| max | amount > max) {
+                return Err(format!("recipient amount {} TON is above the maximum of {} TON", amount, max));
+            }
+        }
+
+        let total: Nanotons = amounts.iter().sum();
+        if let Some(max) = self.max_total.filter(| max | total > *max) {
+            return Err(format!("spread total {} TON is above the maximum of {} TON", total, max));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the limits that apply to every asset of a spread: the allowed hours and the
+    /// number of recipients.
+    ///
+    /// # Arguments
+    ///
+    /// * `legs` - The number of recipients of all assets.
+    /// * `now` - The current Unix time.
+    pub fn check_legs(&self, legs: usize, now: u64) -> Result<(), String> {
+        if let (Some((from, to)), Some(_)) = (self.allowed_hours, self.next_allowed(now)) {
+            return Err(format!("spreads are only accepted between {}:00 and {}:59 UTC", from, to));
+        }
+
+        if let Some(max_legs) = self.max_legs {
+            if legs > max_legs {
+                return Err(format!("a spread may have at most {} recipients", max_legs));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the amounts of the legs of one jetton of a mixed spread.
+    ///
+    /// # Arguments
+    ///
+    /// * `jetton` - The address of the jetton master, named in the error.
+    /// * `amounts` - The amount of each leg in whole jettons.
+    pub fn check_jetton(&self, jetton: &str, amounts: &[f64]) -> Result<(), String> {
+        if let Some(max) = self.max_jetton_leg {
+            if let Some(amount) = amounts.iter().find(| amount | **amount > max) {
+                return Err(format!("recipient amount {} of jetton {} is above the maximum of {}", amount, jetton, max));
+            }
+        }
+
+        let total: f64 = amounts.iter().sum();
+        if let Some(max) = self.max_jetton_total.filter(| max | total > *max) {
+            return Err(format!("spread total {} of jetton {} is above the maximum of {}", total, jetton, max));
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the configured spread limits, loading them again if the configuration was reloaded.
+///
+/// # Panics
+///
+/// Panics if the limits were never loaded and `SPREAD_ALLOWED_HOURS` is invalid. Invalid
+/// values after a reload are logged and the previous limits stay in effect.
+pub fn spread_limits() -> Arc<SpreadLimits> {
+    let generation: u64 = config::generation();
+
+    if let Some((loaded, limits)) = LIMITS.read().unwrap().as_ref() {
+        if *loaded == generation {
+            return limits.clone();
+        }
+    }
+
+    let mut cached = LIMITS.write().unwrap();
+    let limits: Arc<SpreadLimits> = match cached.take() {
+        None => Arc::new(SpreadLimits::from_env()),
+        Some((_, previous)) => match std::panic::catch_unwind(SpreadLimits::from_env) {
+            Ok(limits) => Arc::new(limits),
+            Err(_) => {
+                log_error!("Can not reload the spread limits, keeping the previous ones");
+                previous
+            }
+        }
+    };
+
+    *cached = Some((generation, limits.clone()));
+    limits
+}
+
+/// Returns the daily withdrawal limit in nanotons, `None` if withdrawals are not limited.
+pub fn daily_limit() -> Option<i64> {
+    nanotons_from_env("DAILY_WITHDRAWAL_LIMIT").map(Nanotons::signed)
+}
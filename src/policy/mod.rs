@@ -0,0 +1,533 @@
+//! # Mixer Policies
+//!
+//! This module implements a background task that applies operational policies to the
+//! mixer contract without operator intervention. The auto-fork policy forks the contract
+//! once its balance passes a configured size, keeping individual pools small for privacy,
+//! and the auto-collect policy collects funds that grew too large or sat too long.
+//! The auto top-up policy refills the gas wallet when it runs low, from the mixer contract
+//! or a treasury wallet, and keeps an audit record of every top-up in `gas_topups`. The
+//! treasury refills every account wallet, several per message.
+//! The limits spreads are checked against live in `limits`.
+
+use std::time::Duration;
+
+use serde_json::json;
+use sqlx::PgPool;
+use tonlib::address::TonAddress;
+
+use crate::{config, db, indexer, leader, multisig, notify::{Notification, Notifier}, services, ton, types::{nanotons::Nanotons, outbox::{OUTBOX_CONFIRMED, OUTBOX_EXPIRED}, CollectMessageData, MixerCollectionModes, OperationReceipt, DEFAULT_SEND_MODE, MAX_WALLET_MESSAGES}};
+
+pub mod limits;
+
+/// Interval between policy passes in seconds, used when `POLICY_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 60;
+
+/// Time to wait after a fork before forking again in seconds, used when `AUTO_FORK_COOLDOWN` is not set.
+///
+/// The balance only drops once the fork is applied, so forking again right away would fork twice.
+const DEFAULT_FORK_COOLDOWN: u64 = 300;
+
+/// Collection mode used by auto-collect when `AUTO_COLLECT_MODE` is not set.
+const DEFAULT_COLLECT_MODE: u8 = 2;
+
+/// Time to wait after a top-up before topping up again in seconds, used when `GAS_TOPUP_COOLDOWN` is not set.
+///
+/// The gas wallet balance only grows once the top-up is applied.
+const DEFAULT_TOPUP_COOLDOWN: u64 = 600;
+
+/// Reads a TON amount from the environment and converts it to nanotons, `None` if it is not set.
+///
+/// # Panics
+///
+/// Panics if the amount is invalid, which `config::runtime` already refuses on startup and
+/// `config::reload` on a reload.
+pub fn nanotons_from_env(key: &str) -> Option<Nanotons> {
+    let value: String = config::var(key).ok().filter(| v | !v.trim().is_empty())?;
+
+    match Nanotons::parse_ton(&value) {
+        Ok(nanotons) => Some(nanotons),
+        Err(err) => panic!("[ FATAL ] Configuration Error: `{}` is invalid: {}", key, err)
+    }
+}
+
+/// Represents the auto-fork policy of the mixer contract.
+struct AutoFork {
+    threshold: i64,
+    cooldown: u64,
+    last_fork: u64
+}
+
+impl AutoFork {
+    /// Forks the contract if its balance passed the threshold and the cooldown elapsed.
+    async fn apply(&mut self, pool: &PgPool, notifier: &Notifier, contract: &TonAddress) {
+        let balance: i64 = match ton::get_balance(contract).await {
+            Ok(balance) => balance,
+            Err(err) => {
+                log_error!("Policy can not fetch the mixer contract balance: {}", err);
+                return;
+            }
+        };
+
+        if balance <= self.threshold || ton::time_now() < self.last_fork + self.cooldown {
+            return;
+        }
+
+        let receipt: String = match ton::contract_invoke_fork(pool, contract.clone(), ton::time_now()).await {
+            Ok(receipt) => receipt,
+            Err(err) => {
+                log_error!("Policy can not fork the mixer contract {}: {}", contract, err);
+                return;
+            }
+        };
+        self.last_fork = ton::time_now();
+
+        let notification: Notification = Notification::new(
+            "auto_fork",
+            format!(
+                "Balance of the mixer contract {} is {} TON, above the threshold of {} TON, forked it",
+                contract.to_base64_url(),
+                Nanotons::from_signed(balance),
+                Nanotons::from_signed(self.threshold)
+            ),
+            json!({
+                "address": contract.to_base64_url(),
+                "balance": balance,
+                "threshold": self.threshold,
+                "receipt": serde_json::from_str::<serde_json::Value>(&receipt).unwrap_or_default()
+            })
+        );
+
+        log_info!("{}", notification.message);
+        notifier.send(&notification).await;
+    }
+}
+
+/// Represents a collect sent by the auto-collect policy.
+#[derive(Debug, Clone, Copy)]
+struct LastCollect {
+    query_id: Option<u64>,
+    valid_until: u64
+}
+
+/// Represents the auto-collect rules of the mixer contract.
+struct AutoCollect {
+    threshold: Option<i64>,
+    max_age: Option<u64>,
+    mode: u8,
+    last_collect: Option<LastCollect>
+}
+
+impl AutoCollect {
+    /// Loads the rules from the environment, `None` if no rule is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `last_collect` - The last collect of the policy, kept across reloads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `AUTO_COLLECT_MODE` is not a TON collection mode.
+    fn from_env(last_collect: Option<LastCollect>) -> Option<Self> {
+        // collects through a multisig need approvals, they can not be automated
+        if multisig::enabled() {
+            return None;
+        }
+
+        let threshold: Option<i64> = nanotons_from_env("AUTO_COLLECT_THRESHOLD").map(Nanotons::signed);
+        let max_age: Option<u64> = config::var("AUTO_COLLECT_MAX_AGE").ok()
+            .and_then(| h | h.parse::<u64>().ok())
+            .map(| hours | hours * 3600);
+
+        if threshold.is_none() && max_age.is_none() {
+            return None;
+        }
+
+        let mode: u8 = config::env_or("AUTO_COLLECT_MODE", DEFAULT_COLLECT_MODE);
+        if mode >= MixerCollectionModes::new().given_jetton_balance {
+            panic!("[ FATAL ] Configuration Error: `AUTO_COLLECT_MODE` must be a TON collection mode, got `{}`", mode);
+        }
+
+        Some(AutoCollect { threshold, max_age, mode, last_collect })
+    }
+
+    /// Returns whether the last collect may still be applied, so the balance has not dropped yet.
+    ///
+    /// A collect is in flight until the outbox saw it confirmed or expired, or it is past its
+    /// `valid_until` and can no longer be applied.
+    async fn collect_in_flight(&mut self, pool: &PgPool) -> bool {
+        let Some(last) = self.last_collect else {
+            return false;
+        };
+
+        if ton::time_now() > last.valid_until {
+            self.last_collect = None;
+            return false;
+        }
+
+        // without a query id the collect can not be followed, it is waited for until it expires
+        let Some(query_id) = last.query_id else {
+            return true;
+        };
+
+        match db::outbox::by_query_id(pool, query_id as i64).await {
+            Ok(entries) if !entries.is_empty() && entries.iter().all(| e | [OUTBOX_CONFIRMED, OUTBOX_EXPIRED].contains(&e.status.as_str())) => {
+                self.last_collect = None;
+                false
+            },
+            Ok(_) => true,
+            Err(err) => {
+                log_error!("Policy can not fetch the state of the last collect {}: {:?}", query_id, err);
+                true
+            }
+        }
+    }
+
+    /// Collects from the contract if its balance or the age of its funds passed a rule,
+    /// unless the last collect is still in flight.
+    async fn apply(&mut self, pool: &PgPool, notifier: &Notifier, contract: &TonAddress) {
+        let address: String = contract.to_base64_url();
+
+        if self.collect_in_flight(pool).await {
+            return;
+        }
+
+        let balance: i64 = match ton::get_balance(contract).await {
+            Ok(balance) => balance,
+            Err(err) => {
+                log_error!("Policy can not fetch the mixer contract balance: {}", err);
+                return;
+            }
+        };
+
+        let oldest: Option<i64> = match db::events::oldest_uncollected(pool, &address).await {
+            Ok(oldest) => oldest,
+            Err(err) => {
+                log_error!("Policy can not fetch the age of the mixer contract funds: {:?}", err);
+                return;
+            }
+        };
+        let age: Option<u64> = oldest.map(| utime | ton::time_now().saturating_sub(utime as u64));
+
+        let reason: String = match (self.threshold, self.max_age, age) {
+            (Some(threshold), _, _) if balance > threshold => format!(
+                "balance of {} TON is above the threshold of {} TON",
+                Nanotons::from_signed(balance),
+                Nanotons::from_signed(threshold)
+            ),
+            (_, Some(max_age), Some(age)) if age > max_age => format!(
+                "funds are {} hours old, older than {} hours",
+                age / 3600,
+                max_age / 3600
+            ),
+            _ => return
+        };
+
+        let receipt: String = match ton::contract_invoke_collect(pool, contract.clone(), CollectMessageData {
+            mode: self.mode,
+            jetton_wallet: None,
+            amount: None
+        }, DEFAULT_SEND_MODE).await {
+            Ok(receipt) => receipt,
+            Err(err) => {
+                log_error!("Policy can not collect from the mixer contract {}: {}", address, err);
+                return;
+            }
+        };
+
+        self.last_collect = match serde_json::from_str::<OperationReceipt>(&receipt) {
+            Ok(sent) => Some(LastCollect { query_id: sent.query_id, valid_until: sent.valid_until }),
+            Err(err) => {
+                log_error!("Policy can not read the receipt of the collect from {}, pausing auto-collect for a policy pass: {}", address, err);
+                Some(LastCollect { query_id: None, valid_until: ton::time_now() + config::env_or("POLICY_INTERVAL", DEFAULT_INTERVAL) })
+            }
+        };
+
+        let notification: Notification = Notification::new(
+            "auto_collect",
+            format!("Collected from the mixer contract {} in mode {}, {}", address, self.mode, reason),
+            json!({
+                "address": address,
+                "balance": balance,
+                "age": age,
+                "mode": self.mode,
+                "receipt": serde_json::from_str::<serde_json::Value>(&receipt).unwrap_or_default()
+            })
+        );
+
+        log_info!("{}", notification.message);
+        notifier.send(&notification).await;
+    }
+}
+
+/// Represents where the gas wallet is topped up from.
+enum TopUpSource {
+    /// The mixer contract, collected in mode 2.
+    Contract,
+    /// A treasury wallet controlled by its mnemonic and the passphrase of the mnemonic, if any.
+    Treasury(String, Option<String>)
+}
+
+/// Represents the auto top-up policy of the gas wallet.
+struct AutoTopUp {
+    threshold: i64,
+    amount: i64,
+    source: TopUpSource,
+    cooldown: u64,
+    last_topup: u64
+}
+
+impl AutoTopUp {
+    /// Loads the policy from the environment, `None` if `GAS_TOPUP_THRESHOLD` is not set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the source is unknown, or the treasury lacks its mnemonic or an amount.
+    fn from_env(last_topup: u64) -> Option<Self> {
+        let threshold: i64 = nanotons_from_env("GAS_TOPUP_THRESHOLD")?.signed();
+        let amount: i64 = nanotons_from_env("GAS_TOPUP_AMOUNT").unwrap_or_default().signed();
+
+        let source: TopUpSource = match config::env_or("GAS_TOPUP_SOURCE", String::from("contract")).as_str() {
+            // collects through a multisig need approvals, they can not be automated
+            "contract" if multisig::enabled() => return None,
+            "contract" => TopUpSource::Contract,
+            "treasury" => match config::var("GAS_TOPUP_TREASURY_MNEMONIC").ok().filter(| m | !m.is_empty()) {
+                Some(_) if amount <= 0 => panic!("[ FATAL ] Configuration Error: `GAS_TOPUP_SOURCE=treasury` requires `GAS_TOPUP_AMOUNT`"),
+                Some(mnemonic) => TopUpSource::Treasury(mnemonic, config::var("GAS_TOPUP_TREASURY_MNEMONIC_PASSWORD").ok().filter(| p | !p.is_empty())),
+                None => panic!("[ FATAL ] Configuration Error: `GAS_TOPUP_SOURCE=treasury` requires `GAS_TOPUP_TREASURY_MNEMONIC`")
+            },
+            source => panic!("[ FATAL ] Configuration Error: `GAS_TOPUP_SOURCE` has an invalid value `{}`", source)
+        };
+
+        Some(AutoTopUp {
+            threshold,
+            amount,
+            source,
+            cooldown: config::env_or("GAS_TOPUP_COOLDOWN", DEFAULT_TOPUP_COOLDOWN),
+            last_topup
+        })
+    }
+
+    /// Returns the wallets watched by the policy: the gas wallet for top-ups from the contract,
+    /// which pays a collect out to the gas wallet only, and every account wallet for top-ups
+    /// from the treasury.
+    fn watched(&self) -> Vec<TonAddress> {
+        match &self.source {
+            TopUpSource::Contract => vec![ton::wallet_address()],
+            TopUpSource::Treasury(_, _) => {
+                let mut wallets: Vec<TonAddress> = Vec::new();
+                for (_, wallet) in ton::account_addresses() {
+                    if !wallets.contains(&wallet) {
+                        wallets.push(wallet);
+                    }
+                }
+                wallets
+            }
+        }
+    }
+
+    /// Tops up the watched wallets whose balance dropped below the threshold, once the cooldown elapsed.
+    ///
+    /// Top-ups from the treasury are packed up to `MAX_WALLET_MESSAGES` per treasury message,
+    /// so topping up several account wallets takes a single seqno round-trip per batch.
+    async fn apply(&mut self, pool: &PgPool, notifier: &Notifier, contract: &TonAddress) {
+        if ton::time_now() < self.last_topup + self.cooldown {
+            return;
+        }
+
+        let mut low: Vec<(TonAddress, i64)> = Vec::new();
+        for wallet in self.watched() {
+            match ton::get_balance(&wallet).await {
+                Ok(balance) if balance < self.threshold => low.push((wallet, balance)),
+                Ok(_) => {},
+                Err(err) => log_error!("Policy can not fetch the balance of wallet {}: {}", wallet.to_base64_url(), err)
+            }
+        }
+
+        if low.is_empty() {
+            return;
+        }
+
+        self.last_topup = ton::time_now();
+
+        match &self.source {
+            TopUpSource::Contract => {
+                // mode 2 sends the whole available balance, which is what the record shows
+                let amount: i64 = ton::get_balance(contract).await.unwrap_or(0);
+                let sent: Result<String, String> = ton::contract_invoke_collect(pool, contract.clone(), CollectMessageData {
+                    mode: MixerCollectionModes::new().available_ton_balance,
+                    jetton_wallet: None,
+                    amount: None
+                }, DEFAULT_SEND_MODE).await.and_then(| receipt | {
+                    serde_json::from_str::<OperationReceipt>(&receipt).map(| r | r.hash.hex).map_err(|e| e.to_string())
+                });
+
+                for topped_up in low.iter() {
+                    self.record(pool, notifier, topped_up, &contract.to_base64_url(), amount, &sent).await;
+                }
+            },
+            TopUpSource::Treasury(mnemonic, password) => {
+                for batch in low.chunks(MAX_WALLET_MESSAGES) {
+                    let transfers: Vec<(TonAddress, u64)> = batch.iter().map(| (wallet, _) | (wallet.clone(), self.amount as u64)).collect();
+                    let (sender, sent): (String, Result<String, String>) = match ton::treasury_transfer(mnemonic, password.clone(), "top_up", transfers).await {
+                        Ok((treasury, hash)) => (treasury.to_base64_url(), Ok(hash.hex)),
+                        Err(err) => (String::new(), Err(err))
+                    };
+
+                    for topped_up in batch {
+                        self.record(pool, notifier, topped_up, &sender, self.amount, &sent).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records the top-up of a wallet and the balance it had in `gas_topups`, and notifies about it.
+    async fn record(&self, pool: &PgPool, notifier: &Notifier, topped_up: &(TonAddress, i64), sender: &str, amount: i64, sent: &Result<String, String>) {
+        let (wallet, wallet_balance): (&TonAddress, i64) = (&topped_up.0, topped_up.1);
+        let source: &str = match self.source {
+            TopUpSource::Contract => "contract",
+            TopUpSource::Treasury(_, _) => "treasury"
+        };
+        let (tx_hash, error): (Option<&str>, Option<&str>) = match sent {
+            Ok(hash) => (Some(hash.as_str()), None),
+            Err(err) => (None, Some(err.as_str()))
+        };
+
+        if let Err(err) = db::topups::insert(pool, source, sender, amount, wallet_balance, self.threshold, tx_hash, error).await {
+            log_error!("Can not record the gas wallet top-up: {:?}", err);
+        }
+
+        let message: String = match sent {
+            Ok(_) => format!(
+                "Balance of the gas wallet {} is {} TON, below the threshold of {} TON, topped it up with {} TON from the {}",
+                wallet.to_base64_url(),
+                Nanotons::from_signed(wallet_balance),
+                Nanotons::from_signed(self.threshold),
+                Nanotons::from_signed(amount),
+                source
+            ),
+            Err(err) => format!(
+                "Balance of the gas wallet {} is {} TON, below the threshold of {} TON, but the top-up from the {} failed: {}",
+                wallet.to_base64_url(),
+                Nanotons::from_signed(wallet_balance),
+                Nanotons::from_signed(self.threshold),
+                source,
+                err
+            )
+        };
+
+        let notification: Notification = Notification::new(
+            "gas_topup",
+            message,
+            json!({
+                "address": wallet.to_base64_url(),
+                "balance": wallet_balance,
+                "threshold": self.threshold,
+                "source": source,
+                "sender": sender,
+                "amount": amount,
+                "tx_hash": tx_hash,
+                "error": error
+            })
+        );
+
+        match sent {
+            Ok(_) => log_info!("{}", notification.message),
+            Err(_) => log_error!("{}", notification.message)
+        }
+        notifier.send(&notification).await;
+    }
+}
+
+/// Runs the policy loop forever.
+///
+/// The auto-fork threshold is configured in TON with `AUTO_FORK_THRESHOLD`, the auto-collect
+/// rules with `AUTO_COLLECT_THRESHOLD` (TON) and `AUTO_COLLECT_MAX_AGE` (hours), the gas
+/// wallet top-up with `GAS_TOPUP_THRESHOLD` (TON) and `GAS_TOPUP_SOURCE`; the task
+/// exits right away when no policy is configured. Every pass first runs the indexer,
+/// so children of previous forks are registered before new operations are decided.
+/// No policy is applied while the mixer is paused. Thresholds and notification channels
+/// follow configuration reloads, but a task that exited at startup is not started again.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+pub async fn run(pool: PgPool) {
+    let mut auto_fork: Option<AutoFork> = nanotons_from_env("AUTO_FORK_THRESHOLD").map(| threshold | AutoFork {
+        threshold: threshold.signed(),
+        cooldown: config::env_or("AUTO_FORK_COOLDOWN", DEFAULT_FORK_COOLDOWN),
+        last_fork: 0
+    });
+
+    let mut auto_collect: Option<AutoCollect> = AutoCollect::from_env(None);
+    let mut auto_topup: Option<AutoTopUp> = AutoTopUp::from_env(0);
+
+    if auto_fork.is_none() && auto_collect.is_none() && auto_topup.is_none() {
+        log_info!("Mixer policies are disabled");
+        return;
+    }
+
+    let interval: u64 = config::env_or("POLICY_INTERVAL", DEFAULT_INTERVAL);
+    let mut notifier: Notifier = Notifier::from_env(pool.clone());
+    let mut generation: u64 = config::generation();
+    let contract: TonAddress = ton::mixer_contract_address();
+
+    log_info!("Mixer policies are applied every {:?} seconds", interval);
+
+    loop {
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+
+        if !leader::is_leader() || services::mixer::is_paused(&pool).await {
+            continue;
+        }
+
+        if generation != config::generation() {
+            generation = config::generation();
+            notifier = Notifier::from_env(pool.clone());
+            let last_collect: Option<LastCollect> = auto_collect.as_ref().and_then(| policy | policy.last_collect);
+            match std::panic::catch_unwind(|| AutoCollect::from_env(last_collect)) {
+                Ok(policy) => auto_collect = policy,
+                Err(_) => log_error!("Can not reload the auto-collect policy, keeping the previous one")
+            }
+
+            let last_topup: u64 = auto_topup.as_ref().map_or(0, | policy | policy.last_topup);
+            match std::panic::catch_unwind(|| AutoTopUp::from_env(last_topup)) {
+                Ok(policy) => auto_topup = policy,
+                Err(_) => log_error!("Can not reload the auto top-up policy, keeping the previous one")
+            }
+
+            let last_fork: u64 = auto_fork.as_ref().map_or(0, | policy | policy.last_fork);
+            match std::panic::catch_unwind(|| nanotons_from_env("AUTO_FORK_THRESHOLD").map(| threshold | AutoFork {
+                threshold: threshold.signed(),
+                cooldown: config::env_or("AUTO_FORK_COOLDOWN", DEFAULT_FORK_COOLDOWN),
+                last_fork
+            })) {
+                Ok(policy) => auto_fork = policy,
+                Err(_) => log_error!("Can not reload the auto-fork policy, keeping the previous one")
+            }
+
+            log_info!("Mixer policies reloaded");
+        }
+
+        if let Err(err) = indexer::index(&pool).await {
+            log_error!("Policy indexer pass failed: {}", err);
+        }
+
+        if let Err(err) = ton::ensure_code(&contract).await {
+            log_error!("Policies are not applied: {}", err);
+            continue;
+        }
+
+        if let Some(policy) = auto_collect.as_mut() {
+            policy.apply(&pool, &notifier, &contract).await;
+        }
+
+        if let Some(policy) = auto_fork.as_mut() {
+            policy.apply(&pool, &notifier, &contract).await;
+        }
+
+        if let Some(policy) = auto_topup.as_mut() {
+            policy.apply(&pool, &notifier, &contract).await;
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! # Exchange Rates
+//!
+//! This module provides the TON/USD rate used for USD-denominated amounts. The rate
+//! comes from a pluggable `PriceSource`, selected with `RATE_SOURCE`, and is cached
+//! for `RATE_CACHE_TTL` seconds so requests don't hit the price API every time.
+
+use std::sync::{Mutex, OnceLock};
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{config, ton::time_now, types::rates::Rate};
+
+/// Seconds a fetched rate is reused, used when `RATE_CACHE_TTL` is not set.
+const DEFAULT_CACHE_TTL: u64 = 60;
+
+/// A source of the TON/USD price.
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    /// Returns the name of the source, reported with every rate.
+    fn name(&self) -> &'static str;
+
+    /// Fetches the price of one TON in USD.
+    async fn ton_usd(&self) -> Result<f64, String>;
+}
+
+/// Fetches the price from the public CoinGecko API.
+pub struct CoinGecko {
+    client: reqwest::Client
+}
+
+#[async_trait]
+impl PriceSource for CoinGecko {
+    fn name(&self) -> &'static str {
+        "coingecko"
+    }
+
+    async fn ton_usd(&self) -> Result<f64, String> {
+        let response: Value = self.client.get("https://api.coingecko.com/api/v3/simple/price")
+            .query(&[("ids", "the-open-network"), ("vs_currencies", "usd")])
+            .send()
+            .await
+            .and_then(| r | r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<Value>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        response["the-open-network"]["usd"].as_f64()
+            .ok_or_else(|| String::from("CoinGecko response has no TON/USD price"))
+    }
+}
+
+/// Returns a fixed price configured with `RATE_TON_USD`, for offline development.
+pub struct FixedRate {
+    rate: f64
+}
+
+#[async_trait]
+impl PriceSource for FixedRate {
+    fn name(&self) -> &'static str {
+        "fixed"
+    }
+
+    async fn ton_usd(&self) -> Result<f64, String> {
+        Ok(self.rate)
+    }
+}
+
+/// The configured price source.
+static SOURCE: OnceLock<Box<dyn PriceSource>> = OnceLock::new();
+
+/// The last fetched rate.
+static CACHE: Mutex<Option<Rate>> = Mutex::new(None);
+
+/// Returns the price source configured with `RATE_SOURCE` (`coingecko` or `fixed`).
+///
+/// The source is read on the first call, which `main` makes on startup.
+///
+/// # Panics
+///
+/// Panics on the first call if the source is unknown, or `fixed` is used without a positive
+/// `RATE_TON_USD`.
+pub fn source() -> &'static dyn PriceSource {
+    SOURCE.get_or_init(|| {
+        let name: String = config::env_or("RATE_SOURCE", String::from("coingecko"));
+
+        match name.as_str() {
+            "coingecko" => Box::new(CoinGecko { client: reqwest::Client::new() }) as Box<dyn PriceSource>,
+            "fixed" => match config::var("RATE_TON_USD").ok().filter(| r | !r.trim().is_empty()).map(| r | r.trim().parse::<f64>()) {
+                Some(Ok(rate)) if rate.is_finite() && rate > 0.0 => Box::new(FixedRate { rate }),
+                Some(_) => panic!("[ FATAL ] Configuration Error: `RATE_TON_USD` must be a positive TON/USD price"),
+                None => panic!("[ FATAL ] Configuration Error: `RATE_SOURCE=fixed` requires `RATE_TON_USD`")
+            },
+            _ => panic!("[ FATAL ] Configuration Error: `RATE_SOURCE` has an invalid value `{}`", name)
+        }
+    }).as_ref()
+}
+
+/// Returns the current TON/USD rate, fetching it if the cached one is stale.
+pub async fn ton_usd() -> Result<Rate, String> {
+    let ttl: u64 = config::env_or("RATE_CACHE_TTL", DEFAULT_CACHE_TTL);
+
+    if let Some(rate) = CACHE.lock().unwrap().as_ref() {
+        if time_now() < rate.fetched_at + ttl {
+            return Ok(rate.clone());
+        }
+    }
+
+    let source: &dyn PriceSource = source();
+    let price: f64 = source.ton_usd().await?;
+
+    if price.is_nan() || price <= 0.0 {
+        return Err(format!("{} returned an invalid TON/USD price {}", source.name(), price));
+    }
+
+    let rate: Rate = Rate {
+        pair: String::from("TON/USD"),
+        rate: price,
+        source: source.name().to_string(),
+        fetched_at: time_now()
+    };
+
+    *CACHE.lock().unwrap() = Some(rate.clone());
+    Ok(rate)
+}
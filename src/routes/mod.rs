@@ -1,30 +1,291 @@
 //! # Mixer Routes
 //!
-//! This module defines the routes for the mixer service in the TON (The Open Network) application.
+//! This module defines the routes for the mixer service and its administrative API in the TON (The Open Network) application.
 //! It uses the Actix web framework to set up the routing.
 
-use actix_web::{web, Scope};
+use actix_web::{body::{EitherBody, MessageBody}, dev::{ServiceFactory, ServiceRequest, ServiceResponse}, error::ErrorServiceUnavailable, http::Method, middleware::{from_fn, DefaultHeaders, Next}, web, Error, Scope};
+use serde_json::Value;
 
-use crate::controllers::mixer;
+use crate::{auth::{self, rbac::{self, RouteGroup}}, config, controllers::{addressbook, admin, connect, deposit, groups, health, jobs, mixer, multisig, reports}, leader, types::Response};
 
-/// Creates and returns a new `Scope` for the mixer routes.
+/// Current version of the API, the prefix of all non-deprecated mixer routes.
+const API_VERSION: &str = "/v1";
+
+/// Creates and returns a new `Scope` for the versioned mixer routes under "/v1/mixer".
+///
+/// Every route requires the role `ROUTE_ROLES` gives its group, see `mixer_route_group`.
+///
+/// # Returns
+///
+/// Returns a `Scope` object configured with the mixer routes.
+pub fn new() -> Scope {
+    web::scope(API_VERSION).service(mixer_scope().wrap(from_fn(authorize_mixer)))
+}
+
+/// Creates and returns the unversioned "/mixer" routes, kept as deprecated aliases of "/v1/mixer".
+///
+/// Responses carry a `Deprecation` header and a `Link` to the successor version.
+///
+/// # Returns
+///
+/// Returns a `Scope` object configured with the mixer routes.
+pub fn legacy() -> Scope<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse<impl MessageBody>, Error = Error, InitError = ()>> {
+    mixer_scope().wrap(from_fn(authorize_mixer)).wrap(
+        DefaultHeaders::new()
+            .add(("Deprecation", "true"))
+            .add(("Link", format!("<{}/mixer>; rel=\"successor-version\"", API_VERSION)))
+    )
+}
+
+/// Returns the group of a mixer route, given by its method and its path below "/mixer".
+///
+/// Reads, previews and the TON Connect messages, which the wallet of the user sends, are
+/// `Read`. Writes to the address book, recipient groups, jobs and deposits are `Manage`, and
+/// every other write makes the mixer wallet send messages and is `Invoke`.
+fn mixer_route_group(method: &Method, path: &str) -> RouteGroup {
+    if [Method::GET, Method::HEAD, Method::OPTIONS].contains(method) || path == "/spread/preview" || path.starts_with("/connect/") {
+        return RouteGroup::Read;
+    }
+
+    let managed: bool = ["/address-book", "/recipient-groups", "/jobs"].iter()
+        .any(| prefix | path == *prefix || path.starts_with(&format!("{}/", prefix)));
+
+    if managed || path == "/deposits" {
+        return RouteGroup::Manage;
+    }
+
+    RouteGroup::Invoke
+}
+
+/// Rejects mixer requests without a token of the role their route group requires.
+///
+/// A read-only replica answers every route outside the `Read` group with a 503 error, the
+/// caller retries against the primary. A follower forwards them to the leader, see `leader`.
+async fn authorize_mixer(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let group: RouteGroup = mixer_route_group(req.method(), req.match_info().unprocessed());
+    if group != RouteGroup::Read && config::read_only() {
+        return Err(ErrorServiceUnavailable(
+            Response::error(Value::String(String::from("this instance is a read-only replica, send writes to the primary"))).to_string()
+        ));
+    }
+    rbac::authorize(group, &req)?;
+
+    if group != RouteGroup::Read && !leader::is_leader() {
+        return leader::forward(req).await.map(| res | res.map_into_right_body());
+    }
+
+    next.call(req).await.map(| res | res.map_into_left_body())
+}
+
+/// Forwards admin writes reaching a follower to the leader, see `leader`.
+///
+/// The pause state, contract upgrades, the faucet and the top-ups all act through the
+/// leader, and a read-only replica answers them with a 503 error like mixer writes.
+/// Reloading the configuration applies to the instance receiving it and is served locally.
+async fn route_admin(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let write: bool = ![Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method())
+        && req.match_info().unprocessed() != "/config/reload";
+
+    if write && config::read_only() {
+        return Err(ErrorServiceUnavailable(
+            Response::error(Value::String(String::from("this instance is a read-only replica, send writes to the primary"))).to_string()
+        ));
+    }
+
+    if write && !leader::is_leader() {
+        return leader::forward(req).await.map(| res | res.map_into_right_body());
+    }
+
+    next.call(req).await.map(| res | res.map_into_left_body())
+}
+
+/// Creates a `Scope` with the mixer routes.
 ///
 /// This function sets up the following routes under the "/mixer" path:
 /// - POST /fork
 /// - POST /spread
+/// - POST /spread/direct
+/// - POST /spread/mixed
+/// - POST /spread/preview
+/// - POST /spread/split
+/// - POST /spread/group
 /// - POST /collect
+/// - POST /collect/batch
+/// - POST /consolidate
+/// - POST /rebalance
+/// - GET /rebalance/{id}
+/// - GET /jobs
+/// - DELETE /jobs/{id}
+/// - POST /multisig/collect
+/// - GET /multisig/orders/{seqno}
+/// - POST /multisig/orders/{seqno}/approve
+/// - POST /connect/spread
+/// - POST /connect/collect
+/// - POST /connect/fork
 /// - GET /collect_modes
 /// - GET /opcodes
+/// - GET /rates
+/// - GET /chain/info
+/// - GET /deposit/link
+/// - GET /deposit/qr
+/// - POST /deposits
+/// - GET /deposits/{id}
+/// - GET /transactions/{lt}/{hash}/decode
+/// - GET /contract/transactions
+/// - GET /contract/jettons
+/// - GET /balance/aggregate
+/// - GET /contract/verify
+/// - GET /contracts/tree
+/// - GET /contracts/child-address
+/// - GET /operations/by-query-id/{id}
+/// - GET /address-book
+/// - POST /address-book
+/// - GET /address-book/{id}
+/// - PUT /address-book/{id}
+/// - DELETE /address-book/{id}
+/// - GET /recipient-groups
+/// - POST /recipient-groups
+/// - GET /recipient-groups/{id}
+/// - PUT /recipient-groups/{id}
+/// - DELETE /recipient-groups/{id}
+/// - GET /reports/fees
+/// - GET /stats
 ///
 /// # Returns
 ///
 /// Returns a `Scope` object configured with the mixer routes.
-pub fn new() -> Scope {
+fn mixer_scope() -> Scope {
     web::scope("/mixer")
         // Route for retrieving archived emails
         .service(mixer::fork)
         .service(mixer::spread)
+        .service(mixer::spread_direct)
+        .service(mixer::spread_mixed)
+        .service(mixer::spread_preview)
+        .service(mixer::spread_split)
+        .service(groups::spread)
         .service(mixer::collect)
+        .service(mixer::collect_batch)
+        .service(mixer::consolidate)
+        .service(mixer::rebalance)
+        .service(mixer::rebalance_job)
+        .service(jobs::list)
+        .service(jobs::cancel)
+        .service(multisig::collect)
+        .service(multisig::order)
+        .service(multisig::approve)
+        .service(connect::spread)
+        .service(connect::collect)
+        .service(connect::fork)
         .service(mixer::get_collect_modes)
         .service(mixer::opcodes)
+        .service(mixer::rates)
+        .service(mixer::chain_info)
+        .service(deposit::link)
+        .service(deposit::qr)
+        .service(deposit::create)
+        .service(deposit::get)
+        .service(mixer::decode_transaction)
+        .service(mixer::contract_transactions)
+        .service(mixer::contract_jettons)
+        .service(mixer::aggregate_balance)
+        .service(mixer::verify_contract)
+        .service(mixer::contract_tree)
+        .service(mixer::child_address)
+        .service(mixer::operation_by_query_id)
+        .service(addressbook::list)
+        .service(addressbook::create)
+        .service(addressbook::get)
+        .service(addressbook::update)
+        .service(addressbook::remove)
+        .service(groups::list)
+        .service(groups::create)
+        .service(groups::get)
+        .service(groups::update)
+        .service(groups::remove)
+        .service(reports::fees)
+        .service(reports::stats)
+}
+
+/// Creates and returns a new `Scope` for the admin routes.
+///
+/// All routes under the "/admin" path require the admin bearer token, writes other than
+/// the configuration reload are forwarded to the leader:
+/// - GET /dashboard
+/// - GET /notifications/routes
+/// - PUT /notifications/routes/{event}
+/// - DELETE /notifications/routes/{event}
+/// - GET /contracts
+/// - PUT /contracts/{address}
+/// - DELETE /contracts/{address}
+/// - POST /config/reload
+/// - POST /pause
+/// - POST /resume
+/// - POST /jobs/{id}/approve
+/// - GET /limits/daily
+/// - POST /limits/daily/overrides
+/// - POST /contract/upgrade
+/// - GET /webhooks
+/// - POST /webhooks
+/// - GET /webhooks/{id}
+/// - PUT /webhooks/{id}
+/// - DELETE /webhooks/{id}
+/// - POST /webhooks/{id}/test
+/// - GET /webhooks/{id}/deliveries
+/// - GET /gas/topups
+/// - GET /schedules
+/// - POST /schedules
+/// - POST /schedules/{id}/pause
+/// - POST /schedules/{id}/resume
+/// - DELETE /schedules/{id}
+/// - POST /faucet
+///
+/// # Returns
+///
+/// Returns a `Scope` object configured with the admin routes.
+pub fn admin() -> Scope<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse<impl MessageBody>, Error = Error, InitError = ()>> {
+    web::scope("/admin")
+        .wrap(from_fn(route_admin))
+        .wrap(from_fn(auth::require_admin))
+        .service(admin::dashboard)
+        .service(admin::list_notification_routes)
+        .service(admin::set_notification_route)
+        .service(admin::remove_notification_route)
+        .service(admin::list_allowed_contracts)
+        .service(admin::add_allowed_contract)
+        .service(admin::remove_allowed_contract)
+        .service(admin::reload_config)
+        .service(admin::pause)
+        .service(admin::resume)
+        .service(admin::approve_job)
+        .service(admin::daily_limit_status)
+        .service(admin::add_limit_override)
+        .service(admin::upgrade_contract)
+        .service(admin::list_webhooks)
+        .service(admin::create_webhook)
+        .service(admin::get_webhook)
+        .service(admin::update_webhook)
+        .service(admin::remove_webhook)
+        .service(admin::test_webhook)
+        .service(admin::list_webhook_deliveries)
+        .service(admin::list_gas_topups)
+        .service(admin::list_schedules)
+        .service(admin::create_schedule)
+        .service(admin::pause_schedule)
+        .service(admin::resume_schedule)
+        .service(admin::remove_schedule)
+        .service(admin::faucet)
+}
+
+/// Creates and returns a new `Scope` for the probes at the root path:
+/// - GET /ready
+/// - GET /metrics
+///
+/// # Returns
+///
+/// Returns a `Scope` object configured with the probe routes.
+pub fn probes() -> Scope {
+    web::scope("")
+        .service(health::ready)
+        .service(health::metrics)
 }
@@ -0,0 +1,260 @@
+//! # Cron Expressions
+//!
+//! This module parses the five-field cron expressions recurring schedules are defined by,
+//! `minute hour day-of-month month day-of-week`, all in UTC. Fields accept `*`, numbers,
+//! ranges `a-b`, steps `*/n` or `a-b/n` and comma-separated lists of those. Day-of-week
+//! counts from Sunday as `0`, `7` is Sunday too. As in classic cron, when both day fields
+//! are restricted a day matching either of them is a match.
+//!
+//! The shorthands `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are accepted.
+
+use std::str::FromStr;
+
+/// Number of days searched for the next match before an expression is considered to never match,
+/// enough for a leap day.
+const SEARCH_DAYS: u64 = 366 * 8;
+
+/// Represents a parsed cron expression, each field as a bit set of its allowed values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day-of-month field is `*`.
+    any_day: bool,
+    /// Whether the day-of-week field is `*`.
+    any_weekday: bool
+}
+
+impl Cron {
+    /// Returns the first Unix time strictly after `time` the expression matches, at the start of a minute.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the expression matches no date, e.g. `0 0 30 2 *`.
+    pub fn next_after(&self, time: u64) -> Option<u64> {
+        let mut minute: u64 = time / 60 + 1;
+        let last_day: u64 = minute / 1440 + SEARCH_DAYS;
+
+        while minute / 1440 <= last_day {
+            let day: u64 = minute / 1440;
+
+            if !self.matches_day(day) {
+                minute = (day + 1) * 1440;
+                continue;
+            }
+
+            if self.hours & 1 << (minute % 1440 / 60) == 0 {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+
+            if self.minutes & 1 << (minute % 60) == 0 {
+                minute += 1;
+                continue;
+            }
+
+            return Some(minute * 60);
+        }
+
+        None
+    }
+
+    /// Checks the month and the day fields against a day counted from the Unix epoch.
+    fn matches_day(&self, day: u64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+
+        if self.months & 1 << month == 0 {
+            return false;
+        }
+
+        // 1970-01-01 was a Thursday
+        let weekday: u64 = (day + 4) % 7;
+        let by_day: bool = self.days & 1 << day_of_month != 0;
+        let by_weekday: bool = self.weekdays & 1 << weekday != 0;
+
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => by_weekday,
+            (false, true) => by_day,
+            (false, false) => by_day || by_weekday
+        }
+    }
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expression: &str = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" => "0 0 1 1 *",
+            expression => expression
+        };
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("cron expression `{}` must have 5 fields, it has {}", expression, fields.len()));
+        }
+
+        let mut weekdays: u64 = parse_field(fields[4], 0, 7, "day-of-week")?;
+        // 7 is Sunday as well
+        if weekdays & 1 << 7 != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Cron {
+            minutes: parse_field(fields[0], 0, 59, "minute")?,
+            hours: parse_field(fields[1], 0, 23, "hour")?,
+            days: parse_field(fields[2], 1, 31, "day-of-month")?,
+            months: parse_field(fields[3], 1, 12, "month")?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*"
+        })
+    }
+}
+
+/// Parses one field of a cron expression into the bit set of its values.
+///
+/// # Arguments
+///
+/// * `field` - The field, e.g. `*/15` or `1-5`.
+/// * `min` - The lowest allowed value.
+/// * `max` - The highest allowed value.
+/// * `name` - The name of the field used in errors.
+fn parse_field(field: &str, min: u64, max: u64, name: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid {} field `{}`", name, field);
+    let mut bits: u64 = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().map_err(|_| invalid())?),
+            None => (part, 1)
+        };
+
+        let (from, to) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((from, to)) => (from.parse::<u64>().map_err(|_| invalid())?, to.parse::<u64>().map_err(|_| invalid())?),
+                None => {
+                    let value: u64 = range.parse::<u64>().map_err(|_| invalid())?;
+                    // `5/10` runs from 5 to the end of the field
+                    (value, if part.contains('/') { max } else { value })
+                }
+            }
+        };
+
+        if step == 0 || from < min || to > max || from > to {
+            return Err(invalid());
+        }
+
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+/// Converts a day counted from the Unix epoch to its year, month and day of month, in the
+/// proleptic Gregorian calendar.
+pub fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z: u64 = days + 719468;
+    let era: u64 = z / 146097;
+    let doe: u64 = z - era * 146097;
+    let yoe: u64 = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy: u64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp: u64 = (5 * doy + 2) / 153;
+    let day: u64 = doy - (153 * mp + 2) / 5 + 1;
+    let month: u64 = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year: u64 = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-01 00:00 UTC, a Monday.
+    const MONDAY: u64 = 1704067200;
+
+    /// Seconds of a day.
+    const DAY: u64 = 86400;
+
+    fn cron(expression: &str) -> Cron {
+        expression.parse::<Cron>().unwrap()
+    }
+
+    #[test]
+    fn converts_days_to_civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(MONDAY / DAY), (2024, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(civil_from_days(19783), (2024, 3, 1));
+        assert_eq!(civil_from_days(47540), (2100, 2, 28));
+        assert_eq!(civil_from_days(47541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn next_is_strictly_after_the_time() {
+        assert_eq!(cron("0 0 * * *").next_after(MONDAY), Some(MONDAY + DAY));
+        assert_eq!(cron("* * * * *").next_after(MONDAY + 30), Some(MONDAY + 60));
+        assert_eq!(cron("*/15 9-17 * * *").next_after(MONDAY + 17 * 3600 + 45 * 60), Some(MONDAY + DAY + 9 * 3600));
+        assert_eq!(cron("@yearly").next_after(MONDAY), Some(1735689600));
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // the 13th or a Friday: Friday the 5th, Friday the 12th, then Saturday the 13th
+        let expression: Cron = cron("0 0 13 * 5");
+
+        assert_eq!(expression.next_after(MONDAY), Some(MONDAY + 4 * DAY));
+        assert_eq!(expression.next_after(MONDAY + 4 * DAY), Some(MONDAY + 11 * DAY));
+        assert_eq!(expression.next_after(MONDAY + 11 * DAY), Some(MONDAY + 12 * DAY));
+    }
+
+    #[test]
+    fn single_restricted_day_field_matches_alone() {
+        assert_eq!(cron("0 0 13 * *").next_after(MONDAY), Some(MONDAY + 12 * DAY));
+        assert_eq!(cron("0 0 * * 5").next_after(MONDAY), Some(MONDAY + 4 * DAY));
+    }
+
+    #[test]
+    fn seven_is_sunday() {
+        assert_eq!(cron("0 0 * * 7"), cron("0 0 * * 0"));
+        assert_eq!(cron("0 0 * * 7").next_after(MONDAY), Some(MONDAY + 6 * DAY));
+        assert_eq!(cron("0 0 * * 6-7"), cron("0 0 * * 0,6"));
+    }
+
+    #[test]
+    fn impossible_dates_never_match() {
+        assert_eq!(cron("0 0 30 2 *").next_after(MONDAY), None);
+        assert_eq!(cron("0 0 31 4,6,9,11 *").next_after(MONDAY), None);
+    }
+
+    #[test]
+    fn leap_days_match_every_four_years() {
+        let expression: Cron = cron("0 12 29 2 *");
+
+        // 2024-02-29 12:00, then 2028-02-29 12:00
+        assert_eq!(expression.next_after(MONDAY), Some(19782 * DAY + 12 * 3600));
+        assert_eq!(expression.next_after(19783 * DAY), Some((19782 + 1461) * DAY + 12 * 3600));
+        // 2100 is no leap year, the next leap day is in 2104
+        assert_eq!(expression.next_after(47541 * DAY), Some((47541 + 1460) * DAY + 12 * 3600));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expression in ["0 0 * * 8", "60 * * * *", "* 24 * * *", "* * 0 * *", "* * * 13 *", "* * *", "5-1 * * * *", "*/0 * * * *", "a * * * *"] {
+            assert!(expression.parse::<Cron>().is_err(), "`{}` was accepted", expression);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! # Recurring Schedules
+//!
+//! This module implements the scheduler of recurring operations: every schedule holds a
+//! cron expression and an operation template, and each time it comes due the template is
+//! submitted to the jobs queue, so the operation runs with the retries, priorities and
+//! audit trail of every other job. A rebalance is planned from the balances at the time
+//! of the run, and skipped when no funds have to move.
+//!
+//! Runs missed while no instance was up are not caught up: a schedule that is found
+//! overdue runs once and continues with its next occurrence after the current time.
+//! The run is claimed by moving `next_run_at`, so several instances can share the schedules.
+
+use std::{str::FromStr, time::Duration};
+
+use serde_json::Value;
+use sqlx::PgPool;
+use tonlib::address::TonAddress;
+
+use crate::{config, db, jobs, services::{addressbook, mixer}, ton, types::{jobs::{CollectJob, ForkJob, Job, SnapshotJob, SpreadJob, JOB_COLLECT, JOB_FORK, JOB_REBALANCE, JOB_SNAPSHOT, JOB_SPREAD}, rebalance::{RebalanceJob, RebalancePlan}, schedules::{OperationTemplate, Schedule}}};
+
+pub mod cron;
+
+use cron::Cron;
+
+/// Interval between scheduler passes in seconds, used when `SCHEDULER_INTERVAL` is not set.
+const DEFAULT_INTERVAL: u64 = 30;
+
+/// Runs the scheduler loop forever.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+pub async fn run(pool: PgPool) {
+    let interval: u64 = config::env_or("SCHEDULER_INTERVAL", DEFAULT_INTERVAL);
+
+    log_info!("Scheduler is running every {:?} seconds", interval);
+
+    loop {
+        actix_web::rt::time::sleep(Duration::from_secs(interval)).await;
+
+        let schedules: Vec<Schedule> = match db::schedules::due(&pool).await {
+            Ok(schedules) => schedules,
+            Err(err) => {
+                log_error!("Scheduler can not read the due schedules: {:?}", err);
+                continue;
+            }
+        };
+
+        for schedule in schedules {
+            fire(&pool, schedule).await;
+        }
+    }
+}
+
+/// Returns the next run of a cron expression after `time`.
+///
+/// # Returns
+///
+/// The Unix time of the next run, or an error if the expression is invalid or never matches.
+pub fn next_run(cron: &str, time: u64) -> Result<u64, String> {
+    Cron::from_str(cron)?
+        .next_after(time)
+        .ok_or_else(|| format!("cron expression `{}` never matches", cron))
+}
+
+/// Claims a due schedule, submits its template and records the outcome.
+async fn fire(pool: &PgPool, schedule: Schedule) {
+    let next_run_at: i64 = match next_run(&schedule.cron, ton::time_now()) {
+        Ok(next) => next as i64,
+        Err(err) => {
+            log_error!("Schedule {} can not run: {}", schedule.id, err);
+            return;
+        }
+    };
+
+    match db::schedules::claim_run(pool, schedule.id, schedule.next_run_at, next_run_at).await {
+        Ok(true) => {},
+        Ok(false) => return,
+        Err(err) => {
+            log_error!("Scheduler can not claim schedule {}: {:?}", schedule.id, err);
+            return;
+        }
+    }
+
+    let submitted: Result<Option<Job>, String> = submit(pool, &schedule).await;
+
+    let recorded = match &submitted {
+        Ok(Some(job)) => {
+            log_info!("Schedule {} ({}) submitted job {}", schedule.id, schedule.name, job.id);
+            db::schedules::record_run(pool, schedule.id, Some(job.id), None).await
+        },
+        Ok(None) => {
+            log_info!("Schedule {} ({}) had nothing to do", schedule.id, schedule.name);
+            db::schedules::record_run(pool, schedule.id, None, None).await
+        },
+        Err(err) => {
+            log_error!("Schedule {} ({}) failed: {}", schedule.id, schedule.name, err);
+            db::schedules::record_run(pool, schedule.id, None, Some(err)).await
+        }
+    };
+
+    if let Err(err) = recorded {
+        log_error!("Can not record the run of schedule {}: {:?}", schedule.id, err);
+    }
+}
+
+/// Submits the operation template of a schedule to the jobs queue.
+///
+/// Schedules keep their `@name` references, which are resolved into the job of every run,
+/// so a changed entry applies to the next run but not to jobs that are already queued.
+///
+/// # Returns
+///
+/// The submitted job, `None` if a rebalance has no funds to move.
+async fn submit(pool: &PgPool, schedule: &Schedule) -> Result<Option<Job>, String> {
+    let (kind, payload) = match schedule.template()? {
+        OperationTemplate::Spread(template) => (JOB_SPREAD, serde_json::to_value(SpreadJob {
+            contract: contract_or_default(template.contract.as_deref())?,
+            wallets: addressbook::resolve_wallets(pool, &template.wallets).await.map_err(|e| e.to_string())?,
+            total: template.total
+        })),
+        OperationTemplate::Collect(template) => (JOB_COLLECT, serde_json::to_value(CollectJob {
+            contract: contract_or_default(template.contract.as_deref())?,
+            collect: addressbook::resolve_collect(pool, template.collect).await.map_err(|e| e.to_string())?
+        })),
+        OperationTemplate::Fork(template) => (JOB_FORK, serde_json::to_value(ForkJob {
+            contract: contract_or_default(template.contract.as_deref())?
+        })),
+        OperationTemplate::Rebalance(template) => {
+            let plan: RebalancePlan = mixer::plan_rebalance(pool, &template).await.map_err(|e| e.to_string())?;
+            if plan.is_empty() {
+                return Ok(None);
+            }
+
+            (JOB_REBALANCE, serde_json::to_value(RebalanceJob { plan }))
+        },
+        OperationTemplate::Snapshot(template) => (JOB_SNAPSHOT, serde_json::to_value(SnapshotJob {
+            window: template.window.unwrap_or(String::from("24h"))
+        }))
+    };
+
+    let payload: Value = payload.map_err(|e| e.to_string())?;
+    jobs::schedule(pool, kind, &payload, ton::time_now(), schedule.priority, None).await.map(Some)
+}
+
+/// Returns the contract of a template in its user-friendly form, `MIXER_CONTRACT` if it has none.
+fn contract_or_default(contract: Option<&str>) -> Result<String, String> {
+    match contract {
+        Some(contract) => TonAddress::from_str(contract).map(| a | a.to_base64_url()).map_err(|e| e.to_string()),
+        None => Ok(ton::mixer_contract_address().to_base64_url())
+    }
+}
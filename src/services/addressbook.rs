@@ -0,0 +1,232 @@
+//! # Address Book Services
+//!
+//! This module provides service functions managing the address book, resolving the `@name`
+//! references of payloads to their addresses and labeling the known addresses of responses.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use actix_web::{error::{ErrorBadRequest, ErrorConflict, ErrorInternalServerError, ErrorNotFound}, Error, HttpResponse};
+use serde_json::Value;
+use sqlx::PgPool;
+use tonlib::address::TonAddress;
+
+use crate::{db, types::{addressbook::{AddressBookEntry, AddressBookPayload, AddressBookQuery, AddressLabels}, CollectPayload, Response, SpreadWalletPayload}};
+
+/// Returns the 404 error of a missing entry.
+fn entry_not_found(id: i64) -> Error {
+    ErrorNotFound(Response::error(Value::String(format!("address book entry {} does not exist", id))).to_string())
+}
+
+/// Returns the 409 error of a name taken by another entry.
+fn name_taken(name: &str) -> Error {
+    ErrorConflict(Response::error(Value::String(format!("address book name `{}` is already taken", name))).to_string())
+}
+
+/// Returns the 500 error of a failed query.
+fn internal(err: sqlx::Error) -> Error {
+    ErrorInternalServerError(Response::error(Value::String(err.to_string())).to_string())
+}
+
+/// Returns the address of a validated payload and its raw form.
+fn addresses(payload: &AddressBookPayload) -> (String, String) {
+    let address: &str = payload.address.trim();
+
+    (address.to_string(), TonAddress::from_str(address).unwrap().to_hex())
+}
+
+/// Lists the address book entries by name.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - The tag to filter by, if any.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the entries or an error.
+pub async fn list(pool: &PgPool, query: AddressBookQuery) -> Result<HttpResponse, Error> {
+    match db::addressbook::list(pool, query.tag.as_deref()).await {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+        Err(err) => Err(internal(err))
+    }
+}
+
+/// Returns an address book entry.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the entry, or a 404 error if it does not exist.
+pub async fn get(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    match db::addressbook::get(pool, id).await {
+        Ok(Some(entry)) => Ok(HttpResponse::Ok().json(entry)),
+        Ok(None) => Err(entry_not_found(id)),
+        Err(err) => Err(internal(err))
+    }
+}
+
+/// Creates an address book entry.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `payload` - The name, address, tags and notes of the entry.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the entry, or a 409 error if the name is taken.
+pub async fn create(pool: &PgPool, payload: AddressBookPayload) -> Result<HttpResponse, Error> {
+    let (address, raw_address): (String, String) = addresses(&payload);
+    let tags: Vec<String> = payload.tags.unwrap_or_default();
+
+    match db::addressbook::create(pool, &payload.name, &address, &raw_address, &tags, payload.notes.as_deref()).await {
+        Ok(Some(entry)) => {
+            log_info!("Address book entry {} created: {} = {}", entry.id, entry.name, entry.address);
+            Ok(HttpResponse::Created().json(entry))
+        },
+        Ok(None) => Err(name_taken(&payload.name)),
+        Err(err) => Err(internal(err))
+    }
+}
+
+/// Replaces an address book entry.
+///
+/// Payloads referencing the entry by name resolve to the new address from now on, already
+/// queued jobs included.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the entry, a 404 error if it does not exist, or a
+/// 409 error if the name is taken by another entry.
+pub async fn update(pool: &PgPool, id: i64, payload: AddressBookPayload) -> Result<HttpResponse, Error> {
+    let (address, raw_address): (String, String) = addresses(&payload);
+    let tags: Vec<String> = payload.tags.unwrap_or_default();
+
+    match db::addressbook::update(pool, id, &payload.name, &address, &raw_address, &tags, payload.notes.as_deref()).await {
+        Ok(Some(entry)) => {
+            log_info!("Address book entry {} updated: {} = {}", entry.id, entry.name, entry.address);
+            Ok(HttpResponse::Ok().json(entry))
+        },
+        Ok(None) => Err(entry_not_found(id)),
+        Err(err) if err.as_database_error().is_some_and(| e | e.is_unique_violation()) => Err(name_taken(&payload.name)),
+        Err(err) => Err(internal(err))
+    }
+}
+
+/// Removes an address book entry.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response, a 404 error if the entry does not exist, or a 409 error
+/// if it is a member of a recipient group.
+pub async fn remove(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    match db::addressbook::remove(pool, id).await {
+        Ok(0) => Err(entry_not_found(id)),
+        Ok(_) => {
+            log_info!("Address book entry {} removed", id);
+            Ok(HttpResponse::NoContent().finish())
+        },
+        Err(err) if err.as_database_error().is_some_and(| e | e.is_foreign_key_violation()) => Err(ErrorConflict(
+            Response::error(Value::String(format!("address book entry {} is a member of a recipient group", id))).to_string()
+        )),
+        Err(err) => Err(internal(err))
+    }
+}
+
+/// Resolves the `@name` references among recipients to the addresses of their entries.
+///
+/// # Returns
+///
+/// The addresses keyed by the references, or a 400 error naming the first unknown entry.
+async fn resolve_names<'a>(pool: &PgPool, recipients: impl IntoIterator<Item = &'a str>) -> Result<BTreeMap<String, String>, Error> {
+    let mut names: Vec<String> = recipients.into_iter()
+        .filter_map(| r | r.strip_prefix('@'))
+        .map(String::from)
+        .collect();
+    names.sort();
+    names.dedup();
+
+    if names.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let entries: Vec<AddressBookEntry> = db::addressbook::by_names(pool, &names).await.map_err(internal)?;
+    let resolved: BTreeMap<String, String> = entries.into_iter().map(| e | (format!("@{}", e.name), e.address)).collect();
+
+    match names.iter().find(| n | !resolved.contains_key(&format!("@{}", n))) {
+        Some(unknown) => Err(ErrorBadRequest(
+            Response::error(Value::String(format!("address book entry `@{}` does not exist", unknown))).to_string()
+        )),
+        None => Ok(resolved)
+    }
+}
+
+/// Resolves a recipient given as an address or `@name` to an address.
+///
+/// # Returns
+///
+/// The address, or a 400 error if the entry does not exist.
+pub async fn resolve(pool: &PgPool, recipient: &str) -> Result<String, Error> {
+    let mut resolved: BTreeMap<String, String> = resolve_names(pool, [recipient]).await?;
+
+    Ok(resolved.remove(recipient).unwrap_or_else(|| recipient.to_string()))
+}
+
+/// Resolves the `@name` recipients of a spread to the addresses of their entries.
+///
+/// # Returns
+///
+/// The recipients with addresses only, or a 400 error if an entry does not exist.
+pub async fn resolve_wallets(pool: &PgPool, wallets: &[SpreadWalletPayload]) -> Result<Vec<SpreadWalletPayload>, Error> {
+    let resolved: BTreeMap<String, String> = resolve_names(pool, wallets.iter().map(| v | v.account.as_str())).await?;
+
+    Ok(wallets.iter().map(| v | match resolved.get(&v.account) {
+        Some(address) => SpreadWalletPayload { account: address.clone(), ..v.clone() },
+        None => v.clone()
+    }).collect())
+}
+
+/// Labels the known addresses of a response with the names of their entries.
+///
+/// Addresses are matched in any form and keyed as given. Labels only annotate a response,
+/// so a failed lookup is logged and leaves them out.
+pub async fn labels<'a>(pool: &PgPool, addresses: impl IntoIterator<Item = &'a str>) -> AddressLabels {
+    let raw: BTreeMap<&str, String> = addresses.into_iter()
+        .filter_map(| a | TonAddress::from_str(a).ok().map(| parsed | (a, parsed.to_hex())))
+        .collect();
+
+    if raw.is_empty() {
+        return AddressLabels::new();
+    }
+
+    let raw_addresses: Vec<String> = raw.values().cloned().collect();
+    let entries: Vec<AddressBookEntry> = match db::addressbook::by_raw_addresses(pool, &raw_addresses).await {
+        Ok(entries) => entries,
+        Err(err) => {
+            log_warn!("Can not label addresses from the address book: {:?}", err);
+            return AddressLabels::new();
+        }
+    };
+
+    // the oldest entry names an address stored more than once
+    let mut names: BTreeMap<&str, &str> = BTreeMap::new();
+    for entry in &entries {
+        names.entry(entry.raw_address.as_str()).or_insert(entry.name.as_str());
+    }
+
+    raw.iter()
+        .filter_map(| (address, raw) | names.get(raw.as_str()).map(| name | (address.to_string(), name.to_string())))
+        .collect()
+}
+
+/// Resolves the jetton wallet of a collect given as `@name` to the address of its entry.
+///
+/// # Returns
+///
+/// The payload with addresses only, or a 400 error if the entry does not exist.
+pub async fn resolve_collect(pool: &PgPool, mut payload: CollectPayload) -> Result<CollectPayload, Error> {
+    if let Some(wallet) = &payload.jetton_wallet {
+        payload.jetton_wallet = Some(resolve(pool, wallet).await?);
+    }
+
+    Ok(payload)
+}
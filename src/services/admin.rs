@@ -0,0 +1,874 @@
+//! # Admin Services
+//!
+//! This module provides service functions for the administrative API.
+
+use std::str::FromStr;
+
+use actix_web::{error::{ErrorBadRequest, ErrorConflict, ErrorForbidden, ErrorInternalServerError, ErrorNotFound, ErrorServiceUnavailable}, Error, HttpResponse};
+use base64::{Engine as _, engine::general_purpose};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tonlib::{address::TonAddress, cell::{BagOfCells, Cell}};
+
+use crate::{alerts, config::{self, Network}, db, logging::trace, multisig, notify::{webhooks, Notification}, policy, scheduler, services::mixer, ton, types::{dashboard::{ContractPoolSummary, Dashboard, DashboardAlerts}, faucet::{FaucetGrant, FaucetPayload, FaucetTarget}, jobs::{JobQueueStats, JOB_PRIORITY_NORMAL}, limits::{DailyLimitStatus, LimitOverride, LimitOverridePayload}, nanotons::Nanotons, notifications::NotificationRoute, outbox::OutboxEntry, schedules::{Schedule, SchedulePayload}, topups::GasTopUpQuery, upgrade::{ContractUpgradePayload, ContractUpgradePreview}, webhooks::{WebhookDeliveryQuery, WebhookSubscription, WebhookSubscriptionPayload}, Balances, Response}};
+
+/// Lists the notification routes.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the routes in JSON format.
+pub async fn list_notification_routes(pool: &PgPool) -> Result<HttpResponse, Error> {
+    match db::notifications::list(pool).await {
+        Ok(routes) => Ok(HttpResponse::Ok().json(routes)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Replaces the channels an event type is routed to.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `event` - The notification event type.
+/// * `channels` - The channel names the event is delivered to.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the new routes of the event.
+pub async fn set_notification_route(pool: &PgPool, event: String, channels: Vec<String>) -> Result<HttpResponse, Error> {
+    if let Err(err) = db::notifications::set(pool, &event, &channels).await {
+        return Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ));
+    }
+
+    let routes: Vec<NotificationRoute> = channels.into_iter().map(| channel | NotificationRoute {
+        event: event.clone(),
+        channel
+    }).collect();
+
+    Ok(HttpResponse::Ok().json(routes))
+}
+
+/// Removes the routes of an event type, delivering it to every channel again.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or a 404 error if the event had no routes.
+pub async fn remove_notification_route(pool: &PgPool, event: String) -> Result<HttpResponse, Error> {
+    match db::notifications::remove(pool, &event).await {
+        Ok(0) => Err(ErrorNotFound(
+            Response::error(Value::String(format!("event `{}` has no routes", event))).to_string()
+        )),
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Normalizes a contract address to the form stored in the allow-list.
+fn normalize_address(address: &str) -> Result<String, Error> {
+    match TonAddress::from_str(address) {
+        Ok(a) => Ok(a.to_base64_url()),
+        Err(err) => Err(ErrorBadRequest(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Lists the contracts operations may target besides `MIXER_CONTRACT`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the allowed contracts in JSON format.
+pub async fn list_allowed_contracts(pool: &PgPool) -> Result<HttpResponse, Error> {
+    match db::allowlist::list(pool).await {
+        Ok(contracts) => Ok(HttpResponse::Ok().json(contracts)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Adds a contract to the allow-list.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `address` - The address of the contract in any form.
+/// * `label` - An optional human readable name of the contract.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the allowed contract.
+pub async fn add_allowed_contract(pool: &PgPool, address: String, label: Option<String>) -> Result<HttpResponse, Error> {
+    let address: String = normalize_address(&address)?;
+
+    match db::allowlist::add(pool, &address, label.as_deref()).await {
+        Ok(contract) => Ok(HttpResponse::Ok().json(contract)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Removes a contract from the allow-list.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or a 404 error if the contract was not allowed.
+pub async fn remove_allowed_contract(pool: &PgPool, address: String) -> Result<HttpResponse, Error> {
+    let address: String = normalize_address(&address)?;
+
+    match db::allowlist::remove(pool, &address).await {
+        Ok(0) => Err(ErrorNotFound(
+            Response::error(Value::String(format!("contract {} is not allowed", address))).to_string()
+        )),
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Reloads the non-critical configuration from the environment and the `.env` file.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the new runtime configuration, or a 400 error
+/// if a variable has an invalid value and the previous configuration stays in effect.
+pub fn reload_config() -> Result<HttpResponse, Error> {
+    match config::reload() {
+        Ok(runtime) => {
+            log_info!("Configuration reloaded by the admin API");
+            Ok(HttpResponse::Ok().json(runtime.as_ref()))
+        },
+        Err(err) => Err(ErrorBadRequest(
+            Response::error(Value::String(err)).to_string()
+        ))
+    }
+}
+
+/// Pauses or resumes invocations of the mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `paused` - Whether to pause the mixer.
+/// * `operator` - The operator who signed the request.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the state of the mixer.
+pub async fn set_paused(pool: &PgPool, paused: bool, operator: &str) -> Result<HttpResponse, Error> {
+    mixer::set_paused(pool, paused, &format!("operator {}", operator)).await
+        .map_err(| e | ErrorInternalServerError(Response::error(Value::String(format!("can not store the pause state: {}", e))).to_string()))?;
+    log_warn!("Mixer {} by operator {}", if paused { "paused" } else { "resumed" }, operator);
+
+    Ok(HttpResponse::Ok().json(json!({ "paused": paused })))
+}
+
+/// Approves a job held by a limit, so it runs right away without the spread limits.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `id` - The id of the held job.
+/// * `operator` - The operator who signed the request, recorded as the approver.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the approved job, or a 404 error if there is no held job with the id.
+pub async fn approve_job(pool: &PgPool, id: i64, operator: &str) -> Result<HttpResponse, Error> {
+    match db::jobs::approve(pool, id, &format!("operator {}", operator)).await {
+        Ok(Some(job)) => {
+            log_warn!("Job {} ({}) was approved by operator {}", job.id, job.kind, operator);
+            Ok(HttpResponse::Ok().json(job))
+        },
+        Ok(None) => Err(ErrorNotFound(
+            Response::error(Value::String(format!("there is no held job {}", id))).to_string()
+        )),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Reports the usage of the daily withdrawal limit and the active overrides.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the `DailyLimitStatus` in JSON format.
+pub async fn daily_limit_status(pool: &PgPool) -> Result<HttpResponse, Error> {
+    let (used, granted): (i64, i64) = match db::limits::usage(pool).await {
+        Ok(usage) => usage,
+        Err(err) => return Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    };
+
+    let overrides: Vec<LimitOverride> = match db::limits::active_overrides(pool).await {
+        Ok(overrides) => overrides,
+        Err(err) => return Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    };
+
+    let limit: Option<i64> = policy::limits::daily_limit();
+
+    Ok(HttpResponse::Ok().json(DailyLimitStatus {
+        limit,
+        used,
+        granted,
+        remaining: limit.map(| limit | (limit + granted - used).max(0)),
+        overrides
+    }))
+}
+
+/// Grants extra allowance on top of the daily withdrawal limit.
+///
+/// The override is stored with its reason and the client that requested it, and logged
+/// as a warning, so every bypass of the limit leaves an audit trail.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `payload` - The extra TON, how long they are granted for and why.
+/// * `requested_by` - The address of the admin client.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the stored `LimitOverride`.
+pub async fn add_limit_override(pool: &PgPool, payload: LimitOverridePayload, requested_by: Option<String>) -> Result<HttpResponse, Error> {
+    let amount: i64 = Nanotons::from_ton(payload.amount).unwrap().signed();
+    let hours: u32 = payload.hours.unwrap_or(24);
+    let expires_at: i64 = (ton::time_now() + hours as u64 * 3600) as i64;
+
+    match db::limits::add_override(pool, amount, &payload.reason, requested_by.as_deref(), expires_at).await {
+        Ok(grant) => {
+            log_warn!(
+                "Daily withdrawal limit override {} granted by {}: {} TON for {} hours, reason: {}",
+                grant.id, requested_by.as_deref().unwrap_or("unknown"), payload.amount, hours, payload.reason
+            );
+            Ok(HttpResponse::Ok().json(grant))
+        },
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Action name of contract upgrade confirmations.
+const UPGRADE_ACTION: &str = "contract_upgrade";
+
+/// Seconds a contract upgrade confirmation token is valid for.
+const UPGRADE_CONFIRMATION_TTL: u64 = 600;
+
+/// Reads the new contract code of an upgrade, sent in the payload or bundled with `MIXER_UPGRADE_CODE`.
+///
+/// # Returns
+///
+/// The root cell of the code, or a 400 error if there is none or it is not a single-root BOC.
+fn upgrade_code(code: Option<String>) -> Result<Cell, Error> {
+    let bad_request = | message: String | ErrorBadRequest(Response::error(Value::String(message)).to_string());
+
+    let bytes: Vec<u8> = match code {
+        Some(code) => general_purpose::STANDARD.decode(code.trim())
+            .map_err(|e| bad_request(format!("`code` is not base64: {}", e)))?,
+        None => {
+            let path: String = config::var("MIXER_UPGRADE_CODE").ok().filter(| p | !p.is_empty())
+                .ok_or_else(|| bad_request(String::from("`code` is required, no code is bundled with `MIXER_UPGRADE_CODE`")))?;
+            let bytes: Vec<u8> = std::fs::read(&path).map_err(|e| bad_request(format!("can not read `{}`: {}", path, e)))?;
+
+            // the bundled file may be a binary BOC or its base64 form
+            general_purpose::STANDARD.decode(String::from_utf8_lossy(&bytes).trim()).unwrap_or(bytes)
+        }
+    };
+
+    BagOfCells::parse(&bytes)
+        .and_then(| boc | boc.single_root())
+        .map(| root | root.as_ref().clone())
+        .map_err(|e| bad_request(format!("code is not a valid BOC: {}", e)))
+}
+
+/// Checks that the new code of an upgrade is one the contracts may have.
+///
+/// # Returns
+///
+/// Nothing, or a 409 error if `MIXER_CODE_HASH` is not set or does not list the hash, so a
+/// contract is never moved to code whose source was not verified.
+fn ensure_upgrade_code(code_hash: &str) -> Result<(), Error> {
+    let expected: Vec<String> = ton::expected_code_hashes();
+
+    match expected.iter().any(| hash | hash == code_hash) {
+        true => Ok(()),
+        false if expected.is_empty() => Err(ErrorConflict(Response::error(Value::String(format!(
+            "upgrades require `MIXER_CODE_HASH`, add the verified hash {} of the new code to it", code_hash
+        ))).to_string())),
+        false => Err(ErrorConflict(Response::error(Value::String(format!(
+            "new code hash {} is not listed in `MIXER_CODE_HASH`, add it once the code is verified against its source", code_hash
+        ))).to_string()))
+    }
+}
+
+/// Upgrades the code of a mixer contract in two steps.
+///
+/// The first request previews the upgrade and issues a one-time confirmation token bound to
+/// the contract and the hash of the new code; the upgrade message is only sent by a second
+/// request carrying that token. The hash of the new code must be listed in `MIXER_CODE_HASH`
+/// in both steps. It goes through the outbox like every other operation, and both steps are
+/// logged as warnings with the operator who signed them.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to upgrade, `MIXER_CONTRACT` if `None`.
+/// * `payload` - The new code and the confirmation token, if any.
+/// * `requested_by` - The name of the operator who signed the request.
+///
+/// # Returns
+///
+/// Returns a 202 response with the `ContractUpgradePreview`, the receipt of the sent upgrade,
+/// a 403 error for an invalid, used or expired confirmation token, or a 409 error for code
+/// that is not listed in `MIXER_CODE_HASH`.
+pub async fn upgrade_contract(pool: &PgPool, contract: Option<String>, payload: ContractUpgradePayload, requested_by: Option<String>) -> Result<HttpResponse, Error> {
+    let contract: TonAddress = mixer::resolve_contract(pool, contract.as_deref()).await?;
+    let address: String = contract.to_base64_url();
+    let code: Cell = upgrade_code(payload.code)?;
+    let code_hash: String = hex::encode(code.cell_hash());
+    let requested_by_name: &str = requested_by.as_deref().unwrap_or("unknown");
+    ensure_upgrade_code(&code_hash)?;
+
+    let Some(confirmation) = payload.confirmation else {
+        let current_code_hash: Option<String> = match ton::verify_code(&contract).await {
+            Ok(verification) => verification.code_hash,
+            Err(err) => return Err(ErrorInternalServerError(
+                Response::error(Value::String(format!("can not fetch the code of contract {}: {}", address, err))).to_string()
+            ))
+        };
+
+        let token: String = trace::random_hex(16);
+        let expires_at: i64 = (ton::time_now() + UPGRADE_CONFIRMATION_TTL) as i64;
+
+        if let Err(err) = db::confirmations::insert(pool, &token, UPGRADE_ACTION, &address, &code_hash, requested_by.as_deref(), expires_at).await {
+            return Err(ErrorInternalServerError(
+                Response::error(Value::String(err.to_string())).to_string()
+            ));
+        }
+
+        log_warn!("Upgrade of contract {} to code {} requested by {}, awaiting confirmation", address, code_hash, requested_by_name);
+
+        return Ok(HttpResponse::Accepted().json(ContractUpgradePreview {
+            contract: address,
+            current_code_hash,
+            code_hash,
+            confirmation: token,
+            expires_at
+        }));
+    };
+
+    match db::confirmations::consume(pool, &confirmation, UPGRADE_ACTION, &address, &code_hash).await {
+        Ok(true) => {},
+        Ok(false) => return Err(ErrorForbidden(
+            Response::error(Value::String(String::from("confirmation is invalid, used, expired or was issued for other code"))).to_string()
+        )),
+        Err(err) => return Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+
+    log_warn!("Upgrade of contract {} to code {} confirmed by {}, sending", address, code_hash, requested_by_name);
+    let receipt: String = ton::contract_invoke_upgrade(pool, contract, code).await.map_err(mixer::send_failed)?;
+
+    Ok(HttpResponse::Ok().body(receipt))
+}
+
+/// Returns the 404 error of an unknown webhook subscription.
+fn webhook_not_found(id: i64) -> Error {
+    ErrorNotFound(Response::error(Value::String(format!("webhook subscription {} does not exist", id))).to_string())
+}
+
+/// Loads a webhook subscription.
+///
+/// # Returns
+///
+/// The subscription, or a 404 error if it does not exist.
+async fn load_webhook(pool: &PgPool, id: i64) -> Result<WebhookSubscription, Error> {
+    match db::webhooks::get(pool, id).await {
+        Ok(Some(subscription)) => Ok(subscription),
+        Ok(None) => Err(webhook_not_found(id)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Lists the webhook subscriptions, without their secrets.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the subscriptions in JSON format.
+pub async fn list_webhooks(pool: &PgPool) -> Result<HttpResponse, Error> {
+    match db::webhooks::list(pool).await {
+        Ok(subscriptions) => Ok(HttpResponse::Ok().json(subscriptions)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Returns a webhook subscription, without its secret.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the subscription, or a 404 error if it does not exist.
+pub async fn get_webhook(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(load_webhook(pool, id).await?))
+}
+
+/// Creates a webhook subscription.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `payload` - The endpoint, its secret, event types and whether it is enabled.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the subscription, or a 400 error if the secret is missing.
+pub async fn create_webhook(pool: &PgPool, payload: WebhookSubscriptionPayload) -> Result<HttpResponse, Error> {
+    let Some(secret) = payload.secret else {
+        return Err(ErrorBadRequest(
+            Response::error(Value::String(String::from("`secret` is required to create a webhook subscription"))).to_string()
+        ));
+    };
+
+    let events: Vec<String> = payload.events.unwrap_or_default();
+
+    match db::webhooks::create(pool, &payload.url, &secret, &events, payload.enabled.unwrap_or(true)).await {
+        Ok(subscription) => {
+            log_info!("Webhook subscription {} created for {:?}", subscription.id, subscription.events);
+            Ok(HttpResponse::Created().json(subscription))
+        },
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Replaces a webhook subscription, keeping its secret if the payload has none.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `id` - The id of the subscription.
+/// * `payload` - The new endpoint, secret, event types and enabled flag.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the subscription, or a 404 error if it does not exist.
+pub async fn update_webhook(pool: &PgPool, id: i64, payload: WebhookSubscriptionPayload) -> Result<HttpResponse, Error> {
+    let events: Vec<String> = payload.events.unwrap_or_default();
+
+    match db::webhooks::update(pool, id, &payload.url, payload.secret.as_deref(), &events, payload.enabled.unwrap_or(true)).await {
+        Ok(Some(subscription)) => Ok(HttpResponse::Ok().json(subscription)),
+        Ok(None) => Err(webhook_not_found(id)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Removes a webhook subscription and its delivery log.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or a 404 error if the subscription does not exist.
+pub async fn remove_webhook(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    match db::webhooks::remove(pool, id).await {
+        Ok(0) => Err(webhook_not_found(id)),
+        Ok(_) => {
+            log_info!("Webhook subscription {} removed", id);
+            Ok(HttpResponse::NoContent().finish())
+        },
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Sends a signed test event to a webhook subscription and waits for the endpoint.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the recorded `WebhookDelivery`, delivered or failed,
+/// or a 404 error if the subscription does not exist.
+pub async fn test_webhook(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    let subscription: WebhookSubscription = load_webhook(pool, id).await?;
+
+    let notification: Notification = Notification::new(
+        webhooks::TEST_EVENT,
+        format!("Test delivery to webhook subscription {}", id),
+        json!({ "subscription_id": id })
+    );
+    let payload: Value = serde_json::to_value(&notification).unwrap_or(Value::Null);
+
+    match webhooks::send_test(pool, &subscription, &payload).await {
+        Ok(delivery) => Ok(HttpResponse::Ok().json(delivery)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err)).to_string()
+        ))
+    }
+}
+
+/// Lists the most recent deliveries of a webhook subscription with their retry state.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `id` - The id of the subscription.
+/// * `query` - The number of deliveries and an optional status filter.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the deliveries, newest first, or a 404 error if the
+/// subscription does not exist.
+pub async fn list_webhook_deliveries(pool: &PgPool, id: i64, query: WebhookDeliveryQuery) -> Result<HttpResponse, Error> {
+    load_webhook(pool, id).await?;
+
+    match db::webhooks::deliveries(pool, id, query.status.as_deref(), query.limit.unwrap_or(50)).await {
+        Ok(deliveries) => Ok(HttpResponse::Ok().json(deliveries)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Lists the most recent top-ups of the gas wallet, the audit trail of the auto top-up policy.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - The number of top-ups to return.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the top-ups, newest first.
+pub async fn list_gas_topups(pool: &PgPool, query: GasTopUpQuery) -> Result<HttpResponse, Error> {
+    match db::topups::list(pool, query.limit.unwrap_or(50)).await {
+        Ok(topups) => Ok(HttpResponse::Ok().json(topups)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Builds the 404 error of a schedule that does not exist.
+fn schedule_not_found(id: i64) -> Error {
+    ErrorNotFound(Response::error(Value::String(format!("schedule {} does not exist", id))).to_string())
+}
+
+/// Lists the recurring schedules with the outcome of their last run.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the schedules in JSON format.
+pub async fn list_schedules(pool: &PgPool) -> Result<HttpResponse, Error> {
+    match db::schedules::list(pool).await {
+        Ok(schedules) => Ok(HttpResponse::Ok().json(schedules)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Creates a recurring schedule.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `payload` - The name, cron expression and operation template of the schedule.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the schedule, or a 400 error if the cron expression never matches.
+pub async fn create_schedule(pool: &PgPool, payload: SchedulePayload) -> Result<HttpResponse, Error> {
+    let next_run_at: u64 = scheduler::next_run(&payload.cron, ton::time_now()).map_err(| err | {
+        ErrorBadRequest(Response::error(Value::String(err)).to_string())
+    })?;
+
+    let created = db::schedules::create(
+        pool,
+        &payload.name,
+        &payload.cron,
+        &payload.template,
+        payload.priority.unwrap_or(JOB_PRIORITY_NORMAL),
+        payload.paused.unwrap_or(false),
+        next_run_at as i64
+    ).await;
+
+    match created {
+        Ok(schedule) => {
+            log_info!("Schedule {} created: {} `{}` on `{}`", schedule.id, schedule.name, schedule.kind, schedule.cron);
+            Ok(HttpResponse::Created().json(schedule))
+        },
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Pauses a schedule, or resumes it at the next occurrence of its cron expression.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `id` - The id of the schedule.
+/// * `paused` - Whether the schedule is paused or resumed.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the schedule, or a 404 error if it does not exist.
+pub async fn pause_schedule(pool: &PgPool, id: i64, paused: bool) -> Result<HttpResponse, Error> {
+    // a resumed schedule does not catch up on the runs it missed while paused
+    let next_run_at: Option<i64> = match paused {
+        true => None,
+        false => {
+            let schedule: Schedule = match db::schedules::get(pool, id).await {
+                Ok(Some(schedule)) => schedule,
+                Ok(None) => return Err(schedule_not_found(id)),
+                Err(err) => return Err(ErrorInternalServerError(
+                    Response::error(Value::String(err.to_string())).to_string()
+                ))
+            };
+
+            let next: u64 = scheduler::next_run(&schedule.cron, ton::time_now()).map_err(| err | {
+                ErrorInternalServerError(Response::error(Value::String(err)).to_string())
+            })?;
+            Some(next as i64)
+        }
+    };
+
+    match db::schedules::set_paused(pool, id, paused, next_run_at).await {
+        Ok(Some(schedule)) => {
+            log_info!("Schedule {} {}", id, if paused { "paused" } else { "resumed" });
+            Ok(HttpResponse::Ok().json(schedule))
+        },
+        Ok(None) => Err(schedule_not_found(id)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Removes a schedule, the jobs it already submitted are kept.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or a 404 error if the schedule does not exist.
+pub async fn remove_schedule(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    match db::schedules::remove(pool, id).await {
+        Ok(0) => Err(schedule_not_found(id)),
+        Ok(_) => {
+            log_info!("Schedule {} removed", id);
+            Ok(HttpResponse::NoContent().finish())
+        },
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Number of recent operations shown on the dashboard.
+const DASHBOARD_OPERATIONS: i64 = 10;
+
+/// Number of contract balances the dashboard fetches at once.
+const DASHBOARD_BALANCE_CONCURRENCY: usize = 8;
+
+/// Builds the landing page of the ops UI in one response.
+///
+/// Sections that fail to load are left empty and named in `errors`, so one unreachable
+/// liteserver or contract does not hide the rest of the dashboard.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the `Dashboard`.
+pub async fn dashboard(pool: &PgPool) -> Result<HttpResponse, Error> {
+    let mut errors: Vec<String> = Vec::new();
+
+    let balances: Option<Balances> = match mixer::balances().await {
+        Ok(balances) => Some(balances),
+        Err(err) => {
+            errors.push(format!("can not fetch the balances: {}", err));
+            None
+        }
+    };
+
+    let queue: Vec<JobQueueStats> = db::jobs::queue_stats(pool).await.unwrap_or_else(| err | {
+        errors.push(format!("can not read the jobs queue: {}", err));
+        Vec::new()
+    });
+
+    let operations: Vec<OutboxEntry> = db::outbox::recent(pool, DASHBOARD_OPERATIONS).await.unwrap_or_else(| err | {
+        errors.push(format!("can not read the recent operations: {}", err));
+        Vec::new()
+    });
+
+    let mut contracts: ContractPoolSummary = ContractPoolSummary::default();
+    match db::contracts::list(pool).await {
+        Ok(tracked) => {
+            for contract in tracked.iter() {
+                match contract.parent {
+                    Some(_) => contracts.forks += 1,
+                    None => contracts.roots += 1
+                }
+            }
+
+            // balances are fetched concurrently in batches, so the page does not wait for every fork in turn
+            let mut fetched: Vec<Nanotons> = Vec::new();
+            for batch in tracked.chunks(DASHBOARD_BALANCE_CONCURRENCY) {
+                let handles: Vec<_> = batch.iter().map(| contract | {
+                    let address: String = contract.address.clone();
+                    actix_web::rt::spawn(async move {
+                        match TonAddress::from_str(&address) {
+                            Ok(address) => ton::get_balance(&address).await,
+                            Err(err) => Err(err.to_string())
+                        }
+                    })
+                }).collect();
+
+                for handle in handles {
+                    match handle.await {
+                        Ok(Ok(balance)) => fetched.push(Nanotons::from_signed(balance)),
+                        _ => contracts.unreachable += 1
+                    }
+                }
+            }
+
+            contracts.balance = Nanotons::checked_sum(&fetched).unwrap_or_else(|| {
+                errors.push(String::from("the balance of the tracked contracts overflows"));
+                Nanotons::ZERO
+            });
+        },
+        Err(err) => errors.push(format!("can not read the tracked contracts: {}", err))
+    }
+
+    Ok(HttpResponse::Ok().json(Dashboard {
+        generated_at: ton::time_now() as i64,
+        balances,
+        queue,
+        operations,
+        alerts: DashboardAlerts {
+            paused: mixer::is_paused(pool).await,
+            multisig: multisig::enabled(),
+            degraded: ton::degraded(),
+            low_balances: alerts::low_balances()
+        },
+        contracts,
+        errors
+    }))
+}
+
+/// TON the faucet sends to every target, used when `FAUCET_AMOUNT` is not set.
+const DEFAULT_FAUCET_AMOUNT: f64 = 2.0;
+
+/// Funds the wallet and the mixer contract with testnet TON, for setting up a test environment.
+///
+/// The funds come from the faucet wallet of `FAUCET_WALLET_MNEMONIC` (protected by
+/// `FAUCET_WALLET_MNEMONIC_PASSWORD` if set), sent to all targets in one message, or are
+/// requested from the faucet at `FAUCET_URL`, which receives the address and the nanotons
+/// of every target, authenticated with `FAUCET_TOKEN` as a bearer token when it is set.
+///
+/// # Arguments
+///
+/// * `payload` - The targets and the amount.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing a grant per target, a 404 error outside of testnet,
+/// or a 503 error if no faucet is configured.
+pub async fn faucet(payload: FaucetPayload) -> Result<HttpResponse, Error> {
+    if Network::from_env() != Network::Testnet {
+        return Err(ErrorNotFound(Response::error(Value::String(String::from("the faucet is only available on testnet"))).to_string()));
+    }
+
+    let mnemonic: Option<String> = config::var("FAUCET_WALLET_MNEMONIC").ok().filter(| m | !m.is_empty());
+    let url: Option<String> = config::var("FAUCET_URL").ok().filter(| u | !u.is_empty());
+    if mnemonic.is_none() && url.is_none() {
+        return Err(ErrorServiceUnavailable(
+            Response::error(Value::String(String::from("no faucet is configured, set `FAUCET_WALLET_MNEMONIC` or `FAUCET_URL`"))).to_string()
+        ));
+    }
+
+    let amount: Nanotons = Nanotons::from_ton(payload.amount.unwrap_or_else(|| config::env_or("FAUCET_AMOUNT", DEFAULT_FAUCET_AMOUNT))).map_err(| err | {
+        ErrorBadRequest(Response::error(Value::String(err)).to_string())
+    })?;
+
+    let mut targets: Vec<FaucetTarget> = payload.targets.unwrap_or(vec![FaucetTarget::Wallet, FaucetTarget::Contract]);
+    targets.dedup();
+    let addresses: Vec<(FaucetTarget, TonAddress)> = targets.into_iter().map(| target | match target {
+        FaucetTarget::Wallet => (target, ton::wallet_address()),
+        FaucetTarget::Contract => (target, ton::mixer_contract_address())
+    }).collect();
+
+    let grant = | target: FaucetTarget, address: &TonAddress, source: &str, sent: Result<Option<String>, String> | {
+        let (tx_hash, error): (Option<String>, Option<String>) = match sent {
+            Ok(hash) => (hash, None),
+            Err(err) => (None, Some(err))
+        };
+        FaucetGrant { target, address: address.to_base64_url(), amount: amount.get(), source: source.to_string(), tx_hash, error }
+    };
+
+    let grants: Vec<FaucetGrant> = match mnemonic {
+        Some(mnemonic) => {
+            let password: Option<String> = config::var("FAUCET_WALLET_MNEMONIC_PASSWORD").ok().filter(| p | !p.is_empty());
+            let transfers: Vec<(TonAddress, u64)> = addresses.iter().map(| (_, address) | (address.clone(), amount.get())).collect();
+            let sent: Result<Option<String>, String> = ton::treasury_transfer(&mnemonic, password, "faucet", transfers).await.map(| (_, hash) | Some(hash.hex));
+
+            addresses.iter().map(| (target, address) | grant(*target, address, "wallet", sent.clone())).collect()
+        },
+        None => {
+            let url: String = url.unwrap_or_default();
+            let mut grants: Vec<FaucetGrant> = Vec::new();
+            for (target, address) in addresses.iter() {
+                let sent: Result<Option<String>, String> = request_faucet(&url, address, amount).await.map(|_| None);
+                grants.push(grant(*target, address, "url", sent));
+            }
+            grants
+        }
+    };
+
+    for grant in grants.iter() {
+        match &grant.error {
+            Some(err) => log_error!("Faucet could not fund the {:?} {}: {}", grant.target, grant.address, err),
+            None => log_info!("Faucet funded the {:?} {} with {} TON", grant.target, grant.address, amount)
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(grants))
+}
+
+/// Requests testnet TON for an address from the faucet at `url`.
+async fn request_faucet(url: &str, address: &TonAddress, amount: Nanotons) -> Result<(), String> {
+    let mut request: reqwest::RequestBuilder = trace::inject(reqwest::Client::new().post(url)).json(&json!({
+        "address": address.to_base64_url(),
+        "amount": amount.get()
+    }));
+
+    if let Ok(token) = config::var("FAUCET_TOKEN") {
+        request = request.bearer_auth(token);
+    }
+
+    request.send()
+        .await
+        .and_then(| r | r.error_for_status())
+        .map(|_| ())
+        .map_err(|e| format!("faucet rejected the request: {}", e))
+}
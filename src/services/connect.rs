@@ -0,0 +1,132 @@
+//! # TON Connect Services
+//!
+//! This module builds TON Connect transaction requests for the spread, collect and fork
+//! operations. Nothing is signed or sent by the server, which lets the mixer run in a
+//! non-custodial deployment where users sign with their own wallets.
+
+use actix_web::{error::{ErrorBadRequest, ErrorInternalServerError}, Error, HttpResponse};
+use base64::{Engine as _, engine::general_purpose};
+use serde_json::Value;
+use sqlx::PgPool;
+use tonlib::{address::TonAddress, cell::{BagOfCells, Cell}};
+
+use crate::{config::{env_or, Network}, services::{addressbook, mixer}, ton, types::{connect::{TonConnectMessage, TonConnectRequest}, CollectPayload, Response, SpreadWalletPayload}};
+
+/// Seconds a user has to confirm a request in their wallet.
+const CONNECT_TTL: u64 = 300;
+
+/// Returns the TON Connect network id requests are issued for.
+///
+/// Defaults to the id of `TON_NETWORK`.
+fn network() -> String {
+    env_or("TON_CONNECT_NETWORK", String::from(Network::from_env().global_id()))
+}
+
+/// Wraps a message body into a TON Connect transaction request.
+///
+/// # Arguments
+///
+/// * `contract` - The contract the message is sent to, usually a mixer contract.
+/// * `query_id` - The query id stored in the body.
+/// * `amount` - The nanotons attached to the message, gas included.
+/// * `body` - The message body.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request or an error.
+fn request(contract: &TonAddress, query_id: u64, amount: u64, body: Cell) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(build_request(contract, query_id, amount, body)?))
+}
+
+/// Builds the TON Connect transaction request of a message body, see `request`.
+pub fn build_request(contract: &TonAddress, query_id: u64, amount: u64, body: Cell) -> Result<TonConnectRequest, Error> {
+    let boc: Vec<u8> = match BagOfCells::from_root(body).serialize(true) {
+        Ok(boc) => boc,
+        Err(err) => {
+            return Err(ErrorInternalServerError(
+                Response::error(Value::String(err.to_string())).to_string()
+            ));
+        }
+    };
+
+    let request: TonConnectRequest = TonConnectRequest {
+        valid_until: ton::time_now() + CONNECT_TTL,
+        network: network(),
+        messages: vec![TonConnectMessage {
+            address: contract.to_base64_url(),
+            amount: amount.to_string(),
+            payload: general_purpose::STANDARD.encode(boc)
+        }],
+        query_id
+    };
+
+    Ok(request)
+}
+
+/// Builds the TON Connect request of a spread.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to spread through, `MIXER_CONTRACT` if `None`.
+/// * `wallets` - A vector of `SpreadWalletPayload` structs containing wallet addresses and amounts.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request or an error.
+pub async fn spread(pool: &PgPool, contract: Option<String>, wallets: &Vec<SpreadWalletPayload>) -> Result<HttpResponse, Error> {
+    let contract: TonAddress = mixer::resolve_verified_contract(pool, contract.as_deref()).await?;
+    let wallets: Vec<SpreadWalletPayload> = addressbook::resolve_wallets(pool, wallets).await?;
+    let (total_amount, recipients, _) = mixer::prepare_spread(&wallets, None, true).await?;
+
+    let query_id: u64 = ton::time_now();
+    let body: Cell = ton::spread_body(query_id, total_amount, recipients);
+
+    return request(&contract, query_id, total_amount.get().saturating_add(ton::spread_gas()), body);
+}
+
+/// Builds the TON Connect request of a collect.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to collect from, `MIXER_CONTRACT` if `None`.
+/// * `payload` - A `CollectPayload` struct containing collection details.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request or an error.
+pub async fn collect(pool: &PgPool, contract: Option<String>, payload: CollectPayload) -> Result<HttpResponse, Error> {
+    if payload.send_mode.is_some() {
+        return Err(ErrorBadRequest(Response::error(Value::String(
+            "`send_mode` is chosen by the wallet signing a TON Connect request".into()
+        )).to_string()));
+    }
+
+    let contract: TonAddress = mixer::resolve_verified_contract(pool, contract.as_deref()).await?;
+    let payload: CollectPayload = addressbook::resolve_collect(pool, payload).await?;
+
+    let query_id: u64 = ton::time_now();
+    let body: Cell = ton::collect_body(query_id, mixer::collect_message_data(payload)?);
+
+    return request(&contract, query_id, ton::collect_gas(), body);
+}
+
+/// Builds the TON Connect request of a fork.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to fork, `MIXER_CONTRACT` if `None`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request or an error.
+pub async fn fork(pool: &PgPool, contract: Option<String>) -> Result<HttpResponse, Error> {
+    let contract: TonAddress = mixer::resolve_verified_contract(pool, contract.as_deref()).await?;
+
+    let query_id: u64 = ton::time_now();
+    let body: Cell = ton::fork_body(query_id);
+
+    return request(&contract, query_id, ton::fork_gas(), body);
+}
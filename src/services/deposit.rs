@@ -0,0 +1,226 @@
+//! # Deposit Services
+//!
+//! This module provides service functions that help wallet users deposit into the mixer contract
+//! and that manage the payment requests incoming transfers are matched against.
+
+use std::{io::Cursor, sync::atomic::{AtomicU64, Ordering}};
+
+use actix_web::{error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound}, Error, HttpResponse};
+use image::{ImageFormat, Luma};
+use qrcode::{render::svg, QrCode};
+use serde_json::Value;
+use sqlx::PgPool;
+use tonlib::address::TonAddress;
+
+use crate::{config, db, services::mixer, strategy, ton, types::{deposit::{Deposit, DepositDetails, DepositLink, DepositLinkQuery, DepositPayload, DepositRequest, QrFormat}, nanotons::Nanotons, Response}};
+
+/// Minimum width and height of a rendered QR code in pixels.
+const QR_MIN_SIZE: u32 = 256;
+
+/// Seconds until a payment request expires, used when `DEPOSIT_TTL` is not set.
+const DEFAULT_TTL: u64 = 3600;
+
+/// Disambiguates tracking comments generated within the same nanosecond.
+static COMMENT_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Generates a unique tracking comment from the current time.
+fn tracking_comment() -> String {
+    let nanos: u128 = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    format!("dep-{:x}-{:x}", nanos, COMMENT_COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Builds a transfer link on top of the given base, e.g. `ton://transfer/`.
+fn transfer_link(base: &str, address: &str, amount: Option<&String>, comment: &str) -> String {
+    match amount {
+        Some(amount) => format!("{}{}?amount={}&text={}", base, address, amount, comment),
+        None => format!("{}{}?text={}", base, address, comment)
+    }
+}
+
+/// Builds the deeplinks of a deposit into the mixer contract.
+///
+/// The comment only contains URL-safe characters, so it is embedded without escaping.
+async fn build_link(pool: &PgPool, query: DepositLinkQuery) -> Result<DepositLink, Error> {
+    let contract: TonAddress = mixer::resolve_verified_contract(pool, query.contract.as_deref()).await?;
+
+    // validated amounts are positive and at most `MAX_TON`
+    let amount: Option<String> = query.amount.map(| a | Nanotons::from_ton(a).unwrap().get().to_string());
+    let comment: String = query.comment.unwrap_or_else(tracking_comment);
+
+    Ok(links(contract.to_base64_url(), amount, comment))
+}
+
+/// Builds the generic and wallet-specific deeplinks of a transfer.
+fn links(address: String, amount: Option<String>, comment: String) -> DepositLink {
+    DepositLink {
+        ton: transfer_link("ton://transfer/", &address, amount.as_ref(), &comment),
+        tonkeeper: transfer_link("https://app.tonkeeper.com/transfer/", &address, amount.as_ref(), &comment),
+        tonhub: transfer_link("https://tonhub.com/transfer/", &address, amount.as_ref(), &comment),
+        address,
+        amount,
+        comment
+    }
+}
+
+/// Returns the deeplinks of a deposit into the mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - The contract, amount and comment of the deposit.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the deeplinks or an error.
+pub async fn link(pool: &PgPool, query: DepositLinkQuery) -> Result<HttpResponse, Error> {
+    let link: DepositLink = build_link(pool, query).await?;
+    Ok(HttpResponse::Ok().json(link))
+}
+
+/// Renders the `ton://transfer` deeplink of a deposit as a QR code.
+///
+/// The tracking comment is returned in the `X-Deposit-Comment` header, so the
+/// frontend can match the deposit without decoding the image.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - The contract, amount and comment of the deposit.
+/// * `format` - The image format of the QR code.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the image or an error.
+pub async fn qr(pool: &PgPool, query: DepositLinkQuery, format: QrFormat) -> Result<HttpResponse, Error> {
+    let link: DepositLink = build_link(pool, query).await?;
+
+    let code: QrCode = match QrCode::new(link.ton.as_bytes()) {
+        Ok(code) => code,
+        Err(err) => {
+            return Err(ErrorInternalServerError(
+                Response::error(Value::String(err.to_string())).to_string()
+            ));
+        }
+    };
+
+    match format {
+        QrFormat::Svg => {
+            let image: String = code.render::<svg::Color>()
+                .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+                .build();
+
+            Ok(HttpResponse::Ok()
+                .content_type("image/svg+xml")
+                .insert_header(("X-Deposit-Comment", link.comment))
+                .body(image))
+        },
+        QrFormat::Png => {
+            let image = code.render::<Luma<u8>>()
+                .min_dimensions(QR_MIN_SIZE, QR_MIN_SIZE)
+                .build();
+
+            let mut png: Vec<u8> = Vec::new();
+            if let Err(err) = image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png) {
+                return Err(ErrorInternalServerError(
+                    Response::error(Value::String(err.to_string())).to_string()
+                ));
+            }
+
+            Ok(HttpResponse::Ok()
+                .content_type("image/png")
+                .insert_header(("X-Deposit-Comment", link.comment))
+                .body(png))
+        }
+    }
+}
+
+/// Creates a payment request with a unique comment.
+///
+/// The deposit watcher settles it once a transfer carrying the comment reaches the contract,
+/// and schedules the spread of its mixing plan if one is given. An allow-listed contract the
+/// indexer does not track yet is registered with it, as only indexed transfers are matched.
+/// A plan naming no strategy gets the strategy of the tenant, if it has one.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `payload` - The contract, expected amount and lifetime of the request.
+/// * `tenant` - The tenant of the token of the request, if any.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request and its deeplinks or an error.
+pub async fn create(pool: &PgPool, mut payload: DepositPayload, tenant: Option<String>) -> Result<HttpResponse, Error> {
+    let contract: TonAddress = mixer::resolve_verified_contract(pool, payload.contract.as_deref()).await?;
+
+    let amount: i64 = Nanotons::from_ton(payload.amount).unwrap().signed();
+    let ttl: u64 = payload.ttl.unwrap_or_else(|| config::env_or("DEPOSIT_TTL", DEFAULT_TTL));
+    let expires_at: i64 = (ton::time_now() + ttl) as i64;
+
+    if let Some(plan) = payload.plan.as_mut().filter(| p | p.strategy.is_none()) {
+        plan.strategy = tenant.as_deref().and_then(strategy::for_tenant).map(String::from);
+
+        if plan.strategy.as_deref() == Some("scheduled") && plan.schedule.is_none() {
+            return Err(ErrorBadRequest(
+                Response::error(Value::String(String::from("field `schedule` is required by the `scheduled` strategy of the tenant"))).to_string()
+            ));
+        }
+    }
+
+    let plan: Option<Value> = payload.plan.as_ref().map(| p | serde_json::to_value(p).unwrap());
+
+    if let Err(err) = db::contracts::register(pool, &contract.to_base64_url(), None, ton::time_now() as i64).await {
+        return Err(ErrorInternalServerError(
+            Response::error(Value::String(format!("can not register the contract with the indexer: {}", err))).to_string()
+        ));
+    }
+
+    let deposit: Deposit = match db::deposits::insert(pool, &contract.to_base64_url(), &tracking_comment(), amount, expires_at, plan.as_ref()).await {
+        Ok(deposit) => deposit,
+        Err(err) => {
+            return Err(ErrorInternalServerError(
+                Response::error(Value::String(err.to_string())).to_string()
+            ));
+        }
+    };
+
+    let request: DepositRequest = DepositRequest {
+        links: links(deposit.contract.clone(), Some(deposit.amount.to_string()), deposit.comment.clone()),
+        deposit
+    };
+
+    Ok(HttpResponse::Created().json(request))
+}
+
+/// Retrieves a payment request, its status and the jobs spreading it.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `id` - The id of the request.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request, or a 404 error if it does not exist.
+pub async fn get(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    let deposit: Deposit = match db::deposits::get(pool, id).await {
+        Ok(Some(deposit)) => deposit,
+        Ok(None) => {
+            return Err(ErrorNotFound(
+                Response::error(Value::String(format!("payment request {} not found", id))).to_string()
+            ));
+        },
+        Err(err) => {
+            return Err(ErrorInternalServerError(
+                Response::error(Value::String(err.to_string())).to_string()
+            ));
+        }
+    };
+
+    match db::jobs::by_deposit(pool, id).await {
+        Ok(jobs) => Ok(HttpResponse::Ok().json(DepositDetails { deposit, jobs })),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
@@ -0,0 +1,165 @@
+//! # Recipient Group Services
+//!
+//! This module provides service functions managing the recipient groups and spreading a
+//! total to the members of a group by their weights.
+
+use actix_web::{error::{ErrorBadRequest, ErrorConflict, ErrorInternalServerError, ErrorNotFound}, Error, HttpResponse};
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{db, services::mixer, types::{groups::{GroupSpreadPayload, RecipientGroup, RecipientGroupPayload}, Response, SpreadWalletPayload}};
+
+/// Returns the 404 error of a missing group.
+fn group_not_found(id: i64) -> Error {
+    ErrorNotFound(Response::error(Value::String(format!("recipient group {} does not exist", id))).to_string())
+}
+
+/// Maps the error of storing a group, a taken name is a 409 and an unknown entry a 400 error.
+fn store_error(err: sqlx::Error, name: &str) -> Error {
+    match err.as_database_error() {
+        Some(e) if e.is_unique_violation() => ErrorConflict(
+            Response::error(Value::String(format!("recipient group name `{}` is already taken", name))).to_string()
+        ),
+        Some(e) if e.is_foreign_key_violation() => ErrorBadRequest(
+            Response::error(Value::String(String::from("a member is not an address book entry"))).to_string()
+        ),
+        _ => ErrorInternalServerError(Response::error(Value::String(err.to_string())).to_string())
+    }
+}
+
+/// Returns the entry ids and weights of the members of a payload.
+fn members(payload: &RecipientGroupPayload) -> Vec<(i64, f64)> {
+    payload.members.iter().map(| m | (m.entry_id, m.weight.unwrap_or(1.0))).collect()
+}
+
+/// Lists the recipient groups with their members.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the groups or an error.
+pub async fn list(pool: &PgPool) -> Result<HttpResponse, Error> {
+    match db::groups::list(pool).await {
+        Ok(groups) => Ok(HttpResponse::Ok().json(groups)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Loads a recipient group.
+///
+/// # Returns
+///
+/// The group, or a 404 error if it does not exist.
+async fn load(pool: &PgPool, id: i64) -> Result<RecipientGroup, Error> {
+    match db::groups::get(pool, id).await {
+        Ok(Some(group)) => Ok(group),
+        Ok(None) => Err(group_not_found(id)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Returns a recipient group with its members.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the group, or a 404 error if it does not exist.
+pub async fn get(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(load(pool, id).await?))
+}
+
+/// Creates a recipient group.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `payload` - The name and the members of the group.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the group, a 400 error if a member is not an
+/// address book entry, or a 409 error if the name is taken.
+pub async fn create(pool: &PgPool, payload: RecipientGroupPayload) -> Result<HttpResponse, Error> {
+    match db::groups::create(pool, &payload.name, &members(&payload)).await {
+        Ok(group) => {
+            log_info!("Recipient group {} created: {} with {} members", group.id, group.name, group.members.len());
+            Ok(HttpResponse::Created().json(group))
+        },
+        Err(err) => Err(store_error(err, &payload.name))
+    }
+}
+
+/// Replaces the name and the members of a recipient group.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the group, or a 404 error if it does not exist and
+/// the errors of `create` otherwise.
+pub async fn update(pool: &PgPool, id: i64, payload: RecipientGroupPayload) -> Result<HttpResponse, Error> {
+    match db::groups::update(pool, id, &payload.name, &members(&payload)).await {
+        Ok(Some(group)) => {
+            log_info!("Recipient group {} updated: {} with {} members", group.id, group.name, group.members.len());
+            Ok(HttpResponse::Ok().json(group))
+        },
+        Ok(None) => Err(group_not_found(id)),
+        Err(err) => Err(store_error(err, &payload.name))
+    }
+}
+
+/// Removes a recipient group, its members stay in the address book.
+///
+/// # Returns
+///
+/// Returns an empty HTTP response or a 404 error if the group does not exist.
+pub async fn remove(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    match db::groups::remove(pool, id).await {
+        Ok(0) => Err(group_not_found(id)),
+        Ok(_) => {
+            log_info!("Recipient group {} removed", id);
+            Ok(HttpResponse::NoContent().finish())
+        },
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Spreads a total to the members of a recipient group, divided by their weights.
+///
+/// The weights become the percentages of a spread, see `mixer::percent_amounts`, so the
+/// legs add up to the total exactly.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The allow-listed mixer contract to spread through, `MIXER_CONTRACT` if omitted.
+/// * `payload` - The group and the total.
+/// * `wait` - Whether to wait for the transaction to be confirmed.
+///
+/// # Returns
+///
+/// Returns an HTTP response like a spread, or a 404 error if the group does not exist.
+pub async fn spread(pool: &PgPool, contract: Option<String>, payload: GroupSpreadPayload, wait: bool) -> Result<HttpResponse, Error> {
+    let group: RecipientGroup = load(pool, payload.group_id).await?;
+    let weights: f64 = group.members.iter().map(| m | m.weight).sum();
+
+    if group.members.is_empty() || weights <= 0.0 {
+        return Err(ErrorBadRequest(
+            Response::error(Value::String(format!("recipient group {} has no members", group.id))).to_string()
+        ));
+    }
+
+    let wallets: Vec<SpreadWalletPayload> = group.members.iter().map(| m | SpreadWalletPayload {
+        account: m.address.clone(),
+        amount: None,
+        amount_usd: None,
+        percent: Some(m.weight / weights * 100.0),
+        bounce: None,
+        send_mode: None
+    }).collect();
+
+    log_info!("Spreading {} TON to recipient group {} ({})", payload.total, group.id, group.name);
+    mixer::spread(pool, contract, &wallets, Some(payload.total), wait).await
+}
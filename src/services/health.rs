@@ -0,0 +1,66 @@
+//! # Health Services
+//!
+//! This module provides the readiness check load balancers use to decide whether an
+//! instance can actually broadcast messages, and the sync status of the liteserver.
+
+use actix_web::{error::ErrorServiceUnavailable, Error, HttpResponse};
+use serde_json::Value;
+
+use crate::{config, ton, types::{chain::{ChainInfo, Readiness}, Response}};
+
+/// Maximum liteserver lag in seconds, used when `READY_MAX_LAG` is not set.
+const DEFAULT_MAX_LAG: i64 = 60;
+
+/// Fetches the latest masterchain block and its lag behind the local clock.
+pub async fn chain_info() -> Result<ChainInfo, String> {
+    let info = ton::masterchain_info().await?;
+
+    Ok(ChainInfo {
+        seqno: info.seqno,
+        block_time: info.utime,
+        lag: ton::time_now() as i64 - info.utime,
+        backend: ton::backend().await.name().to_string(),
+        degraded: ton::degraded()
+    })
+}
+
+/// Checks that the liteserver is reachable and in sync.
+///
+/// # Returns
+///
+/// Returns a 200 response if the latest masterchain block is at most `READY_MAX_LAG`
+/// seconds old, and a 503 response if it is older or the liteserver is unreachable.
+pub async fn ready() -> Result<HttpResponse, Error> {
+    let max_lag: i64 = config::env_or("READY_MAX_LAG", DEFAULT_MAX_LAG);
+
+    let readiness: Readiness = match chain_info().await {
+        Ok(chain) if chain.lag <= max_lag => Readiness { ready: true, max_lag, chain: Some(chain), error: None },
+        Ok(chain) => Readiness {
+            ready: false,
+            max_lag,
+            error: Some(format!("liteserver is {} seconds behind", chain.lag)),
+            chain: Some(chain)
+        },
+        Err(err) => Readiness { ready: false, max_lag, chain: None, error: Some(err) }
+    };
+
+    if readiness.ready {
+        Ok(HttpResponse::Ok().json(readiness))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(readiness))
+    }
+}
+
+/// Retrieves the latest masterchain block and the lag observed by the API.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the chain info, or a 503 error if the liteserver is unreachable.
+pub async fn get_chain_info() -> Result<HttpResponse, Error> {
+    match chain_info().await {
+        Ok(chain) => Ok(HttpResponse::Ok().json(chain)),
+        Err(err) => Err(ErrorServiceUnavailable(
+            Response::error(Value::String(err)).to_string()
+        ))
+    }
+}
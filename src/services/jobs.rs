@@ -0,0 +1,70 @@
+//! # Job Services
+//!
+//! This module provides service functions managing the background jobs operations are
+//! queued as, e.g. the legs of a deposit or a time-locked collect.
+
+use actix_web::{error::{ErrorConflict, ErrorInternalServerError, ErrorNotFound}, Error, HttpResponse};
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{db, types::{jobs::{Job, JobListQuery, JobPage}, Response}};
+
+/// Number of jobs listed when the query does not set a limit.
+const DEFAULT_LIMIT: i64 = 50;
+
+/// Lists jobs matching the filters of the query, newest first.
+///
+/// Jobs carry their `attempts` and last `error`, so stuck or retried jobs can be found.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query` - The filters and the page.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the `JobPage` or an error.
+pub async fn list(pool: &PgPool, query: JobListQuery) -> Result<HttpResponse, Error> {
+    let limit: i64 = query.limit.unwrap_or(DEFAULT_LIMIT);
+
+    match db::jobs::list(pool, &query, limit).await {
+        Ok(jobs) => {
+            let next: Option<i64> = if jobs.len() as i64 == limit { jobs.last().map(| j | j.id) } else { None };
+            Ok(HttpResponse::Ok().json(JobPage { jobs, next }))
+        },
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Cancels a job that was not run yet.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `id` - The id of the job.
+/// * `reason` - Why the job is cancelled, stored with the job.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the cancelled job, a 404 error if there is no
+/// such job, or a 409 error if it already started or finished.
+pub async fn cancel(pool: &PgPool, id: i64, reason: Option<String>) -> Result<HttpResponse, Error> {
+    let internal = | err: sqlx::Error | ErrorInternalServerError(Response::error(Value::String(err.to_string())).to_string());
+
+    if let Some(job) = db::jobs::cancel(pool, id, reason.as_deref()).await.map_err(internal)? {
+        log_info!("Cancelled {} job {}: {}", job.kind, job.id, reason.as_deref().unwrap_or("no reason given"));
+        return Ok(HttpResponse::Ok().json(job));
+    }
+
+    let job: Option<Job> = db::jobs::get(pool, id).await.map_err(internal)?;
+    match job {
+        Some(job) => Err(ErrorConflict(
+            Response::error(Value::String(format!("job {} is {} and can no longer be cancelled", id, job.status))).to_string()
+        )),
+        None => Err(ErrorNotFound(
+            Response::error(Value::String(format!("job {} not found", id))).to_string()
+        ))
+    }
+}
@@ -3,53 +3,1030 @@
 //! This module provides service functions for a TON (The Open Network) mixer application,
 //! including spreading funds, collecting funds, forking, and retrieving opcodes and collection modes.
 
-use std::str::FromStr;
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
 
-use actix_web::{Error, HttpResponse};
-use num_bigint::BigUint;
-use tonlib::address::TonAddress;
+use actix_web::{error::{ErrorBadRequest, ErrorConflict, ErrorForbidden, ErrorInternalServerError, ErrorNotFound, ErrorServiceUnavailable, ErrorTooManyRequests, ErrorUnprocessableEntity}, Error, HttpResponse};
+use base64::{Engine as _, engine::general_purpose};
+use num_bigint::{BigInt, BigUint};
+use serde_json::Value;
+use sqlx::PgPool;
+use tonlib::{address::TonAddress, cell::{BagOfCells, Cell}, tl::{InternalTransactionId, RawTransaction}, types::TonHash};
 
-use crate::{ton::{self, contract_invoke_fork}, types::{CollectMessageData, CollectPayload, MixerCollectionModes, MixerOpcodes, SpreadWallet, SpreadWalletPayload}};
+use crate::{alerts, config, db, explorer, indexer, jettons, jobs, multisig, policy, rates, services::addressbook, ton::{self, contract_invoke_fork, dict}, types::{addressbook::AddressLabels, decode, jettons::{JettonBalance, JettonTotal}, jobs::{CollectBatchJob, CollectJob, Job, JOB_COLLECT, JOB_COLLECT_BATCH, JOB_PRIORITY_HIGH, JOB_PRIORITY_LOW, JOB_PRIORITY_NORMAL, JOB_REBALANCE}, nanotons::{Nanotons, NANOTONS_PER_TON}, rates::Rate, rebalance::{RebalanceJob, RebalanceMove, RebalancePayload, RebalancePlan}, split::{SplitRemainder, SplitSpreadPayload}, events::{ContractEdge, ContractNode, ContractTransactionsPage, ContractTree, MixerContract, MixerEvent}, outbox::{OperationLookup, OutboxEntry}, AccountBalance, AggregateBalance, Balances, CellStats, ChildAddress, CollectBatchPayload, CollectMessageData, CollectPayload, CollectTargets, Consolidation, MixedSpreadLegPayload, MixedSpreadReceipt, MixerCollectionModes, MixerOpcodes, OpcodeList, OperationReceipt, Response, SpreadFees, SpreadGroupReceipt, SpreadPreview, SpreadWallet, SpreadWalletPayload, WalletTransfer, ASSET_TON, DEFAULT_SEND_MODE, MAX_WALLET_MESSAGES, SEND_CARRY_ALL_BALANCE}, warnings};
+
+/// Pauses or resumes invocations of the mixer contract on every instance.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `paused` - Whether to pause the mixer.
+/// * `updated_by` - The operator or channel pausing or resuming it.
+pub async fn set_paused(pool: &PgPool, paused: bool, updated_by: &str) -> Result<(), String> {
+    db::state::set_paused(pool, paused, updated_by).await.map_err(|e| e.to_string())
+}
+
+/// Returns whether invocations of the mixer contract are paused.
+///
+/// The state is kept in the database, so a pause reaching any instance stops the one
+/// sending. A state that can not be read counts as paused.
+pub async fn is_paused(pool: &PgPool) -> bool {
+    match db::state::paused(pool).await {
+        Ok(paused) => paused,
+        Err(err) => {
+            log_error!("Can not read the pause state, treating the mixer as paused: {:?}", err);
+            true
+        }
+    }
+}
+
+/// Fails with a 503 error while the mixer is paused.
+pub async fn ensure_not_paused(pool: &PgPool) -> Result<(), Error> {
+    if is_paused(pool).await {
+        return Err(ErrorServiceUnavailable(
+            Response::error(Value::String(String::from("mixer is paused by the operator"))).to_string()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Fails with a 409 error when collects must be routed through the multisig.
+fn ensure_direct_collect() -> Result<(), Error> {
+    if multisig::enabled() {
+        return Err(ErrorConflict(
+            Response::error(Value::String(String::from("collects require multisig approval, use /multisig/collect"))).to_string()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Resolves the mixer contract an operation targets.
+///
+/// `MIXER_CONTRACT` is always allowed and used when no contract is given,
+/// any other contract has to be on the admin-managed allow-list.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The contract requested by the caller, if any.
+///
+/// # Returns
+///
+/// Returns the address of the contract, or a 400 error for an invalid address
+/// and a 403 error for a contract that is not allowed.
+pub async fn resolve_contract(pool: &PgPool, contract: Option<&str>) -> Result<TonAddress, Error> {
+    let default: TonAddress = ton::mixer_contract_address();
+
+    let address: TonAddress = match contract {
+        Some(c) => match TonAddress::from_str(c) {
+            Ok(address) => address,
+            Err(err) => {
+                return Err(ErrorBadRequest(
+                    Response::error(Value::String(err.to_string())).to_string()
+                ));
+            }
+        },
+        None => return Ok(default)
+    };
+
+    if address == default {
+        return Ok(address);
+    }
+
+    match db::allowlist::contains(pool, &address.to_base64_url()).await {
+        Ok(true) => Ok(address),
+        Ok(false) => Err(ErrorForbidden(
+            Response::error(Value::String(format!("contract {} is not allowed", address.to_base64_url()))).to_string()
+        )),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Resolves the mixer contract an operation targets and checks its code against `MIXER_CODE_HASH`.
+///
+/// # Returns
+///
+/// Returns the address of the contract, the errors of `resolve_contract`, a 409 error if
+/// the code does not match and a 503 error if it can not be fetched.
+pub async fn resolve_verified_contract(pool: &PgPool, contract: Option<&str>) -> Result<TonAddress, Error> {
+    let contract: TonAddress = resolve_contract(pool, contract).await?;
+
+    match ton::verify_code(&contract).await {
+        Ok(verification) if verification.verified == Some(false) => {
+            log_error!("Refusing to operate on {}: code hash {:?} is not expected", contract, verification.code_hash);
+            Err(ErrorConflict(
+                Response::error(Value::String(format!(
+                    "contract {} does not have the expected code, see GET /contract/verify", verification.contract
+                ))).to_string()
+            ))
+        },
+        Ok(_) => Ok(contract),
+        Err(err) => Err(ErrorServiceUnavailable(
+            Response::error(Value::String(format!("can not verify the code of contract {}: {}", contract, err))).to_string()
+        ))
+    }
+}
+
+/// Compares the code of a mixer contract with `MIXER_CODE_HASH`.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The allow-listed mixer contract, `MIXER_CONTRACT` if `None`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the `CodeVerification` of the contract.
+pub async fn verify_contract(pool: &PgPool, contract: Option<String>) -> Result<HttpResponse, Error> {
+    let contract: TonAddress = resolve_contract(pool, contract.as_deref()).await?;
+
+    match ton::verify_code(&contract).await {
+        Ok(verification) => Ok(HttpResponse::Ok().json(verification)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err)).to_string()
+        ))
+    }
+}
+
+/// Builds the fork tree of the tracked contracts with their current balances.
+///
+/// A balance that can not be fetched is left out rather than failing the whole tree.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the `ContractTree` in JSON format.
+pub async fn contract_tree(pool: &PgPool) -> Result<HttpResponse, Error> {
+    let contracts: Vec<MixerContract> = db::contracts::list(pool).await.map_err(| e | {
+        ErrorInternalServerError(Response::error(Value::String(e.to_string())).to_string())
+    })?;
+
+    let parents: BTreeMap<&str, Option<&str>> = contracts.iter()
+        .map(| c | (c.address.as_str(), c.parent.as_deref()))
+        .collect();
+
+    let mut nodes: Vec<ContractNode> = Vec::new();
+    let mut edges: Vec<ContractEdge> = Vec::new();
+    let mut total_balance: i64 = 0;
+
+    for contract in contracts.iter() {
+        // bounded by the number of contracts, so a corrupted parent cycle can not loop forever
+        let mut depth: u32 = 0;
+        let mut parent: Option<&str> = contract.parent.as_deref();
+        while let Some(address) = parent.filter(| _ | (depth as usize) < contracts.len()) {
+            depth += 1;
+            parent = parents.get(address).copied().flatten();
+        }
+
+        let balance: Option<i64> = match TonAddress::from_str(&contract.address) {
+            Ok(address) => match ton::get_balance(&address).await {
+                Ok(balance) => Some(balance),
+                Err(err) => {
+                    log_warn!("Can not fetch the balance of contract {}: {}", contract.address, err);
+                    None
+                }
+            },
+            Err(_) => None
+        };
+        total_balance += balance.unwrap_or(0);
+
+        if let Some(parent) = &contract.parent {
+            edges.push(ContractEdge { parent: parent.clone(), child: contract.address.clone() });
+        }
+
+        nodes.push(ContractNode {
+            address: contract.address.clone(),
+            parent: contract.parent.clone(),
+            depth,
+            created_at: contract.created_at,
+            balance
+        });
+    }
+
+    let roots: Vec<String> = contracts.iter().filter(| c | c.parent.is_none()).map(| c | c.address.clone()).collect();
+
+    Ok(HttpResponse::Ok().json(ContractTree { roots, nodes, edges, total_balance }))
+}
+
+/// Number of recipient accounts whose state is fetched at once when deriving bounce flags.
+const BOUNCE_STATE_CONCURRENCY: usize = 16;
+
+/// Returns the bounce flag stored for a spread recipient in the layout of `SPREAD_LAYOUT`.
+///
+/// The `legacy` layout stores no flag. In the `bounce` layout a recipient without one bounces
+/// unless its address is in the non-bounceable form, which `default_bounce` narrows down by
+/// the state of the account once the spread is known to fit a message.
+///
+/// # Returns
+///
+/// Returns the flag, or a 422 error for a `bounce` the layout can not store.
+fn bounce_flag(wallet: &SpreadWalletPayload) -> Result<Option<bool>, Error> {
+    match (dict::spread_layout(), wallet.bounce) {
+        (dict::SpreadLayout::Legacy, None) => Ok(None),
+        (dict::SpreadLayout::Legacy, Some(_)) => Err(ErrorUnprocessableEntity(Response::error(Value::String(format!(
+            "recipient {} sets `bounce`, which the contract only reads with `SPREAD_LAYOUT=bounce`", wallet.account
+        ))).to_string())),
+        (dict::SpreadLayout::Bounce, Some(bounce)) => Ok(Some(bounce)),
+        (dict::SpreadLayout::Bounce, None) => Ok(Some(!is_non_bounceable_form(&wallet.account)))
+    }
+}
+
+/// Derives the bounce flags of the spread recipients that did not set one.
+///
+/// A user-friendly address in the non-bounceable form never bounces. Otherwise
+/// the message only bounces when the account is already active, so funds sent
+/// to a fresh wallet stay there instead of being returned to the contract. The
+/// states are fetched concurrently, `BOUNCE_STATE_CONCURRENCY` at a time.
+///
+/// # Arguments
+///
+/// * `wallets` - The recipients as passed by the caller.
+/// * `recipients` - The recipients with the flags of `bounce_flag`, in the same order.
+///
+/// # Returns
+///
+/// Nothing, or an error if the state of an account can not be fetched.
+async fn default_bounce(wallets: &[SpreadWalletPayload], recipients: &mut [SpreadWallet]) -> Result<(), String> {
+    let pending: Vec<usize> = wallets.iter().enumerate()
+        .filter(| (i, v) | v.bounce.is_none() && recipients[*i].bounce == Some(true))
+        .map(| (i, _) | i)
+        .collect();
+
+    for batch in pending.chunks(BOUNCE_STATE_CONCURRENCY) {
+        let handles: Vec<_> = batch.iter().map(| i | {
+            let address: TonAddress = recipients[*i].account.clone();
+            actix_web::rt::spawn(async move { ton::is_account_active(&address).await })
+        }).collect();
+
+        for (i, handle) in batch.iter().zip(handles) {
+            let active: bool = handle.await.map_err(|e| e.to_string())??;
+            if !active {
+                warnings::warn(format!("recipient wallet {} is not initialized, sending non-bounceable", wallets[*i].account));
+                recipients[*i].bounce = Some(false);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns whether an address is given in the user-friendly non-bounceable form.
+fn is_non_bounceable_form(account: &str) -> bool {
+    TonAddress::from_base64_url_flags(account)
+        .or_else(| _ | TonAddress::from_base64_std_flags(account))
+        .map(| (_, non_bounceable, _) | non_bounceable)
+        .unwrap_or(false)
+}
+
+/// Locks in the TON/USD rate of an operation if any recipient amount is given in USD.
+async fn lock_rate(wallets: &Vec<SpreadWalletPayload>) -> Result<Option<Rate>, Error> {
+    if wallets.iter().all(| v | v.amount_usd.is_none()) {
+        return Ok(None);
+    }
+
+    match rates::ton_usd().await {
+        Ok(rate) => Ok(Some(rate)),
+        Err(err) => Err(ErrorServiceUnavailable(
+            Response::error(Value::String(format!("TON/USD rate is unavailable: {}", err))).to_string()
+        ))
+    }
+}
+
+/// Maps a violated spread limit to a 422 error.
+fn limit_violated(err: String) -> Error {
+    ErrorUnprocessableEntity(Response::error(Value::String(format!("{}: {}", policy::limits::LIMIT_VIOLATED, err))).to_string())
+}
+
+/// Parses a recipient or jetton address resolved from a payload or the database.
+///
+/// # Returns
+///
+/// Returns the address, or a 422 error if it is not a valid address.
+fn parse_address(address: &str) -> Result<TonAddress, Error> {
+    TonAddress::from_str(address).map_err(| err | {
+        ErrorUnprocessableEntity(Response::error(Value::String(format!("`{}` is not a valid address: {}", address, err))).to_string())
+    })
+}
+
+/// Checks the recipient amounts of a spread against the configured spread limits.
+fn check_spread_limits(amounts: &[Nanotons]) -> Result<(), Error> {
+    policy::limits::spread_limits().check(amounts, ton::time_now()).map_err(limit_violated)
+}
+
+/// Converts an amount in TON to nanotons, warning the caller if it had to be rounded.
+///
+/// # Returns
+///
+/// Returns the nanotons, or a 400 error if the amount is not a valid amount of TON.
+fn ton_to_nanotons(account: &str, ton: f64) -> Result<Nanotons, Error> {
+    let nano: Nanotons = Nanotons::from_ton(ton).map_err(| err | {
+        ErrorBadRequest(Response::error(Value::String(format!("amount of {}: {}", account, err))).to_string())
+    })?;
+
+    // tolerate the error of the float itself, e.g. 0.1 TON
+    let scaled: f64 = ton * NANOTONS_PER_TON as f64;
+    if (scaled - scaled.round()).abs() > 0.001 {
+        warnings::warn(format!("amount of {} rounded from {} to {} TON", account, ton, nano));
+    }
+
+    Ok(nano)
+}
+
+/// Warns the caller about watched accounts whose balance is low, and about running degraded.
+fn warn_alerts() {
+    for warning in alerts::low_balances() {
+        warnings::warn(warning);
+    }
+
+    if ton::degraded() {
+        warnings::warn("liteservers are unreachable, the operation goes through the toncenter HTTP fallback");
+    }
+}
+
+/// Returns the amount of a recipient in nanotons, converting USD amounts at the locked-in rate.
+fn recipient_nanotons(wallet: &SpreadWalletPayload, rate: Option<&Rate>) -> Result<Nanotons, Error> {
+    match (wallet.amount, wallet.amount_usd, rate) {
+        (Some(ton), _, _) => ton_to_nanotons(&wallet.account, ton),
+        (None, Some(usd), Some(rate)) => rate.usd_to_nanotons(usd).map_err(| err | {
+            ErrorBadRequest(Response::error(Value::String(format!("amount of {}: {}", wallet.account, err))).to_string())
+        }),
+        // validated payloads carry exactly one amount, the rate is locked for USD amounts
+        // and percentages are converted by `percent_amounts`
+        _ => Ok(Nanotons::ZERO)
+    }
+}
+
+/// Sum of the percentages of a spread may be off 100 by this much, to allow for rounded shares like 33.33.
+const PERCENT_TOLERANCE: f64 = 0.01;
+
+/// Returns the nanotons `percent` amounts of a spread are taken of.
+///
+/// The total is always given by the caller: the amount of a spread is attached from the
+/// wallet, so a total taken from the balance of the contract would send that much again.
+///
+/// # Arguments
+///
+/// * `wallets` - The recipients of the spread.
+/// * `total` - The total in TON given with the request, if any.
+///
+/// # Returns
+///
+/// The total, `None` if no recipient has a percentage, or a 400 error if it is missing or invalid.
+pub fn percent_base(wallets: &[SpreadWalletPayload], total: Option<f64>) -> Result<Option<Nanotons>, Error> {
+    if wallets.iter().all(| v | v.percent.is_none()) {
+        return Ok(None);
+    }
+
+    match total {
+        Some(total) => Nanotons::from_ton(total)
+            .map(Some)
+            .map_err(| e | ErrorBadRequest(Response::error(Value::String(e)).to_string())),
+        None => Err(ErrorBadRequest(
+            Response::error(Value::String(String::from("a spread with `percent` amounts needs a `total`"))).to_string()
+        ))
+    }
+}
+
+/// Converts the percentages of a spread to nanotons of `base`.
+///
+/// Every recipient needs a percentage and they must add up to 100. The shares are scaled
+/// to the actual sum and rounded down, and the nanotons left over go to the last recipient,
+/// so the amounts add up to `base` exactly.
+///
+/// # Returns
+///
+/// The amounts, or a 400 error if the percentages are invalid or there is no base.
+fn percent_amounts(wallets: &[SpreadWalletPayload], base: Option<Nanotons>) -> Result<Vec<Nanotons>, Error> {
+    let bad_request = | message: String | ErrorBadRequest(Response::error(Value::String(message)).to_string());
+
+    let percents: Vec<f64> = wallets.iter().map(| v | v.percent).collect::<Option<Vec<f64>>>()
+        .ok_or_else(|| bad_request(String::from("either every recipient of a spread or none has a `percent`")))?;
+    let base: Nanotons = base
+        .ok_or_else(|| bad_request(String::from("a spread with `percent` amounts needs a `total`")))?;
+
+    let sum: f64 = percents.iter().sum();
+    if (sum - 100.0).abs() > PERCENT_TOLERANCE {
+        return Err(bad_request(format!("percentages of the spread add up to {} instead of 100", sum)));
+    }
+
+    // percentages in billionths, so the division is exact integer arithmetic
+    let shares: Vec<u128> = percents.iter().map(| p | (p * 1e9).round() as u128).collect();
+    let total_shares: u128 = shares.iter().sum();
+
+    let mut amounts: Vec<Nanotons> = shares.iter()
+        .map(| share | Nanotons::from((base.get() as u128 * share / total_shares) as u64))
+        .collect();
+
+    let assigned: u64 = amounts.iter().map(| a | a.get()).sum();
+    if let Some(last) = amounts.last_mut() {
+        *last = Nanotons::from(last.get() + (base.get() - assigned));
+    }
+
+    Ok(amounts)
+}
+
+/// Converts the recipients of a spread to nanotons and checks them against the spread limits.
+///
+/// # Arguments
+///
+/// * `wallets` - The recipients of the spread.
+/// * `rate` - The rate USD amounts are converted at.
+/// * `base` - The nanotons `percent` amounts are taken of, see `percent_base`.
+/// * `check_limits` - Whether to check the spread limits, false for a held job an operator approved.
+///
+/// # Returns
+///
+/// Returns the amounts and their total, or a 400 error if an amount is invalid or the
+/// total overflows, and a 422 error if a limit is violated.
+fn spread_amounts(wallets: &[SpreadWalletPayload], rate: Option<&Rate>, base: Option<Nanotons>, check_limits: bool) -> Result<(Vec<Nanotons>, Nanotons), Error> {
+    let amounts: Vec<Nanotons> = match wallets.iter().any(| v | v.percent.is_some()) {
+        true => percent_amounts(wallets, base)?,
+        false => wallets.iter().map(| v | recipient_nanotons(v, rate)).collect::<Result<Vec<Nanotons>, Error>>()?
+    };
+    let total: Nanotons = Nanotons::checked_sum(&amounts).ok_or_else(|| {
+        ErrorBadRequest(Response::error(Value::String(String::from("the total amount of the spread is too large"))).to_string())
+    })?;
+    if check_limits {
+        check_spread_limits(&amounts)?;
+    }
+
+    Ok((amounts, total))
+}
+
+/// Seconds to wait for a message to be applied when the caller asked for confirmation,
+/// used when `CONFIRMATION_TIMEOUT` is not set.
+const DEFAULT_CONFIRMATION_TIMEOUT: u64 = 45;
+
+/// Answers with the receipt of a sent message, first waiting until it is applied on chain if requested.
+///
+/// The receipt then carries the transaction the message was applied in. A message not seen
+/// applied within `CONFIRMATION_TIMEOUT` seconds is answered with the receipt as sent and a warning.
+///
+/// # Arguments
+///
+/// * `tx` - The `OperationReceipt` of the sent message as JSON.
+/// * `wait` - Whether to wait for the message to be applied.
+async fn respond_confirmed(tx: String, wait: bool) -> Result<HttpResponse, Error> {
+    if !wait {
+        return Ok(HttpResponse::Ok().body(tx));
+    }
+
+    let mut receipt: OperationReceipt = serde_json::from_str(&tx).map_err(| e | {
+        ErrorInternalServerError(Response::error(Value::String(e.to_string())).to_string())
+    })?;
+    let timeout: Duration = Duration::from_secs(config::env_or("CONFIRMATION_TIMEOUT", DEFAULT_CONFIRMATION_TIMEOUT));
+
+    match ton::wait_for_transaction(&receipt.op, receipt.seqno, &receipt.hash.normalized_hex, timeout).await {
+        Ok(transaction) => receipt.transaction = Some(transaction),
+        Err(err) => warnings::warn(err)
+    }
+
+    Ok(HttpResponse::Ok().json(receipt))
+}
 
 /// Spreads funds across multiple wallets.
 ///
 /// # Arguments
 ///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to spread through, `MIXER_CONTRACT` if `None`.
 /// * `wallets` - A vector of `SpreadWalletPayload` structs containing wallet addresses and amounts.
+/// * `total` - TON `percent` amounts are taken of, required if a recipient has a `percent`.
+/// * `wait` - Whether to hold the response until the message is applied on chain.
 ///
 /// # Returns
 ///
 /// Returns an HTTP response containing the transaction details.
-pub async fn spread(wallets: &Vec<SpreadWalletPayload>) -> Result<HttpResponse, Error> {
-    let mut total_coins_amout: u64 = 0;
-    let serialized_closer_to_ton: Vec<SpreadWallet> = wallets.iter().map(| v | {
-        let nano = (v.amount * 1_000_000_000.0).round() as u64;
-        total_coins_amout += nano;
+pub async fn spread(pool: &PgPool, contract: Option<String>, wallets: &Vec<SpreadWalletPayload>, total: Option<f64>, wait: bool) -> Result<HttpResponse, Error> {
+    ensure_not_paused(pool).await?;
+    warn_alerts();
+    let contract: TonAddress = resolve_verified_contract(pool, contract.as_deref()).await?;
+    let wallets: Vec<SpreadWalletPayload> = addressbook::resolve_wallets(pool, wallets).await?;
+    let base: Option<Nanotons> = percent_base(&wallets, total)?;
+    let (total_coins_amout, serialized_closer_to_ton, rate) = prepare_spread(&wallets, base, true).await?;
+
+    let tx: String = ton::contract_invoke_spread(
+        pool,
+        contract,
+        total_coins_amout,
+        serialized_closer_to_ton,
+        rate.map(| r | r.rate)
+    ).await.map_err(send_failed)?;
+
+    respond_confirmed(tx, wait).await
+}
+
+/// Spreads a total amount divided evenly between the recipients of a split.
+///
+/// The total is divided in nanotons, and the nanotons left over go where `remainder` says,
+/// so the legs always add up to the total exactly.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The allow-listed mixer contract to spread through, `MIXER_CONTRACT` if omitted.
+/// * `payload` - The total and the recipients to divide it between.
+/// * `wait` - Whether to wait for the transaction to be confirmed.
+///
+/// # Returns
+///
+/// Returns an HTTP response like a spread, or a 400 error if the total is too small for every recipient to get a nanoton.
+pub async fn spread_split(pool: &PgPool, contract: Option<String>, payload: &SplitSpreadPayload, wait: bool) -> Result<HttpResponse, Error> {
+    let wallets: Vec<SpreadWalletPayload> = split_wallets(payload)?;
+
+    spread(pool, contract, &wallets, None, wait).await
+}
+
+/// Divides the total of a split into the recipients of a spread.
+fn split_wallets(payload: &SplitSpreadPayload) -> Result<Vec<SpreadWalletPayload>, Error> {
+    let recipients: Vec<(String, Option<bool>)> = match (&payload.accounts, &payload.template, payload.count) {
+        (Some(accounts), _, _) => accounts.iter().map(| a | (a.clone(), payload.bounce)).collect(),
+        (None, Some(template), Some(count)) => vec![(template.account.clone(), template.bounce); count as usize],
+        _ => Vec::new()
+    };
+
+    let total: Nanotons = Nanotons::from_ton(payload.total)
+        .map_err(| e | ErrorBadRequest(Response::error(Value::String(e)).to_string()))?;
+    let count: u64 = recipients.len() as u64;
+
+    if count == 0 || total.get() < count {
+        return Err(ErrorBadRequest(
+            Response::error(Value::String(format!("{} nanotons can not be split between {} recipients", total.get(), count))).to_string()
+        ));
+    }
+
+    let (share, remainder): (u64, u64) = (total.get() / count, total.get() % count);
 
-        SpreadWallet {
-            account: TonAddress::from_str(&v.account).unwrap(),
-            amount: BigUint::from_str(&nano.to_string()).unwrap()
+    let wallets: Vec<SpreadWalletPayload> = recipients.into_iter().enumerate().map(| (i, (account, bounce)) | {
+        let i: u64 = i as u64;
+        let extra: u64 = match payload.remainder {
+            SplitRemainder::Spread => if i < remainder { 1 } else { 0 },
+            SplitRemainder::First => if i == 0 { remainder } else { 0 },
+            SplitRemainder::Last => if i == count - 1 { remainder } else { 0 }
+        };
+
+        SpreadWalletPayload {
+            account,
+            amount: Some(Nanotons::from(share + extra).to_ton()),
+            amount_usd: None,
+            percent: None,
+            bounce,
+            send_mode: None
         }
     }).collect();
 
-    let tx: String = ton::contract_invoke_spread(
-        total_coins_amout,
-        serialized_closer_to_ton
-    ).await;
+    Ok(wallets)
+}
+
+/// Builds the spread message of a payload without touching the wallet or the network.
+///
+/// Amounts must be given in TON, as converting USD needs a rate, and percentages need a
+/// `total`. An omitted bounce flag only follows the address form: unlike a real spread,
+/// which also checks whether the recipient is deployed, a bounceable address bounces.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool, to resolve `@name` recipients.
+/// * `wallets` - A vector of `SpreadWalletPayload` structs containing wallet addresses and amounts.
+/// * `total` - TON `percent` amounts are taken of.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the `SpreadPreview`, a 400 error for USD amounts, or a
+/// 422 error if the recipients do not fit a spread message.
+pub async fn spread_preview(pool: &PgPool, wallets: &Vec<SpreadWalletPayload>, total: Option<f64>) -> Result<HttpResponse, Error> {
+    if wallets.iter().any(| v | v.amount_usd.is_some()) {
+        return Err(ErrorBadRequest(
+            Response::error(Value::String(String::from("a spread preview needs `amount` in TON, `amount_usd` is not converted"))).to_string()
+        ));
+    }
 
-    Ok(HttpResponse::Ok().body(tx))
+    let wallets: Vec<SpreadWalletPayload> = addressbook::resolve_wallets(pool, wallets).await?;
+    let base: Option<Nanotons> = percent_base(&wallets, total)?;
+    let (amounts, total_amount): (Vec<Nanotons>, Nanotons) = spread_amounts(&wallets, None, base, true)?;
+
+    let recipients: Vec<SpreadWallet> = wallets.iter().zip(amounts.iter()).map(| (v, nano) | Ok(SpreadWallet {
+        account: parse_address(&v.account)?,
+        amount: BigUint::from(*nano),
+        bounce: bounce_flag(v)?
+    })).collect::<Result<Vec<SpreadWallet>, Error>>()?;
+    check_spread_capacity(&recipients)?;
+
+    let gas: Nanotons = Nanotons::from(ton::spread_gas());
+    let query_id: u64 = ton::time_now();
+    let count: usize = recipients.len();
+    let fees: SpreadFees = ton::spread_fees(&recipients);
+    let body: Cell = ton::spread_body(query_id, total_amount, recipients);
+    let boc: Vec<u8> = BagOfCells::from_root(body.clone()).serialize(true).map_err(| e | {
+        ErrorInternalServerError(Response::error(Value::String(e.to_string())).to_string())
+    })?;
+
+    Ok(HttpResponse::Ok().json(SpreadPreview {
+        query_id,
+        boc: general_purpose::STANDARD.encode(boc),
+        hash: hex::encode(body.cell_hash()),
+        stats: CellStats::of(&body),
+        recipients: count,
+        total_amount,
+        gas,
+        fees,
+        total_with_fees: total_amount.checked_add(gas).unwrap_or(total_amount)
+    }))
+}
+
+/// Converts the recipients of a spread to nanotons and resolves their bounce flags.
+///
+/// The amounts are checked against the spread limits and the recipients against the capacity
+/// of a spread message, a violation is a 422 error.
+///
+/// # Arguments
+///
+/// * `wallets` - The recipients of the spread.
+/// * `base` - The nanotons `percent` amounts are taken of, see `percent_base`.
+/// * `check_limits` - Whether to check the spread limits, false for a held job an operator approved.
+///
+/// # Returns
+///
+/// Returns the total amount in nanotons, the recipients and the rate USD amounts were converted at.
+pub async fn prepare_spread(wallets: &Vec<SpreadWalletPayload>, base: Option<Nanotons>, check_limits: bool) -> Result<(Nanotons, Vec<SpreadWallet>, Option<Rate>), Error> {
+    if let Some(leg) = wallets.iter().find(| v | v.send_mode.is_some()) {
+        return Err(ErrorBadRequest(Response::error(Value::String(format!(
+            "recipient {} has a `send_mode`, which only direct spreads take as the contract sends the legs of a spread", leg.account
+        ))).to_string()));
+    }
+
+    let rate: Option<Rate> = lock_rate(wallets).await?;
+    let (amounts, total_coins_amout): (Vec<Nanotons>, Nanotons) = spread_amounts(wallets, rate.as_ref(), base, check_limits)?;
+
+    let mut serialized_closer_to_ton: Vec<SpreadWallet> = Vec::new();
+
+    for (v, nano) in wallets.iter().zip(amounts) {
+        serialized_closer_to_ton.push(SpreadWallet {
+            account: parse_address(&v.account)?,
+            amount: BigUint::from(nano),
+            bounce: bounce_flag(v)?
+        });
+    }
+    // the flag takes the same bit whatever the account state, so an oversized spread fails before any is fetched
+    check_spread_capacity(&serialized_closer_to_ton)?;
+
+    if let Err(err) = default_bounce(wallets, &mut serialized_closer_to_ton).await {
+        return Err(ErrorInternalServerError(
+            Response::error(Value::String(err)).to_string()
+        ));
+    }
+
+    Ok((total_coins_amout, serialized_closer_to_ton, rate))
+}
+
+/// Maps an error sending a message of the wallet to a 429 error if it would exceed the daily
+/// withdrawal limit, a 422 error if the wallet rejected it, a 503 error if the liteserver,
+/// the relayer or the database could not be reached.
+pub fn send_failed(err: String) -> Error {
+    if ton::limit_exceeded(&err) {
+        return ErrorTooManyRequests(Response::error(Value::String(err)).to_string());
+    }
+
+    match ton::send_rejected(&err) {
+        true => ErrorUnprocessableEntity(Response::error(Value::String(format!("the message was rejected: {}", err))).to_string()),
+        false => ErrorServiceUnavailable(Response::error(Value::String(format!("the message was not sent: {}", err))).to_string())
+    }
+}
+
+/// Checks that the recipients fit a spread message, a spread too large for the contract is a 422 error.
+///
+/// A spread close to a limit goes out with a warning naming the chunk size to split it into.
+fn check_spread_capacity(recipients: &[SpreadWallet]) -> Result<(), Error> {
+    match ton::check_spread_capacity(recipients) {
+        Ok(stats) => {
+            if let Some(warning) = ton::spread_capacity_warning(recipients.len(), &stats) {
+                warnings::warn(warning);
+            }
+            Ok(())
+        },
+        Err(err) => Err(ErrorUnprocessableEntity(
+            Response::error(Value::String(err)).to_string()
+        ))
+    }
+}
+
+/// Spreads funds directly from the wallet, bypassing the mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `wallets` - A vector of `SpreadWalletPayload` structs containing wallet addresses and amounts.
+/// * `total` - TON `percent` amounts are taken of.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the receipts of the sent external messages.
+pub async fn spread_direct(pool: &PgPool, wallets: &Vec<SpreadWalletPayload>, total: Option<f64>) -> Result<HttpResponse, Error> {
+    ensure_not_paused(pool).await?;
+    warn_alerts();
+    let wallets: Vec<SpreadWalletPayload> = addressbook::resolve_wallets(pool, wallets).await?;
+    let rate: Option<Rate> = lock_rate(&wallets).await?;
+    let base: Option<Nanotons> = percent_base(&wallets, total)?;
+    let (amounts, total): (Vec<Nanotons>, Nanotons) = spread_amounts(&wallets, rate.as_ref(), base, true)?;
+
+    // the whole balance goes with the first message sending it, later ones would fail
+    if wallets.len() > 1 && wallets.iter().any(| v | v.send_mode.is_some_and(| m | m & SEND_CARRY_ALL_BALANCE != 0)) {
+        return Err(ErrorBadRequest(Response::error(Value::String(
+            "a transfer carrying all the balance must be the only recipient of a direct spread".into()
+        )).to_string()));
+    }
+
+    let transfers: Vec<WalletTransfer> = wallets.iter().zip(amounts).map(| (v, nano) | {
+        Ok(WalletTransfer {
+            destination: parse_address(&v.account)?,
+            amount: BigUint::from(nano),
+            body: None,
+            mode: v.send_mode.unwrap_or(DEFAULT_SEND_MODE)
+        })
+    }).collect::<Result<Vec<WalletTransfer>, Error>>()?;
+
+    match ton::wallet_transfer(pool, "spread_direct", transfers, rate.map(| r | r.rate), Nanotons::ZERO).await {
+        Ok(receipts) => Ok(HttpResponse::Ok().json(receipts)),
+        Err(err) => Err(send_failed(err))
+    }
+}
+
+/// Spreads native TON and jettons in one operation.
+///
+/// TON legs are spread through the mixer contract in one spread message, jetton legs are
+/// sent as TEP-74 transfers from the jetton wallets of the gas wallet. All messages go out
+/// in one batch of wallet messages, so mixing assets costs no extra seqno round-trip.
+/// Jetton legs do not pass the mixer contract, which only mixes TON, but are held to the
+/// same spread limits: all legs count toward the recipients and the allowed hours, TON legs
+/// are checked against the TON amount limits and each jetton against the jetton ones.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to spread TON through, `MIXER_CONTRACT` if `None`.
+/// * `wallets` - The recipients and their assets.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the receipts of every asset group, a 422 error
+/// for a jetton that is not configured in `JETTON_MASTERS`, an invalid jetton amount or a
+/// violated spread limit.
+pub async fn spread_mixed(pool: &PgPool, contract: Option<String>, wallets: &Vec<MixedSpreadLegPayload>) -> Result<HttpResponse, Error> {
+    ensure_not_paused(pool).await?;
+    warn_alerts();
+    let limits: std::sync::Arc<policy::limits::SpreadLimits> = policy::limits::spread_limits();
+    limits.check_legs(wallets.len(), ton::time_now()).map_err(limit_violated)?;
+    let contract: TonAddress = resolve_verified_contract(pool, contract.as_deref()).await?;
+    let wallet: TonAddress = ton::wallet_address_for("spread_mixed");
+    let masters: Vec<TonAddress> = jettons::configured_masters();
+    let unprocessable = | message: String | ErrorUnprocessableEntity(Response::error(Value::String(message)).to_string());
+    let internal = | message: String | ErrorInternalServerError(Response::error(Value::String(message)).to_string());
+
+    // group the legs by asset, TON first, keeping the order of the legs within a group
+    let mut groups: BTreeMap<(bool, String), Vec<&MixedSpreadLegPayload>> = BTreeMap::new();
+    for leg in wallets {
+        let asset: String = match leg.asset() {
+            ASSET_TON => String::from(ASSET_TON),
+            master => parse_address(master)?.to_base64_url()
+        };
+        groups.entry((asset != ASSET_TON, asset)).or_default().push(leg);
+    }
+
+    let query_id: u64 = ton::time_now();
+    let mut transfers: Vec<WalletTransfer> = Vec::new();
+    // asset, number of legs, query id and the range of the group in `transfers`
+    let mut spans: Vec<(String, usize, u64, std::ops::Range<usize>)> = Vec::new();
+
+    for ((is_jetton, asset), legs) in groups {
+        let start: usize = transfers.len();
+
+        if !is_jetton {
+            let payloads: Vec<SpreadWalletPayload> = legs.iter().map(| leg | SpreadWalletPayload {
+                account: leg.account.clone(),
+                amount: Some(leg.amount),
+                amount_usd: None,
+                percent: None,
+                bounce: leg.bounce,
+                send_mode: None
+            }).collect();
+            // the TON legs form a single group
+            let (total, recipients, _) = prepare_spread(&payloads, None, true).await?;
+
+            transfers.push(WalletTransfer {
+                destination: contract.clone(),
+                amount: BigUint::from(total) + ton::spread_gas(),
+                body: Some(ton::spread_body(query_id, total, recipients)),
+                mode: DEFAULT_SEND_MODE
+            });
+        } else {
+            let master: TonAddress = parse_address(&asset)?;
+            if !masters.contains(&master) {
+                return Err(unprocessable(format!("jetton {} is not configured in `JETTON_MASTERS`", asset)));
+            }
+
+            let amounts: Vec<f64> = legs.iter().map(| leg | leg.amount).collect();
+            limits.check_jetton(&asset, &amounts).map_err(limit_violated)?;
+
+            let jetton_wallet: TonAddress = ton::get_jetton_wallet_address(&master, &wallet).await.map_err(internal)?;
+
+            for (index, leg) in legs.iter().enumerate() {
+                let amount: BigUint = jettons::to_units(&master, leg.amount).await.map_err(unprocessable)?;
+                let account: TonAddress = parse_address(&leg.account)?;
+
+                transfers.push(WalletTransfer {
+                    destination: jetton_wallet.clone(),
+                    amount: BigUint::from(ton::jetton_transfer_gas()),
+                    body: Some(ton::jetton_transfer_body(query_id + 1 + (start + index) as u64, &amount, &account, &wallet)),
+                    mode: DEFAULT_SEND_MODE
+                });
+            }
+        }
+
+        let query_id: u64 = if is_jetton { query_id + 1 + start as u64 } else { query_id };
+        spans.push((asset, legs.len(), query_id, start..transfers.len()));
+    }
+
+    let receipts: Vec<OperationReceipt> = ton::wallet_transfer(pool, "spread_mixed", transfers, None, Nanotons::ZERO).await.map_err(send_failed)?;
+
+    let groups: Vec<SpreadGroupReceipt> = spans.into_iter().map(| (asset, legs, query_id, range) | {
+        // every external message carries up to `MAX_WALLET_MESSAGES` transfers in order
+        let first: usize = range.start / MAX_WALLET_MESSAGES;
+        let last: usize = (range.end - 1) / MAX_WALLET_MESSAGES;
+
+        SpreadGroupReceipt {
+            asset,
+            legs,
+            query_id,
+            receipts: receipts[first..=last].to_vec()
+        }
+    }).collect();
+
+    Ok(HttpResponse::Ok().json(MixedSpreadReceipt { groups }))
 }
 
 /// Collects funds from the mixer.
 ///
 /// # Arguments
 ///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to collect from, `MIXER_CONTRACT` if `None`.
 /// * `payload` - A `CollectPayload` struct containing collection details.
+/// * `wait` - Whether to hold the response until the message is applied on chain.
 ///
 /// # Returns
 ///
-/// Returns an HTTP response containing the transaction details.
-pub async fn collect(payload: CollectPayload) -> Result<HttpResponse, Error> {
+/// Returns an HTTP response containing the transaction details, or a 202 response with the
+/// scheduled job when `min_dwell_hours` is set and the newest uncollected funds are younger than that.
+pub async fn collect(pool: &PgPool, contract: Option<String>, payload: CollectPayload, wait: bool) -> Result<HttpResponse, Error> {
+    ensure_not_paused(pool).await?;
+    warn_alerts();
+    ensure_direct_collect()?;
+    let contract: TonAddress = resolve_verified_contract(pool, contract.as_deref()).await?;
+    let payload: CollectPayload = addressbook::resolve_collect(pool, payload).await?;
+
+    if let Some(hours) = payload.min_dwell_hours {
+        let due: u64 = match dwell_due(pool, &contract, hours).await {
+            Ok(due) => due,
+            Err(err) => return Err(ErrorInternalServerError(
+                Response::error(Value::String(format!("can not fetch the age of the contract funds: {}", err))).to_string()
+            ))
+        };
+
+        if due > ton::time_now() {
+            let priority: i32 = payload.priority.unwrap_or(JOB_PRIORITY_HIGH);
+            let job: CollectJob = CollectJob { contract: contract.to_base64_url(), collect: payload };
+            let job: Job = match jobs::schedule(pool, JOB_COLLECT, &serde_json::to_value(&job).unwrap(), due, priority, None).await {
+                Ok(job) => job,
+                Err(err) => return Err(ErrorInternalServerError(
+                    Response::error(Value::String(format!("can not schedule the collect: {}", err))).to_string()
+                ))
+            };
+
+            log_info!("Collect from {} is deferred to {} by a dwell time of {} hours", contract, due, hours);
+            return Ok(HttpResponse::Accepted().json(job));
+        }
+    }
+
+    let send_mode: u8 = payload.send_mode.unwrap_or(DEFAULT_SEND_MODE);
+    let tx: String = ton::contract_invoke_collect(pool, contract, collect_message_data(payload)?, send_mode).await.map_err(send_failed)?;
+    respond_confirmed(tx, wait).await
+}
+
+/// Schedules a batch collect from several mixer contracts as a job.
+///
+/// The job sends the collects one after another, see `jobs::run`, so the request returns
+/// before the first of them is sent and the results are stored with the job.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `payload` - A `CollectBatchPayload` struct with the contracts and the collection mode.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the scheduled job, whose result is the `CollectBatchReceipt`.
+pub async fn collect_batch(pool: &PgPool, payload: CollectBatchPayload) -> Result<HttpResponse, Error> {
+    ensure_not_paused(pool).await?;
+    ensure_direct_collect()?;
+
+    // tracked contracts were deployed by the mixer itself, listed ones must be allowed
+    let (contracts, tracked): (Vec<String>, bool) = match payload.contracts {
+        CollectTargets::Contracts(contracts) => (contracts, false),
+        CollectTargets::Keyword(_) => match db::contracts::list(pool).await {
+            Ok(contracts) => (contracts.into_iter().map(| c | c.address).collect(), true),
+            Err(err) => return Err(ErrorInternalServerError(
+                Response::error(Value::String(format!("can not list the tracked contracts: {}", err))).to_string()
+            ))
+        }
+    };
+
+    let job: CollectBatchJob = CollectBatchJob { contracts, tracked, mode: payload.mode };
+    match jobs::schedule(pool, JOB_COLLECT_BATCH, &serde_json::to_value(&job).unwrap(), ton::time_now(), JOB_PRIORITY_NORMAL, None).await {
+        Ok(scheduled) => {
+            log_info!("Batch collect from {} contracts is scheduled as job {}", job.contracts.len(), scheduled.id);
+            Ok(HttpResponse::Accepted().json(scheduled))
+        },
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(format!("can not schedule the batch collect: {}", err))).to_string()
+        ))
+    }
+}
+
+/// Resolves a contract of a batch collect and checks its code.
+pub async fn prepare_batch_collect(pool: &PgPool, contract: &str, tracked: bool) -> Result<TonAddress, Error> {
+    let address: TonAddress = match tracked {
+        false => resolve_verified_contract(pool, Some(contract)).await?,
+        true => {
+            let address: TonAddress = TonAddress::from_str(contract).map_err(| e | {
+                ErrorBadRequest(Response::error(Value::String(e.to_string())).to_string())
+            })?;
+
+            match ton::verify_code(&address).await {
+                Ok(verification) if verification.verified == Some(false) => return Err(ErrorConflict(
+                    Response::error(Value::String(String::from("contract does not have the expected code"))).to_string()
+                )),
+                Ok(_) => address,
+                Err(err) => return Err(ErrorServiceUnavailable(
+                    Response::error(Value::String(format!("can not verify the code: {}", err))).to_string()
+                ))
+            }
+        }
+    };
+
+    Ok(address)
+}
+
+/// Returns the message of an error response, or the error itself if it is not one.
+pub fn error_message(err: &Error) -> String {
+    let body: String = err.to_string();
+
+    match serde_json::from_str::<Value>(&body).ok().and_then(| v | v.get("message").cloned()) {
+        Some(Value::String(message)) => message,
+        Some(message) => message.to_string(),
+        None => body
+    }
+}
+
+/// Returns the time the newest uncollected funds of a contract reach a dwell time.
+///
+/// The indexed events are read as they are, the indexer runs on the leader only. Transactions
+/// made since its last pass are fetched and decoded without being recorded, so a deposit the
+/// indexer did not reach yet still counts.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract.
+/// * `hours` - The dwell time in hours.
+///
+/// # Returns
+///
+/// The unix time, in the past when all funds are old enough or none were deposited.
+pub async fn dwell_due(pool: &PgPool, contract: &TonAddress, hours: u32) -> Result<u64, String> {
+    let address: String = contract.to_base64_url();
+    let mut newest: Option<i64> = db::events::newest_uncollected(pool, &address).await.map_err(|e| e.to_string())?;
+    let last_lt: i64 = db::contracts::last_lt(pool, &address).await.map_err(|e| e.to_string())?.unwrap_or(0);
+
+    for transaction in ton::get_transactions_since(contract, last_lt).await?.iter() {
+        let event: MixerEvent = indexer::to_event(&address, transaction);
+        match event.op.as_str() {
+            "collect" => newest = None,
+            _ if event.value_in > 0 => newest = Some(event.utime),
+            _ => {}
+        }
+    }
+
+    Ok(newest.map(| utime | utime as u64 + hours as u64 * 3600).unwrap_or(0))
+}
+
+/// Converts a validated collect payload into the data of a collect message.
+///
+/// # Returns
+///
+/// Returns the data, or a 422 error if the resolved jetton wallet is not a valid address.
+pub fn collect_message_data(payload: CollectPayload) -> Result<CollectMessageData, Error> {
     let mut collect_message_data: CollectMessageData = CollectMessageData {
         mode: payload.mode,
         jetton_wallet: None,
@@ -57,37 +1034,328 @@ pub async fn collect(payload: CollectPayload) -> Result<HttpResponse, Error> {
     };
 
     if let Some(w) = payload.jetton_wallet {
-        collect_message_data.jetton_wallet = Some(TonAddress::from_str(&w).unwrap());
+        collect_message_data.jetton_wallet = Some(parse_address(&w)?);
     }
 
     if let Some(a) = payload.amount {
-        let nano: u64 = (a * 1_000_000_000.0).round() as u64;
+        let nano: Nanotons = ton_to_nanotons("the collect", a)?;
         collect_message_data.amount = Some(BigUint::from(nano))
     }
 
-    let tx = ton::contract_invoke_collect(collect_message_data).await;
-    Ok(HttpResponse::Ok().body(tx))
+    Ok(collect_message_data)
 }
 
 /// Invokes the fork operation on the mixer contract.
 ///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to fork, `MIXER_CONTRACT` if `None`.
+/// * `query_id` - The query id of the fork, the current time if `None`.
+/// * `wait` - Whether to hold the response until the message is applied on chain.
+///
 /// # Returns
 ///
 /// Returns an HTTP response containing the transaction details.
-pub async fn fork() -> Result<HttpResponse, Error> {
-    let a = contract_invoke_fork().await;
-    Ok(HttpResponse::Ok().body(a))
+pub async fn fork(pool: &PgPool, contract: Option<String>, query_id: Option<u64>, wait: bool) -> Result<HttpResponse, Error> {
+    ensure_not_paused(pool).await?;
+    warn_alerts();
+    let contract: TonAddress = resolve_verified_contract(pool, contract.as_deref()).await?;
+
+    let a: String = contract_invoke_fork(pool, contract, query_id.unwrap_or_else(ton::time_now)).await.map_err(send_failed)?;
+    respond_confirmed(a, wait).await
+}
+
+/// Derives the address a fork of a mixer contract with the given query id deploys its child to.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract that is forked, `MIXER_CONTRACT` if `None`.
+/// * `query_id` - The query id the fork will be sent with.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the `ChildAddress`, a 503 error if the code of the
+/// contract can not be read, or a 409 error unless an indexed fork of the same code was
+/// deployed to the address the derivation gives, so no funds are sent to a wrong address.
+pub async fn child_address(pool: &PgPool, contract: Option<String>, query_id: u64) -> Result<HttpResponse, Error> {
+    let contract: TonAddress = resolve_verified_contract(pool, contract.as_deref()).await?;
+    let unavailable = | message: String | ErrorServiceUnavailable(Response::error(Value::String(message)).to_string());
+    let conflict = | message: String | ErrorConflict(Response::error(Value::String(message)).to_string());
+
+    let child: ChildAddress = ton::child_address(&contract, query_id).await.map_err(unavailable)?;
+    let code_hash: String = child.code_hash.clone().ok_or_else(|| unavailable(format!("the code hash of {} is not available", child.parent)))?;
+
+    let forks: Vec<(String, i64, String)> = db::contracts::indexed_forks(pool, CHILD_VERIFICATION_FORKS).await
+        .map_err(| e | ErrorInternalServerError(Response::error(Value::String(e.to_string())).to_string()))?;
+
+    match ton::verify_child_derivation(&code_hash, &forks).await {
+        Ok(Some(true)) => Ok(HttpResponse::Ok().json(child)),
+        Ok(Some(false)) => Err(conflict(String::from(
+            "the derived child address does not match where forks of this code deploy their children, do not fund it"
+        ))),
+        Ok(None) => Err(conflict(String::from(
+            "the child address can not be verified yet, fork once without funding the child so the indexer can check the derivation"
+        ))),
+        Err(err) => Err(unavailable(err))
+    }
+}
+
+/// Number of the most recent indexed forks searched for one verifying a child address.
+const CHILD_VERIFICATION_FORKS: i64 = 16;
+
+/// Balance in nanotons below which a fork counts as dust, used when `CONSOLIDATE_THRESHOLD` is not set.
+const DEFAULT_CONSOLIDATE_THRESHOLD: u64 = NANOTONS_PER_TON;
+
+/// Balance in nanotons the gas wallet keeps when consolidating, used when `CONSOLIDATE_WALLET_RESERVE` is not set.
+const DEFAULT_CONSOLIDATE_WALLET_RESERVE: u64 = 2 * NANOTONS_PER_TON;
+
+/// Gathers dust left on forks and the gas wallet back into the mixer contract.
+///
+/// Forks holding less than `CONSOLIDATE_THRESHOLD` TON, but more than the gas of a
+/// collect, are collected in mode 2 into the gas wallet, and everything the gas wallet
+/// holds above `CONSOLIDATE_WALLET_RESERVE` TON is sent to the mixer contract, all in
+/// one batch of wallet messages. Collected funds reach the gas wallet after the pass,
+/// so they are moved into the contract by the next one.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the consolidated forks and the receipts, or an error.
+pub async fn consolidate(pool: &PgPool) -> Result<HttpResponse, Error> {
+    ensure_not_paused(pool).await?;
+    ensure_direct_collect()?;
+
+    let threshold: i64 = policy::nanotons_from_env("CONSOLIDATE_THRESHOLD").unwrap_or(Nanotons::from(DEFAULT_CONSOLIDATE_THRESHOLD)).signed();
+    let reserve: i64 = policy::nanotons_from_env("CONSOLIDATE_WALLET_RESERVE").unwrap_or(Nanotons::from(DEFAULT_CONSOLIDATE_WALLET_RESERVE)).signed();
+
+    let root: TonAddress = ton::mixer_contract_address();
+    let contracts: Vec<MixerContract> = db::contracts::list(pool).await.map_err(| e | {
+        ErrorInternalServerError(Response::error(Value::String(e.to_string())).to_string())
+    })?;
+
+    let query_id: u64 = ton::time_now();
+    let mut forks: Vec<String> = Vec::new();
+    let mut transfers: Vec<WalletTransfer> = Vec::new();
+    let mut collected: Nanotons = Nanotons::ZERO;
+
+    for contract in contracts.iter().filter(| c | c.parent.is_some()) {
+        let address: TonAddress = parse_address(&contract.address)?;
+        let balance: i64 = ton::get_balance(&address).await.map_err(| e | {
+            ErrorInternalServerError(Response::error(Value::String(e)).to_string())
+        })?;
+
+        if balance <= ton::collect_gas() as i64 || balance >= threshold {
+            continue;
+        }
+
+        let data: CollectMessageData = CollectMessageData {
+            mode: MixerCollectionModes::new().available_ton_balance,
+            jetton_wallet: None,
+            amount: None
+        };
+
+        transfers.push(WalletTransfer {
+            destination: address,
+            amount: BigUint::from(ton::collect_gas()),
+            body: Some(ton::collect_body(query_id, data)),
+            mode: DEFAULT_SEND_MODE
+        });
+        forks.push(contract.address.clone());
+        collected = collected.checked_add(Nanotons::from_signed(balance)).unwrap_or(collected);
+    }
+
+    let wallet_balance: i64 = ton::get_balance(&ton::wallet_address_for("consolidate")).await.map_err(| e | {
+        ErrorInternalServerError(Response::error(Value::String(e)).to_string())
+    })?;
+
+    // the collect gas is paid from the wallet as well, keep it on top of the reserve
+    let spare: i64 = wallet_balance - reserve - (transfers.len() as i64 * ton::collect_gas() as i64);
+    let wallet_amount: u64 = spare.max(0) as u64;
+
+    if wallet_amount > 0 {
+        transfers.push(WalletTransfer {
+            destination: root,
+            amount: BigUint::from(wallet_amount),
+            body: None,
+            mode: DEFAULT_SEND_MODE
+        });
+    }
+
+    if transfers.is_empty() {
+        return Ok(HttpResponse::Ok().json(Consolidation { forks, wallet_amount, receipts: Vec::new() }));
+    }
+
+    match ton::wallet_transfer(pool, "consolidate", transfers, None, collected).await {
+        Ok(receipts) => Ok(HttpResponse::Ok().json(Consolidation { forks, wallet_amount, receipts })),
+        Err(err) => Err(send_failed(err))
+    }
+}
+
+/// Percent of its target a contract may be off by without being rebalanced, used when the payload omits it.
+const DEFAULT_REBALANCE_TOLERANCE: f64 = 5.0;
+
+/// Plans moving funds between tracked contracts so each holds its share of their total.
+///
+/// Contracts above their target are collected in mode 2 into the gas wallet, and the
+/// collected funds are spread through the mixer contract to every contract below its
+/// target, including the collected ones. The moves are executed by a background job,
+/// whose progress is reported at `GET /rebalance/{id}`. The funds stay in the pool, so
+/// the moves do not count against the withdrawal limits.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `payload` - A `RebalancePayload` struct with the target weights of the contracts.
+///
+/// # Returns
+///
+/// Returns a 202 response with the scheduled job, or the plan if no funds have to move.
+pub async fn rebalance(pool: &PgPool, payload: RebalancePayload) -> Result<HttpResponse, Error> {
+    ensure_not_paused(pool).await?;
+    ensure_direct_collect()?;
+
+    let plan: RebalancePlan = plan_rebalance(pool, &payload).await?;
+    if plan.is_empty() {
+        return Ok(HttpResponse::Ok().json(plan));
+    }
+
+    let job: RebalanceJob = RebalanceJob { plan };
+    match jobs::schedule(pool, JOB_REBALANCE, &serde_json::to_value(&job).unwrap(), ton::time_now(), payload.priority.unwrap_or(JOB_PRIORITY_LOW), None).await {
+        Ok(job) => {
+            log_info!("Rebalance of {} contracts is scheduled as job {}", payload.targets.len(), job.id);
+            Ok(HttpResponse::Accepted().json(job))
+        },
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(format!("can not schedule the rebalance: {}", err))).to_string()
+        ))
+    }
+}
+
+/// Plans a rebalance from the current balances of the target contracts.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `payload` - A `RebalancePayload` struct with the target weights of the contracts.
+///
+/// # Returns
+///
+/// The `RebalancePlan`, empty if no funds have to move, or a 400 error if a contract is
+/// not tracked, a 422 error if collects and spreads use different accounts and a 503 error
+/// if a balance can not be fetched.
+pub async fn plan_rebalance(pool: &PgPool, payload: &RebalancePayload) -> Result<RebalancePlan, Error> {
+    let bad_request = | message: String | ErrorBadRequest(Response::error(Value::String(message)).to_string());
+
+    if let Err(err) = ton::ensure_rebalance_accounts() {
+        return Err(ErrorUnprocessableEntity(Response::error(Value::String(err)).to_string()));
+    }
+
+    let mut tracked: Vec<String> = db::contracts::list(pool).await.map_err(| e | {
+        ErrorInternalServerError(Response::error(Value::String(e.to_string())).to_string())
+    })?.into_iter().map(| c | c.address).collect();
+    tracked.push(ton::mixer_contract_address().to_base64_url());
+
+    let mut balances: Vec<(String, f64, i64)> = Vec::new();
+    for target in payload.targets.iter() {
+        let address: TonAddress = TonAddress::from_str(&target.contract).map_err(| e | bad_request(e.to_string()))?;
+        let contract: String = address.to_base64_url();
+
+        if !tracked.contains(&contract) {
+            return Err(bad_request(format!("contract {} is not tracked, see GET /contracts/tree", contract)));
+        }
+
+        if balances.iter().any(| (c, _, _) | *c == contract) {
+            return Err(bad_request(format!("contract {} is listed twice", contract)));
+        }
+
+        let balance: i64 = ton::get_balance(&address).await.map_err(| e | {
+            ErrorServiceUnavailable(Response::error(Value::String(format!("can not fetch the balance of {}: {}", contract, e))).to_string())
+        })?;
+        balances.push((contract, target.weight, balance));
+    }
+
+    Ok(rebalance_plan(&balances, payload.tolerance.unwrap_or(DEFAULT_REBALANCE_TOLERANCE) / 100.0))
+}
+
+/// Computes the moves that bring contracts to their share of the total.
+///
+/// # Arguments
+///
+/// * `balances` - The address, weight and balance in nanotons of every contract.
+/// * `tolerance` - Fraction of its target a contract may be off by without being touched.
+fn rebalance_plan(balances: &[(String, f64, i64)], tolerance: f64) -> RebalancePlan {
+    let total: i64 = balances.iter().map(| (_, _, balance) | *balance).sum();
+    let weights: f64 = balances.iter().map(| (_, weight, _) | *weight).sum();
+
+    let moves: Vec<RebalanceMove> = balances.iter().map(| (contract, weight, balance) | {
+        let target: i64 = (total as f64 * weight / weights) as i64;
+        let slack: i64 = (target as f64 * tolerance) as i64;
+
+        // a collect takes the whole available balance, so a collected contract is refilled to its target
+        let collect: bool = *balance - target > slack && *balance > ton::collect_gas() as i64;
+        let send: u64 = match collect {
+            true => target.max(0) as u64,
+            false if target - *balance > slack => (target - *balance) as u64,
+            false => 0
+        };
+
+        RebalanceMove { contract: contract.clone(), balance: *balance, target, collect, send }
+    }).collect();
+
+    RebalancePlan { total, moves }
+}
+
+/// Returns a rebalance job with its progress.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `id` - The id of the job.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the job, or a 404 error if there is no such rebalance.
+pub async fn rebalance_job(pool: &PgPool, id: i64) -> Result<HttpResponse, Error> {
+    match db::jobs::get(pool, id).await {
+        Ok(Some(job)) if job.kind == JOB_REBALANCE => Ok(HttpResponse::Ok().json(job)),
+        Ok(_) => Err(ErrorNotFound(
+            Response::error(Value::String(format!("rebalance {} not found", id))).to_string()
+        )),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err.to_string())).to_string()
+        ))
+    }
+}
+
+/// Retrieves the current TON/USD rate.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the rate and its source, or a 503 error
+/// if the price source is unavailable.
+pub async fn get_rates() -> Result<HttpResponse, Error> {
+    match rates::ton_usd().await {
+        Ok(rate) => Ok(HttpResponse::Ok().json(rate)),
+        Err(err) => Err(ErrorServiceUnavailable(
+            Response::error(Value::String(err)).to_string()
+        ))
+    }
 }
 
 /// Retrieves the opcodes for mixer operations.
 ///
 /// # Returns
 ///
-/// Returns an HTTP response containing the opcodes in JSON format.
+/// Returns an HTTP response containing the opcodes in decimal and in hex in JSON format.
 pub async fn get_opcodes() -> Result<HttpResponse, Error> {
-    let op = MixerOpcodes::new();
+    let opcodes: MixerOpcodes = *MixerOpcodes::get();
 
-    Ok(HttpResponse::Ok().json(op))
+    Ok(HttpResponse::Ok().json(OpcodeList { opcodes, hex: opcodes.hex() }))
 }
 
 /// Retrieves the collection modes for the mixer.
@@ -99,4 +1367,247 @@ pub async fn get_collect_modes() -> Result<HttpResponse, Error> {
     let op = MixerCollectionModes::new();
 
     Ok(HttpResponse::Ok().json(op))
+}
+
+/// Decodes the inbound message of a contract transaction.
+///
+/// # Arguments
+///
+/// * `address` - The contract address, or `None` to use the mixer contract.
+/// * `lt` - The logical time of the transaction.
+/// * `hash` - The hash of the transaction.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the decoded message in JSON format.
+pub async fn decode_transaction(address: Option<TonAddress>, lt: i64, hash: TonHash) -> Result<HttpResponse, Error> {
+    let address: TonAddress = address.unwrap_or_else(ton::mixer_contract_address);
+
+    let body: Cell = match ton::get_transaction_in_message(address, lt, hash).await {
+        Ok(Some(body)) => body,
+        Ok(None) => {
+            return Err(ErrorNotFound(
+                Response::error(
+                    Value::String(String::from("transaction not found or its in-message has no body"))
+                ).to_string()
+            ));
+        },
+        Err(err) => {
+            return Err(ErrorInternalServerError(
+                Response::error(Value::String(err)).to_string()
+            ));
+        }
+    };
+
+    match decode::decode(&body) {
+        Ok(decoded) => Ok(HttpResponse::Ok().json(decoded)),
+        Err(err) => Err(ErrorUnprocessableEntity(
+            Response::error(Value::String(err)).to_string()
+        ))
+    }
+}
+
+/// Lists the most recent transactions of a contract with their decoded operations.
+///
+/// # Arguments
+///
+/// * `address` - The contract address, or `None` to use the mixer contract.
+/// * `from` - The logical time and hash of the transaction the page starts from.
+/// * `limit` - The maximum number of transactions to return.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the page of transactions in JSON format.
+pub async fn list_contract_transactions(pool: &PgPool, address: Option<TonAddress>, from: Option<(i64, TonHash)>, limit: usize) -> Result<HttpResponse, Error> {
+    let address: TonAddress = address.unwrap_or_else(ton::mixer_contract_address);
+    let from: Option<InternalTransactionId> = from.map(| (lt, hash) | InternalTransactionId { lt, hash });
+
+    let (transactions, next): (Vec<RawTransaction>, Option<InternalTransactionId>) =
+        match ton::get_transactions_page(&address, from, limit).await {
+            Ok(page) => page,
+            Err(err) => {
+                return Err(ErrorInternalServerError(
+                    Response::error(Value::String(err)).to_string()
+                ));
+            }
+        };
+
+    let contract: String = address.to_base64_url();
+    let events: Vec<MixerEvent> = transactions.iter().map(| t | explorer::link_event(indexer::to_event(&contract, t))).collect();
+    let labels: AddressLabels = addressbook::labels(pool, event_addresses(&events)).await;
+
+    Ok(HttpResponse::Ok().json(ContractTransactionsPage {
+        transactions: events,
+        next_lt: next.as_ref().map(| id | id.lt),
+        next_hash: next.as_ref().map(| id | hex::encode(id.hash)),
+        labels
+    }))
+}
+
+/// Resolves a query id to the submitted operation and its indexed transactions.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `query_id` - The query id embedded in the message body.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the matching operations and transactions,
+/// or a 404 error if the query id is unknown.
+pub async fn operation_by_query_id(pool: &PgPool, query_id: i64) -> Result<HttpResponse, Error> {
+    let operations: Vec<OutboxEntry> = db::outbox::by_query_id(pool, query_id).await.map_err(| e | {
+        ErrorInternalServerError(Response::error(Value::String(e.to_string())).to_string())
+    })?;
+    let transactions: Vec<MixerEvent> = db::events::by_query_id(pool, query_id).await.map_err(| e | {
+        ErrorInternalServerError(Response::error(Value::String(e.to_string())).to_string())
+    })?;
+
+    if operations.is_empty() && transactions.is_empty() {
+        return Err(ErrorNotFound(
+            Response::error(Value::String(format!("no operation with query id {}", query_id))).to_string()
+        ));
+    }
+
+    let addresses = operations.iter().map(| o | o.wallet.as_str()).chain(event_addresses(&transactions));
+    let labels: AddressLabels = addressbook::labels(pool, addresses).await;
+
+    Ok(HttpResponse::Ok().json(OperationLookup {
+        query_id,
+        operations: operations.into_iter().map(explorer::link_outbox_entry).collect(),
+        transactions: transactions.into_iter().map(explorer::link_event).collect(),
+        labels
+    }))
+}
+
+/// Returns the contracts and sources of indexed transactions, to be labeled from the address book.
+fn event_addresses(events: &[MixerEvent]) -> impl Iterator<Item = &str> {
+    events.iter().flat_map(| e | std::iter::once(e.contract.as_str()).chain(e.source.as_deref()))
+}
+
+/// Lists the balances of the configured jettons held by a mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The allow-listed mixer contract, `MIXER_CONTRACT` if `None`.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the jetton balances, i.e. what collect mode 3 can retrieve.
+pub async fn contract_jettons(pool: &PgPool, contract: Option<String>) -> Result<HttpResponse, Error> {
+    let contract: TonAddress = resolve_contract(pool, contract.as_deref()).await?;
+
+    match jettons::balances(&contract).await {
+        Ok(balances) => Ok(HttpResponse::Ok().json(balances)),
+        Err(err) => Err(ErrorInternalServerError(
+            Response::error(Value::String(err)).to_string()
+        ))
+    }
+}
+
+/// Sums the funds held by the gas wallet and every tracked contract.
+///
+/// Accounts whose balance can not be fetched are reported with their error and left
+/// out of the totals, so one unreachable fork does not hide the rest.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `selected` - Comma separated jetton masters to include, `all` for every configured one.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the `AggregateBalance`, or a 400 error for jettons
+/// that are not configured.
+pub async fn aggregate_balance(pool: &PgPool, selected: Option<String>) -> Result<HttpResponse, Error> {
+    let configured: Vec<TonAddress> = jettons::configured_masters();
+    let masters: Vec<TonAddress> = match selected.as_deref().map(str::trim) {
+        None | Some("") => Vec::new(),
+        Some("all") => configured,
+        Some(list) => {
+            let mut masters: Vec<TonAddress> = Vec::new();
+            for master in list.split(',').map(str::trim).filter(| m | !m.is_empty()) {
+                match TonAddress::from_str(master) {
+                    Ok(address) if configured.contains(&address) => masters.push(address),
+                    _ => return Err(ErrorBadRequest(
+                        Response::error(Value::String(format!("jetton `{}` is not configured in `JETTON_MASTERS`", master))).to_string()
+                    ))
+                }
+            }
+            masters
+        }
+    };
+
+    let contracts: Vec<MixerContract> = db::contracts::list(pool).await.map_err(| e | {
+        ErrorInternalServerError(Response::error(Value::String(e.to_string())).to_string())
+    })?;
+
+    let root: String = ton::mixer_contract_address().to_base64_url();
+    let mut accounts: Vec<(String, &str)> = ton::account_addresses().into_iter().map(| (_, a) | (a.to_base64_url(), "wallet")).collect();
+    if !contracts.iter().any(| c | c.address == root) {
+        accounts.push((root, "root"));
+    }
+    accounts.extend(contracts.iter().map(| c | (c.address.clone(), if c.parent.is_some() { "fork" } else { "root" })));
+
+    let mut total: i64 = 0;
+    let mut jetton_totals: Vec<(JettonBalance, BigInt)> = Vec::new();
+    let mut balances: Vec<AccountBalance> = Vec::new();
+
+    for (address, role) in accounts {
+        let mut account: AccountBalance = AccountBalance { address, role: role.to_string(), balance: None, jettons: Vec::new(), error: None };
+
+        let fetched: Result<(i64, Vec<JettonBalance>), String> = match TonAddress::from_str(&account.address) {
+            Ok(owner) => match ton::get_balance(&owner).await {
+                Ok(balance) => jettons::balances_of(&owner, &masters).await.map(| j | (balance, j)),
+                Err(err) => Err(err)
+            },
+            Err(err) => Err(err.to_string())
+        };
+
+        match fetched {
+            Ok((balance, held)) => {
+                total += balance;
+                for jetton in held.iter() {
+                    let amount: BigInt = BigInt::from_str(&jetton.balance).unwrap_or_default();
+                    match jetton_totals.iter_mut().find(| (t, _) | t.master == jetton.master) {
+                        Some((_, sum)) => *sum += amount,
+                        None => jetton_totals.push((jetton.clone(), amount))
+                    }
+                }
+                account.balance = Some(balance);
+                account.jettons = held;
+            },
+            Err(err) => {
+                log_warn!("Can not fetch the balances of {}: {}", account.address, err);
+                account.error = Some(err);
+            }
+        }
+
+        balances.push(account);
+    }
+
+    Ok(HttpResponse::Ok().json(AggregateBalance {
+        total,
+        jettons: jetton_totals.into_iter().map(| (jetton, sum) | JettonTotal {
+            master: jetton.master,
+            symbol: jetton.symbol,
+            decimals: jetton.decimals,
+            amount: jettons::format_amount(&sum, jetton.decimals),
+            balance: sum.to_string()
+        }).collect(),
+        accounts: balances
+    }))
+}
+
+/// Fetches the balances of the gas wallet and the mixer contract.
+///
+/// # Returns
+///
+/// Returns the balances in nanotons.
+pub async fn balances() -> Result<Balances, String> {
+    Ok(Balances {
+        wallet: ton::get_balance(&ton::wallet_address()).await?,
+        contract: ton::get_balance(&ton::mixer_contract_address()).await?
+    })
 }
\ No newline at end of file
@@ -1 +1,10 @@
-pub mod mixer;
\ No newline at end of file
+pub mod addressbook;
+pub mod admin;
+pub mod connect;
+pub mod deposit;
+pub mod groups;
+pub mod health;
+pub mod jobs;
+pub mod mixer;
+pub mod multisig;
+pub mod reports;
\ No newline at end of file
@@ -0,0 +1,179 @@
+//! # Multisig Services
+//!
+//! This module provides the service functions routing collects through the multisig
+//! configured with `MULTISIG_ADDRESS`: the hot wallet proposes a collect as an order,
+//! and signers approve it with their own wallets through TON Connect requests.
+
+use actix_web::{error::{ErrorBadRequest, ErrorConflict, ErrorInternalServerError, ErrorNotFound}, Error, HttpResponse};
+use num_bigint::BigUint;
+use serde_json::Value;
+use sqlx::PgPool;
+use tonlib::{address::TonAddress, cell::Cell};
+
+use crate::{multisig::{self, MultisigConfig, OrderData}, services::{addressbook, connect, mixer}, ton, types::{multisig::{MultisigApproval, MultisigOrder, MultisigOrderStatus}, nanotons::Nanotons, CollectPayload, OperationReceipt, Response, WalletTransfer, DEFAULT_SEND_MODE}};
+
+/// Returns the multisig setup, or a 404 error when collects are not routed through a multisig.
+fn setup() -> Result<MultisigConfig, Error> {
+    match MultisigConfig::from_env() {
+        Some(setup) => Ok(setup),
+        None => Err(ErrorNotFound(
+            Response::error(Value::String(String::from("no multisig is configured"))).to_string()
+        ))
+    }
+}
+
+/// Wraps an error of the network into a 500 error.
+fn internal(err: String) -> Error {
+    ErrorInternalServerError(Response::error(Value::String(err)).to_string())
+}
+
+/// Proposes a collect to the multisig.
+///
+/// The hot wallet sends a new order whose only action is the collect message from the
+/// multisig to the mixer contract; the collect is executed once enough signers approved.
+/// The `send_mode` of the payload is the mode the multisig sends the collect with.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to collect from, `MIXER_CONTRACT` if `None`.
+/// * `payload` - A `CollectPayload` struct containing collection details.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the order or an error.
+pub async fn collect(pool: &PgPool, contract: Option<String>, payload: CollectPayload) -> Result<HttpResponse, Error> {
+    mixer::ensure_not_paused(pool).await?;
+    let setup: MultisigConfig = setup()?;
+    let contract: TonAddress = mixer::resolve_verified_contract(pool, contract.as_deref()).await?;
+    let payload: CollectPayload = addressbook::resolve_collect(pool, payload).await?;
+
+    let query_id: u64 = ton::time_now();
+    let send_mode: u8 = payload.send_mode.unwrap_or(DEFAULT_SEND_MODE);
+    let withdrawn: Nanotons = ton::collected_nanotons(&contract, payload.mode).await.map_err(internal)?;
+    let collect: Cell = ton::collect_body(query_id, mixer::collect_message_data(payload)?);
+    let order: Cell = multisig::single_message_order(&contract, ton::collect_gas(), collect, send_mode).map_err(internal)?;
+
+    let order_seqno: u64 = multisig::allocate_order_seqno(pool, &setup.address).await.map_err(internal)?;
+    let prepared: Result<(TonAddress, u64, Cell), String> = async {
+        let order_address: TonAddress = multisig::order_address(&setup.address, order_seqno).await?;
+        let expires_at: u64 = multisig::order_expiry(&setup);
+        let body: Cell = multisig::new_order_body(&setup, query_id, order_seqno, expires_at, order)?;
+
+        Ok((order_address, expires_at, body))
+    }.await;
+    let (order_address, expires_at, body) = match prepared {
+        Ok(prepared) => prepared,
+        Err(err) => {
+            multisig::release_order_seqno(pool, &setup.address, order_seqno).await;
+            return Err(internal(err));
+        }
+    };
+
+    let sent: Result<Vec<OperationReceipt>, String> = ton::wallet_transfer(pool, "multisig_order", vec![WalletTransfer {
+        destination: setup.address.clone(),
+        amount: BigUint::from(setup.order_gas),
+        body: Some(body),
+        mode: DEFAULT_SEND_MODE
+    }], None, withdrawn).await;
+    let mut receipts: Vec<OperationReceipt> = match sent {
+        Ok(receipts) => receipts,
+        Err(err) => {
+            // a message that may have reached the network keeps its seqno, the order may still be created
+            if ton::send_rejected(&err) || ton::limit_exceeded(&err) {
+                multisig::release_order_seqno(pool, &setup.address, order_seqno).await;
+            }
+            return Err(mixer::send_failed(err));
+        }
+    };
+
+    log_info!("Proposed collect {} to the multisig as order {}", query_id, order_seqno);
+
+    Ok(HttpResponse::Ok().json(MultisigOrder {
+        multisig: setup.address.to_base64_url(),
+        order_seqno,
+        order_address: order_address.to_base64_url(),
+        query_id,
+        expires_at,
+        receipt: receipts.remove(0)
+    }))
+}
+
+/// Reports the approval state of an order.
+///
+/// # Arguments
+///
+/// * `order_seqno` - The seqno of the order.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the state of the order or an error.
+pub async fn order(order_seqno: u64) -> Result<HttpResponse, Error> {
+    let setup: MultisigConfig = setup()?;
+    let order_address: TonAddress = multisig::order_address(&setup.address, order_seqno).await.map_err(internal)?;
+    let data: Option<OrderData> = multisig::order_data(&order_address).await.map_err(internal)?;
+
+    Ok(HttpResponse::Ok().json(MultisigOrderStatus {
+        order_seqno,
+        order_address: order_address.to_base64_url(),
+        created: data.is_some(),
+        threshold: data.as_ref().map(| d | d.threshold),
+        approved_by: data.as_ref().map(| d | d.approved_by.clone()).unwrap_or_default(),
+        executed: data.as_ref().is_some_and(| d | d.executed),
+        expires_at: data.as_ref().map(| d | d.expires_at)
+    }))
+}
+
+/// Builds the TON Connect request a signer approves an order with.
+///
+/// Nothing is signed by the server: the signer sends the approval from their own wallet.
+///
+/// # Arguments
+///
+/// * `order_seqno` - The seqno of the order.
+/// * `signer_index` - The index of the signer in the multisig.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the request, a 404 error for an order that was
+/// not created and a 409 error for an order that was executed, expired or approved by the signer.
+pub async fn approve(order_seqno: u64, signer_index: u8) -> Result<HttpResponse, Error> {
+    let setup: MultisigConfig = setup()?;
+    let order_address: TonAddress = multisig::order_address(&setup.address, order_seqno).await.map_err(internal)?;
+
+    let data: OrderData = match multisig::order_data(&order_address).await.map_err(internal)? {
+        Some(data) => data,
+        None => {
+            return Err(ErrorNotFound(
+                Response::error(Value::String(format!("order {} was not created", order_seqno))).to_string()
+            ));
+        }
+    };
+
+    let conflict: Option<&str> = if data.executed {
+        Some("was executed")
+    } else if data.expires_at <= ton::time_now() {
+        Some("has expired")
+    } else if data.approved_by.contains(&signer_index) {
+        Some("was already approved by this signer")
+    } else {
+        None
+    };
+
+    if let Some(reason) = conflict {
+        return Err(ErrorConflict(
+            Response::error(Value::String(format!("order {} {}", order_seqno, reason))).to_string()
+        ));
+    }
+
+    let query_id: u64 = ton::time_now();
+    let body: Cell = multisig::approve_body(query_id, signer_index).map_err(| e | {
+        ErrorBadRequest(Response::error(Value::String(e)).to_string())
+    })?;
+
+    Ok(HttpResponse::Ok().json(MultisigApproval {
+        order_address: order_address.to_base64_url(),
+        signer_index,
+        request: connect::build_request(&order_address, query_id, multisig::APPROVE_GAS, body)?
+    }))
+}
@@ -0,0 +1,73 @@
+//! # Report Services
+//!
+//! This module provides service functions building accounting reports from indexed on-chain data.
+
+use actix_web::{error::ErrorInternalServerError, Error, HttpResponse};
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{db, types::{reports::{FeeReportRow, MixerStats, OperationStats}, Response}};
+
+/// Builds the fee and profit report.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `period` - The period to group by (`day` or `week`).
+/// * `from` - The Unix timestamp the report starts at.
+/// * `to` - The Unix timestamp the report ends at.
+/// * `csv` - Whether to return the report as CSV instead of JSON.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the report rows.
+pub async fn fees(pool: &PgPool, period: &str, from: i64, to: i64, csv: bool) -> Result<HttpResponse, Error> {
+    let rows: Vec<FeeReportRow> = match db::reports::fees(pool, period, from, to).await {
+        Ok(rows) => rows,
+        Err(err) => {
+            return Err(ErrorInternalServerError(
+                Response::error(Value::String(err.to_string())).to_string()
+            ));
+        }
+    };
+
+    if !csv {
+        return Ok(HttpResponse::Ok().json(rows));
+    }
+
+    let mut body: String = String::from(FeeReportRow::csv_header());
+    for row in rows.iter() {
+        body.push('\n');
+        body.push_str(&row.to_csv());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"fees-{}.csv\"", period)))
+        .body(body))
+}
+
+/// Builds the mixing statistics over a window.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `window` - The name of the window (`24h`, `7d` or `30d`).
+/// * `from` - The Unix timestamp the window starts at.
+/// * `to` - The Unix timestamp the window ends at.
+///
+/// # Returns
+///
+/// Returns an HTTP response containing the statistics in JSON format.
+pub async fn stats(pool: &PgPool, window: String, from: i64, to: i64) -> Result<HttpResponse, Error> {
+    let operations: Vec<OperationStats> = match db::reports::operation_stats(pool, from).await {
+        Ok(operations) => operations,
+        Err(err) => {
+            return Err(ErrorInternalServerError(
+                Response::error(Value::String(err.to_string())).to_string()
+            ));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(MixerStats::new(window, from, to, operations)))
+}
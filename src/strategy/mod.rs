@@ -0,0 +1,280 @@
+//! # Mixing Strategies
+//!
+//! This module defines the `MixStrategy` trait, which decides how a paid deposit is split
+//! into spread legs, when each leg is sent and through which contract. Strategies are
+//! selected per payment request with `plan.strategy`, then per tenant with
+//! `MIX_STRATEGY_TENANTS`, falling back to `MIX_STRATEGY` for the whole deployment, so
+//! privacy behavior can change without touching the services. The tenant of a request is
+//! the tenant of its `API_KEYS` token, see `auth::rbac`.
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{config, types::deposit::{MixPlan, MixRecipient}};
+
+/// Names of the built-in strategies.
+pub const STRATEGIES: [&str; 4] = ["uniform", "randomized", "scheduled", "poisson"];
+
+/// Strategy used when neither the plan nor `MIX_STRATEGY` names one.
+const DEFAULT_STRATEGY: &str = "uniform";
+
+/// Mean gap between legs of the `poisson` strategy in seconds, used when neither
+/// `plan.delay` nor `MIX_POISSON_MEAN` is set.
+const DEFAULT_POISSON_MEAN: u64 = 600;
+
+/// Represents the strategy of the deployment and the strategies of its tenants.
+#[derive(Debug, Clone)]
+pub struct StrategyConfig {
+    /// The strategy of `MIX_STRATEGY`.
+    pub default: &'static str,
+    /// The strategies of `MIX_STRATEGY_TENANTS`, by tenant.
+    pub tenants: HashMap<String, &'static str>
+}
+
+/// The configured strategies, read once on startup.
+static STRATEGY_CONFIG: OnceLock<StrategyConfig> = OnceLock::new();
+
+/// Returns the known strategy of the given name.
+fn known(name: &str) -> Option<&'static str> {
+    STRATEGIES.iter().find(| s | **s == name).copied()
+}
+
+/// Returns the strategies of `MIX_STRATEGY` and of `MIX_STRATEGY_TENANTS`, given as
+/// `tenant:strategy` pairs.
+///
+/// The strategies are read on the first call, which `main` makes on startup.
+///
+/// # Panics
+///
+/// Panics on the first call if a strategy is unknown or an entry is not `tenant:strategy`.
+pub fn configured() -> &'static StrategyConfig {
+    STRATEGY_CONFIG.get_or_init(|| {
+        let name: String = config::env_or("MIX_STRATEGY", String::from(DEFAULT_STRATEGY));
+        let default: &'static str = known(&name)
+            .unwrap_or_else(|| panic!("[ FATAL ] Configuration Error: `MIX_STRATEGY` names the unknown mixing strategy `{}`", name));
+
+        let tenants: HashMap<String, &'static str> = config::env_or("MIX_STRATEGY_TENANTS", String::new())
+            .split(',')
+            .map(| e | e.trim())
+            .filter(| e | !e.is_empty())
+            .map(| entry | match entry.split_once(':').map(| (tenant, name) | (tenant.trim(), known(name.trim()))) {
+                Some((tenant, Some(strategy))) if !tenant.is_empty() => (tenant.to_string(), strategy),
+                _ => panic!("[ FATAL ] Configuration Error: `MIX_STRATEGY_TENANTS` has an invalid entry `{}`", entry)
+            })
+            .collect();
+
+        StrategyConfig { default, tenants }
+    })
+}
+
+/// Returns the strategy of a tenant, `None` if the tenant uses the strategy of the deployment.
+pub fn for_tenant(tenant: &str) -> Option<&'static str> {
+    configured().tenants.get(tenant).copied()
+}
+
+/// Represents one spread of a mixing plan.
+#[derive(Debug, Clone)]
+pub struct MixLeg {
+    /// Seconds after the deposit was matched.
+    pub delay: u64,
+    /// The contract the leg is spread through.
+    pub contract: String,
+    /// Nanotons per recipient, in the order of `plan.recipients`.
+    pub amounts: Vec<u64>
+}
+
+/// Decides the splits, delays and contract routing of a paid deposit.
+pub trait MixStrategy: Send + Sync {
+    /// Returns the name the strategy is selected by.
+    fn name(&self) -> &'static str;
+
+    /// Splits a deposit into legs.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - The mixing plan of the deposit.
+    /// * `contracts` - The contracts legs may be routed through, the deposit contract first.
+    /// * `received` - The received amount in nanotons.
+    ///
+    /// # Returns
+    ///
+    /// The legs, whose amounts add up to `received`.
+    fn legs(&self, plan: &MixPlan, contracts: &[String], received: u64) -> Vec<MixLeg>;
+}
+
+/// Sends `plan.legs` equal legs through the deposit contract, `plan.delay` seconds apart.
+pub struct Uniform;
+
+impl MixStrategy for Uniform {
+    fn name(&self) -> &'static str {
+        "uniform"
+    }
+
+    fn legs(&self, plan: &MixPlan, contracts: &[String], received: u64) -> Vec<MixLeg> {
+        let count: usize = plan.legs.unwrap_or(1) as usize;
+        let delays: Vec<u64> = (1..=count as u64).map(| k | plan.delay * k).collect();
+
+        build_legs(plan, received, &delays, | _ | contracts[0].clone(), | amount | split_evenly(amount, count))
+    }
+}
+
+/// Sends `plan.legs` legs of random size through random forks of the deposit contract.
+///
+/// The gaps between legs are drawn uniformly from `0..=2 * plan.delay`, so they average `plan.delay`.
+pub struct Randomized;
+
+impl MixStrategy for Randomized {
+    fn name(&self) -> &'static str {
+        "randomized"
+    }
+
+    fn legs(&self, plan: &MixPlan, contracts: &[String], received: u64) -> Vec<MixLeg> {
+        let count: usize = plan.legs.unwrap_or(1) as usize;
+        let mut rng = rand::thread_rng();
+
+        let mut delay: u64 = 0;
+        let delays: Vec<u64> = (0..count).map(| _ | {
+            delay += rng.gen_range(0..=plan.delay * 2);
+            delay
+        }).collect();
+
+        build_legs(
+            plan,
+            received,
+            &delays,
+            | _ | contracts.choose(&mut rand::thread_rng()).unwrap().clone(),
+            | amount | split_randomly(amount, count)
+        )
+    }
+}
+
+/// Sends equal legs through the deposit contract at the offsets listed in `plan.schedule`.
+pub struct Scheduled;
+
+impl MixStrategy for Scheduled {
+    fn name(&self) -> &'static str {
+        "scheduled"
+    }
+
+    fn legs(&self, plan: &MixPlan, contracts: &[String], received: u64) -> Vec<MixLeg> {
+        let delays: Vec<u64> = plan.schedule.clone().unwrap_or_else(|| vec![plan.delay]);
+        let count: usize = delays.len();
+
+        build_legs(plan, received, &delays, | _ | contracts[0].clone(), | amount | split_evenly(amount, count))
+    }
+}
+
+/// Sends `plan.legs` legs of random size through the deposit contract as a Poisson process.
+///
+/// The gaps between legs are drawn from an exponential distribution with a mean of
+/// `plan.delay`, or `MIX_POISSON_MEAN` when the plan has no delay. Unlike fixed or
+/// uniformly drawn gaps this matches the timing of independent organic payments.
+pub struct Poisson;
+
+impl MixStrategy for Poisson {
+    fn name(&self) -> &'static str {
+        "poisson"
+    }
+
+    fn legs(&self, plan: &MixPlan, contracts: &[String], received: u64) -> Vec<MixLeg> {
+        let count: usize = plan.legs.unwrap_or(1) as usize;
+        let mean: f64 = match plan.delay {
+            0 => config::env_or("MIX_POISSON_MEAN", DEFAULT_POISSON_MEAN),
+            delay => delay
+        } as f64;
+        let mut rng = rand::thread_rng();
+
+        let mut delay: f64 = 0.0;
+        let delays: Vec<u64> = (0..count).map(| _ | {
+            // inverse transform sampling, `1 - u` keeps the logarithm finite
+            let u: f64 = rng.gen::<f64>();
+            delay += -mean * (1.0 - u).ln();
+            delay.round() as u64
+        }).collect();
+
+        build_legs(plan, received, &delays, | _ | contracts[0].clone(), | amount | split_randomly(amount, count))
+    }
+}
+
+/// Returns the strategy of a mixing plan, the strategy of `MIX_STRATEGY` if it names none.
+///
+/// Plans are validated when they are stored, a plan naming a strategy that is no longer
+/// known is logged and mixed with the strategy of `MIX_STRATEGY`.
+pub fn for_plan(plan: &MixPlan) -> Box<dyn MixStrategy> {
+    let default: &'static str = configured().default;
+    let name: &str = plan.strategy.as_deref().unwrap_or(default);
+
+    match (by_name(name), by_name(default)) {
+        (Some(strategy), _) => strategy,
+        (None, Some(strategy)) => {
+            log_error!("Mixing plan names the unknown strategy `{}`, using `{}`", name, default);
+            strategy
+        },
+        (None, None) => Box::new(Uniform)
+    }
+}
+
+/// Returns the built-in strategy with the given name.
+pub fn by_name(name: &str) -> Option<Box<dyn MixStrategy>> {
+    match name {
+        "uniform" => Some(Box::new(Uniform)),
+        "randomized" => Some(Box::new(Randomized)),
+        "scheduled" => Some(Box::new(Scheduled)),
+        "poisson" => Some(Box::new(Poisson)),
+        _ => None
+    }
+}
+
+/// Splits the received amount by the shares of the recipients, then each share into legs.
+///
+/// # Arguments
+///
+/// * `plan` - The mixing plan of the deposit.
+/// * `received` - The received amount in nanotons.
+/// * `delays` - The delay of each leg.
+/// * `contract` - Picks the contract of the leg with the given index.
+/// * `split` - Splits the amount of one recipient into one part per leg.
+fn build_legs(plan: &MixPlan, received: u64, delays: &[u64], mut contract: impl FnMut(usize) -> String, mut split: impl FnMut(u64) -> Vec<u64>) -> Vec<MixLeg> {
+    let parts: Vec<Vec<u64>> = split_by_shares(received, &plan.recipients).into_iter().map(&mut split).collect();
+
+    delays.iter().enumerate().map(| (k, delay) | MixLeg {
+        delay: *delay,
+        contract: contract(k),
+        amounts: parts.iter().map(| p | p[k]).collect()
+    }).collect()
+}
+
+/// Splits an amount by the shares of the recipients, the rounding remainder goes to the last one.
+fn split_by_shares(amount: u64, recipients: &[MixRecipient]) -> Vec<u64> {
+    let weights: Vec<u64> = recipients.iter().map(| r | r.share as u64).collect();
+    split_weighted(amount, &weights)
+}
+
+/// Splits an amount into equal parts, the rounding remainder goes to the last one.
+fn split_evenly(amount: u64, parts: usize) -> Vec<u64> {
+    split_weighted(amount, &vec![1; parts])
+}
+
+/// Splits an amount into parts of random size between half and one and a half of the mean.
+fn split_randomly(amount: u64, parts: usize) -> Vec<u64> {
+    let mut rng = rand::thread_rng();
+    let weights: Vec<u64> = (0..parts).map(| _ | rng.gen_range(500..=1500)).collect();
+    split_weighted(amount, &weights)
+}
+
+/// Splits an amount by weights, the rounding remainder goes to the last part.
+fn split_weighted(amount: u64, weights: &[u64]) -> Vec<u64> {
+    let total: u128 = weights.iter().map(| w | *w as u128).sum();
+    let mut remaining: u64 = amount;
+
+    weights.iter().enumerate().map(| (i, weight) | {
+        let part: u64 = if i + 1 == weights.len() {
+            remaining
+        } else {
+            (amount as u128 * *weight as u128 / total) as u64
+        };
+        remaining -= part;
+        part
+    }).collect()
+}
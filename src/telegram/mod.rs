@@ -0,0 +1,199 @@
+//! # Telegram Bot
+//!
+//! This module implements an optional Telegram bot (enabled with the `telegram` feature)
+//! that lets authorized operators query balances, view recent operations and held jobs, and
+//! pause the mixer from a chat, reusing the service layer of the HTTP API.
+//!
+//! A chat id only tells where a message came from, so the bot offers nothing beyond stopping
+//! the mixer: resuming it and approving held jobs take an operator signature, see
+//! `POST /admin/resume` and `POST /admin/jobs/{id}/approve`.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{config, db, leader, services::mixer, types::{events::MixerEvent, jobs::Job, nanotons::Nanotons, Balances}};
+
+/// Number of recent operations shown by the `/recent` command.
+const RECENT_LIMIT: i64 = 10;
+
+/// Number of held jobs shown by the `/held` command.
+const HELD_LIMIT: i64 = 10;
+
+/// Long polling timeout of `getUpdates` in seconds.
+const POLL_TIMEOUT: u64 = 30;
+
+/// Represents a `getUpdates` response of the Bot API.
+#[derive(Deserialize, Debug)]
+struct UpdatesResponse {
+    ok: bool,
+    result: Vec<Update>
+}
+
+/// Represents a single update of the Bot API.
+#[derive(Deserialize, Debug)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>
+}
+
+/// Represents an incoming chat message.
+#[derive(Deserialize, Debug)]
+struct Message {
+    chat: Chat,
+    text: Option<String>
+}
+
+/// Represents the chat a message was sent in.
+#[derive(Deserialize, Debug)]
+struct Chat {
+    id: i64
+}
+
+/// Formats an amount of nanotons as TON.
+fn format_ton(nano: i64) -> String {
+    format!("{} TON", Nanotons::from_signed(nano))
+}
+
+/// Runs the bot loop forever.
+///
+/// The bot is configured with `TELEGRAM_BOT_TOKEN` and `TELEGRAM_OPERATOR_CHAT_IDS`
+/// (comma separated); messages from any other chat are ignored. The task exits
+/// right away when the token is not set.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+pub async fn run(pool: PgPool) {
+    let token: String = match config::var("TELEGRAM_BOT_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => {
+            log_info!("Telegram bot is disabled");
+            return;
+        }
+    };
+
+    let operators: Vec<i64> = config::var("TELEGRAM_OPERATOR_CHAT_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(| id | id.trim().parse::<i64>().ok())
+        .collect();
+
+    let client: reqwest::Client = reqwest::Client::new();
+    let mut offset: i64 = 0;
+
+    log_info!("Telegram bot is running for {} operator chats", operators.len());
+
+    loop {
+        // Telegram hands updates to one poller, the leader answers the operators
+        if !leader::is_leader() {
+            actix_web::rt::time::sleep(Duration::from_secs(5)).await;
+            continue;
+        }
+
+        let updates: Vec<Update> = match poll(&client, &token, offset).await {
+            Ok(updates) => updates,
+            Err(err) => {
+                log_error!("Telegram polling failed: {}", err);
+                actix_web::rt::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        for update in updates {
+            offset = update.update_id + 1;
+
+            let (chat_id, text) = match update.message {
+                Some(Message { chat, text: Some(text) }) => (chat.id, text),
+                _ => continue
+            };
+
+            if !operators.contains(&chat_id) {
+                continue;
+            }
+
+            let reply: String = handle_command(&pool, chat_id, text.trim()).await;
+            if let Err(err) = reply_to(&client, &token, chat_id, &reply).await {
+                log_error!("Telegram reply failed: {}", err);
+            }
+        }
+    }
+}
+
+/// Fetches new updates with long polling.
+async fn poll(client: &reqwest::Client, token: &str, offset: i64) -> Result<Vec<Update>, String> {
+    let response: UpdatesResponse = client.get(format!("https://api.telegram.org/bot{}/getUpdates", token))
+        .query(&[("offset", offset.to_string()), ("timeout", POLL_TIMEOUT.to_string())])
+        .timeout(Duration::from_secs(POLL_TIMEOUT + 10))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .json::<UpdatesResponse>()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.ok {
+        return Err(String::from("Bot API returned an unsuccessful response"));
+    }
+
+    Ok(response.result)
+}
+
+/// Sends a text message to a chat.
+async fn reply_to(client: &reqwest::Client, token: &str, chat_id: i64, text: &str) -> Result<(), String> {
+    client.post(format!("https://api.telegram.org/bot{}/sendMessage", token))
+        .json(&serde_json::json!({
+            "chat_id": chat_id,
+            "text": text
+        }))
+        .send()
+        .await
+        .and_then(| r | r.error_for_status())
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Executes an operator command and returns the reply text.
+async fn handle_command(pool: &PgPool, chat_id: i64, command: &str) -> String {
+    match command.split_whitespace().next().unwrap_or("") {
+        "/balance" => match mixer::balances().await {
+            Ok(Balances { wallet, contract }) => format!(
+                "Gas wallet: {}\nMixer contract: {}",
+                format_ton(wallet),
+                format_ton(contract)
+            ),
+            Err(err) => format!("Can not fetch balances: {}", err)
+        },
+        "/recent" => match db::events::recent(pool, RECENT_LIMIT).await {
+            Ok(events) if events.is_empty() => String::from("No operations indexed yet"),
+            Ok(events) => events.iter().map(| e: &MixerEvent | format!(
+                "{} {} in {} out {} (lt {})",
+                e.utime,
+                e.op,
+                format_ton(e.value_in),
+                format_ton(e.value_out),
+                e.lt
+            )).collect::<Vec<String>>().join("\n"),
+            Err(err) => format!("Can not fetch recent operations: {}", err)
+        },
+        "/pause" => match mixer::set_paused(pool, true, &format!("telegram:{}", chat_id)).await {
+            Ok(_) => String::from("Mixer is paused, spread/collect/fork requests are rejected"),
+            Err(err) => format!("Can not pause the mixer: {}", err)
+        },
+        "/status" => format!("Mixer is {}", if mixer::is_paused(pool).await { "paused" } else { "running" }),
+        "/held" => match db::jobs::held(pool, HELD_LIMIT).await {
+            Ok(jobs) if jobs.is_empty() => String::from("No jobs are held"),
+            Ok(jobs) => jobs.iter().map(| j: &Job | format!(
+                "{} {} held since {}: {}",
+                j.id,
+                j.kind,
+                j.held_at.unwrap_or_default(),
+                j.error.as_deref().unwrap_or("")
+            )).collect::<Vec<String>>().join("\n"),
+            Err(err) => format!("Can not fetch held jobs: {}", err)
+        },
+        _ => String::from("Commands: /balance, /recent, /status, /pause, /held")
+    }
+}
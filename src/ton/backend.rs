@@ -0,0 +1,463 @@
+//! # TON Backend
+//!
+//! This module defines the `TonBackend` trait the rest of the `ton` module talks to the
+//! network through, its liteserver implementation on top of tonlib, and the wrapper
+//! recording RPC metrics of any backend.
+
+use std::{future::Future, net::Ipv4Addr, sync::{Mutex, OnceLock}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
+use serde_json::{json, Value};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tonlib::{address::TonAddress, cell::{BagOfCells, Cell}, client::{TonClient, TonClientBuilder, TonClientInterface, TonConnectionParams}, contract::{TonContractFactory, TonContractInterface, TonWalletContract}, tl::{BlocksHeader, BlocksMasterchainInfo, InternalTransactionId, RawFullAccountState, RawTransactions}, types::{TvmStackEntry, TvmSuccess}};
+
+use crate::{config, metrics};
+
+/// Represents the state of an account as seen by the mixer.
+#[derive(Debug, Clone)]
+pub struct AccountState {
+    /// The balance in nanotons.
+    pub balance: i64,
+    /// Whether the account has code, i.e. is deployed.
+    pub active: bool,
+    /// Lowercase hex hash of the code cell, `None` without code or if the backend can not tell.
+    pub code_hash: Option<String>,
+    /// The code cell, `None` without code or if the backend can not tell.
+    pub code: Option<Cell>,
+    /// The id of the last transaction of the account.
+    pub last_transaction_id: InternalTransactionId
+}
+
+/// Represents the latest masterchain block seen by a backend.
+#[derive(Debug, Clone)]
+pub struct MasterchainInfo {
+    pub seqno: u32,
+    /// Unix time the block was generated at.
+    pub utime: i64
+}
+
+/// Network operations used by the mixer.
+///
+/// Implemented by `LiteBackend` for liteservers, by `http::HttpBackend` for the
+/// toncenter HTTP API and by `mock::MockBackend` for running without network access.
+#[async_trait]
+pub trait TonBackend: Send + Sync {
+    /// Returns the name of the backend, the `liteserver` label of its RPC metrics.
+    fn name(&self) -> &str;
+
+    /// Fetches the seqno of a wallet.
+    async fn seqno(&self, wallet: &TonAddress) -> Result<u32, String>;
+
+    /// Runs a get-method of a contract and returns the resulting stack.
+    async fn run_get_method(&self, address: &TonAddress, method: &'static str, stack: Vec<TvmStackEntry>) -> Result<Vec<TvmStackEntry>, String>;
+
+    /// Broadcasts a signed external message and returns its hash.
+    async fn send(&self, boc: &[u8]) -> Result<Vec<u8>, String>;
+
+    /// Fetches the state of an account.
+    async fn account_state(&self, address: &TonAddress) -> Result<AccountState, String>;
+
+    /// Fetches up to `count` transactions of an account, starting from `from` (inclusive) backwards.
+    async fn transactions(&self, address: &TonAddress, from: &InternalTransactionId, count: usize) -> Result<RawTransactions, String>;
+
+    /// Fetches the latest masterchain block.
+    async fn masterchain_info(&self) -> Result<MasterchainInfo, String>;
+}
+
+/// Limits concurrent liteserver read operations (seqno, account states, transactions).
+static READ_PERMITS: OnceLock<Semaphore> = OnceLock::new();
+
+/// Limits concurrent message broadcasts, kept separate so reads can't starve sends.
+static SEND_PERMITS: OnceLock<Semaphore> = OnceLock::new();
+
+/// Waits for a permit to perform a liteserver read.
+///
+/// The limit is configured with `TON_READ_CONCURRENCY` (default `8`).
+pub(super) async fn read_permit() -> SemaphorePermit<'static> {
+    let semaphore: &Semaphore = READ_PERMITS.get_or_init(|| Semaphore::new(config::env_or("TON_READ_CONCURRENCY", 8)));
+    // the semaphore is never closed, so acquiring can not fail
+    semaphore.acquire().await.unwrap()
+}
+
+/// Waits for a permit to broadcast a message.
+///
+/// The limit is configured with `TON_SEND_CONCURRENCY` (default `4`).
+pub(super) async fn send_permit() -> SemaphorePermit<'static> {
+    let semaphore: &Semaphore = SEND_PERMITS.get_or_init(|| Semaphore::new(config::env_or("TON_SEND_CONCURRENCY", 4)));
+    // the semaphore is never closed, so acquiring can not fail
+    semaphore.acquire().await.unwrap()
+}
+
+/// Messages per second broadcast to liteservers, used when `TON_SEND_RATE` is not set.
+const DEFAULT_SEND_RATE: f64 = 5.0;
+
+/// Messages broadcast at once before pacing starts, used when `TON_SEND_BURST` is not set.
+const DEFAULT_SEND_BURST: f64 = 10.0;
+
+/// Paces message broadcasts, so liteservers don't ban the client for flooding them.
+static SEND_BUCKET: OnceLock<TokenBucket> = OnceLock::new();
+
+/// Represents a token bucket refilled at `rate` tokens per second up to `burst` tokens.
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    /// Tokens left, negative while sends wait for a token, and the time they were counted at.
+    state: Mutex<(f64, Instant)>
+}
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    fn new(rate: f64, burst: f64) -> Self {
+        TokenBucket { rate, burst, state: Mutex::new((burst, Instant::now())) }
+    }
+
+    /// Takes a token and returns how long the caller has to wait until it is refilled.
+    ///
+    /// Tokens are reserved in order, so concurrent senders are paced one after another.
+    fn reserve(&self) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now: Instant = Instant::now();
+        let tokens: f64 = (state.0 + now.duration_since(state.1).as_secs_f64() * self.rate).min(self.burst) - 1.0;
+
+        *state = (tokens, now);
+        self.wait(tokens)
+    }
+
+    /// Returns how long a token taken now would have to wait, without taking it.
+    fn backlog(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        let tokens: f64 = (state.0 + state.1.elapsed().as_secs_f64() * self.rate).min(self.burst) - 1.0;
+
+        self.wait(tokens)
+    }
+
+    /// Returns the time until a balance of `tokens` is refilled to zero.
+    fn wait(&self, tokens: f64) -> Duration {
+        match tokens < 0.0 {
+            true => Duration::from_secs_f64(-tokens / self.rate),
+            false => Duration::ZERO
+        }
+    }
+}
+
+/// Waits until the send rate allows another broadcast.
+///
+/// The rate is configured with `TON_SEND_RATE` messages per second (default `5`) and
+/// `TON_SEND_BURST` (default `10`); a rate of `0` disables pacing.
+pub(super) async fn send_pace() {
+    let bucket: &TokenBucket = SEND_BUCKET.get_or_init(|| TokenBucket::new(
+        config::env_or("TON_SEND_RATE", DEFAULT_SEND_RATE),
+        config::env_or("TON_SEND_BURST", DEFAULT_SEND_BURST).max(1.0)
+    ));
+
+    if bucket.rate <= 0.0 {
+        return;
+    }
+
+    let wait: Duration = bucket.reserve();
+    if !wait.is_zero() {
+        actix_web::rt::time::sleep(wait).await;
+    }
+}
+
+/// Returns how long a message broadcast now would wait for the send rate, the backlog of the send queue.
+pub fn send_backlog() -> Duration {
+    match SEND_BUCKET.get() {
+        Some(bucket) if bucket.rate > 0.0 => bucket.backlog(),
+        _ => Duration::ZERO
+    }
+}
+
+/// Represents a set of liteservers: a global config and the name its RPCs are recorded under.
+#[derive(Debug, Clone)]
+pub struct LiteserverSet {
+    pub name: String,
+    pub config: String
+}
+
+/// Returns the liteserver sets to connect to, in the order they are failed over to.
+///
+/// The sets are taken from the comma separated global config files in `TON_GLOBAL_CONFIG`,
+/// e.g. of a local network, or from the bundled testnet config when it is not set. Every set
+/// is named after its file, the bundled one `testnet`, so metrics and logs tell them apart.
+///
+/// Liteservers listed in `TON_LITESERVERS` or `TON_LITESERVERS_FILE` form a set named
+/// `custom` that comes first, with the validator section of the first global config. They
+/// require `TON_GLOBAL_CONFIG`, so the bundled testnet config never backs a custom set.
+///
+/// # Panics
+///
+/// Panics if a config file can not be read, a custom liteserver is invalid, or custom
+/// liteservers are given without `TON_GLOBAL_CONFIG`.
+pub fn liteserver_sets() -> Vec<LiteserverSet> {
+    let paths: Vec<String> = config::var("TON_GLOBAL_CONFIG").unwrap_or_default()
+        .split(',')
+        .map(| path | path.trim().to_string())
+        .filter(| path | !path.is_empty())
+        .collect();
+
+    let custom: Option<Vec<Value>> = custom_liteservers().unwrap_or_else(| err | panic!("[ FATAL ] Configuration Error: {}", err));
+    if custom.is_some() && paths.is_empty() {
+        panic!("[ FATAL ] Configuration Error: Custom liteservers require `TON_GLOBAL_CONFIG` with the global config of their network");
+    }
+
+    let mut sets: Vec<LiteserverSet> = match paths.is_empty() {
+        true => vec![LiteserverSet {
+            name: String::from("testnet"),
+            config: include_str!("../config/testnet-global.config.json").to_string()
+        }],
+        false => {
+            paths.into_iter().map(| path | {
+                let config: String = match std::fs::read_to_string(&path) {
+                    Ok(config) => config,
+                    Err(err) => panic!("[ FATAL ] Configuration Error: Can not read `TON_GLOBAL_CONFIG` file {}: {}", path, err)
+                };
+                let name: String = std::path::Path::new(&path).file_stem().map(| s | s.to_string_lossy().to_string()).unwrap_or(path.clone());
+
+                LiteserverSet { name, config }
+            }).collect()
+        }
+    };
+
+    if let Some(liteservers) = custom {
+        let mut config: Value = match serde_json::from_str(&sets[0].config) {
+            Ok(config) => config,
+            Err(err) => panic!("[ FATAL ] Configuration Error: Global config of liteserver set {} is not JSON: {}", sets[0].name, err)
+        };
+        config["liteservers"] = Value::Array(liteservers);
+
+        sets.insert(0, LiteserverSet { name: String::from("custom"), config: config.to_string() });
+    }
+
+    sets
+}
+
+/// Reads the custom liteservers from `TON_LITESERVERS`, or the file at `TON_LITESERVERS_FILE`.
+///
+/// Liteservers are given as `ip:port:key` entries, separated by commas or new lines, with
+/// the base64 ed25519 public key of the server. A file may hold the `liteservers` array of
+/// a global config instead. Lines starting with `#` are ignored.
+///
+/// # Returns
+///
+/// The liteservers in the format of a global config, `None` if none are configured.
+fn custom_liteservers() -> Result<Option<Vec<Value>>, String> {
+    let (source, list): (&str, String) = match (config::var("TON_LITESERVERS"), config::var("TON_LITESERVERS_FILE")) {
+        (Ok(list), _) if !list.trim().is_empty() => ("TON_LITESERVERS", list),
+        (_, Ok(path)) if !path.trim().is_empty() => ("TON_LITESERVERS_FILE", std::fs::read_to_string(path.trim())
+            .map_err(| err | format!("Can not read `TON_LITESERVERS_FILE` file {}: {}", path, err))?),
+        _ => return Ok(None)
+    };
+
+    if list.trim_start().starts_with('[') {
+        let liteservers: Vec<Value> = serde_json::from_str(&list).map_err(| err | format!("`{}` is not a liteserver array: {}", source, err))?;
+        return Ok(Some(liteservers).filter(| l | !l.is_empty()));
+    }
+
+    let mut liteservers: Vec<Value> = Vec::new();
+    for entry in list.lines().filter(| l | !l.trim_start().starts_with('#')).flat_map(| l | l.split(',')).map(str::trim).filter(| e | !e.is_empty()) {
+        let invalid = | reason: &str | format!("`{}` has an invalid liteserver `{}`, expected `ip:port:key`: {}", source, entry, reason);
+
+        let mut parts = entry.splitn(3, ':');
+        let (Some(ip), Some(port), Some(key)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid("missing fields"));
+        };
+
+        let ip: Ipv4Addr = ip.parse().map_err(|_| invalid("the ip is not an IPv4 address"))?;
+        let port: u16 = port.parse().map_err(|_| invalid("the port is not a number"))?;
+        match general_purpose::STANDARD.decode(key) {
+            Ok(bytes) if bytes.len() == 32 => {},
+            _ => return Err(invalid("the key is not a base64 ed25519 public key"))
+        }
+
+        // global configs hold the address as a signed 32-bit integer
+        liteservers.push(json!({
+            "ip": u32::from(ip) as i32,
+            "port": port,
+            "id": { "@type": "pub.ed25519", "key": key }
+        }));
+    }
+
+    Ok(Some(liteservers).filter(| l | !l.is_empty()))
+}
+
+/// Initializes and returns a TON client.
+///
+/// # Arguments
+///
+/// * `config` - The global config with the liteservers to connect to.
+async fn ton_client(config: String) -> Result<TonClient, String> {
+    TonClientBuilder::new()
+        .with_connection_params(&TonConnectionParams{
+            config,
+            blockchain_name: None,
+            use_callbacks_for_network: false,
+            ignore_cache: false,
+            keystore_dir: None,
+            notification_queue_length: 100,
+            concurrency_limit: 5,
+        })
+        .with_pool_size(10)
+        .with_logging_callback()
+        .build()
+        .await
+        .map_err(| err | format!("{:?}", err))
+}
+
+/// Talks to the network through tonlib liteserver connections.
+pub struct LiteBackend {
+    name: String,
+    client: TonClient,
+    contract_factory: TonContractFactory
+}
+
+impl LiteBackend {
+    /// Connects to the liteservers of a set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TON client initialization fails.
+    pub async fn connect(set: LiteserverSet) -> Self {
+        match LiteBackend::try_connect(set).await {
+            Ok(backend) => backend,
+            Err(err) => {
+                panic!("[ FATAL ] Ton Client Initialization Error: Can not establish connection \n {}", err);
+            }
+        }
+    }
+
+    /// Connects to the liteservers of a set, failing instead of panicking.
+    pub async fn try_connect(set: LiteserverSet) -> Result<Self, String> {
+        let client: TonClient = ton_client(set.config).await?;
+        let contract_factory: TonContractFactory = TonContractFactory::builder(&client).build().await.map_err(|e| e.to_string())?;
+
+        Ok(LiteBackend {
+            name: set.name,
+            client,
+            contract_factory
+        })
+    }
+}
+
+#[async_trait]
+impl TonBackend for LiteBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn seqno(&self, wallet: &TonAddress) -> Result<u32, String> {
+        let _permit = read_permit().await;
+        self.contract_factory.get_contract(wallet).seqno().await.map_err(|e| e.to_string())
+    }
+
+    async fn run_get_method(&self, address: &TonAddress, method: &'static str, stack: Vec<TvmStackEntry>) -> Result<Vec<TvmStackEntry>, String> {
+        let _permit = read_permit().await;
+        let result: TvmSuccess = self.contract_factory.get_contract(address)
+            .run_get_method(method, &stack)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(result.stack)
+    }
+
+    async fn send(&self, boc: &[u8]) -> Result<Vec<u8>, String> {
+        send_pace().await;
+        let _permit = send_permit().await;
+        self.client.send_raw_message_return_hash(boc).await.map_err(|e| e.to_string())
+    }
+
+    async fn account_state(&self, address: &TonAddress) -> Result<AccountState, String> {
+        let _permit = read_permit().await;
+        let state: RawFullAccountState = self.client.get_raw_account_state(address).await.map_err(|e| e.to_string())?;
+        let code: Option<Cell> = parse_code(&state.code)?;
+
+        Ok(AccountState {
+            balance: state.balance,
+            active: !state.code.is_empty(),
+            code_hash: code.as_ref().map(| code | hex::encode(code.cell_hash())),
+            code,
+            last_transaction_id: state.last_transaction_id
+        })
+    }
+
+    async fn transactions(&self, address: &TonAddress, from: &InternalTransactionId, count: usize) -> Result<RawTransactions, String> {
+        let _permit = read_permit().await;
+        self.client.get_raw_transactions_v2(address, from, count, false).await.map_err(|e| e.to_string())
+    }
+
+    async fn masterchain_info(&self) -> Result<MasterchainInfo, String> {
+        let _permit = read_permit().await;
+        let (_, info): (_, BlocksMasterchainInfo) = self.client.get_masterchain_info().await.map_err(|e| e.to_string())?;
+        let header: BlocksHeader = self.client.get_block_header(&info.last).await.map_err(|e| e.to_string())?;
+
+        Ok(MasterchainInfo {
+            seqno: info.last.seqno as u32,
+            utime: header.gen_utime as i64
+        })
+    }
+}
+
+/// Parses the serialized code of an account, `None` if it has no code.
+pub(super) fn parse_code(code: &[u8]) -> Result<Option<Cell>, String> {
+    if code.is_empty() {
+        return Ok(None);
+    }
+
+    BagOfCells::parse(code)
+        .and_then(| b | b.single_root())
+        .map(| code | Some(code.as_ref().clone()))
+        .map_err(|e| e.to_string())
+}
+
+/// Wraps a backend and records the count, errors and latency of every call in `metrics`.
+pub struct Instrumented {
+    inner: Box<dyn TonBackend>
+}
+
+impl Instrumented {
+    /// Wraps a backend.
+    pub fn new(inner: Box<dyn TonBackend>) -> Self {
+        Instrumented { inner }
+    }
+
+    /// Runs a call of the wrapped backend and records it under the RPC type `rpc`.
+    async fn observe<T>(&self, rpc: &'static str, call: impl Future<Output = Result<T, String>>) -> Result<T, String> {
+        let started: Instant = Instant::now();
+        let result: Result<T, String> = call.await;
+
+        metrics::observe_rpc(self.inner.name(), rpc, result.is_ok(), started.elapsed().as_secs_f64() * 1000.0);
+        result
+    }
+}
+
+#[async_trait]
+impl TonBackend for Instrumented {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn seqno(&self, wallet: &TonAddress) -> Result<u32, String> {
+        self.observe("seqno", self.inner.seqno(wallet)).await
+    }
+
+    async fn run_get_method(&self, address: &TonAddress, method: &'static str, stack: Vec<TvmStackEntry>) -> Result<Vec<TvmStackEntry>, String> {
+        self.observe("run_get_method", self.inner.run_get_method(address, method, stack)).await
+    }
+
+    async fn send(&self, boc: &[u8]) -> Result<Vec<u8>, String> {
+        self.observe("send_raw_message", self.inner.send(boc)).await
+    }
+
+    async fn account_state(&self, address: &TonAddress) -> Result<AccountState, String> {
+        self.observe("get_account_state", self.inner.account_state(address)).await
+    }
+
+    async fn transactions(&self, address: &TonAddress, from: &InternalTransactionId, count: usize) -> Result<RawTransactions, String> {
+        self.observe("get_transactions", self.inner.transactions(address, from, count)).await
+    }
+
+    async fn masterchain_info(&self) -> Result<MasterchainInfo, String> {
+        self.observe("get_masterchain_info", self.inner.masterchain_info()).await
+    }
+}
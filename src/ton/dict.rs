@@ -0,0 +1,396 @@
+//! # Recipient Dictionary
+//!
+//! This module serializes the recipients of a spread into a TL-B `HashmapE 16` keyed by the
+//! index of the recipient, for contract versions that read the recipients with dictionary
+//! primitives instead of walking a linked list. The dictionary is built by hand, following
+//! the `Hashmap` scheme of the block layout:
+//!
+//! ```text
+//! hm_edge#_ label:(HmLabel ~l n) node:(HashmapNode m X) = Hashmap n X;   // n = m + l
+//! hmn_leaf#_ value:X = HashmapNode 0 X;
+//! hmn_fork#_ left:^(Hashmap n X) right:^(Hashmap n X) = HashmapNode (n + 1) X;
+//! hml_short$0 len:(Unary ~n) s:(n * Bit) = HmLabel ~n m;
+//! hml_long$10 n:(#<= m) s:(n * Bit) = HmLabel ~n m;
+//! hml_same$11 v:Bit n:(#<= m) = HmLabel ~n m;
+//! ```
+//!
+//! Every label is stored in the shortest of its three forms, and the value of a leaf is a
+//! recipient as in a cell of the list, see `store_recipient`.
+//!
+//! The module also holds the layout of a recipient, which like the encoding follows the
+//! deployed contract: the `legacy` layout stores the address and the coins, the `bounce`
+//! layout appends the bounce flag of the internal message for contracts that read it.
+
+use std::sync::OnceLock;
+
+use num_bigint::BigUint;
+use tonlib::{address::TonAddress, cell::{ArcCell, Cell, CellBuilder, CellParser}};
+
+use crate::{config, types::SpreadWallet};
+
+/// Bits of the keys of the recipient dictionary.
+pub const SPREAD_DICT_KEY_BITS: u32 = 16;
+
+/// Represents how the recipients of a spread are stored in the message body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpreadEncoding {
+    /// A chain of cells, each referencing the cell of the previous recipient.
+    List,
+    /// A `HashmapE 16` keyed by the index of the recipient.
+    Dict
+}
+
+/// The encoding of the spread bodies, read once on startup.
+static SPREAD_ENCODING: OnceLock<SpreadEncoding> = OnceLock::new();
+
+/// Returns the encoding of the spread bodies, taken from `SPREAD_ENCODING` (`list` or `dict`).
+///
+/// The encoding follows the deployed contract, so it is read on the first call, which `main`
+/// makes on startup, and needs a restart to change.
+///
+/// # Panics
+///
+/// Panics on the first call if `SPREAD_ENCODING` is invalid.
+pub fn spread_encoding() -> SpreadEncoding {
+    *SPREAD_ENCODING.get_or_init(|| {
+        let encoding_str: String = config::env_or("SPREAD_ENCODING", String::from("list"));
+
+        match encoding_str.as_str() {
+            "list" => SpreadEncoding::List,
+            "dict" => SpreadEncoding::Dict,
+            _ => panic!("[ FATAL ] Configuration Error: `SPREAD_ENCODING` has an invalid value `{}`", encoding_str)
+        }
+    })
+}
+
+/// Represents what a recipient of a spread holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpreadLayout {
+    /// The address and the coins, the contract sets the bounce flag of the message.
+    Legacy,
+    /// The address, the coins and the bounce flag of the message.
+    Bounce
+}
+
+/// The layout of the spread recipients, read once on startup.
+static SPREAD_LAYOUT: OnceLock<SpreadLayout> = OnceLock::new();
+
+/// Returns the layout of the spread recipients, taken from `SPREAD_LAYOUT` (`legacy` or `bounce`).
+///
+/// The layout follows the deployed contract, so it is read on the first call, which `main`
+/// makes on startup, and needs a restart to change.
+///
+/// # Panics
+///
+/// Panics on the first call if `SPREAD_LAYOUT` is invalid.
+pub fn spread_layout() -> SpreadLayout {
+    *SPREAD_LAYOUT.get_or_init(|| {
+        let layout_str: String = config::env_or("SPREAD_LAYOUT", String::from("legacy"));
+
+        match layout_str.as_str() {
+            "legacy" => SpreadLayout::Legacy,
+            "bounce" => SpreadLayout::Bounce,
+            _ => panic!("[ FATAL ] Configuration Error: `SPREAD_LAYOUT` has an invalid value `{}`", layout_str)
+        }
+    })
+}
+
+/// Stores a recipient in the layout of `SPREAD_LAYOUT`.
+///
+/// # Returns
+///
+/// Nothing, or an error if the bounce flag of the recipient does not match the layout: the
+/// `bounce` layout needs one, the `legacy` layout can not carry it.
+pub fn store_recipient(builder: &mut CellBuilder, entry: &SpreadWallet) -> Result<(), String> {
+    builder.store_address(&entry.account).map_err(|e| e.to_string())?;
+    builder.store_coins(&entry.amount).map_err(|e| e.to_string())?;
+
+    match (spread_layout(), entry.bounce) {
+        (SpreadLayout::Bounce, Some(bounce)) => builder.store_bit(bounce).map(|_| ()).map_err(|e| e.to_string()), //bounce flag of the internal message
+        (SpreadLayout::Legacy, None) => Ok(()),
+        (SpreadLayout::Bounce, None) => Err(format!("recipient {} has no bounce flag, which `SPREAD_LAYOUT=bounce` stores", entry.account)),
+        (SpreadLayout::Legacy, Some(_)) => Err(format!("recipient {} has a bounce flag, which `SPREAD_LAYOUT=legacy` can not store", entry.account))
+    }
+}
+
+/// Loads a recipient stored by `store_recipient` in either layout.
+///
+/// The layout is told apart by the bits left after the coins: none in the `legacy` layout,
+/// whose recipients have no bounce flag, and the flag in the `bounce` layout.
+///
+/// # Returns
+///
+/// The recipient, or an error if the cell holds anything else.
+pub fn load_recipient(parser: &mut CellParser) -> Result<SpreadWallet, String> {
+    let account: TonAddress = parser.load_address().map_err(|e| e.to_string())?;
+    let amount: BigUint = parser.load_coins().map_err(|e| e.to_string())?;
+
+    let bounce: Option<bool> = match parser.remaining_bits() {
+        0 => None,
+        1 => Some(parser.load_bit().map_err(|e| e.to_string())?),
+        bits => return Err(format!("recipient {} is followed by {} unknown bits", account, bits))
+    };
+
+    Ok(SpreadWallet { account, amount, bounce })
+}
+
+/// Returns the bits of a `#<= m` field.
+fn len_bits(m: u32) -> u32 {
+    32 - m.leading_zeros()
+}
+
+/// Stores the label of an edge in the shortest of its forms.
+///
+/// # Arguments
+///
+/// * `label` - The `l` bits of the label, in the low bits.
+/// * `l` - The length of the label.
+/// * `m` - The key bits left at the edge, `l` at most.
+fn store_label(builder: &mut CellBuilder, label: u32, l: u32, m: u32) -> Result<(), String> {
+    let k: u32 = len_bits(m);
+    let same: bool = l > 0 && (label == 0 || label == (1u32 << l) - 1);
+
+    let short: u32 = 2 * l + 2;
+    let long: u32 = 2 + k + l;
+    let same_len: u32 = if same { 3 + k } else { u32::MAX };
+
+    if same_len < short && same_len < long {
+        builder.store_bit(true).map_err(|e| e.to_string())?;
+        builder.store_bit(true).map_err(|e| e.to_string())?;
+        builder.store_bit(label != 0).map_err(|e| e.to_string())?;
+        builder.store_u32(k as usize, l).map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+
+    if short <= long {
+        builder.store_bit(false).map_err(|e| e.to_string())?;
+        for _ in 0..l {
+            builder.store_bit(true).map_err(|e| e.to_string())?;
+        }
+        builder.store_bit(false).map_err(|e| e.to_string())?;
+    } else {
+        builder.store_bit(true).map_err(|e| e.to_string())?;
+        builder.store_bit(false).map_err(|e| e.to_string())?;
+        builder.store_u32(k as usize, l).map_err(|e| e.to_string())?;
+    }
+
+    if l > 0 {
+        builder.store_u32(l as usize, label).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Builds the edge of a `Hashmap n` holding the entries, sorted by their `n` bit keys.
+fn build_edge(entries: &[(u32, &SpreadWallet)], n: u32) -> Result<Cell, String> {
+    let first: u32 = entries[0].0;
+    let last: u32 = entries[entries.len() - 1].0;
+
+    // the label is the prefix all keys share
+    let l: u32 = if entries.len() == 1 { n } else { ((first ^ last) << (32 - n)).leading_zeros() };
+    let m: u32 = n - l;
+    let label: u32 = if l == 0 { 0 } else { first >> m };
+
+    let mut builder: CellBuilder = CellBuilder::new();
+    store_label(&mut builder, label, l, n)?;
+
+    if m == 0 {
+        let entry: &SpreadWallet = entries[0].1;
+        store_recipient(&mut builder, entry)?;
+
+        return builder.build().map_err(|e| format!("recipient {} does not fit a cell: {}", entry.account, e));
+    }
+
+    let mask: u32 = (1u32 << (m - 1)) - 1;
+    let split: usize = entries.iter().position(| (key, _) | key & (1u32 << (m - 1)) != 0).unwrap();
+    let (left, right): (Vec<(u32, &SpreadWallet)>, Vec<(u32, &SpreadWallet)>) = (
+        entries[..split].iter().map(| (key, entry) | (key & mask, *entry)).collect(),
+        entries[split..].iter().map(| (key, entry) | (key & mask, *entry)).collect()
+    );
+
+    builder.store_reference(&ArcCell::new(build_edge(&left, m - 1)?)).map_err(|e| e.to_string())?;
+    builder.store_reference(&ArcCell::new(build_edge(&right, m - 1)?)).map_err(|e| e.to_string())?;
+
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Builds the root of the recipient dictionary, keyed by the index of every recipient.
+///
+/// # Returns
+///
+/// The root of the `Hashmap`, the cell referenced by the `hme_root$1` of a `HashmapE`, or an
+/// error if there are no recipients or more than the keys can index.
+pub fn build(recipients: &[SpreadWallet]) -> Result<Cell, String> {
+    if recipients.is_empty() {
+        return Err("a spread has at least one recipient".into());
+    }
+    if recipients.len() > 1 << SPREAD_DICT_KEY_BITS {
+        return Err(format!("a recipient dictionary holds at most {} recipients", 1u32 << SPREAD_DICT_KEY_BITS));
+    }
+
+    let entries: Vec<(u32, &SpreadWallet)> = recipients.iter().enumerate().map(| (i, r) | (i as u32, r)).collect();
+
+    build_edge(&entries, SPREAD_DICT_KEY_BITS)
+}
+
+/// Loads an edge label, returning its bits and length.
+fn load_label(parser: &mut CellParser, m: u32) -> Result<(u32, u32), String> {
+    let k: u32 = len_bits(m);
+
+    if !parser.load_bit().map_err(|e| e.to_string())? {
+        let mut l: u32 = 0;
+        while parser.load_bit().map_err(|e| e.to_string())? {
+            l += 1;
+        }
+        if l > m {
+            return Err(format!("dictionary label of {} bits exceeds the {} bits left", l, m));
+        }
+        let label: u32 = if l > 0 { parser.load_u32(l as usize).map_err(|e| e.to_string())? } else { 0 };
+
+        return Ok((label, l));
+    }
+
+    let same: bool = parser.load_bit().map_err(|e| e.to_string())?;
+    let bit: bool = same && parser.load_bit().map_err(|e| e.to_string())?;
+    let l: u32 = if k > 0 { parser.load_u32(k as usize).map_err(|e| e.to_string())? } else { 0 };
+    if l > m {
+        return Err(format!("dictionary label of {} bits exceeds the {} bits left", l, m));
+    }
+
+    let label: u32 = match (same, l) {
+        (_, 0) => 0,
+        (true, _) => if bit { (1u32 << l) - 1 } else { 0 },
+        (false, _) => parser.load_u32(l as usize).map_err(|e| e.to_string())?
+    };
+
+    Ok((label, l))
+}
+
+/// Collects the entries below an edge of a `Hashmap n`, in the order of their keys.
+fn parse_edge(cell: &Cell, n: u32, prefix: u32, entries: &mut Vec<(u32, SpreadWallet)>) -> Result<(), String> {
+    let mut parser = cell.parser();
+    let (label, l): (u32, u32) = load_label(&mut parser, n)?;
+    let m: u32 = n - l;
+    let key: u32 = if l == 0 { prefix } else { (prefix << l) | label };
+
+    if m == 0 {
+        entries.push((key, load_recipient(&mut parser)?));
+        return Ok(());
+    }
+
+    let left: Cell = parser.next_reference().map_err(|e| e.to_string())?.as_ref().clone();
+    let right: Cell = parser.next_reference().map_err(|e| e.to_string())?.as_ref().clone();
+
+    parse_edge(&left, m - 1, key << 1, entries)?;
+    parse_edge(&right, m - 1, (key << 1) | 1, entries)
+}
+
+/// Reads the recipients of a dictionary built by `build`, in the order of their indexes.
+///
+/// # Returns
+///
+/// The recipients, or an error if the cell is no `Hashmap 16` of recipients or indexes are missing.
+pub fn parse(root: &Cell) -> Result<Vec<SpreadWallet>, String> {
+    let mut entries: Vec<(u32, SpreadWallet)> = Vec::new();
+    parse_edge(root, SPREAD_DICT_KEY_BITS, 0, &mut entries)?;
+
+    if let Some((index, (key, _))) = entries.iter().enumerate().find(| (i, (key, _)) | *key != *i as u32) {
+        return Err(format!("recipient dictionary has key {} at index {}", key, index));
+    }
+
+    Ok(entries.into_iter().map(| (_, recipient) | recipient).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns recipients with distinct addresses and amounts, in the legacy layout.
+    fn recipients(count: usize) -> Vec<SpreadWallet> {
+        (0..count).map(| i | {
+            let mut hash: [u8; 32] = [0xab; 32];
+            hash[..8].copy_from_slice(&(i as u64).to_be_bytes());
+
+            SpreadWallet { account: TonAddress::new(0, &hash), amount: BigUint::from(i as u64 + 1), bounce: None }
+        }).collect()
+    }
+
+    /// Asserts that a dictionary of `count` recipients parses back to the recipients in their order.
+    fn assert_round_trip(count: usize) {
+        let expected: Vec<SpreadWallet> = recipients(count);
+        let parsed: Vec<SpreadWallet> = parse(&build(&expected).unwrap()).unwrap();
+
+        assert_eq!(parsed.len(), count);
+        for (index, (parsed, expected)) in parsed.iter().zip(&expected).enumerate() {
+            assert_eq!(parsed.account, expected.account, "account of recipient {}", index);
+            assert_eq!(parsed.amount, expected.amount, "amount of recipient {}", index);
+            assert_eq!(parsed.bounce, None, "bounce flag of recipient {}", index);
+        }
+    }
+
+    #[test]
+    fn round_trips_small_dictionaries() {
+        for count in [1, 2, 3, 7, 255, 256, 257] {
+            assert_round_trip(count);
+        }
+    }
+
+    #[test]
+    fn round_trips_the_largest_dictionary() {
+        assert_round_trip(1 << SPREAD_DICT_KEY_BITS);
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_dictionaries() {
+        assert!(build(&[]).is_err());
+        assert!(build(&recipients((1 << SPREAD_DICT_KEY_BITS) + 1)).is_err());
+    }
+
+    #[test]
+    fn single_recipient_is_a_leaf_and_more_fork() {
+        assert!(build(&recipients(1)).unwrap().references().is_empty());
+        assert_eq!(build(&recipients(2)).unwrap().references().len(), 2);
+        assert_eq!(build(&recipients(7)).unwrap().references().len(), 2);
+    }
+
+    #[test]
+    fn labels_take_their_shortest_form() {
+        for (label, l, m) in [(0, 0, 16), (0, 16, 16), (0xffff, 16, 16), (0b1011, 4, 16), (1, 1, 1), (0x7fff, 15, 15), (0x1234, 13, 16)] {
+            let mut builder: CellBuilder = CellBuilder::new();
+            store_label(&mut builder, label, l, m).unwrap();
+            let cell: Cell = builder.build().unwrap();
+
+            assert_eq!(load_label(&mut cell.parser(), m).unwrap(), (label, l), "label {:b} of {} bits", label, l);
+        }
+    }
+
+    #[test]
+    fn loads_recipients_in_both_layouts() {
+        let account: TonAddress = TonAddress::new(0, &[7u8; 32]);
+        let amount: BigUint = BigUint::from(1_500_000_000u64);
+
+        for bounce in [None, Some(false), Some(true)] {
+            let mut builder: CellBuilder = CellBuilder::new();
+            builder.store_address(&account).unwrap();
+            builder.store_coins(&amount).unwrap();
+            if let Some(bounce) = bounce {
+                builder.store_bit(bounce).unwrap();
+            }
+            let cell: Cell = builder.build().unwrap();
+
+            let recipient: SpreadWallet = load_recipient(&mut cell.parser()).unwrap();
+            assert_eq!((recipient.account, recipient.amount, recipient.bounce), (account.clone(), amount.clone(), bounce));
+        }
+    }
+
+    #[test]
+    fn rejects_recipients_followed_by_unknown_bits() {
+        let mut builder: CellBuilder = CellBuilder::new();
+        builder.store_address(&TonAddress::new(0, &[7u8; 32])).unwrap();
+        builder.store_coins(&BigUint::from(1u64)).unwrap();
+        builder.store_u8(2, 3).unwrap();
+        let cell: Cell = builder.build().unwrap();
+
+        assert!(load_recipient(&mut cell.parser()).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! # Liteserver Failover
+//!
+//! This module implements a backend that spreads the risk of a degraded liteserver set over
+//! several global configs. Calls go to the active set until it fails `TON_FAILOVER_ERRORS`
+//! calls in a row or answers `TON_FAILOVER_SLOW_CALLS` calls in a row slower than
+//! `TON_FAILOVER_LATENCY` milliseconds, then the next set takes over, wrapping around after
+//! the last one. Sets other than the first are connected when they are failed over to.
+//!
+//! With `TONCENTER_URL` set, the toncenter HTTP API is the last member, see `http`. While it
+//! serves the calls the mixer is degraded, see `degraded`, and after `TONCENTER_FAILBACK_INTERVAL`
+//! seconds the first set is tried again.
+//!
+//! Only transport and timeout errors count as failed calls. Errors the chain answered with,
+//! like rejected external messages or get-methods exiting with an error code, are the
+//! outcome of the request and say nothing about the health of the set.
+//!
+//! Failovers are logged and counted in `ton_liteserver_failovers_total`, and the RPC metrics
+//! are recorded under the name of the set that served them.
+
+use std::{future::Future, sync::{atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering}, Mutex}, time::{Duration, Instant}};
+
+use async_trait::async_trait;
+use tokio::sync::OnceCell;
+use tonlib::{address::TonAddress, tl::{InternalTransactionId, RawTransactions}, types::TvmStackEntry};
+
+use crate::{config, metrics};
+
+use super::{backend::{AccountState, LiteBackend, LiteserverSet, MasterchainInfo, TonBackend}, http::HttpBackend};
+
+/// Consecutive failed calls after which the next set takes over, used when `TON_FAILOVER_ERRORS` is not set.
+const DEFAULT_MAX_ERRORS: u32 = 5;
+
+/// Latency in milliseconds above which a call counts as slow, used when `TON_FAILOVER_LATENCY` is not set.
+const DEFAULT_MAX_LATENCY: u64 = 5000;
+
+/// Consecutive slow calls after which the next set takes over, used when `TON_FAILOVER_SLOW_CALLS` is not set.
+const DEFAULT_MAX_SLOW_CALLS: u32 = 10;
+
+/// Seconds on the HTTP fallback before the first set is tried again, used when `TONCENTER_FAILBACK_INTERVAL` is not set.
+const DEFAULT_FAILBACK_INTERVAL: u64 = 300;
+
+/// Parts of errors the chain answered with, lowercase: exit codes of rejected external
+/// messages and of get-methods, as reported by liteservers, tonlib and toncenter.
+const ANSWERED_ERRORS: [&str; 3] = ["exitcode=", "exit code", "exit_code"];
+
+/// Returns whether an error is a transport or timeout error rather than an answer of the chain.
+fn transport_error(err: &str) -> bool {
+    let err: String = err.to_lowercase();
+    !ANSWERED_ERRORS.iter().any(| answer | err.contains(answer))
+}
+
+/// Whether calls are served by the HTTP fallback.
+static DEGRADED: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether calls are served by the toncenter HTTP API instead of liteservers.
+pub fn degraded() -> bool {
+    DEGRADED.load(Ordering::SeqCst)
+}
+
+/// Represents a member of the failover and its backend, connected on first use.
+struct Member {
+    name: String,
+    /// The liteserver set of the member, `None` for the HTTP fallback.
+    set: Option<LiteserverSet>,
+    backend: OnceCell<Box<dyn TonBackend>>
+}
+
+/// Fails over between liteserver sets.
+pub struct Failover {
+    members: Vec<Member>,
+    /// Index of the set calls go to.
+    active: AtomicUsize,
+    /// Consecutive failed calls of the active set.
+    errors: AtomicU32,
+    /// Consecutive slow calls of the active set.
+    slow: AtomicU32,
+    /// Serializes failovers, so concurrent failing calls move on by one set only, holding the time of the last one.
+    switching: Mutex<Instant>,
+    max_errors: u32,
+    max_latency: Duration,
+    max_slow_calls: u32,
+    failback_interval: Duration
+}
+
+impl Failover {
+    /// Creates a backend failing over between the sets, in their order, and the HTTP fallback last.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no sets.
+    pub fn new(sets: Vec<LiteserverSet>, fallback: Option<HttpBackend>) -> Self {
+        assert!(!sets.is_empty(), "failover needs at least one liteserver set");
+
+        let mut members: Vec<Member> = sets.into_iter()
+            .map(| set | Member { name: set.name.clone(), set: Some(set), backend: OnceCell::new() })
+            .collect();
+
+        if let Some(fallback) = fallback {
+            members.push(Member {
+                name: fallback.name().to_string(),
+                set: None,
+                backend: OnceCell::new_with(Some(Box::new(fallback) as Box<dyn TonBackend>))
+            });
+        }
+
+        metrics::set_gauge("ton_backend_degraded", 0.0);
+
+        Failover {
+            members,
+            active: AtomicUsize::new(0),
+            errors: AtomicU32::new(0),
+            slow: AtomicU32::new(0),
+            switching: Mutex::new(Instant::now()),
+            max_errors: config::env_or("TON_FAILOVER_ERRORS", DEFAULT_MAX_ERRORS).max(1),
+            max_latency: Duration::from_millis(config::env_or("TON_FAILOVER_LATENCY", DEFAULT_MAX_LATENCY)),
+            max_slow_calls: config::env_or("TON_FAILOVER_SLOW_CALLS", DEFAULT_MAX_SLOW_CALLS).max(1),
+            failback_interval: Duration::from_secs(config::env_or("TONCENTER_FAILBACK_INTERVAL", DEFAULT_FAILBACK_INTERVAL))
+        }
+    }
+
+    /// Returns the index and the backend of the active set, connecting to it on first use.
+    ///
+    /// A set that can not be connected to is failed over right away. Once the HTTP fallback
+    /// served calls for the failback interval, the first set takes over again.
+    async fn active(&self) -> Result<(usize, &dyn TonBackend), String> {
+        let mut index: usize = self.active.load(Ordering::SeqCst);
+
+        if self.members[index].set.is_none() && self.switching.lock().unwrap().elapsed() >= self.failback_interval {
+            self.fail_over(index, "failback");
+            index = self.active.load(Ordering::SeqCst);
+        }
+
+        let member: &Member = &self.members[index];
+        let connected = member.backend.get_or_try_init(|| async {
+            match &member.set {
+                Some(set) => LiteBackend::try_connect(set.clone()).await.map(| b | Box::new(b) as Box<dyn TonBackend>),
+                None => Err(String::from("the HTTP fallback is not configured"))
+            }
+        }).await;
+
+        match connected {
+            Ok(backend) => Ok((index, backend.as_ref())),
+            Err(err) => {
+                self.fail_over(index, "connect");
+                Err(format!("can not connect to liteserver set {}: {}", member.name, err))
+            }
+        }
+    }
+
+    /// Runs a call of the set at `index` and fails over when the set keeps failing or is slow.
+    ///
+    /// Errors the chain answered with count as successful calls of the set.
+    async fn observe<T>(&self, index: usize, call: impl Future<Output = Result<T, String>>) -> Result<T, String> {
+        let started: Instant = Instant::now();
+        let result: Result<T, String> = call.await;
+
+        // a call that started before a failover says nothing about the new set
+        if self.active.load(Ordering::SeqCst) != index {
+            return result;
+        }
+
+        if !result.as_ref().is_err_and(| err | transport_error(err)) {
+            self.errors.store(0, Ordering::SeqCst);
+        } else if self.errors.fetch_add(1, Ordering::SeqCst) + 1 >= self.max_errors {
+            self.fail_over(index, "errors");
+            return result;
+        }
+
+        if started.elapsed() <= self.max_latency {
+            self.slow.store(0, Ordering::SeqCst);
+        } else if self.slow.fetch_add(1, Ordering::SeqCst) + 1 >= self.max_slow_calls {
+            self.fail_over(index, "latency");
+        }
+
+        result
+    }
+
+    /// Moves on to the set after `index`, unless another call already failed over.
+    fn fail_over(&self, index: usize, reason: &'static str) {
+        let mut switched_at = self.switching.lock().unwrap();
+
+        if self.members.len() < 2 || self.active.load(Ordering::SeqCst) != index {
+            return;
+        }
+
+        let next: usize = (index + 1) % self.members.len();
+        self.active.store(next, Ordering::SeqCst);
+        self.errors.store(0, Ordering::SeqCst);
+        self.slow.store(0, Ordering::SeqCst);
+        *switched_at = Instant::now();
+
+        let (from, to): (&str, &str) = (&self.members[index].name, &self.members[next].name);
+        log_warn!("Liteserver set {} failed over to {} because of {}", from, to, reason);
+        metrics::observe_failover(from, to, reason);
+
+        let degraded: bool = self.members[next].set.is_none();
+        if degraded {
+            log_warn!("No liteserver set is usable, running degraded on the {} HTTP API", to);
+        }
+        DEGRADED.store(degraded, Ordering::SeqCst);
+        metrics::set_gauge("ton_backend_degraded", if degraded { 1.0 } else { 0.0 });
+    }
+}
+
+#[async_trait]
+impl TonBackend for Failover {
+    fn name(&self) -> &str {
+        &self.members[self.active.load(Ordering::SeqCst)].name
+    }
+
+    async fn seqno(&self, wallet: &TonAddress) -> Result<u32, String> {
+        let (index, backend) = self.active().await?;
+        self.observe(index, backend.seqno(wallet)).await
+    }
+
+    async fn run_get_method(&self, address: &TonAddress, method: &'static str, stack: Vec<TvmStackEntry>) -> Result<Vec<TvmStackEntry>, String> {
+        let (index, backend) = self.active().await?;
+        self.observe(index, backend.run_get_method(address, method, stack)).await
+    }
+
+    async fn send(&self, boc: &[u8]) -> Result<Vec<u8>, String> {
+        let (index, backend) = self.active().await?;
+        self.observe(index, backend.send(boc)).await
+    }
+
+    async fn account_state(&self, address: &TonAddress) -> Result<AccountState, String> {
+        let (index, backend) = self.active().await?;
+        self.observe(index, backend.account_state(address)).await
+    }
+
+    async fn transactions(&self, address: &TonAddress, from: &InternalTransactionId, count: usize) -> Result<RawTransactions, String> {
+        let (index, backend) = self.active().await?;
+        self.observe(index, backend.transactions(address, from, count)).await
+    }
+
+    async fn masterchain_info(&self) -> Result<MasterchainInfo, String> {
+        let (index, backend) = self.active().await?;
+        self.observe(index, backend.masterchain_info()).await
+    }
+}
@@ -0,0 +1,258 @@
+//! # Toncenter HTTP Backend
+//!
+//! This module provides the backend used when no liteserver set can be reached: it talks to
+//! a toncenter-compatible HTTP API v2 (toncenter.com, or a self-hosted `ton-http-api`) set
+//! with `TONCENTER_URL`, authenticated with `TONCENTER_API_KEY` when it is set. It is the
+//! last member of the failover, see `failover`, so the mixer stays operable during a
+//! liteserver incident, and reads and sends share the limits of the liteserver backend.
+//!
+//! The API answers from liteservers of its operator and enforces its own rate limits, so
+//! the mixer reports itself degraded while it runs on this backend.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
+use num_bigint::BigInt;
+use serde_json::{json, Value};
+use tonlib::{address::TonAddress, cell::{ArcCell, BagOfCells, Cell, CellSlice}, tl::{InternalTransactionId, RawTransaction, RawTransactions}, types::TvmStackEntry};
+
+use crate::{config, logging::trace};
+
+use super::backend::{self, AccountState, MasterchainInfo, TonBackend};
+
+/// Timeout of a request in seconds, used when `TONCENTER_TIMEOUT` is not set.
+const DEFAULT_TIMEOUT: u64 = 10;
+
+/// Shard id of the masterchain, as the API expects it.
+const MASTERCHAIN_SHARD: &str = "-9223372036854775808";
+
+/// Talks to the network through a toncenter-compatible HTTP API.
+pub struct HttpBackend {
+    url: String,
+    api_key: Option<String>,
+    client: reqwest::Client
+}
+
+impl HttpBackend {
+    /// Creates the backend configured with `TONCENTER_URL`, e.g. `https://toncenter.com/api/v2`.
+    ///
+    /// # Returns
+    ///
+    /// The backend, `None` if `TONCENTER_URL` is not set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client can not be built.
+    pub fn from_env() -> Option<Self> {
+        let url: String = config::var("TONCENTER_URL").ok().filter(| url | !url.trim().is_empty())?;
+        let timeout: u64 = config::env_or("TONCENTER_TIMEOUT", DEFAULT_TIMEOUT);
+
+        let client: reqwest::Client = match reqwest::Client::builder().timeout(Duration::from_secs(timeout)).build() {
+            Ok(client) => client,
+            Err(err) => panic!("[ FATAL ] Configuration Error: Can not build the `TONCENTER_URL` client: {}", err)
+        };
+
+        Some(HttpBackend {
+            url: url.trim().trim_end_matches('/').to_string(),
+            api_key: config::var("TONCENTER_API_KEY").ok().filter(| key | !key.is_empty()),
+            client
+        })
+    }
+
+    /// Calls a method of the API and returns its `result`.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The method, e.g. `getAddressInformation`.
+    /// * `query` - The query parameters of a GET request.
+    /// * `body` - The JSON body of a POST request, sent instead of a GET request if present.
+    async fn call(&self, method: &str, query: &[(&str, String)], body: Option<Value>) -> Result<Value, String> {
+        let url: String = format!("{}/{}", self.url, method);
+        let mut request: reqwest::RequestBuilder = match body {
+            Some(body) => trace::inject(self.client.post(url)).json(&body),
+            None => trace::inject(self.client.get(url)).query(query)
+        };
+
+        if let Some(key) = &self.api_key {
+            request = request.header("X-API-Key", key);
+        }
+
+        let response: reqwest::Response = request.send().await.map_err(| e | format!("toncenter {}: {}", method, e))?;
+        let status: reqwest::StatusCode = response.status();
+
+        // errors come with a JSON body too, e.g. `{"ok": false, "error": "...", "code": 429}`
+        let mut body: Value = response.json().await.map_err(| e | format!("toncenter {} returned {}: {}", method, status, e))?;
+
+        match body["ok"].as_bool() {
+            Some(true) => Ok(body["result"].take()),
+            _ => Err(format!("toncenter {} returned {}: {}", method, status, body["error"].as_str().unwrap_or("unknown error")))
+        }
+    }
+}
+
+#[async_trait]
+impl TonBackend for HttpBackend {
+    fn name(&self) -> &str {
+        "toncenter"
+    }
+
+    async fn seqno(&self, wallet: &TonAddress) -> Result<u32, String> {
+        let stack: Vec<TvmStackEntry> = self.run_get_method(wallet, "seqno", Vec::new()).await?;
+
+        match stack.first() {
+            Some(entry) => u32::try_from(entry.get_bigint().map_err(|e| e.to_string())?).map_err(|e| e.to_string()),
+            None => Err(format!("seqno of {} returned an empty stack", wallet))
+        }
+    }
+
+    async fn run_get_method(&self, address: &TonAddress, method: &'static str, stack: Vec<TvmStackEntry>) -> Result<Vec<TvmStackEntry>, String> {
+        let stack: Vec<Value> = stack.iter().map(stack_entry_to_json).collect::<Result<_, String>>()?;
+
+        let _permit = backend::read_permit().await;
+        let result: Value = self.call("runGetMethod", &[], Some(json!({
+            "address": address.to_base64_url(),
+            "method": method,
+            "stack": stack
+        }))).await?;
+
+        match result["exit_code"].as_i64() {
+            Some(0) | Some(1) => {},
+            code => return Err(format!("get-method `{}` of {} failed with exit code {:?}", method, address, code))
+        }
+
+        result["stack"].as_array()
+            .ok_or_else(|| format!("get-method `{}` of {} returned no stack", method, address))?
+            .iter()
+            .map(stack_entry_from_json)
+            .collect()
+    }
+
+    async fn send(&self, boc: &[u8]) -> Result<Vec<u8>, String> {
+        backend::send_pace().await;
+        let _permit = backend::send_permit().await;
+        let result: Value = self.call("sendBocReturnHash", &[], Some(json!({ "boc": general_purpose::STANDARD.encode(boc) }))).await?;
+
+        decode_base64(&result["hash"])
+    }
+
+    async fn account_state(&self, address: &TonAddress) -> Result<AccountState, String> {
+        let _permit = backend::read_permit().await;
+        let result: Value = self.call("getAddressInformation", &[("address", address.to_base64_url())], None).await?;
+
+        let code: Option<Cell> = backend::parse_code(&decode_base64(&result["code"])?)?;
+        let last_transaction_id: InternalTransactionId = serde_json::from_value(result["last_transaction_id"].clone())
+            .map_err(| e | format!("toncenter returned an invalid last transaction of {}: {}", address, e))?;
+
+        Ok(AccountState {
+            balance: integer(&result["balance"]).ok_or_else(|| format!("toncenter returned no balance of {}", address))?,
+            active: code.is_some(),
+            code_hash: code.as_ref().map(| code | hex::encode(code.cell_hash())),
+            code,
+            last_transaction_id
+        })
+    }
+
+    /// Fetches one transaction more than asked for, as the API doesn't return the id of the
+    /// transaction before the page.
+    async fn transactions(&self, address: &TonAddress, from: &InternalTransactionId, count: usize) -> Result<RawTransactions, String> {
+        let mut query: Vec<(&str, String)> = vec![
+            ("address", address.to_base64_url()),
+            ("limit", (count + 1).to_string()),
+            ("archival", String::from("true"))
+        ];
+        if from.lt != 0 {
+            query.push(("lt", from.lt.to_string()));
+            query.push(("hash", hex::encode(from.hash)));
+        }
+
+        let _permit = backend::read_permit().await;
+        let result: Value = self.call("getTransactions", &query, None).await?;
+
+        let mut transactions: Vec<RawTransaction> = serde_json::from_value(result)
+            .map_err(| e | format!("toncenter returned invalid transactions of {}: {}", address, e))?;
+
+        let previous_transaction_id: InternalTransactionId = match transactions.len() > count {
+            true => transactions.split_off(count).remove(0).transaction_id,
+            false => InternalTransactionId { lt: 0, hash: [0u8; 32] }
+        };
+
+        Ok(RawTransactions { transactions, previous_transaction_id })
+    }
+
+    async fn masterchain_info(&self) -> Result<MasterchainInfo, String> {
+        let _permit = backend::read_permit().await;
+        let info: Value = self.call("getMasterchainInfo", &[], None).await?;
+        let seqno: i64 = integer(&info["last"]["seqno"]).ok_or("toncenter returned no masterchain seqno")?;
+
+        let header: Value = self.call("getBlockHeader", &[
+            ("workchain", String::from("-1")),
+            ("shard", String::from(MASTERCHAIN_SHARD)),
+            ("seqno", seqno.to_string())
+        ], None).await?;
+
+        Ok(MasterchainInfo {
+            seqno: seqno as u32,
+            utime: integer(&header["gen_utime"]).ok_or("toncenter returned no block time")?
+        })
+    }
+}
+
+/// Converts a get-method argument to the `[type, value]` pair of the API.
+fn stack_entry_to_json(entry: &TvmStackEntry) -> Result<Value, String> {
+    match entry {
+        TvmStackEntry::Int257(value) => Ok(json!(["num", value.to_string()])),
+        TvmStackEntry::Cell(cell) => Ok(json!(["tvm.Cell", serialize(cell.as_ref().clone())?])),
+        TvmStackEntry::Slice(slice) => Ok(json!(["tvm.Slice", serialize(slice.into_cell().map_err(|e| e.to_string())?)?])),
+        entry => Err(format!("stack entry {:?} is not supported by toncenter", entry))
+    }
+}
+
+/// Converts a `[type, value]` pair of a get-method result to a stack entry.
+fn stack_entry_from_json(entry: &Value) -> Result<TvmStackEntry, String> {
+    let unsupported = || format!("stack entry {} returned by toncenter is not supported", entry);
+
+    match (entry[0].as_str(), &entry[1]) {
+        (Some("num"), Value::String(value)) => {
+            let (negative, digits): (bool, &str) = match value.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, value.as_str())
+            };
+            let number: BigInt = BigInt::parse_bytes(digits.trim_start_matches("0x").as_bytes(), 16).ok_or_else(unsupported)?;
+
+            Ok(TvmStackEntry::Int257(if negative { -number } else { number }))
+        },
+        (Some("cell"), value) => Ok(TvmStackEntry::Cell(deserialize(&value["bytes"])?)),
+        (Some("slice"), value) => Ok(TvmStackEntry::Slice(
+            CellSlice::full_cell(deserialize(&value["bytes"])?.as_ref().clone()).map_err(|e| e.to_string())?
+        )),
+        (Some("null"), _) => Ok(TvmStackEntry::Null),
+        _ => Err(unsupported())
+    }
+}
+
+/// Serializes a cell to a base64 BOC.
+fn serialize(cell: Cell) -> Result<String, String> {
+    let boc: Vec<u8> = BagOfCells::from_root(cell).serialize(true).map_err(|e| e.to_string())?;
+    Ok(general_purpose::STANDARD.encode(boc))
+}
+
+/// Parses the root cell of a base64 BOC.
+fn deserialize(boc: &Value) -> Result<ArcCell, String> {
+    BagOfCells::parse(&decode_base64(boc)?)
+        .and_then(| b | b.single_root())
+        .map_err(|e| e.to_string())
+}
+
+/// Decodes a base64 string of the API, empty if it is missing.
+fn decode_base64(value: &Value) -> Result<Vec<u8>, String> {
+    general_purpose::STANDARD.decode(value.as_str().unwrap_or_default()).map_err(|e| e.to_string())
+}
+
+/// Reads an integer the API returns either as a number or as a string.
+fn integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::String(value) => value.parse().ok(),
+        value => value.as_i64()
+    }
+}
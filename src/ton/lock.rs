@@ -0,0 +1,186 @@
+//! # Seqno Lock
+//!
+//! This module implements a lock around reading the seqno of a wallet and broadcasting the
+//! message signed with it, shared through Redis, so processes sending from the same wallet,
+//! e.g. the API and a CLI, never sign two messages with one seqno. It is enabled by setting
+//! `SEQNO_LOCK_REDIS_URL` to `redis://[user:password@]host:port[/db]`, or `rediss://` for
+//! TLS. For a wallet with the raw address `{address}`, a process
+//!
+//! 1. takes the lock with `SET mixer:seqno:lock:{address} {token} NX PX {SEQNO_LOCK_TTL}`,
+//!    retrying until `SEQNO_LOCK_WAIT` passed,
+//! 2. signs with the higher of the seqno of the wallet and `GET mixer:seqno:next:{address}`,
+//!    the seqno after the last message broadcast that may not be applied yet,
+//! 3. extends the lock with `PEXPIRE` right before every broadcast if it still holds `{token}`,
+//!    and does not broadcast if it lost the lock,
+//! 4. broadcasts and stores the seqno after its own in `mixer:seqno:next:{address}`, expiring
+//!    with the message,
+//! 5. and deletes the lock if it still holds `{token}`.
+//!
+//! Other tools sharing the wallet follow the same steps. A lock is never held longer than
+//! its TTL past the last extension, so a crashed process blocks the wallet for
+//! `SEQNO_LOCK_TTL` seconds at most. A lock dropped without being released, e.g. by a
+//! request dropped at its deadline, is released in the background.
+
+use std::time::{Duration, Instant};
+
+use redis::{aio::ConnectionManager, Client, FromRedisValue, Script};
+use tokio::sync::OnceCell;
+use tonlib::address::TonAddress;
+
+use crate::config;
+
+/// Seconds a lock is held at most, used when `SEQNO_LOCK_TTL` is not set.
+const DEFAULT_LOCK_TTL: u64 = 60;
+
+/// Seconds to wait for a lock, used when `SEQNO_LOCK_WAIT` is not set.
+const DEFAULT_LOCK_WAIT: u64 = 30;
+
+/// Interval between attempts to take a held lock.
+const LOCK_RETRY: Duration = Duration::from_millis(100);
+
+/// Time a Redis command may take.
+const REDIS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Extends the lock only if it still holds the token of its owner.
+const EXTEND_SCRIPT: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('PEXPIRE', KEYS[1], ARGV[2]) else return 0 end";
+
+/// Deletes the lock only if it still holds the token of its owner.
+const RELEASE_SCRIPT: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end";
+
+/// The connection shared by all locks, reconnecting on its own after a failure.
+static CONNECTION: OnceCell<ConnectionManager> = OnceCell::const_new();
+
+/// Returns the Redis URL of `SEQNO_LOCK_REDIS_URL`, `None` if the lock is disabled.
+fn redis_url() -> Option<String> {
+    config::var("SEQNO_LOCK_REDIS_URL").ok().filter(| u | !u.is_empty())
+}
+
+/// Returns the shared connection to `url`, connecting on first use.
+async fn connection(url: &str) -> Result<ConnectionManager, String> {
+    CONNECTION.get_or_try_init(|| async {
+        let client: Client = Client::open(url).map_err(|e| format!("invalid `SEQNO_LOCK_REDIS_URL`: {}", e))?;
+
+        actix_web::rt::time::timeout(REDIS_TIMEOUT, client.get_connection_manager()).await
+            .map_err(|_| String::from("Redis connection timed out"))?
+            .map_err(|e| format!("can not connect to Redis: {}", e))
+    }).await.cloned()
+}
+
+/// Runs a command or script invocation, bounded by `REDIS_TIMEOUT`.
+async fn query<T: FromRedisValue>(name: &str, command: impl std::future::Future<Output = redis::RedisResult<T>>) -> Result<T, String> {
+    actix_web::rt::time::timeout(REDIS_TIMEOUT, command).await
+        .map_err(|_| format!("Redis {} timed out", name))?
+        .map_err(|e| format!("Redis {} failed: {}", name, e))
+}
+
+/// Deletes the lock of a wallet if it still holds `token`.
+async fn unlock(mut connection: ConnectionManager, address: &str, token: &str) {
+    let key: String = format!("mixer:seqno:lock:{}", address);
+    let released: Result<i64, String> = query("EVAL", Script::new(RELEASE_SCRIPT).key(&key).arg(token).invoke_async(&mut connection)).await;
+
+    if let Err(err) = released {
+        log_error!("Can not release the seqno lock of wallet {}, it expires on its own: {}", address, err);
+    }
+}
+
+/// Represents a held lock on the seqno of a wallet.
+pub struct SeqnoLock {
+    connection: ConnectionManager,
+    address: String,
+    token: String,
+    ttl_ms: u64,
+    released: bool
+}
+
+impl SeqnoLock {
+    /// Takes the lock of a wallet, waiting up to `SEQNO_LOCK_WAIT` seconds while another process holds it.
+    ///
+    /// # Returns
+    ///
+    /// The lock, `None` if no `SEQNO_LOCK_REDIS_URL` is set, or an error if Redis can not be
+    /// reached or the lock stayed held.
+    pub async fn acquire(wallet: &TonAddress) -> Result<Option<SeqnoLock>, String> {
+        let url: String = match redis_url() {
+            Some(url) => url,
+            None => return Ok(None)
+        };
+
+        let ttl: u64 = config::env_or("SEQNO_LOCK_TTL", DEFAULT_LOCK_TTL);
+        let wait: Duration = Duration::from_secs(config::env_or("SEQNO_LOCK_WAIT", DEFAULT_LOCK_WAIT));
+        let address: String = wallet.to_hex();
+        let token: String = format!("{}-{:016x}", std::process::id(), rand::random::<u64>());
+
+        let mut connection: ConnectionManager = connection(&url).await?;
+        let key: String = format!("mixer:seqno:lock:{}", address);
+        let ttl_ms: u64 = ttl * 1000;
+        let started: Instant = Instant::now();
+
+        loop {
+            let taken: Option<String> = query("SET", redis::cmd("SET").arg(&key).arg(&token).arg("NX").arg("PX").arg(ttl_ms).query_async(&mut connection)).await?;
+            if taken.is_some() {
+                break;
+            }
+
+            if started.elapsed() >= wait {
+                return Err(format!("the seqno of wallet {} stayed locked by another process for {} seconds", address, wait.as_secs()));
+            }
+            actix_web::rt::time::sleep(LOCK_RETRY).await;
+        }
+
+        Ok(Some(SeqnoLock { connection, address, token, ttl_ms, released: false }))
+    }
+
+    /// Returns the seqno after the last message broadcast under the lock, if it did not expire yet.
+    pub async fn next_seqno(&mut self) -> Result<Option<u32>, String> {
+        let key: String = format!("mixer:seqno:next:{}", self.address);
+        let seqno: Option<String> = query("GET", redis::cmd("GET").arg(&key).query_async(&mut self.connection)).await?;
+
+        match seqno {
+            Some(seqno) => seqno.parse::<u32>().map(Some).map_err(|e| format!("`{}` holds no seqno: {}", key, e)),
+            None => Ok(None)
+        }
+    }
+
+    /// Extends the lock by another `SEQNO_LOCK_TTL`, right before a message is broadcast.
+    ///
+    /// # Returns
+    ///
+    /// Nothing, or an error if the lock expired and may be held by another process meanwhile,
+    /// in which case the message must not be broadcast.
+    pub async fn extend(&mut self) -> Result<(), String> {
+        let key: String = format!("mixer:seqno:lock:{}", self.address);
+        let extended: i64 = query("EVAL", Script::new(EXTEND_SCRIPT).key(&key).arg(&self.token).arg(self.ttl_ms).invoke_async(&mut self.connection)).await?;
+
+        match extended {
+            1 => Ok(()),
+            _ => Err(format!("the seqno lock of wallet {} expired before the message was broadcast, raise `SEQNO_LOCK_TTL`", self.address))
+        }
+    }
+
+    /// Records the broadcast of a message signed with `seqno` that is valid until `valid_until`.
+    pub async fn broadcast(&mut self, seqno: u32, valid_until: u64) -> Result<(), String> {
+        let key: String = format!("mixer:seqno:next:{}", self.address);
+        let ttl_ms: u64 = valid_until.saturating_sub(super::time_now()).max(1) * 1000;
+
+        query::<()>("SET", redis::cmd("SET").arg(&key).arg(seqno + 1).arg("PX").arg(ttl_ms).query_async(&mut self.connection)).await
+    }
+
+    /// Releases the lock, unless it expired and was taken by another process meanwhile.
+    pub async fn release(mut self) {
+        unlock(self.connection.clone(), &self.address, &self.token).await;
+        self.released = true;
+    }
+}
+
+impl Drop for SeqnoLock {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+
+        let (connection, address, token): (ConnectionManager, String, String) = (self.connection.clone(), self.address.clone(), self.token.clone());
+        actix_web::rt::spawn(async move {
+            unlock(connection, &address, &token).await;
+        });
+    }
+}
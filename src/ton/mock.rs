@@ -0,0 +1,166 @@
+//! # Mock TON Backend
+//!
+//! This module provides an in-memory `TonBackend`, so services and controllers can be
+//! exercised without network access. State is seeded with the `with_*` methods and
+//! sent messages are recorded instead of broadcast. Clones share their state, so a test
+//! keeps a clone to inspect the backend it installed with `ton::set_backend`.
+
+use std::{collections::{HashMap, VecDeque}, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use tonlib::{address::TonAddress, cell::{ArcCell, BagOfCells}, tl::{InternalTransactionId, RawTransaction, RawTransactions}, types::TvmStackEntry};
+
+use super::{backend::{AccountState, MasterchainInfo, TonBackend}, time_now};
+
+/// The transaction id tonlib uses to mark the beginning of an account history.
+fn empty_transaction_id() -> InternalTransactionId {
+    InternalTransactionId { lt: 0, hash: [0u8; 32] }
+}
+
+#[derive(Default)]
+struct MockState {
+    seqnos: HashMap<String, u32>,
+    accounts: HashMap<String, AccountState>,
+    transactions: HashMap<String, Vec<RawTransaction>>,
+    get_methods: HashMap<(String, String), Vec<TvmStackEntry>>,
+    masterchain: Option<MasterchainInfo>,
+    send_errors: VecDeque<String>,
+    sent: Vec<Vec<u8>>
+}
+
+/// In-memory `TonBackend`.
+///
+/// Unknown wallets have seqno `0`, unknown accounts are empty and inactive,
+/// unknown get-methods fail like a missing method would, and the masterchain
+/// is at seqno `0` with a block generated just now.
+#[derive(Default, Clone)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>
+}
+
+impl MockBackend {
+    /// Creates an empty mock backend.
+    pub fn new() -> Self {
+        MockBackend::default()
+    }
+
+    /// Sets the seqno returned for a wallet.
+    pub fn with_seqno(self, wallet: &TonAddress, seqno: u32) -> Self {
+        self.state.lock().unwrap().seqnos.insert(wallet.to_hex(), seqno);
+        self
+    }
+
+    /// Sets the state returned for an account.
+    pub fn with_account(self, address: &TonAddress, state: AccountState) -> Self {
+        self.state.lock().unwrap().accounts.insert(address.to_hex(), state);
+        self
+    }
+
+    /// Sets the transactions of an account, in any order.
+    pub fn with_transactions(self, address: &TonAddress, mut transactions: Vec<RawTransaction>) -> Self {
+        transactions.sort_by(| a, b | b.transaction_id.lt.cmp(&a.transaction_id.lt));
+        self.state.lock().unwrap().transactions.insert(address.to_hex(), transactions);
+        self
+    }
+
+    /// Sets the stack returned by a get-method of a contract.
+    pub fn with_get_method(self, address: &TonAddress, method: &str, stack: Vec<TvmStackEntry>) -> Self {
+        self.state.lock().unwrap().get_methods.insert((address.to_hex(), method.to_string()), stack);
+        self
+    }
+
+    /// Sets the latest masterchain block.
+    pub fn with_masterchain(self, seqno: u32, utime: i64) -> Self {
+        self.state.lock().unwrap().masterchain = Some(MasterchainInfo { seqno, utime });
+        self
+    }
+
+    /// Makes the next send fail with an error, e.g. the exit code of a stale seqno, instead
+    /// of recording the message. Errors queued by several calls are returned in order.
+    pub fn with_send_error(self, error: &str) -> Self {
+        self.state.lock().unwrap().send_errors.push_back(error.to_string());
+        self
+    }
+
+    /// Returns the messages sent so far, oldest first.
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().sent.clone()
+    }
+}
+
+#[async_trait]
+impl TonBackend for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn seqno(&self, wallet: &TonAddress) -> Result<u32, String> {
+        Ok(self.state.lock().unwrap().seqnos.get(&wallet.to_hex()).copied().unwrap_or(0))
+    }
+
+    async fn run_get_method(&self, address: &TonAddress, method: &'static str, _stack: Vec<TvmStackEntry>) -> Result<Vec<TvmStackEntry>, String> {
+        match self.state.lock().unwrap().get_methods.get(&(address.to_hex(), method.to_string())) {
+            Some(stack) => Ok(stack.clone()),
+            None => Err(format!("get-method `{}` is not mocked for {}", method, address))
+        }
+    }
+
+    /// Records the message and returns the hash of its root cell, or fails with the next
+    /// queued send error.
+    async fn send(&self, boc: &[u8]) -> Result<Vec<u8>, String> {
+        let root: ArcCell = BagOfCells::parse(boc)
+            .and_then(| b | b.single_root())
+            .map_err(|e| e.to_string())?;
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(error) = state.send_errors.pop_front() {
+            return Err(error);
+        }
+        state.sent.push(boc.to_vec());
+        Ok(root.cell_hash().to_vec())
+    }
+
+    async fn account_state(&self, address: &TonAddress) -> Result<AccountState, String> {
+        let state = self.state.lock().unwrap();
+
+        if let Some(account) = state.accounts.get(&address.to_hex()) {
+            return Ok(account.clone());
+        }
+
+        let last_transaction_id: InternalTransactionId = state.transactions.get(&address.to_hex())
+            .and_then(| t | t.first())
+            .map(| t | t.transaction_id.clone())
+            .unwrap_or_else(empty_transaction_id);
+
+        Ok(AccountState {
+            balance: 0,
+            active: false,
+            code_hash: None,
+            code: None,
+            last_transaction_id
+        })
+    }
+
+    async fn transactions(&self, address: &TonAddress, from: &InternalTransactionId, count: usize) -> Result<RawTransactions, String> {
+        let state = self.state.lock().unwrap();
+        let history: Vec<RawTransaction> = state.transactions.get(&address.to_hex()).cloned().unwrap_or_default();
+
+        let mut older = history.into_iter().filter(| t | t.transaction_id.lt <= from.lt);
+        let transactions: Vec<RawTransaction> = older.by_ref().take(count).collect();
+        let previous_transaction_id: InternalTransactionId = older.next()
+            .map(| t | t.transaction_id)
+            .unwrap_or_else(empty_transaction_id);
+
+        Ok(RawTransactions {
+            transactions,
+            previous_transaction_id
+        })
+    }
+
+    async fn masterchain_info(&self) -> Result<MasterchainInfo, String> {
+        Ok(self.state.lock().unwrap().masterchain.clone().unwrap_or(MasterchainInfo {
+            seqno: 0,
+            utime: time_now() as i64
+        }))
+    }
+}
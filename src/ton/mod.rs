@@ -4,216 +4,1890 @@
 //! including initializing a TON client, creating a wallet, and performing various contract operations.
 
 
-use std::{str::FromStr, thread, time::{Duration, SystemTime}};
+pub mod backend;
+pub mod dict;
+pub mod failover;
+pub mod http;
+pub mod lock;
+pub mod mock;
+pub mod offline;
+pub mod relay;
+pub mod signer;
 
-use tonlib::{address::TonAddress, cell::{ArcCell, Cell, CellBuilder}, client::{TonClient, TonClientBuilder, TonClientInterface, TonConnectionParams}, contract::{TonContract, TonContractFactory, TonWalletContract}, mnemonic::{KeyPair, Mnemonic}, wallet::{TonWallet, WalletVersion}
+use std::{collections::HashMap, str::FromStr, sync::OnceLock, time::{Duration, SystemTime}};
+
+use num_bigint::{BigInt, BigUint};
+use tonlib::{address::TonAddress, cell::{ArcCell, BagOfCells, Cell, CellBuilder, CellSlice}, mnemonic::KeyPair, tl::{InternalTransactionId, MsgData, RawMessage, RawTransaction, RawTransactions}, types::{TonHash, TvmStackEntry}, wallet::{TonWallet, WalletVersion}
 };
 
-use crate::types::{create_external_singed_message, CollectMessage, CollectMessageData, ForkMessage, SpreadMessage, SpreadWallet, TXHash};
+use tokio::sync::OnceCell;
+
+use sqlx::PgPool;
+
+use crate::{bus, config, db, deadline, explorer, leader, policy, types::outbox::{OUTBOX_EXPIRED, OUTBOX_UNKNOWN}};
+use crate::types::{nanotons::Nanotons, normalized_message_hash, create_external_signed_multi_message, AppliedTransaction, create_signed_internal_message, CellStats, ChildAddress, CodeVerification, CollectMessage, CollectMessageData, ForkMessage, MixerCollectionModes, OperationReceipt, SignedExternalMessage, SpreadFees, SpreadLegFee, SpreadMessage, SpreadWallet, TXHash, UpgradeMessage, WalletTransfer, DEFAULT_MESSAGE_TTL, DEFAULT_SEND_MODE, MAX_WALLET_MESSAGES, SEND_CARRY_ALL_BALANCE};
+use backend::{AccountState, Instrumented, LiteBackend, LiteserverSet, MasterchainInfo, TonBackend};
+use failover::Failover;
+use http::HttpBackend;
+use lock::SeqnoLock;
+use signer::{MnemonicSigner, Signer};
 use base64::{Engine as _, engine::general_purpose};
 use hex;
 
-/// Initializes and returns a TON client.
+/// The backend all network operations of the module go through.
+static BACKEND: OnceCell<Box<dyn TonBackend>> = OnceCell::const_new();
+
+/// Installs the backend used by the module, e.g. a `mock::MockBackend` in tests.
+///
+/// Must be called before the first network operation, as the liteserver backend
+/// is connected lazily on first use otherwise. Calls are recorded in the RPC metrics.
+pub fn set_backend(backend: Box<dyn TonBackend>) -> Result<(), String> {
+    BACKEND.set(Box::new(Instrumented::new(backend))).map_err(|_| String::from("ton backend is already initialized"))
+}
+
+/// Returns the installed backend, connecting to the liteservers on first use.
+///
+/// With several liteserver sets or the toncenter HTTP fallback configured, calls fail
+/// over between them, see `failover`.
+///
+/// # Panics
+///
+/// Panics if a config file can not be read, or the TON client initialization of a single set fails.
+pub async fn backend() -> &'static dyn TonBackend {
+    BACKEND.get_or_init(|| async {
+        let mut sets: Vec<LiteserverSet> = backend::liteserver_sets();
+
+        let inner: Box<dyn TonBackend> = match (sets.len(), HttpBackend::from_env()) {
+            (1, None) => Box::new(LiteBackend::connect(sets.remove(0)).await),
+            (_, fallback) => Box::new(Failover::new(sets, fallback))
+        };
+        Box::new(Instrumented::new(inner)) as Box<dyn TonBackend>
+    }).await.as_ref()
+}
+
+/// Default subwallet id of v4 wallets in the basechain.
+const DEFAULT_WALLET_ID: i32 = 698983191;
+
+/// Default wallet id of W5 wallets in the basechain of the mainnet.
+const DEFAULT_W5_WALLET_ID: i32 = 2147483409;
+
+/// Name of the account of `WALLET_ID`, used by operations without an account of their own.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// Represents a wallet account, one of the subwallets of the key of the signer.
+#[derive(Debug, Clone)]
+pub struct WalletAccount {
+    pub name: String,
+    pub wallet_id: i32
+}
+
+/// Represents the wallet accounts and the accounts selected for operations, read once on startup.
+struct Accounts {
+    /// The default account first, then the accounts of `WALLET_ACCOUNTS`.
+    all: Vec<WalletAccount>,
+    /// The account of every operation named by a `WALLET_ACCOUNT_<OP>` variable, by lowercase operation.
+    selected: HashMap<String, WalletAccount>
+}
+
+/// The wallet accounts, loaded on the first call of `wallet_accounts`, which `main` makes on startup.
+static ACCOUNTS: OnceLock<Accounts> = OnceLock::new();
+
+/// Returns the wallet accounts: the default account of `WALLET_ID` first, then the accounts of `WALLET_ACCOUNTS`.
+///
+/// `WALLET_ACCOUNTS` lists further accounts as comma separated `name:wallet_id` entries,
+/// e.g. `payouts:698983192,sweeps:698983193`. All accounts share the key of the signer,
+/// so one mnemonic backs them all, while each has its own address, balance and seqno.
+/// The accounts and the `WALLET_ACCOUNT_<OP>` selections are read once, like the wallet.
+///
+/// # Panics
+///
+/// Panics on the first call if an entry is invalid, a name or wallet id is used twice, or
+/// a `WALLET_ACCOUNT_<OP>` variable selects an account that is not configured.
+pub fn wallet_accounts() -> &'static [WalletAccount] {
+    &ACCOUNTS.get_or_init(load_accounts).all
+}
+
+/// Reads the accounts of `WALLET_ID` and `WALLET_ACCOUNTS` and the selections of `WALLET_ACCOUNT_<OP>`.
 ///
 /// # Panics
 ///
-/// Panics if the TON client initialization fails.
-async fn ton_client() -> TonClient {
-    let testnet_config = include_str!("../config/testnet-global.config.json").to_string();
-    let client_builder = TonClientBuilder::new()
-        .with_connection_params(&TonConnectionParams{
-            config: testnet_config,
-            blockchain_name: None,
-            use_callbacks_for_network: false,
-            ignore_cache: false,
-            keystore_dir: None,
-            notification_queue_length: 100,
-            concurrency_limit: 5,
-        })
-        .with_pool_size(10)
-        .with_logging_callback()
-        .build()
-        .await;
+/// Panics if an entry or a selection is invalid, see `wallet_accounts`.
+fn load_accounts() -> Accounts {
+    let (_, default_wallet_id): (WalletVersion, i32) = wallet_version();
+    let mut accounts: Vec<WalletAccount> = vec![WalletAccount {
+        name: String::from(DEFAULT_ACCOUNT),
+        wallet_id: config::env_or("WALLET_ID", default_wallet_id)
+    }];
+
+    let list: String = config::var("WALLET_ACCOUNTS").unwrap_or_default();
+    for entry in list.split(',').map(str::trim).filter(| e | !e.is_empty()) {
+        let account: Option<WalletAccount> = entry.split_once(':').and_then(| (name, wallet_id) | Some(WalletAccount {
+            name: name.trim().to_string(),
+            wallet_id: wallet_id.trim().parse().ok()?
+        }));
+
+        match account {
+            Some(account) if account.name.is_empty() => panic!("[ FATAL ] Configuration Error: `WALLET_ACCOUNTS` entry `{}` has no name", entry),
+            Some(account) if accounts.iter().any(| a | a.name == account.name || a.wallet_id == account.wallet_id) => {
+                panic!("[ FATAL ] Configuration Error: `WALLET_ACCOUNTS` entry `{}` repeats the name or wallet id of another account", entry)
+            },
+            Some(account) => accounts.push(account),
+            None => panic!("[ FATAL ] Configuration Error: `WALLET_ACCOUNTS` entry `{}` is not `name:wallet_id`", entry)
+        }
+    }
 
-    match client_builder {
-        Ok(client) => return client,
-        Err(err) => {
-            panic!("[ FATAL ] Ton Client Initialization Error: Can not establish connection \n {:?}", err);
+    let mut selected: HashMap<String, WalletAccount> = HashMap::new();
+    for (key, name) in std::env::vars().filter(| (key, name) | key.starts_with("WALLET_ACCOUNT_") && !name.trim().is_empty()) {
+        match accounts.iter().find(| a | a.name == name.trim()) {
+            Some(account) => { selected.insert(key["WALLET_ACCOUNT_".len()..].to_lowercase(), account.clone()); },
+            None => panic!("[ FATAL ] Configuration Error: `{}` selects the account `{}` that is not in `WALLET_ACCOUNTS`", key, name)
         }
     }
+
+    Accounts { all: accounts, selected }
+}
+
+/// Returns the account that sends the messages of an operation.
+///
+/// The account is selected by name with `WALLET_ACCOUNT_<OP>`, e.g. `WALLET_ACCOUNT_SPREAD_DIRECT=payouts`,
+/// operations without one use the default account. Accounts are selected per operation only,
+/// the API has no tenants to select them by: deployments isolating tenants run an instance
+/// with its own `WALLET_ID` per tenant.
+pub fn account_for(op: &str) -> &'static WalletAccount {
+    let accounts: &Accounts = ACCOUNTS.get_or_init(load_accounts);
+
+    accounts.selected.get(op).unwrap_or(&accounts.all[0])
+}
+
+/// Checks that the collects and the spreads of a rebalance go through the same account.
+///
+/// A rebalance spreads what its collects sent to the wallet, which another account's
+/// wallet would spread from a balance it never received.
+pub fn ensure_rebalance_accounts() -> Result<(), String> {
+    let (collect, spread): (&WalletAccount, &WalletAccount) = (account_for("collect"), account_for("spread"));
+
+    match collect.wallet_id == spread.wallet_id {
+        true => Ok(()),
+        false => Err(format!(
+            "rebalances need collects and spreads in one account, `WALLET_ACCOUNT_COLLECT` selects `{}` and `WALLET_ACCOUNT_SPREAD` selects `{}`",
+            collect.name, spread.name
+        ))
+    }
 }
 
-/// Creates and returns a TON wallet.
+/// Returns the version of the wallets and the default wallet id of the version.
 ///
 /// # Panics
 ///
-/// Panics if the wallet mnemonic environment variable is not set or invalid.
-fn ton_wallet() -> TonWallet {
-    let mnemonic_str: String = std::env::var("WALLET_MNEMONIC").unwrap();
-    let mnemonic: Mnemonic = Mnemonic::from_str(&mnemonic_str, &None).unwrap();
-    let keys: KeyPair = mnemonic.to_key_pair().unwrap();
+/// Panics if `WALLET_VERSION` is invalid, or gasless relaying is configured for a wallet other than W5.
+fn wallet_version() -> (WalletVersion, i32) {
+    let version_str: String = config::env_or("WALLET_VERSION", String::from("v4r2"));
+    let (version, default_wallet_id): (WalletVersion, i32) = match version_str.as_str() {
+        "v4r2" => (WalletVersion::V4R2, DEFAULT_WALLET_ID),
+        "v5r1" => (WalletVersion::V5R1, DEFAULT_W5_WALLET_ID),
+        _ => panic!("[ FATAL ] Configuration Error: `WALLET_VERSION` has an invalid value `{}`", version_str)
+    };
+
+    if relay::enabled() && !matches!(version, WalletVersion::V5R1) {
+        panic!("[ FATAL ] Configuration Error: gasless relaying with `RELAYER_URL` requires `WALLET_VERSION=v5r1`");
+    }
+
+    (version, default_wallet_id)
+}
 
-    let wallet = TonWallet::derive_default(WalletVersion::V4R2, &keys).unwrap();
+/// Creates and returns the TON wallet of an account.
+///
+/// The wallet version is taken from `WALLET_VERSION` (`v4r2` or `v5r1`), the subwallet
+/// id from the account. The public key comes from the configured signer, the key pair
+/// of the wallet never holds the secret key.
+///
+/// # Panics
+///
+/// Panics if the signer can not be initialized, or the wallet version is invalid.
+fn derive_wallet(account: &WalletAccount) -> TonWallet {
+    let keys: KeyPair = KeyPair {
+        public_key: signer::signer().public_key().to_vec(),
+        secret_key: Vec::new()
+    };
+    let (version, _): (WalletVersion, i32) = wallet_version();
+
+    let wallet = TonWallet::derive(0, version, &keys, account.wallet_id).unwrap();
     return wallet;
 }
 
+/// Creates and returns the TON wallet of the default account, see `derive_wallet`.
+fn ton_wallet() -> TonWallet {
+    derive_wallet(&wallet_accounts()[0])
+}
+
+/// Creates and returns the TON wallet sending the messages of an operation, see `account_for`.
+fn ton_wallet_for(op: &str) -> TonWallet {
+    derive_wallet(account_for(op))
+}
+
+/// Returns the address of the wallet of the default account, which pays for outgoing messages
+/// of operations without an account of their own.
+pub fn wallet_address() -> TonAddress {
+    ton_wallet().address
+}
+
+/// Returns the address of the wallet sending the messages of an operation.
+pub fn wallet_address_for(op: &str) -> TonAddress {
+    ton_wallet_for(op).address
+}
+
+/// Returns the names and the wallet addresses of all accounts, the default account first.
+pub fn account_addresses() -> Vec<(String, TonAddress)> {
+    wallet_accounts().iter().map(| account | (account.name.clone(), derive_wallet(account).address)).collect()
+}
+
+/// The mixer contract of `MIXER_CONTRACT`, parsed on startup.
+static MIXER_CONTRACT: OnceLock<TonAddress> = OnceLock::new();
+
+/// Returns the address of the mixer contract configured in the environment.
+///
+/// The address is parsed once, on startup, as the contract is not affected by a reload.
+///
+/// # Panics
+///
+/// Panics on the first call if the `MIXER_CONTRACT` environment variable is not set or invalid.
+pub fn mixer_contract_address() -> TonAddress {
+    MIXER_CONTRACT.get_or_init(|| {
+        let contract_str: String = match config::var("MIXER_CONTRACT") {
+            Ok(contract_str) => contract_str,
+            Err(_) => panic!("[ FATAL ] Configuration Error: `MIXER_CONTRACT` is not set")
+        };
+
+        match TonAddress::from_str(&contract_str) {
+            Ok(address) => address,
+            Err(err) => panic!("[ FATAL ] Configuration Error: `MIXER_CONTRACT` is not a valid address: {}", err)
+        }
+    }).clone()
+}
+
 /// Returns the current Unix timestamp.
-fn time_now() -> u64 {
+pub fn time_now() -> u64 {
     SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
 }
 
-/// Sends a raw message with retries.
+/// Returns the seconds an external message of an operation stays valid after it is signed.
+///
+/// `MESSAGE_TTL_<OP>`, e.g. `MESSAGE_TTL_SPREAD_DIRECT`, overrides `MESSAGE_TTL` (default `60`)
+/// for one operation.
+///
+/// # Panics
+///
+/// Panics if a TTL is set but is not a number.
+pub fn message_ttl(op: &str) -> u64 {
+    let default: u64 = config::env_or("MESSAGE_TTL", DEFAULT_MESSAGE_TTL);
+    config::env_or(&format!("MESSAGE_TTL_{}", op.to_uppercase()), default).max(1)
+}
+
+/// Returns the Unix time a message of an operation signed now stays valid until.
+///
+/// # Returns
+///
+/// The expiry, or an error if the send queue is backed up so far that the message would
+/// already be expired when its turn to be broadcast comes.
+fn message_valid_until(op: &str) -> Result<u64, String> {
+    let ttl: u64 = message_ttl(op);
+
+    // relayed messages don't go through the send queue
+    let backlog: Duration = match relay::enabled() {
+        true => Duration::ZERO,
+        false => backend::send_backlog()
+    };
+
+    if backlog.as_secs() >= ttl {
+        return Err(format!("the send queue is backed up for {} seconds, `{}` messages expire after {} seconds", backlog.as_secs(), op, ttl));
+    }
+
+    Ok(time_now() + ttl)
+}
+
+/// Returns whether the mixer runs degraded on the toncenter HTTP fallback, see `http`.
+pub fn degraded() -> bool {
+    failover::degraded()
+}
+
+/// Fetches the latest masterchain block.
+pub async fn masterchain_info() -> Result<MasterchainInfo, String> {
+    backend().await.masterchain_info().await
+}
+
+/// Fetches the current seqno of the wallet of the default account.
+pub async fn wallet_seqno() -> Result<u32, String> {
+    get_seqno(&wallet_address()).await
+}
+
+/// Fetches the current seqno of a wallet, e.g. of an account other than the default one.
+pub async fn get_seqno(wallet: &TonAddress) -> Result<u32, String> {
+    backend().await.seqno(wallet).await
+}
+
+/// Fails on a read-only replica or a follower, so only the leader sends from the shared wallet.
+///
+/// Called right before every broadcast: with leader election the lock is checked in the
+/// database, so a leader whose session was lost stops before its next heartbeat notices.
+async fn ensure_primary() -> Result<(), String> {
+    if config::read_only() {
+        return Err(String::from("this instance is a read-only replica and does not send messages"));
+    }
+
+    leader::ensure_leader().await
+}
+
+/// Broadcasts an already signed external message, or relays it in gasless mode.
+///
+/// # Arguments
+///
+/// * `wallet` - The address of the account wallet that signed the message.
+/// * `tx` - The serialized message.
+///
+/// # Returns
+///
+/// The hash of the external message.
+pub async fn broadcast(wallet: &TonAddress, tx: &[u8]) -> Result<Vec<u8>, String> {
+    let mut lock: Option<SeqnoLock> = SeqnoLock::acquire(wallet).await?;
+    let allowed: Result<(), String> = match (ensure_primary().await, lock.as_mut()) {
+        (Err(err), _) => Err(err),
+        (Ok(()), Some(lock)) => lock.extend().await,
+        (Ok(()), None) => Ok(())
+    };
+    let hash: Result<Vec<u8>, String> = match (allowed, relay::enabled()) {
+        (Err(err), _) => Err(err),
+        (Ok(_), true) => match wallet_accounts().iter().map(derive_wallet).find(| w | &w.address == wallet) {
+            Some(user_wallet) => relay::relay(&user_wallet, tx).await,
+            None => Err(format!("{} is not the wallet of an account", wallet))
+        },
+        (Ok(_), false) => backend().await.send(tx).await
+    };
+
+    if let Some(lock) = lock {
+        lock.release().await;
+    }
+
+    hash
+}
+
+/// Signs transfers of the wallet for the configured sending path.
+///
+/// Gasless mode produces a W5 request for the relayer, otherwise an external message.
+async fn sign_transfers(user_wallet: &TonWallet, seqno: u32, transfers: Vec<WalletTransfer>, valid_until: u64) -> Result<SignedExternalMessage, String> {
+    if relay::enabled() {
+        return create_signed_internal_message(user_wallet, signer::signer(), seqno, transfers, valid_until).await;
+    }
+
+    create_external_signed_multi_message(user_wallet.clone(), signer::signer(), seqno, transfers, valid_until).await
+}
+
+/// Sends a message signed by `sign_transfers` through the configured sending path.
+async fn send_signed(backend: &dyn TonBackend, user_wallet: &TonWallet, tx: &SignedExternalMessage) -> Result<Vec<u8>, String> {
+    if relay::enabled() {
+        return relay::relay(user_wallet, tx.boc.as_slice()).await;
+    }
+
+    backend.send(tx.boc.as_slice()).await
+}
+
+/// Signs and broadcasts a message with the given body to the mixer contract.
+///
+/// The signed message is written to the outbox before it is broadcast and marked
+/// as sent afterwards, so a crash in between can be recovered on the next start.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract_address` - The mixer contract the message is sent to.
+/// * `op` - The name of the operation, recorded in the outbox.
+/// * `query_id` - The query id stored in the body.
+/// * `value` - The amount of nanotons forwarded by the operation itself.
+/// * `gas` - The amount of nanotons attached on top of `value` to pay for gas.
+/// * `usd_rate` - The TON/USD rate USD amounts of the operation were converted at, if any.
+/// * `body_payload` - The body of the message.
+/// * `fees` - The estimated fees of the messages the contract sends, if known.
+/// * `mode` - The send mode of the message, `DEFAULT_SEND_MODE` unless a caller chose another one.
+/// * `withdrawn` - The nanotons the message makes the contract pay out, counted against the daily limit.
+///
+/// # Returns
+///
+/// A string containing the `OperationReceipt` of the sent message, or an error if the seqno
+/// can not be read or the message was not broadcast, see `send_rejected` and `limit_exceeded`.
+async fn invoke_contract(pool: &PgPool, contract_address: TonAddress, op: &str, query_id: u64, value: u64, gas: u64, usd_rate: Option<f64>, body_payload: Cell, fees: Option<SpreadFees>, mode: u8, withdrawn: Nanotons) -> Result<String, String> {
+    let backend: &dyn TonBackend = backend().await;
+    let user_wallet: TonWallet = ton_wallet_for(op);
+
+    let seqno: u32 = backend.seqno(&user_wallet.address).await?;
+
+    let wallet: String = user_wallet.address.to_base64_url();
+    let contract: String = contract_address.to_base64_url();
+
+    let sent: SentMessage = send_transfers(pool, &user_wallet, op, Some(query_id), usd_rate, seqno, vec![WalletTransfer {
+        destination: contract_address,
+        amount: BigUint::from(value + gas),
+        body: Some(body_payload),
+        mode
+    }], withdrawn).await?;
+    let outbox_id: i64 = sent.outbox_id;
+
+    let tx_hash: TXHash = tx_hash(&sent.hash, &sent.tx.normalized_hash);
+
+    if let Err(err) = db::outbox::mark_sent(pool, outbox_id, &tx_hash.hex).await {
+        log_error!("Can not mark outbox entry {} as sent: {:?}", outbox_id, err);
+    }
+
+    let receipt: OperationReceipt = OperationReceipt {
+        op: op.to_string(),
+        seqno: sent.seqno,
+        query_id: Some(query_id),
+        contract,
+        gas,
+        valid_until: sent.valid_until,
+        usd_rate,
+        links: explorer::operation(&tx_hash.normalized_hex, &wallet, &contract),
+        hash: tx_hash,
+        fees,
+        transaction: None
+    };
+
+    bus::publish(bus::EVENT_SUBMITTED, serde_json::json!({ "outbox_id": outbox_id, "receipt": &receipt }));
+
+    return Ok(receipt.to_string());
+}
+
+/// Returns whether a send failed because the wallet rejected the message, rather than because
+/// the liteserver, the relayer or the database could not be reached.
+pub fn send_rejected(err: &str) -> bool {
+    err.contains("exitcode=")
+}
+
+/// Start of the error of a send refused by the daily withdrawal limit.
+const LIMIT_EXCEEDED: &str = "daily withdrawal limit";
+
+/// Returns whether a send was refused because it would exceed the daily withdrawal limit.
+pub fn limit_exceeded(err: &str) -> bool {
+    err.starts_with(LIMIT_EXCEEDED)
+}
+
+/// Returns the nanotons a collect in `mode` withdraws from a contract, its balance in modes 1 and 2.
+pub async fn collected_nanotons(contract: &TonAddress, mode: u8) -> Result<Nanotons, String> {
+    let modes: MixerCollectionModes = MixerCollectionModes::new();
+    if mode != modes.all_ton_balance && mode != modes.available_ton_balance {
+        return Ok(Nanotons::ZERO);
+    }
+
+    get_balance(contract).await
+        .map(Nanotons::from_signed)
+        .map_err(|e| format!("can not fetch the mixer contract balance: {}", e))
+}
+
+/// Counts a message of the wallet against the daily withdrawal limit, see `policy::limits::daily_limit`.
+///
+/// Every nanoton the transfers take from the wallet counts, its whole balance for a transfer
+/// in mode `128`, plus the nanotons the message makes a contract pay out. A single transfer
+/// records its destination as the contract of the withdrawal.
+///
+/// # Returns
+///
+/// The id of the recorded withdrawal, `None` without a limit or amount, or an error if the limit would be exceeded.
+async fn reserve_daily(pool: &PgPool, user_wallet: &TonWallet, op: &str, transfers: &[WalletTransfer], withdrawn: Nanotons) -> Result<Option<i64>, String> {
+    let Some(limit) = policy::limits::daily_limit() else {
+        return Ok(None);
+    };
+
+    let mut amount: u64 = withdrawn.get();
+    for transfer in transfers {
+        let taken: u64 = match transfer.mode & SEND_CARRY_ALL_BALANCE != 0 {
+            true => get_balance(&user_wallet.address).await?.max(0) as u64,
+            false => u64::try_from(&transfer.amount).unwrap_or(u64::MAX)
+        };
+        amount = amount.saturating_add(taken);
+    }
+
+    let amount: Nanotons = Nanotons::from(amount.min(i64::MAX as u64));
+    if amount.is_zero() {
+        return Ok(None);
+    }
+
+    let contract: Option<String> = match transfers {
+        [transfer] => Some(transfer.destination.to_base64_url()),
+        _ => None
+    };
+
+    match db::limits::reserve(pool, op, contract.as_deref(), amount.signed(), limit).await {
+        Ok(Ok(id)) => Ok(Some(id)),
+        Ok(Err((used, granted))) => {
+            log_warn!("{} of {} TON exceeds the daily withdrawal limit, {} TON used", op, amount, Nanotons::from_signed(used));
+            Err(format!(
+                "{} of {} TON exceeded: {} TON withdrawn in the last 24 hours, {} TON requested",
+                LIMIT_EXCEEDED, Nanotons::from_signed(limit + granted), Nanotons::from_signed(used), amount
+            ))
+        },
+        Err(err) => Err(format!("can not check the daily withdrawal limit: {}", err))
+    }
+}
+
+/// Represents a withdrawal recorded against the daily limit while its message is sent.
+///
+/// The withdrawal is released when the reservation is dropped, unless the message may have
+/// reached the network, so it is also released when a request is dropped at its deadline.
+struct DailyReservation {
+    pool: PgPool,
+    id: Option<i64>,
+    /// Whether a message of the withdrawal may have been broadcast.
+    maybe_sent: bool
+}
+
+impl Drop for DailyReservation {
+    fn drop(&mut self) {
+        let Some(id) = self.id.take().filter(|_| !self.maybe_sent) else {
+            return;
+        };
+
+        let pool: PgPool = self.pool.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(err) = db::limits::release(&pool, id).await {
+                log_error!("Can not release withdrawal {} of the daily limit: {:?}", id, err);
+            }
+        });
+    }
+}
+
+/// Retries of a message rejected as stale, used when `SEND_RETRIES` is not set.
+const DEFAULT_SEND_RETRIES: u32 = 2;
+
+/// Exit codes of wallets rejecting an external message for its seqno or as expired: 33 and 36
+/// of v4 wallets, 133 and 136 of W5 wallets.
+const STALE_EXIT_CODES: [u32; 4] = [33, 36, 133, 136];
+
+/// Represents a message of the wallet that was written to the outbox and broadcast.
+struct SentMessage {
+    /// The seqno the message was signed with, newer than the requested one if it was rebuilt.
+    seqno: u32,
+    valid_until: u64,
+    outbox_id: i64,
+    tx: SignedExternalMessage,
+    /// The hash returned by the liteserver or the relayer.
+    hash: Vec<u8>
+}
+
+/// Signs transfers of the wallet, writes them to the outbox and broadcasts them.
+///
+/// A message the wallet rejects for a stale seqno or as expired is rebuilt with the current
+/// seqno and a fresh `valid_until`, re-signed and sent again, up to `SEND_RETRIES` times
+/// (default `2`). The outbox entry of the rejected message is expired. Only messages that
+/// were never accepted are rebuilt, so a retry can not send the transfers twice.
+///
+/// With `SEQNO_LOCK_REDIS_URL` set the wallet is locked from signing to broadcasting, and
+/// the seqno is raised past the messages other processes broadcast meanwhile, see `lock`.
+///
+/// The transfers and `withdrawn` are counted against the daily withdrawal limit before
+/// anything is signed, and released again if the message surely never left the wallet.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `user_wallet` - The wallet sending the transfers.
+/// * `op` - The name of the operation, recorded in the outbox.
+/// * `query_id` - The query id of the message body, if any.
+/// * `usd_rate` - The TON/USD rate USD amounts of the transfers were converted at, if any.
+/// * `seqno` - The current seqno of the wallet.
+/// * `transfers` - The transfers to send.
+/// * `withdrawn` - The nanotons the message makes a contract pay out, e.g. by a collect.
+async fn send_transfers(pool: &PgPool, user_wallet: &TonWallet, op: &str, query_id: Option<u64>, usd_rate: Option<f64>, seqno: u32, transfers: Vec<WalletTransfer>, withdrawn: Nanotons) -> Result<SentMessage, String> {
+    let mut reservation: DailyReservation = DailyReservation {
+        pool: pool.clone(),
+        id: reserve_daily(pool, user_wallet, op, &transfers, withdrawn).await?,
+        maybe_sent: false
+    };
+
+    // the lock and the reservation are released when dropped, also if the request is dropped at its deadline
+    let mut lock: Option<SeqnoLock> = SeqnoLock::acquire(&user_wallet.address).await?;
+    let sent: Result<SentMessage, String> = send_transfers_locked(pool, user_wallet, op, query_id, usd_rate, seqno, transfers, &mut lock, &mut reservation).await;
+
+    if let Some(lock) = lock {
+        lock.release().await;
+    }
+
+    sent
+}
+
+/// Sends transfers like `send_transfers` while the wallet is locked, if the lock is enabled.
 ///
-/// This function is currently unused (dead code).
-#[warn(dead_code)]
-async fn send_with_retrys(client: &TonClient, tx: &Vec<u8>) -> Vec<u8> {
-    let max = 2;
-    let mut attempts = 0;
+/// The reservation is marked as possibly sent while a message is broadcast, and stays
+/// marked unless the wallet surely rejected it, so a message that may have reached the
+/// network stays counted.
+async fn send_transfers_locked(pool: &PgPool, user_wallet: &TonWallet, op: &str, query_id: Option<u64>, usd_rate: Option<f64>, seqno: u32, transfers: Vec<WalletTransfer>, lock: &mut Option<SeqnoLock>, reservation: &mut DailyReservation) -> Result<SentMessage, String> {
+    let backend: &dyn TonBackend = backend().await;
+    let wallet: String = user_wallet.address.to_base64_url();
+    let max_retries: u32 = config::env_or("SEND_RETRIES", DEFAULT_SEND_RETRIES);
+
+    let mut seqno: u32 = seqno;
+    let mut retries: u32 = 0;
+
+    // the seqno was read before the lock was taken
+    if lock.is_some() {
+        seqno = seqno.max(fresh_seqno(backend, &user_wallet.address, lock).await?);
+    }
 
     loop {
-        match client.send_raw_message_return_hash(tx).await {
-            Ok(ans) => return ans,
-            Err(e) if attempts < max => {
-                println!("Attempt {} failed: {:?}. Retrying...", attempts + 1, e);
-                thread::sleep(Duration::from_secs(2u64.pow(attempts)));
-                attempts += 1;
+        let valid_until: u64 = message_valid_until(op)?;
+        let tx: SignedExternalMessage = sign_transfers(user_wallet, seqno, transfers.clone(), valid_until).await?;
+
+        let outbox_id: i64 = db::outbox::insert(pool, &wallet, op, query_id, seqno, valid_until, usd_rate, &tx).await.map_err(|e| e.to_string())?;
+        deadline::track(outbox_id, op, query_id);
+
+        // a message this instance may not send, or may no longer send under the seqno lock,
+        // never leaves the outbox, so it is expired right away
+        let allowed: Result<(), String> = match (ensure_primary().await, lock.as_mut()) {
+            (Err(err), _) => Err(err),
+            (Ok(()), Some(lock)) => lock.extend().await,
+            (Ok(()), None) => Ok(())
+        };
+        if let Err(err) = allowed {
+            if let Err(status_err) = db::outbox::set_status(pool, outbox_id, OUTBOX_EXPIRED).await {
+                log_error!("Can not expire outbox entry {}: {:?}", outbox_id, status_err);
+            }
+            return Err(err);
+        }
+
+        reservation.maybe_sent = true;
+        let err: String = match send_signed(backend, user_wallet, &tx).await {
+            Ok(hash) => {
+                if let Some(lock) = lock.as_mut() {
+                    if let Err(err) = lock.broadcast(seqno, valid_until).await {
+                        log_error!("Can not record seqno {} of wallet {} in the seqno lock: {}", seqno, wallet, err);
+                    }
+                }
+
+                return Ok(SentMessage { seqno, valid_until, outbox_id, tx, hash });
             },
-            Err(e) => {
-                println!("client error {:?}", e);
-                return Vec::<u8>::new();
+            Err(err) if stale_message(&err) => {
+                reservation.maybe_sent = false;
+                err
             },
+            Err(err) => {
+                // a rejected message is never applied, while a failed broadcast may still have
+                // reached the network, so it is left to the confirmation pass instead of being sent again
+                let status: &str = if send_rejected(&err) { OUTBOX_EXPIRED } else { OUTBOX_UNKNOWN };
+                reservation.maybe_sent = status == OUTBOX_UNKNOWN;
+                if let Err(status_err) = db::outbox::set_status(pool, outbox_id, status).await {
+                    log_error!("Can not set outbox entry {} to {}: {:?}", outbox_id, status, status_err);
+                }
+                return Err(err);
+            }
+        };
+
+        if let Err(status_err) = db::outbox::set_status(pool, outbox_id, OUTBOX_EXPIRED).await {
+            log_error!("Can not expire outbox entry {}: {:?}", outbox_id, status_err);
+        }
+
+        if retries >= max_retries {
+            return Err(format!("`{}` message with seqno {} was rejected as stale after {} retries: {}", op, seqno, retries, err));
         }
+
+        retries += 1;
+        let rebuilt: u32 = fresh_seqno(backend, &user_wallet.address, lock).await?;
+        log_warn!("Outbox {} `{}` with seqno {} was rejected as stale, rebuilding it with seqno {} (retry {} of {}): {}", outbox_id, op, seqno, rebuilt, retries, max_retries, err);
+        seqno = rebuilt;
     }
 }
 
-/// Invokes the fork operation on the mixer contract.
-///
-/// # Returns
-///
-/// A string containing the transaction hash in hex and base64 formats.
-pub async fn contract_invoke_fork() -> String {
-    let client: TonClient = ton_client().await;
-    let user_wallet: TonWallet = ton_wallet();
-    let contract_str: String = std::env::var("MIXER_CONTRACT").unwrap();
+/// Returns the seqno to sign the next message of a wallet with: the seqno of the wallet, or
+/// the seqno after a message another process broadcast under the lock meanwhile, which may
+/// not be applied yet.
+async fn fresh_seqno(backend: &dyn TonBackend, wallet: &TonAddress, lock: &mut Option<SeqnoLock>) -> Result<u32, String> {
+    let current: u32 = backend.seqno(wallet).await?;
+    let next: u32 = match lock.as_mut() {
+        Some(lock) => lock.next_seqno().await?.unwrap_or(0),
+        None => 0
+    };
 
-    let contract_factory: TonContractFactory = TonContractFactory::builder(&client).build().await.unwrap();
-    let contract_address: TonAddress = TonAddress::from_str(&contract_str).unwrap();
-    let wallet_contract: TonContract = contract_factory.get_contract(&user_wallet.address);
+    Ok(current.max(next))
+}
+
+/// Returns whether a send failed because the wallet rejected the message for its seqno or as expired.
+fn stale_message(err: &str) -> bool {
+    err.split("exitcode=")
+        .skip(1)
+        .filter_map(| rest | rest.split(| c: char | !c.is_ascii_digit()).next()?.parse::<u32>().ok())
+        .any(| code | STALE_EXIT_CODES.contains(&code))
+}
+
+/// Encodes the liteserver and the normalized hash of a sent message.
+fn tx_hash(hash: &Vec<u8>, normalized_hash: &TonHash) -> TXHash {
+    TXHash::new(
+        hex::encode(hash),
+        general_purpose::STANDARD.encode(hash),
+        hex::encode(normalized_hash),
+        general_purpose::STANDARD.encode(normalized_hash)
+    )
+}
+
+/// Number of the newest wallet transactions searched for a confirmed message.
+const CONFIRMATION_SCAN: usize = 16;
 
-    let seqno: u32 = wallet_contract.seqno().await.unwrap();
+/// Waits until the wallet seqno moves past the given one, at most `ttl` seconds.
+///
+/// Each external message must be applied before the next one can be accepted,
+/// so batches sent back to back have to wait for the previous seqno to be used.
+async fn wait_for_seqno(backend: &dyn TonBackend, wallet: &TonAddress, seqno: u32, ttl: u64) -> Result<u32, String> {
+    let deadline: u64 = time_now() + ttl;
 
-    let body_payload: Cell = ForkMessage::new(time_now()).build();
+    loop {
+        let current: u32 = backend.seqno(wallet).await?;
 
-    let tx: Vec<u8> = create_external_singed_message(
-        user_wallet,
-        seqno,
-        contract_address,
-        5000000u64,
-        time_now(),
-        body_payload
-    );
-    
-    let hash: Vec<u8> = client.send_raw_message_return_hash(tx.as_slice()).await.unwrap();
+        if current > seqno {
+            return Ok(current);
+        }
 
-    let hex_tx: String = hex::encode(&hash);
-    let base64_tx: String = general_purpose::STANDARD.encode(&hash);
+        if time_now() >= deadline {
+            return Err(format!("wallet seqno did not advance past {} in {} seconds", seqno, ttl));
+        }
 
-    return TXHash::new(hex_tx, base64_tx).to_string();
+        actix_web::rt::time::sleep(Duration::from_secs(2)).await;
+    }
 }
 
-/// Invokes the spread operation on the mixer contract.
+/// Waits until the seqno of the wallet of an operation moves past the given one.
 ///
 /// # Arguments
 ///
-/// * `total_amount` - The total amount to spread.
-/// * `spread_payload` - A vector of `SpreadWallet` structs containing the spread information.
+/// * `seqno` - The seqno the last message was signed with.
+/// * `op` - The operation of the last message, whose account is watched and whose TTL bounds the wait.
 ///
 /// # Returns
 ///
-/// A string containing the transaction hash in hex and base64 formats.
-pub async fn contract_invoke_spread(total_amount: u64, spread_payload: Vec<SpreadWallet>) -> String {
-    let client: TonClient = ton_client().await;
-    let user_wallet: TonWallet = ton_wallet();
-    let contract_str: String = std::env::var("MIXER_CONTRACT").unwrap();
+/// The new seqno, or an error if it did not move within the lifetime of the message.
+pub async fn wait_for_wallet_seqno(seqno: u32, op: &str) -> Result<u32, String> {
+    wait_for_seqno(backend().await, &wallet_address_for(op), seqno, message_ttl(op)).await
+}
 
-    let contract_factory: TonContractFactory = TonContractFactory::builder(&client).build().await.unwrap();
-    let contract_address: TonAddress = TonAddress::from_str(&contract_str).unwrap();
-    let wallet_contract: TonContract = contract_factory.get_contract(&user_wallet.address);
+/// Waits until a message of the wallet is applied on chain and locates its transaction.
+///
+/// The wallet seqno has to move past the seqno of the message first, then the newest
+/// transactions of the wallet are searched for the external message by its normalized
+/// hash, as the liteserver may lag behind the seqno. With gasless relaying the request
+/// arrives in an internal message of the relayer instead, whose body hashes the same way.
+///
+/// # Arguments
+///
+/// * `op` - The operation of the message, whose account sent it.
+/// * `seqno` - The wallet seqno the message was signed with.
+/// * `normalized_hash` - The normalized hash of the message in hex.
+/// * `timeout` - How long to wait in total.
+///
+/// # Returns
+///
+/// The transaction the message was applied in, or an error if it was not found in time.
+pub async fn wait_for_transaction(op: &str, seqno: u32, normalized_hash: &str, timeout: Duration) -> Result<AppliedTransaction, String> {
+    let backend: &dyn TonBackend = backend().await;
+    let wallet: TonAddress = wallet_address_for(op);
+    let deadline: u64 = time_now() + timeout.as_secs();
 
-    let seqno: u32 = wallet_contract.seqno().await.unwrap();
+    loop {
+        if backend.seqno(&wallet).await? > seqno {
+            let (transactions, _) = get_transactions_page(&wallet, None, CONFIRMATION_SCAN).await?;
+            let applied = transactions.iter().find(| t | {
+                t.in_msg.as_ref()
+                    .filter(| m | m.source.account_address.is_empty() || relay::enabled())
+                    .and_then(| m | message_body(m).ok().flatten())
+                    .is_some_and(| body | hex::encode(normalized_message_hash(&wallet, &body)) == normalized_hash)
+            });
 
-    let mut payload = CellBuilder::new().build().unwrap();
-    for entry in spread_payload {
-        let previous_cell = payload;
+            if let Some(transaction) = applied {
+                return Ok(AppliedTransaction {
+                    lt: transaction.transaction_id.lt,
+                    hash: hex::encode(transaction.transaction_id.hash),
+                    utime: transaction.utime
+                });
+            }
+        }
 
-        let mut builder = CellBuilder::new();
-        builder.store_reference(&ArcCell::new(previous_cell)).unwrap();
+        if time_now() >= deadline {
+            return Err(format!("message with seqno {} was not seen applied in {} seconds", seqno, timeout.as_secs()));
+        }
 
-        builder.store_address(&entry.account).unwrap();
-        builder.store_coins(&entry.amount).unwrap();
+        actix_web::rt::time::sleep(Duration::from_secs(2)).await;
+    }
+}
 
-        payload = builder.build().unwrap();
+/// Sends TON from a treasury wallet that has its own mnemonic, e.g. to top up the gas wallet.
+///
+/// The treasury is a v4r2 wallet with the default subwallet id. Its messages are not
+/// tracked in the outbox, which follows the seqno of the account wallets only.
+///
+/// # Arguments
+///
+/// * `mnemonic` - The mnemonic of the treasury wallet.
+/// * `password` - The passphrase of the mnemonic, if it is protected by one.
+/// * `op` - The name of the operation, whose TTL the message gets.
+/// * `transfers` - The recipients and the nanotons sent to each, at most `MAX_WALLET_MESSAGES`.
+///
+/// # Returns
+///
+/// The address of the treasury and the hash of the sent message.
+pub async fn treasury_transfer(mnemonic: &str, password: Option<String>, op: &str, transfers: Vec<(TonAddress, u64)>) -> Result<(TonAddress, TXHash), String> {
+    if transfers.len() > MAX_WALLET_MESSAGES {
+        return Err(format!("the treasury can send at most {} transfers at once", MAX_WALLET_MESSAGES));
     }
 
-    let body_payload: Cell = SpreadMessage::new(0, time_now(), total_amount, payload).build(); 
+    let treasury_signer: MnemonicSigner = MnemonicSigner::from_phrase(mnemonic, password)?;
+    let keys: KeyPair = KeyPair {
+        public_key: treasury_signer.public_key().to_vec(),
+        secret_key: Vec::new()
+    };
+    let treasury: TonWallet = TonWallet::derive(0, WalletVersion::V4R2, &keys, DEFAULT_WALLET_ID).map_err(|e| e.to_string())?;
+
+    let backend: &dyn TonBackend = backend().await;
+    let transfers: Vec<WalletTransfer> = transfers.into_iter().map(| (destination, amount) | WalletTransfer {
+        destination,
+        amount: BigUint::from(amount),
+        body: None,
+        mode: DEFAULT_SEND_MODE
+    }).collect();
+
+    let mut lock: Option<SeqnoLock> = SeqnoLock::acquire(&treasury.address).await?;
+    let sent: Result<TXHash, String> = async {
+        let mut seqno: u32 = backend.seqno(&treasury.address).await?;
+        if let Some(lock) = lock.as_mut() {
+            seqno = seqno.max(lock.next_seqno().await?.unwrap_or(0));
+        }
+
+        let valid_until: u64 = message_valid_until(op)?;
+        let tx: SignedExternalMessage = create_external_signed_multi_message(treasury.clone(), &treasury_signer, seqno, transfers, valid_until).await?;
+        ensure_primary().await?;
+        if let Some(lock) = lock.as_mut() {
+            lock.extend().await?;
+        }
+        let hash: Vec<u8> = backend.send(tx.boc.as_slice()).await?;
+
+        if let Some(lock) = lock.as_mut() {
+            if let Err(err) = lock.broadcast(seqno, valid_until).await {
+                log_error!("Can not record seqno {} of the treasury in the seqno lock: {}", seqno, err);
+            }
+        }
+
+        Ok(tx_hash(&hash, &tx.normalized_hash))
+    }.await;
 
-    let tx: Vec<u8> = create_external_singed_message(
-        user_wallet,
-        seqno,
-        contract_address,
-        total_amount+5000000u64, //send total amount to spread + fee
-        time_now(),
-        body_payload
-    );
-    
-    let hash: Vec<u8> = client.send_raw_message_return_hash(tx.as_slice()).await.unwrap();
-    
-    let hex_tx = hex::encode(&hash);
-    let base64_tx = general_purpose::STANDARD.encode(&hash);
+    if let Some(lock) = lock {
+        lock.release().await;
+    }
 
-    return TXHash::new(hex_tx, base64_tx).to_string();
+    Ok((treasury.address, sent?))
 }
 
-/// Invokes the collect operation on the mixer contract.
+/// Sends internal transfers directly from the wallet, bypassing the mixer contract.
+///
+/// Transfers are packed up to `MAX_WALLET_MESSAGES` per external message, so a
+/// batch needs a quarter of the seqno round-trips of one external per transfer.
 ///
 /// # Arguments
 ///
-/// * `message_data` - A `CollectMessageData` struct containing the collect operation details.
+/// * `pool` - The database connection pool.
+/// * `op` - The name of the operation, recorded in the outbox.
+/// * `transfers` - The transfers to send.
+/// * `usd_rate` - The TON/USD rate USD amounts of the transfers were converted at, if any.
+/// * `withdrawn` - The nanotons the transfers make a contract pay out, counted with the first batch.
 ///
 /// # Returns
 ///
-/// A string containing the transaction hash in hex and base64 formats.
-pub async fn contract_invoke_collect(message_data: CollectMessageData) -> String {
-    let client: TonClient = ton_client().await;
-    let user_wallet: TonWallet = ton_wallet();
-    let contract_str: String = std::env::var("MIXER_CONTRACT").unwrap();
+/// The receipts of the external messages, one per batch. Direct transfers carry
+/// no query id and no gas, and target the wallet itself.
+pub async fn wallet_transfer(pool: &PgPool, op: &str, transfers: Vec<WalletTransfer>, usd_rate: Option<f64>, withdrawn: Nanotons) -> Result<Vec<OperationReceipt>, String> {
+    let backend: &dyn TonBackend = backend().await;
+    let user_wallet: TonWallet = ton_wallet_for(op);
+    let wallet: String = user_wallet.address.to_base64_url();
 
-    let contract_factory: TonContractFactory = TonContractFactory::builder(&client).build().await.unwrap();
-    let contract_address: TonAddress = TonAddress::from_str(&contract_str).unwrap();
-    let wallet_contract: TonContract = contract_factory.get_contract(&user_wallet.address);
+    let mut seqno: u32 = backend.seqno(&user_wallet.address).await?;
+    let mut receipts: Vec<OperationReceipt> = Vec::new();
 
-    let seqno: u32 = wallet_contract.seqno().await.unwrap();
+    for (index, batch) in transfers.chunks(MAX_WALLET_MESSAGES).enumerate() {
+        if index > 0 {
+            seqno = wait_for_seqno(backend, &user_wallet.address, seqno, message_ttl(op)).await?;
+        }
 
-    let body_payload: Cell = CollectMessage::new(
-        message_data.mode, 
-        time_now(),
-        message_data.jetton_wallet,
-        message_data.amount
-    ).build().unwrap();
-
-    let tx: Vec<u8> = create_external_singed_message(
-        user_wallet,
-        seqno,
-        contract_address,
-        50000000u64,
-        time_now(),
-        body_payload
-    );
-    
-    let hash: Vec<u8> = client.send_raw_message_return_hash(tx.as_slice()).await.unwrap();
-
-    let hex_tx = hex::encode(&hash);
-    let base64_tx = general_purpose::STANDARD.encode(&hash);
-
-    return TXHash::new(hex_tx, base64_tx).to_string();
-}
\ No newline at end of file
+        let withdrawn: Nanotons = if index == 0 { withdrawn } else { Nanotons::ZERO };
+        let sent: SentMessage = send_transfers(pool, &user_wallet, op, None, usd_rate, seqno, batch.to_vec(), withdrawn).await?;
+        let outbox_id: i64 = sent.outbox_id;
+        let valid_until: u64 = sent.valid_until;
+        seqno = sent.seqno;
+
+        let tx_hash: TXHash = tx_hash(&sent.hash, &sent.tx.normalized_hash);
+
+        if let Err(err) = db::outbox::mark_sent(pool, outbox_id, &tx_hash.hex).await {
+            log_error!("Can not mark outbox entry {} as sent: {:?}", outbox_id, err);
+        }
+
+        let receipt: OperationReceipt = OperationReceipt {
+            op: op.to_string(),
+            seqno,
+            query_id: None,
+            contract: wallet.clone(),
+            gas: 0,
+            valid_until,
+            usd_rate,
+            links: explorer::operation(&tx_hash.normalized_hex, &wallet, &wallet),
+            hash: tx_hash,
+            fees: None,
+            transaction: None
+        };
+
+        bus::publish(bus::EVENT_SUBMITTED, serde_json::json!({ "outbox_id": outbox_id, "receipt": &receipt }));
+        receipts.push(receipt);
+    }
+
+    Ok(receipts)
+}
+
+/// Returns the nanotons attached to a fork message for gas.
+pub fn fork_gas() -> u64 {
+    config::runtime().fork_gas
+}
+
+/// Returns the nanotons attached to a spread message for gas, on top of the spread amount.
+pub fn spread_gas() -> u64 {
+    config::runtime().spread_gas
+}
+
+/// Share of the forward fee charged in the action phase of the sender, in 1/65536 (`first_frac`).
+const FWD_FIRST_FRAC: u64 = 21845;
+
+/// Returns the forward fee of a message carrying `bits` and `cells` beyond its root cell.
+pub fn forward_fee(bits: u64, cells: u64) -> u64 {
+    let runtime: std::sync::Arc<config::RuntimeConfig> = config::runtime();
+    let variable: u128 = runtime.fwd_bit_price as u128 * bits as u128 + runtime.fwd_cell_price as u128 * cells as u128;
+
+    runtime.fwd_lump_price + variable.div_ceil(1 << 16) as u64
+}
+
+/// Estimates the forward fees of the legs of a spread.
+///
+/// Every leg is a plain transfer without a body, so it pays the lump price only.
+pub fn spread_fees(recipients: &[SpreadWallet]) -> SpreadFees {
+    let fwd_fee: u64 = forward_fee(0, 0);
+
+    let legs: Vec<SpreadLegFee> = recipients.iter().map(| r | {
+        let amount: u64 = r.amount.to_u64_digits().first().copied().unwrap_or(0);
+        SpreadLegFee {
+            account: r.account.to_base64_url(),
+            amount,
+            fwd_fee,
+            received: amount.saturating_sub(fwd_fee)
+        }
+    }).collect();
+
+    SpreadFees {
+        total_fwd_fees: fwd_fee * legs.len() as u64,
+        total_action_fees: ((fwd_fee * FWD_FIRST_FRAC) >> 16) * legs.len() as u64,
+        legs
+    }
+}
+
+/// Returns the nanotons attached to a collect message for gas.
+pub fn collect_gas() -> u64 {
+    config::runtime().collect_gas
+}
+
+/// Returns the nanotons attached to a jetton transfer for gas.
+pub fn jetton_transfer_gas() -> u64 {
+    config::runtime().jetton_transfer_gas
+}
+
+/// Opcode of a TEP-74 jetton transfer.
+const JETTON_TRANSFER: u32 = 0x0f8a7ea5;
+
+/// Builds the body of a TEP-74 jetton transfer, sent to the jetton wallet of the sender.
+///
+/// # Arguments
+///
+/// * `query_id` - The query id stored in the body.
+/// * `amount` - The amount in the smallest units of the jetton.
+/// * `destination` - The owner of the receiving jetton wallet.
+/// * `response_destination` - The account excess gas is returned to.
+pub fn jetton_transfer_body(query_id: u64, amount: &BigUint, destination: &TonAddress, response_destination: &TonAddress) -> Cell {
+    let mut builder = CellBuilder::new();
+    builder.store_u32(32, JETTON_TRANSFER).unwrap();
+    builder.store_u64(64, query_id).unwrap();
+    builder.store_coins(amount).unwrap();
+    builder.store_address(destination).unwrap();
+    builder.store_address(response_destination).unwrap();
+    builder.store_bit(false).unwrap(); //no custom payload
+    builder.store_coins(&BigUint::from(0u32)).unwrap(); //no forward amount, the recipient is not notified
+    builder.store_bit(false).unwrap(); //empty forward payload in place
+
+    builder.build().unwrap()
+}
+
+/// Builds the body of a fork message.
+pub fn fork_body(query_id: u64) -> Cell {
+    ForkMessage::new(query_id).build()
+}
+
+/// Builds the state init a fork of a mixer contract deploys its child with.
+///
+/// The child runs the code of its parent, and its data holds the parent address and
+/// the query id of the fork, as `op::fork` of the mixer contract lays it out.
+pub fn fork_state_init(code: &Cell, parent: &TonAddress, query_id: u64) -> Cell {
+    let mut data: CellBuilder = CellBuilder::new();
+    data.store_address(parent).unwrap();
+    data.store_u64(64, query_id).unwrap();
+
+    let mut builder: CellBuilder = CellBuilder::new();
+    builder.store_bit(false).unwrap(); //no split depth
+    builder.store_bit(false).unwrap(); //not special
+    builder.store_bit(true).unwrap(); //code
+    builder.store_reference(&ArcCell::new(code.clone())).unwrap();
+    builder.store_bit(true).unwrap(); //data
+    builder.store_reference(&ArcCell::new(data.build().unwrap())).unwrap();
+    builder.store_bit(false).unwrap(); //no libraries
+
+    builder.build().unwrap()
+}
+
+/// Checks `fork_state_init` against forks the indexer saw, as the layout of the child data is
+/// not published with the contract.
+///
+/// A fork is usable if its parent runs the code with `code_hash`; the child address derived
+/// from its parent and query id must be the address the child was actually deployed to.
+///
+/// # Arguments
+///
+/// * `code_hash` - The hex hash of the code whose derivation is checked.
+/// * `forks` - Indexed forks as the parent, the query id and the deployed child.
+///
+/// # Returns
+///
+/// Whether the first fork of that code confirmed or contradicted the derivation, `None`
+/// without one, or an error if an account state can not be read.
+pub async fn verify_child_derivation(code_hash: &str, forks: &[(String, i64, String)]) -> Result<Option<bool>, String> {
+    let backend: &dyn TonBackend = backend().await;
+
+    for (parent, query_id, child) in forks {
+        let parent: TonAddress = TonAddress::from_str(parent).map_err(|e| e.to_string())?;
+        let child: TonAddress = TonAddress::from_str(child).map_err(|e| e.to_string())?;
+        let state: AccountState = backend.account_state(&parent).await?;
+
+        let code: Cell = match (state.code, state.code_hash.as_deref()) {
+            (Some(code), Some(hash)) if hash == code_hash => code,
+            _ => continue
+        };
+
+        let derived: TonAddress = TonAddress::new(parent.workchain, &fork_state_init(&code, &parent, *query_id as u64).cell_hash());
+        if derived != child {
+            log_error!(
+                "Fork {} of {} deployed its child to {}, not to the derived {}",
+                query_id, parent.to_base64_url(), child.to_base64_url(), derived.to_base64_url()
+            );
+        }
+
+        return Ok(Some(derived == child));
+    }
+
+    Ok(None)
+}
+
+/// Computes the address a fork of a contract with the given query id deploys its child to.
+///
+/// The derivation is unchecked, verify it with `verify_child_derivation` before funding the address.
+///
+/// # Arguments
+///
+/// * `parent` - The mixer contract that is forked.
+/// * `query_id` - The query id the fork is sent with.
+///
+/// # Returns
+///
+/// The address and state init hash of the child, or an error if the code of the parent
+/// can not be read.
+pub async fn child_address(parent: &TonAddress, query_id: u64) -> Result<ChildAddress, String> {
+    let backend: &dyn TonBackend = backend().await;
+    let state: AccountState = backend.account_state(parent).await?;
+    let code: Cell = state.code.ok_or_else(|| format!("the code of contract {} is not available", parent.to_base64_url()))?;
+
+    let state_init_hash: TonHash = fork_state_init(&code, parent, query_id).cell_hash();
+    let address: TonAddress = TonAddress::new(parent.workchain, &state_init_hash);
+    let active: bool = backend.account_state(&address).await?.active;
+
+    Ok(ChildAddress {
+        parent: parent.to_base64_url(),
+        query_id,
+        address: address.to_base64_url(),
+        raw_address: address.to_hex(),
+        state_init_hash: hex::encode(state_init_hash),
+        code_hash: state.code_hash,
+        active
+    })
+}
+
+/// Data bits a cell can hold.
+const MAX_CELL_BITS: usize = 1023;
+
+/// References a cell can hold.
+const MAX_CELL_REFS: usize = 4;
+
+/// Depth of an external message validators accept, `max_ext_msg_depth` of config param 43.
+const MAX_EXT_MESSAGE_DEPTH: u32 = 512;
+
+/// Cells of an external message, `max_msg_cells` of config param 43.
+const MAX_MESSAGE_CELLS: u32 = 1 << 13;
+
+/// Data bits of an external message, `max_msg_bits` of config param 43.
+const MAX_MESSAGE_BITS: u64 = 1 << 21;
+
+/// Levels the wallet message, the internal message and the spread body add above the recipient list.
+const SPREAD_LIST_OFFSET: u32 = 4;
+
+/// Depth of the recipient list a spread may reach, used when `SPREAD_MAX_DEPTH` is not set.
+///
+/// Every recipient adds a level, and the contract walks the list one reference at a time, so
+/// the default stays well below the message limit to keep the walk cheap.
+const DEFAULT_SPREAD_MAX_DEPTH: u32 = 384;
+
+/// Returns the depth of the recipient list a spread may reach, at most what an external message allows.
+fn spread_max_depth() -> u32 {
+    config::env_or("SPREAD_MAX_DEPTH", DEFAULT_SPREAD_MAX_DEPTH).clamp(1, MAX_EXT_MESSAGE_DEPTH - SPREAD_LIST_OFFSET)
+}
+
+/// Share of a spread capacity limit above which the caller is warned before the limit is reached.
+const CAPACITY_WARNING: f64 = 0.8;
+
+/// Returns the error advising to split a spread of `recipients` into chunks of `chunk`.
+fn chunking(recipients: usize, limit: String, chunk: usize) -> String {
+    format!("a spread of {} recipients exceeds {}, split it into spreads of at most {} recipients", recipients, limit, chunk.max(1))
+}
+
+/// Links the recipients of a spread into a list of cells, the last recipient first.
+///
+/// Every cell is checked against the bits and references a cell holds, and the list against
+/// the depth a message may have, as the contract can not iterate a list validators drop or
+/// that runs out of gas half way.
+///
+/// # Returns
+///
+/// The head of the list, or an error naming the limit and the chunk size that fits.
+fn spread_list(spread_payload: &[SpreadWallet]) -> Result<Cell, String> {
+    let max_depth: u32 = spread_max_depth();
+
+    if spread_payload.len() > max_depth as usize {
+        return Err(chunking(spread_payload.len(), format!("the list depth of {} cells", max_depth), max_depth as usize));
+    }
+
+    let mut payload: Cell = CellBuilder::new().build().map_err(|e| e.to_string())?;
+    for entry in spread_payload {
+        let previous_cell: Cell = payload;
+
+        let mut builder = CellBuilder::new();
+        builder.store_reference(&ArcCell::new(previous_cell)).map_err(|e| e.to_string())?;
+
+        dict::store_recipient(&mut builder, entry)?;
+
+        payload = builder.build().map_err(|e| format!("recipient {} does not fit a cell: {}", entry.account, e))?;
+
+        if payload.bit_len() > MAX_CELL_BITS || payload.references().len() > MAX_CELL_REFS {
+            return Err(format!("recipient {} does not fit a cell", entry.account));
+        }
+    }
+
+    Ok(payload)
+}
+
+/// Stores the recipients of a spread in the encoding of `SPREAD_ENCODING`.
+///
+/// A list is built by `spread_list`, a dictionary by `dict::build`, its depth bounded by the
+/// key bits rather than the recipients. Either is checked against the cells and bits a
+/// message may have.
+///
+/// # Returns
+///
+/// The recipients cell, or an error naming the limit and the chunk size that fits.
+fn spread_recipients(spread_payload: &[SpreadWallet]) -> Result<Cell, String> {
+    let payload: Cell = match dict::spread_encoding() {
+        dict::SpreadEncoding::List => spread_list(spread_payload)?,
+        dict::SpreadEncoding::Dict => dict::build(spread_payload)?
+    };
+
+    // the recipients take about the same room each, so the chunk scales with the room left
+    let stats: CellStats = CellStats::of(&payload);
+    let recipients: usize = spread_payload.len();
+    if stats.cells + SPREAD_LIST_OFFSET > MAX_MESSAGE_CELLS {
+        let chunk: usize = recipients * (MAX_MESSAGE_CELLS - SPREAD_LIST_OFFSET) as usize / stats.cells as usize;
+        return Err(chunking(recipients, format!("the {} cells of a message", MAX_MESSAGE_CELLS), chunk));
+    }
+    let overhead: u64 = SPREAD_LIST_OFFSET as u64 * MAX_CELL_BITS as u64;
+    if stats.bits + overhead > MAX_MESSAGE_BITS {
+        let chunk: usize = (recipients as u64 * (MAX_MESSAGE_BITS - overhead) / stats.bits) as usize;
+        return Err(chunking(recipients, format!("the {} bits of a message", MAX_MESSAGE_BITS), chunk));
+    }
+
+    Ok(payload)
+}
+
+/// Checks that the recipients of a spread fit a spread message, see `spread_recipients`.
+///
+/// # Returns
+///
+/// The size of the recipients cell, or an error advising to split the spread.
+pub fn check_spread_capacity(spread_payload: &[SpreadWallet]) -> Result<CellStats, String> {
+    spread_recipients(spread_payload).map(| recipients | CellStats::of(&recipients))
+}
+
+/// Returns a warning if the recipients of a spread come close to a capacity limit.
+///
+/// The limits are the ones of `spread_recipients`: the list depth of `SPREAD_MAX_DEPTH` for
+/// a list, and the cells and bits of a message. A spread above `CAPACITY_WARNING` of any of
+/// them still goes out, but the caller is advised of the chunk size that stays below it.
+///
+/// # Arguments
+///
+/// * `recipients` - The number of recipients of the spread.
+/// * `stats` - The size of the recipients cell, see `check_spread_capacity`.
+pub fn spread_capacity_warning(recipients: usize, stats: &CellStats) -> Option<String> {
+    let mut usage: Vec<(f64, String)> = vec![
+        ((stats.cells + SPREAD_LIST_OFFSET) as f64 / MAX_MESSAGE_CELLS as f64, format!("the {} cells of a message", MAX_MESSAGE_CELLS)),
+        ((stats.bits + SPREAD_LIST_OFFSET as u64 * MAX_CELL_BITS as u64) as f64 / MAX_MESSAGE_BITS as f64, format!("the {} bits of a message", MAX_MESSAGE_BITS))
+    ];
+    if dict::spread_encoding() == dict::SpreadEncoding::List {
+        let max_depth: u32 = spread_max_depth();
+        usage.push((recipients as f64 / max_depth as f64, format!("the list depth of {} cells", max_depth)));
+    }
+
+    let (share, limit): (f64, String) = usage.into_iter().max_by(| a, b | a.0.total_cmp(&b.0))?;
+    if share < CAPACITY_WARNING {
+        return None;
+    }
+
+    let chunk: usize = ((recipients as f64 * CAPACITY_WARNING / share) as usize).max(1);
+    Some(format!(
+        "a spread of {} recipients takes {:.0}% of {}, consider splitting it into spreads of at most {} recipients",
+        recipients, share * 100.0, limit, chunk
+    ))
+}
+
+/// Builds the body of a spread message, storing the recipients as `SPREAD_ENCODING` says.
+///
+/// # Arguments
+///
+/// * `query_id` - The query id stored in the body.
+/// * `total_amount` - The total amount to spread.
+/// * `spread_payload` - A vector of `SpreadWallet` structs containing the spread information.
+///
+/// # Panics
+///
+/// Panics if the recipients do not fit, which `check_spread_capacity` rejects beforehand.
+pub fn spread_body(query_id: u64, total_amount: Nanotons, spread_payload: Vec<SpreadWallet>) -> Cell {
+    let payload: Cell = spread_recipients(&spread_payload).unwrap();
+
+    SpreadMessage::new(0, query_id, total_amount, payload).build()
+}
+
+/// Builds the body of a collect message.
+pub fn collect_body(query_id: u64, message_data: CollectMessageData) -> Cell {
+    CollectMessage::new(
+        message_data.mode, 
+        query_id,
+        message_data.jetton_wallet,
+        message_data.amount
+    ).build().unwrap()
+}
+
+/// Invokes the fork operation on the mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to fork.
+/// * `query_id` - The query id of the fork, which the address of the child is derived from.
+///
+/// # Returns
+///
+/// A string containing the `OperationReceipt` of the sent message, or an error if it was not sent.
+pub async fn contract_invoke_fork(pool: &PgPool, contract: TonAddress, query_id: u64) -> Result<String, String> {
+    let body_payload: Cell = fork_body(query_id);
+
+    return invoke_contract(pool, contract, "fork", query_id, 0, fork_gas(), None, body_payload, None, DEFAULT_SEND_MODE, Nanotons::ZERO).await;
+}
+
+/// Invokes the spread operation on the mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to spread through.
+/// * `total_amount` - The total amount to spread.
+/// * `spread_payload` - A vector of `SpreadWallet` structs containing the spread information.
+/// * `usd_rate` - The TON/USD rate USD amounts were converted at, if any.
+///
+/// # Returns
+///
+/// A string containing the `OperationReceipt` of the sent message, or an error if it was not sent.
+pub async fn contract_invoke_spread(pool: &PgPool, contract: TonAddress, total_amount: Nanotons, spread_payload: Vec<SpreadWallet>, usd_rate: Option<f64>) -> Result<String, String> {
+    let query_id: u64 = time_now();
+    let fees: SpreadFees = spread_fees(&spread_payload);
+    let body_payload: Cell = spread_body(query_id, total_amount, spread_payload);
+
+    //send total amount to spread + fee
+    return invoke_contract(pool, contract, "spread", query_id, total_amount.get(), spread_gas(), usd_rate, body_payload, Some(fees), DEFAULT_SEND_MODE, Nanotons::ZERO).await;
+}
+
+/// Invokes the collect operation on the mixer contract.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to collect from.
+/// * `message_data` - A `CollectMessageData` struct containing the collect operation details.
+/// * `send_mode` - The send mode of the message to the contract.
+///
+/// # Returns
+///
+/// A string containing the `OperationReceipt` of the sent message, or an error if it was not sent.
+pub async fn contract_invoke_collect(pool: &PgPool, contract: TonAddress, message_data: CollectMessageData, send_mode: u8) -> Result<String, String> {
+    let query_id: u64 = time_now();
+    let withdrawn: Nanotons = collected_nanotons(&contract, message_data.mode).await?;
+    let body_payload: Cell = collect_body(query_id, message_data);
+
+    return invoke_contract(pool, contract, "collect", query_id, 0, collect_gas(), None, body_payload, None, send_mode, withdrawn).await;
+}
+
+/// Nanotons attached to an upgrade message for gas.
+const UPGRADE_GAS: u64 = 50_000_000;
+
+/// Builds the body of an upgrade message carrying the new code of the contract.
+pub fn upgrade_body(query_id: u64, code: Cell) -> Cell {
+    UpgradeMessage::new(query_id, code).build()
+}
+
+/// Invokes the upgrade operation on the mixer contract, replacing its code.
+///
+/// # Arguments
+///
+/// * `pool` - The database connection pool.
+/// * `contract` - The mixer contract to upgrade.
+/// * `code` - The root cell of the new code.
+///
+/// # Returns
+///
+/// A string containing the `OperationReceipt` of the sent message, or an error if it was not sent.
+pub async fn contract_invoke_upgrade(pool: &PgPool, contract: TonAddress, code: Cell) -> Result<String, String> {
+    let query_id: u64 = time_now();
+    let body_payload: Cell = upgrade_body(query_id, code);
+
+    return invoke_contract(pool, contract, "upgrade", query_id, 0, UPGRADE_GAS, None, body_payload, None, DEFAULT_SEND_MODE, Nanotons::ZERO).await;
+}
+
+/// Fetches the body of the inbound message of a single transaction.
+///
+/// # Arguments
+///
+/// * `address` - The account the transaction belongs to.
+/// * `lt` - The logical time of the transaction.
+/// * `hash` - The hash of the transaction.
+///
+/// # Returns
+///
+/// The root cell of the inbound message body, or `None` if the transaction
+/// does not exist or its inbound message carries no body.
+pub async fn get_transaction_in_message(address: TonAddress, lt: i64, hash: TonHash) -> Result<Option<Cell>, String> {
+    let transaction_id: InternalTransactionId = InternalTransactionId { lt, hash };
+    let transactions: RawTransactions = backend().await.transactions(&address, &transaction_id, 1).await?;
+
+    let transaction = match transactions.transactions.into_iter().find(| t | t.transaction_id.lt == lt) {
+        Some(t) => t,
+        None => return Ok(None)
+    };
+
+    match transaction.in_msg {
+        Some(message) => message_body(&message),
+        None => Ok(None)
+    }
+}
+
+/// Parses the body of a raw message into a cell.
+///
+/// # Returns
+///
+/// The root cell of the body, or `None` if the message carries no raw body.
+pub fn message_body(message: &RawMessage) -> Result<Option<Cell>, String> {
+    let body: &Vec<u8> = match &message.msg_data {
+        MsgData::Raw { body, .. } if !body.is_empty() => body,
+        _ => return Ok(None)
+    };
+
+    let boc: BagOfCells = BagOfCells::parse(body).map_err(|e| e.to_string())?;
+    let root: ArcCell = boc.single_root().map_err(|e| e.to_string())?;
+
+    Ok(Some(root.as_ref().clone()))
+}
+
+/// Reads the text comment of a message body.
+///
+/// # Returns
+///
+/// The comment, or `None` if the body is not a text comment (opcode `0`).
+pub fn text_comment(body: &Cell) -> Option<String> {
+    let mut parser = body.parser();
+
+    if parser.remaining_bits() < 32 || parser.load_u32(32).ok()? != 0 {
+        return None;
+    }
+
+    // long comments are stored in snake format: the bytes continue in the first reference of each cell
+    let mut bytes: Vec<u8> = parser.load_bytes(parser.remaining_bits() / 8).ok()?;
+    let mut next: Option<Cell> = body.references().first().map(| c | c.as_ref().clone());
+
+    while let Some(cell) = next {
+        let mut parser = cell.parser();
+        bytes.extend(parser.load_bytes(parser.remaining_bits() / 8).ok()?);
+        next = cell.references().first().map(| c | c.as_ref().clone());
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Fetches all transactions of an account newer than the given logical time.
+///
+/// # Arguments
+///
+/// * `address` - The account to fetch transactions for.
+/// * `since_lt` - The logical time of the last already known transaction, or `0` to fetch the whole history.
+///
+/// # Returns
+///
+/// The transactions ordered from the oldest to the newest.
+pub async fn get_transactions_since(address: &TonAddress, since_lt: i64) -> Result<Vec<RawTransaction>, String> {
+    let backend: &dyn TonBackend = backend().await;
+
+    let state: AccountState = backend.account_state(address).await?;
+    let mut from: InternalTransactionId = state.last_transaction_id;
+    let mut transactions: Vec<RawTransaction> = Vec::new();
+
+    'walk: while from.lt > since_lt {
+        let batch: RawTransactions = backend.transactions(address, &from, 16).await?;
+
+        if batch.transactions.is_empty() {
+            break;
+        }
+
+        for transaction in batch.transactions {
+            if transaction.transaction_id.lt <= since_lt {
+                break 'walk;
+            }
+            transactions.push(transaction);
+        }
+
+        from = batch.previous_transaction_id;
+    }
+
+    transactions.reverse();
+    Ok(transactions)
+}
+
+/// Fetches one page of account transactions, newest first.
+///
+/// # Arguments
+///
+/// * `address` - The account to fetch transactions for.
+/// * `from` - The transaction to start from (inclusive), or `None` to start from the latest one.
+/// * `limit` - The maximum number of transactions to return.
+///
+/// # Returns
+///
+/// The transactions and the id of the transaction the next page starts from,
+/// which is `None` once the beginning of the history is reached.
+pub async fn get_transactions_page(address: &TonAddress, from: Option<InternalTransactionId>, limit: usize) -> Result<(Vec<RawTransaction>, Option<InternalTransactionId>), String> {
+    let backend: &dyn TonBackend = backend().await;
+
+    let mut from: InternalTransactionId = match from {
+        Some(id) => id,
+        None => backend.account_state(address).await?.last_transaction_id
+    };
+    let mut transactions: Vec<RawTransaction> = Vec::new();
+
+    while transactions.len() < limit && from.lt > 0 {
+        let count: usize = (limit - transactions.len()).min(16);
+        let batch: RawTransactions = backend.transactions(address, &from, count).await?;
+
+        if batch.transactions.is_empty() {
+            break;
+        }
+
+        transactions.extend(batch.transactions);
+        from = batch.previous_transaction_id;
+    }
+
+    let next: Option<InternalTransactionId> = if from.lt > 0 { Some(from) } else { None };
+    Ok((transactions, next))
+}
+
+/// Fetches the balance of an account.
+///
+/// # Arguments
+///
+/// * `address` - The account to fetch the balance of.
+///
+/// # Returns
+///
+/// The balance in nanotons.
+pub async fn get_balance(address: &TonAddress) -> Result<i64, String> {
+    let state: AccountState = backend().await.account_state(address).await?;
+
+    Ok(state.balance)
+}
+
+/// Returns the code hashes mixer contracts may have, from the comma separated `MIXER_CODE_HASH`.
+///
+/// Hashes are accepted in hex or base64 and returned as lowercase hex, invalid ones are
+/// logged and ignored. Empty if contract code is not verified.
+pub fn expected_code_hashes() -> Vec<String> {
+    let value: String = config::var("MIXER_CODE_HASH").unwrap_or_default();
+
+    value.split(',').map(str::trim).filter(| h | !h.is_empty()).filter_map(| hash | {
+        let bytes: Option<Vec<u8>> = hex::decode(hash).ok()
+            .or_else(|| general_purpose::STANDARD.decode(hash).ok())
+            .or_else(|| general_purpose::URL_SAFE.decode(hash).ok())
+            .filter(| b | b.len() == 32);
+
+        if bytes.is_none() {
+            log_warn!("`MIXER_CODE_HASH` has an invalid hash `{}`, it is ignored", hash);
+        }
+
+        bytes.map(hex::encode)
+    }).collect()
+}
+
+/// Compares the code of a contract with the expected code hashes.
+///
+/// # Arguments
+///
+/// * `contract` - The mixer contract to verify.
+///
+/// # Returns
+///
+/// The code hash of the contract and whether it matches, `None` if no hash is configured.
+pub async fn verify_code(contract: &TonAddress) -> Result<CodeVerification, String> {
+    let state: AccountState = backend().await.account_state(contract).await?;
+    let expected: Vec<String> = expected_code_hashes();
+
+    let verified: Option<bool> = match (expected.is_empty(), state.active, &state.code_hash) {
+        (true, _, _) => None,
+        (false, false, _) => Some(false),
+        (false, true, Some(hash)) => Some(expected.contains(hash)),
+        // a backend that can not read code, e.g. offline mode, can not verify it either
+        (false, true, None) => None
+    };
+
+    Ok(CodeVerification {
+        contract: contract.to_base64_url(),
+        active: state.active,
+        code_hash: state.code_hash,
+        expected,
+        verified
+    })
+}
+
+/// Fails if the code of a contract does not have an expected hash.
+///
+/// # Returns
+///
+/// An error naming the contract and its code hash on a mismatch.
+pub async fn ensure_code(contract: &TonAddress) -> Result<(), String> {
+    let verification: CodeVerification = verify_code(contract).await?;
+
+    match verification.verified {
+        Some(false) if !verification.active => Err(format!("contract {} is not deployed", verification.contract)),
+        Some(false) => Err(format!(
+            "contract {} has code hash {}, expected one of `MIXER_CODE_HASH`",
+            verification.contract, verification.code_hash.unwrap_or_default()
+        )),
+        _ => Ok(())
+    }
+}
+
+/// Checks whether an account is deployed and active.
+///
+/// # Arguments
+///
+/// * `address` - The account to check.
+///
+/// # Returns
+///
+/// `false` for accounts without code, i.e. uninitialized or non-existent ones.
+pub async fn is_account_active(address: &TonAddress) -> Result<bool, String> {
+    let state: AccountState = backend().await.account_state(address).await?;
+
+    Ok(state.active)
+}
+
+/// Fetches the metadata content cell of a jetton master.
+///
+/// # Returns
+///
+/// The content cell returned by `get_jetton_data`.
+pub async fn get_jetton_content(master: &TonAddress) -> Result<Cell, String> {
+    let stack: Vec<TvmStackEntry> = backend().await.run_get_method(master, "get_jetton_data", Vec::new()).await?;
+
+    match stack.get(3) {
+        Some(entry) => Ok(entry.get_cell().map_err(|e| e.to_string())?.as_ref().clone()),
+        None => Err(format!("get_jetton_data of {} returned {} stack entries", master, stack.len()))
+    }
+}
+
+/// Resolves the jetton wallet of an owner through the jetton master.
+pub async fn get_jetton_wallet_address(master: &TonAddress, owner: &TonAddress) -> Result<TonAddress, String> {
+    let mut builder: CellBuilder = CellBuilder::new();
+    builder.store_address(owner).map_err(|e| e.to_string())?;
+    let owner_slice: CellSlice = CellSlice::full_cell(builder.build().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+
+    let stack: Vec<TvmStackEntry> = backend().await
+        .run_get_method(master, "get_wallet_address", vec![TvmStackEntry::Slice(owner_slice)])
+        .await?;
+
+    match stack.first() {
+        Some(entry) => entry.get_address().map_err(|e| e.to_string()),
+        None => Err(format!("get_wallet_address of {} returned an empty stack", master))
+    }
+}
+
+/// Fetches the balance of a jetton wallet in its smallest units.
+///
+/// # Returns
+///
+/// The balance, `0` when the wallet is not deployed yet.
+pub async fn get_jetton_balance(wallet: &TonAddress) -> Result<BigInt, String> {
+    if !is_account_active(wallet).await? {
+        return Ok(BigInt::from(0));
+    }
+
+    let stack: Vec<TvmStackEntry> = backend().await.run_get_method(wallet, "get_wallet_data", Vec::new()).await?;
+
+    match stack.first() {
+        Some(entry) => entry.get_bigint().map_err(|e| e.to_string()),
+        None => Err(format!("get_wallet_data of {} returned an empty stack", wallet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use crate::types::decode::{self, DecodedMessage};
+
+    use super::*;
+
+    /// The backend every test of the module talks to, installed on first use.
+    static MOCK: OnceLock<mock::MockBackend> = OnceLock::new();
+
+    /// Serializes the tests that send, so a queued send error fails the message it was queued for.
+    static SENDS: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// Returns a handle to the installed mock backend.
+    fn mock() -> mock::MockBackend {
+        MOCK.get_or_init(|| {
+            let backend: mock::MockBackend = mock::MockBackend::new();
+            set_backend(Box::new(backend.clone())).unwrap();
+            backend
+        }).clone()
+    }
+
+    /// Signer holding a key pair derived from a fixed seed.
+    struct TestSigner {
+        key_pair: KeyPair
+    }
+
+    #[async_trait]
+    impl Signer for TestSigner {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn public_key(&self) -> &[u8] {
+            &self.key_pair.public_key
+        }
+
+        async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+            nacl::sign::signature(message, &self.key_pair.secret_key).map_err(|e| e.message)
+        }
+    }
+
+    /// Returns a signer and its v4r2 wallet, a distinct one for every seed.
+    fn test_wallet(seed: u8) -> (TestSigner, TonWallet) {
+        let pair = nacl::sign::generate_keypair(&[seed; 32]);
+        let key_pair: KeyPair = KeyPair { public_key: pair.pkey.to_vec(), secret_key: pair.skey.to_vec() };
+        let wallet: TonWallet = TonWallet::derive(0, WalletVersion::V4R2, &key_pair, DEFAULT_WALLET_ID).unwrap();
+
+        (TestSigner { key_pair }, wallet)
+    }
+
+    /// Returns an address in the basechain made of one repeated byte.
+    fn address(byte: u8) -> TonAddress {
+        TonAddress::new(0, &[byte; 32])
+    }
+
+    /// Returns whether a cell or any cell below it has the hash.
+    fn contains(cell: &Cell, hash: &TonHash) -> bool {
+        &cell.cell_hash() == hash || cell.references().iter().any(| r | contains(r, hash))
+    }
+
+    /// Returns recipients with distinct addresses and amounts, in the legacy layout.
+    fn spread_wallets(count: usize) -> Vec<SpreadWallet> {
+        (0..count).map(| i | {
+            let mut hash: [u8; 32] = [0xcd; 32];
+            hash[..8].copy_from_slice(&(i as u64).to_be_bytes());
+
+            SpreadWallet { account: TonAddress::new(0, &hash), amount: BigUint::from(1_000 * i as u64 + 1), bounce: None }
+        }).collect()
+    }
+
+    /// Asserts that a spread message carrying the recipients cell reads back the recipients in their order.
+    fn assert_recipients(data: Cell, expected: &[SpreadWallet]) {
+        let body: Cell = SpreadMessage::new(0, 1, Nanotons::from(1u64), data).build();
+        let recipients: Vec<SpreadWallet> = SpreadMessage::parse(&body).and_then(| m | m.recipients()).unwrap();
+
+        assert_eq!(recipients.len(), expected.len());
+        for (index, (recipient, expected)) in recipients.iter().zip(expected).enumerate() {
+            assert_eq!((&recipient.account, &recipient.amount, recipient.bounce), (&expected.account, &expected.amount, expected.bounce), "recipient {}", index);
+        }
+    }
+
+    #[test]
+    fn spread_recipients_decode_from_a_list() {
+        for count in [1, 2, 7, spread_max_depth() as usize] {
+            let expected: Vec<SpreadWallet> = spread_wallets(count);
+            assert_recipients(spread_list(&expected).unwrap(), &expected);
+        }
+    }
+
+    #[test]
+    fn spread_recipients_decode_from_a_dictionary() {
+        for count in [1, 2, 7, 1 << dict::SPREAD_DICT_KEY_BITS] {
+            let expected: Vec<SpreadWallet> = spread_wallets(count);
+            assert_recipients(dict::build(&expected).unwrap(), &expected);
+        }
+    }
+
+    #[test]
+    fn spread_recipients_decode_bounce_flags_from_a_list() {
+        let mut expected: Vec<SpreadWallet> = spread_wallets(3);
+        let mut data: Cell = CellBuilder::new().build().unwrap();
+        for (index, entry) in expected.iter_mut().enumerate() {
+            entry.bounce = Some(index % 2 == 0);
+
+            let mut builder: CellBuilder = CellBuilder::new();
+            builder.store_reference(&ArcCell::new(data)).unwrap();
+            builder.store_address(&entry.account).unwrap();
+            builder.store_coins(&entry.amount).unwrap();
+            builder.store_bit(index % 2 == 0).unwrap();
+            data = builder.build().unwrap();
+        }
+
+        assert_recipients(data, &expected);
+    }
+
+    #[test]
+    fn empty_list_has_no_recipients() {
+        assert_recipients(spread_list(&[]).unwrap(), &[]);
+    }
+
+    #[actix_web::test]
+    async fn seqno_comes_from_the_backend() {
+        let wallet: TonAddress = address(1);
+        mock().with_seqno(&wallet, 42);
+
+        assert_eq!(get_seqno(&wallet).await.unwrap(), 42);
+        assert_eq!(get_seqno(&address(2)).await.unwrap(), 0);
+    }
+
+    #[actix_web::test]
+    async fn spread_is_broadcast_with_its_body() {
+        let _sends = SENDS.lock().await;
+        let (signer, wallet): (TestSigner, TonWallet) = test_wallet(1);
+        mock().with_seqno(&wallet.address, 5);
+
+        let recipients: Vec<SpreadWallet> = vec![
+            SpreadWallet { account: address(3), amount: BigUint::from(1_000_000_000u64), bounce: None },
+            SpreadWallet { account: address(4), amount: BigUint::from(2_000_000_000u64), bounce: None }
+        ];
+        let body: Cell = spread_body(7, Nanotons::from(3_000_000_000u64), recipients);
+
+        let seqno: u32 = get_seqno(&wallet.address).await.unwrap();
+        let tx: SignedExternalMessage = create_external_signed_multi_message(wallet.clone(), &signer, seqno, vec![WalletTransfer {
+            destination: address(2),
+            amount: BigUint::from(3_100_000_000u64),
+            body: Some(body.clone()),
+            mode: DEFAULT_SEND_MODE
+        }], time_now() + 60).await.unwrap();
+        let hash: Vec<u8> = broadcast(&wallet.address, &tx.boc).await.unwrap();
+
+        let sent: Vec<u8> = mock().sent().into_iter().find(| boc | boc == &tx.boc).expect("the message was not sent");
+        let root: ArcCell = BagOfCells::parse(&sent).and_then(| b | b.single_root()).unwrap();
+        assert_eq!(hash, root.cell_hash().to_vec());
+        assert!(contains(&root, &body.cell_hash()), "the sent message does not carry the spread body");
+
+        match decode::decode(&body).unwrap() {
+            DecodedMessage::Spread { query_id, recipients, .. } => {
+                assert_eq!(query_id, 7);
+                assert_eq!(recipients.len(), 2);
+                assert_eq!(recipients[0].account, address(3).to_base64_url());
+                assert_eq!(recipients[1].amount, "2000000000");
+                assert_eq!(recipients[1].bounce, None);
+            },
+            other => panic!("decoded {:?} instead of a spread", other)
+        }
+    }
+
+    #[actix_web::test]
+    async fn stale_seqno_is_rebuilt_from_the_backend() {
+        let _sends = SENDS.lock().await;
+        let (signer, wallet): (TestSigner, TonWallet) = test_wallet(2);
+        mock()
+            .with_seqno(&wallet.address, 3)
+            .with_send_error("cannot apply external message to current state: exitcode=33, steps=37, gas_used=0");
+        let transfers = || vec![WalletTransfer { destination: address(5), amount: BigUint::from(1u64), body: None, mode: DEFAULT_SEND_MODE }];
+
+        let stale: SignedExternalMessage = create_external_signed_multi_message(wallet.clone(), &signer, 2, transfers(), time_now() + 60).await.unwrap();
+        let err: String = send_signed(backend().await, &wallet, &stale).await.unwrap_err();
+        assert!(stale_message(&err), "`{}` is not classified as stale", err);
+        assert!(!mock().sent().contains(&stale.boc));
+
+        let seqno: u32 = fresh_seqno(backend().await, &wallet.address, &mut None).await.unwrap();
+        assert_eq!(seqno, 3);
+
+        let rebuilt: SignedExternalMessage = create_external_signed_multi_message(wallet.clone(), &signer, seqno, transfers(), time_now() + 60).await.unwrap();
+        send_signed(backend().await, &wallet, &rebuilt).await.unwrap();
+        assert!(mock().sent().contains(&rebuilt.boc));
+    }
+
+    #[actix_web::test]
+    async fn jetton_wallet_comes_from_the_get_method() {
+        let (master, owner, jetton_wallet): (TonAddress, TonAddress, TonAddress) = (address(6), address(7), address(8));
+        let mut builder: CellBuilder = CellBuilder::new();
+        builder.store_address(&jetton_wallet).unwrap();
+        let slice: CellSlice = CellSlice::full_cell(builder.build().unwrap()).unwrap();
+        mock().with_get_method(&master, "get_wallet_address", vec![TvmStackEntry::Slice(slice)]);
+
+        assert_eq!(get_jetton_wallet_address(&master, &owner).await.unwrap(), jetton_wallet);
+
+        let err: String = get_jetton_wallet_address(&owner, &master).await.unwrap_err();
+        assert!(err.contains("not mocked"), "unexpected error `{}`", err);
+    }
+
+    #[actix_web::test]
+    async fn jetton_balance_reads_active_wallets_only() {
+        let (inactive, active): (TonAddress, TonAddress) = (address(9), address(10));
+        mock()
+            .with_get_method(&inactive, "get_wallet_data", vec![TvmStackEntry::Int257(BigInt::from(5))])
+            .with_account(&active, AccountState {
+                balance: 0,
+                active: true,
+                code_hash: None,
+                code: None,
+                last_transaction_id: InternalTransactionId { lt: 0, hash: [0u8; 32] }
+            })
+            .with_get_method(&active, "get_wallet_data", vec![TvmStackEntry::Int257(BigInt::from(123))]);
+
+        assert_eq!(get_jetton_balance(&inactive).await.unwrap(), BigInt::from(0));
+        assert_eq!(get_jetton_balance(&active).await.unwrap(), BigInt::from(123));
+    }
+}
@@ -0,0 +1,60 @@
+//! # Gasless Relay
+//!
+//! This module implements the gasless sending path for W5 wallets. Instead of an external
+//! message the wallet signs an internal request ("sint"), which a relayer configured with
+//! `RELAYER_URL` delivers to the wallet in an internal message it pays the fees for, so
+//! the operator wallet doesn't need a TON balance for fees.
+
+use std::sync::OnceLock;
+
+use base64::{Engine as _, engine::general_purpose};
+use serde_json::json;
+use tonlib::{cell::{ArcCell, BagOfCells}, wallet::TonWallet};
+
+use crate::{config, logging::trace};
+
+/// Client shared by all relay requests.
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Returns whether messages are relayed, i.e. `RELAYER_URL` is set.
+pub fn enabled() -> bool {
+    config::var("RELAYER_URL").map(| url | !url.is_empty()).unwrap_or(false)
+}
+
+/// Hands a signed internal request of the wallet to the relayer.
+///
+/// The relayer receives the wallet address, its public key and the signed body as a
+/// base64 BOC, authenticated with `RELAYER_TOKEN` as a bearer token when it is set.
+///
+/// # Arguments
+///
+/// * `wallet` - The wallet that signed the request.
+/// * `boc` - The serialized signed request.
+///
+/// # Returns
+///
+/// The hash of the signed request.
+pub async fn relay(wallet: &TonWallet, boc: &[u8]) -> Result<Vec<u8>, String> {
+    let url: String = config::var("RELAYER_URL").map_err(|_| String::from("`RELAYER_URL` is not set"))?;
+    let root: ArcCell = BagOfCells::parse(boc)
+        .and_then(| b | b.single_root())
+        .map_err(|e| e.to_string())?;
+
+    let client: &reqwest::Client = CLIENT.get_or_init(reqwest::Client::new);
+    let mut request = trace::inject(client.post(url)).json(&json!({
+        "wallet": wallet.address.to_base64_url(),
+        "wallet_public_key": hex::encode(&wallet.key_pair.public_key),
+        "body": general_purpose::STANDARD.encode(boc)
+    }));
+
+    if let Ok(token) = config::var("RELAYER_TOKEN") {
+        request = request.bearer_auth(token);
+    }
+
+    request.send()
+        .await
+        .and_then(| r | r.error_for_status())
+        .map_err(|e| format!("relayer rejected the request: {}", e))?;
+
+    Ok(root.cell_hash().to_vec())
+}
@@ -0,0 +1,378 @@
+//! # Signers
+//!
+//! This module implements the ed25519 signers of the wallet. Messages are always built
+//! locally; only the hash to sign is handed to the signer selected with `SIGNER`:
+//!
+//! - `mnemonic` (default) - the key pair derived from `WALLET_MNEMONIC` and the optional
+//!   passphrase `WALLET_MNEMONIC_PASSWORD`, held in memory
+//! - `aws-kms` - an `ECC_NIST_EDWARDS25519` key in AWS KMS, `KMS_KEY_ID` in `AWS_REGION`,
+//!   authenticated with `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`
+//! - `gcp-kms` - an `EC_SIGN_ED25519` key version in Cloud KMS, `KMS_KEY_ID` being its full
+//!   resource name, authenticated with `GCP_ACCESS_TOKEN` or the metadata server
+//!
+//! With a KMS signer the private key never enters the process, and `WALLET_MNEMONIC` is
+//! not needed: the wallet is derived from the public key of the KMS key.
+
+use std::{str::FromStr, sync::{Mutex, OnceLock}, time::Duration};
+
+use async_trait::async_trait;
+use base64::{Engine as _, engine::general_purpose};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tonlib::mnemonic::{KeyPair, Mnemonic};
+
+use crate::{config, scheduler::cron};
+
+use super::time_now;
+
+/// Length of an ed25519 public key in bytes.
+const PUBLIC_KEY_LEN: usize = 32;
+
+/// Timeout of a single KMS request.
+const KMS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The signer selected at startup.
+static SIGNER: OnceLock<Box<dyn Signer>> = OnceLock::new();
+
+/// Signs hashes on behalf of the wallet.
+#[async_trait]
+pub trait Signer: Send + Sync {
+    /// Returns the name of the signer, e.g. `aws-kms`.
+    fn name(&self) -> &str;
+
+    /// Returns the ed25519 public key of the wallet.
+    fn public_key(&self) -> &[u8];
+
+    /// Signs a message, in practice the 32 byte hash of a cell.
+    ///
+    /// # Returns
+    ///
+    /// The 64 byte ed25519 signature.
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+/// Signer holding the key pair of `WALLET_MNEMONIC` in memory.
+pub struct MnemonicSigner {
+    key_pair: KeyPair
+}
+
+impl MnemonicSigner {
+    /// Derives the key pair from `WALLET_MNEMONIC`, protected by `WALLET_MNEMONIC_PASSWORD` if it is set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the wallet mnemonic environment variable is not set, or the mnemonic is
+    /// invalid or does not match the passphrase.
+    pub fn from_env() -> MnemonicSigner {
+        let mnemonic_str: String = config::var("WALLET_MNEMONIC").unwrap();
+        let password: Option<String> = config::var("WALLET_MNEMONIC_PASSWORD").ok().filter(| p | !p.is_empty());
+
+        match MnemonicSigner::from_phrase(&mnemonic_str, password) {
+            Ok(signer) => signer,
+            Err(err) => panic!("[ FATAL ] Configuration Error: `WALLET_MNEMONIC` is invalid or does not match `WALLET_MNEMONIC_PASSWORD`: {}", err)
+        }
+    }
+
+    /// Derives the key pair from a mnemonic phrase, e.g. of a wallet other than the gas wallet.
+    ///
+    /// # Arguments
+    ///
+    /// * `phrase` - The 24 words of the mnemonic.
+    /// * `password` - The passphrase of a passphrase-protected mnemonic, if any.
+    pub fn from_phrase(phrase: &str, password: Option<String>) -> Result<MnemonicSigner, String> {
+        let mnemonic: Mnemonic = Mnemonic::from_str(phrase, &password).map_err(|e| e.to_string())?;
+
+        Ok(MnemonicSigner { key_pair: mnemonic.to_key_pair().map_err(|e| e.to_string())? })
+    }
+}
+
+#[async_trait]
+impl Signer for MnemonicSigner {
+    fn name(&self) -> &str {
+        "mnemonic"
+    }
+
+    fn public_key(&self) -> &[u8] {
+        &self.key_pair.public_key
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        nacl::sign::signature(message, &self.key_pair.secret_key).map_err(|e| e.message)
+    }
+}
+
+/// Represents a KMS provider and the key signing in it.
+enum KmsProvider {
+    Aws {
+        region: String,
+        key_id: String
+    },
+    Gcp {
+        key_name: String
+    }
+}
+
+/// Signer delegating signatures to a cloud KMS key.
+pub struct KmsSigner {
+    provider: KmsProvider,
+    public_key: Vec<u8>,
+    client: reqwest::Client,
+    /// Cached GCP access token and the Unix time it expires at.
+    gcp_token: Mutex<Option<(String, u64)>>
+}
+
+impl KmsSigner {
+    /// Connects to the KMS key of `SIGNER` and fetches its public key.
+    async fn connect(signer: &str) -> Result<KmsSigner, String> {
+        let key_id: String = config::var("KMS_KEY_ID").ok().filter(| k | !k.is_empty())
+            .ok_or_else(|| format!("`SIGNER={}` requires `KMS_KEY_ID`", signer))?;
+
+        let provider: KmsProvider = match signer {
+            "aws-kms" => KmsProvider::Aws {
+                region: config::var("AWS_REGION").map_err(|_| String::from("`SIGNER=aws-kms` requires `AWS_REGION`"))?,
+                key_id
+            },
+            _ => KmsProvider::Gcp { key_name: key_id }
+        };
+
+        let client: reqwest::Client = reqwest::Client::builder().timeout(KMS_TIMEOUT).build().map_err(|e| e.to_string())?;
+        let mut kms: KmsSigner = KmsSigner { provider, public_key: Vec::new(), client, gcp_token: Mutex::new(None) };
+        kms.public_key = kms.fetch_public_key().await?;
+
+        Ok(kms)
+    }
+
+    /// Fetches the raw ed25519 public key of the KMS key.
+    async fn fetch_public_key(&self) -> Result<Vec<u8>, String> {
+        let key: Vec<u8> = match &self.provider {
+            KmsProvider::Aws { key_id, .. } => {
+                let response: Value = self.aws("GetPublicKey", json!({ "KeyId": key_id })).await?;
+                decode_field(&response, "PublicKey")?
+            },
+            KmsProvider::Gcp { key_name } => {
+                let url: String = format!("https://cloudkms.googleapis.com/v1/{}/publicKey?publicKeyFormat=NACL_ED25519", key_name);
+                let response: Value = self.gcp(self.client.get(url)).await?;
+                decode_field(&response["publicKey"], "data")?
+            }
+        };
+
+        // AWS returns a DER SubjectPublicKeyInfo, which ends with the raw key
+        match key.len() >= PUBLIC_KEY_LEN {
+            true => Ok(key[key.len() - PUBLIC_KEY_LEN..].to_vec()),
+            false => Err(format!("KMS returned a public key of {} bytes", key.len()))
+        }
+    }
+
+    /// Calls an action of the AWS KMS JSON API, signing the request with SigV4.
+    async fn aws(&self, action: &str, body: Value) -> Result<Value, String> {
+        let KmsProvider::Aws { region, .. } = &self.provider else {
+            return Err(String::from("not an AWS KMS signer"));
+        };
+
+        let access_key: String = config::var("AWS_ACCESS_KEY_ID").map_err(|_| String::from("`AWS_ACCESS_KEY_ID` is not set"))?;
+        let secret_key: String = config::var("AWS_SECRET_ACCESS_KEY").map_err(|_| String::from("`AWS_SECRET_ACCESS_KEY` is not set"))?;
+        let session_token: Option<String> = config::var("AWS_SESSION_TOKEN").ok().filter(| t | !t.is_empty());
+
+        let host: String = format!("kms.{}.amazonaws.com", region);
+        let target: String = format!("TrentService.{}", action);
+        let payload: String = body.to_string();
+        let (amz_date, date) = amz_dates(time_now());
+
+        let mut headers: Vec<(&str, String)> = vec![
+            ("content-type", String::from("application/x-amz-json-1.1")),
+            ("host", host.clone()),
+            ("x-amz-date", amz_date.clone())
+        ];
+        if let Some(token) = &session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.push(("x-amz-target", target.clone()));
+
+        let signed_headers: String = headers.iter().map(| (name, _) | *name).collect::<Vec<&str>>().join(";");
+        let canonical_headers: String = headers.iter().map(| (name, value) | format!("{}:{}\n", name, value)).collect();
+        let canonical_request: String = format!(
+            "POST\n/\n\n{}\n{}\n{}", canonical_headers, signed_headers, hex::encode(Sha256::digest(payload.as_bytes()))
+        );
+
+        let scope: String = format!("{}/{}/kms/aws4_request", date, region);
+        let string_to_sign: String = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut key: Vec<u8> = hmac(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes());
+        for part in [region.as_str(), "kms", "aws4_request"] {
+            key = hmac(&key, part.as_bytes());
+        }
+        let signature: String = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+
+        let mut request = self.client.post(format!("https://{}/", host))
+            .header("content-type", "application/x-amz-json-1.1")
+            .header("x-amz-date", amz_date)
+            .header("x-amz-target", target)
+            .header("authorization", format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", access_key, scope, signed_headers, signature
+            ))
+            .body(payload);
+        if let Some(token) = session_token {
+            request = request.header("x-amz-security-token", token);
+        }
+
+        let response: reqwest::Response = request.send().await.map_err(|e| format!("AWS KMS {} failed: {}", action, e))?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(|e| format!("AWS KMS {} returned no JSON: {}", action, e))?;
+
+        match status.is_success() {
+            true => Ok(body),
+            false => Err(format!("AWS KMS {} failed with {}: {}", action, status, body["message"].as_str().unwrap_or("unknown error")))
+        }
+    }
+
+    /// Sends a Cloud KMS request with the access token.
+    async fn gcp(&self, request: reqwest::RequestBuilder) -> Result<Value, String> {
+        let token: String = self.gcp_token().await?;
+        let response: reqwest::Response = request.bearer_auth(token).send().await.map_err(|e| format!("Cloud KMS request failed: {}", e))?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(|e| format!("Cloud KMS returned no JSON: {}", e))?;
+
+        match status.is_success() {
+            true => Ok(body),
+            false => Err(format!("Cloud KMS failed with {}: {}", status, body["error"]["message"].as_str().unwrap_or("unknown error")))
+        }
+    }
+
+    /// Returns the GCP access token, from `GCP_ACCESS_TOKEN` or the metadata server of the instance.
+    async fn gcp_token(&self) -> Result<String, String> {
+        if let Ok(token) = config::var("GCP_ACCESS_TOKEN") {
+            return Ok(token);
+        }
+
+        if let Some((token, expires_at)) = self.gcp_token.lock().unwrap_or_else(| e | e.into_inner()).as_ref() {
+            if *expires_at > time_now() {
+                return Ok(token.clone());
+            }
+        }
+
+        let response: Value = self.client
+            .get("http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token")
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await
+            .and_then(| r | r.error_for_status())
+            .map_err(|e| format!("can not fetch a GCP access token: {}", e))?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let token: String = response["access_token"].as_str().ok_or("the metadata server returned no access token")?.to_string();
+        // refresh a minute before the token expires
+        let expires_at: u64 = time_now() + response["expires_in"].as_u64().unwrap_or(300).saturating_sub(60);
+        *self.gcp_token.lock().unwrap_or_else(| e | e.into_inner()) = Some((token.clone(), expires_at));
+
+        Ok(token)
+    }
+}
+
+#[async_trait]
+impl Signer for KmsSigner {
+    fn name(&self) -> &str {
+        match self.provider {
+            KmsProvider::Aws { .. } => "aws-kms",
+            KmsProvider::Gcp { .. } => "gcp-kms"
+        }
+    }
+
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        let encoded: String = general_purpose::STANDARD.encode(message);
+
+        let signature: Vec<u8> = match &self.provider {
+            KmsProvider::Aws { key_id, .. } => {
+                let response: Value = self.aws("Sign", json!({
+                    "KeyId": key_id,
+                    "Message": encoded,
+                    "MessageType": "RAW",
+                    "SigningAlgorithm": "ED25519_SHA_512"
+                })).await?;
+                decode_field(&response, "Signature")?
+            },
+            KmsProvider::Gcp { key_name } => {
+                let url: String = format!("https://cloudkms.googleapis.com/v1/{}:asymmetricSign", key_name);
+                let response: Value = self.gcp(self.client.post(url).json(&json!({ "data": encoded }))).await?;
+                decode_field(&response, "signature")?
+            }
+        };
+
+        // a signature the wallet would reject fails here, before the message is written to the outbox
+        match signature.len() {
+            64 if nacl::sign::verify(&signature, message, &self.public_key).unwrap_or(false) => Ok(signature),
+            64 => Err(String::from("KMS returned a signature that does not match the public key of the wallet")),
+            len => Err(format!("KMS returned a signature of {} bytes", len))
+        }
+    }
+}
+
+/// Decodes a base64 field of a KMS response.
+fn decode_field(response: &Value, field: &str) -> Result<Vec<u8>, String> {
+    let value: &str = response[field].as_str().ok_or_else(|| format!("KMS response has no `{}`", field))?;
+    general_purpose::STANDARD.decode(value).map_err(|e| e.to_string())
+}
+
+/// Computes an HMAC-SHA256.
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Formats a Unix time as the `YYYYMMDDTHHMMSSZ` timestamp and `YYYYMMDD` date of SigV4.
+fn amz_dates(now: u64) -> (String, String) {
+    let (year, month, day): (u64, u64, u64) = cron::civil_from_days(now / 86400);
+    let seconds: u64 = now % 86400;
+
+    let date: String = format!("{:04}{:02}{:02}", year, month, day);
+    let timestamp: String = format!("{}T{:02}{:02}{:02}Z", date, seconds / 3600, seconds % 3600 / 60, seconds % 60);
+
+    (timestamp, date)
+}
+
+/// Selects the signer configured with `SIGNER` and, for KMS signers, fetches the public key.
+///
+/// Must run before anything signs or derives the wallet address.
+///
+/// # Returns
+///
+/// An error if the signer is unknown or its KMS key can not be reached.
+pub async fn init() -> Result<(), String> {
+    let name: String = config::env_or("SIGNER", String::from("mnemonic"));
+
+    let signer: Box<dyn Signer> = match name.as_str() {
+        "mnemonic" => Box::new(MnemonicSigner::from_env()),
+        "aws-kms" | "gcp-kms" => Box::new(KmsSigner::connect(&name).await?),
+        _ => return Err(format!("`SIGNER` has an invalid value `{}`", name))
+    };
+
+    log_info!("Wallet messages are signed with the {} signer", signer.name());
+    SIGNER.set(signer).map_err(|_| String::from("the signer is already initialized"))
+}
+
+/// Returns the signer of the wallet.
+///
+/// # Panics
+///
+/// Panics if a KMS signer is configured but `init` did not run. The mnemonic signer is
+/// selected on first use otherwise.
+pub fn signer() -> &'static dyn Signer {
+    SIGNER.get_or_init(|| {
+        let name: String = config::env_or("SIGNER", String::from("mnemonic"));
+        if name != "mnemonic" {
+            panic!("[ FATAL ] Configuration Error: the `{}` signer was not initialized", name);
+        }
+
+        Box::new(MnemonicSigner::from_env())
+    }).as_ref()
+}
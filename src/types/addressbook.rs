@@ -0,0 +1,59 @@
+//! # Address Book Types
+//!
+//! This module defines the labeled addresses of the address book and the payloads managing them.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+use crate::validation;
+
+pub use mixer_types::addressbook::AddressLabels;
+
+/// Represents a labeled address.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct AddressBookEntry {
+    pub id: i64,
+    /// Name the entry is referenced by, as `@name`.
+    pub name: String,
+    pub address: String,
+    /// Raw form of the address, the entry is matched by.
+    pub raw_address: String,
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64
+}
+
+/// Represents the payload creating or replacing an address book entry.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct AddressBookPayload {
+    #[validate(regex(path = *validation::LABEL_RE))]
+    pub name: String,
+    #[validate(regex(path = *validation::ADDRESS_RE), custom(function = "validation::validate_address"))]
+    pub address: String,
+    #[validate(length(max = 16), custom(function = "validate_tags"))]
+    pub tags: Option<Vec<String>>,
+    #[validate(length(max = 1024))]
+    pub notes: Option<String>
+}
+
+/// Checks that every tag is a name like the ones of entries.
+fn validate_tags(tags: &Vec<String>) -> Result<(), ValidationError> {
+    if let Some(invalid) = tags.iter().find(| t | !validation::LABEL_RE.is_match(t)) {
+        let mut error: ValidationError = ValidationError::new("tags");
+        error.message = Some(format!("invalid tag `{}`", invalid).into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Represents the query filtering the address book.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Validate)]
+pub struct AddressBookQuery {
+    /// Only entries with this tag.
+    #[validate(regex(path = *validation::LABEL_RE))]
+    pub tag: Option<String>
+}
+
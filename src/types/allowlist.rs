@@ -0,0 +1,30 @@
+//! # Contract Allow-List Types
+//!
+//! This module defines the types of the admin-managed list of mixer contracts requests may target.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+use validator::Validate;
+
+/// Represents a mixer contract requests may target.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct AllowedContract {
+    pub address: String,
+    pub label: Option<String>,
+    pub created_at: i64
+}
+
+/// Represents the payload adding a contract to the allow-list.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct AllowedContractPayload {
+    #[validate(length(max = 64))]
+    pub label: Option<String>
+}
+
+/// Represents the query parameter selecting the mixer contract of an operation.
+///
+/// `MIXER_CONTRACT` is used when `contract` is omitted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContractQuery {
+    pub contract: Option<String>
+}
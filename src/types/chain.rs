@@ -0,0 +1,31 @@
+//! # Chain Types
+//!
+//! This module defines the sync status of the liteserver the API talks to.
+
+use serde::{Serialize, Deserialize};
+
+/// Represents the latest masterchain block and how far it is behind the local clock.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainInfo {
+    pub seqno: u32,
+    /// Unix time the block was generated at.
+    pub block_time: i64,
+    /// Seconds between the block time and now.
+    pub lag: i64,
+    /// Name of the liteserver set or HTTP API that answered.
+    pub backend: String,
+    /// Whether the liteservers are unreachable and calls go through the toncenter HTTP fallback.
+    pub degraded: bool
+}
+
+/// Represents the outcome of a readiness check.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Maximum lag in seconds the instance accepts before it reports itself not ready.
+    pub max_lag: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain: Option<ChainInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
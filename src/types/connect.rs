@@ -0,0 +1,30 @@
+//! # TON Connect Types
+//!
+//! This module defines the transaction requests handed to TON Connect wallets,
+//! so end users sign mixer operations with their own keys.
+
+use serde::{Serialize, Deserialize};
+
+/// Represents a single message of a TON Connect transaction request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TonConnectMessage {
+    /// Destination in the user-friendly bounceable form.
+    pub address: String,
+    /// Amount in nanotons, as a decimal string.
+    pub amount: String,
+    /// Base64-encoded BOC of the message body.
+    pub payload: String
+}
+
+/// Represents a TON Connect `sendTransaction` request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TonConnectRequest {
+    /// Unix time after which the wallet must reject the request.
+    pub valid_until: u64,
+    /// Network the request is meant for, `-239` for mainnet and `-3` for testnet.
+    pub network: String,
+    pub messages: Vec<TonConnectMessage>,
+    /// Query id stored in the message body, to look the operation up once it lands.
+    pub query_id: u64
+}
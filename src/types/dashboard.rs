@@ -0,0 +1,48 @@
+//! # Dashboard Types
+//!
+//! This module defines the aggregated state returned to the landing page of the ops UI.
+
+use serde::{Serialize, Deserialize};
+
+use super::{jobs::JobQueueStats, nanotons::Nanotons, outbox::OutboxEntry, Balances};
+
+/// Represents the state of the mixer at a glance.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Dashboard {
+    pub generated_at: i64,
+    /// Balances of the gas wallet and the mixer contract, `None` if they can not be fetched.
+    pub balances: Option<Balances>,
+    /// State of the jobs queue per kind of job.
+    pub queue: Vec<JobQueueStats>,
+    /// Most recent operations sent from the gas wallet, newest first.
+    pub operations: Vec<OutboxEntry>,
+    pub alerts: DashboardAlerts,
+    pub contracts: ContractPoolSummary,
+    /// Sections that could not be loaded, the others are still returned.
+    pub errors: Vec<String>
+}
+
+/// Represents the alert states shown on the dashboard.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DashboardAlerts {
+    /// Whether invocations of the mixer contract are paused.
+    pub paused: bool,
+    /// Whether collects require multisig approval.
+    pub multisig: bool,
+    /// Whether the liteservers are unreachable and calls go through the toncenter HTTP fallback.
+    pub degraded: bool,
+    /// Warnings of the accounts below their low-balance threshold.
+    pub low_balances: Vec<String>
+}
+
+/// Represents the tracked mixer contracts as a whole.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ContractPoolSummary {
+    /// Contracts that were not forked from another one.
+    pub roots: usize,
+    pub forks: usize,
+    /// Nanotons held by all contracts whose balance could be fetched.
+    pub balance: Nanotons,
+    /// Contracts whose balance could not be fetched.
+    pub unreachable: usize
+}
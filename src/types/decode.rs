@@ -0,0 +1,263 @@
+//! # Mixer Message Decoders
+//!
+//! This module provides parse counterparts to the `build` methods of the mixer messages,
+//! turning a message body cell back into the typed struct it was built from.
+
+use num_bigint::BigUint;
+use serde::{Serialize, Deserialize};
+use tonlib::cell::Cell;
+
+use crate::ton::dict;
+
+use super::{nanotons::Nanotons, CollectMessage, ForkMessage, MixerOp, MixerOpcodes, SpreadMessage, SpreadWallet, UpgradeMessage};
+
+/// Represents a single decoded recipient of a spread message.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DecodedSpreadRecipient {
+    pub account: String,
+    pub amount: String,
+    /// The bounce flag of the message, `None` in the legacy layout where the contract sets it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounce: Option<bool>
+}
+
+/// Represents a decoded mixer message body in a JSON friendly form.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DecodedMessage {
+    Spread {
+        query_id: u64,
+        mode: u8,
+        amount: Nanotons,
+        recipients: Vec<DecodedSpreadRecipient>
+    },
+    Collect {
+        query_id: u64,
+        mode: u8,
+        jetton_wallet: Option<String>,
+        amount: Option<String>
+    },
+    Fork {
+        query_id: u64
+    },
+    Upgrade {
+        query_id: u64,
+        code_hash: String
+    },
+    Unknown {
+        opcode: u32
+    }
+}
+
+impl DecodedMessage {
+    /// Returns the name of the decoded operation.
+    pub fn op_name(&self) -> &'static str {
+        match self {
+            DecodedMessage::Spread { .. } => "spread",
+            DecodedMessage::Collect { .. } => "collect",
+            DecodedMessage::Fork { .. } => "fork",
+            DecodedMessage::Upgrade { .. } => "upgrade",
+            DecodedMessage::Unknown { .. } => "unknown"
+        }
+    }
+
+    /// Returns the query id of the decoded operation, if it has one.
+    pub fn query_id(&self) -> Option<u64> {
+        match self {
+            DecodedMessage::Spread { query_id, .. } => Some(*query_id),
+            DecodedMessage::Collect { query_id, .. } => Some(*query_id),
+            DecodedMessage::Fork { query_id } => Some(*query_id),
+            DecodedMessage::Upgrade { query_id, .. } => Some(*query_id),
+            DecodedMessage::Unknown { .. } => None
+        }
+    }
+
+    /// Returns the mode of the decoded operation, if it has one.
+    pub fn mode(&self) -> Option<u8> {
+        match self {
+            DecodedMessage::Spread { mode, .. } => Some(*mode),
+            DecodedMessage::Collect { mode, .. } => Some(*mode),
+            _ => None
+        }
+    }
+}
+
+/// Reads the 32 bit operation code from the beginning of a message body.
+pub fn read_opcode(cell: &Cell) -> Result<u32, String> {
+    let mut parser = cell.parser();
+    parser.load_u32(32).map_err(|e| e.to_string())
+}
+
+/// Checks that the message body starts with the expected operation code.
+fn expect_opcode(cell: &Cell, expected: u32) -> Result<(), String> {
+    let opcode: u32 = read_opcode(cell)?;
+
+    if opcode != expected {
+        return Err(format!("unexpected opcode {:#010x}, expected {:#010x}", opcode, expected));
+    }
+
+    Ok(())
+}
+
+impl ForkMessage {
+    /// Parses a fork message cell built by `ForkMessage::build`.
+    pub fn parse(cell: &Cell) -> Result<Self, String> {
+        expect_opcode(cell, MixerOp::Fork.code())?;
+
+        let mut parser = cell.parser();
+        parser.load_u32(32).map_err(|e| e.to_string())?; //operation
+        let timestamp: u64 = parser.load_u64(64).map_err(|e| e.to_string())?; //query_id
+
+        Ok(ForkMessage::new(timestamp))
+    }
+}
+
+impl UpgradeMessage {
+    /// Parses an upgrade message cell built by `UpgradeMessage::build`.
+    pub fn parse(cell: &Cell) -> Result<Self, String> {
+        expect_opcode(cell, MixerOp::Upgrade.code())?;
+
+        let mut parser = cell.parser();
+        parser.load_u32(32).map_err(|e| e.to_string())?; //operation
+        let timestamp: u64 = parser.load_u64(64).map_err(|e| e.to_string())?; //query_id
+        let code: Cell = parser.next_reference().map_err(|e| e.to_string())?.as_ref().clone(); //new code
+
+        Ok(UpgradeMessage::new(timestamp, code))
+    }
+}
+
+impl SpreadMessage {
+    /// Parses a spread message cell built by `SpreadMessage::build`.
+    pub fn parse(cell: &Cell) -> Result<Self, String> {
+        expect_opcode(cell, MixerOp::Spread.code())?;
+
+        let mut parser = cell.parser();
+        parser.load_u32(32).map_err(|e| e.to_string())?; //operation
+        let timestamp: u64 = parser.load_u64(64).map_err(|e| e.to_string())?; //query_id
+        let amount: Nanotons = Nanotons::from(parser.load_u64(64).map_err(|e| e.to_string())?); //total amount of coins
+        let mode: u8 = parser.load_u8(8).map_err(|e| e.to_string())?; //spread mode
+
+        if !parser.load_bit().map_err(|e| e.to_string())? {
+            return Err("spread message has no recipients body".into());
+        }
+        let data: Cell = parser.next_reference().map_err(|e| e.to_string())?.as_ref().clone();
+
+        Ok(SpreadMessage::new(mode, timestamp, amount, data))
+    }
+
+    /// Reads the recipients stored in the message body, in either encoding.
+    ///
+    /// Every cell of a list keeps exactly one reference, to the previous one, while the root
+    /// of a recipient dictionary forks into two references or is a leaf without any, so the
+    /// encoding is told apart by the references of the root. See `dict::parse` for the latter.
+    ///
+    /// The recipients of a list are collected from the outermost cell and reversed to restore
+    /// the order in which they were passed to the spread operation. Recipients are read in
+    /// either layout, see `dict::load_recipient`, so spreads sent before the bounce flag was
+    /// stored still decode.
+    pub fn recipients(&self) -> Result<Vec<SpreadWallet>, String> {
+        let refs: usize = self.data.references().len();
+        if refs == 2 || (refs == 0 && self.data.bit_len() > 0) {
+            return dict::parse(&self.data);
+        }
+
+        let mut recipients: Vec<SpreadWallet> = Vec::new();
+        let mut current: Cell = self.data.clone();
+
+        while !current.references().is_empty() {
+            let next: Cell = {
+                let mut parser = current.parser();
+                let previous = parser.next_reference().map_err(|e| e.to_string())?;
+
+                recipients.push(dict::load_recipient(&mut parser)?);
+
+                previous.as_ref().clone()
+            };
+
+            current = next;
+        }
+
+        recipients.reverse();
+        Ok(recipients)
+    }
+}
+
+impl CollectMessage {
+    /// Parses a collect message cell built by `CollectMessage::build`.
+    pub fn parse(cell: &Cell) -> Result<Self, String> {
+        expect_opcode(cell, MixerOp::Collect.code())?;
+
+        let mut parser = cell.parser();
+        parser.load_u32(32).map_err(|e| e.to_string())?; //operation
+        let timestamp: u64 = parser.load_u64(64).map_err(|e| e.to_string())?; //query_id
+        let mode: u8 = parser.load_u8(8).map_err(|e| e.to_string())?; //collect mode
+
+        match mode {
+            0 | 1 | 2 => Ok(CollectMessage::new(mode, timestamp, None, None)),
+            3 => {
+                let jetton_wallet = parser.load_address().map_err(|e| e.to_string())?;
+                let amount: BigUint = parser.load_coins().map_err(|e| e.to_string())?;
+
+                Ok(CollectMessage::new(mode, timestamp, Some(jetton_wallet), Some(amount)))
+            },
+            _ => Err("Invalid collect mode".into()),
+        }
+    }
+}
+
+/// Decodes any mixer message body by dispatching on its operation code.
+///
+/// Bodies with an operation code that does not belong to the mixer are
+/// returned as `DecodedMessage::Unknown` rather than treated as an error.
+pub fn decode(cell: &Cell) -> Result<DecodedMessage, String> {
+    let opcodes: &MixerOpcodes = MixerOpcodes::get();
+    let opcode: u32 = read_opcode(cell)?;
+
+    if opcode == opcodes.spread {
+        let message: SpreadMessage = SpreadMessage::parse(cell)?;
+        let recipients: Vec<DecodedSpreadRecipient> = message.recipients()?.iter().map(| r | {
+            DecodedSpreadRecipient {
+                account: r.account.to_base64_url(),
+                amount: r.amount.to_string(),
+                bounce: r.bounce
+            }
+        }).collect();
+
+        return Ok(DecodedMessage::Spread {
+            query_id: message.timestamp,
+            mode: message.mode,
+            amount: message.amount,
+            recipients
+        });
+    }
+
+    if opcode == opcodes.collect {
+        let message: CollectMessage = CollectMessage::parse(cell)?;
+
+        return Ok(DecodedMessage::Collect {
+            query_id: message.timestamp,
+            mode: message.mode,
+            jetton_wallet: message.jetton_wallet.map(|w| w.to_base64_url()),
+            amount: message.amount.map(|a| a.to_string())
+        });
+    }
+
+    if opcode == opcodes.fork {
+        let message: ForkMessage = ForkMessage::parse(cell)?;
+
+        return Ok(DecodedMessage::Fork {
+            query_id: message.timestamp
+        });
+    }
+
+    if opcode == opcodes.upgrade {
+        let message: UpgradeMessage = UpgradeMessage::parse(cell)?;
+
+        return Ok(DecodedMessage::Upgrade {
+            query_id: message.timestamp,
+            code_hash: hex::encode(message.code.cell_hash())
+        });
+    }
+
+    Ok(DecodedMessage::Unknown { opcode })
+}
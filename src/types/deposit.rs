@@ -0,0 +1,185 @@
+//! # Deposit Types
+//!
+//! This module defines the types used to help wallet users deposit into the mixer contract,
+//! and the payment requests incoming transfers are matched against.
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+use crate::{strategy, validation};
+
+use super::nanotons::MAX_TON;
+
+use super::{jobs::Job, MAX_SPREAD_RECIPIENTS};
+
+/// Represents the query parameters of a deposit link.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct DepositLinkQuery {
+    /// Optional allow-listed mixer contract, `MIXER_CONTRACT` if omitted.
+    pub contract: Option<String>,
+    /// Amount in TON to pre-fill, left to the user if omitted.
+    #[validate(range(exclusive_min = 0.0, max = MAX_TON))]
+    pub amount: Option<f64>,
+    /// Tracking comment, generated if omitted.
+    #[validate(length(min = 1, max = 64), regex(path = *validation::COMMENT_RE))]
+    pub comment: Option<String>
+}
+
+/// Represents a deposit deeplink and its wallet-specific variants.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DepositLink {
+    pub address: String,
+    /// Amount in nanotons, if one was requested.
+    pub amount: Option<String>,
+    /// Text comment identifying the deposit.
+    pub comment: String,
+    /// Generic `ton://transfer` link.
+    pub ton: String,
+    pub tonkeeper: String,
+    pub tonhub: String
+}
+
+/// Represents the image format of a deposit QR code.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Svg,
+    Png
+}
+
+/// Represents the query parameter selecting the image format of a deposit QR code.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QrFormatQuery {
+    #[serde(default)]
+    pub format: QrFormat
+}
+
+/// The payment request waits for a transfer carrying its comment.
+pub const DEPOSIT_PENDING: &str = "pending";
+
+/// A transfer of at least the expected amount carried the comment.
+pub const DEPOSIT_PAID: &str = "paid";
+
+/// A transfer carried the comment but less than the expected amount.
+pub const DEPOSIT_UNDERPAID: &str = "underpaid";
+
+/// No transfer carried the comment before the request expired.
+pub const DEPOSIT_EXPIRED: &str = "expired";
+
+/// A transfer carried the comment after the request expired, its funds are held for a refund
+/// or a review instead of being spread.
+pub const DEPOSIT_LATE: &str = "late";
+
+/// Represents the payload creating a payment request.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct DepositPayload {
+    /// Optional allow-listed mixer contract, `MIXER_CONTRACT` if omitted.
+    #[validate(regex(path = *validation::ADDRESS_RE), custom(function = "validation::validate_address"))]
+    pub contract: Option<String>,
+    /// Expected amount in TON.
+    #[validate(range(exclusive_min = 0.0, max = MAX_TON))]
+    pub amount: f64,
+    /// Seconds until the request expires, `DEPOSIT_TTL` if omitted.
+    #[validate(range(min = 60, max = 604800))]
+    pub ttl: Option<u64>,
+    /// How the deposit is spread once it is paid, left to the caller if omitted.
+    #[validate(nested)]
+    pub plan: Option<MixPlan>
+}
+
+/// Maximum number of legs a deposit is spread in.
+pub const MAX_MIX_LEGS: u64 = 16;
+
+/// Represents how a paid deposit is spread.
+///
+/// The strategy decides how `delay`, `legs` and `schedule` are interpreted, see `strategy::MixStrategy`.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+#[validate(schema(function = "validate_mix_plan"))]
+pub struct MixPlan {
+    #[validate(length(min = 1, max = MAX_SPREAD_RECIPIENTS), nested)]
+    pub recipients: Vec<MixRecipient>,
+    /// Seconds between legs, or their mean gap for the `poisson` strategy, `0` if omitted.
+    #[validate(range(max = 604800))]
+    #[serde(default)]
+    pub delay: u64,
+    /// Name of the strategy, `MIX_STRATEGY` if omitted.
+    pub strategy: Option<String>,
+    /// Number of legs, `1` if omitted.
+    #[validate(range(min = 1, max = MAX_MIX_LEGS))]
+    pub legs: Option<u32>,
+    /// Seconds after the deposit at which the legs of the `scheduled` strategy are sent.
+    #[validate(length(min = 1, max = MAX_MIX_LEGS))]
+    pub schedule: Option<Vec<u64>>
+}
+
+/// Checks that a mixing plan names a known strategy and carries what it needs.
+fn validate_mix_plan(plan: &MixPlan) -> Result<(), ValidationError> {
+    if let Some(name) = &plan.strategy {
+        if !strategy::STRATEGIES.contains(&name.as_str()) {
+            let mut error: ValidationError = ValidationError::new("strategy");
+            error.message = Some(format!("field `strategy` must be one of {}", strategy::STRATEGIES.join(", ")).into());
+            return Err(error);
+        }
+
+        if name == "scheduled" && plan.schedule.is_none() {
+            let mut error: ValidationError = ValidationError::new("schedule");
+            error.message = Some("field `schedule` is required by the `scheduled` strategy".into());
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+/// Represents a target address of a mixing plan.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct MixRecipient {
+    #[validate(regex(path = *validation::ADDRESS_RE), custom(function = "validation::validate_address"))]
+    pub account: String,
+    /// Weight of the recipient, it receives `share / sum of shares` of the deposit.
+    #[validate(range(min = 1))]
+    pub share: u32,
+    #[serde(default)]
+    pub bounce: Option<bool>
+}
+
+/// Represents a payment request.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct Deposit {
+    pub id: i64,
+    pub contract: String,
+    /// Unique text comment the transfer has to carry.
+    pub comment: String,
+    /// Expected amount in nanotons.
+    pub amount: i64,
+    pub status: String,
+    pub expires_at: i64,
+    /// Amount of the matched transfer in nanotons.
+    pub received: Option<i64>,
+    pub sender: Option<String>,
+    pub tx_hash: Option<String>,
+    pub tx_lt: Option<i64>,
+    /// The `MixPlan` of the deposit.
+    pub plan: Option<Value>,
+    pub created_at: i64,
+    pub updated_at: i64
+}
+
+/// Represents a newly created payment request and the deeplinks paying it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DepositRequest {
+    #[serde(flatten)]
+    pub deposit: Deposit,
+    pub links: DepositLink
+}
+
+/// Represents a payment request and the jobs spreading it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DepositDetails {
+    #[serde(flatten)]
+    pub deposit: Deposit,
+    pub jobs: Vec<Job>
+}
@@ -0,0 +1,61 @@
+//! # Mixer Event Types
+//!
+//! This module defines the records produced by the on-chain indexer
+//! for the mixer contract and its forks.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+
+use super::addressbook::AddressLabels;
+
+pub use mixer_types::events::MixerEvent;
+
+/// Represents a contract tracked by the indexer.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct MixerContract {
+    pub address: String,
+    pub parent: Option<String>,
+    pub created_at: i64,
+    pub last_lt: i64
+}
+
+/// Represents a tracked contract in the fork tree.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContractNode {
+    pub address: String,
+    pub parent: Option<String>,
+    /// Number of forks between the contract and its root, `0` for roots.
+    pub depth: u32,
+    pub created_at: i64,
+    /// Balance in nanotons, `None` if it could not be fetched.
+    pub balance: Option<i64>
+}
+
+/// Represents a fork of a contract in the fork tree.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContractEdge {
+    pub parent: String,
+    pub child: String
+}
+
+/// Represents the tracked contracts as a graph, from the root mixer contracts to their forks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContractTree {
+    /// Contracts that were not forked from another one.
+    pub roots: Vec<String>,
+    pub nodes: Vec<ContractNode>,
+    pub edges: Vec<ContractEdge>,
+    /// Sum of the fetched balances in nanotons.
+    pub total_balance: i64
+}
+
+/// Represents a page of contract transactions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContractTransactionsPage {
+    pub transactions: Vec<MixerEvent>,
+    pub next_lt: Option<i64>,
+    pub next_hash: Option<String>,
+    /// Address book names of the contracts and sources of the page.
+    #[serde(default, skip_serializing_if = "AddressLabels::is_empty")]
+    pub labels: AddressLabels
+}
@@ -0,0 +1,43 @@
+//! # Faucet Types
+//!
+//! This module defines the requests funding a test environment from a testnet faucet.
+
+use serde::{Serialize, Deserialize};
+use validator::Validate;
+
+/// Represents an account the faucet funds.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FaucetTarget {
+    /// The wallet of the default account, paying for gas.
+    Wallet,
+    /// The mixer contract of `MIXER_CONTRACT`.
+    Contract
+}
+
+/// Represents the payload requesting testnet TON from the faucet.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Validate)]
+pub struct FaucetPayload {
+    /// The accounts to fund, the wallet and the mixer contract if omitted.
+    #[validate(length(min = 1, max = 2))]
+    pub targets: Option<Vec<FaucetTarget>>,
+    /// TON sent to every target, `FAUCET_AMOUNT` if omitted.
+    #[validate(range(min = 0.01, max = 100.0))]
+    pub amount: Option<f64>
+}
+
+/// Represents the funds granted to an account by the faucet.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FaucetGrant {
+    pub target: FaucetTarget,
+    pub address: String,
+    /// Nanotons requested for the account.
+    pub amount: u64,
+    /// `wallet` for the faucet wallet of `FAUCET_WALLET_MNEMONIC`, `url` for the faucet at `FAUCET_URL`.
+    pub source: String,
+    /// Hex hash of the message sent by the faucet wallet, `None` for an external faucet or if sending failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
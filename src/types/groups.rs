@@ -0,0 +1,77 @@
+//! # Recipient Group Types
+//!
+//! This module defines the named sets of address book entries a spread can be submitted
+//! against by id, with the weights the total is divided by.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+use crate::validation;
+
+use super::{nanotons::MAX_TON, MAX_SPREAD_RECIPIENTS};
+
+/// Represents a named set of recipients.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct RecipientGroup {
+    pub id: i64,
+    pub name: String,
+    #[sqlx(skip)]
+    pub members: Vec<RecipientGroupMember>,
+    pub created_at: i64,
+    pub updated_at: i64
+}
+
+/// Represents an address book entry in a group.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct RecipientGroupMember {
+    #[serde(skip)]
+    pub group_id: i64,
+    pub entry_id: i64,
+    /// Name of the address book entry.
+    pub name: String,
+    pub address: String,
+    /// Share of the member in the total, relative to the weights of the other members.
+    pub weight: f64
+}
+
+/// Represents a member of the payload creating or replacing a group.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct RecipientGroupMemberPayload {
+    pub entry_id: i64,
+    /// Weight of the member, 1 if omitted.
+    #[validate(range(exclusive_min = 0.0, max = 1_000_000.0))]
+    pub weight: Option<f64>
+}
+
+/// Represents the payload creating or replacing a group.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct RecipientGroupPayload {
+    #[validate(regex(path = *validation::LABEL_RE))]
+    pub name: String,
+    #[validate(length(min = 1, max = MAX_SPREAD_RECIPIENTS), nested, custom(function = "validate_members"))]
+    pub members: Vec<RecipientGroupMemberPayload>
+}
+
+/// Checks that no entry is a member twice.
+fn validate_members(members: &Vec<RecipientGroupMemberPayload>) -> Result<(), ValidationError> {
+    let mut ids: Vec<i64> = members.iter().map(| m | m.entry_id).collect();
+    ids.sort_unstable();
+
+    if ids.windows(2).any(| pair | pair[0] == pair[1]) {
+        let mut error: ValidationError = ValidationError::new("members");
+        error.message = Some("an address book entry can be a member only once".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Represents the payload of a spread to a group.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct GroupSpreadPayload {
+    pub group_id: i64,
+    /// TON divided between the members by their weights.
+    #[validate(range(exclusive_min = 0.0, max = MAX_TON))]
+    pub total: f64
+}
@@ -0,0 +1,36 @@
+//! # Jetton Types
+//!
+//! This module defines the jetton balances reported for the mixer contract.
+
+use serde::{Serialize, Deserialize};
+
+/// Represents the sum of the balances of one jetton held by several accounts.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JettonTotal {
+    pub master: String,
+    pub symbol: Option<String>,
+    pub decimals: u32,
+    pub balance: String,
+    pub amount: String
+}
+
+/// Represents the metadata of a jetton needed to display balances.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JettonMetadata {
+    pub symbol: Option<String>,
+    pub decimals: u32
+}
+
+/// Represents the balance of one jetton held by a contract.
+///
+/// `balance` is in the smallest units of the jetton, `amount` is the same balance
+/// scaled by `decimals`, as shown in wallets.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JettonBalance {
+    pub master: String,
+    pub wallet: String,
+    pub symbol: Option<String>,
+    pub decimals: u32,
+    pub balance: String,
+    pub amount: String
+}
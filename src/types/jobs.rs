@@ -0,0 +1,150 @@
+//! # Job Types
+//!
+//! This module defines the background jobs operations are scheduled as.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+use validator::Validate;
+
+use super::{CollectPayload, SpreadWalletPayload};
+
+pub use mixer_types::jobs::Job;
+
+/// The job waits for its `run_at` time.
+pub const JOB_PENDING: &str = "pending";
+
+/// The job was claimed by the runner.
+pub const JOB_RUNNING: &str = "running";
+
+/// The job finished successfully.
+pub const JOB_DONE: &str = "done";
+
+/// The job failed, see its `error`.
+pub const JOB_FAILED: &str = "failed";
+
+/// The job was cancelled before it ran, see its `cancel_reason`.
+pub const JOB_CANCELLED: &str = "cancelled";
+
+/// Kind of a job spreading funds through a mixer contract.
+pub const JOB_SPREAD: &str = "spread";
+
+/// Kind of a job collecting funds once they spent their dwell time on a mixer contract.
+pub const JOB_COLLECT: &str = "collect";
+
+/// Kind of a job collecting from several contracts one after another.
+pub const JOB_COLLECT_BATCH: &str = "collect_batch";
+
+/// Kind of a job moving funds between fork contracts to reach target ratios.
+pub const JOB_REBALANCE: &str = "rebalance";
+
+/// Kind of a job forking a mixer contract.
+pub const JOB_FORK: &str = "fork";
+
+/// Kind of a job storing the mixing statistics of a window as its result.
+pub const JOB_SNAPSHOT: &str = "snapshot";
+
+/// Priority of background work that may wait, e.g. a rebalance.
+pub const JOB_PRIORITY_LOW: i32 = 0;
+
+/// Priority of routine work, e.g. the spread legs of a deposit.
+pub const JOB_PRIORITY_NORMAL: i32 = 1;
+
+/// Priority of work that should not wait behind routine jobs, e.g. a time-locked collect.
+pub const JOB_PRIORITY_HIGH: i32 = 2;
+
+/// Outcome of a job that was put back into the queue for later.
+pub const JOB_DEFERRED: &str = "deferred";
+
+/// Represents the filters and the page of a job listing.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct JobListQuery {
+    /// Only jobs with this status, e.g. `pending`.
+    pub status: Option<String>,
+    /// Only jobs of this kind, e.g. `spread`.
+    pub kind: Option<String>,
+    /// Only jobs of this deposit.
+    pub deposit_id: Option<i64>,
+    /// Only jobs with this priority.
+    #[validate(range(min = 0, max = 3))]
+    pub priority: Option<i32>,
+    /// Only jobs created at or after this Unix time.
+    pub from: Option<i64>,
+    /// Only jobs created before this Unix time.
+    pub to: Option<i64>,
+    /// Only jobs with an id below this one, the `next` of the previous page.
+    #[validate(range(min = 1))]
+    pub before: Option<i64>,
+    /// Number of jobs returned, 50 if omitted.
+    #[validate(range(min = 1, max = 500))]
+    pub limit: Option<i64>
+}
+
+/// Represents a page of jobs, newest first.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobPage {
+    pub jobs: Vec<Job>,
+    /// The `before` of the next page, `None` on the last page.
+    pub next: Option<i64>
+}
+
+/// Represents the query of a job cancellation.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct CancelJobQuery {
+    #[validate(length(max = 256))]
+    pub reason: Option<String>
+}
+
+/// Represents the state of the queue for one kind of job.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct JobQueueStats {
+    pub kind: String,
+    /// Number of pending jobs, due or not.
+    pub pending: i64,
+    /// Number of pending jobs whose `run_at` time has come.
+    pub due: i64,
+    /// Number of jobs claimed by a runner and not finished yet.
+    pub running: i64,
+    /// Seconds the oldest due job has been waiting past its `run_at` time.
+    pub oldest_due_age: i64,
+    /// Number of attempts beyond the first of all jobs.
+    pub retries: i64
+}
+
+/// Represents the payload of a spread job.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpreadJob {
+    pub contract: String,
+    pub wallets: Vec<SpreadWalletPayload>,
+    /// TON `percent` amounts are taken of, required if a recipient has a `percent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<f64>
+}
+
+/// Represents the payload of a time-locked collect job.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectJob {
+    pub contract: String,
+    pub collect: CollectPayload
+}
+
+/// Represents the payload of a batch collect job.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectBatchJob {
+    pub contracts: Vec<String>,
+    /// Whether the contracts are tracked ones deployed by the mixer, rather than allow-listed ones.
+    pub tracked: bool,
+    pub mode: u8
+}
+
+/// Represents the payload of a fork job, forked with the time it runs at as query id.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ForkJob {
+    pub contract: String
+}
+
+/// Represents the payload of a statistics snapshot job.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotJob {
+    /// The statistics window, `24h`, `7d` or `30d`.
+    pub window: String
+}
@@ -0,0 +1,16 @@
+//! # Leader Types
+//!
+//! This module defines the record of the instance elected to send from the wallet.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+
+/// Represents the instance holding the sender lock.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct Leader {
+    /// `INSTANCE_ID` of the leader.
+    pub instance: String,
+    /// Base URL followers forward writes to, `None` if the leader did not advertise one.
+    pub url: Option<String>,
+    pub elected_at: i64
+}
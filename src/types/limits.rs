@@ -0,0 +1,49 @@
+//! # Withdrawal Limit Types
+//!
+//! This module defines the types of the daily withdrawal limit and its admin overrides.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+use validator::Validate;
+
+use super::nanotons::MAX_TON;
+
+/// Represents extra allowance an admin granted on top of the daily withdrawal limit.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct LimitOverride {
+    pub id: i64,
+    /// Extra nanotons that may be withdrawn while the override is active.
+    pub amount: i64,
+    pub reason: String,
+    /// Address of the admin client that granted the override.
+    pub requested_by: Option<String>,
+    pub created_at: i64,
+    pub expires_at: i64
+}
+
+/// Represents the payload granting a limit override.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct LimitOverridePayload {
+    /// Extra TON that may be withdrawn.
+    #[validate(range(exclusive_min = 0.0, max = MAX_TON))]
+    pub amount: f64,
+    /// Hours the override is active for, 24 if omitted.
+    #[validate(range(min = 1, max = 168))]
+    pub hours: Option<u32>,
+    #[validate(length(min = 1, max = 256))]
+    pub reason: String
+}
+
+/// Represents the usage of the daily withdrawal limit.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DailyLimitStatus {
+    /// The configured limit in nanotons, `None` if withdrawals are not limited.
+    pub limit: Option<i64>,
+    /// Nanotons withdrawn in the last 24 hours.
+    pub used: i64,
+    /// Nanotons granted by active overrides.
+    pub granted: i64,
+    /// Nanotons that can still be withdrawn, `None` if withdrawals are not limited.
+    pub remaining: Option<i64>,
+    pub overrides: Vec<LimitOverride>
+}
@@ -3,14 +3,51 @@
 //! This module defines types and functions for a TON (The Open Network) mixer,
 //! including response types, wallet operations, and message building.
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::{Arc, OnceLock}};
 
 use crc32fast::Hasher;
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
-use tonlib::{address::TonAddress, cell::{ArcCell, BagOfCells, Cell, CellBuilder}, message::TransferMessage, wallet::TonWallet};
+use tonlib::{address::TonAddress, cell::{ArcCell, BagOfCells, Cell, CellBuilder}, message::TransferMessage, types::TonHash, wallet::{TonWallet, WalletVersion}};
 
 use num_bigint::BigUint;
+use validator::{Validate, ValidationError};
+
+use crate::{ton::signer::Signer, validation};
+
+use nanotons::{Nanotons, MAX_TON};
+
+pub use mixer_types::{explorer, AppliedTransaction, OperationReceipt, SpreadFees, SpreadLegFee, TXHash};
+
+pub mod addressbook;
+pub mod allowlist;
+pub mod chain;
+pub mod connect;
+pub mod dashboard;
+pub mod decode;
+pub mod deposit;
+pub mod events;
+pub mod faucet;
+pub mod groups;
+pub mod jobs;
+pub mod jettons;
+pub mod leader;
+pub mod limits;
+pub mod multisig;
+pub mod nanotons;
+pub mod notifications;
+pub mod outbox;
+pub mod rates;
+pub mod rebalance;
+pub mod reports;
+pub mod schedules;
+pub mod split;
+pub mod topups;
+pub mod upgrade;
+pub mod webhooks;
+
+/// Number of seconds an external message stays valid after it is signed, used when `MESSAGE_TTL` is not set.
+pub const DEFAULT_MESSAGE_TTL: u64 = 60;
 
 /// Represents the status of a response.
 #[derive(Serialize, Deserialize, Debug)]
@@ -27,15 +64,30 @@ pub enum ResponseStatus {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Response {
     pub status: ResponseStatus,
-    pub message: Value
+    pub message: Value,
+    /// Id of the request, so a failure can be found in the logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Non-fatal issues of the request, see `warnings::warn`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>
 }
 
 impl Response {
     /// Creates a new error response.
+    ///
+    /// A string message is redacted, as errors of tonlib or the relayer may echo secrets.
     pub fn error(message: Value) -> Response {
+        let message: Value = match message {
+            Value::String(message) => Value::String(crate::logging::redact::redact(&message)),
+            message => message
+        };
+
         Response{
             status: ResponseStatus::Error,
-            message
+            message,
+            request_id: None,
+            warnings: Vec::new()
         }
     }
 
@@ -43,7 +95,9 @@ impl Response {
     pub fn info(message: Value) -> Response {
         Response{
             status: ResponseStatus::Info,
-            message
+            message,
+            request_id: None,
+            warnings: Vec::new()
         }
     }
 
@@ -51,54 +105,452 @@ impl Response {
     pub fn success(message: Value) -> Response {
         Response{
             status: ResponseStatus::Success,
-            message
+            message,
+            request_id: None,
+            warnings: Vec::new()
         }
     }
 
+    /// Sets the id of the request the response answers.
+    pub fn with_request_id(mut self, request_id: &str) -> Response {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+
     /// Converts the response to a JSON string.
     pub fn to_string(&self) -> String {
         serde_json::to_string::<Response>(self).unwrap()
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct TXHash {
-    pub hex: String,
-    pub base64: String
+/// Represents the query setting the total `percent` amounts of a spread are taken of, in TON.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Validate)]
+pub struct SpreadTotalQuery {
+    #[validate(range(exclusive_min = 0.0, max = MAX_TON))]
+    pub total: Option<f64>
 }
 
-impl TXHash {
-    pub fn new(hex: String, base64: String) -> Self {
-        TXHash{
-            hex,
-            base64
-        }
-    }
+/// Represents the query asking to hold the response until the message is applied on chain.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ConfirmationQuery {
+    #[serde(default)]
+    pub wait_for_confirmation: bool
+}
 
-    pub fn to_string(&self) -> String {
-        serde_json::to_string::<TXHash>(self).unwrap()
-    }
+/// Maximum number of recipients of a single spread operation.
+///
+/// It matches the deepest recipient list an external message can carry, the upper bound of
+/// `SPREAD_MAX_DEPTH`, so the capacity checks of a spread decide where it has to be split.
+pub const MAX_SPREAD_RECIPIENTS: u64 = 508;
+
+/// Represents the body of a spread operation: the list of its recipients.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+#[serde(transparent)]
+pub struct SpreadPayload {
+    #[validate(length(min = 1, max = MAX_SPREAD_RECIPIENTS), nested)]
+    pub wallets: Vec<SpreadWalletPayload>
 }
 
 /// Represents the payload for a spread wallet operation.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+///
+/// The amount is given either in TON with `amount`, in USD with `amount_usd`, which is
+/// converted at the rate locked in for the whole operation, or as a `percent` of the total
+/// of the spread, see `services::mixer::percent_amounts`.
+/// A `bounce` is only stored with `SPREAD_LAYOUT=bounce`, where an omitted one is derived from
+/// the address form and the status of the account, see `services::mixer::default_bounce`.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+#[validate(schema(function = "validate_spread_amount"))]
 pub struct SpreadWalletPayload {
+    /// The recipient, an address or `@name` of an address book entry.
+    #[validate(custom(function = "validation::validate_recipient"))]
     pub account: String,
-    pub amount: f64
+    #[validate(range(exclusive_min = 0.0, max = MAX_TON))]
+    pub amount: Option<f64>,
+    #[validate(range(exclusive_min = 0.0))]
+    pub amount_usd: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(exclusive_min = 0.0, max = 100.0))]
+    pub percent: Option<f64>,
+    #[serde(default)]
+    pub bounce: Option<bool>,
+    /// Send mode of the transfer, for direct spreads only, see `validation::validate_send_mode`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(custom(function = "validation::validate_send_mode"))]
+    pub send_mode: Option<u8>
+}
+
+/// Checks that a spread recipient has exactly one of `amount`, `amount_usd` and `percent`.
+fn validate_spread_amount(payload: &SpreadWalletPayload) -> Result<(), ValidationError> {
+    let amounts: usize = [payload.amount.is_some(), payload.amount_usd.is_some(), payload.percent.is_some()]
+        .iter()
+        .filter(| given | **given)
+        .count();
+
+    if amounts != 1 {
+        let mut error: ValidationError = ValidationError::new("amount");
+        error.message = Some("exactly one of fields `amount`, `amount_usd` and `percent` is required".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Asset of a mixed spread leg sending native TON.
+pub const ASSET_TON: &str = "ton";
+
+/// Represents the body of a mixed spread: recipients of native TON and of jettons.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+#[serde(transparent)]
+pub struct MixedSpreadPayload {
+    #[validate(length(min = 1, max = MAX_SPREAD_RECIPIENTS), nested)]
+    pub wallets: Vec<MixedSpreadLegPayload>
 }
 
-/// Represents a spread wallet with a TON address and amount.
+/// Represents a recipient of a mixed spread.
+///
+/// `asset` is `ton` (the default) or the address of a jetton master configured in
+/// `JETTON_MASTERS`, and `amount` is in TON or in whole jettons, scaled by their decimals.
+/// `bounce` only applies to TON legs, see `SpreadWalletPayload`.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct MixedSpreadLegPayload {
+    #[validate(regex(path = *validation::ADDRESS_RE), custom(function = "validation::validate_address"))]
+    pub account: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_asset"))]
+    pub asset: Option<String>,
+    #[validate(range(exclusive_min = 0.0))]
+    pub amount: f64,
+    #[serde(default)]
+    pub bounce: Option<bool>
+}
+
+impl MixedSpreadLegPayload {
+    /// Returns the asset of the leg, `ton` when none is given.
+    pub fn asset(&self) -> &str {
+        self.asset.as_deref().unwrap_or(ASSET_TON)
+    }
+}
+
+/// Checks that an asset is `ton` or an address.
+fn validate_asset(asset: &str) -> Result<(), ValidationError> {
+    if asset == ASSET_TON {
+        return Ok(());
+    }
+
+    validation::validate_address(asset)
+}
+
+/// Represents the messages sent for one asset of a mixed spread.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpreadGroupReceipt {
+    /// `ton` or the jetton master address.
+    pub asset: String,
+    /// Number of recipients of the asset.
+    pub legs: usize,
+    /// Query id of the spread through the mixer contract, or of the first jetton transfer.
+    pub query_id: u64,
+    /// The external messages carrying the group, a message may carry several groups.
+    pub receipts: Vec<OperationReceipt>
+}
+
+/// Represents the outcome of a mixed spread.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MixedSpreadReceipt {
+    pub groups: Vec<SpreadGroupReceipt>
+}
+
+/// Represents a spread wallet with a TON address, amount and bounce flag.
 pub struct SpreadWallet {
     pub account: TonAddress,
-    pub amount: BigUint
+    pub amount: BigUint,
+    /// The bounce flag of the message, `None` in the legacy layout where the contract sets it.
+    pub bounce: Option<bool>
 }
 
 /// Represents the payload for a collect operation.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+#[validate(schema(function = "validate_collect_payload"))]
 pub struct CollectPayload {
+    #[validate(range(max = 3))]
     pub mode: u8,
+    /// The jetton wallet collected from, an address or `@name` of an address book entry.
+    #[validate(custom(function = "validation::validate_recipient"))]
     pub jetton_wallet: Option<String>,
-    pub amount: Option<f64>
+    #[validate(range(exclusive_min = 0.0, max = MAX_TON))]
+    pub amount: Option<f64>,
+    /// Hours the newest uncollected funds must have been on the contract before the collect is sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, max = 720))]
+    pub min_dwell_hours: Option<u32>,
+    /// Priority of the scheduled collect job, `JOB_PRIORITY_HIGH` if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 0, max = 3))]
+    pub priority: Option<i32>,
+    /// Send mode of the message to the contract, `DEFAULT_SEND_MODE` if omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(custom(function = "validation::validate_send_mode"))]
+    pub send_mode: Option<u8>
+}
+
+/// Checks that collection mode 3 carries the jetton wallet and the amount to collect, and
+/// that the send mode neither carries the whole balance nor destroys the sender.
+///
+/// A collect message only pays for gas, so mode `128` would move the whole balance of the
+/// gas wallet or the multisig into the contract, and `32` would destroy the sender.
+fn validate_collect_payload(payload: &CollectPayload) -> Result<(), ValidationError> {
+    if payload.mode == 3 && (payload.jetton_wallet.is_none() || payload.amount.is_none()) {
+        let mut error: ValidationError = ValidationError::new("collect_mode");
+        error.message = Some("in collection mode 3 fields `jetton_wallet` and `amount` are required".into());
+        return Err(error);
+    }
+
+    if payload.send_mode.is_some_and(| m | m & (SEND_CARRY_ALL_BALANCE | SEND_DESTROY_IF_ZERO) != 0) {
+        let mut error: ValidationError = ValidationError::new("send_mode");
+        error.message = Some("collects can not send with modes carrying the whole balance or destroying the sender".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Maximum number of contracts collected from in one batch.
+pub const MAX_BATCH_COLLECT: usize = 64;
+
+/// Represents the contracts a batch collect targets.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum CollectTargets {
+    /// `"all"`, every contract tracked by the indexer.
+    Keyword(String),
+    /// The addresses of the contracts.
+    Contracts(Vec<String>)
+}
+
+/// Represents the payload for a batch collect operation.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct CollectBatchPayload {
+    #[validate(custom(function = "validate_collect_targets"))]
+    pub contracts: CollectTargets,
+    /// Collection mode of every collect, jettons are collected one contract at a time.
+    #[validate(range(max = 2))]
+    pub mode: u8
+}
+
+/// Checks that the targets are `"all"` or a bounded list of valid addresses.
+fn validate_collect_targets(targets: &CollectTargets) -> Result<(), ValidationError> {
+    let message: Option<String> = match targets {
+        CollectTargets::Keyword(keyword) if keyword == "all" => None,
+        CollectTargets::Keyword(keyword) => Some(format!("expected `all` or a list of addresses, got `{}`", keyword)),
+        CollectTargets::Contracts(contracts) if contracts.is_empty() || contracts.len() > MAX_BATCH_COLLECT => {
+            Some(format!("expected between 1 and {} contracts", MAX_BATCH_COLLECT))
+        },
+        CollectTargets::Contracts(contracts) => contracts.iter()
+            .find(| c | !validation::ADDRESS_RE.is_match(c) || validation::validate_address(c).is_err())
+            .map(| c | format!("invalid address `{}`", c))
+    };
+
+    match message {
+        Some(message) => {
+            let mut error: ValidationError = ValidationError::new("contracts");
+            error.message = Some(message.into());
+            Err(error)
+        },
+        None => Ok(())
+    }
+}
+
+/// Represents the outcome of the collect from one contract of a batch.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectBatchResult {
+    pub contract: String,
+    /// The receipt of the sent collect, `None` if it was not sent.
+    pub receipt: Option<OperationReceipt>,
+    /// Why the collect was not sent.
+    pub error: Option<String>
+}
+
+/// Represents the outcome of a batch collect operation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectBatchReceipt {
+    /// Number of collects sent.
+    pub sent: usize,
+    /// Number of contracts not collected from.
+    pub failed: usize,
+    pub results: Vec<CollectBatchResult>
+}
+
+/// Represents the outcome of comparing the code of a contract with `MIXER_CODE_HASH`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CodeVerification {
+    pub contract: String,
+    /// Whether the contract is deployed.
+    pub active: bool,
+    /// Hex hash of the code of the contract, `None` if it has none.
+    pub code_hash: Option<String>,
+    /// The accepted code hashes in hex.
+    pub expected: Vec<String>,
+    /// Whether the code matches, `None` if no hash is configured or the code can not be read.
+    pub verified: Option<bool>
+}
+
+/// Represents the size of a cell tree.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CellStats {
+    /// Number of cells, counting a cell referenced twice twice.
+    pub cells: u32,
+    /// Number of data bits of all cells.
+    pub bits: u64,
+    /// Number of references of all cells.
+    pub refs: u32,
+    /// Length of the longest chain of references below the root.
+    pub depth: u32,
+    pub root_bits: u32,
+    pub root_refs: u32
+}
+
+impl CellStats {
+    /// Measures a cell tree.
+    pub fn of(cell: &Cell) -> CellStats {
+        let mut stats: CellStats = CellStats {
+            root_bits: cell.bit_len() as u32,
+            root_refs: cell.references().len() as u32,
+            ..CellStats::default()
+        };
+
+        // walk the tree without recursion, spread lists are as deep as they are long
+        let mut stack: Vec<(&Cell, u32)> = vec![(cell, 0)];
+        while let Some((current, depth)) = stack.pop() {
+            stats.cells += 1;
+            stats.bits += current.bit_len() as u64;
+            stats.refs += current.references().len() as u32;
+            stats.depth = stats.depth.max(depth);
+            stack.extend(current.references().iter().map(| r | (r.as_ref(), depth + 1)));
+        }
+
+        stats
+    }
+}
+
+/// Represents a spread message built without sending it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpreadPreview {
+    pub query_id: u64,
+    /// The body of the spread message as a base64 BOC.
+    pub boc: String,
+    /// Hex hash of the root cell of the body.
+    pub hash: String,
+    pub stats: CellStats,
+    pub recipients: usize,
+    /// Nanotons forwarded to the recipients.
+    pub total_amount: Nanotons,
+    /// Nanotons attached for gas on top of the total.
+    pub gas: Nanotons,
+    pub fees: SpreadFees,
+    /// Nanotons the wallet attaches to the message, the total plus gas.
+    pub total_with_fees: Nanotons
+}
+
+/// Represents the address a fork deploys its child to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChildAddress {
+    pub parent: String,
+    pub query_id: u64,
+    /// Address of the child in the user-friendly form.
+    pub address: String,
+    /// Address of the child in the raw `workchain:hash` form.
+    pub raw_address: String,
+    /// Hex hash of the state init of the child, the hash part of its address.
+    pub state_init_hash: String,
+    /// Hex hash of the code the child runs, the code of the parent.
+    pub code_hash: Option<String>,
+    /// Whether the child is already deployed.
+    pub active: bool
+}
+
+/// Represents the query of a fork, optionally with the query id to send it with.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct ForkQuery {
+    pub contract: Option<String>,
+    /// Query id of the fork, the current time if omitted. Pass the one a child address
+    /// was derived with to deploy the child there.
+    #[validate(range(min = 1))]
+    pub query_id: Option<u64>
+}
+
+/// Represents the query deriving the address of a fork child.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct ChildAddressQuery {
+    pub contract: Option<String>,
+    #[validate(range(min = 1))]
+    pub query_id: u64
+}
+
+/// Represents the balances of the gas wallet and the mixer contract in nanotons.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Balances {
+    pub wallet: i64,
+    pub contract: i64
+}
+
+/// Represents the balance of one account of the mixer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountBalance {
+    pub address: String,
+    /// `wallet` for the wallets of the accounts, `root` for contracts that were not forked, `fork` otherwise.
+    pub role: String,
+    /// Nanotons held by the account, `None` if the balance can not be fetched.
+    pub balance: Option<i64>,
+    /// Balances of the selected jettons.
+    pub jettons: Vec<jettons::JettonBalance>,
+    /// Why a balance is missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
+}
+
+/// Represents the funds held by the gas wallet and every tracked contract.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AggregateBalance {
+    /// Nanotons held by all accounts whose balance could be fetched.
+    pub total: i64,
+    /// Totals of the selected jettons.
+    pub jettons: Vec<jettons::JettonTotal>,
+    pub accounts: Vec<AccountBalance>
+}
+
+/// Represents the query parameters of the aggregate balance.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct AggregateBalanceQuery {
+    /// Comma separated jetton masters to sum, `all` for every configured one, none if omitted.
+    #[validate(length(max = 2048))]
+    pub jettons: Option<String>
+}
+
+/// Represents the outcome of a dust consolidation pass.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Consolidation {
+    /// Forks whose leftovers were collected into the gas wallet.
+    pub forks: Vec<String>,
+    /// Nanotons moved from the gas wallet into the mixer contract.
+    pub wallet_amount: u64,
+    pub receipts: Vec<OperationReceipt>
+}
+
+/// Represents the query parameters for transaction lookups.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionQuery {
+    pub address: Option<String>
+}
+
+/// Represents the query parameters for paging contract transactions.
+///
+/// `lt` and `hash` point to the transaction the page starts from and are
+/// taken from the `next_lt`/`next_hash` fields of the previous page.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionsPageQuery {
+    pub address: Option<String>,
+    pub lt: Option<i64>,
+    pub hash: Option<String>,
+    pub limit: Option<usize>
 }
 
 /// Represents the data for a collect message.
@@ -108,12 +560,16 @@ pub struct CollectMessageData {
     pub amount: Option<BigUint>
 }
 
+/// Opcodes of the mixer operations, hashed from their method names on first use.
+static OPCODES: OnceLock<MixerOpcodes> = OnceLock::new();
+
 /// Represents the opcodes for mixer operations.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct MixerOpcodes {
     pub spread: u32,
     pub collect: u32,
-    pub fork: u32
+    pub fork: u32,
+    pub upgrade: u32
 }
 
 /// Generates an opcode for a given method name.
@@ -124,16 +580,78 @@ fn generate_opcode(method_name: &str) -> u32 {
 }
 
 impl MixerOpcodes {
-    /// Creates a new MixerOpcodes instance with generated opcodes.
-    pub fn new() -> MixerOpcodes {
-        MixerOpcodes {
-            spread: generate_opcode("op::spread"),
-            collect: generate_opcode("op::collect"),
-            fork: generate_opcode("op::fork")
+    /// Returns the opcodes, generating them on the first call.
+    pub fn get() -> &'static MixerOpcodes {
+        OPCODES.get_or_init(|| MixerOpcodes {
+            spread: generate_opcode(MixerOp::Spread.method()),
+            collect: generate_opcode(MixerOp::Collect.method()),
+            fork: generate_opcode(MixerOp::Fork.method()),
+            upgrade: generate_opcode(MixerOp::Upgrade.method())
+        })
+    }
+
+    /// Returns the opcodes as `0x` prefixed hex, keyed by operation.
+    pub fn hex(&self) -> BTreeMap<&'static str, String> {
+        [
+            (MixerOp::Spread, self.spread),
+            (MixerOp::Collect, self.collect),
+            (MixerOp::Fork, self.fork),
+            (MixerOp::Upgrade, self.upgrade)
+        ].into_iter().map(| (op, code) | (op.name(), format!("{:#010x}", code))).collect()
+    }
+}
+
+/// Represents a mixer operation, the typed form of its opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MixerOp {
+    Spread,
+    Collect,
+    Fork,
+    Upgrade
+}
+
+impl MixerOp {
+    /// Returns the name of the operation, e.g. `spread`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            MixerOp::Spread => "spread",
+            MixerOp::Collect => "collect",
+            MixerOp::Fork => "fork",
+            MixerOp::Upgrade => "upgrade"
+        }
+    }
+
+    /// Returns the method name of the contract the opcode is the CRC32 of, e.g. `op::spread`.
+    pub const fn method(self) -> &'static str {
+        match self {
+            MixerOp::Spread => "op::spread",
+            MixerOp::Collect => "op::collect",
+            MixerOp::Fork => "op::fork",
+            MixerOp::Upgrade => "op::upgrade"
+        }
+    }
+
+    /// Returns the opcode of the operation.
+    pub fn code(self) -> u32 {
+        let opcodes: &MixerOpcodes = MixerOpcodes::get();
+
+        match self {
+            MixerOp::Spread => opcodes.spread,
+            MixerOp::Collect => opcodes.collect,
+            MixerOp::Fork => opcodes.fork,
+            MixerOp::Upgrade => opcodes.upgrade
         }
     }
 }
 
+/// Represents the opcodes returned by the API, in decimal and in hex.
+#[derive(Serialize, Debug)]
+pub struct OpcodeList {
+    #[serde(flatten)]
+    pub opcodes: MixerOpcodes,
+    pub hex: BTreeMap<&'static str, String>
+}
+
 /// Represents the collection modes for the mixer.
 #[derive(Serialize, Deserialize)]
 pub struct MixerCollectionModes {
@@ -172,25 +690,52 @@ impl ForkMessage {
     /// Builds the fork message cell.
     pub fn build(&self) -> Cell {
         let mut mess_builder: CellBuilder = CellBuilder::new();
-        mess_builder.store_u32(32, MixerOpcodes::new().fork).unwrap(); //operation
+        mess_builder.store_u32(32, MixerOp::Fork.code()).unwrap(); //operation
         mess_builder.store_u64(64, self.timestamp).unwrap(); //query_id
 
         return mess_builder.build().unwrap(); 
     }
 }
 
+/// Represents an upgrade message replacing the code of the mixer contract.
+#[derive(Clone)]
+pub struct UpgradeMessage {
+    pub timestamp: u64,
+    pub code: Cell
+}
+
+impl UpgradeMessage {
+    /// Creates a new UpgradeMessage instance.
+    pub fn new(timestamp: u64, code: Cell) -> Self {
+        UpgradeMessage {
+            timestamp,
+            code
+        }
+    }
+
+    /// Builds the upgrade message cell, the new code is its only reference.
+    pub fn build(&self) -> Cell {
+        let mut mess_builder: CellBuilder = CellBuilder::new();
+        mess_builder.store_u32(32, MixerOp::Upgrade.code()).unwrap(); //operation
+        mess_builder.store_u64(64, self.timestamp).unwrap(); //query_id
+        mess_builder.store_reference(&ArcCell::new(self.code.clone())).unwrap(); //new code
+
+        return mess_builder.build().unwrap();
+    }
+}
+
 /// Represents a spread message.
 #[derive(Clone)]
 pub struct SpreadMessage {
     pub mode: u8,
     pub timestamp: u64,
-    pub amount: u64,
+    pub amount: Nanotons,
     pub data: Cell
 }
 
 impl SpreadMessage {
     /// Creates a new SpreadMessage instance.
-    pub fn new(mode: u8, timestamp: u64, amount: u64,  data: Cell) -> Self {
+    pub fn new(mode: u8, timestamp: u64, amount: Nanotons,  data: Cell) -> Self {
         SpreadMessage {
             mode,
             timestamp,
@@ -202,9 +747,9 @@ impl SpreadMessage {
     /// Builds the spread message cell.
     pub fn build(&self) -> Cell {
         let mut mess_builder: CellBuilder = CellBuilder::new();
-        mess_builder.store_u32(32, MixerOpcodes::new().spread).unwrap(); //operation
+        mess_builder.store_u32(32, MixerOp::Spread.code()).unwrap(); //operation
         mess_builder.store_u64(64, self.timestamp).unwrap(); //query_id
-        mess_builder.store_u64(64, self.amount).unwrap(); //total amount of coins
+        mess_builder.store_u64(64, self.amount.get()).unwrap(); //total amount of coins
         mess_builder.store_u8(8, self.mode).unwrap(); //spread mode
 
         mess_builder.store_bit(true).unwrap();
@@ -238,7 +783,7 @@ impl CollectMessage {
     /// Builds the collect message cell.
     pub fn build(&self) -> Result<Cell, String> {
         let mut mess_builder: CellBuilder = CellBuilder::new();
-        mess_builder.store_u32(32, MixerOpcodes::new().collect).unwrap(); //operation
+        mess_builder.store_u32(32, MixerOp::Collect.code()).unwrap(); //operation
         mess_builder.store_u64(64, self.timestamp).unwrap(); //query_id
         mess_builder.store_u8(8, self.mode).unwrap(); //spread mode
 
@@ -261,21 +806,239 @@ impl CollectMessage {
     }
 }
 
-/// Creates an external signed message for a TON wallet.
-pub fn create_external_singed_message(user_wallet: TonWallet, seqno: u32, destination_address: TonAddress, amount: u64, now: u64, body_payload: Cell) -> Vec<u8> {
-    //create external message
-    let transfer = TransferMessage::new(
-        &destination_address, 
-        &BigUint::from(amount)
-    ).with_data(body_payload)
-        .build()
-        .unwrap();
+/// Maximum number of internal messages a V4R2 wallet sends from one external message.
+pub const MAX_WALLET_MESSAGES: usize = 4;
+
+/// Send mode flag paying the forward fees from the wallet balance rather than the amount.
+pub const SEND_PAY_FEES_SEPARATELY: u8 = 1;
+
+/// Send mode flag skipping a message that fails in the action phase instead of failing all of them.
+pub const SEND_IGNORE_ERRORS: u8 = 2;
+
+/// Send mode flag destroying the wallet once its balance is zero.
+pub const SEND_DESTROY_IF_ZERO: u8 = 32;
 
-    let msg_arc: Vec<Arc<Cell>> = vec![transfer].into_iter().map(Arc::new).collect();
-    let body: Cell = user_wallet.create_external_body(now as u32 + 60, seqno, msg_arc).unwrap();
-    let signed: Cell = user_wallet.sign_external_body(&body).unwrap();
+/// Send mode flag sending the whole remaining balance of the wallet, whatever the amount.
+pub const SEND_CARRY_ALL_BALANCE: u8 = 128;
+
+/// Send mode of wallet transfers unless a caller asks for another one.
+pub const DEFAULT_SEND_MODE: u8 = SEND_PAY_FEES_SEPARATELY | SEND_IGNORE_ERRORS;
+
+/// Returns whether a send mode combines the flags a wallet transfer may use sensibly.
+///
+/// Only the flags above are allowed. Destroying the wallet only happens along with sending
+/// all of its balance, and fees paid separately make no sense when the whole balance goes.
+pub fn safe_send_mode(mode: u8) -> bool {
+    let known: u8 = SEND_PAY_FEES_SEPARATELY | SEND_IGNORE_ERRORS | SEND_DESTROY_IF_ZERO | SEND_CARRY_ALL_BALANCE;
+    let carry_all: bool = mode & SEND_CARRY_ALL_BALANCE != 0;
+
+    mode & !known == 0
+        && (carry_all || mode & SEND_DESTROY_IF_ZERO == 0)
+        && !(carry_all && mode & SEND_PAY_FEES_SEPARATELY != 0)
+}
+
+/// Represents a single internal transfer sent from the wallet.
+#[derive(Clone)]
+pub struct WalletTransfer {
+    pub destination: TonAddress,
+    pub amount: BigUint,
+    pub body: Option<Cell>,
+    /// Send mode of the message, `DEFAULT_SEND_MODE` unless a caller chose another one.
+    pub mode: u8
+}
+
+/// Represents a signed external message ready to be broadcast.
+///
+/// In gasless mode `boc` holds the signed internal request handed to the relayer
+/// instead. `normalized_hash` is then computed over the request as if it was sent
+/// as an external message, so it is found the same way in the relayed message.
+pub struct SignedExternalMessage {
+    pub boc: Vec<u8>,
+    pub normalized_hash: TonHash
+}
+
+/// Computes the normalized hash of an external-in message (TEP-467).
+///
+/// The message is rebuilt with an empty source, zero import fee, no state init
+/// and the body stored in a reference, so the hash does not depend on how the
+/// sender chose to serialize those fields.
+pub fn normalized_message_hash(destination: &TonAddress, body: &Cell) -> TonHash {
+    let mut builder: CellBuilder = CellBuilder::new();
+    builder.store_u8(2, 0b10).unwrap(); //ext_in_msg_info
+    builder.store_u8(2, 0b00).unwrap(); //src: addr_none
+    builder.store_address(destination).unwrap(); //dest
+    builder.store_coins(&BigUint::from(0u32)).unwrap(); //import_fee
+    builder.store_bit(false).unwrap(); //no state init
+    builder.store_bit(true).unwrap(); //body in reference
+    builder.store_reference(&ArcCell::new(body.clone())).unwrap();
+
+    builder.build().unwrap().cell_hash()
+}
+
+/// Creates an external signed message carrying up to `MAX_WALLET_MESSAGES` internal transfers.
+///
+/// The body is built locally and only its hash is handed to the signer.
+///
+/// # Returns
+///
+/// The signed message, or an error if more transfers are passed than the wallet can send
+/// at once or the signer failed.
+pub async fn create_external_signed_multi_message(user_wallet: TonWallet, signer: &dyn Signer, seqno: u32, transfers: Vec<WalletTransfer>, valid_until: u64) -> Result<SignedExternalMessage, String> {
+    if transfers.len() > MAX_WALLET_MESSAGES {
+        return Err(format!("the wallet can send at most {} messages at once, {} were passed", MAX_WALLET_MESSAGES, transfers.len()));
+    }
+
+    //create internal messages
+    let modes: Vec<u8> = transfers.iter().map(| t | t.mode).collect();
+    let msg_arc: Vec<Arc<Cell>> = transfers.into_iter().map(| t | {
+        let mut message: TransferMessage = TransferMessage::new(&t.destination, &t.amount);
+        if let Some(body) = t.body {
+            message.with_data(body);
+        }
+
+        Arc::new(message.build().unwrap())
+    }).collect();
+
+    //create external message, the wallet builder sends every message with the default mode
+    let body: Cell = if modes.iter().all(| m | *m == DEFAULT_SEND_MODE) {
+        user_wallet.create_external_body(valid_until as u32, seqno, msg_arc).unwrap()
+    } else {
+        external_body(&user_wallet, seqno, modes.into_iter().zip(msg_arc).collect(), valid_until)?
+    };
+    let signature: Vec<u8> = signer.sign(&body.cell_hash()).await?;
+
+    //W5 expects the signature after the request, older wallets before it
+    let mut builder: CellBuilder = CellBuilder::new();
+    match user_wallet.version {
+        WalletVersion::V5R1 => {
+            builder.store_cell(&body).unwrap();
+            builder.store_slice(&signature).unwrap();
+        },
+        _ => {
+            builder.store_slice(&signature).unwrap();
+            builder.store_cell(&body).unwrap();
+        }
+    }
+    let signed: Cell = builder.build().unwrap();
+
+    let normalized_hash: TonHash = normalized_message_hash(&user_wallet.address, &signed);
     let wrapped: Cell = user_wallet.wrap_signed_body(signed, true).unwrap();
     let boc: BagOfCells = BagOfCells::from_root(wrapped);
 
-    boc.serialize(true).unwrap()
-}
\ No newline at end of file
+    Ok(SignedExternalMessage {
+        boc: boc.serialize(true).unwrap(),
+        normalized_hash
+    })
+}
+
+/// Opcode of W5 requests signed for delivery in an internal message ("sint").
+const W5_SIGNED_INTERNAL: u32 = 0x73696e74;
+
+/// Opcode of W5 requests signed for delivery in an external message ("sign").
+const W5_SIGNED_EXTERNAL: u32 = 0x7369676e;
+
+/// Opcode of the W5 out action sending a message.
+const W5_ACTION_SEND_MSG: u32 = 0x0ec3c86d;
+
+/// Builds the W5 out-action list sending the messages, every action keeping a reference to the previous one.
+fn w5_actions(messages: Vec<(u8, Arc<Cell>)>) -> Cell {
+    let mut actions: Cell = CellBuilder::new().build().unwrap();
+    for (mode, message) in messages {
+        let mut builder: CellBuilder = CellBuilder::new();
+        builder.store_reference(&ArcCell::new(actions)).unwrap();
+        builder.store_u32(32, W5_ACTION_SEND_MSG).unwrap();
+        builder.store_u8(8, mode).unwrap();
+        builder.store_reference(&message).unwrap();
+        actions = builder.build().unwrap();
+    }
+
+    actions
+}
+
+/// Builds the unsigned body of an external message sending every message with its own mode.
+///
+/// The wallet builder sends all messages with `DEFAULT_SEND_MODE`, so bodies carrying
+/// other modes are laid out here, for the wallet versions the mixer runs.
+///
+/// # Returns
+///
+/// The body, or an error for a wallet version other than v4r2 and v5r1.
+fn external_body(user_wallet: &TonWallet, seqno: u32, messages: Vec<(u8, Arc<Cell>)>, valid_until: u64) -> Result<Cell, String> {
+    let mut builder: CellBuilder = CellBuilder::new();
+
+    match user_wallet.version {
+        WalletVersion::V4R2 => {
+            builder.store_u32(32, user_wallet.wallet_id as u32).unwrap();
+            builder.store_u32(32, valid_until as u32).unwrap();
+            builder.store_u32(32, seqno).unwrap();
+            builder.store_u8(8, 0).unwrap(); //simple send
+            for (mode, message) in messages {
+                builder.store_u8(8, mode).unwrap();
+                builder.store_reference(&message).unwrap();
+            }
+        },
+        WalletVersion::V5R1 => {
+            builder.store_u32(32, W5_SIGNED_EXTERNAL).unwrap();
+            builder.store_u32(32, user_wallet.wallet_id as u32).unwrap();
+            builder.store_u32(32, valid_until as u32).unwrap();
+            builder.store_u32(32, seqno).unwrap();
+            builder.store_bit(true).unwrap(); //out actions in reference
+            builder.store_reference(&ArcCell::new(w5_actions(messages))).unwrap();
+            builder.store_bit(false).unwrap(); //no extended actions
+        },
+        _ => return Err(String::from("send modes other than the default need a v4r2 or v5r1 wallet"))
+    }
+
+    Ok(builder.build().unwrap())
+}
+
+/// Creates a W5 request signed for delivery in an internal message, for gasless relaying.
+///
+/// The request carries the transfers as an out-action list and the signature over the
+/// hash of the unsigned request in its last 512 bits, as W5 expects it.
+///
+/// # Returns
+///
+/// The signed request, or an error if more transfers are passed than the wallet can send
+/// at once or the signer failed.
+pub async fn create_signed_internal_message(user_wallet: &TonWallet, signer: &dyn Signer, seqno: u32, transfers: Vec<WalletTransfer>, valid_until: u64) -> Result<SignedExternalMessage, String> {
+    if transfers.len() > MAX_WALLET_MESSAGES {
+        return Err(format!("the wallet can send at most {} messages at once, {} were passed", MAX_WALLET_MESSAGES, transfers.len()));
+    }
+
+    //create out-action list
+    let actions: Cell = w5_actions(transfers.into_iter().map(| t | {
+        let mut message: TransferMessage = TransferMessage::new(&t.destination, &t.amount);
+        if let Some(body) = t.body {
+            message.with_data(body);
+        }
+
+        (t.mode, Arc::new(message.build().unwrap()))
+    }).collect());
+
+    let request = | signature: Option<&[u8]> | -> Cell {
+        let mut builder: CellBuilder = CellBuilder::new();
+        builder.store_u32(32, W5_SIGNED_INTERNAL).unwrap();
+        builder.store_u32(32, user_wallet.wallet_id as u32).unwrap();
+        builder.store_u32(32, valid_until as u32).unwrap();
+        builder.store_u32(32, seqno).unwrap();
+        builder.store_bit(true).unwrap(); //out actions in reference
+        builder.store_reference(&ArcCell::new(actions.clone())).unwrap();
+        builder.store_bit(false).unwrap(); //no extended actions
+        if let Some(signature) = signature {
+            builder.store_slice(signature).unwrap();
+        }
+
+        builder.build().unwrap()
+    };
+
+    let unsigned: Cell = request(None);
+    let signature: Vec<u8> = signer.sign(&unsigned.cell_hash()).await?;
+    let signed: Cell = request(Some(&signature));
+    let normalized_hash: TonHash = normalized_message_hash(&user_wallet.address, &signed);
+
+    Ok(SignedExternalMessage {
+        boc: BagOfCells::from_root(signed).serialize(true).unwrap(),
+        normalized_hash
+    })
+}
@@ -0,0 +1,53 @@
+//! # Multisig Types
+//!
+//! This module defines the orders collects are proposed as when they are routed
+//! through a multisig wallet.
+
+use serde::{Serialize, Deserialize};
+use validator::Validate;
+
+use super::{connect::TonConnectRequest, OperationReceipt};
+
+/// Represents a collect proposed to the multisig.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultisigOrder {
+    pub multisig: String,
+    pub order_seqno: u64,
+    /// The order contract signers approve.
+    pub order_address: String,
+    /// Query id of the collect the order executes.
+    pub query_id: u64,
+    pub expires_at: u64,
+    /// The message of the hot wallet creating the order.
+    pub receipt: OperationReceipt
+}
+
+/// Represents the approval state of an order.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultisigOrderStatus {
+    pub order_seqno: u64,
+    pub order_address: String,
+    /// Whether the order contract is deployed, i.e. the order was created.
+    pub created: bool,
+    pub threshold: Option<u32>,
+    /// Indices of the signers that approved the order.
+    pub approved_by: Vec<u8>,
+    /// Whether the order reached its threshold and the collect was sent.
+    pub executed: bool,
+    pub expires_at: Option<u64>
+}
+
+/// Represents the payload asking for the approval request of a signer.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct MultisigApprovePayload {
+    /// Index of the signer in the multisig.
+    pub signer_index: u8
+}
+
+/// Represents the TON Connect request a signer approves an order with.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultisigApproval {
+    pub order_address: String,
+    pub signer_index: u8,
+    pub request: TonConnectRequest
+}
@@ -0,0 +1,274 @@
+//! # Nanoton Amounts
+//!
+//! This module defines `Nanotons`, the amount type of TON used by the services and the
+//! TON module, so amounts are converted from TON in one place and their arithmetic
+//! can not silently overflow.
+
+use std::{fmt, iter::Sum, str::FromStr};
+
+use num_bigint::BigUint;
+use serde::{de::{self, Visitor}, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Number of nanotons in one TON.
+pub const NANOTONS_PER_TON: u64 = 1_000_000_000;
+
+/// Largest amount in TON accepted in payloads, so it always fits in `Nanotons`.
+pub const MAX_TON: f64 = 18_000_000_000.0;
+
+/// Number of decimal places of a TON amount.
+const TON_DECIMALS: usize = 9;
+
+/// Represents an amount of TON in nanotons.
+///
+/// Serialized as an integer, and deserialized from an integer or a string of digits,
+/// as clients in languages without 64-bit integers send large amounts as strings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Nanotons(u64);
+
+impl Nanotons {
+    /// No nanotons.
+    pub const ZERO: Nanotons = Nanotons(0);
+
+    /// Converts an amount in TON to nanotons, rounding to the nearest nanoton.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the amount is negative, not finite or too large.
+    pub fn from_ton(ton: f64) -> Result<Nanotons, String> {
+        let nano: f64 = (ton * NANOTONS_PER_TON as f64).round();
+
+        if !nano.is_finite() || nano < 0.0 || nano >= u64::MAX as f64 {
+            return Err(format!("{} is not a valid amount of TON", ton));
+        }
+
+        Ok(Nanotons(nano as u64))
+    }
+
+    /// Parses an amount in TON written as a decimal, e.g. `1.25`, without rounding.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the amount is not a non-negative decimal with at most nine
+    /// decimal places, or is too large.
+    pub fn parse_ton(ton: &str) -> Result<Nanotons, String> {
+        let invalid = || format!("`{}` is not a valid amount of TON", ton);
+        let (whole, fraction): (&str, &str) = ton.trim().split_once('.').unwrap_or((ton.trim(), ""));
+
+        if (whole.is_empty() && fraction.is_empty()) || fraction.len() > TON_DECIMALS
+            || !whole.bytes().chain(fraction.bytes()).all(| b | b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        let whole: u64 = if whole.is_empty() { 0 } else { whole.parse::<u64>().map_err(|_| invalid())? };
+        let fraction: u64 = format!("{:0<width$}", fraction, width = TON_DECIMALS).parse::<u64>().map_err(|_| invalid())?;
+
+        whole.checked_mul(NANOTONS_PER_TON)
+            .and_then(| n | n.checked_add(fraction))
+            .map(Nanotons)
+            .ok_or_else(invalid)
+    }
+
+    /// Converts a signed amount, e.g. a balance read from the database, clamping negative amounts to zero.
+    pub fn from_signed(nanotons: i64) -> Nanotons {
+        Nanotons(nanotons.max(0) as u64)
+    }
+
+    /// Returns the amount in nanotons.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the amount in nanotons as the signed integer of the database, saturating at `i64::MAX`.
+    pub fn signed(self) -> i64 {
+        i64::try_from(self.0).unwrap_or(i64::MAX)
+    }
+
+    /// Returns the amount in TON, which may lose precision above 2^53 nanotons.
+    pub fn to_ton(self) -> f64 {
+        self.0 as f64 / NANOTONS_PER_TON as f64
+    }
+
+    /// Returns whether the amount is zero.
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Adds two amounts, `None` on overflow.
+    pub fn checked_add(self, other: Nanotons) -> Option<Nanotons> {
+        self.0.checked_add(other.0).map(Nanotons)
+    }
+
+    /// Subtracts an amount, `None` if it is larger than this one.
+    pub fn checked_sub(self, other: Nanotons) -> Option<Nanotons> {
+        self.0.checked_sub(other.0).map(Nanotons)
+    }
+
+    /// Subtracts an amount, zero if it is larger than this one.
+    pub fn saturating_sub(self, other: Nanotons) -> Nanotons {
+        Nanotons(self.0.saturating_sub(other.0))
+    }
+
+    /// Multiplies the amount, `None` on overflow.
+    pub fn checked_mul(self, factor: u64) -> Option<Nanotons> {
+        self.0.checked_mul(factor).map(Nanotons)
+    }
+
+    /// Sums amounts, `None` on overflow.
+    pub fn checked_sum<'a>(amounts: impl IntoIterator<Item = &'a Nanotons>) -> Option<Nanotons> {
+        amounts.into_iter().try_fold(Nanotons::ZERO, | total, amount | total.checked_add(*amount))
+    }
+}
+
+impl From<u64> for Nanotons {
+    fn from(nanotons: u64) -> Nanotons {
+        Nanotons(nanotons)
+    }
+}
+
+impl From<Nanotons> for u64 {
+    fn from(amount: Nanotons) -> u64 {
+        amount.0
+    }
+}
+
+impl From<Nanotons> for BigUint {
+    fn from(amount: Nanotons) -> BigUint {
+        BigUint::from(amount.0)
+    }
+}
+
+impl<'a> Sum<&'a Nanotons> for Nanotons {
+    /// Sums amounts, saturating instead of overflowing. Use `checked_sum` for amounts of clients.
+    fn sum<I: Iterator<Item = &'a Nanotons>>(iter: I) -> Nanotons {
+        Nanotons(iter.fold(0u64, | total, amount | total.saturating_add(amount.0)))
+    }
+}
+
+impl FromStr for Nanotons {
+    type Err = String;
+
+    /// Parses a string of digits in nanotons. Use `parse_ton` for amounts in TON.
+    fn from_str(nanotons: &str) -> Result<Nanotons, String> {
+        nanotons.trim().parse::<u64>()
+            .map(Nanotons)
+            .map_err(|_| format!("`{}` is not a valid amount of nanotons", nanotons))
+    }
+}
+
+impl fmt::Display for Nanotons {
+    /// Formats the amount in TON without losing precision, e.g. `1.25` for 1250000000 nanotons.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let whole: u64 = self.0 / NANOTONS_PER_TON;
+        let fraction: u64 = self.0 % NANOTONS_PER_TON;
+
+        if fraction == 0 {
+            return write!(f, "{}", whole);
+        }
+
+        let fraction: String = format!("{:0width$}", fraction, width = TON_DECIMALS);
+        write!(f, "{}.{}", whole, fraction.trim_end_matches('0'))
+    }
+}
+
+impl Serialize for Nanotons {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Nanotons {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Nanotons, D::Error> {
+        struct NanotonsVisitor;
+
+        impl<'de> Visitor<'de> for NanotonsVisitor {
+            type Value = Nanotons;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an amount of nanotons as an integer or a string of digits")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Nanotons, E> {
+                Ok(Nanotons(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Nanotons, E> {
+                u64::try_from(value).map(Nanotons).map_err(|_| E::custom("an amount of nanotons can not be negative"))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Nanotons, E> {
+                value.parse::<Nanotons>().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(NanotonsVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_ton_rounds_to_the_nearest_nanoton() {
+        assert_eq!(Nanotons::from_ton(1.25).unwrap(), Nanotons(1_250_000_000));
+        assert_eq!(Nanotons::from_ton(0.0).unwrap(), Nanotons::ZERO);
+        assert_eq!(Nanotons::from_ton(1.0000000004).unwrap(), Nanotons(1_000_000_000));
+        assert_eq!(Nanotons::from_ton(1.0000000006).unwrap(), Nanotons(1_000_000_001));
+        assert_eq!(Nanotons::from_ton(MAX_TON).unwrap(), Nanotons(18_000_000_000 * NANOTONS_PER_TON));
+    }
+
+    #[test]
+    fn from_ton_rejects_invalid_amounts() {
+        for ton in [-0.1, -1.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 19_000_000_000.0, f64::MAX] {
+            assert!(Nanotons::from_ton(ton).is_err(), "{} was accepted", ton);
+        }
+    }
+
+    #[test]
+    fn parse_ton_is_exact() {
+        assert_eq!(Nanotons::parse_ton("1.25").unwrap(), Nanotons(1_250_000_000));
+        assert_eq!(Nanotons::parse_ton("0.000000001").unwrap(), Nanotons(1));
+        assert_eq!(Nanotons::parse_ton(".5").unwrap(), Nanotons(500_000_000));
+        assert_eq!(Nanotons::parse_ton("5.").unwrap(), Nanotons(5_000_000_000));
+        assert_eq!(Nanotons::parse_ton(" 2 ").unwrap(), Nanotons(2_000_000_000));
+        assert_eq!(Nanotons::parse_ton("18446744073.709551615").unwrap(), Nanotons(u64::MAX));
+    }
+
+    #[test]
+    fn parse_ton_rejects_invalid_amounts() {
+        let amounts: [&str; 10] = ["", ".", "-1", "+1", "1.0000000001", "1e9", "1.2.3", "0x10", "18446744073.709551616", "99999999999999999999"];
+
+        for ton in amounts {
+            assert!(Nanotons::parse_ton(ton).is_err(), "`{}` was accepted", ton);
+        }
+    }
+
+    #[test]
+    fn display_round_trips_through_parse_ton() {
+        for nanotons in [0, 1, 1_250_000_000, 5_000_000_000, u64::MAX] {
+            let amount: Nanotons = Nanotons(nanotons);
+            assert_eq!(Nanotons::parse_ton(&amount.to_string()).unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn serializes_as_an_integer() {
+        assert_eq!(serde_json::to_string(&Nanotons(u64::MAX)).unwrap(), u64::MAX.to_string());
+    }
+
+    #[test]
+    fn deserializes_integers_and_strings_of_digits() {
+        assert_eq!(serde_json::from_str::<Nanotons>("1500000000").unwrap(), Nanotons(1_500_000_000));
+        assert_eq!(serde_json::from_str::<Nanotons>("\"1500000000\"").unwrap(), Nanotons(1_500_000_000));
+        assert_eq!(serde_json::from_str::<Nanotons>("\"18446744073709551615\"").unwrap(), Nanotons(u64::MAX));
+    }
+
+    #[test]
+    fn rejects_negative_decimal_and_overflowing_input() {
+        let inputs: [&str; 7] = ["-1", "\"-1\"", "1.5", "\"1.5\"", "\"18446744073709551616\"", "18446744073709551616", "null"];
+
+        for input in inputs {
+            assert!(serde_json::from_str::<Nanotons>(input).is_err(), "`{}` was accepted", input);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! # Notification Types
+//!
+//! This module defines the types used to configure notification routing.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+/// Names of the channels notifications can be routed to.
+pub const CHANNEL_NAMES: [&str; 5] = ["webhook", "telegram", "email", "slack", "discord"];
+
+/// Represents a single event to channel route.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct NotificationRoute {
+    pub event: String,
+    pub channel: String
+}
+
+/// Represents the payload replacing the channels of an event.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct NotificationRoutePayload {
+    #[validate(length(min = 1), custom(function = "validate_channels"))]
+    pub channels: Vec<String>
+}
+
+/// Checks that every channel is one of `CHANNEL_NAMES`.
+fn validate_channels(channels: &Vec<String>) -> Result<(), ValidationError> {
+    if let Some(unknown) = channels.iter().find(| c | !CHANNEL_NAMES.contains(&c.as_str())) {
+        let mut error: ValidationError = ValidationError::new("channel");
+        error.message = Some(format!("unknown channel `{}`, expected one of {:?}", unknown, CHANNEL_NAMES).into());
+        return Err(error);
+    }
+
+    Ok(())
+}
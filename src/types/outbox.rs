@@ -0,0 +1,23 @@
+//! # Outbox Types
+//!
+//! This module defines the records of the outbox signed external messages are written to before broadcasting.
+
+pub use mixer_types::outbox::{OperationLookup, OutboxEntry};
+
+/// The message is stored but was not broadcast yet.
+pub const OUTBOX_PENDING: &str = "pending";
+
+/// The message was accepted by a liteserver.
+pub const OUTBOX_SENT: &str = "sent";
+
+/// The wallet seqno advanced past the message, so it was applied.
+pub const OUTBOX_CONFIRMED: &str = "confirmed";
+
+/// The message expired before the wallet seqno advanced past it.
+pub const OUTBOX_EXPIRED: &str = "expired";
+
+/// The request sending the message hit its deadline before it was known to be broadcast.
+///
+/// The outbox task still confirms or expires the entry, it is only never re-broadcast.
+pub const OUTBOX_UNKNOWN: &str = "unknown";
+
@@ -0,0 +1,27 @@
+//! # Rate Types
+//!
+//! This module defines the exchange rates used to denominate amounts in fiat.
+
+use serde::{Serialize, Deserialize};
+
+use super::nanotons::Nanotons;
+
+/// Represents the price of one TON in USD.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rate {
+    pub pair: String,
+    pub rate: f64,
+    pub source: String,
+    pub fetched_at: u64
+}
+
+impl Rate {
+    /// Converts an amount in USD to nanotons at this rate.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the amount in TON is not valid, see `Nanotons::from_ton`.
+    pub fn usd_to_nanotons(&self, usd: f64) -> Result<Nanotons, String> {
+        Nanotons::from_ton(usd / self.rate)
+    }
+}
@@ -0,0 +1,90 @@
+//! # Rebalance Types
+//!
+//! This module defines the target ratios fork contracts are rebalanced to, the plan of the
+//! moves and the progress of a rebalance job.
+
+use serde::{Serialize, Deserialize};
+use validator::Validate;
+
+use crate::validation;
+
+use super::OperationReceipt;
+
+/// Surplus contracts are being collected into the gas wallet.
+pub const PHASE_COLLECT: &str = "collect";
+
+/// The job waits for the indexer to record the collects.
+pub const PHASE_SETTLE: &str = "settle";
+
+/// The collected funds are being spread to the contracts below their target.
+pub const PHASE_SPREAD: &str = "spread";
+
+/// Every move was sent.
+pub const PHASE_DONE: &str = "done";
+
+/// Represents the share of the pool a contract should hold.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct RebalanceTarget {
+    #[validate(regex(path = *validation::ADDRESS_RE), custom(function = "validation::validate_address"))]
+    pub contract: String,
+    /// Weight of the contract, the target is its share of the sum of all weights.
+    #[validate(range(exclusive_min = 0.0))]
+    pub weight: f64
+}
+
+/// Represents the payload of a rebalance operation.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct RebalancePayload {
+    #[validate(length(min = 2, max = 64), nested)]
+    pub targets: Vec<RebalanceTarget>,
+    /// Percent of its target a contract may be off by without being touched, 5 if omitted.
+    #[validate(range(min = 0.0, max = 50.0))]
+    pub tolerance: Option<f64>,
+    /// Priority of the rebalance job, `JOB_PRIORITY_LOW` if omitted.
+    #[validate(range(min = 0, max = 3))]
+    pub priority: Option<i32>
+}
+
+/// Represents the planned move of one contract, amounts in nanotons.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RebalanceMove {
+    pub contract: String,
+    pub balance: i64,
+    pub target: i64,
+    /// Whether the contract holds a surplus and is collected.
+    pub collect: bool,
+    /// Nanotons spread to the contract after the collects, before scaling to what was received.
+    pub send: u64
+}
+
+/// Represents the moves that bring the contracts to their targets.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RebalancePlan {
+    /// Nanotons held by all target contracts.
+    pub total: i64,
+    pub moves: Vec<RebalanceMove>
+}
+
+impl RebalancePlan {
+    /// Returns whether the plan moves any funds.
+    pub fn is_empty(&self) -> bool {
+        !self.moves.iter().any(| m | m.collect) || !self.moves.iter().any(| m | m.send > 0)
+    }
+}
+
+/// Represents the progress of a rebalance job, stored with the job after every step.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RebalanceProgress {
+    pub phase: String,
+    pub plan: RebalancePlan,
+    /// Nanotons the indexed collects sent out.
+    pub received: Option<u64>,
+    /// Receipts of the sent collects and spread, in order.
+    pub receipts: Vec<OperationReceipt>
+}
+
+/// Represents the payload of a rebalance job.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RebalanceJob {
+    pub plan: RebalancePlan
+}
@@ -0,0 +1,107 @@
+//! # Report Types
+//!
+//! This module defines the types used by the accounting reports built from indexed events.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+
+/// Represents the query parameters of the fee report.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeeReportQuery {
+    pub period: Option<String>,
+    pub format: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>
+}
+
+/// Represents the fee and flow totals of a single report period.
+///
+/// All amounts are in nanotons.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct FeeReportRow {
+    pub period_start: i64,
+    pub transactions: i64,
+    pub gas_fees: i64,
+    pub forward_fees: i64,
+    pub fee_income: i64,
+    pub inflow: i64,
+    pub outflow: i64,
+    pub net_flow: i64
+}
+
+impl FeeReportRow {
+    /// Returns the CSV header matching `FeeReportRow::to_csv`.
+    pub fn csv_header() -> &'static str {
+        "period_start,transactions,gas_fees,forward_fees,fee_income,inflow,outflow,net_flow"
+    }
+
+    /// Converts the row to a CSV line.
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{}",
+            self.period_start,
+            self.transactions,
+            self.gas_fees,
+            self.forward_fees,
+            self.fee_income,
+            self.inflow,
+            self.outflow,
+            self.net_flow
+        )
+    }
+}
+
+/// Represents the query parameters of the statistics endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatsQuery {
+    pub window: Option<String>
+}
+
+/// Returns the length in seconds of a statistics window, `None` unless it is `24h`, `7d` or `30d`.
+pub fn window_seconds(window: &str) -> Option<i64> {
+    match window {
+        "24h" => Some(24 * 60 * 60),
+        "7d" => Some(7 * 24 * 60 * 60),
+        "30d" => Some(30 * 24 * 60 * 60),
+        _ => None
+    }
+}
+
+/// Represents the aggregated counters of one operation type.
+///
+/// Volumes are in nanotons.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct OperationStats {
+    pub op: String,
+    pub count: i64,
+    pub succeeded: i64,
+    pub volume_in: i64,
+    pub volume_out: i64,
+    pub average_legs: f64
+}
+
+/// Represents the statistics of all mixer operations over a window.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MixerStats {
+    pub window: String,
+    pub from: i64,
+    pub to: i64,
+    pub success_rate: f64,
+    pub operations: Vec<OperationStats>
+}
+
+impl MixerStats {
+    /// Creates a new MixerStats instance, computing the overall success rate.
+    pub fn new(window: String, from: i64, to: i64, operations: Vec<OperationStats>) -> Self {
+        let count: i64 = operations.iter().map(| o | o.count).sum();
+        let succeeded: i64 = operations.iter().map(| o | o.succeeded).sum();
+
+        MixerStats {
+            window,
+            from,
+            to,
+            success_rate: if count > 0 { succeeded as f64 / count as f64 } else { 0.0 },
+            operations
+        }
+    }
+}
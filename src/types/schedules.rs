@@ -0,0 +1,198 @@
+//! # Schedule Types
+//!
+//! This module defines the recurring schedules managed with the admin API and the
+//! operation templates they run.
+
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+use crate::{scheduler::cron::Cron, validation};
+
+use super::{nanotons::MAX_TON, rebalance::RebalancePayload, reports, CollectPayload, SpreadWalletPayload, MAX_SPREAD_RECIPIENTS};
+
+/// Represents a recurring schedule.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct Schedule {
+    pub id: i64,
+    pub name: String,
+    /// Cron expression in UTC, e.g. `0 3 * * 1`.
+    pub cron: String,
+    /// Kind of the operation template, e.g. `rebalance`.
+    pub kind: String,
+    /// Payload of the operation template.
+    pub payload: Value,
+    /// Priority of the jobs the schedule submits.
+    pub priority: i32,
+    pub paused: bool,
+    /// Unix time the schedule runs next.
+    pub next_run_at: i64,
+    pub last_run_at: Option<i64>,
+    /// The job submitted by the last run, if it submitted one.
+    pub last_job_id: Option<i64>,
+    /// Error of the last run.
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64
+}
+
+impl Schedule {
+    /// Returns the operation template the schedule runs.
+    pub fn template(&self) -> Result<OperationTemplate, String> {
+        serde_json::from_value(json!({ "kind": self.kind, "payload": self.payload })).map_err(|e| e.to_string())
+    }
+}
+
+/// Represents the payload creating a schedule.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct SchedulePayload {
+    #[validate(length(min = 1, max = 128))]
+    pub name: String,
+    /// Cron expression in UTC, e.g. `0 3 * * 1` for Mondays at 03:00.
+    #[validate(custom(function = "validate_cron"))]
+    pub cron: String,
+    /// The operation run on every occurrence.
+    #[serde(flatten)]
+    #[validate(custom(function = "validate_template"))]
+    pub template: OperationTemplate,
+    /// Priority of the submitted jobs, `JOB_PRIORITY_NORMAL` if omitted.
+    #[validate(range(min = 0, max = 3))]
+    pub priority: Option<i32>,
+    /// Whether the schedule is created paused, `false` if omitted.
+    pub paused: Option<bool>
+}
+
+/// Represents an operation a schedule runs, the `kind` with its `payload`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", content = "payload", rename_all = "snake_case")]
+pub enum OperationTemplate {
+    /// Spreads to fixed recipients, submitted as a spread job.
+    Spread(SpreadTemplate),
+    /// Collects from a contract, submitted as a collect job.
+    Collect(CollectTemplate),
+    /// Forks a contract, submitted as a fork job.
+    Fork(ForkTemplate),
+    /// Plans a rebalance at the time of the run and submits it as a rebalance job.
+    Rebalance(RebalancePayload),
+    /// Stores the mixing statistics of a window as the result of a snapshot job.
+    Snapshot(SnapshotTemplate)
+}
+
+impl OperationTemplate {
+    /// Returns the kind of the template, as stored with the schedule.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            OperationTemplate::Spread(_) => "spread",
+            OperationTemplate::Collect(_) => "collect",
+            OperationTemplate::Fork(_) => "fork",
+            OperationTemplate::Rebalance(_) => "rebalance",
+            OperationTemplate::Snapshot(_) => "snapshot"
+        }
+    }
+
+    /// Returns the payload of the template, as stored with the schedule.
+    pub fn payload(&self) -> Value {
+        match self {
+            OperationTemplate::Spread(t) => serde_json::to_value(t),
+            OperationTemplate::Collect(t) => serde_json::to_value(t),
+            OperationTemplate::Fork(t) => serde_json::to_value(t),
+            OperationTemplate::Rebalance(t) => serde_json::to_value(t),
+            OperationTemplate::Snapshot(t) => serde_json::to_value(t)
+        }.unwrap()
+    }
+}
+
+/// Represents the template of a recurring spread.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+#[validate(schema(function = "validate_spread_template"))]
+pub struct SpreadTemplate {
+    /// The contract to spread through, `MIXER_CONTRACT` if omitted.
+    #[validate(regex(path = *validation::ADDRESS_RE), custom(function = "validation::validate_address"))]
+    pub contract: Option<String>,
+    #[validate(length(min = 1, max = MAX_SPREAD_RECIPIENTS), nested)]
+    pub wallets: Vec<SpreadWalletPayload>,
+    /// TON `percent` amounts are taken of, required if a recipient has a `percent`.
+    #[validate(range(exclusive_min = 0.0, max = MAX_TON))]
+    pub total: Option<f64>
+}
+
+/// Checks that a spread template with `percent` amounts has a `total`.
+fn validate_spread_template(template: &SpreadTemplate) -> Result<(), ValidationError> {
+    if template.total.is_none() && template.wallets.iter().any(| w | w.percent.is_some()) {
+        let mut error: ValidationError = ValidationError::new("total");
+        error.message = Some("a spread with `percent` amounts needs a `total`".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Represents the template of a recurring collect.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct CollectTemplate {
+    /// The contract to collect from, `MIXER_CONTRACT` if omitted.
+    #[validate(regex(path = *validation::ADDRESS_RE), custom(function = "validation::validate_address"))]
+    pub contract: Option<String>,
+    #[validate(nested)]
+    pub collect: CollectPayload
+}
+
+/// Represents the template of a recurring fork.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct ForkTemplate {
+    /// The contract to fork, `MIXER_CONTRACT` if omitted.
+    #[validate(regex(path = *validation::ADDRESS_RE), custom(function = "validation::validate_address"))]
+    pub contract: Option<String>
+}
+
+/// Represents the template of a recurring statistics snapshot.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct SnapshotTemplate {
+    /// The statistics window, `24h` if omitted.
+    #[validate(custom(function = "validate_window"))]
+    pub window: Option<String>
+}
+
+/// Checks that a cron expression parses.
+fn validate_cron(cron: &str) -> Result<(), ValidationError> {
+    if let Err(err) = Cron::from_str(cron) {
+        let mut error: ValidationError = ValidationError::new("cron");
+        error.message = Some(err.into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Checks the payload of an operation template.
+fn validate_template(template: &OperationTemplate) -> Result<(), ValidationError> {
+    let validated = match template {
+        OperationTemplate::Spread(t) => t.validate(),
+        OperationTemplate::Collect(t) => t.validate(),
+        OperationTemplate::Fork(t) => t.validate(),
+        OperationTemplate::Rebalance(t) => t.validate(),
+        OperationTemplate::Snapshot(t) => t.validate()
+    };
+
+    if let Err(errors) = validated {
+        let mut error: ValidationError = ValidationError::new("payload");
+        error.message = Some(errors.to_string().into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Checks that a statistics window is one of `24h`, `7d` or `30d`.
+fn validate_window(window: &str) -> Result<(), ValidationError> {
+    if reports::window_seconds(window).is_none() {
+        let mut error: ValidationError = ValidationError::new("window");
+        error.message = Some("field `window` must be `24h`, `7d` or `30d`".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! # Split Types
+//!
+//! This module defines the payload of a spread built by dividing a total amount evenly
+//! between its recipients.
+
+use serde::{Serialize, Deserialize};
+use validator::{Validate, ValidationError};
+
+use crate::validation;
+
+use super::{nanotons::MAX_TON, MAX_SPREAD_RECIPIENTS};
+
+/// Represents where the nanotons left over by an even division go.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitRemainder {
+    /// One nanoton more to each of the first recipients, so no two shares differ by more than one.
+    #[default]
+    Spread,
+    /// All of the remainder to the first recipient.
+    First,
+    /// All of the remainder to the last recipient.
+    Last
+}
+
+/// Represents the recipient repeated `count` times in a split.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct SplitTemplate {
+    #[validate(custom(function = "validation::validate_recipient"))]
+    pub account: String,
+    #[serde(default)]
+    pub bounce: Option<bool>
+}
+
+/// Represents the payload of a split spread.
+///
+/// The recipients are given either as a list of addresses in `accounts`, or as a `count`
+/// of legs to the recipient of `template`. `bounce` applies to every address of `accounts`.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+#[validate(schema(function = "validate_split_recipients"))]
+pub struct SplitSpreadPayload {
+    /// TON divided between the recipients.
+    #[validate(range(exclusive_min = 0.0, max = MAX_TON))]
+    pub total: f64,
+    #[validate(length(min = 1, max = MAX_SPREAD_RECIPIENTS), custom(function = "validate_split_accounts"))]
+    pub accounts: Option<Vec<String>>,
+    #[validate(range(min = 1, max = MAX_SPREAD_RECIPIENTS))]
+    pub count: Option<u64>,
+    #[validate(nested)]
+    pub template: Option<SplitTemplate>,
+    #[serde(default)]
+    pub bounce: Option<bool>,
+    /// Where the remainder of the division goes, `spread` if omitted.
+    #[serde(default)]
+    pub remainder: SplitRemainder
+}
+
+/// Checks every recipient of a split, given as an address or `@name`.
+fn validate_split_accounts(accounts: &Vec<String>) -> Result<(), ValidationError> {
+    for account in accounts {
+        validation::validate_recipient(account)?;
+    }
+
+    Ok(())
+}
+
+/// Checks that a split has either `accounts`, or both `count` and `template`.
+fn validate_split_recipients(payload: &SplitSpreadPayload) -> Result<(), ValidationError> {
+    let listed: bool = payload.accounts.is_some();
+    let templated: bool = payload.count.is_some() || payload.template.is_some();
+
+    if listed == templated || (templated && (payload.count.is_none() || payload.template.is_none())) {
+        let mut error: ValidationError = ValidationError::new("accounts");
+        error.message = Some("either field `accounts`, or both fields `count` and `template` are required".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,34 @@
+//! # Gas Top-Up Types
+//!
+//! This module defines the audit records of the automatic top-ups of the gas wallet.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+use validator::Validate;
+
+/// Represents a top-up of the gas wallet, sent or attempted.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct GasTopUp {
+    pub id: i64,
+    /// `contract` for a collect from the mixer contract, `treasury` for a transfer from the treasury wallet.
+    pub source: String,
+    /// Address the funds were pulled from.
+    pub sender: String,
+    /// Nanotons pulled, for a collect the available balance of the contract at the time.
+    pub amount: i64,
+    /// Nanotons the gas wallet held when the top-up was triggered.
+    pub wallet_balance: i64,
+    pub threshold: i64,
+    /// Hex hash of the sent message, `None` if sending failed.
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: i64
+}
+
+/// Represents the query paging the top-ups of the gas wallet.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct GasTopUpQuery {
+    /// Number of most recent top-ups returned, 50 if omitted.
+    #[validate(range(min = 1, max = 500))]
+    pub limit: Option<i64>
+}
@@ -0,0 +1,32 @@
+//! # Contract Upgrade Types
+//!
+//! This module defines the types of the two-step upgrade of the mixer contract code.
+
+use serde::{Serialize, Deserialize};
+use validator::Validate;
+
+/// Represents the payload of a contract upgrade.
+///
+/// Without `confirmation` the upgrade is only previewed and a confirmation token is issued;
+/// sending the same code with that token performs it.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct ContractUpgradePayload {
+    /// BOC of the new code, base64; the bundled `MIXER_UPGRADE_CODE` is used if omitted.
+    #[validate(length(min = 1, max = 262144))]
+    pub code: Option<String>,
+    #[validate(length(equal = 32))]
+    pub confirmation: Option<String>
+}
+
+/// Represents a previewed upgrade awaiting confirmation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContractUpgradePreview {
+    pub contract: String,
+    /// Hex hash of the code the contract runs now, `None` if it is not deployed.
+    pub current_code_hash: Option<String>,
+    /// Hex hash of the new code.
+    pub code_hash: String,
+    /// The token to send back to perform the upgrade.
+    pub confirmation: String,
+    pub expires_at: i64
+}
@@ -0,0 +1,88 @@
+//! # Webhook Types
+//!
+//! This module defines the webhook subscriptions managed with the admin API and the
+//! log of their deliveries.
+
+use serde::{Serialize, Deserialize};
+use sqlx::FromRow;
+use validator::{Validate, ValidationError};
+
+/// The delivery waits for its next attempt.
+pub const DELIVERY_PENDING: &str = "pending";
+
+/// The endpoint accepted the delivery.
+pub const DELIVERY_DELIVERED: &str = "delivered";
+
+/// The delivery ran out of attempts, see its `error`.
+pub const DELIVERY_FAILED: &str = "failed";
+
+/// Represents an endpoint events are posted to.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct WebhookSubscription {
+    pub id: i64,
+    pub url: String,
+    /// Secret deliveries are signed with, never returned by the API.
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// Event types delivered to the endpoint, all of them when empty.
+    pub events: Vec<String>,
+    pub enabled: bool,
+    pub created_at: i64,
+    pub updated_at: i64
+}
+
+/// Represents the payload creating or replacing a webhook subscription.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct WebhookSubscriptionPayload {
+    #[validate(url, length(max = 2048))]
+    pub url: String,
+    /// Shared secret, required on creation and kept if omitted on replacement.
+    #[validate(length(min = 16, max = 256))]
+    pub secret: Option<String>,
+    /// Event types to deliver, all of them if omitted or empty.
+    #[validate(length(max = 32), custom(function = "validate_events"))]
+    pub events: Option<Vec<String>>,
+    /// Whether events are delivered, `true` if omitted.
+    pub enabled: Option<bool>
+}
+
+/// Checks that every event type is a non-empty name of at most 64 characters.
+fn validate_events(events: &Vec<String>) -> Result<(), ValidationError> {
+    if let Some(invalid) = events.iter().find(| e | e.is_empty() || e.len() > 64) {
+        let mut error: ValidationError = ValidationError::new("events");
+        error.message = Some(format!("invalid event type `{}`", invalid).into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Represents a delivery of an event to a webhook subscription.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub subscription_id: i64,
+    pub event: String,
+    pub payload: serde_json::Value,
+    /// `pending`, `delivered` or `failed`.
+    pub status: String,
+    pub attempts: i32,
+    /// Unix time of the next attempt of a pending delivery.
+    pub next_attempt_at: i64,
+    /// HTTP status of the last response, if the endpoint answered.
+    pub response_status: Option<i32>,
+    /// Error of the last failed attempt.
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64
+}
+
+/// Represents the query paging the deliveries of a subscription.
+#[derive(Serialize, Deserialize, Debug, Clone, Validate)]
+pub struct WebhookDeliveryQuery {
+    /// Number of most recent deliveries returned, 50 if omitted.
+    #[validate(range(min = 1, max = 500))]
+    pub limit: Option<i64>,
+    /// Only deliveries with this status.
+    pub status: Option<String>
+}
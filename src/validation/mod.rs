@@ -0,0 +1,174 @@
+//! # Request Validation
+//!
+//! This module provides the `ValidatedJson` and `ValidatedQuery` extractors, which deserialize
+//! a JSON body or query string and run the `validator` rules declared on its type, together with the shared rules
+//! used by the request payloads.
+
+use std::{future::Future, pin::Pin, str::FromStr, sync::{LazyLock, OnceLock}};
+
+use actix_web::{dev::Payload, error::ErrorBadRequest, web::{Json, Query}, Error, FromRequest, HttpRequest};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use tonlib::address::TonAddress;
+use validator::{Validate, ValidationError};
+
+use crate::{config, types::{safe_send_mode, Response}};
+
+/// Matches comments that can be put into a URL without escaping.
+pub static COMMENT_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[A-Za-z0-9_\-.]+$").unwrap()
+});
+
+/// Matches TON addresses in raw (`0:<hex>`) or user-friendly (48 base64 characters) form.
+pub static ADDRESS_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(-?[0-9]+:[0-9a-fA-F]{64}|[A-Za-z0-9_\-+/]{48})$").unwrap()
+});
+
+/// Checks that a string that looks like an address also parses as one.
+///
+/// The regex only checks the shape, this also verifies the checksum of user-friendly addresses.
+pub fn validate_address(value: &str) -> Result<(), ValidationError> {
+    match TonAddress::from_str(value) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            let mut error: ValidationError = ValidationError::new("address");
+            error.message = Some(err.to_string().into());
+            Err(error)
+        }
+    }
+}
+
+/// Matches the names of address book entries.
+pub static LABEL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[A-Za-z0-9_\-.]{1,64}$").unwrap()
+});
+
+/// Checks a recipient given either as an address or as `@name` of an address book entry.
+///
+/// Labels are resolved to their address by the services, see `services::addressbook`.
+pub fn validate_recipient(value: &str) -> Result<(), ValidationError> {
+    if let Some(name) = value.strip_prefix('@') {
+        if !LABEL_RE.is_match(name) {
+            let mut error: ValidationError = ValidationError::new("label");
+            error.message = Some(format!("`{}` is not a valid address book name", name).into());
+            return Err(error);
+        }
+
+        return Ok(());
+    }
+
+    if !ADDRESS_RE.is_match(value) {
+        let mut error: ValidationError = ValidationError::new("address");
+        error.message = Some(format!("`{}` is neither an address nor `@name`", value).into());
+        return Err(error);
+    }
+
+    validate_address(value)
+}
+
+/// Send modes callers may request, used when `SEND_MODES` is not set: the flags paying fees
+/// separately and ignoring errors, but neither sending the whole balance nor destroying the wallet.
+const DEFAULT_SEND_MODES: &str = "0,1,2,3";
+
+/// The send modes of `SEND_MODES`, parsed once on startup.
+static SEND_MODES: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Returns the send modes callers may request, taken from `SEND_MODES`.
+///
+/// Called on startup, so an invalid allow-list stops the server before it takes requests.
+///
+/// # Panics
+///
+/// Panics if an entry is not a number or not a safe combination of flags, see `types::safe_send_mode`.
+pub fn allowed_send_modes() -> &'static [u8] {
+    SEND_MODES.get_or_init(|| {
+        config::env_or("SEND_MODES", String::from(DEFAULT_SEND_MODES))
+            .split(',')
+            .map(| m | m.trim())
+            .filter(| m | !m.is_empty())
+            .map(| m | match m.parse::<u8>() {
+                Ok(mode) if safe_send_mode(mode) => mode,
+                _ => panic!("[ FATAL ] Configuration Error: `SEND_MODES` has an invalid or unsafe mode `{}`", m)
+            })
+            .collect()
+    })
+}
+
+/// Checks that a requested send mode is on the allow-list of `SEND_MODES`.
+pub fn validate_send_mode(mode: &u8) -> Result<(), ValidationError> {
+    let allowed: &[u8] = allowed_send_modes();
+
+    if !allowed.contains(mode) {
+        let mut error: ValidationError = ValidationError::new("send_mode");
+        error.message = Some(format!("send mode {} is not allowed, allowed modes are {:?}", mode, allowed).into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// A JSON body that passed the validation rules of its type.
+///
+/// Rejected bodies are answered with a 400 response listing every failed rule per field.
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    /// Unwraps the validated body.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = Json::<T>::from_request(req, payload);
+
+        Box::pin(async move {
+            let value: T = json.await?.into_inner();
+
+            if let Err(errors) = value.validate() {
+                return Err(ErrorBadRequest(
+                    Response::error(serde_json::to_value(&errors).unwrap()).to_string()
+                ));
+            }
+
+            Ok(ValidatedJson(value))
+        })
+    }
+}
+
+/// A query string that passed the validation rules of its type.
+///
+/// Rejected query strings are answered like rejected `ValidatedJson` bodies.
+pub struct ValidatedQuery<T>(pub T);
+
+impl<T> ValidatedQuery<T> {
+    /// Unwraps the validated query.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedQuery<T> {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let query = Query::<T>::from_request(req, payload);
+
+        Box::pin(async move {
+            let value: T = query.await?.into_inner();
+
+            if let Err(errors) = value.validate() {
+                return Err(ErrorBadRequest(
+                    Response::error(serde_json::to_value(&errors).unwrap()).to_string()
+                ));
+            }
+
+            Ok(ValidatedQuery(value))
+        })
+    }
+}
@@ -0,0 +1,65 @@
+//! # Response Warnings
+//!
+//! This module lets the services layer report non-fatal issues to the caller, e.g. an
+//! uninitialized recipient or an amount rounded to nanotons. `warn` records a warning
+//! for the request handled by the current task, and the `collect` middleware adds the
+//! warnings of a request as a `warnings` list to its JSON object response, the same
+//! field `Response::warnings` carries. Warnings raised outside of a request, e.g. by a
+//! background job, are only logged.
+
+use std::cell::RefCell;
+
+use actix_web::{body::{BoxBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, error::ErrorInternalServerError, middleware::Next, Error, HttpResponse};
+use serde_json::Value;
+
+tokio::task_local! {
+    /// The warnings of the request handled by the current task.
+    static WARNINGS: RefCell<Vec<String>>;
+}
+
+/// Records a warning for the current request and logs it.
+///
+/// # Arguments
+///
+/// * `message` - The warning, phrased for the caller.
+pub fn warn(message: impl Into<String>) {
+    let message: String = message.into();
+    log_warn!("{}", message);
+
+    let _ = WARNINGS.try_with(| warnings | {
+        let mut warnings = warnings.borrow_mut();
+        if !warnings.contains(&message) {
+            warnings.push(message);
+        }
+    });
+}
+
+/// Middleware adding the warnings raised while handling a request to its response.
+///
+/// Only responses whose body is a JSON object are extended, other bodies are passed on unchanged.
+pub async fn collect(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let (res, warnings) = WARNINGS.scope(RefCell::new(Vec::new()), async {
+        let res = next.call(req).await;
+        (res, WARNINGS.with(| warnings | warnings.take()))
+    }).await;
+    let res = res?;
+
+    if warnings.is_empty() {
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let (request, response) = res.into_parts();
+    let (head, body): (HttpResponse<()>, _) = response.into_parts();
+    let bytes = actix_web::body::to_bytes(body).await
+        .map_err(|_| ErrorInternalServerError("can not read the response body"))?;
+
+    let body: Vec<u8> = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(mut object)) => {
+            object.insert(String::from("warnings"), Value::from(warnings));
+            serde_json::to_vec(&object).unwrap()
+        },
+        _ => bytes.to_vec()
+    };
+
+    Ok(ServiceResponse::new(request, head.set_body(body).map_into_boxed_body()))
+}
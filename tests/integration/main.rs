@@ -0,0 +1,72 @@
+//! # End-to-end Tests
+//!
+//! These tests run the API against a local TON network and check that fork, spread and
+//! collect messages are accepted, applied and indexed, so regressions in message building
+//! are caught before they reach testnet, and that operator signatures can not be replayed. They are built with `--features integration`,
+//! see `harness` for the environment they need:
+//!
+//! ```sh
+//! IT_WALLET_MNEMONIC="..." IT_MIXER_CODE=mixer.code.boc IT_MIXER_DATA=mixer.data.boc \
+//!     cargo test --features integration --test integration
+//! ```
+
+mod harness;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use harness::Harness;
+
+/// Runs the scenarios in order against one environment, as starting it takes minutes.
+#[tokio::test(flavor = "multi_thread")]
+async fn mixer_end_to_end() {
+    let harness: Harness = Harness::start().await;
+
+    operator_signatures(&harness).await;
+    spread(&harness).await;
+    fork(&harness).await;
+    collect(&harness).await;
+}
+
+/// Returns the query id of an operation receipt.
+fn query_id(receipt: &Value) -> u64 {
+    receipt["query_id"].as_u64().unwrap_or_else(|| panic!("receipt has no query id: {}", receipt))
+}
+
+/// Pauses and resumes the mixer with operator signatures, rejecting a replayed one.
+async fn operator_signatures(harness: &Harness) {
+    let timestamp: u64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    assert_eq!(harness.signed_post("/admin/pause", timestamp).await.status(), 200);
+    assert_eq!(harness.signed_post("/admin/pause", timestamp).await.status(), 401, "a replayed signature was accepted");
+    assert_eq!(harness.signed_post("/admin/resume", timestamp).await.status(), 200);
+    assert_eq!(harness.signed_post("/admin/resume", timestamp - 3600).await.status(), 401, "a stale signature was accepted");
+}
+
+/// Spreads funds from the contract back to the wallet.
+async fn spread(harness: &Harness) {
+    let receipt: Value = harness.post("/v1/mixer/spread", json!({
+        "wallets": [{ "account": harness.wallet.to_base64_url(), "amount": 0.5 }]
+    })).await;
+
+    assert_eq!(receipt["op"], "spread");
+    assert_eq!(receipt["contract"], harness.contract.to_base64_url());
+    harness.wait_confirmed(query_id(&receipt)).await;
+}
+
+/// Forks the contract.
+async fn fork(harness: &Harness) {
+    let receipt: Value = harness.post("/v1/mixer/fork", json!({})).await;
+
+    assert_eq!(receipt["op"], "fork");
+    harness.wait_confirmed(query_id(&receipt)).await;
+}
+
+/// Collects the TON balance of the contract in mode 2.
+async fn collect(harness: &Harness) {
+    let receipt: Value = harness.post("/v1/mixer/collect", json!({ "mode": 2 })).await;
+
+    assert_eq!(receipt["op"], "collect");
+    harness.wait_confirmed(query_id(&receipt)).await;
+}